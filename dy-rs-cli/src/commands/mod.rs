@@ -1,2 +0,0 @@
-pub mod new;
-pub mod dev;
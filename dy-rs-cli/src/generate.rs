@@ -0,0 +1,811 @@
+//! Code generators for an existing project
+//!
+//! `dy generate resource post title:string body:text published:bool` writes:
+//!
+//! - `src/resources/post.rs`: the `Post` model and its validated `PostInput`,
+//!   a `Resource` impl and a `PostService` storing posts through a
+//!   `Repository`, with tests
+//! - `src/resources/mod.rs`: `register`, mounting the CRUD routes of every
+//!   generated resource
+//! - `migrations/<timestamp>_create_posts.sql`, creating the table
+//!
+//! and declares `mod resources;` and any missing dependencies. Fields are
+//! `name:type`, with a trailing `?` for optional ones (`subtitle:string?`);
+//! every resource also gets an `id` and a `created_at` timestamp, both
+//! assigned by the database.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use dy_rs::prelude::Utc;
+
+/// Line of `register` that new resources are added above
+const REGISTER_MARKER: &str = "// dy generate resource adds resources above this line";
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while",
+];
+
+/// Type of a generated field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    String,
+    Text,
+    Bool,
+    Int,
+    BigInt,
+    Float,
+    Uuid,
+    DateTime,
+    Date,
+    Json,
+}
+
+impl FieldType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "string" => FieldType::String,
+            "text" => FieldType::Text,
+            "bool" | "boolean" => FieldType::Bool,
+            "int" | "integer" | "i32" => FieldType::Int,
+            "bigint" | "i64" => FieldType::BigInt,
+            "float" | "double" | "f64" => FieldType::Float,
+            "uuid" => FieldType::Uuid,
+            "datetime" | "timestamp" => FieldType::DateTime,
+            "date" => FieldType::Date,
+            "json" => FieldType::Json,
+            _ => return None,
+        })
+    }
+
+    fn rust_type(self) -> &'static str {
+        match self {
+            FieldType::String | FieldType::Text => "String",
+            FieldType::Bool => "bool",
+            FieldType::Int => "i32",
+            FieldType::BigInt => "i64",
+            FieldType::Float => "f64",
+            FieldType::Uuid => "Uuid",
+            FieldType::DateTime => "DateTime<Utc>",
+            FieldType::Date => "chrono::NaiveDate",
+            FieldType::Json => "serde_json::Value",
+        }
+    }
+
+    fn sql_type(self) -> &'static str {
+        match self {
+            FieldType::String => "VARCHAR(255)",
+            FieldType::Text => "TEXT",
+            FieldType::Bool => "BOOLEAN",
+            FieldType::Int => "INTEGER",
+            FieldType::BigInt => "BIGINT",
+            FieldType::Float => "DOUBLE PRECISION",
+            FieldType::Uuid => "UUID",
+            FieldType::DateTime => "TIMESTAMPTZ",
+            FieldType::Date => "DATE",
+            FieldType::Json => "JSONB",
+        }
+    }
+
+    /// `#[validate]` rule of the input field
+    fn validation(self) -> Option<&'static str> {
+        match self {
+            FieldType::String => Some("length(min = 1, max = 255)"),
+            FieldType::Text => Some("length(min = 1)"),
+            _ => None,
+        }
+    }
+
+    /// A valid value, as JSON, for the generated tests
+    fn example(self) -> &'static str {
+        match self {
+            FieldType::String => r#""Example""#,
+            FieldType::Text => r#""Example text""#,
+            FieldType::Bool => "true",
+            FieldType::Int | FieldType::BigInt => "1",
+            FieldType::Float => "1.5",
+            FieldType::Uuid => r#""6d0c1f0e-5f8a-4c3e-9a43-1c5b2a8c9d10""#,
+            FieldType::DateTime => r#""2025-01-01T00:00:00Z""#,
+            FieldType::Date => r#""2025-01-01""#,
+            FieldType::Json => r#"{ "key": "value" }"#,
+        }
+    }
+}
+
+/// A `name:type` field of the generated model
+#[derive(Debug, PartialEq, Eq)]
+struct Field {
+    name: String,
+    ty: FieldType,
+    optional: bool,
+}
+
+impl Field {
+    fn parse(spec: &str) -> anyhow::Result<Self> {
+        let Some((name, ty)) = spec.split_once(':') else {
+            anyhow::bail!("Invalid field '{}', expected name:type", spec);
+        };
+        let (ty, optional) = match ty.strip_suffix('?') {
+            Some(ty) => (ty, true),
+            None => (ty, false),
+        };
+        let ty = FieldType::parse(ty).with_context(|| {
+            format!(
+                "Unknown type '{ty}' of field '{name}' (use string, text, bool, int, bigint, \
+                 float, uuid, datetime, date or json)"
+            )
+        })?;
+        let name = check_identifier(name, "field")?;
+        if name == "id" || name == "created_at" {
+            anyhow::bail!("Field '{}' is generated for every resource", name);
+        }
+        Ok(Self { name, ty, optional })
+    }
+
+    fn rust_type(&self) -> String {
+        if self.optional {
+            format!("Option<{}>", self.ty.rust_type())
+        } else {
+            self.ty.rust_type().to_string()
+        }
+    }
+}
+
+/// What `dy generate resource` writes for one resource
+#[derive(Debug)]
+struct ResourceSpec {
+    /// Module name, e.g. `blog_post`
+    module: String,
+    /// Model name, e.g. `BlogPost`
+    model: String,
+    /// Table name, e.g. `blog_posts`
+    table: String,
+    /// Collection path, e.g. `/blog-posts`
+    path: String,
+    fields: Vec<Field>,
+}
+
+impl ResourceSpec {
+    fn new(name: &str, fields: &[String]) -> anyhow::Result<Self> {
+        let module = check_identifier(&snake_case(name), "resource")?;
+        if fields.is_empty() {
+            anyhow::bail!("Give the resource at least one field, e.g. title:string");
+        }
+        let fields = fields
+            .iter()
+            .map(|field| Field::parse(field))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for (i, field) in fields.iter().enumerate() {
+            if fields[..i].iter().any(|other| other.name == field.name) {
+                anyhow::bail!("Field '{}' is given twice", field.name);
+            }
+        }
+
+        let table = plural(&module);
+        Ok(Self {
+            model: pascal_case(&module),
+            path: format!("/{}", table.replace('_', "-")),
+            table,
+            module,
+            fields,
+        })
+    }
+
+    /// Contents of `src/resources/<module>.rs`
+    fn model_source(&self) -> String {
+        let Self {
+            model, table, path, ..
+        } = self;
+        let service = format!("{model}Service");
+
+        let mut model_fields = String::new();
+        let mut input_fields = String::new();
+        let mut from_input = String::new();
+        for field in &self.fields {
+            let (name, ty) = (&field.name, field.rust_type());
+            model_fields.push_str(&format!("    pub {name}: {ty},\n"));
+            if let Some(rule) = field.ty.validation() {
+                input_fields.push_str(&format!("    #[validate({rule})]\n"));
+            }
+            input_fields.push_str(&format!("    pub {name}: {ty},\n"));
+            from_input.push_str(&format!("            {name}: input.{name},\n"));
+        }
+
+        format!(
+            r#"//! {model} resource, generated by `dy generate resource`
+
+use dy_rs::db::{{Entity, PgPool, Repository}};
+use dy_rs::prelude::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, sqlx::FromRow, Entity)]
+#[entity(table = "{table}")]
+pub struct {model} {{
+    #[entity(generated)]
+    pub id: i64,
+{model_fields}    #[entity(generated)]
+    pub created_at: DateTime<Utc>,
+}}
+
+/// Body of `POST {path}` and `PUT {path}/{{id}}`
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct {model}Input {{
+{input_fields}}}
+
+impl Resource for {model} {{
+    const PATH: &'static str = "{path}";
+    type Id = i64;
+    type Create = {model}Input;
+    type Update = {model}Input;
+}}
+
+impl {model} {{
+    fn from_input(input: {model}Input) -> Self {{
+        Self {{
+            // Set by the database, never written
+            id: 0,
+{from_input}            created_at: Utc::now(),
+        }}
+    }}
+}}
+
+/// {model} rows of the `{table}` table
+#[derive(Clone)]
+pub struct {service} {{
+    repository: Repository<{model}, i64>,
+}}
+
+impl {service} {{
+    pub fn new(pool: PgPool) -> Self {{
+        Self {{
+            repository: Repository::new(pool),
+        }}
+    }}
+}}
+
+#[async_trait::async_trait]
+impl CrudService<{model}> for {service} {{
+    async fn list(&self, pagination: &Pagination) -> Result<(Vec<{model}>, u64), ApiError> {{
+        self.repository.find_page(pagination).await
+    }}
+
+    async fn get(&self, id: &i64) -> Result<Option<{model}>, ApiError> {{
+        self.repository.find(id).await
+    }}
+
+    async fn create(&self, input: {model}Input) -> Result<{model}, ApiError> {{
+        let row = {model}::from_input(input);
+        self.repository.insert(&row).await
+    }}
+
+    async fn update(&self, id: &i64, input: {model}Input) -> Result<Option<{model}>, ApiError> {{
+        let row = {model}::from_input(input);
+        self.repository.update(id, &row).await
+    }}
+
+    async fn delete(&self, id: &i64) -> Result<bool, ApiError> {{
+        self.repository.delete(id).await
+    }}
+}}
+
+{tests}"#,
+            tests = self.tests_source(&service),
+        )
+    }
+
+    /// The `tests` module of the model file
+    fn tests_source(&self, service: &str) -> String {
+        let Self {
+            module,
+            model,
+            path,
+            ..
+        } = self;
+
+        let example = self
+            .fields
+            .iter()
+            .map(|field| format!(r#""{}": {}"#, field.name, field.ty.example()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let invalid = match self
+            .fields
+            .iter()
+            .find(|field| field.ty.validation().is_some())
+        {
+            Some(field) => format!(
+                r#"
+
+        let mut invalid = input();
+        invalid["{}"] = json!("");
+        let invalid: {model}Input = serde_json::from_value(invalid).unwrap();
+        assert!(invalid.validate().is_err());"#,
+                field.name
+            ),
+            None => String::new(),
+        };
+
+        format!(
+            r#"#[cfg(test)]
+mod tests {{
+    use super::*;
+    use dy_rs::db::testing::TestDatabase;
+    use dy_rs::testing::TestClient;
+    use serde_json::{{Value, json}};
+
+    fn input() -> Value {{
+        json!({{ {example} }})
+    }}
+
+    #[test]
+    fn input_is_validated() {{
+        let valid: {model}Input = serde_json::from_value(input()).unwrap();
+        assert!(valid.validate().is_ok());{invalid}
+    }}
+
+    #[tokio::test]
+    #[ignore = "needs a database: set TEST_DATABASE_URL"]
+    async fn {module}_crud() {{
+        let db = TestDatabase::from_env().await.unwrap();
+        db.migrate("migrations").await.unwrap();
+        let app = App::new().resource::<{model}>({service}::new(db.pool().clone()));
+        let client = TestClient::new(app);
+
+        let response = client.post("{path}").json(&input()).await;
+        assert_eq!(response.status(), 201);
+        let created: {model} = response.json();
+        let item = format!("{path}/{{}}", created.id);
+
+        client.get(&item).await.assert_ok();
+        client.get("{path}").await.assert_ok();
+        client.put(&item).json(&input()).await.assert_ok();
+        assert_eq!(client.delete(&item).await.status(), 204);
+        assert_eq!(client.get(&item).await.status(), 404);
+
+        db.cleanup().await.unwrap();
+    }}
+}}
+"#
+        )
+    }
+
+    /// Contents of the migration creating the table
+    fn migration_source(&self) -> String {
+        let mut columns =
+            vec!["    id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY".to_string()];
+        for field in &self.fields {
+            let null = if field.optional { "" } else { " NOT NULL" };
+            columns.push(format!("    {} {}{null}", field.name, field.ty.sql_type()));
+        }
+        columns.push("    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()".to_string());
+        format!(
+            "CREATE TABLE {} (\n{}\n);\n",
+            self.table,
+            columns.join(",\n")
+        )
+    }
+
+    /// The line of `register` mounting this resource
+    fn register_line(&self) -> String {
+        format!(
+            "    app = app.resource::<{module}::{model}>({module}::{model}Service::new(pool.clone()));\n",
+            module = self.module,
+            model = self.model,
+        )
+    }
+
+    /// Dependencies the generated code uses, as `Cargo.toml` lines
+    fn dependencies(&self) -> Vec<(&'static str, &'static str)> {
+        let mut dependencies = vec![
+            ("async-trait", r#"async-trait = "0.1""#),
+            (
+                "serde",
+                r#"serde = { version = "1.0", features = ["derive"] }"#,
+            ),
+            ("serde_json", r#"serde_json = "1.0""#),
+            (
+                "sqlx",
+                r#"sqlx = { version = "0.8", features = ["runtime-tokio", "postgres", "uuid", "chrono", "json"] }"#,
+            ),
+            ("tokio", r#"tokio = { version = "1", features = ["full"] }"#),
+            (
+                "utoipa",
+                r#"utoipa = { version = "5", features = ["chrono", "uuid"] }"#,
+            ),
+            (
+                "validator",
+                r#"validator = { version = "0.20", features = ["derive"] }"#,
+            ),
+        ];
+        if self.fields.iter().any(|field| field.ty == FieldType::Date) {
+            dependencies.push((
+                "chrono",
+                r#"chrono = { version = "0.4", features = ["serde"] }"#,
+            ));
+        }
+        dependencies
+    }
+}
+
+/// Generate resource `name` with `fields` in the project at `project`
+pub fn resource(project: &Path, name: &str, fields: &[String]) -> anyhow::Result<()> {
+    let spec = ResourceSpec::new(name, fields)?;
+
+    let cargo_toml = project.join("Cargo.toml");
+    // Libraries declare the module in lib.rs, binaries in main.rs
+    let crate_root = ["src/lib.rs", "src/main.rs"]
+        .iter()
+        .map(|root| project.join(root))
+        .find(|root| root.exists());
+    let Some(crate_root) = crate_root.filter(|_| cargo_toml.exists()) else {
+        anyhow::bail!("Run dy generate from the root of a project, next to Cargo.toml");
+    };
+    let resources = project.join("src/resources");
+    let model_path = resources.join(format!("{}.rs", spec.module));
+    if model_path.exists() {
+        anyhow::bail!("{} already exists", model_path.display());
+    }
+
+    println!("🧱 Generating resource {} at {}", spec.model, spec.path);
+
+    fs::create_dir_all(&resources)?;
+    fs::write(&model_path, spec.model_source())?;
+    println!("   created {}", relative(project, &model_path));
+
+    let mod_path = resources.join("mod.rs");
+    let mod_source = match fs::read_to_string(&mod_path) {
+        Ok(source) => add_resource(&source, &spec)?,
+        Err(_) => new_resources_mod(&spec),
+    };
+    fs::write(&mod_path, mod_source)?;
+    println!("   updated {}", relative(project, &mod_path));
+
+    let migrations = project.join("migrations");
+    fs::create_dir_all(&migrations)?;
+    let migration_path = migrations.join(format!(
+        "{}_create_{}.sql",
+        migration_version(&migrations)?,
+        spec.table
+    ));
+    fs::write(&migration_path, spec.migration_source())?;
+    println!("   created {}", relative(project, &migration_path));
+
+    let root_source = fs::read_to_string(&crate_root)?;
+    if !declares_resources(&root_source) {
+        let visibility = if crate_root.ends_with("lib.rs") {
+            "pub "
+        } else {
+            ""
+        };
+        fs::write(
+            &crate_root,
+            declare_module(&root_source, &format!("{visibility}mod resources;")),
+        )?;
+        println!("   updated {}", relative(project, &crate_root));
+    }
+
+    let manifest = fs::read_to_string(&cargo_toml)?;
+    let missing: Vec<_> = spec
+        .dependencies()
+        .into_iter()
+        .filter(|(name, _)| !has_dependency(&manifest, name))
+        .map(|(_, line)| line)
+        .collect();
+    if !missing.is_empty() {
+        fs::write(&cargo_toml, add_dependencies(&manifest, &missing))?;
+        println!("   updated Cargo.toml");
+    }
+
+    println!("\n✅ Resource {} generated!", spec.model);
+    if !root_source.contains("resources::register") {
+        println!("\n🔌 Mount the resources once, with the app's database pool:");
+        println!("   let pool = PgPool::connect(&database_url).await?;");
+        println!("   let app = resources::register(App::new().auto_configure(), pool);");
+    }
+    println!("\n🗄️  Apply the migration, e.g. with sqlx-cli:");
+    println!("   sqlx migrate run");
+
+    Ok(())
+}
+
+/// `src/resources/mod.rs` with its first resource
+fn new_resources_mod(spec: &ResourceSpec) -> String {
+    format!(
+        r#"//! Resources generated by `dy generate resource`
+
+use dy_rs::db::PgPool;
+use dy_rs::prelude::*;
+
+pub mod {module};
+
+/// Mount the CRUD routes of every resource, stored in `pool`
+pub fn register(mut app: App, pool: PgPool) -> App {{
+{register}    {REGISTER_MARKER}
+    app
+}}
+"#,
+        module = spec.module,
+        register = spec.register_line(),
+    )
+}
+
+/// `source` of `src/resources/mod.rs` with `spec` declared and registered
+fn add_resource(source: &str, spec: &ResourceSpec) -> anyhow::Result<String> {
+    let Some(marker) = source.find(REGISTER_MARKER) else {
+        anyhow::bail!(
+            "src/resources/mod.rs has no '{}' line to add the resource above",
+            REGISTER_MARKER
+        );
+    };
+    let line_start = source[..marker].rfind('\n').map_or(0, |i| i + 1);
+    let mut source = format!(
+        "{}{}{}",
+        &source[..line_start],
+        spec.register_line(),
+        &source[line_start..]
+    );
+
+    // Keep the declarations sorted, as rustfmt does
+    let declaration = format!("pub mod {};\n", spec.module);
+    let mut at = None;
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        if let Some(module) = line.strip_prefix("pub mod ") {
+            if module.trim_end().trim_end_matches(';') > spec.module.as_str() {
+                at = Some(offset);
+                break;
+            }
+            at = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    source.insert_str(at.unwrap_or(0), &declaration);
+    Ok(source)
+}
+
+/// Whether a crate root already declares the `resources` module
+fn declares_resources(source: &str) -> bool {
+    source
+        .lines()
+        .any(|line| matches!(line.trim(), "mod resources;" | "pub mod resources;"))
+}
+
+/// `source` with `declaration` added after its inner attributes and docs
+fn declare_module(source: &str, declaration: &str) -> String {
+    let header: usize = source
+        .split_inclusive('\n')
+        .take_while(|line| {
+            let line = line.trim_start();
+            line.starts_with("//!") || line.starts_with("#![") || line.trim().is_empty()
+        })
+        .map(str::len)
+        .sum();
+    let separator = if header == 0 { "" } else { "\n\n" };
+    format!(
+        "{}{separator}{declaration}\n\n{}",
+        source[..header].trim_end(),
+        &source[header..]
+    )
+    .trim_start()
+    .to_string()
+}
+
+/// Whether `manifest` has dependency `name`
+fn has_dependency(manifest: &str, name: &str) -> bool {
+    manifest.lines().any(|line| {
+        line.trim_start()
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.trim_start().starts_with(['=', '.']))
+    })
+}
+
+/// `manifest` with `lines` added at the end of `[dependencies]`
+fn add_dependencies(manifest: &str, lines: &[&str]) -> String {
+    let mut out: Vec<&str> = manifest.lines().collect();
+    let section = out.iter().position(|line| line.trim() == "[dependencies]");
+    let at = match section {
+        Some(section) => {
+            let end = out[section + 1..]
+                .iter()
+                .position(|line| line.trim_start().starts_with('['))
+                .map_or(out.len(), |i| section + 1 + i);
+            // Before the blank lines ending the section
+            let mut at = end;
+            while at > section + 1 && out[at - 1].trim().is_empty() {
+                at -= 1;
+            }
+            at
+        }
+        None => {
+            out.extend(["", "[dependencies]"]);
+            out.len()
+        }
+    };
+    out.splice(at..at, lines.iter().copied());
+    let mut manifest = out.join("\n");
+    manifest.push('\n');
+    manifest
+}
+
+/// A sqlx migration version, the current UTC time, unused in `migrations`
+fn migration_version(migrations: &Path) -> anyhow::Result<u64> {
+    let mut version: u64 = Utc::now().format("%Y%m%d%H%M%S").to_string().parse()?;
+    let existing: Vec<String> = fs::read_dir(migrations)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    while existing
+        .iter()
+        .any(|name| name.starts_with(&format!("{version}_")))
+    {
+        version += 1;
+    }
+    Ok(version)
+}
+
+fn relative(project: &Path, path: &Path) -> String {
+    path.strip_prefix(project)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// `name` if it's a usable snake_case identifier
+fn check_identifier(name: &str, what: &str) -> anyhow::Result<String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        anyhow::bail!(
+            "Invalid {} name '{}', use lowercase letters, digits and _",
+            what,
+            name
+        );
+    }
+    if KEYWORDS.contains(&name) {
+        anyhow::bail!("Invalid {} name '{}', it's a Rust keyword", what, name);
+    }
+    Ok(name.to_string())
+}
+
+/// `BlogPost`, `blog-post` or `blog_post` as `blog_post`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c == '-' {
+            snake.push('_');
+        } else if c.is_ascii_uppercase() {
+            if previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit()) {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+        previous = Some(c);
+    }
+    snake
+}
+
+/// `blog_post` as `BlogPost`
+fn pascal_case(snake: &str) -> String {
+    snake
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// English plural of a snake_case name, e.g. `categories` or `boxes`
+fn plural(snake: &str) -> String {
+    if let Some(stem) = snake.strip_suffix('y')
+        && !stem.ends_with(['a', 'e', 'i', 'o', 'u'])
+    {
+        return format!("{stem}ies");
+    }
+    if ["s", "x", "z", "ch", "sh"]
+        .iter()
+        .any(|suffix| snake.ends_with(suffix))
+    {
+        return format!("{snake}es");
+    }
+    format!("{snake}s")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(specs: &[&str]) -> Vec<String> {
+        specs.iter().map(|spec| spec.to_string()).collect()
+    }
+
+    #[test]
+    fn names_are_derived_from_the_resource() {
+        let spec = ResourceSpec::new("BlogPost", &fields(&["title:string"])).unwrap();
+        assert_eq!(spec.module, "blog_post");
+        assert_eq!(spec.model, "BlogPost");
+        assert_eq!(spec.table, "blog_posts");
+        assert_eq!(spec.path, "/blog-posts");
+
+        assert_eq!(plural("category"), "categories");
+        assert_eq!(plural("day"), "days");
+        assert_eq!(plural("box"), "boxes");
+        assert_eq!(snake_case("order-item"), "order_item");
+    }
+
+    #[test]
+    fn fields_are_parsed() {
+        assert_eq!(
+            Field::parse("subtitle:string?").unwrap(),
+            Field {
+                name: "subtitle".to_string(),
+                ty: FieldType::String,
+                optional: true,
+            }
+        );
+        assert_eq!(Field::parse("views:i64").unwrap().rust_type(), "i64");
+        assert!(Field::parse("title").is_err());
+        assert!(Field::parse("title:varchar").is_err());
+        assert!(Field::parse("type:string").is_err());
+        assert!(Field::parse("id:uuid").is_err());
+        assert!(ResourceSpec::new("post", &fields(&["a:int", "a:int"])).is_err());
+        assert!(ResourceSpec::new("post", &[]).is_err());
+    }
+
+    #[test]
+    fn migrations_create_the_table() {
+        let spec = ResourceSpec::new("post", &fields(&["title:string", "published_at:datetime?"]))
+            .unwrap();
+        assert_eq!(
+            spec.migration_source(),
+            "CREATE TABLE posts (\n    id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,\n    title VARCHAR(255) NOT NULL,\n    \
+             published_at TIMESTAMPTZ,\n    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()\n);\n"
+        );
+    }
+
+    #[test]
+    fn resources_are_registered_in_order() {
+        let post = ResourceSpec::new("post", &fields(&["title:string"])).unwrap();
+        let comment = ResourceSpec::new("comment", &fields(&["body:text"])).unwrap();
+        let source = add_resource(&new_resources_mod(&post), &comment).unwrap();
+        assert!(source.contains("pub mod comment;\npub mod post;\n"));
+        assert!(source.contains(
+            "    app = app.resource::<post::Post>(post::PostService::new(pool.clone()));\n    \
+             app = app.resource::<comment::Comment>(comment::CommentService::new(pool.clone()));\n    \
+             // dy generate resource"
+        ));
+        assert!(add_resource("pub mod post;\n", &comment).is_err());
+    }
+
+    #[test]
+    fn project_files_are_wired_up() {
+        assert_eq!(
+            declare_module("//! My app\n\nuse dy_rs::prelude::*;\n", "mod resources;"),
+            "//! My app\n\nmod resources;\n\nuse dy_rs::prelude::*;\n"
+        );
+        assert_eq!(
+            declare_module("use dy_rs::prelude::*;\n", "mod resources;"),
+            "mod resources;\n\nuse dy_rs::prelude::*;\n"
+        );
+        assert!(declares_resources("mod resources;\nfn main() {}\n"));
+
+        let manifest = "[package]\nname = \"app\"\n\n[dependencies]\ndy-rs = \"0.2\"\nserde = \"1\"\n\n[dev-dependencies]\n";
+        assert!(has_dependency(manifest, "serde"));
+        assert!(!has_dependency(manifest, "serde_json"));
+        assert_eq!(
+            add_dependencies(manifest, &["async-trait = \"0.1\""]),
+            "[package]\nname = \"app\"\n\n[dependencies]\ndy-rs = \"0.2\"\nserde = \"1\"\nasync-trait = \"0.1\"\n\n[dev-dependencies]\n"
+        );
+    }
+}
@@ -1,8 +1,13 @@
-use clap::{Parser, Subcommand};
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
 use std::fs;
+use std::io::Read;
 use std::path::Path;
 use std::process::Command;
 
+mod generate;
+mod templates;
+
 #[derive(Parser)]
 #[command(name = "dy")]
 #[command(about = "CLI tool for dy-rs framework", long_about = None)]
@@ -18,215 +23,282 @@ enum Commands {
         /// Project name
         name: String,
 
-        /// Template to use (rest-api, graphql, grpc)
+        /// Template to use: rest-api, minimal, graphql, grpc, websocket,
+        /// worker, github:org/repo[/dir][#ref], a git URL or a local path
         #[arg(short, long, default_value = "rest-api")]
         template: String,
-    },
 
-    /// Run the project in development mode with hot reload
-    Dev,
-}
+        /// Format of the generated config files, for built-in templates
+        #[arg(long, value_enum, default_value_t = ConfigFormat::Toml)]
+        config_format: ConfigFormat,
 
-fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+        /// Set a variable of a custom template
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
 
-    match cli.command {
-        Commands::New { name, template } => {
-            create_project(&name, &template)?;
-        }
-        Commands::Dev => {
-            run_dev_mode()?;
-        }
-    }
+    /// Generate code in the current project
+    Generate {
+        #[command(subcommand)]
+        generator: Generator,
+    },
 
-    Ok(())
-}
+    /// Make keys for and encrypt `ENC(...)` config values
+    Secrets {
+        #[command(subcommand)]
+        command: SecretsCommand,
+    },
 
-fn create_project(name: &str, template: &str) -> anyhow::Result<()> {
-    println!("🚀 Creating new dy-rs project: {}", name);
+    /// Run the project in development mode with hot reload
+    Dev,
 
-    if template != "rest-api" {
-        anyhow::bail!("Only 'rest-api' template is currently supported");
-    }
+    /// Serve example responses generated from an OpenAPI spec
+    Mock {
+        /// OpenAPI document (.json, .yaml or .yml)
+        spec: String,
 
-    let project_path = Path::new(name);
-    if project_path.exists() {
-        anyhow::bail!("Directory '{}' already exists", name);
-    }
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 3000)]
+        port: u16,
+    },
 
-    // Create project structure
-    fs::create_dir_all(project_path.join("src"))?;
-    fs::create_dir_all(project_path.join("config"))?;
+    /// Run benchmarks (`cargo bench`)
+    Bench {
+        /// Run the framework's own hot-path benchmarks, from a dy-rs checkout
+        #[arg(long)]
+        internal: bool,
 
-    // Create Cargo.toml
-    let cargo_toml = format!(
-        r#"[package]
-name = "{}"
-version = "0.1.0"
-edition = "2021"
-
-[dependencies]
-dy-rs = "0.1"
-tokio = {{ version = "1", features = ["full"] }}
-serde = {{ version = "1.0", features = ["derive"] }}
-uuid = {{ version = "1.0", features = ["v4", "serde"] }}
-chrono = {{ version = "0.4", features = ["serde"] }}
-validator = {{ version = "0.18", features = ["derive"] }}
-"#,
-        name
-    );
-    fs::write(project_path.join("Cargo.toml"), cargo_toml)?;
-
-    // Create main.rs with full example
-    let main_rs = r#"use dy_rs::prelude::*;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-
-#[derive(Serialize, Deserialize, Clone)]
-struct User {
-    id: Uuid,
-    email: String,
-    name: String,
-    created_at: DateTime<Utc>,
-}
+        /// Only run benchmarks whose name contains this
+        filter: Option<String>,
 
-#[derive(Deserialize, Validate)]
-struct CreateUserRequest {
-    #[validate(email(message = "Invalid email format"))]
-    email: String,
-    
-    #[validate(length(min = 2, max = 100))]
-    name: String,
-}
+        /// Record the results as baseline NAME
+        #[arg(long, value_name = "NAME")]
+        save_baseline: Option<String>,
 
-type Database = Arc<Mutex<HashMap<Uuid, User>>>;
-
-async fn create_user(
-    State(db): State<Database>,
-    ValidatedJson(payload): ValidatedJson<CreateUserRequest>,
-) -> ApiResult<User> {
-    let user = User {
-        id: Uuid::new_v4(),
-        email: payload.email,
-        name: payload.name,
-        created_at: Utc::now(),
-    };
+        /// Compare with baseline NAME, failing on regressions
+        #[arg(long, value_name = "NAME")]
+        baseline: Option<String>,
 
-    db.lock().unwrap().insert(user.id, user.clone());
-    Ok(Json(user))
+        /// Slowdown in percent that counts as a regression
+        #[arg(long, default_value_t = 10.0)]
+        threshold: f64,
+    },
 }
 
-async fn list_users(State(db): State<Database>) -> ApiResult<Vec<User>> {
-    let users: Vec<User> = db.lock().unwrap().values().cloned().collect();
-    Ok(Json(users))
-}
+#[derive(Subcommand)]
+enum Generator {
+    /// A database-backed CRUD resource: model, routes, migration and tests
+    Resource {
+        /// Resource name, e.g. post or BlogPost
+        name: String,
 
-async fn get_user(
-    State(db): State<Database>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<User>, ApiError> {
-    let user = db
-        .lock()
-        .unwrap()
-        .get(&id)
-        .ok_or_else(|| ApiError::NotFound(format!("User {} not found", id)))?
-        .clone();
-    Ok(Json(user))
+        /// Fields as name:type, e.g. title:string body:text published:bool;
+        /// end the type with ? for optional fields
+        #[arg(required = true)]
+        fields: Vec<String>,
+    },
 }
 
-fn routes() -> Router<Database> {
-    Router::new()
-        .route("/users", post(create_user))
-        .route("/users", get(list_users))
-        .route("/users/:id", get(get_user))
+#[derive(Subcommand)]
+enum SecretsCommand {
+    /// Print a new random master key for APP_CONFIG_KEY
+    Keygen,
+
+    /// Encrypt a value with the master key in APP_CONFIG_KEY, printing the
+    /// ENC(...) value to put in a config file
+    Encrypt {
+        /// Value to encrypt; read from stdin if left out, which keeps it out
+        /// of the shell history
+        value: Option<String>,
+    },
 }
 
-#[tokio::main]
-async fn main() {
-    let db: Database = Arc::new(Mutex::new(HashMap::new()));
-
-    App::new()
-        .auto_configure()
-        .mount(routes().with_state(db))
-        .run()
-        .await
-        .unwrap();
+/// File formats `AppConfig::load` reads
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
 }
-"#;
-    fs::write(project_path.join("src/main.rs"), main_rs)?;
 
-    // Create config files
-    let default_config = r#"[server]
+impl ConfigFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Json => "json",
+        }
+    }
+
+    /// The project's `config/default.*` and `config/local.*` contents
+    fn files(self) -> (&'static str, &'static str) {
+        match self {
+            ConfigFormat::Toml => (
+                r#"[server]
 host = "0.0.0.0"
 port = 3000
 
 [database]
 url = "postgres://localhost/dy_rs"
 max_connections = 10
-"#;
-    fs::write(project_path.join("config/default.toml"), default_config)?;
-
-    let local_config = r#"# Override settings for local development
+"#,
+                r#"# Override settings for local development
 # This file is gitignored by default
 
 [server]
 port = 3000
-"#;
-    fs::write(project_path.join("config/local.toml"), local_config)?;
-
-    // Create .gitignore
-    let gitignore = r#"/target
-/config/local.toml
-.env
-"#;
-    fs::write(project_path.join(".gitignore"), gitignore)?;
-
-    // Create README
-    let readme = format!(
-        r#"# {}
-
-A dy-rs API project.
-
-## Getting Started
-
-```bash
-# Run the server
-cargo run
+"#,
+            ),
+            ConfigFormat::Yaml => (
+                r#"server:
+  host: 0.0.0.0
+  port: 3000
+
+database:
+  url: postgres://localhost/dy_rs
+  max_connections: 10
+"#,
+                r#"# Override settings for local development
+# This file is gitignored by default
 
-# The server will start at http://localhost:3000
-# Swagger UI: http://localhost:3000/docs
-# Health check: http://localhost:3000/health
-```
+server:
+  port: 3000
+"#,
+            ),
+            ConfigFormat::Json => (
+                r#"{
+  "server": {
+    "host": "0.0.0.0",
+    "port": 3000
+  },
+  "database": {
+    "url": "postgres://localhost/dy_rs",
+    "max_connections": 10
+  }
+}
+"#,
+                r#"{
+  "server": {
+    "port": 3000
+  }
+}
+"#,
+            ),
+        }
+    }
+}
 
-## API Endpoints
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
 
-- `POST /users` - Create a new user
-- `GET /users` - List all users
-- `GET /users/:id` - Get a user by ID
+    match cli.command {
+        Commands::New {
+            name,
+            template,
+            config_format,
+            vars,
+        } => {
+            create_project(&name, &template, config_format, &vars)?;
+        }
+        Commands::Generate {
+            generator: Generator::Resource { name, fields },
+        } => {
+            generate::resource(Path::new("."), &name, &fields)?;
+        }
+        Commands::Secrets { command } => {
+            run_secrets(command)?;
+        }
+        Commands::Dev => {
+            run_dev_mode()?;
+        }
+        Commands::Mock { spec, port } => {
+            run_mock_server(&spec, port)?;
+        }
+        Commands::Bench {
+            internal,
+            filter,
+            save_baseline,
+            baseline,
+            threshold,
+        } => {
+            run_benchmarks(internal, filter, save_baseline, baseline, threshold)?;
+        }
+    }
 
-## Configuration
+    Ok(())
+}
 
-Configuration is loaded from:
-1. `config/default.toml` - Default settings
-2. `config/local.toml` - Local overrides (gitignored)
-3. Environment variables (prefixed with `APP__`)
+fn run_secrets(command: SecretsCommand) -> anyhow::Result<()> {
+    use dy_rs::secrets::{MASTER_KEY_ENV, MasterKey};
+
+    match command {
+        SecretsCommand::Keygen => println!("{}", MasterKey::generate()),
+        SecretsCommand::Encrypt { value } => {
+            let key = MasterKey::from_env()
+                .map_err(|e| anyhow::anyhow!("{e}"))?
+                .with_context(|| {
+                    format!("Set {MASTER_KEY_ENV}, e.g. to a key from `dy secrets keygen`")
+                })?;
+            let value = match value {
+                Some(value) => value,
+                None => {
+                    let mut value = String::new();
+                    std::io::stdin().read_to_string(&mut value)?;
+                    value.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            println!("{}", key.encrypt(&value));
+        }
+    }
+    Ok(())
+}
 
-Example:
-```bash
-APP__SERVER__PORT=8080 cargo run
-```
+/// A `--var NAME=VALUE` argument
+fn parse_var(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got '{arg}'")),
+    }
+}
 
-## Development
+fn create_project(
+    name: &str,
+    template: &str,
+    config_format: ConfigFormat,
+    vars: &[(String, String)],
+) -> anyhow::Result<()> {
+    let project_path = Path::new(name);
+    if project_path.exists() {
+        anyhow::bail!("Directory '{}' already exists", name);
+    }
 
-```bash
-# Run with hot reload (requires cargo-watch)
-cargo install cargo-watch
-cargo watch -x run
-```
-"#,
-        name
-    );
-    fs::write(project_path.join("README.md"), readme)?;
+    if let Some(template) = templates::find(template) {
+        if !vars.is_empty() {
+            anyhow::bail!("Built-in templates take no --var values");
+        }
+        println!(
+            "🚀 Creating new dy-rs project: {} ({})",
+            name, template.name
+        );
+        create_builtin_project(project_path, name, template, config_format)?;
+    } else if let Some(source) = templates::custom::Source::parse(template) {
+        let template = templates::custom::CustomTemplate::fetch(&source)?;
+        println!(
+            "🚀 Creating new dy-rs project: {} ({})",
+            name,
+            template.name()
+        );
+        if let Some(description) = template.description() {
+            println!("   {}", description);
+        }
+        template.generate(project_path, name, vars)?;
+    } else {
+        anyhow::bail!(
+            "Unknown template '{}' (available: {}, or github:org/repo, a git URL or a local path)",
+            template,
+            templates::names()
+        );
+    }
 
     println!("✅ Project created successfully!");
     println!("\n📦 Next steps:");
@@ -239,18 +311,53 @@ cargo watch -x run
     Ok(())
 }
 
+/// Files of a built-in template, with config files in `config_format`
+fn create_builtin_project(
+    project_path: &Path,
+    name: &str,
+    template: &templates::Template,
+    config_format: ConfigFormat,
+) -> anyhow::Result<()> {
+    // Create the template's files and README
+    let extension = config_format.extension();
+    template.generate(project_path, name, extension)?;
+
+    // Create config files
+    fs::create_dir_all(project_path.join("config"))?;
+    let (default_config, local_config) = config_format.files();
+    fs::write(
+        project_path.join(format!("config/default.{extension}")),
+        default_config,
+    )?;
+    fs::write(
+        project_path.join(format!("config/local.{extension}")),
+        local_config,
+    )?;
+
+    // Create .gitignore
+    let gitignore = format!(
+        r#"/target
+/config/local.{extension}
+.env
+"#
+    );
+    fs::write(project_path.join(".gitignore"), gitignore)?;
+
+    Ok(())
+}
+
 fn run_dev_mode() -> anyhow::Result<()> {
     println!("🔥 Starting development mode with hot reload...");
 
     // Check if cargo-watch is installed
-    let status = Command::new("cargo").args(&["watch", "--version"]).output();
+    let status = Command::new("cargo").args(["watch", "--version"]).output();
 
     if status.is_err() {
         println!("⚠️  cargo-watch is not installed.");
         println!("Installing cargo-watch...");
 
         let install_status = Command::new("cargo")
-            .args(&["install", "cargo-watch"])
+            .args(["install", "cargo-watch"])
             .status()?;
 
         if !install_status.success() {
@@ -260,7 +367,7 @@ fn run_dev_mode() -> anyhow::Result<()> {
 
     // Run cargo watch
     let status = Command::new("cargo")
-        .args(&["watch", "-x", "run"])
+        .args(["watch", "-x", "run"])
         .status()?;
 
     if !status.success() {
@@ -269,3 +376,67 @@ fn run_dev_mode() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn run_mock_server(spec: &str, port: u16) -> anyhow::Result<()> {
+    let text = fs::read_to_string(spec)
+        .map_err(|err| anyhow::anyhow!("Failed to read '{}': {}", spec, err))?;
+    let is_yaml = spec.ends_with(".yaml") || spec.ends_with(".yml");
+    let doc = if is_yaml {
+        dy_rs::openapi::from_yaml(&text).map_err(|err| anyhow::anyhow!(err))?
+    } else {
+        dy_rs::openapi::from_json(&text)?
+    };
+
+    println!("🎭 Mocking {} from {}", doc.info.title, spec);
+
+    // SAFETY: set before the runtime starts, while this is the only thread.
+    unsafe { std::env::set_var("APP__SERVER__PORT", port.to_string()) };
+
+    tokio::runtime::Runtime::new()?.block_on(async {
+        dy_rs::App::new()
+            .auto_configure()
+            .mock_from_openapi(doc)
+            .run()
+            .await
+            .map_err(|err| anyhow::anyhow!(err.to_string()))
+    })
+}
+
+fn run_benchmarks(
+    internal: bool,
+    filter: Option<String>,
+    save_baseline: Option<String>,
+    baseline: Option<String>,
+    threshold: f64,
+) -> anyhow::Result<()> {
+    let mut command = Command::new("cargo");
+    command.arg("bench");
+    if internal {
+        if !Path::new("dy-rs/benches/hot_paths.rs").exists() {
+            anyhow::bail!("--internal must be run from the root of a dy-rs checkout");
+        }
+        println!("⏱️  Running dy-rs hot-path benchmarks...");
+        command.args(["-p", "dy-rs", "--bench", "hot_paths", "--"]);
+        command.args(filter);
+        if let Some(name) = save_baseline {
+            command.args(["--save-baseline", &name]);
+        }
+        if let Some(name) = baseline {
+            command.args(["--baseline", &name, "--threshold", &threshold.to_string()]);
+        }
+    } else {
+        println!("⏱️  Running benchmarks...");
+        if save_baseline.is_some() || baseline.is_some() {
+            anyhow::bail!("Baselines are only supported with --internal");
+        }
+        command.arg("--");
+        command.args(filter);
+    }
+
+    let status = command.status()?;
+    if !status.success() {
+        anyhow::bail!("Benchmarks failed or regressed");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,526 @@
+//! Templates from outside the CLI
+//!
+//! Besides the built-in templates, `dy new --template` takes a local
+//! directory (`./starters/service`), a git URL, or `github:org/repo`, so
+//! teams can maintain their own starters. Git sources may name a branch or
+//! tag after `#`, and GitHub ones a directory inside the repository:
+//! `github:acme/starters/http-service#v2`.
+//!
+//! Files ending in `.hbs` are rendered with Handlebars and written without
+//! the suffix; everything else is copied as it is, so workflow files using
+//! `${{ ... }}` need no escaping. Paths may use variables too, e.g.
+//! `src/{{crate_name}}.rs`. Every template gets `name`, `crate_name` and
+//! `dy_version`; others are declared in a `dy-template.toml` at its root:
+//!
+//! ```toml
+//! name = "acme-service"
+//! description = "Acme's standard HTTP service"
+//! exclude = ["docs/maintainers.md"]
+//!
+//! [variables]
+//! team = { description = "Owning team" }
+//! database = { description = "Database name", default = "{{crate_name}}" }
+//! ```
+//!
+//! Values are given as `--var team=payments`. Variables without a default
+//! must be given; defaults may use the built-in variables.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use handlebars::Handlebars;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+/// Name of the manifest at the root of a template
+pub const MANIFEST: &str = "dy-template.toml";
+
+/// Prefixes of `--template` values that are git URLs
+const GIT_PREFIXES: &[&str] = &["https://", "http://", "ssh://", "git://", "file://", "git@"];
+
+/// Where a custom template comes from
+#[derive(Debug, PartialEq, Eq)]
+pub enum Source {
+    Local(PathBuf),
+    Git {
+        url: String,
+        /// Branch or tag to check out
+        reference: Option<String>,
+        /// Directory of the template inside the repository
+        subdir: Option<String>,
+    },
+}
+
+impl Source {
+    /// Parse a `--template` value, or `None` if it names no custom template
+    pub fn parse(spec: &str) -> Option<Self> {
+        if let Some(repo) = spec.strip_prefix("github:") {
+            let (path, reference) = split_reference(repo);
+            let mut parts = path.splitn(3, '/');
+            let org = parts.next().filter(|org| !org.is_empty())?;
+            let repo = parts.next().filter(|repo| !repo.is_empty())?;
+            return Some(Source::Git {
+                url: format!("https://github.com/{org}/{repo}.git"),
+                reference,
+                subdir: parts.next().map(|dir| dir.trim_matches('/').to_string()),
+            });
+        }
+
+        let (url, reference) = split_reference(spec);
+        if GIT_PREFIXES.iter().any(|prefix| url.starts_with(prefix)) || url.ends_with(".git") {
+            return Some(Source::Git {
+                url: url.to_string(),
+                reference,
+                subdir: None,
+            });
+        }
+
+        let path = Path::new(spec);
+        (spec.starts_with('.') || path.is_absolute() || path.is_dir())
+            .then(|| Source::Local(path.to_path_buf()))
+    }
+}
+
+/// `spec` and the reference after its `#`, if any
+fn split_reference(spec: &str) -> (&str, Option<String>) {
+    match spec.split_once('#') {
+        Some((spec, reference)) if !reference.is_empty() => (spec, Some(reference.to_string())),
+        Some((spec, _)) => (spec, None),
+        None => (spec, None),
+    }
+}
+
+/// The contents of `dy-template.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Paths, relative to the template root, not to copy
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub variables: BTreeMap<String, Variable>,
+}
+
+/// A variable declared by a template
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Variable {
+    pub description: Option<String>,
+    /// Value when none is given, itself a Handlebars template
+    pub default: Option<String>,
+}
+
+/// A custom template, ready to generate projects from
+pub struct CustomTemplate {
+    root: PathBuf,
+    manifest: Manifest,
+    // Removes the clone of a git template once done
+    _checkout: Option<Checkout>,
+}
+
+impl CustomTemplate {
+    /// Open a local template or clone a git one
+    pub fn fetch(source: &Source) -> anyhow::Result<Self> {
+        match source {
+            Source::Local(path) => Self::open(path.clone(), None),
+            Source::Git {
+                url,
+                reference,
+                subdir,
+            } => {
+                let checkout = Checkout::new(url, reference.as_deref())?;
+                let root = match subdir {
+                    Some(subdir) => checkout.0.join(inside("Template directory", subdir)?),
+                    None => checkout.0.clone(),
+                };
+                Self::open(root, Some(checkout))
+            }
+        }
+    }
+
+    fn open(root: PathBuf, checkout: Option<Checkout>) -> anyhow::Result<Self> {
+        if !root.is_dir() {
+            anyhow::bail!("Template directory '{}' does not exist", root.display());
+        }
+        let manifest_path = root.join(MANIFEST);
+        let manifest = if manifest_path.exists() {
+            let text = fs::read_to_string(&manifest_path)?;
+            toml::from_str(&text).with_context(|| format!("Invalid {}", manifest_path.display()))?
+        } else {
+            Manifest::default()
+        };
+        Ok(Self {
+            root,
+            manifest,
+            _checkout: checkout,
+        })
+    }
+
+    /// The manifest's name, or the template directory's
+    pub fn name(&self) -> String {
+        self.manifest.name.clone().unwrap_or_else(|| {
+            self.root
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "custom".to_string())
+        })
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.manifest.description.as_deref()
+    }
+
+    /// Write project `name` into `dir`, with the `--var` values `given`
+    pub fn generate(
+        &self,
+        dir: &Path,
+        name: &str,
+        given: &[(String, String)],
+    ) -> anyhow::Result<()> {
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(true);
+        handlebars.register_escape_fn(handlebars::no_escape);
+
+        let data = self.variables(&handlebars, name, given)?;
+
+        // Every path is checked before anything is written
+        let mut files = Vec::new();
+        for relative in template_files(&self.root, &self.manifest.exclude)? {
+            let mut target = relative.to_string_lossy().into_owned();
+            if target.contains("{{") {
+                target = handlebars
+                    .render_template(&target, &data)
+                    .with_context(|| format!("Failed to render path {}", relative.display()))?;
+            }
+            let rendered = target.strip_suffix(".hbs").map(str::to_string);
+            let target = inside("Template path", rendered.as_deref().unwrap_or(&target))?;
+            files.push((
+                self.root.join(&relative),
+                dir.join(target),
+                rendered.is_some(),
+            ));
+        }
+
+        for (source, target, render) in files {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            if render {
+                let text = fs::read_to_string(&source)?;
+                let contents = handlebars
+                    .render_template(&text, &data)
+                    .with_context(|| format!("Failed to render {}", source.display()))?;
+                fs::write(target, contents)?;
+            } else {
+                fs::copy(&source, target)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Values of the built-in and declared variables
+    fn variables(
+        &self,
+        handlebars: &Handlebars,
+        name: &str,
+        given: &[(String, String)],
+    ) -> anyhow::Result<Value> {
+        let mut values = Map::new();
+        values.insert("name".to_string(), json!(name));
+        values.insert("crate_name".to_string(), json!(name.replace('-', "_")));
+        values.insert("dy_version".to_string(), json!(env!("CARGO_PKG_VERSION")));
+        let builtins = Value::Object(values.clone());
+
+        for (key, _) in given {
+            if !self.manifest.variables.contains_key(key) {
+                anyhow::bail!("Template '{}' has no variable '{}'", self.name(), key);
+            }
+        }
+
+        let mut missing = Vec::new();
+        for (key, variable) in &self.manifest.variables {
+            if builtins.get(key).is_some() {
+                anyhow::bail!(
+                    "Template variable '{}' is built in and can't be declared",
+                    key
+                );
+            }
+            let value = match given.iter().rev().find(|(given, _)| given == key) {
+                Some((_, value)) => value.clone(),
+                None => match &variable.default {
+                    Some(default) => handlebars
+                        .render_template(default, &builtins)
+                        .with_context(|| format!("Invalid default of variable '{key}'"))?,
+                    None => {
+                        missing.push(match &variable.description {
+                            Some(description) => format!("{key} ({description})"),
+                            None => key.clone(),
+                        });
+                        continue;
+                    }
+                },
+            };
+            values.insert(key.clone(), json!(value));
+        }
+
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "Missing template variables: {} (set them with --var NAME=VALUE)",
+                missing.join(", ")
+            );
+        }
+
+        Ok(Value::Object(values))
+    }
+}
+
+/// `path`, if it stays inside the directory it's relative to
+///
+/// Paths come from templates, which may be anyone's, and from `--var`
+/// values, so `..` and absolute paths are refused.
+fn inside<'a>(what: &str, path: &'a str) -> anyhow::Result<&'a Path> {
+    let relative = Path::new(path);
+    let escapes = relative.components().any(|component| {
+        matches!(
+            component,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_)
+        )
+    });
+    if escapes || relative.as_os_str().is_empty() {
+        anyhow::bail!("{} '{}' is outside the project", what, path);
+    }
+    Ok(relative)
+}
+
+/// Files under `root` to generate from, relative to it and sorted, leaving
+/// out `.git`, the manifest and the `exclude`d paths
+fn template_files(root: &Path, exclude: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            if relative == Path::new(".git")
+                || relative == Path::new(MANIFEST)
+                || exclude.iter().any(|path| relative.starts_with(path))
+            {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                dirs.push(relative);
+            } else {
+                files.push(relative);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// A shallow clone in a temporary directory, removed when dropped
+struct Checkout(PathBuf);
+
+impl Checkout {
+    fn new(url: &str, reference: Option<&str>) -> anyhow::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos())
+            .unwrap_or_default();
+        let checkout =
+            Self(std::env::temp_dir().join(format!("dy-template-{}-{nanos}", std::process::id())));
+
+        println!("📥 Cloning {}", url);
+        let mut command = Command::new("git");
+        command.args([
+            "-c",
+            "advice.detachedHead=false",
+            "clone",
+            "--quiet",
+            "--depth",
+            "1",
+        ]);
+        if let Some(reference) = reference {
+            command.args(["--branch", reference]);
+        }
+        let status = command
+            .arg("--")
+            .arg(url)
+            .arg(&checkout.0)
+            .status()
+            .context("Failed to run git, which custom templates need")?;
+        if !status.success() {
+            anyhow::bail!("Failed to clone template {}", url);
+        }
+
+        Ok(checkout)
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(url: &str, reference: Option<&str>, subdir: Option<&str>) -> Source {
+        Source::Git {
+            url: url.to_string(),
+            reference: reference.map(str::to_string),
+            subdir: subdir.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn sources_are_parsed() {
+        assert_eq!(
+            Source::parse("github:acme/starters"),
+            Some(git("https://github.com/acme/starters.git", None, None))
+        );
+        assert_eq!(
+            Source::parse("github:acme/starters/http-service#v2"),
+            Some(git(
+                "https://github.com/acme/starters.git",
+                Some("v2"),
+                Some("http-service")
+            ))
+        );
+        assert_eq!(
+            Source::parse("git@gitlab.com:acme/service.git#main"),
+            Some(git("git@gitlab.com:acme/service.git", Some("main"), None))
+        );
+        assert_eq!(
+            Source::parse("https://git.acme.dev/starters/service"),
+            Some(git("https://git.acme.dev/starters/service", None, None))
+        );
+        assert_eq!(
+            Source::parse("./starters/service"),
+            Some(Source::Local(PathBuf::from("./starters/service")))
+        );
+        assert_eq!(Source::parse("github:acme"), None);
+        assert_eq!(Source::parse("soap"), None);
+    }
+
+    /// A template with a manifest, rendered and copied files
+    fn write_template(root: &Path) {
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join(".github/workflows")).unwrap();
+        fs::create_dir_all(root.join("notes")).unwrap();
+        fs::write(
+            root.join(MANIFEST),
+            r#"
+name = "acme-service"
+exclude = ["notes"]
+
+[variables]
+team = { description = "Owning team" }
+database = { default = "{{crate_name}}_db" }
+"#,
+        )
+        .unwrap();
+        fs::write(
+            root.join("Cargo.toml.hbs"),
+            "[package]\nname = \"{{name}}\"\n# team: {{team}}, db: {{database}}\n",
+        )
+        .unwrap();
+        fs::write(root.join("src/{{crate_name}}.rs.hbs"), "// {{team}}\n").unwrap();
+        fs::write(
+            root.join(".github/workflows/ci.yml"),
+            "token: ${{ secrets.TOKEN }}\n",
+        )
+        .unwrap();
+        fs::write(root.join("notes/maintainers.md"), "internal\n").unwrap();
+    }
+
+    #[test]
+    fn local_templates_render_variables() {
+        let root = std::env::temp_dir().join(format!("dy-custom-{}", std::process::id()));
+        let template_dir = root.join("template");
+        let project = root.join("project");
+        write_template(&template_dir);
+
+        let template = CustomTemplate::fetch(&Source::Local(template_dir)).unwrap();
+        assert_eq!(template.name(), "acme-service");
+
+        let err = template.generate(&project, "my-app", &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Missing template variables: team (Owning team) (set them with --var NAME=VALUE)"
+        );
+        let err = template
+            .generate(&project, "my-app", &[("teem".into(), "payments".into())])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Template 'acme-service' has no variable 'teem'"
+        );
+        assert!(!project.exists());
+
+        template
+            .generate(&project, "my-app", &[("team".into(), "payments".into())])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(project.join("Cargo.toml")).unwrap(),
+            "[package]\nname = \"my-app\"\n# team: payments, db: my_app_db\n"
+        );
+        assert_eq!(
+            fs::read_to_string(project.join("src/my_app.rs")).unwrap(),
+            "// payments\n"
+        );
+        assert_eq!(
+            fs::read_to_string(project.join(".github/workflows/ci.yml")).unwrap(),
+            "token: ${{ secrets.TOKEN }}\n"
+        );
+        assert!(!project.join("notes").exists());
+        assert!(!project.join(MANIFEST).exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn paths_may_not_leave_the_project() {
+        let root = std::env::temp_dir().join(format!("dy-escape-{}", std::process::id()));
+        let template_dir = root.join("template");
+        let project = root.join("project");
+        fs::create_dir_all(&template_dir).unwrap();
+        fs::write(
+            template_dir.join(MANIFEST),
+            "[variables]\ndir = { default = \"src\" }\n",
+        )
+        .unwrap();
+        fs::write(template_dir.join("README.md"), "readme\n").unwrap();
+        fs::write(template_dir.join("{{dir}}.txt"), "owned\n").unwrap();
+
+        let template = CustomTemplate::fetch(&Source::Local(template_dir)).unwrap();
+        for dir in ["../escaped", "/tmp/escaped", "a/../../escaped"] {
+            let err = template
+                .generate(&project, "my-app", &[("dir".into(), dir.into())])
+                .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("Template path '{dir}.txt' is outside the project")
+            );
+        }
+        // Nothing is written once a path is refused
+        assert!(!project.exists());
+        assert!(!root.join("escaped.txt").exists());
+
+        assert!(inside("Template directory", "starters/http").is_ok());
+        assert!(inside("Template directory", "../starters").is_err());
+        assert!(inside("Template directory", "").is_err());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Project templates for `dy new`
+//!
+//! A template's files live under `templates/<name>/`, are embedded in the
+//! binary and rendered with Handlebars, with `{{name}}` and `{{dy_version}}`
+//! available to every file. Cargo manifests are stored as `Cargo.toml.hbs`
+//! so cargo doesn't mistake the templates for packages.
+
+use std::fs;
+use std::path::Path;
+
+use handlebars::Handlebars;
+use serde_json::json;
+
+pub mod custom;
+
+/// A kind of project `dy new` can create
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Paths in the project and the sources they're rendered from
+    files: &'static [(&'static str, &'static str)],
+    /// README section on what the project serves
+    usage: &'static str,
+}
+
+macro_rules! template_file {
+    ($template:literal, $path:literal) => {
+        (
+            $path,
+            include_str!(concat!("../../templates/", $template, "/", $path)),
+        )
+    };
+}
+
+macro_rules! cargo_toml {
+    ($template:literal) => {
+        (
+            "Cargo.toml",
+            include_str!(concat!("../../templates/", $template, "/Cargo.toml.hbs")),
+        )
+    };
+}
+
+macro_rules! usage {
+    ($template:literal) => {
+        include_str!(concat!("../../templates/", $template, "/USAGE.md"))
+    };
+}
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "rest-api",
+        description: "REST API with CRUD operations",
+        files: &[
+            cargo_toml!("rest-api"),
+            template_file!("rest-api", "src/main.rs"),
+        ],
+        usage: usage!("rest-api"),
+    },
+    Template {
+        name: "minimal",
+        description: "Minimal API with a single route",
+        files: &[
+            cargo_toml!("minimal"),
+            template_file!("minimal", "src/main.rs"),
+        ],
+        usage: usage!("minimal"),
+    },
+    Template {
+        name: "graphql",
+        description: "GraphQL API with async-graphql",
+        files: &[
+            cargo_toml!("graphql"),
+            template_file!("graphql", "src/main.rs"),
+        ],
+        usage: usage!("graphql"),
+    },
+    Template {
+        name: "grpc",
+        description: "gRPC service with tonic",
+        files: &[
+            cargo_toml!("grpc"),
+            template_file!("grpc", "build.rs"),
+            template_file!("grpc", "proto/greeter.proto"),
+            template_file!("grpc", "src/main.rs"),
+        ],
+        usage: usage!("grpc"),
+    },
+    Template {
+        name: "websocket",
+        description: "WebSocket chat server",
+        files: &[
+            cargo_toml!("websocket"),
+            template_file!("websocket", "src/main.rs"),
+        ],
+        usage: usage!("websocket"),
+    },
+    Template {
+        name: "worker",
+        description: "Background job worker with a message queue and cron schedule",
+        files: &[
+            cargo_toml!("worker"),
+            template_file!("worker", "src/main.rs"),
+        ],
+        usage: usage!("worker"),
+    },
+];
+
+const README: &str = include_str!("../../templates/README.md.hbs");
+
+/// The template called `name`
+pub fn find(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|template| template.name == name)
+}
+
+/// Names of all templates, for help and error messages
+pub fn names() -> String {
+    TEMPLATES
+        .iter()
+        .map(|template| template.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Template {
+    /// Write the template's files and README for project `name` into `dir`
+    pub fn generate(&self, dir: &Path, name: &str, config_extension: &str) -> anyhow::Result<()> {
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(true);
+        handlebars.register_escape_fn(handlebars::no_escape);
+
+        let data = json!({
+            "name": name,
+            "dy_version": env!("CARGO_PKG_VERSION"),
+            "description": self.description,
+            "usage": self.usage,
+            "config_extension": config_extension,
+        });
+
+        for (path, source) in self.files.iter().chain([&("README.md", README)]) {
+            let contents = handlebars.render_template(source, &data)?;
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_render_every_file() {
+        for template in TEMPLATES {
+            let dir = std::env::temp_dir().join(format!(
+                "dy-template-{}-{}",
+                template.name,
+                std::process::id()
+            ));
+            template.generate(&dir, "my-app", "toml").unwrap();
+
+            let cargo_toml = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+            assert!(
+                cargo_toml.contains(r#"name = "my-app""#),
+                "{}",
+                template.name
+            );
+            assert!(
+                cargo_toml.contains(&format!(r#"dy-rs = "{}""#, env!("CARGO_PKG_VERSION"))),
+                "{}",
+                template.name
+            );
+            assert!(dir.join("src/main.rs").exists(), "{}", template.name);
+            let readme = fs::read_to_string(dir.join("README.md")).unwrap();
+            assert!(readme.starts_with("# my-app\n"), "{}", template.name);
+            assert!(readme.contains("config/default.toml"), "{}", template.name);
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn templates_are_found_by_name() {
+        assert_eq!(find("grpc").unwrap().name, "grpc");
+        assert!(find("soap").is_none());
+        assert_eq!(
+            names(),
+            "rest-api, minimal, graphql, grpc, websocket, worker"
+        );
+    }
+}
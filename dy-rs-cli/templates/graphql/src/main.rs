@@ -0,0 +1,66 @@
+use std::sync::Mutex;
+
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{Context, EmptySubscription, ID, Object, Result, Schema, SimpleObject};
+use async_graphql_axum::GraphQL;
+use axum::response::Html;
+use dy_rs::prelude::*;
+
+#[derive(SimpleObject, Clone)]
+struct Book {
+    id: ID,
+    title: String,
+    author: String,
+}
+
+/// In-memory "database" for demo purposes
+type Library = Mutex<Vec<Book>>;
+
+struct Query;
+
+#[Object]
+impl Query {
+    async fn books(&self, ctx: &Context<'_>) -> Result<Vec<Book>> {
+        Ok(ctx.data::<Library>()?.lock().unwrap().clone())
+    }
+
+    async fn book(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Book>> {
+        let books = ctx.data::<Library>()?.lock().unwrap();
+        Ok(books.iter().find(|book| book.id == id).cloned())
+    }
+}
+
+struct Mutation;
+
+#[Object]
+impl Mutation {
+    async fn add_book(&self, ctx: &Context<'_>, title: String, author: String) -> Result<Book> {
+        let book = Book {
+            id: ID(Uuid::new_v4().to_string()),
+            title,
+            author,
+        };
+        ctx.data::<Library>()?.lock().unwrap().push(book.clone());
+        Ok(book)
+    }
+}
+
+type LibrarySchema = Schema<Query, Mutation, EmptySubscription>;
+
+async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+#[tokio::main]
+async fn main() {
+    let schema: LibrarySchema = Schema::build(Query, Mutation, EmptySubscription)
+        .data(Library::default())
+        .finish();
+
+    App::new()
+        .auto_configure()
+        .route("/graphql", get(graphiql).post_service(GraphQL::new(schema)))
+        .run()
+        .await
+        .unwrap();
+}
@@ -0,0 +1,42 @@
+use dy_rs::prelude::*;
+use tonic::{Request, Response, Status};
+
+use greeter::greeter_server::{Greeter, GreeterServer};
+use greeter::{HelloReply, HelloRequest};
+
+pub mod greeter {
+    tonic::include_proto!("greeter");
+}
+
+#[derive(Default)]
+struct MyGreeter;
+
+#[tonic::async_trait]
+impl Greeter for MyGreeter {
+    async fn say_hello(
+        &self,
+        request: Request<HelloRequest>,
+    ) -> Result<Response<HelloReply>, Status> {
+        let name = request.into_inner().name;
+        if name.is_empty() {
+            return Err(Status::invalid_argument("name is required"));
+        }
+        Ok(Response::new(HelloReply {
+            message: format!("Hello, {name}!"),
+        }))
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // gRPC services are served next to the usual HTTP routes (/health,
+    // /docs, ...) on the same port
+    let grpc = tonic::service::Routes::new(GreeterServer::new(MyGreeter)).into_axum_router();
+
+    App::new()
+        .auto_configure()
+        .mount(grpc)
+        .run()
+        .await
+        .unwrap();
+}
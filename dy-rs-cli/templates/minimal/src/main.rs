@@ -0,0 +1,22 @@
+use dy_rs::prelude::*;
+
+#[derive(Serialize)]
+struct Greeting {
+    message: String,
+}
+
+async fn hello() -> ApiResult<Greeting> {
+    Ok(Json(Greeting {
+        message: "Hello from dy-rs!".to_string(),
+    }))
+}
+
+#[tokio::main]
+async fn main() {
+    App::new()
+        .auto_configure()
+        .route("/hello", get(hello))
+        .run()
+        .await
+        .unwrap();
+}
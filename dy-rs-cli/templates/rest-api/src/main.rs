@@ -0,0 +1,70 @@
+use dy_rs::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct User {
+    id: Uuid,
+    email: String,
+    name: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Validate)]
+struct CreateUserRequest {
+    #[validate(email(message = "Invalid email format"))]
+    email: String,
+
+    #[validate(length(min = 2, max = 100))]
+    name: String,
+}
+
+type Database = Arc<Mutex<HashMap<Uuid, User>>>;
+
+async fn create_user(
+    State(db): State<Database>,
+    ValidatedJson(payload): ValidatedJson<CreateUserRequest>,
+) -> ApiResult<User> {
+    let user = User {
+        id: Uuid::new_v4(),
+        email: payload.email,
+        name: payload.name,
+        created_at: Utc::now(),
+    };
+
+    db.lock().unwrap().insert(user.id, user.clone());
+    Ok(Json(user))
+}
+
+async fn list_users(State(db): State<Database>) -> ApiResult<Vec<User>> {
+    let users: Vec<User> = db.lock().unwrap().values().cloned().collect();
+    Ok(Json(users))
+}
+
+async fn get_user(State(db): State<Database>, Path(id): Path<Uuid>) -> ApiResult<User> {
+    let user = db
+        .lock()
+        .unwrap()
+        .get(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("User {id} not found")))?
+        .clone();
+    Ok(Json(user))
+}
+
+fn routes() -> Router<Database> {
+    Router::new()
+        .route("/users", post(create_user).get(list_users))
+        .route("/users/{id}", get(get_user))
+}
+
+#[tokio::main]
+async fn main() {
+    let db: Database = Arc::new(Mutex::new(HashMap::new()));
+
+    App::new()
+        .auto_configure()
+        .mount(routes().with_state(db))
+        .run()
+        .await
+        .unwrap();
+}
@@ -0,0 +1,49 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::Response;
+use dy_rs::prelude::*;
+use tokio::sync::broadcast;
+
+/// Messages sent by any client, relayed to every connected client
+type Chat = broadcast::Sender<String>;
+
+async fn chat(ws: WebSocketUpgrade, State(chat): State<Chat>) -> Response {
+    ws.on_upgrade(move |socket| relay(socket, chat))
+}
+
+async fn relay(mut socket: WebSocket, chat: Chat) {
+    let mut messages = chat.subscribe();
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    // Fails only when nobody is listening, not even us
+                    let _ = chat.send(text.to_string());
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            outgoing = messages.recv() => match outgoing {
+                Ok(text) => {
+                    if socket.send(Message::text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                // Slow clients miss messages rather than hold everyone up
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (chat_tx, _) = broadcast::channel::<String>(100);
+
+    App::new()
+        .auto_configure()
+        .mount(Router::new().route("/ws", get(chat)).with_state(chat_tx))
+        .run()
+        .await
+        .unwrap();
+}
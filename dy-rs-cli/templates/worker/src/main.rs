@@ -0,0 +1,73 @@
+use dy_rs::cron::CronSchedule;
+use dy_rs::messaging::{ConsumerGroup, MemoryBroker, Message, SharedBroker};
+use dy_rs::prelude::*;
+
+/// Topic the jobs are published to
+const JOBS: &str = "jobs";
+
+#[derive(Serialize, Deserialize)]
+struct Job {
+    id: Uuid,
+    kind: String,
+}
+
+impl Job {
+    fn new(kind: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            kind: kind.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EnqueueRequest {
+    kind: String,
+}
+
+/// Queue a job for the workers
+async fn enqueue(broker: SharedBroker, Json(request): Json<EnqueueRequest>) -> ApiResult<Job> {
+    let job = Job::new(request.kind);
+    broker.publish_json(JOBS, &job).await?;
+    Ok(Json(job))
+}
+
+/// Run one job; an error has it delivered again, then dead-lettered
+async fn process(message: Message) -> Result<(), ApiError> {
+    let job: Job = message.json()?;
+    tracing::info!(id = %job.id, kind = %job.kind, "processing job");
+    Ok(())
+}
+
+/// Queue a `cleanup` job every five minutes until shutdown
+fn schedule_cleanup(tasks: TaskScope, broker: SharedBroker) {
+    let schedule = CronSchedule::parse("*/5 * * * *").expect("valid cron expression");
+    tasks.clone().spawn("cleanup_schedule", async move {
+        while let Some(next) = schedule.next_after(Utc::now()) {
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tasks.cancelled() => break,
+                _ = tokio::time::sleep(wait) => {}
+            }
+            if let Err(err) = broker.publish_json(JOBS, &Job::new("cleanup")).await {
+                tracing::error!(error = %err, "failed to queue cleanup");
+            }
+        }
+    });
+}
+
+#[tokio::main]
+async fn main() {
+    // Swap in `NatsBroker` (the `nats` feature) to share jobs between
+    // instances
+    let broker = SharedBroker::new(MemoryBroker::new());
+
+    let app = App::new()
+        .auto_configure()
+        .with_broker(broker.clone())
+        .consume(ConsumerGroup::new(broker.clone(), JOBS, "workers", process))
+        .route("/jobs", post(enqueue));
+    schedule_cleanup(app.tasks(), broker);
+
+    app.run().await.unwrap();
+}
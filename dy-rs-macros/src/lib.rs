@@ -2,12 +2,15 @@
 //!
 //! Currently exposes:
 //! - `#[dy_api(...)]` to document handlers and auto-register them for OpenAPI generation.
+//! - `#[dy_controller(...)]` to group `#[dy_api]` handlers under a shared prefix.
+//! - `#[derive(Entity)]` to map a struct to a table for `dy_rs::db::Repository`.
+//! - `#[derive(ApiErrorKind)]` to give application errors a status, code and message.
 
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
-    Expr, Ident, Lit, LitInt, LitStr, Meta, Token, Type, TypePath, parse_macro_input,
-    punctuated::Punctuated, spanned::Spanned,
+    Expr, FnArg, GenericArgument, Ident, Lit, LitInt, LitStr, Meta, PathArguments, ReturnType,
+    Token, Type, TypePath, parse_macro_input, punctuated::Punctuated, spanned::Spanned,
 };
 
 #[derive(Default)]
@@ -18,8 +21,73 @@ struct ApiArgs {
     response: Option<Type>,
     status: Option<LitInt>,
     tag: Option<LitStr>,
+    version: Option<LitStr>,
     summary: Option<LitStr>,
     description: Option<LitStr>,
+    security: Vec<LitStr>,
+    operation_id: Option<LitStr>,
+    deprecated: bool,
+    external_docs: Option<LitStr>,
+    external_docs_description: Option<LitStr>,
+    request_content_type: Option<LitStr>,
+    response_content_type: Option<LitStr>,
+    state: Option<Type>,
+    skip_route: bool,
+    errors: Vec<Type>,
+}
+
+fn lit_str_arg(value: Expr, name: &str) -> syn::Result<LitStr> {
+    match value {
+        Expr::Lit(syn::ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Ok(lit),
+        other => Err(syn::Error::new(
+            other.span(),
+            format!("{name} must be a string literal"),
+        )),
+    }
+}
+
+fn parse_security(value: Expr) -> syn::Result<Vec<LitStr>> {
+    match value {
+        Expr::Lit(syn::ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Ok(vec![lit]),
+        Expr::Array(array) => array
+            .elems
+            .into_iter()
+            .map(|elem| match elem {
+                Expr::Lit(syn::ExprLit {
+                    lit: Lit::Str(lit), ..
+                }) => Ok(lit),
+                other => Err(syn::Error::new(
+                    other.span(),
+                    "security entries must be string literals",
+                )),
+            })
+            .collect(),
+        other => Err(syn::Error::new(
+            other.span(),
+            "security must be a string literal or an array of string literals",
+        )),
+    }
+}
+
+fn parse_errors(value: Expr) -> syn::Result<Vec<Type>> {
+    let as_type = |expr: Expr| match expr {
+        Expr::Path(expr_path) => Ok(Type::Path(TypePath {
+            qself: expr_path.qself,
+            path: expr_path.path,
+        })),
+        other => Err(syn::Error::new(
+            other.span(),
+            "errors entries must be types",
+        )),
+    };
+    match value {
+        Expr::Array(array) => array.elems.into_iter().map(as_type).collect(),
+        other => Ok(vec![as_type(other)?]),
+    }
 }
 
 fn parse_args(args: Punctuated<Meta, Token![,]>) -> syn::Result<ApiArgs> {
@@ -111,6 +179,9 @@ fn parse_args(args: Punctuated<Meta, Token![,]>) -> syn::Result<ApiArgs> {
                     }
                 }
             }
+            Meta::NameValue(nv) if nv.path.is_ident("version") => {
+                out.version = Some(lit_str_arg(nv.value, "version")?);
+            }
             Meta::NameValue(nv) if nv.path.is_ident("summary") => {
                 if let Expr::Lit(expr_lit) = nv.value {
                     if let Lit::Str(lit) = expr_lit.lit {
@@ -135,10 +206,60 @@ fn parse_args(args: Punctuated<Meta, Token![,]>) -> syn::Result<ApiArgs> {
                     }
                 }
             }
+            Meta::NameValue(nv) if nv.path.is_ident("security") => {
+                out.security.extend(parse_security(nv.value)?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("operation_id") => {
+                out.operation_id = Some(lit_str_arg(nv.value, "operation_id")?);
+            }
+            Meta::Path(path) if path.is_ident("deprecated") => {
+                out.deprecated = true;
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("deprecated") => match nv.value {
+                Expr::Lit(syn::ExprLit {
+                    lit: Lit::Bool(lit),
+                    ..
+                }) => out.deprecated = lit.value,
+                other => {
+                    return Err(syn::Error::new(
+                        other.span(),
+                        "deprecated must be a boolean literal",
+                    ));
+                }
+            },
+            Meta::NameValue(nv) if nv.path.is_ident("external_docs") => {
+                out.external_docs = Some(lit_str_arg(nv.value, "external_docs")?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("external_docs_description") => {
+                out.external_docs_description =
+                    Some(lit_str_arg(nv.value, "external_docs_description")?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("state") => {
+                if let Expr::Path(expr_path) = nv.value {
+                    out.state = Some(Type::Path(TypePath {
+                        qself: expr_path.qself,
+                        path: expr_path.path,
+                    }));
+                } else {
+                    return Err(syn::Error::new(nv.value.span(), "state must be a type"));
+                }
+            }
+            Meta::Path(path) if path.is_ident("skip_route") => {
+                out.skip_route = true;
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("errors") => {
+                out.errors.extend(parse_errors(nv.value)?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("request_content_type") => {
+                out.request_content_type = Some(lit_str_arg(nv.value, "request_content_type")?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("response_content_type") => {
+                out.response_content_type = Some(lit_str_arg(nv.value, "response_content_type")?);
+            }
             other => {
                 return Err(syn::Error::new(
                     other.span(),
-                    "unsupported attribute, expected method, path, request, response, status, tag, summary, or description",
+                    "unsupported attribute, expected method, path, request, response, status, tag, version, summary, description, security, operation_id, deprecated, external_docs, external_docs_description, request_content_type, response_content_type, state, skip_route, or errors",
                 ));
             }
         }
@@ -147,10 +268,192 @@ fn parse_args(args: Punctuated<Meta, Token![,]>) -> syn::Result<ApiArgs> {
     Ok(out)
 }
 
+/// Handler metadata recovered from the function signature.
+#[derive(Default)]
+struct Inferred {
+    request: Option<Type>,
+    response: Option<Type>,
+    path: Option<Type>,
+    query: Vec<Type>,
+    state: Option<Type>,
+    status: Option<&'static str>,
+    request_content_type: Option<&'static str>,
+}
+
+/// Name of the outermost type, ignoring its module path.
+fn type_ident(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// Generic type arguments of the outermost type.
+fn generic_args(ty: &Type) -> Vec<&Type> {
+    let Type::Path(type_path) = ty else {
+        return Vec::new();
+    };
+    let Some(segment) = type_path.path.segments.last() else {
+        return Vec::new();
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn first_generic(ty: &Type) -> Option<Type> {
+    generic_args(ty).first().map(|ty| (*ty).clone())
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Tuple(tuple) if tuple.elems.is_empty())
+}
+
+fn infer_from_signature(sig: &syn::Signature) -> Inferred {
+    let mut inferred = Inferred::default();
+
+    for input in &sig.inputs {
+        let FnArg::Typed(arg) = input else { continue };
+        match type_ident(&arg.ty).as_deref() {
+            Some("ValidatedJson" | "Json") => inferred.request = first_generic(&arg.ty),
+            Some("ValidatedForm" | "Form") => {
+                inferred.request = first_generic(&arg.ty);
+                inferred.request_content_type = Some("application/x-www-form-urlencoded");
+            }
+            Some("BulkJson") => {
+                inferred.request = first_generic(&arg.ty).map(|item| syn::parse_quote!(Vec<#item>))
+            }
+            // The body is an encrypted string, so the plaintext type isn't documented.
+            Some("JweJson") => inferred.request_content_type = Some("application/jose"),
+            Some("Path" | "ValidatedPath") => inferred.path = first_generic(&arg.ty),
+            Some("Query") => inferred.query.extend(first_generic(&arg.ty)),
+            Some("Pagination") => inferred
+                .query
+                .push(syn::parse_quote!(::dy_rs::pagination::PaginationQuery)),
+            Some("State") => inferred.state = first_generic(&arg.ty),
+            _ => {}
+        }
+    }
+
+    if let ReturnType::Type(_, ty) = &sig.output {
+        inferred.response = match type_ident(ty).as_deref() {
+            Some("ApiResult" | "Json") => first_generic(ty),
+            Some("Result") => first_generic(ty)
+                .filter(|ok| type_ident(ok).as_deref() == Some("Json"))
+                .and_then(|ok| first_generic(&ok)),
+            Some("BulkResponse") => {
+                inferred.status = Some("207");
+                Some((**ty).clone())
+            }
+            _ => None,
+        }
+        .filter(|ty| !is_unit(ty));
+    }
+
+    inferred
+}
+
+/// Types documented through `__private::known_schema` rather than their
+/// `PartialSchema` impl, which utoipa only provides inside its derives.
+fn known_schema_type(ty: &Type) -> Option<String> {
+    type_ident(ty).filter(|ident| {
+        matches!(
+            ident.as_str(),
+            "Uuid" | "DateTime" | "NaiveDateTime" | "NaiveDate"
+        )
+    })
+}
+
+/// Schema expression for a path parameter of type `ty`.
+fn param_schema(ty: &Type) -> proc_macro2::TokenStream {
+    match known_schema_type(ty) {
+        Some(name) => quote! { known_schema(#name) },
+        None => quote! { (&Probe::<#ty>::new()).schema() },
+    }
+}
+
+/// Names of the `{param}` segments in a route path.
+fn path_param_names(path: &str) -> Vec<String> {
+    path.split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}'))
+        .map(|(name, _)| name.trim_start_matches('*').to_string())
+        .collect()
+}
+
+/// Type to register as a component for an inferred body type.
+///
+/// Containers are peeled down to their element type and primitives are
+/// skipped. Returns the type and whether it can be registered by name
+/// (non-generic) or only its nested components.
+fn component_type(ty: &Type) -> Option<(Type, bool)> {
+    const CONTAINERS: &[&str] = &["Vec", "Option", "Box", "Arc", "Rc", "HashSet", "BTreeSet"];
+    const PRIMITIVES: &[&str] = &[
+        "String",
+        "str",
+        "bool",
+        "char",
+        "i8",
+        "i16",
+        "i32",
+        "i64",
+        "i128",
+        "isize",
+        "u8",
+        "u16",
+        "u32",
+        "u64",
+        "u128",
+        "usize",
+        "f32",
+        "f64",
+        "Uuid",
+        "Value",
+        "DateTime",
+        "NaiveDate",
+        "NaiveDateTime",
+        "NaiveTime",
+    ];
+
+    let mut ty = ty.clone();
+    loop {
+        match &ty {
+            Type::Slice(slice) => ty = (*slice.elem).clone(),
+            Type::Array(array) => ty = (*array.elem).clone(),
+            Type::Reference(reference) => ty = (*reference.elem).clone(),
+            _ => match type_ident(&ty) {
+                Some(ident) if CONTAINERS.contains(&ident.as_str()) => {
+                    ty = first_generic(&ty)?;
+                }
+                _ => break,
+            },
+        }
+    }
+
+    let ident = type_ident(&ty)?;
+    if PRIMITIVES.contains(&ident.as_str()) {
+        return None;
+    }
+    let named = generic_args(&ty).is_empty();
+    Some((ty, named))
+}
+
 /// Document a handler for automatic OpenAPI generation.
 ///
 /// Example:
-/// ```rust
+/// ```rust,ignore
 /// #[dy_api(
 ///     method = get,
 ///     path = "/users/{id}",
@@ -161,6 +464,83 @@ fn parse_args(args: Punctuated<Meta, Token![,]>) -> syn::Result<ApiArgs> {
 /// )]
 /// async fn update_user(...) { ... }
 /// ```
+///
+/// `security` attaches security requirements to the operation. Each entry is a
+/// scheme name, optionally followed by `:` and space-separated scopes; listing
+/// several entries means any one of them is accepted:
+///
+/// ```rust,ignore
+/// #[dy_api(method = get, path = "/me", security = "bearerAuth")]
+/// #[dy_api(method = get, path = "/reports", security = ["bearerAuth", "oauth2:reports.read"])]
+/// ```
+///
+/// The `bearerAuth` scheme (HTTP bearer, JWT) is added to the document's
+/// components automatically; other schemes must be declared by the app.
+///
+/// `version` assigns the operation to an API version, documented separately
+/// by `App::docs_version`. Unversioned operations appear in every version:
+///
+/// ```rust,ignore
+/// #[dy_api(method = get, path = "/v2/users", tag = "Users", version = "v2")]
+/// ```
+///
+/// The operation id defaults to the function name and can be overridden with
+/// `operation_id`. Use `deprecated` (or `deprecated = true`) to flag an
+/// operation, and `external_docs` / `external_docs_description` to link to
+/// further documentation:
+///
+/// ```rust,ignore
+/// #[dy_api(
+///     method = get,
+///     path = "/v1/users",
+///     operation_id = "listUsersV1",
+///     deprecated,
+///     external_docs = "https://example.com/migrating-to-v2",
+///     external_docs_description = "Migration guide"
+/// )]
+/// ```
+///
+/// Bodies are documented as `application/json` unless `request_content_type`
+/// or `response_content_type` says otherwise. Without a `request`/`response`
+/// type, binary content types are documented as a binary string and `text/*`
+/// as a plain string:
+///
+/// ```rust,ignore
+/// #[dy_api(method = post, path = "/avatars", request = AvatarForm, request_content_type = "multipart/form-data")]
+/// #[dy_api(method = get, path = "/files/{id}", response_content_type = "application/octet-stream")]
+/// ```
+///
+/// Anything not given explicitly is inferred from the handler signature where
+/// possible: the request body from a `Json<T>` / `ValidatedJson<T>` argument,
+/// path parameters from the route template and a `Path<T>` / `ValidatedPath<T>`
+/// argument, query parameters from `Query<T>` (when `T: IntoParams`) and
+/// `Pagination`, and the response from an `ApiResult<T>`, `Json<T>` or
+/// `Result<Json<T>, _>` return type. A `BulkJson<T>` argument is documented
+/// as an array of `T`, a `Form<T>` / `ValidatedForm<T>` argument as an
+/// `application/x-www-form-urlencoded` body, a `JweJson<T>` argument as an
+/// `application/jose` body, and a `BulkResponse<R>` return type as a `207`
+/// response:
+///
+/// ```rust,ignore
+/// #[dy_api(method = patch, path = "/users/{id}", tag = "Users")]
+/// async fn update_user(
+///     Path(id): Path<Uuid>,
+///     ValidatedJson(payload): ValidatedJson<UpdateUserRequest>,
+/// ) -> ApiResult<User> { ... }
+/// ```
+///
+/// The handler is also recorded for [`App::auto_routes`] /
+/// `App::auto_routes_with_state`, which serve it on the documented path and
+/// method. Its state type is taken from a `State<T>` argument; use
+/// `state = AppState` when the handler extracts a sub-state via `FromRef`, or
+/// `skip_route` to only document it:
+///
+/// ```rust,ignore
+/// #[dy_api(method = get, path = "/users", state = AppState)]
+/// async fn list_users(State(db): State<Database>) -> ApiResult<Vec<User>> { ... }
+/// ```
+///
+/// [`App::auto_routes`]: https://docs.rs/dy-rs/latest/dy_rs/struct.App.html#method.auto_routes
 #[proc_macro_attribute]
 pub fn dy_api(attr: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(attr with Punctuated<Meta, Token![,]>::parse_terminated);
@@ -169,86 +549,159 @@ pub fn dy_api(attr: TokenStream, item: TokenStream) -> TokenStream {
         Err(err) => return err.to_compile_error().into(),
     };
 
+    let input_fn: syn::ItemFn = parse_macro_input!(item as syn::ItemFn);
+    let fn_name = &input_fn.sig.ident;
+    let registration = match expand_operation(parsed, &input_fn.sig, quote! { #fn_name }) {
+        Ok(tokens) => tokens,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    TokenStream::from(quote! {
+        #input_fn
+
+        #registration
+    })
+}
+
+fn method_expr(method: &Ident) -> syn::Result<proc_macro2::TokenStream> {
+    Ok(match method.to_string().as_str() {
+        "get" | "GET" => quote! { utoipa::openapi::path::HttpMethod::Get },
+        "post" | "POST" => quote! { utoipa::openapi::path::HttpMethod::Post },
+        "put" | "PUT" => quote! { utoipa::openapi::path::HttpMethod::Put },
+        "delete" | "DELETE" => quote! { utoipa::openapi::path::HttpMethod::Delete },
+        "patch" | "PATCH" => quote! { utoipa::openapi::path::HttpMethod::Patch },
+        other => {
+            return Err(syn::Error::new(
+                method.span(),
+                format!("unsupported method `{other}`; use get, post, put, delete, or patch"),
+            ));
+        }
+    })
+}
+
+/// Generate the OpenAPI (and, unless `skip_route`, auto-route) registration
+/// for a handler with signature `sig`, referred to by the `handler` path.
+fn expand_operation(
+    parsed: ApiArgs,
+    sig: &syn::Signature,
+    handler: proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
     let method = parsed
         .method
         .unwrap_or_else(|| Ident::new("get", proc_macro2::Span::call_site()));
     let path = parsed
         .path
         .unwrap_or_else(|| LitStr::new("/", proc_macro2::Span::call_site()));
-    let status = parsed
-        .status
-        .unwrap_or_else(|| LitInt::new("200", proc_macro2::Span::call_site()));
-    let status_str = LitStr::new(&status.base10_digits(), status.span());
+    let fn_name = &sig.ident;
+    let inferred = infer_from_signature(sig);
+
+    let status = parsed.status.unwrap_or_else(|| {
+        LitInt::new(
+            inferred.status.unwrap_or("200"),
+            proc_macro2::Span::call_site(),
+        )
+    });
+    let status_str = LitStr::new(status.base10_digits(), status.span());
 
-    let request_ty = parsed.request;
-    let response_ty = parsed.response;
+    // `(type, inferred)`: explicit types must implement the utoipa traits,
+    // inferred ones are probed and skipped when they don't.
+    let request_ty = parsed
+        .request
+        .map(|ty| (ty, false))
+        .or_else(|| inferred.request.map(|ty| (ty, true)));
+    let response_ty = parsed
+        .response
+        .map(|ty| (ty, false))
+        .or_else(|| inferred.response.map(|ty| (ty, true)));
     let tag = parsed.tag;
     let summary = parsed.summary;
     let description = parsed.description;
+    let security = parsed.security;
+    let deprecated = parsed.deprecated;
 
-    let method_expr = match method.to_string().as_str() {
-        "get" | "GET" => quote! { utoipa::openapi::path::HttpMethod::Get },
-        "post" | "POST" => quote! { utoipa::openapi::path::HttpMethod::Post },
-        "put" | "PUT" => quote! { utoipa::openapi::path::HttpMethod::Put },
-        "delete" | "DELETE" => quote! { utoipa::openapi::path::HttpMethod::Delete },
-        "patch" | "PATCH" => quote! { utoipa::openapi::path::HttpMethod::Patch },
-        other => {
-            return syn::Error::new(
-                method.span(),
-                format!("unsupported method `{other}`; use get, post, put, delete, or patch"),
+    let method_expr = method_expr(&method)?;
+
+    let request_content_type = parsed.request_content_type.unwrap_or_else(|| {
+        LitStr::new(
+            inferred.request_content_type.unwrap_or("application/json"),
+            proc_macro2::Span::call_site(),
+        )
+    });
+    let response_content_type = parsed
+        .response_content_type
+        .unwrap_or_else(|| LitStr::new("application/json", proc_macro2::Span::call_site()));
+    let has_request_content_type = request_content_type.value() != "application/json";
+    let has_response_content_type = response_content_type.value() != "application/json";
+
+    let schema_expr = |ty: Option<&(Type, bool)>| match ty {
+        Some((ty, false)) => quote! { Some(<#ty as utoipa::PartialSchema>::schema()) },
+        Some((ty, true)) => quote! {{
+            use ::dy_rs::openapi::__private::*;
+            (&Probe::<#ty>::new()).schema()
+        }},
+        None => quote! { None },
+    };
+
+    let request_body = if request_ty.is_some() || has_request_content_type {
+        let schema = schema_expr(request_ty.as_ref());
+        quote! {
+            Some(
+                utoipa::openapi::request_body::RequestBodyBuilder::new()
+                    .content(
+                        #request_content_type,
+                        ::dy_rs::openapi::content_for(#request_content_type, #schema),
+                    )
+                    .required(Some(utoipa::openapi::Required::True))
+                    .build(),
             )
-            .to_compile_error()
-            .into();
         }
+    } else {
+        quote! { None }
     };
 
-    let request_body = request_ty
-        .as_ref()
-        .map(|ty| {
-            quote! {
-                Some(
-                    utoipa::openapi::request_body::RequestBodyBuilder::new()
-                        .content(
-                            "application/json",
-                            utoipa::openapi::content::ContentBuilder::new()
-                                .schema(Some(<#ty as utoipa::PartialSchema>::schema()))
-                                .build(),
-                        )
-                        .required(Some(utoipa::openapi::Required::True))
-                        .build(),
-                )
-            }
-        })
-        .unwrap_or_else(|| quote! { None });
+    let response_content = if response_ty.is_some() || has_response_content_type {
+        let schema = schema_expr(response_ty.as_ref());
+        quote! {
+            .content(
+                #response_content_type,
+                ::dy_rs::openapi::content_for(#response_content_type, #schema),
+            )
+        }
+    } else {
+        quote! {}
+    };
 
-    let response_block = response_ty
-        .as_ref()
-        .map(|ty| {
-            quote! {
-                responses = responses.response(
-                    #status_str,
-                    utoipa::openapi::response::ResponseBuilder::new()
-                        .description("Success")
-                        .content(
-                            "application/json",
-                            utoipa::openapi::content::ContentBuilder::new()
-                                .schema(Some(<#ty as utoipa::PartialSchema>::schema()))
-                                .build(),
-                        )
-                        .build(),
-                );
-            }
-        })
-        .unwrap_or_else(|| {
-            quote! {
-                responses = responses.response(
-                    #status_str,
-                    utoipa::openapi::response::ResponseBuilder::new()
-                        .description("Success")
-                        .build(),
-                );
-            }
-        });
+    let response_block = quote! {
+        responses = responses.response(
+            #status_str,
+            utoipa::openapi::response::ResponseBuilder::new()
+                .description("Success")
+                #response_content
+                .build(),
+        );
+    };
+
+    let errors = &parsed.errors;
+    let errors_block = if errors.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            responses = ::dy_rs::openapi::error_responses(
+                responses,
+                &[#(<#errors as ::dy_rs::error::ApiErrorKind>::documented()),*].concat(),
+            );
+        }
+    };
+    let error_schema_push = if errors.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            acc.push((
+                <::dy_rs::error::ErrorResponse as utoipa::ToSchema>::name().into(),
+                <::dy_rs::error::ErrorResponse as utoipa::PartialSchema>::schema(),
+            ));
+        }
+    };
 
     let tags_block = tag
         .as_ref()
@@ -259,6 +712,12 @@ pub fn dy_api(attr: TokenStream, item: TokenStream) -> TokenStream {
         })
         .unwrap_or_else(|| quote! {});
 
+    let version_block = parsed
+        .version
+        .as_ref()
+        .map(|v| quote! { ::dy_rs::openapi::set_operation_version(&mut operation, #v); })
+        .unwrap_or_else(|| quote! {});
+
     let summary_block = summary
         .as_ref()
         .map(|s| quote! { operation.summary = Some(#s.to_string()); })
@@ -269,50 +728,167 @@ pub fn dy_api(attr: TokenStream, item: TokenStream) -> TokenStream {
         .map(|d| quote! { operation.description = Some(#d.to_string()); })
         .unwrap_or_else(|| quote! {});
 
-    let mut schema_types: Vec<Type> = Vec::new();
-    if let Some(ty) = request_ty {
-        schema_types.push(ty);
-    }
-    if let Some(ty) = response_ty {
-        schema_types.push(ty);
-    }
-
-    let schema_push = schema_types.iter().map(|ty| {
+    let security_block = if security.is_empty() {
+        quote! {}
+    } else {
+        let requirements = security.iter().map(|entry| {
+            let value = entry.value();
+            let (name, scopes) = value.split_once(':').unwrap_or((value.as_str(), ""));
+            let scopes = scopes.split_whitespace();
+            quote! {
+                utoipa::openapi::security::SecurityRequirement::new(
+                    #name,
+                    ::std::vec::Vec::<&str>::from([#(#scopes),*]),
+                )
+            }
+        });
         quote! {
-            acc.push((<#ty as utoipa::ToSchema>::name().into(), <#ty as utoipa::PartialSchema>::schema()));
-            <#ty as utoipa::ToSchema>::schemas(acc);
+            operation.security = Some(vec![#(#requirements),*]);
         }
-    });
+    };
 
-    let input_fn: syn::ItemFn = parse_macro_input!(item as syn::ItemFn);
-    let fn_name = &input_fn.sig.ident;
+    let deprecated_block = if deprecated {
+        quote! { operation.deprecated = Some(utoipa::openapi::Deprecated::True); }
+    } else {
+        quote! {}
+    };
 
-    let expanded = quote! {
-        #input_fn
+    let external_docs_block = parsed
+        .external_docs
+        .as_ref()
+        .map(|url| {
+            let description = parsed
+                .external_docs_description
+                .as_ref()
+                .map(|d| quote! { Some(#d.to_string()) })
+                .unwrap_or_else(|| quote! { None });
+            quote! {
+                let mut external_docs = utoipa::openapi::external_docs::ExternalDocs::new(#url);
+                external_docs.description = #description;
+                operation.external_docs = Some(external_docs);
+            }
+        })
+        .unwrap_or_else(|| quote! {});
+
+    let schema_push = [request_ty, response_ty]
+        .into_iter()
+        .flatten()
+        .filter_map(|(ty, inferred)| {
+            if !inferred {
+                return Some(quote! {
+                    acc.push((<#ty as utoipa::ToSchema>::name().into(), <#ty as utoipa::PartialSchema>::schema()));
+                    <#ty as utoipa::ToSchema>::schemas(acc);
+                });
+            }
+            let (ty, named) = component_type(&ty)?;
+            Some(if named {
+                quote! { (&Probe::<#ty>::new()).register(acc); }
+            } else {
+                quote! { (&Probe::<#ty>::new()).register_nested(acc); }
+            })
+        })
+        .collect::<Vec<_>>();
 
+    let param_names = path_param_names(&path.value());
+    let path_params = match &inferred.path {
+        _ if param_names.is_empty() => quote! {},
+        Some(Type::Tuple(tuple)) => {
+            let params = param_names.iter().enumerate().map(|(i, name)| {
+                let schema = match tuple.elems.iter().nth(i) {
+                    Some(elem) => param_schema(elem),
+                    None => quote! { None },
+                };
+                quote! { parameters.push(path_parameter(#name, #schema)); }
+            });
+            quote! { #(#params)* }
+        }
+        Some(ty) if known_schema_type(ty).is_some() && param_names.len() == 1 => {
+            let name = &param_names[0];
+            let schema = param_schema(ty);
+            quote! { parameters.push(path_parameter(#name, #schema)); }
+        }
+        Some(ty) => quote! {
+            parameters.extend((&&Probe::<#ty>::new()).path_params(&[#(#param_names),*]));
+        },
+        None => quote! {
+            #(parameters.push(path_parameter(#param_names, None));)*
+        },
+    };
+    let query_types = &inferred.query;
+    let parameters_block = quote! {
+        let mut parameters: Vec<utoipa::openapi::path::Parameter> = Vec::new();
+        #path_params
+        #(parameters.extend((&Probe::<#query_types>::new()).query_params());)*
+        if !parameters.is_empty() {
+            operation.parameters = Some(parameters);
+        }
+    };
+
+    let operation_id = parsed
+        .operation_id
+        .unwrap_or_else(|| LitStr::new(&fn_name.to_string(), fn_name.span()));
+
+    // Generic handlers have no single concrete type to register.
+    let route_block = if parsed.skip_route || !sig.generics.params.is_empty() {
+        quote! {}
+    } else {
+        let state_ty = parsed
+            .state
+            .or(inferred.state)
+            .unwrap_or_else(|| syn::parse_quote!(()));
+        quote! {
+            fn __dy_rs_add_route(router: &mut dyn ::std::any::Any) -> bool {
+                ::dy_rs::routes::add_route::<#state_ty, _, _>(router, #path, #method_expr, #handler)
+            }
+
+            ::dy_rs::openapi::inventory::submit! {
+                ::dy_rs::routes::AutoRoute {
+                    path: #path,
+                    method: #method_expr,
+                    add: __dy_rs_add_route,
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
         #[allow(non_upper_case_globals)]
         const _: () = {
             fn __dy_rs_operation() -> utoipa::openapi::path::Operation {
+                #[allow(unused_imports)]
+                use ::dy_rs::openapi::__private::*;
+
                 let mut responses = utoipa::openapi::ResponsesBuilder::new();
                 #response_block
+                #errors_block
 
                 let mut operation = utoipa::openapi::path::OperationBuilder::new()
-                    .operation_id(Some(stringify!(#fn_name)))
+                    .operation_id(Some(#operation_id))
                     .responses(responses.build())
                     .request_body(#request_body)
                     .build();
 
                 #tags_block
+                #version_block
                 #summary_block
                 #description_block
+                #security_block
+                #deprecated_block
+                #external_docs_block
+                #parameters_block
 
                 operation
             }
 
+            #[allow(clippy::ptr_arg)]
             fn __dy_rs_register_schemas(
                 acc: &mut Vec<(String, utoipa::openapi::RefOr<utoipa::openapi::schema::Schema>)>
             ) {
+                #[allow(unused_imports)]
+                use ::dy_rs::openapi::__private::*;
+
                 #(#schema_push)*
+                #error_schema_push
             }
 
             ::dy_rs::openapi::inventory::submit! {
@@ -323,8 +899,549 @@ pub fn dy_api(attr: TokenStream, item: TokenStream) -> TokenStream {
                     register_schemas: __dy_rs_register_schemas,
                 }
             }
+
+            #route_block
         };
+    })
+}
+
+#[derive(Default)]
+struct ControllerArgs {
+    path: Option<LitStr>,
+    tag: Option<LitStr>,
+    version: Option<LitStr>,
+    state: Option<Type>,
+    security: Vec<LitStr>,
+    layers: Vec<Expr>,
+}
+
+fn parse_controller_args(args: Punctuated<Meta, Token![,]>) -> syn::Result<ControllerArgs> {
+    let mut out = ControllerArgs::default();
+
+    for arg in args {
+        match arg {
+            Meta::NameValue(nv) if nv.path.is_ident("path") => {
+                out.path = Some(lit_str_arg(nv.value, "path")?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("tag") => {
+                out.tag = Some(lit_str_arg(nv.value, "tag")?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("version") => {
+                out.version = Some(lit_str_arg(nv.value, "version")?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("security") => {
+                out.security.extend(parse_security(nv.value)?);
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("state") => {
+                if let Expr::Path(expr_path) = nv.value {
+                    out.state = Some(Type::Path(TypePath {
+                        qself: expr_path.qself,
+                        path: expr_path.path,
+                    }));
+                } else {
+                    return Err(syn::Error::new(nv.value.span(), "state must be a type"));
+                }
+            }
+            Meta::NameValue(nv) if nv.path.is_ident("layer") => {
+                out.layers.push(nv.value);
+            }
+            other => {
+                return Err(syn::Error::new(
+                    other.span(),
+                    "unsupported attribute, expected path, tag, version, security, state, or layer",
+                ));
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Join a controller prefix and a handler path.
+fn join_paths(base: &str, path: &str) -> String {
+    let base = base.trim_end_matches('/');
+    let path = path.trim_start_matches('/');
+    match (base.is_empty(), path.is_empty()) {
+        (true, true) => "/".to_string(),
+        (_, true) => base.to_string(),
+        _ => format!("{base}/{path}"),
+    }
+}
+
+/// Group `#[dy_api]` handlers under a shared path prefix, tag, security and
+/// middleware.
+///
+/// Applied to an `impl` block, every associated function annotated with
+/// `#[dy_api]` is documented with the controller's prefix prepended to its
+/// path, inherits the controller `tag`, `version` and `security` unless it
+/// sets its own, and is added to a generated `routes()` function. Each
+/// `layer = ...` is applied to that router, in order:
+///
+/// ```rust,ignore
+/// struct UserController;
+///
+/// #[dy_controller(path = "/users", tag = "Users", layer = TraceLayer::new_for_http())]
+/// impl UserController {
+///     #[dy_api(method = get, path = "/")]
+///     async fn list(State(db): State<Database>) -> ApiResult<Vec<User>> { ... }
+///
+///     #[dy_api(method = get, path = "/{id}")]
+///     async fn get(State(db): State<Database>, Path(id): Path<Uuid>) -> ApiResult<User> { ... }
+/// }
+///
+/// App::new()
+///     .auto_configure()
+///     .mount(UserController::routes().with_state(db))
+/// ```
+///
+/// `routes()` returns a `Router<S>` where `S` is the controller `state`, or
+/// the first `State<T>` extracted by one of its handlers. Controller handlers
+/// are served through `routes()` only, not through `App::auto_routes`.
+///
+/// As controllers tend to share handler names, a handler's `operationId`
+/// defaults to the controller and function name, e.g. `UserController_list`,
+/// rather than the bare function name.
+#[proc_macro_attribute]
+pub fn dy_controller(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr with Punctuated<Meta, Token![,]>::parse_terminated);
+    let mut item_impl = parse_macro_input!(item as syn::ItemImpl);
+
+    match expand_controller(args, &mut item_impl) {
+        Ok(generated) => TokenStream::from(quote! {
+            #item_impl
+
+            #generated
+        }),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_controller(
+    args: Punctuated<Meta, Token![,]>,
+    item_impl: &mut syn::ItemImpl,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let controller = parse_controller_args(args)?;
+    if !item_impl.generics.params.is_empty() || item_impl.trait_.is_some() {
+        return Err(syn::Error::new(
+            item_impl.span(),
+            "#[dy_controller] must be applied to a non-generic inherent impl block",
+        ));
+    }
+
+    let self_ty = item_impl.self_ty.clone();
+    let controller_name = match &*self_ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+    .unwrap_or_else(|| quote!(#self_ty).to_string().replace(' ', ""));
+    let base = controller
+        .path
+        .as_ref()
+        .map(|p| p.value())
+        .unwrap_or_default();
+    let mut state = controller.state.clone();
+    let mut registrations = Vec::new();
+    let mut routes = Vec::new();
+
+    for impl_item in &mut item_impl.items {
+        let syn::ImplItem::Fn(handler) = impl_item else {
+            continue;
+        };
+        let Some(index) = handler
+            .attrs
+            .iter()
+            .position(|attr| attr.path().is_ident("dy_api"))
+        else {
+            continue;
+        };
+        let attr = handler.attrs.remove(index);
+
+        if let Some(receiver) = handler.sig.receiver() {
+            return Err(syn::Error::new(
+                receiver.span(),
+                "controller handlers must be associated functions without `self`",
+            ));
+        }
+
+        let args = match &attr.meta {
+            Meta::Path(_) => Punctuated::new(),
+            _ => attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?,
+        };
+        let mut parsed = parse_args(args)?;
+
+        let path_span = parsed
+            .path
+            .as_ref()
+            .map(|p| p.span())
+            .unwrap_or_else(proc_macro2::Span::call_site);
+        let sub_path = parsed.path.as_ref().map(|p| p.value()).unwrap_or_default();
+        let path = LitStr::new(&join_paths(&base, &sub_path), path_span);
+        parsed.path = Some(path.clone());
+        if parsed.tag.is_none() {
+            parsed.tag = controller.tag.clone();
+        }
+        if parsed.version.is_none() {
+            parsed.version = controller.version.clone();
+        }
+        if parsed.security.is_empty() {
+            parsed.security = controller.security.clone();
+        }
+        if state.is_none() {
+            state = infer_from_signature(&handler.sig).state;
+        }
+        if parsed.operation_id.is_none() {
+            let fn_name = &handler.sig.ident;
+            parsed.operation_id = Some(LitStr::new(
+                &format!("{controller_name}_{fn_name}"),
+                fn_name.span(),
+            ));
+        }
+
+        let method = parsed
+            .method
+            .clone()
+            .unwrap_or_else(|| Ident::new("get", proc_macro2::Span::call_site()));
+        let method_expr = method_expr(&method)?;
+        let fn_name = handler.sig.ident.clone();
+        if !parsed.skip_route {
+            routes.push(quote! {
+                ::dy_rs::routes::add_route::<__DyRsState, _, _>(
+                    &mut router,
+                    #path,
+                    #method_expr,
+                    Self::#fn_name,
+                );
+            });
+        }
+
+        // Controller handlers are routed through `routes()` only.
+        parsed.skip_route = true;
+        registrations.push(expand_operation(
+            parsed,
+            &handler.sig,
+            quote! { <#self_ty>::#fn_name },
+        )?);
+    }
+
+    let state = state.unwrap_or_else(|| syn::parse_quote!(()));
+    let layers = &controller.layers;
+
+    Ok(quote! {
+        impl #self_ty {
+            /// Router serving every handler of this controller.
+            pub fn routes() -> ::dy_rs::prelude::Router<#state> {
+                type __DyRsState = #state;
+                #[allow(unused_mut)]
+                let mut router = ::dy_rs::prelude::Router::<__DyRsState>::new();
+                #(#routes)*
+                router #(.layer(#layers))*
+            }
+        }
+
+        #(#registrations)*
+    })
+}
+
+/// Derive `dy_rs::db::Entity`, mapping a struct with named fields to a table
+///
+/// ```rust,ignore
+/// #[derive(sqlx::FromRow, Entity)]
+/// #[entity(table = "users")]
+/// struct User {
+///     #[entity(id)]
+///     user_id: Uuid,
+///     email: String,
+///     #[entity(generated)]
+///     created_at: DateTime<Utc>,
+/// }
+/// ```
+///
+/// Each field is a column of the same name. The ID column is the field
+/// marked `#[entity(id)]`, or else the one named `id`; `generated` fields are
+/// filled in by the database and never written.
+#[proc_macro_derive(Entity, attributes(entity))]
+pub fn derive_entity(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    expand_entity(&input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+fn expand_entity(input: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut table = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("entity")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table") {
+                table = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `table = \"...\"`"))
+            }
+        })?;
+    }
+    let table = table.ok_or_else(|| {
+        syn::Error::new(
+            input.ident.span(),
+            "#[derive(Entity)] needs #[entity(table = \"...\")]",
+        )
+    })?;
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(syn::Error::new(
+                input.span(),
+                "#[derive(Entity)] supports structs with named fields only",
+            ));
+        }
+    };
+
+    let mut id = None;
+    let mut columns = Vec::new();
+    let mut idents = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let column = ident.to_string().trim_start_matches("r#").to_string();
+        let mut generated = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("entity")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("id") {
+                    if id.is_some() {
+                        return Err(meta.error("only one field can be the `id`"));
+                    }
+                    id = Some(column.clone());
+                    Ok(())
+                } else if meta.path.is_ident("generated") {
+                    generated = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `id` or `generated`"))
+                }
+            })?;
+        }
+        if !generated {
+            columns.push(column);
+            idents.push(ident);
+        }
+    }
+    let id = match id {
+        Some(id) => id,
+        None if fields
+            .iter()
+            .any(|field| field.ident.as_ref().is_some_and(|ident| ident == "id")) =>
+        {
+            "id".to_string()
+        }
+        None => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "#[derive(Entity)] needs an `id` field or one marked #[entity(id)]",
+            ));
+        }
     };
 
-    TokenStream::from(expanded)
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::dy_rs::db::Entity for #name #ty_generics #where_clause {
+            const TABLE: &'static str = #table;
+            const ID_COLUMN: &'static str = #id;
+            const COLUMNS: &'static [&'static str] = &[#(#columns),*];
+
+            fn push_bind<'q>(
+                &'q self,
+                column: &str,
+                query: &mut ::dy_rs::db::QueryBuilder<'q, ::dy_rs::db::Postgres>,
+            ) {
+                match column {
+                    #(#columns => {
+                        query.push_bind(&self.#idents);
+                    })*
+                    _ => panic!("{} has no column `{}`", #table, column),
+                }
+            }
+        }
+    })
+}
+
+/// Derive `dy_rs::error::ApiErrorKind` for an error enum or struct
+///
+/// ```rust,ignore
+/// #[derive(Debug, thiserror::Error, ApiErrorKind)]
+/// enum OrderError {
+///     #[error("order {0} not found")]
+///     #[api_error(status = 404)]
+///     NotFound(Uuid),
+///     #[error("payment gateway failed: {0}")]
+///     #[api_error(status = 502, code = "PAYMENT_FAILED", message = "Payment provider unavailable")]
+///     Gateway(String),
+/// }
+/// ```
+///
+/// Every variant (or the struct) needs a `status`, which an enum can also
+/// set for all its variants. `code` defaults to the variant or struct name
+/// in `SCREAMING_SNAKE_CASE`, and without a `message` clients see the
+/// error's `Display` for 4xx statuses and the reason phrase for 5xx. The
+/// type also implements `IntoResponse`, so handlers can return it directly.
+#[proc_macro_derive(ApiErrorKind, attributes(api_error))]
+pub fn derive_api_error_kind(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    expand_api_error_kind(&input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[derive(Default)]
+struct ErrorAttrs {
+    status: Option<u16>,
+    code: Option<LitStr>,
+    message: Option<LitStr>,
+}
+
+fn error_attrs(attrs: &[syn::Attribute]) -> syn::Result<ErrorAttrs> {
+    let mut out = ErrorAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("api_error")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("status") {
+                let lit = meta.value()?.parse::<LitInt>()?;
+                let status = lit.base10_parse::<u16>()?;
+                if !(400..=599).contains(&status) {
+                    return Err(syn::Error::new(
+                        lit.span(),
+                        "status must be an error status (400-599)",
+                    ));
+                }
+                out.status = Some(status);
+                Ok(())
+            } else if meta.path.is_ident("code") {
+                out.code = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("message") {
+                out.message = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `status`, `code` or `message`"))
+            }
+        })?;
+    }
+    Ok(out)
+}
+
+/// `OutOfStock` -> `OUT_OF_STOCK`
+fn screaming_snake(name: &str) -> String {
+    let mut out = String::new();
+    let chars: Vec<char> = name.trim_start_matches("r#").chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+        if c.is_uppercase()
+            && i > 0
+            && (chars[i - 1].is_lowercase() || chars[i - 1].is_ascii_digit() || next_lower)
+            && !out.ends_with('_')
+        {
+            out.push('_');
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out
+}
+
+fn expand_api_error_kind(input: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let defaults = error_attrs(&input.attrs)?;
+    // `(pattern, status, code, message)` per variant
+    let cases = match &input.data {
+        syn::Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let attrs = error_attrs(&variant.attrs)?;
+                let ident = &variant.ident;
+                let status = attrs.status.or(defaults.status).ok_or_else(|| {
+                    syn::Error::new(
+                        ident.span(),
+                        "#[derive(ApiErrorKind)] needs #[api_error(status = ...)] on each variant or the enum",
+                    )
+                })?;
+                let code = attrs
+                    .code
+                    .map(|code| code.value())
+                    .unwrap_or_else(|| screaming_snake(&ident.to_string()));
+                Ok((
+                    quote! { Self::#ident { .. } },
+                    status,
+                    code,
+                    attrs.message,
+                ))
+            })
+            .collect::<syn::Result<Vec<_>>>()?,
+        syn::Data::Struct(_) => {
+            let status = defaults.status.ok_or_else(|| {
+                syn::Error::new(
+                    input.ident.span(),
+                    "#[derive(ApiErrorKind)] needs #[api_error(status = ...)]",
+                )
+            })?;
+            let code = defaults
+                .code
+                .as_ref()
+                .map(|code| code.value())
+                .unwrap_or_else(|| screaming_snake(&input.ident.to_string()));
+            vec![(quote! { _ }, status, code, defaults.message.clone())]
+        }
+        syn::Data::Union(_) => {
+            return Err(syn::Error::new(
+                input.span(),
+                "#[derive(ApiErrorKind)] supports enums and structs only",
+            ));
+        }
+    };
+
+    let patterns: Vec<_> = cases.iter().map(|(pattern, ..)| pattern).collect();
+    let statuses: Vec<_> = cases.iter().map(|(_, status, ..)| status).collect();
+    let codes: Vec<_> = cases.iter().map(|(_, _, code, _)| code).collect();
+    let messages = cases.iter().map(|(_, _, _, message)| match message {
+        Some(message) => quote! { #message.to_string() },
+        None => quote! { ::dy_rs::error::default_public_message(self.status(), &self) },
+    });
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::dy_rs::error::ApiErrorKind for #name #ty_generics #where_clause {
+            fn status(&self) -> ::dy_rs::error::__private::StatusCode {
+                let status = match self {
+                    #(#patterns => #statuses,)*
+                };
+                ::dy_rs::error::__private::StatusCode::from_u16(status)
+                    .expect("checked by #[derive(ApiErrorKind)]")
+            }
+
+            fn code(&self) -> &str {
+                match self {
+                    #(#patterns => #codes,)*
+                }
+            }
+
+            fn public_message(&self) -> String {
+                match self {
+                    #(#patterns => #messages,)*
+                }
+            }
+
+            fn documented() -> Vec<::dy_rs::error::ErrorDoc> {
+                vec![#(::dy_rs::error::ErrorDoc { status: #statuses, code: #codes }),*]
+            }
+        }
+
+        impl #impl_generics ::dy_rs::error::__private::IntoResponse for #name #ty_generics #where_clause {
+            fn into_response(self) -> ::dy_rs::error::__private::Response {
+                ::dy_rs::error::__private::IntoResponse::into_response(::dy_rs::ApiError::from(self))
+            }
+        }
+    })
 }
@@ -0,0 +1,313 @@
+//! Benchmarks for the framework's per-request hot paths
+//!
+//! ```bash
+//! cargo bench -p dy-rs --bench hot_paths                            # run all
+//! cargo bench -p dy-rs --bench hot_paths -- jwt                     # names containing "jwt"
+//! cargo bench -p dy-rs --bench hot_paths -- --save-baseline main    # record
+//! cargo bench -p dy-rs --bench hot_paths -- --baseline main         # compare
+//! ```
+//!
+//! `dy bench --internal` runs the same thing. Baselines are kept in
+//! `target/dy-bench/<name>.json`; comparing fails when a benchmark got slower
+//! than `--threshold` percent (default 10).
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    future::Future,
+    hint::black_box,
+    path::PathBuf,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Extension, Json, Router,
+    body::{Body, to_bytes},
+    extract::{FromRequest, FromRequestParts, Request},
+    http::header::{AUTHORIZATION, CONTENT_TYPE},
+    response::IntoResponse,
+    routing::post,
+};
+use dy_rs::{
+    ApiError, ValidatedJson,
+    auth::{AuthConfig, AuthUser, create_token_pair},
+    openapi::{DocInfo, build_auto_openapi, health_openapi, merge_openapi},
+    pagination::PaginationConfig,
+    proxy::ProxyLayer,
+    tasks::TaskScope,
+};
+use serde::Deserialize;
+use tower::ServiceExt;
+use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use validator::Validate;
+
+const WARMUP: Duration = Duration::from_millis(200);
+const SAMPLES: usize = 30;
+const SAMPLE_TIME: Duration = Duration::from_millis(20);
+
+#[derive(Deserialize, Validate)]
+#[allow(dead_code)]
+struct CreateUser {
+    #[validate(email)]
+    email: String,
+    #[validate(length(min = 2, max = 100))]
+    name: String,
+    #[validate(range(min = 13, max = 150))]
+    age: u32,
+}
+
+const VALID_USER: &str = r#"{"email":"ada@example.com","name":"Ada Lovelace","age":36}"#;
+const INVALID_USER: &str = r#"{"email":"not-an-email","name":"A","age":3}"#;
+
+fn json_request(body: &'static str) -> Request {
+    Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn create_user(ValidatedJson(user): ValidatedJson<CreateUser>) -> impl IntoResponse {
+    Json(user.name)
+}
+
+/// The layers `App::into_router` adds to an auto-configured app
+fn app_stack(router: Router) -> Router {
+    router
+        .layer(Extension(PaginationConfig::default()))
+        .layer(ProxyLayer::default())
+        .layer(Extension(TaskScope::new()))
+        .layer(TraceLayer::new_for_http())
+        .layer(CorsLayer::permissive())
+}
+
+fn main() -> ExitCode {
+    let options = Options::parse();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut runner = Runner::new(options.filter.clone());
+
+    runner.run_async(&rt, "extractor/validated_json_ok", || async {
+        ValidatedJson::<CreateUser>::from_request(json_request(VALID_USER), &())
+            .await
+            .is_ok()
+    });
+    runner.run_async(&rt, "extractor/validated_json_invalid", || async {
+        ValidatedJson::<CreateUser>::from_request(json_request(INVALID_USER), &())
+            .await
+            .is_err()
+    });
+
+    runner.run_async(&rt, "error/api_error_response", || async {
+        let response = ApiError::NotFound("User 42 not found".to_string()).into_response();
+        to_bytes(response.into_body(), usize::MAX).await.unwrap()
+    });
+
+    let config = AuthConfig::default();
+    let token = create_token_pair("user-1", "ada@example.com", vec!["admin".into()], &config)
+        .unwrap()
+        .access_token;
+    runner.run_async(&rt, "jwt/auth_user_extract", || {
+        let mut request = Request::builder()
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(())
+            .unwrap();
+        request.extensions_mut().insert(config.clone());
+        async move {
+            let (mut parts, _) = request.into_parts();
+            AuthUser::from_request_parts(&mut parts, &()).await.is_ok()
+        }
+    });
+
+    runner.run("openapi/build", || {
+        merge_openapi(build_auto_openapi(DocInfo::default()), [health_openapi()])
+    });
+
+    let bare = Router::new().route("/users", post(create_user));
+    let stacked = app_stack(bare.clone());
+    runner.run_async(&rt, "middleware/bare_router", || {
+        bare.clone().oneshot(json_request(VALID_USER))
+    });
+    runner.run_async(&rt, "middleware/app_stack", || {
+        stacked.clone().oneshot(json_request(VALID_USER))
+    });
+
+    runner.finish(&options)
+}
+
+struct Options {
+    filter: Option<String>,
+    save_baseline: Option<String>,
+    baseline: Option<String>,
+    threshold: f64,
+}
+
+impl Options {
+    fn parse() -> Self {
+        let mut options = Self {
+            filter: None,
+            save_baseline: None,
+            baseline: None,
+            threshold: 10.0,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                // Passed by `cargo bench`
+                "--bench" => {}
+                "--save-baseline" => options.save_baseline = args.next(),
+                "--baseline" => options.baseline = args.next(),
+                "--threshold" => {
+                    options.threshold = args
+                        .next()
+                        .and_then(|t| t.parse().ok())
+                        .expect("--threshold takes a percentage");
+                }
+                other if !other.starts_with('-') => options.filter = Some(other.to_string()),
+                other => eprintln!("ignoring unknown option {other}"),
+            }
+        }
+        options
+    }
+}
+
+struct Runner {
+    filter: Option<String>,
+    /// Median nanoseconds per iteration
+    results: BTreeMap<String, f64>,
+}
+
+impl Runner {
+    fn new(filter: Option<String>) -> Self {
+        Self {
+            filter,
+            results: BTreeMap::new(),
+        }
+    }
+
+    fn run<T>(&mut self, name: &str, mut f: impl FnMut() -> T) {
+        self.measure(name, |iterations| {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                black_box(f());
+            }
+            start.elapsed()
+        });
+    }
+
+    fn run_async<F: Future>(
+        &mut self,
+        rt: &tokio::runtime::Runtime,
+        name: &str,
+        mut f: impl FnMut() -> F,
+    ) {
+        self.measure(name, |iterations| {
+            rt.block_on(async {
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    black_box(f().await);
+                }
+                start.elapsed()
+            })
+        });
+    }
+
+    /// `batch(n)` runs `n` iterations and returns how long they took
+    fn measure(&mut self, name: &str, mut batch: impl FnMut(u64) -> Duration) {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !name.contains(filter.as_str()))
+        {
+            return;
+        }
+
+        // Warm up while finding a batch size that takes about SAMPLE_TIME
+        let mut iterations = 1;
+        let warmup = Instant::now();
+        loop {
+            let elapsed = batch(iterations);
+            if elapsed >= SAMPLE_TIME {
+                if warmup.elapsed() >= WARMUP {
+                    break;
+                }
+            } else {
+                iterations *= 2;
+            }
+        }
+
+        let mut samples: Vec<f64> = (0..SAMPLES)
+            .map(|_| batch(iterations).as_nanos() as f64 / iterations as f64)
+            .collect();
+        samples.sort_by(f64::total_cmp);
+        let median = samples[SAMPLES / 2];
+        println!("{name:<40} {:>12}/iter", format_ns(median));
+        self.results.insert(name.to_string(), median);
+    }
+
+    fn finish(self, options: &Options) -> ExitCode {
+        if let Some(name) = &options.save_baseline {
+            let path = baseline_path(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, serde_json::to_vec_pretty(&self.results).unwrap()).unwrap();
+            println!("\nsaved baseline to {}", path.display());
+        }
+
+        let Some(name) = &options.baseline else {
+            return ExitCode::SUCCESS;
+        };
+        let path = baseline_path(name);
+        let baseline: BTreeMap<String, f64> = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).expect("baseline file is malformed"),
+            Err(err) => {
+                eprintln!("can't read baseline {}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        println!(
+            "\ncompared to baseline `{name}` (threshold {}%):",
+            options.threshold
+        );
+        let mut regressions = 0;
+        for (bench, now) in &self.results {
+            let Some(before) = baseline.get(bench) else {
+                println!("{bench:<40} new");
+                continue;
+            };
+            let change = (now - before) / before * 100.0;
+            let regressed = change > options.threshold;
+            regressions += regressed as usize;
+            println!(
+                "{bench:<40} {change:>+7.1}%{}",
+                if regressed { "  REGRESSED" } else { "" }
+            );
+        }
+
+        if regressions > 0 {
+            eprintln!("\n{regressions} benchmark(s) regressed");
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+fn baseline_path(name: &str) -> PathBuf {
+    let target = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target"));
+    target.join("dy-bench").join(format!("{name}.json"))
+}
+
+fn format_ns(ns: f64) -> String {
+    match ns {
+        ns if ns >= 1_000_000.0 => format!("{:.2} ms", ns / 1_000_000.0),
+        ns if ns >= 1_000.0 => format!("{:.2} µs", ns / 1_000.0),
+        ns => format!("{ns:.1} ns"),
+    }
+}
@@ -1,21 +1,148 @@
-use axum::{Router, http::Method};
-use std::net::SocketAddr;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use axum::{Router, extract::Request, response::IntoResponse, routing::Route};
+use serde::de::DeserializeOwned;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tower::{Layer, Service};
+use tower_http::trace::TraceLayer;
+use tracing_subscriber::{
+    EnvFilter, Registry, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
 use utoipa::OpenApi;
 
-#[cfg(feature = "swagger-ui")]
-use utoipa_swagger_ui::SwaggerUi;
-
-use crate::{config::AppConfig, openapi};
+use crate::{
+    audit::{AuditSink, SharedAuditSink},
+    cache::{Cache, SharedCache},
+    client::{HttpClient, HttpTransport},
+    config::{AppConfig, Config, ConfigReport, LogFormat, Section},
+    config_watch::ConfigWatcher,
+    cors::CorsConfig,
+    db::{self, Database, SharedDatabase},
+    debug,
+    decompression::RequestDecompressionLayer,
+    docs::{self, DocView, DocsUi, SpecPaths},
+    i18n::{Catalog, LocalizeErrorsLayer},
+    internal_errors::InternalErrorLayer,
+    listen::{BoundListener, Listener},
+    maintenance::Maintenance,
+    messaging::{Broker, ConsumerGroup, SharedBroker},
+    mock::MockServer,
+    openapi::{self, DocFilter, DocSettings},
+    proxy::ProxyLayer,
+    redact::{self, JsonObjectFields, RedactFields, RedactedJson, RedactedSpan, Redactor},
+    reporting::ErrorReporter,
+    resource::{self, CrudService, Resource},
+    routes::{self, RouteInfo},
+    secrets::Secrets,
+    slow_requests::SlowRequests,
+    storage::{SharedStorage, Storage},
+    tasks::TaskScope,
+    trace_context::TraceContextLayer,
+    versioning::{ApiVersion, VersionLayer},
+    warmup::{self, Readiness, WarmupRequest},
+};
 
 /// Main application builder
 pub struct App {
     router: Router,
     config: Option<AppConfig>,
     openapi: Option<utoipa::openapi::OpenApi>,
+    extra_openapi: Vec<utoipa::openapi::OpenApi>,
+    /// Documents of the routes mounted with [`App::resource`]
+    resource_openapi: Vec<utoipa::openapi::OpenApi>,
+    auto_configured: bool,
+    doc_views: Vec<DocView>,
+    docs_uis: Vec<DocsUi>,
+    spec_paths: Option<SpecPaths>,
+    doc_settings: DocSettings,
+    warmups: Vec<WarmupRequest>,
+    readiness: Readiness,
+    mock: bool,
+    i18n: Option<Catalog>,
+    tasks: TaskScope,
+    cache: Option<SharedCache>,
+    storage: Option<SharedStorage>,
+    broker: Option<SharedBroker>,
+    audit: Option<SharedAuditSink>,
+    database: Option<SharedDatabase>,
+    /// Add typed extensions, such as the handle passed to
+    /// [`App::with_database`], to the router
+    extensions: Vec<Box<dyn FnOnce(Router) -> Router + Send>>,
+    consumers: Vec<ConsumerGroup>,
+    config_watcher: Option<ConfigWatcher>,
+    /// Secrets the configuration was resolved with, for reloads
+    secrets: Option<Secrets>,
+    log_filter: Option<LogFilter>,
+    internal_errors: InternalErrorLayer,
+    maintenance: Maintenance,
+    slow_requests: SlowRequests,
+    /// Routers served on their own listeners, with [`App::listen`]
+    listeners: Vec<(Listener, Router)>,
+    versions: Vec<ApiVersion>,
+    route_paths: Vec<String>,
+    /// Why auto-configuration failed, returned by [`App::run`]
+    build_error: Option<BuildError>,
+    /// Middleware added with [`App::layer`], by name, innermost first
+    layers: Vec<(String, RouterLayer)>,
+    /// Middleware added with [`App::layer_before_auto`], innermost first
+    outer_layers: Vec<(String, RouterLayer)>,
+}
+
+/// Why auto-configuration failed
+#[derive(Debug, thiserror::Error)]
+pub enum BuildError {
+    /// The configuration files or variables couldn't be read
+    #[error("Failed to load configuration: {0}")]
+    Load(config::ConfigError),
+    /// A `${secret:...}` placeholder couldn't be resolved
+    #[error("Failed to resolve configuration secrets: {0}")]
+    Secrets(config::ConfigError),
+    /// The configuration loaded but has problems
+    #[error("{0}")]
+    Invalid(ConfigReport),
+}
+
+/// How auto-configuration sets up logging
+#[derive(Debug, Clone, Default)]
+pub struct SubscriberOptions {
+    skip: bool,
+    filter: Option<String>,
+}
+
+impl SubscriberOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leave logging alone, e.g. when the app or test harness sets up its
+    /// own subscriber
+    pub fn skip(mut self) -> Self {
+        self.skip = true;
+        self
+    }
+
+    /// Log filter used when neither `RUST_LOG` nor `log.level` sets one,
+    /// e.g. `warn,my_app=debug`
+    pub fn filter(mut self, filter: impl Into<String>) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+}
+
+/// Wraps a router in a middleware
+type RouterLayer = Box<dyn FnOnce(Router) -> Router + Send>;
+
+/// `L`'s type name without its path and generics, for the route table
+fn layer_name<L>() -> String {
+    let name = std::any::type_name::<L>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name).to_string()
 }
 
+/// Log filter when neither `RUST_LOG` nor `log.level` sets one
+const DEFAULT_LOG_FILTER: &str = "info,dy_rs=debug,tower_http=debug";
+
+type LogFilter = reload::Handle<EnvFilter, Registry>;
+
 impl App {
     /// Create a new App instance
     pub fn new() -> Self {
@@ -23,66 +150,597 @@ impl App {
             router: Router::new(),
             config: None,
             openapi: None,
+            extra_openapi: Vec::new(),
+            resource_openapi: Vec::new(),
+            auto_configured: false,
+            doc_views: Vec::new(),
+            docs_uis: Vec::new(),
+            spec_paths: None,
+            doc_settings: DocSettings::default(),
+            warmups: Vec::new(),
+            readiness: Readiness::default(),
+            mock: false,
+            i18n: None,
+            tasks: TaskScope::new(),
+            cache: None,
+            storage: None,
+            broker: None,
+            audit: None,
+            database: None,
+            extensions: Vec::new(),
+            consumers: Vec::new(),
+            config_watcher: None,
+            secrets: None,
+            log_filter: None,
+            internal_errors: InternalErrorLayer::new(),
+            maintenance: Maintenance::default(),
+            slow_requests: SlowRequests::default(),
+            listeners: Vec::new(),
+            versions: Vec::new(),
+            route_paths: Vec::new(),
+            build_error: None,
+            layers: Vec::new(),
+            outer_layers: Vec::new(),
         }
     }
 
-    /// Provide a custom OpenAPI document for Swagger UI.
-    /// If not set, a minimal default spec is used.
+    /// Merge a hand-written OpenAPI document (e.g. from `#[derive(OpenApi)]`)
+    /// into the served one, alongside the `#[dy_api]` operations and the
+    /// framework routes. Can be called repeatedly; the first document's
+    /// `info` is used, and on conflicting operations earlier documents win.
     pub fn with_openapi(mut self, openapi: utoipa::openapi::OpenApi) -> Self {
+        self.extra_openapi.push(openapi);
+        self
+    }
+
+    /// Merge a hand-written document describing routes mounted with
+    /// [`App::mount_at`] below `prefix`, moving its paths there, e.g.
+    /// `auth_openapi()` for `auth_routes` mounted at `/api`.
+    pub fn with_openapi_at(self, prefix: &str, openapi: utoipa::openapi::OpenApi) -> Self {
+        self.with_openapi(openapi::prefix_paths(openapi, prefix))
+    }
+
+    /// Customize the served OpenAPI document's title, version, servers,
+    /// contact, license, tags and global security. Settings made here win
+    /// over the `[openapi]` config section.
+    ///
+    /// ```rust,no_run
+    /// # use dy_rs::prelude::*;
+    /// App::new().openapi(|doc| {
+    ///     doc.title("Shop API")
+    ///         .version("2.1.0")
+    ///         .server("https://api.example.com", None)
+    ///         .tag("Orders", Some("Order management"))
+    ///         .security("bearerAuth")
+    /// });
+    /// ```
+    pub fn openapi(mut self, customize: impl FnOnce(DocSettings) -> DocSettings) -> Self {
+        self.doc_settings = customize(std::mem::take(&mut self.doc_settings));
+        self
+    }
+
+    /// Serve an OpenAPI document generated ahead of time (see
+    /// [`openapi::write_auto_openapi`]), typically embedded with `include_str!`.
+    /// Skips building the document from `#[dy_api]` routes at startup.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `json` is not a valid OpenAPI document.
+    pub fn with_embedded_openapi(mut self, json: &str) -> Self {
+        let openapi = openapi::from_json(json).expect("embedded OpenAPI document is invalid");
         self.openapi = Some(openapi);
         self
     }
 
-    /// Auto-configure the app and serve the provided OpenAPI doc at
-    /// `/api-docs/openapi.json` with Swagger UI at `/docs`.
-    /// When the `swagger-ui` feature is disabled, this falls back to `auto_configure`.
-    pub fn auto_configure_with_openapi<T: utoipa::OpenApi>(self) -> Self {
-        #[cfg(not(feature = "swagger-ui"))]
-        {
-            tracing::warn!("swagger-ui feature is disabled; skipping OpenAPI registration");
-            return self.auto_configure();
-        }
+    /// Localize messages with the `{locale}.ftl` catalogs in `dir`, e.g.
+    /// `locales/`, for the [`Locale`](crate::i18n::Locale) extractor,
+    /// validation errors and [`ApiError`](crate::ApiError) responses. See
+    /// [`i18n`](crate::i18n) for the file format.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dir` can't be read or a catalog is malformed.
+    pub fn with_i18n(self, dir: impl AsRef<std::path::Path>) -> Self {
+        let dir = dir.as_ref();
+        let catalog = Catalog::load(dir)
+            .unwrap_or_else(|e| panic!("Failed to load locales from {}: {e}", dir.display()));
+        self.with_catalog(catalog)
+    }
 
-        #[cfg(feature = "swagger-ui")]
-        {
-            let openapi = T::openapi();
-            self.with_openapi(openapi).auto_configure()
+    /// Localize messages with an already built [`Catalog`], e.g. one embedded
+    /// with `include_str!`
+    pub fn with_catalog(mut self, catalog: Catalog) -> Self {
+        self.i18n = Some(catalog);
+        self
+    }
+
+    /// Share `cache` with handlers through the
+    /// [`SharedCache`] extractor
+    pub fn with_cache(mut self, cache: impl Cache) -> Self {
+        self.cache = Some(SharedCache::new(cache));
+        self
+    }
+
+    /// Share `storage` with handlers through the
+    /// [`SharedStorage`] extractor
+    pub fn with_storage(mut self, storage: impl Storage) -> Self {
+        self.storage = Some(SharedStorage::new(storage));
+        self
+    }
+
+    /// Share `sink` with handlers through the [`Audit`](crate::audit::Audit)
+    /// and [`SharedAuditSink`] extractors
+    pub fn with_audit(mut self, sink: impl AuditSink) -> Self {
+        self.audit = Some(SharedAuditSink::new(sink));
+        self
+    }
+
+    /// Report `5xx` responses and handler panics to `reporter`; see
+    /// [`reporting`](crate::reporting)
+    pub fn with_error_reporter(mut self, reporter: impl ErrorReporter) -> Self {
+        self.internal_errors = self.internal_errors.reporter(reporter);
+        self
+    }
+
+    /// Share `database` with handlers through the [`Db`](crate::db::Db)
+    /// extractor, e.g. `Db<PgPool>`, and report not ready on `/ready` while
+    /// it doesn't answer. See [`db`](crate::db) for backends other than sqlx.
+    pub fn with_database<D: Database + Clone>(mut self, database: D) -> Self {
+        self.database = Some(SharedDatabase::new(database.clone()));
+        self.extensions.push(Box::new(move |router: Router| {
+            router.layer(axum::Extension(database))
+        }));
+        self
+    }
+
+    /// Share an [`HttpClient`] sending through `transport`, configured by the
+    /// `[http_client]` section, with handlers through the extractor of the
+    /// same name. See [`client`](crate::client).
+    pub fn with_http_client(mut self, transport: impl HttpTransport) -> Self {
+        let config = self
+            .config
+            .as_ref()
+            .map(|config| config.http_client.clone())
+            .unwrap_or_default();
+        let client = HttpClient::new(config, transport);
+        self.extensions.push(Box::new(move |router: Router| {
+            router.layer(axum::Extension(client))
+        }));
+        self
+    }
+
+    /// Load the `[name]` section of the configuration as a `T` and share it
+    /// with handlers through the [`Section<T>`](crate::config::Section)
+    /// extractor. See [`AppConfig::section`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration isn't loaded yet (call `auto_configure`
+    /// first), or the section is missing or doesn't match `T`.
+    pub fn config_section<T>(mut self, name: &str) -> Self
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let config = self
+            .config
+            .as_ref()
+            .expect("App::config_section needs the configuration; call auto_configure first");
+        let section: T = config
+            .section(name)
+            .unwrap_or_else(|e| panic!("Invalid [{name}] config section: {e}"));
+        let section = Section(Arc::new(section));
+        self.extensions.push(Box::new(move |router: Router| {
+            router.layer(axum::Extension(section))
+        }));
+        self
+    }
+
+    /// The configuration loaded by `auto_configure`, e.g. to build
+    /// application state from it
+    pub fn config(&self) -> Option<&AppConfig> {
+        self.config.as_ref()
+    }
+
+    /// Reload the configuration while serving when a file in `config/`
+    /// changes, applying `log.level` right away. Components subscribe to
+    /// sections through [`App::config_watcher`]; see
+    /// [`config_watch`](crate::config_watch).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration isn't loaded yet (call `auto_configure`
+    /// first).
+    pub fn watch_config(mut self) -> Self {
+        let config = self
+            .config
+            .clone()
+            .expect("App::watch_config needs the configuration; call auto_configure first");
+        let mut watcher = ConfigWatcher::new(config);
+        if let Some(secrets) = self.secrets.clone() {
+            watcher = watcher.secrets(secrets);
         }
+        self.config_watcher = Some(watcher);
+        self
+    }
+
+    /// The watcher started by [`App::watch_config`], to subscribe to
+    /// config sections
+    pub fn config_watcher(&self) -> Option<&ConfigWatcher> {
+        self.config_watcher.as_ref()
+    }
+
+    /// Share `broker` with handlers through the
+    /// [`SharedBroker`] extractor
+    pub fn with_broker(mut self, broker: impl Broker) -> Self {
+        self.broker = Some(SharedBroker::new(broker));
+        self
+    }
+
+    /// Run `group` while the server runs: it subscribes on startup and
+    /// stops taking messages on shutdown
+    pub fn consume(mut self, group: ConsumerGroup) -> Self {
+        self.consumers.push(group);
+        self
+    }
+
+    /// Scope of the background tasks handlers spawn with
+    /// [`TaskScope`], for spawning from outside a handler
+    pub fn tasks(&self) -> TaskScope {
+        self.tasks.clone()
+    }
+
+    /// The layer answering panics and hiding internal errors, e.g. to read
+    /// its [`metrics`](InternalErrorLayer::metrics) while serving
+    pub fn internal_errors(&self) -> InternalErrorLayer {
+        self.internal_errors.clone()
+    }
+
+    /// The maintenance switch, set from the `[maintenance]` config section,
+    /// e.g. to flip it from a signal handler or to mount
+    /// [`admin_router`](crate::maintenance::admin_router). See
+    /// [`maintenance`](crate::maintenance).
+    pub fn maintenance(&self) -> Maintenance {
+        self.maintenance.clone()
+    }
+
+    /// The layer warning about slow requests, set from the `[slow_requests]`
+    /// config section, e.g. to read its
+    /// [`metrics`](SlowRequests::metrics) while serving
+    pub fn slow_requests(&self) -> SlowRequests {
+        self.slow_requests.clone()
+    }
+
+    /// Auto-configure the app and serve the provided OpenAPI doc, merged with
+    /// the `#[dy_api]` operations, at `/api-docs/openapi.json` with Swagger UI
+    /// at `/docs`.
+    /// When the `swagger-ui` feature is disabled, only the JSON and YAML
+    /// documents are served.
+    pub fn auto_configure_with_openapi<T: utoipa::OpenApi>(self) -> Self {
+        let openapi = T::openapi();
+        self.with_openapi(openapi).auto_configure()
     }
 
     /// Auto-configure the application with sensible defaults:
-    /// - Loads configuration from files and environment
-    /// - Sets up structured logging with tracing
-    /// - Configures CORS with permissive defaults
+    /// - Loads configuration from files and environment, for the environment
+    ///   named by `--env` or `APP_ENV`, and validates it
+    /// - Sets up structured logging with tracing, unless a global subscriber
+    ///   is already set; as JSON in production, unless `log.format` says
+    ///   otherwise
+    /// - Configures CORS from `[server.cors]`, allowing any origin except in
+    ///   production
     /// - Adds health check endpoint
-    /// - Enables Swagger UI at /docs
+    /// - Enables Swagger UI at /docs, except in production
+    ///
+    /// If the configuration can't be loaded or is invalid, the error is
+    /// logged and returned by [`App::run`]. Use
+    /// [`App::try_auto_configure`] to handle it here instead.
     pub fn auto_configure(mut self) -> Self {
-        // Initialize logging
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| "info,dy_rs=debug,tower_http=debug".into()),
-            )
-            .with(tracing_subscriber::fmt::layer())
-            .init();
+        let loaded = AppConfig::load().map_err(BuildError::Load);
+        self.init_subscriber(&SubscriberOptions::default(), log_format(&loaded));
+        match loaded {
+            Ok(config) => self.configure_or_keep_error(config),
+            Err(e) => self.keep_error(e),
+        }
+        self
+    }
+
+    /// [`App::auto_configure`], returning the [`BuildError`] if the
+    /// configuration can't be loaded or is invalid
+    pub fn try_auto_configure(mut self) -> Result<Self, BuildError> {
+        let loaded = AppConfig::load().map_err(BuildError::Load);
+        self.init_subscriber(&SubscriberOptions::default(), log_format(&loaded));
+        self.finish_auto_configure(loaded?)?;
+        Ok(self)
+    }
+
+    /// Auto-configure the application with `config` instead of loading it,
+    /// setting up logging as `logging` says, e.g. in tests that build their
+    /// configuration in code and already have a subscriber
+    pub fn auto_configure_with(
+        mut self,
+        config: AppConfig,
+        logging: SubscriberOptions,
+    ) -> Result<Self, BuildError> {
+        self.init_subscriber(&logging, config.log_format());
+        self.finish_auto_configure(config)?;
+        Ok(self)
+    }
+
+    /// [`App::auto_configure`], replacing `${secret:<source>:<name>}`
+    /// placeholders in the configuration with secrets from `secrets` before
+    /// validating it. A secret that can't be resolved is a
+    /// [`BuildError::Secrets`], returned by [`App::run`].
+    pub async fn auto_configure_with_secrets(mut self, secrets: &Secrets) -> Self {
+        let loaded = self.load_with_secrets(secrets).await;
+        self.init_subscriber(&SubscriberOptions::default(), log_format(&loaded));
+        match loaded {
+            Ok(config) => self.configure_or_keep_error(config),
+            Err(e) => self.keep_error(e),
+        }
+        self
+    }
+
+    /// [`App::auto_configure_with_secrets`], returning the [`BuildError`]
+    pub async fn try_auto_configure_with_secrets(
+        mut self,
+        secrets: &Secrets,
+    ) -> Result<Self, BuildError> {
+        let loaded = self.load_with_secrets(secrets).await;
+        self.init_subscriber(&SubscriberOptions::default(), log_format(&loaded));
+        self.finish_auto_configure(loaded?)?;
+        Ok(self)
+    }
+
+    async fn load_with_secrets(&mut self, secrets: &Secrets) -> Result<AppConfig, BuildError> {
+        let mut config = AppConfig::load().map_err(BuildError::Load)?;
+        config
+            .resolve_secrets(secrets)
+            .await
+            .map_err(BuildError::Secrets)?;
+        self.secrets = Some(secrets.clone());
+        Ok(config)
+    }
+
+    /// Install the dy-rs subscriber, writing `format` with a filter that
+    /// `log.level` can replace, unless `options` skip it or a subscriber is
+    /// already set
+    fn init_subscriber(&mut self, options: &SubscriberOptions, format: LogFormat) {
+        if options.skip {
+            return;
+        }
+        let default_filter = options.filter.as_deref().unwrap_or(DEFAULT_LOG_FILTER);
+        let (filter, handle) = reload::Layer::new(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| default_filter.into()),
+        );
+        let (text, json) = match format {
+            LogFormat::Text => (
+                Some(tracing_subscriber::fmt::layer().fmt_fields(RedactFields::default())),
+                None,
+            ),
+            LogFormat::Json => (
+                None,
+                Some(
+                    tracing_subscriber::fmt::layer()
+                        .event_format(RedactedJson)
+                        .fmt_fields(RedactFields::new(JsonObjectFields)),
+                ),
+            ),
+        };
+        let installed = tracing_subscriber::registry()
+            .with(filter)
+            .with(text)
+            .with(json)
+            .try_init()
+            .is_ok();
+        if installed {
+            self.log_filter = Some(handle);
+        } else {
+            tracing::debug!("A tracing subscriber is already set; keeping it");
+        }
 
         tracing::info!("🚀 Initializing dy-rs application");
+    }
+
+    fn configure_or_keep_error(&mut self, config: AppConfig) {
+        if let Err(e) = self.finish_auto_configure(config) {
+            self.keep_error(e);
+        }
+    }
+
+    /// Log `error` and keep it for [`App::run`] to return
+    fn keep_error(&mut self, error: BuildError) {
+        tracing::error!("{error}");
+        self.build_error = Some(error);
+    }
+
+    fn finish_auto_configure(&mut self, config: AppConfig) -> Result<(), BuildError> {
+        config.validate().map_err(BuildError::Invalid)?;
+        tracing::info!("✅ Configuration loaded for {}", config.environment);
+        redact::set(Redactor::from_config(&config.log.redact));
+        self.maintenance.configure(config.maintenance.clone());
+        self.slow_requests.configure(config.slow_requests.clone());
+        if let Some(filter) = &self.log_filter
+            && config.log.level.is_some()
+        {
+            apply_log_level(filter, config.log.level.as_deref());
+        }
+
+        self.config = Some(config);
+        self.auto_configured = true;
+
+        tracing::info!("✅ Auto-configuration complete");
+        Ok(())
+    }
+
+    /// Serve the API reference with `ui` at [`DocsUi::path`]. Call it once per
+    /// UI to serve several; this replaces the `docs.ui` config setting, which
+    /// defaults to Swagger UI alone.
+    pub fn docs(mut self, ui: DocsUi) -> Self {
+        if !self.docs_uis.contains(&ui) {
+            self.docs_uis.push(ui);
+        }
+        self
+    }
+
+    /// Docs UIs to serve: those passed to `docs`, otherwise the configured ones.
+    fn resolved_docs_uis(&self) -> Vec<DocsUi> {
+        if !self.docs_uis.is_empty() {
+            return self.docs_uis.clone();
+        }
+        self.config
+            .as_ref()
+            .map(|config| config.docs.ui.clone())
+            .unwrap_or_else(|| vec![DocsUi::Swagger])
+    }
+
+    /// Whether docs are served: the `docs.enabled` config setting, which is
+    /// off in the `production` environment unless set.
+    fn docs_enabled(&self) -> bool {
+        self.config.as_ref().is_none_or(AppConfig::docs_enabled)
+    }
+
+    /// Serve the OpenAPI document at `paths` instead of the `docs.spec`
+    /// config setting, which defaults to `/api-docs/openapi.json` and
+    /// `/api-docs/openapi.yaml`.
+    pub fn spec_paths(mut self, paths: SpecPaths) -> Self {
+        self.spec_paths = Some(paths);
+        self
+    }
 
-        // Load configuration
-        let config = AppConfig::load().expect("Failed to load configuration");
-        tracing::info!("✅ Configuration loaded");
-
-        // Setup CORS
-        let cors = CorsLayer::new()
-            .allow_methods([
-                Method::GET,
-                Method::POST,
-                Method::PUT,
-                Method::DELETE,
-                Method::PATCH,
-            ])
-            .allow_origin(tower_http::cors::Any)
-            .allow_headers(tower_http::cors::Any);
+    /// Spec paths: those passed to `spec_paths`, otherwise the configured ones.
+    fn resolved_spec_paths(&self) -> SpecPaths {
+        self.spec_paths
+            .clone()
+            .or_else(|| self.config.as_ref().map(|config| config.docs.spec.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Serve an additional docs UI at `/docs/{name}` showing only the
+    /// operations matched by `filter`, e.g. a public view that hides
+    /// operations tagged `Internal`. Its spec is served at
+    /// `/api-docs/{name}/openapi.json` (and `.yaml`), and other UIs enabled with `docs`
+    /// serve it below their own path (e.g. `/redoc/{name}`).
+    pub fn docs_view(mut self, name: impl Into<String>, filter: DocFilter) -> Self {
+        self.doc_views.push(DocView::new(name, filter));
+        self
+    }
+
+    /// Serve the docs of API `version` at `/docs/{version}` (and below the
+    /// other enabled UIs), with its spec at `/api-docs/{version}/openapi.json`.
+    /// It holds the operations tagged `#[dy_api(version = "...")]` with that
+    /// version plus unversioned ones. Every UI gets a selector switching
+    /// between the registered versions.
+    pub fn docs_version(mut self, version: impl Into<String>) -> Self {
+        let version = version.into();
+        if !self
+            .doc_views
+            .iter()
+            .any(|v| v.version && v.name == version)
+        {
+            self.doc_views.push(DocView::version(version));
+        }
+        self
+    }
+
+    /// Merge a hand-written document describing API `version` (see
+    /// `with_openapi`) and serve it with [`App::docs_version`]. Its
+    /// operations are assigned to `version` unless they already carry one.
+    pub fn with_versioned_openapi(
+        self,
+        version: impl Into<String>,
+        mut openapi: utoipa::openapi::OpenApi,
+    ) -> Self {
+        let version = version.into();
+        openapi::set_document_version(&mut openapi, &version);
+        self.with_openapi(openapi).docs_version(version)
+    }
+
+    /// Serve `doc` and answer requests for its operations that no route
+    /// implements with example responses generated from its schemas.
+    ///
+    /// The other documented operations, such as `#[dy_api]` handlers that
+    /// aren't mounted, are mocked too. Meant for development: frontends can
+    /// build against the contract before the handlers exist.
+    pub fn mock_from_openapi(mut self, doc: utoipa::openapi::OpenApi) -> Self {
+        self.mock = true;
+        self.with_openapi(doc)
+    }
+
+    /// Send `request` to the app after it binds, before `/ready` reports
+    /// ready. Runs after the requests in the `[warmup]` config section.
+    pub fn warmup(mut self, request: WarmupRequest) -> Self {
+        self.warmups.push(request);
+        self
+    }
+
+    /// Warm-up requests: the configured ones, then those passed to `warmup`.
+    fn resolved_warmups(&self) -> Vec<WarmupRequest> {
+        self.config
+            .iter()
+            .flat_map(|config| config.warmup.requests.iter().cloned())
+            .chain(self.warmups.iter().cloned())
+            .collect()
+    }
+
+    /// Build the final router: auto-configured docs and health routes, the
+    /// mounted application routes, and the default middleware stack.
+    pub fn into_router(mut self) -> Router {
+        let route_table = self
+            .config
+            .as_ref()
+            .is_some_and(|config| self.auto_configured && config.is_development())
+            .then(|| self.routes());
+        if let Some(catalog) = self.i18n.take() {
+            self.router = self
+                .router
+                .layer(LocalizeErrorsLayer)
+                .layer(axum::Extension(catalog));
+        }
+        if let Some(cache) = self.cache.take() {
+            self.router = self.router.layer(axum::Extension(cache));
+        }
+        if let Some(storage) = self.storage.take() {
+            self.router = self.router.layer(axum::Extension(storage));
+        }
+        if let Some(broker) = self.broker.take() {
+            self.router = self.router.layer(axum::Extension(broker));
+        }
+        if let Some(audit) = self.audit.take() {
+            self.router = self.router.layer(axum::Extension(audit));
+        }
+        for extension in std::mem::take(&mut self.extensions) {
+            self.router = extension(self.router);
+        }
+        self.router = self.router.layer(axum::Extension(self.tasks.clone()));
+        if let Some(config) = &self.config {
+            let config = Config(Arc::new(config.clone()));
+            self.router = self.router.layer(axum::Extension(config));
+        }
+
+        let layers = std::mem::take(&mut self.layers);
+        let outer_layers = std::mem::take(&mut self.outer_layers);
+        let wrap = |mut router: Router, layers: Vec<(String, RouterLayer)>| {
+            for (_, layer) in layers {
+                router = layer(router);
+            }
+            router
+        };
+
+        if !self.auto_configured {
+            let mut router = std::mem::take(&mut self.router);
+            if self.mock {
+                let mock = MockServer::new(&self.openapi_document());
+                tracing::warn!("🎭 Serving mocked responses for unimplemented operations");
+                router = router.fallback_service(mock.into_router());
+            }
+            return wrap(wrap(router, layers), outer_layers);
+        }
+
+        let cors = self
+            .config
+            .as_ref()
+            .map(|config| config.server.cors.layer(config.is_production()))
+            .unwrap_or_else(|| CorsConfig::default().layer(false));
 
         // Add health endpoint
         let health_router = Router::new().route(
@@ -95,48 +753,242 @@ impl App {
             }),
         );
 
-        // Setup Swagger UI with a basic OpenAPI spec
-        #[derive(OpenApi)]
-        #[openapi(
-            info(
-                title = "dy-rs API",
-                version = "0.1.0",
-                description = "API built with dy-rs"
-            ),
-            paths(),
-            components(schemas())
-        )]
-        struct ApiDoc;
+        // Serve the OpenAPI document (and Swagger UI if the feature is enabled)
+        let doc = self.openapi_document();
+        let uis = self.resolved_docs_uis();
+        let spec_paths = self.resolved_spec_paths();
+        let mock = self.mock.then(|| MockServer::new(&doc));
 
-        // Add Swagger UI if feature is enabled
-        #[cfg(feature = "swagger-ui")]
-        let swagger_doc = self.openapi.take().unwrap_or_else(|| {
-            if openapi::has_auto_operations() {
-                openapi::build_auto_openapi(openapi::DocInfo::default())
-            } else {
-                ApiDoc::openapi()
-            }
-        });
+        // Build the router with middleware
+        let mut router_with_docs = Router::new();
+        if self.docs_enabled() {
+            router_with_docs = router_with_docs.merge(docs::docs_router(
+                Arc::new(doc),
+                &self.doc_views,
+                &uis,
+                &spec_paths,
+            ));
+        }
+        let router_with_docs =
+            router_with_docs
+                .merge(health_router)
+                .merge(warmup::readiness_router(
+                    self.readiness.clone(),
+                    self.database.clone(),
+                ));
 
-        #[cfg(feature = "swagger-ui")]
-        let swagger = SwaggerUi::new("/docs").url("/api-docs/openapi.json", swagger_doc);
+        let mut router = router_with_docs.merge(self.router);
+        if let Some(config) = self
+            .config
+            .as_ref()
+            .filter(|config| config.is_development())
+        {
+            router = router.merge(debug::debug_config_router(config));
+        }
+        if let Some(routes) = route_table {
+            router = router.merge(debug::debug_routes_router(routes));
+        }
+        if let Some(mock) = mock {
+            tracing::warn!("🎭 Serving mocked responses for unimplemented operations");
+            router = router.fallback_service(mock.into_router());
+        }
+        let pagination = self
+            .config
+            .as_ref()
+            .map(|config| config.pagination)
+            .unwrap_or_default();
+        let proxy = self
+            .config
+            .as_ref()
+            .map(|config| config.server.proxy.clone())
+            .unwrap_or_default();
+        let expose_internal_errors = self
+            .config
+            .as_ref()
+            .is_none_or(|config| config.exposes_internal_errors());
+        let decompression = self
+            .config
+            .as_ref()
+            .map(|config| config.server.decompression.clone())
+            .unwrap_or_default();
+        router = wrap(router, layers);
+        if decompression.enabled {
+            router = router.layer(RequestDecompressionLayer::from_config(&decompression));
+        }
+        let router = router
+            .layer(axum::Extension(pagination))
+            .layer(self.slow_requests.clone())
+            .layer(self.maintenance.clone())
+            .layer(self.internal_errors.clone().expose(expose_internal_errors))
+            .layer(TraceContextLayer)
+            .layer(TraceLayer::new_for_http().make_span_with(RedactedSpan))
+            .layer(ProxyLayer::new(proxy))
+            .layer(cors);
+        wrap(router, outer_layers)
+    }
 
-        // Build the router with middleware
-        #[cfg(feature = "swagger-ui")]
-        let router_with_docs = Router::new().merge(swagger).merge(health_router);
+    /// The served document: an embedded one as-is, otherwise the documents
+    /// passed to `with_openapi` merged with the `#[dy_api]` operations. The
+    /// health route is documented either way, and the configured
+    /// [`DocSettings`] are applied last. See [`testing::contract`](crate::testing::contract)
+    /// for testing it against a committed copy.
+    pub fn openapi_document(&self) -> utoipa::openapi::OpenApi {
+        #[derive(OpenApi)]
+        #[openapi(info(
+            title = "dy-rs API",
+            version = "0.1.0",
+            description = "API built with dy-rs"
+        ))]
+        struct ApiDoc;
 
-        #[cfg(not(feature = "swagger-ui"))]
-        let router_with_docs = health_router;
+        let doc = self.openapi.clone().unwrap_or_else(|| {
+            let mut docs = self.extra_openapi.iter().cloned();
+            let base = docs.next().unwrap_or_else(ApiDoc::openapi);
+            let auto = openapi::has_auto_operations().then(|| {
+                openapi::cached_auto_openapi(openapi::DocInfo::default())
+                    .as_ref()
+                    .clone()
+            });
+            openapi::merge_openapi(base, docs.chain(auto))
+        });
 
-        self.router = router_with_docs
-            .merge(self.router)
-            .layer(TraceLayer::new_for_http())
-            .layer(cors);
+        let resources = self.resource_openapi.iter().cloned();
+        let mut doc = openapi::merge_openapi(
+            doc,
+            std::iter::once(openapi::health_openapi()).chain(resources),
+        );
+        for version in &self.versions {
+            openapi::set_path_version(&mut doc, version.name(), version.is_deprecated());
+        }
+        let settings = self
+            .config
+            .as_ref()
+            .map(|config| config.openapi.clone())
+            .unwrap_or_default()
+            .merge(self.doc_settings.clone());
+        settings.apply(&mut doc);
+        doc
+    }
 
-        self.config = Some(config);
+    /// The routes the app serves, sorted by path: its own, the documented
+    /// operations and the paths added with `route`. See
+    /// [`routes`](crate::routes) for what can't be listed.
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        let mut own = Vec::new();
+        let mut stack = Vec::new();
+        if self.auto_configured {
+            own.push(RouteInfo::new("/health", &["GET"], "health"));
+            own.push(RouteInfo::new(warmup::READY_PATH, &["GET"], "readiness"));
+            if self.docs_enabled() {
+                let spec_paths = self.resolved_spec_paths();
+                let views = self.doc_views.iter().map(Some);
+                for view in std::iter::once(None).chain(views) {
+                    let paths =
+                        view.map_or_else(|| spec_paths.clone(), |v| v.spec_paths(&spec_paths));
+                    own.push(RouteInfo::new(paths.json, &["GET"], "openapi_json"));
+                    own.push(RouteInfo::new(paths.yaml, &["GET"], "openapi_yaml"));
+                    for ui in self.resolved_docs_uis() {
+                        if ui == DocsUi::Swagger && cfg!(not(feature = "swagger-ui")) {
+                            continue;
+                        }
+                        let path =
+                            view.map_or_else(|| ui.path().to_string(), |v| v.ui_path_for(ui));
+                        own.push(RouteInfo::new(path, &["GET"], "docs"));
+                    }
+                }
+            }
+            if self.config.as_ref().is_some_and(AppConfig::is_development) {
+                own.push(RouteInfo::new(
+                    debug::DEBUG_CONFIG_PATH,
+                    &["GET"],
+                    "debug_config",
+                ));
+                own.push(RouteInfo::new(
+                    debug::DEBUG_ROUTES_PATH,
+                    &["GET"],
+                    "debug_routes",
+                ));
+            }
+            stack.extend(["cors", "proxy", "trace", "trace_context", "internal_errors"]);
+            stack.extend(["maintenance", "slow_requests"]);
+            if self
+                .config
+                .as_ref()
+                .is_none_or(|config| config.server.decompression.enabled)
+            {
+                stack.push("decompression");
+            }
+        }
+        let named = |layers: &[(String, RouterLayer)]| {
+            layers
+                .iter()
+                .rev()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        let mut stack: Vec<String> = named(&self.outer_layers)
+            .into_iter()
+            .chain(stack.into_iter().map(str::to_string))
+            .chain(named(&self.layers))
+            .collect();
+        for route in &mut own {
+            route.middleware = stack.clone();
+        }
 
-        tracing::info!("✅ Auto-configuration complete");
-        self
+        if self.i18n.is_some() {
+            stack.push("localize_errors".to_string());
+        }
+        let versioned = |path: &str| {
+            self.versions.iter().any(|version| {
+                let prefix = format!("/{}", version.name());
+                path == prefix || path.starts_with(&format!("{prefix}/"))
+            })
+        };
+        let middleware = |path: &str| {
+            let mut middleware = stack.clone();
+            if versioned(path) {
+                middleware.push("version".to_string());
+            }
+            middleware
+        };
+        let mut routes: Vec<RouteInfo> = Vec::new();
+        let doc = self.openapi_document();
+        for (path, item) in &doc.paths.paths {
+            for (method, operation) in openapi::method_operations(item) {
+                let own_route = own
+                    .iter()
+                    .any(|route| route.path == *path && route.methods.iter().any(|m| m == method));
+                if own_route {
+                    continue;
+                }
+                let handler = operation.operation_id.clone();
+                match routes
+                    .iter_mut()
+                    .find(|route| route.path == *path && route.handler == handler)
+                {
+                    Some(route) => route.methods.push(method.to_string()),
+                    None => routes.push(RouteInfo {
+                        path: path.clone(),
+                        methods: vec![method.to_string()],
+                        handler,
+                        middleware: middleware(path),
+                    }),
+                }
+            }
+        }
+        for path in &self.route_paths {
+            if !routes.iter().any(|route| route.path == *path) {
+                routes.push(RouteInfo {
+                    path: path.clone(),
+                    methods: Vec::new(),
+                    handler: None,
+                    middleware: middleware(path),
+                });
+            }
+        }
+        routes.extend(own);
+        routes.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| a.methods.cmp(&b.methods)));
+        routes
     }
 
     /// Mount additional routes
@@ -145,34 +997,328 @@ impl App {
         self
     }
 
+    /// Mount `router` below `/{version}`, e.g. `"v1"` or an [`ApiVersion`]
+    /// with a deprecation schedule, and serve its docs with
+    /// [`App::docs_version`]. Its handlers can extract the
+    /// [`Version`](crate::versioning::Version), and documented operations
+    /// below the prefix are assigned to it. See [`versioning`](crate::versioning).
+    pub fn mount_versioned(mut self, version: impl Into<ApiVersion>, router: Router) -> Self {
+        let version = version.into();
+        let router = router.layer(VersionLayer::new(version.clone()));
+        let name = version.name().to_string();
+        self.versions.retain(|v| v.name() != name);
+        self.versions.push(version);
+        self.docs_version(name.clone()).mount_at(&name, router)
+    }
+
+    /// Mount `router` below `prefix`, e.g. `/api/v1`. Its routes aren't
+    /// documented; pass their document to [`App::with_openapi_at`] with the
+    /// same prefix.
+    pub fn mount_at(self, prefix: &str, router: Router) -> Self {
+        let prefix = openapi::normalize_prefix(prefix);
+        if prefix.is_empty() {
+            return self.mount(router);
+        }
+        self.mount(Router::new().nest(&prefix, router))
+    }
+
+    /// Mount the list, get, create, update and delete routes of `R`, served
+    /// by `service`, and add them to the OpenAPI document. See
+    /// [`resource`](crate::resource).
+    pub fn resource<R: Resource>(self, service: impl CrudService<R>) -> Self {
+        self.resource_at("", service)
+    }
+
+    /// Like [`App::resource`], with the routes and their documentation below
+    /// `prefix`
+    pub fn resource_at<R: Resource>(mut self, prefix: &str, service: impl CrudService<R>) -> Self {
+        self.resource_openapi
+            .push(openapi::prefix_paths(resource::crud_openapi::<R>(), prefix));
+        self.mount_at(prefix, resource::crud_routes(service))
+    }
+
+    /// Mount every `#[dy_api]` handler that needs no state, on the path and
+    /// method it documents.
+    pub fn auto_routes(self) -> Self {
+        self.mount(routes::auto_router())
+    }
+
+    /// Mount every `#[dy_api]` handler that needs no state or extracts
+    /// `State<S>`, providing `state` to them.
+    pub fn auto_routes_with_state<S>(self, state: S) -> Self
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        self.mount(routes::auto_router_with_state(state))
+    }
+
     /// Add a route manually
     pub fn route(mut self, path: &str, method_router: axum::routing::MethodRouter) -> Self {
+        self.route_paths.push(path.to_string());
         self.router = self.router.route(path, method_router);
         self
     }
 
-    /// Run the application
+    /// Wrap every route, the app's own included, in `layer`, inside the
+    /// auto-configured middleware: requests reach it after CORS, proxy
+    /// resolution, tracing and the other default layers, and see their
+    /// request ID and trace context. Later calls wrap earlier ones.
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.layers.push((
+            layer_name::<L>(),
+            Box::new(move |router: Router| router.layer(layer)),
+        ));
+        self
+    }
+
+    /// Wrap every route in `layer` outside the auto-configured middleware:
+    /// requests reach it before CORS, proxy resolution and tracing, e.g. to
+    /// rewrite paths or turn away clients before they are logged. Later calls
+    /// wrap earlier ones.
+    pub fn layer_before_auto<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.outer_layers.push((
+            layer_name::<L>(),
+            Box::new(move |router: Router| router.layer(layer)),
+        ));
+        self
+    }
+
+    /// Also serve `router`, as given, on `listener`, e.g. `"127.0.0.1:9090"`
+    /// or `"unix:/run/app/admin.sock"`. See [`listen`](crate::listen).
+    pub fn listen(mut self, listener: impl Into<Listener>, router: Router) -> Self {
+        self.listeners.push((listener.into(), router));
+        self
+    }
+
+    /// Run the application. Once the listener is bound, warm-up requests are
+    /// sent to the in-process router and `/ready` answers `503` until they
+    /// are done. On `Ctrl+C` or `SIGTERM` the server stops accepting
+    /// connections and waits for background [`TaskScope`] tasks. Consumer
+    /// groups subscribe once the listener is bound and stop with the tasks.
     pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
-        let config = self.config.unwrap_or_else(|| AppConfig::default());
-        let addr = SocketAddr::from(([0, 0, 0, 0], config.server.port));
+        self.serve(None, shutdown_signal()).await
+    }
 
-        tracing::info!("🎯 Server starting on http://{}", addr);
+    /// The error [`App::run`] would return before serving, if any
+    pub(crate) fn take_build_error(&mut self) -> Option<BuildError> {
+        self.build_error.take()
+    }
 
-        #[cfg(feature = "swagger-ui")]
-        tracing::info!("📚 Swagger UI available at http://{}/docs", addr);
+    /// [`App::run`], on `bound` instead of the configured address if given,
+    /// until `shutdown` completes
+    pub(crate) async fn serve(
+        mut self,
+        bound: Option<BoundListener>,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(error) = self.build_error.take() {
+            return Err(error.into());
+        }
+        let config = self.config.clone().unwrap_or_default();
+        let addr = match (&bound, &config.server.unix_socket) {
+            (Some(bound), _) => bound.local()?,
+            (None, Some(path)) => Listener::Unix(path.clone()),
+            (None, None) => Listener::from(config.server.socket_addr().await?),
+        };
 
-        #[cfg(not(feature = "swagger-ui"))]
-        tracing::info!("💡 Tip: Enable 'swagger-ui' feature for API docs at /docs");
+        tracing::info!("🎯 Server starting on {}", addr);
 
-        tracing::info!("💚 Health check available at http://{}/health", addr);
+        if self.docs_enabled() {
+            for ui in self.resolved_docs_uis() {
+                if ui == DocsUi::Swagger && cfg!(not(feature = "swagger-ui")) {
+                    tracing::info!("💡 Tip: Enable 'swagger-ui' feature for API docs at /docs");
+                    continue;
+                }
+                tracing::info!("📚 {:?} docs available at {}{}", ui, addr, ui.path());
+            }
 
-        let listener = tokio::net::TcpListener::bind(addr).await?;
-        axum::serve(listener, self.router).await?;
+            let spec_paths = self.resolved_spec_paths();
+            tracing::info!(
+                "📄 OpenAPI spec at {addr}{} and {addr}{}",
+                spec_paths.json,
+                spec_paths.yaml
+            );
+        } else {
+            tracing::info!("🔒 Docs are disabled in {}", config.environment);
+        }
+        tracing::info!("💚 Health check available at {}/health", addr);
+        if self.auto_configured && config.is_development() {
+            tracing::info!(
+                "🔍 Effective configuration at {addr}{}",
+                debug::DEBUG_CONFIG_PATH
+            );
+            tracing::info!("🗺️ Route table at {addr}{}", debug::DEBUG_ROUTES_PATH);
+        }
+        if config.server.print_routes {
+            tracing::info!("🗺️ Routes:\n{}", routes::format_table(&self.routes()));
+        }
+
+        if let Some(database) = &self.database
+            && config.database.wait_on_startup_secs > 0
+        {
+            let timeout = std::time::Duration::from_secs(config.database.wait_on_startup_secs);
+            db::wait_for(database, timeout, &config.database.retry).await?;
+            tracing::info!("🗄️ Database {} is up", database.backend());
+        }
+
+        let warmups = self.resolved_warmups();
+        let readiness = self.readiness.clone();
+        readiness.set_ready(warmups.is_empty());
+        let tasks = self.tasks();
+        let consumers = std::mem::take(&mut self.consumers);
+        if let Some(watcher) = self.config_watcher.take() {
+            if let Some(filter) = self.log_filter.clone() {
+                let mut configs = watcher.subscribe_config();
+                tasks.spawn("log_level", async move {
+                    let mut level = configs.borrow().log.level.clone();
+                    while configs.changed().await.is_ok() {
+                        let changed = configs.borrow_and_update().log.level.clone();
+                        if changed != level {
+                            apply_log_level(&filter, changed.as_deref());
+                            level = changed;
+                        }
+                    }
+                });
+            }
+            let maintenance = self.maintenance.clone();
+            let mut configs = watcher.subscribe_config();
+            tasks.spawn("maintenance", async move {
+                let mut settings = configs.borrow().maintenance.clone();
+                while configs.changed().await.is_ok() {
+                    let changed = configs.borrow_and_update().maintenance.clone();
+                    if changed != settings {
+                        maintenance.configure(changed.clone());
+                        settings = changed;
+                    }
+                }
+            });
+            let scope = tasks.clone();
+            tasks.spawn("config_watcher", async move {
+                watcher.run(scope.cancelled()).await;
+            });
+        }
+        let listeners = std::mem::take(&mut self.listeners);
+        let router = self.into_router();
+
+        let listener = match bound {
+            Some(bound) => bound,
+            None => addr.bind().await?,
+        };
+        tracing::info!("🎧 Listening on {}", listener.local()?);
+        let mut others = Vec::new();
+        for (listener, router) in listeners {
+            let listener = listener.bind().await?;
+            tracing::info!("🎧 Also listening on {}", listener.local()?);
+            others.push((listener, router));
+        }
+        for group in consumers {
+            group.start(&tasks).await?;
+        }
+        // One signal stops every listener
+        let (stop, stopped) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            shutdown.await;
+            let _ = stop.send(true);
+        });
+        let shutdown = move || {
+            let mut stopped = stopped.clone();
+            async move {
+                let _ = stopped.wait_for(|stopped| *stopped).await;
+            }
+        };
+        let server = tokio::spawn(listener.serve(router.clone(), shutdown()));
+        let others: Vec<_> = others
+            .into_iter()
+            .map(|(listener, router)| tokio::spawn(listener.serve(router, shutdown())))
+            .collect();
+        if !warmups.is_empty() {
+            tracing::info!("🔥 Warming up with {} request(s)", warmups.len());
+            let outcomes = warmup::run_warmup(&router, &warmups).await;
+            let failed = outcomes.iter().filter(|o| !o.is_ok()).count();
+            readiness.set_ready(true);
+            tracing::info!("✅ Warm-up complete ({failed} failed), ready at {addr}/ready");
+        }
+        server.await??;
+        for server in others {
+            server.await??;
+        }
+
+        let timeout = std::time::Duration::from_secs(config.server.shutdown_timeout_secs);
+        if tasks.active() > 0 {
+            tracing::info!("⏳ Waiting for {} background task(s)", tasks.active());
+        }
+        tasks.drain(timeout).await;
 
         Ok(())
     }
 }
 
+/// The log format of the `loaded` configuration, or the active environment's
+/// default if it couldn't be loaded
+fn log_format(loaded: &Result<AppConfig, BuildError>) -> LogFormat {
+    match loaded {
+        Ok(config) => config.log_format(),
+        Err(_) => LogFormat::default_for(&crate::config::active_environment()),
+    }
+}
+
+/// Filter logs with `level`, or the default filter, unless `RUST_LOG` is set
+fn apply_log_level(filter: &LogFilter, level: Option<&str>) {
+    if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        return;
+    }
+    let level = level.unwrap_or(DEFAULT_LOG_FILTER);
+    match EnvFilter::try_new(level) {
+        Ok(new) => match filter.reload(new) {
+            Ok(()) => tracing::info!(level, "📝 Log level set"),
+            Err(e) => tracing::warn!(error = %e, "Failed to set the log level"),
+        },
+        Err(e) => {
+            tracing::warn!(level, error = %e, "Invalid log.level, keeping the current filter")
+        }
+    }
+}
+
+/// Completes on `Ctrl+C`, or `SIGTERM` on Unix
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("🛑 Shutting down");
+}
+
 impl Default for App {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,457 @@
+//! Audit trail of who did what
+//!
+//! Each [`AuditEntry`] records the acting user, the action, the resource it
+//! touched, the request it happened in and, optionally, what changed. Entries
+//! go to an [`AuditSink`]: [`LogAuditSink`] writes them to the log and
+//! [`PgAuditSink`] to an `audit_log` table.
+//!
+//! Share a sink with [`App::with_audit`](crate::App::with_audit) and record
+//! from handlers with the [`Audit`] extractor, which fills in the actor from
+//! the access token and the request ID:
+//!
+//! ```rust,ignore
+//! App::new().auto_configure().with_audit(PgAuditSink::new(pool.clone()));
+//!
+//! async fn rename_project(audit: Audit, Path(id): Path<i64>, Json(body): Json<Rename>) -> ApiResult<Project> {
+//!     let before = load_project(id).await?;
+//!     let after = save_project(id, &body).await?;
+//!     audit.record("project.renamed", format!("project:{id}"), diff(&before, &after)).await?;
+//!     Ok(Json(after))
+//! }
+//! ```
+//!
+//! With the `auth` feature, [`AuthAppState::audit`](crate::auth::AuthAppState::audit)
+//! also records sign-ins, password changes and the admin actions of the auth
+//! routes.
+
+#[cfg(feature = "sqlx")]
+mod postgres;
+
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+
+use crate::{error::ApiError, redact};
+
+#[cfg(feature = "sqlx")]
+pub use postgres::PgAuditSink;
+
+/// Header the request ID is read from
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// One recorded action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub at: DateTime<Utc>,
+    /// ID of the user who acted, `None` for anonymous requests and the system
+    pub actor: Option<String>,
+    /// What happened, e.g. `project.renamed`
+    pub action: String,
+    /// What it happened to, e.g. `project:42`
+    pub resource: String,
+    /// The `x-request-id` of the request that did it
+    pub request_id: Option<String>,
+    /// What changed, typically from [`diff`]
+    pub diff: Option<Value>,
+}
+
+impl AuditEntry {
+    pub fn new(action: impl Into<String>, resource: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            at: Utc::now(),
+            actor: None,
+            action: action.into(),
+            resource: resource.into(),
+            request_id: None,
+            diff: None,
+        }
+    }
+
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn diff(mut self, diff: impl Into<Option<Value>>) -> Self {
+        self.diff = diff.into();
+        self
+    }
+}
+
+/// The fields that differ between `before` and `after`, as
+/// `{"field": {"from": .., "to": ..}}`, or `None` if nothing changed
+///
+/// Values that don't serialize to objects are compared whole and come back
+/// as `{"from": .., "to": ..}`.
+pub fn diff(before: &impl Serialize, after: &impl Serialize) -> Option<Value> {
+    let before = serde_json::to_value(before).unwrap_or(Value::Null);
+    let after = serde_json::to_value(after).unwrap_or(Value::Null);
+    match (before, after) {
+        (Value::Object(before), Value::Object(mut after)) => {
+            let mut changes = Map::new();
+            for (key, from) in before {
+                let to = after.remove(&key).unwrap_or(Value::Null);
+                if from != to {
+                    changes.insert(key, json!({ "from": from, "to": to }));
+                }
+            }
+            for (key, to) in after {
+                if !to.is_null() {
+                    changes.insert(key, json!({ "from": null, "to": to }));
+                }
+            }
+            (!changes.is_empty()).then_some(Value::Object(changes))
+        }
+        (before, after) if before == after => None,
+        (before, after) => Some(json!({ "from": before, "to": after })),
+    }
+}
+
+/// Where audit entries are kept
+#[async_trait]
+pub trait AuditSink: Send + Sync + 'static {
+    async fn record(&self, entry: AuditEntry) -> Result<(), ApiError>;
+}
+
+/// Sink that logs entries at `info` under the `audit` target
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogAuditSink;
+
+#[async_trait]
+impl AuditSink for LogAuditSink {
+    async fn record(&self, entry: AuditEntry) -> Result<(), ApiError> {
+        let diff = entry.diff.as_ref().map(|diff| diff.to_string());
+        tracing::info!(
+            target: "audit",
+            id = %entry.id,
+            actor = ?entry.actor,
+            action = %entry.action,
+            resource = %entry.resource,
+            request_id = ?entry.request_id,
+            diff = ?diff,
+            "audit"
+        );
+        Ok(())
+    }
+}
+
+/// In-memory sink, for tests and development
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryAuditSink {
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl InMemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything recorded so far, oldest first
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl AuditSink for InMemoryAuditSink {
+    async fn record(&self, entry: AuditEntry) -> Result<(), ApiError> {
+        self.entries.lock().unwrap().push(entry);
+        Ok(())
+    }
+}
+
+/// An [`AuditSink`] shared across the app
+///
+/// Added to requests by [`App::with_audit`](crate::App::with_audit), and
+/// usable as an extractor or as a field of the app state.
+#[derive(Clone)]
+pub struct SharedAuditSink(Arc<dyn AuditSink>);
+
+impl SharedAuditSink {
+    pub fn new(sink: impl AuditSink) -> Self {
+        Self(Arc::new(sink))
+    }
+}
+
+impl From<Arc<dyn AuditSink>> for SharedAuditSink {
+    fn from(sink: Arc<dyn AuditSink>) -> Self {
+        Self(sink)
+    }
+}
+
+/// Redacts the entry's `diff` with the app's [`Redactor`](crate::redact::Redactor)
+/// before recording it
+#[async_trait]
+impl AuditSink for SharedAuditSink {
+    async fn record(&self, mut entry: AuditEntry) -> Result<(), ApiError> {
+        entry.diff = entry.diff.map(|diff| redact::current().value(diff));
+        self.0.record(entry).await
+    }
+}
+
+/// Rejects with `500` when no sink was added with `App::with_audit`
+impl<S> FromRequestParts<S> for SharedAuditSink
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Self>().cloned().ok_or_else(|| {
+            ApiError::InternalServerError(
+                "SharedAuditSink needs a sink added with App::with_audit".to_string(),
+            )
+        })
+    }
+}
+
+/// Who is making a request, and which request it is
+///
+/// The actor is the subject of a valid bearer token, when auth is configured,
+/// and the request ID is the `x-request-id` header set by the client or a
+/// proxy in front of the app. Extracting it never fails.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditContext {
+    pub actor: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl AuditContext {
+    /// An entry for `action` on `resource`, attributed to this request
+    pub fn entry(&self, action: impl Into<String>, resource: impl Into<String>) -> AuditEntry {
+        AuditEntry {
+            actor: self.actor.clone(),
+            request_id: self.request_id.clone(),
+            ..AuditEntry::new(action, resource)
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for AuditContext
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            actor: actor(parts),
+            request_id: parts
+                .headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        })
+    }
+}
+
+#[cfg(feature = "auth")]
+fn actor(parts: &mut Parts) -> Option<String> {
+    use axum::http::header::AUTHORIZATION;
+
+    // Checked first so anonymous requests don't log a missing auth config
+    if parts.extensions.get::<crate::auth::AuthConfig>().is_none()
+        || !parts.headers.contains_key(AUTHORIZATION)
+    {
+        return None;
+    }
+    crate::auth::extractors::extract_auth_user_from_parts(parts)
+        .ok()
+        .map(|user| user.id)
+}
+
+#[cfg(not(feature = "auth"))]
+fn actor(_parts: &mut Parts) -> Option<String> {
+    None
+}
+
+/// Records audit entries attributed to the current request
+///
+/// Rejects with `500` when no sink was added with `App::with_audit`.
+#[derive(Clone)]
+pub struct Audit {
+    sink: SharedAuditSink,
+    context: AuditContext,
+}
+
+impl Audit {
+    pub fn new(sink: SharedAuditSink, context: AuditContext) -> Self {
+        Self { sink, context }
+    }
+
+    pub fn context(&self) -> &AuditContext {
+        &self.context
+    }
+
+    /// Record `action` on `resource`, with what changed, if anything
+    pub async fn record(
+        &self,
+        action: impl Into<String>,
+        resource: impl Into<String>,
+        diff: Option<Value>,
+    ) -> Result<(), ApiError> {
+        let entry = self.context.entry(action, resource).diff(diff);
+        self.sink.record(entry).await
+    }
+
+    /// Record an entry built from [`AuditContext::entry`]
+    pub async fn record_entry(&self, entry: AuditEntry) -> Result<(), ApiError> {
+        self.sink.record(entry).await
+    }
+}
+
+impl<S> FromRequestParts<S> for Audit
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let sink = SharedAuditSink::from_request_parts(parts, state).await?;
+        let Ok(context) = AuditContext::from_request_parts(parts, state).await;
+        Ok(Self::new(sink, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, extract::Request, http::StatusCode, routing::post};
+    use tower::ServiceExt;
+
+    #[test]
+    fn diffs_list_changed_fields() {
+        #[derive(Serialize)]
+        struct Project {
+            name: &'static str,
+            archived: bool,
+            owner: Option<&'static str>,
+        }
+        let before = Project {
+            name: "Apollo",
+            archived: false,
+            owner: None,
+        };
+        let after = Project {
+            name: "Artemis",
+            archived: false,
+            owner: Some("ada"),
+        };
+        assert_eq!(
+            diff(&before, &after),
+            Some(json!({
+                "name": { "from": "Apollo", "to": "Artemis" },
+                "owner": { "from": null, "to": "ada" },
+            }))
+        );
+        assert_eq!(diff(&before, &before), None);
+        assert_eq!(
+            diff(&"draft", &"published"),
+            Some(json!({ "from": "draft", "to": "published" }))
+        );
+    }
+
+    #[tokio::test]
+    async fn shared_sinks_redact_secrets_from_diffs() {
+        let sink = InMemoryAuditSink::new();
+        let entry = AuditEntry::new("user.password_changed", "user:7").diff(json!({
+            "password_hash": { "from": "$argon2id$old", "to": "$argon2id$new" },
+            "email": { "from": "a@example.com", "to": "a@example.com" },
+        }));
+        SharedAuditSink::new(sink.clone())
+            .record(entry)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            sink.entries()[0].diff,
+            Some(json!({
+                "password_hash": "***",
+                "email": { "from": "a@example.com", "to": "a@example.com" },
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn handlers_record_with_the_request_context() {
+        let sink = InMemoryAuditSink::new();
+        let router = Router::new()
+            .route(
+                "/projects/{id}",
+                post(|audit: Audit| async move {
+                    audit
+                        .record("project.renamed", "project:7", diff(&"a", &"b"))
+                        .await
+                }),
+            )
+            .layer(axum::Extension(SharedAuditSink::new(sink.clone())));
+
+        let res = router
+            .clone()
+            .oneshot(
+                Request::post("/projects/7")
+                    .header("x-request-id", "req-1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let entries = sink.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, "project.renamed");
+        assert_eq!(entries[0].resource, "project:7");
+        assert_eq!(entries[0].request_id.as_deref(), Some("req-1"));
+        assert_eq!(entries[0].actor, None);
+        assert_eq!(entries[0].diff, Some(json!({ "from": "a", "to": "b" })));
+
+        let res = Router::new()
+            .route("/", post(|_: Audit| async {}))
+            .oneshot(Request::post("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[cfg(feature = "auth")]
+    #[tokio::test]
+    async fn the_actor_comes_from_the_access_token() {
+        use crate::auth::{AuthConfig, create_token_pair};
+        use axum::http::header::AUTHORIZATION;
+
+        let config = AuthConfig::default();
+        let tokens = create_token_pair("user-1", "a@example.com", vec![], &config).unwrap();
+        let mut parts = Request::post("/")
+            .header(AUTHORIZATION, format!("Bearer {}", tokens.access_token))
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0;
+        parts.extensions.insert(config);
+
+        let Ok(context) = AuditContext::from_request_parts(&mut parts, &()).await;
+        assert_eq!(context.actor.as_deref(), Some("user-1"));
+
+        parts
+            .headers
+            .insert(AUTHORIZATION, "Bearer forged".parse().unwrap());
+        let Ok(context) = AuditContext::from_request_parts(&mut parts, &()).await;
+        assert_eq!(context.actor, None);
+    }
+}
@@ -0,0 +1,72 @@
+//! Audit entries in a Postgres table
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use super::{AuditEntry, AuditSink};
+use crate::error::ApiError;
+
+/// Sink inserting entries into a Postgres table, `audit_log` by default
+///
+/// Create the table with [`PgAuditSink::migrate`] or from
+/// [`PgAuditSink::SCHEMA`] in your own migrations.
+#[derive(Debug, Clone)]
+pub struct PgAuditSink {
+    pool: PgPool,
+    table: String,
+}
+
+impl PgAuditSink {
+    /// `CREATE TABLE` statement for the default `audit_log` table
+    pub const SCHEMA: &'static str = "CREATE TABLE IF NOT EXISTS audit_log (
+    id UUID PRIMARY KEY,
+    at TIMESTAMPTZ NOT NULL,
+    actor TEXT,
+    action TEXT NOT NULL,
+    resource TEXT NOT NULL,
+    request_id TEXT,
+    diff JSONB
+)";
+
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            table: "audit_log".to_string(),
+        }
+    }
+
+    /// Write to `table` instead, which may be schema-qualified
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Create the table if it doesn't exist yet
+    pub async fn migrate(&self) -> Result<(), ApiError> {
+        let schema = Self::SCHEMA.replacen("audit_log", &self.table, 1);
+        sqlx::query(&schema).execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditSink for PgAuditSink {
+    async fn record(&self, entry: AuditEntry) -> Result<(), ApiError> {
+        let insert = format!(
+            "INSERT INTO {} (id, at, actor, action, resource, request_id, diff) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb)",
+            self.table
+        );
+        sqlx::query(&insert)
+            .bind(entry.id)
+            .bind(entry.at)
+            .bind(entry.actor)
+            .bind(entry.action)
+            .bind(entry.resource)
+            .bind(entry.request_id)
+            .bind(entry.diff.map(|diff| diff.to_string()))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
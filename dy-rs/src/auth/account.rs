@@ -0,0 +1,146 @@
+//! Account status lifecycle
+//!
+//! Every account is `pending` (e.g. awaiting email verification), `active`,
+//! `suspended` or `deleted`. Only active accounts can log in or refresh their
+//! tokens. Access tokens already issued stay valid until they expire, so keep
+//! them short-lived.
+//!
+//! Status changes are reported to an [`AccountEventSink`], for audit logs,
+//! emails or revoking sessions:
+//!
+//! ```rust,ignore
+//! let state = AuthAppState::new(config, store).on_account_event(|event: &AccountEvent| {
+//!     tracing::info!(user = %event.user_id, to = %event.to, "account status changed");
+//! });
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Where an account is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    /// Created but not yet allowed in, e.g. until the email is verified
+    Pending,
+    #[default]
+    Active,
+    /// Locked by an admin; can be reactivated
+    Suspended,
+    /// Closed for good
+    Deleted,
+}
+
+impl AccountStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Active => "active",
+            Self::Suspended => "suspended",
+            Self::Deleted => "deleted",
+        }
+    }
+
+    /// Whether an account may move from this status to `to`.
+    ///
+    /// Deletion is final, and a suspended account can only be reactivated
+    /// or deleted.
+    pub fn can_transition_to(self, to: AccountStatus) -> bool {
+        use AccountStatus::*;
+        matches!(
+            (self, to),
+            (Pending, Active | Deleted)
+                | (Active, Suspended | Deleted)
+                | (Suspended, Active | Deleted)
+        )
+    }
+}
+
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AccountStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "active" => Ok(Self::Active),
+            "suspended" => Ok(Self::Suspended),
+            "deleted" => Ok(Self::Deleted),
+            other => Err(format!("unknown account status `{other}`")),
+        }
+    }
+}
+
+/// An account was created or changed status
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountEvent {
+    pub user_id: String,
+    /// `None` when the account was just created
+    pub from: Option<AccountStatus>,
+    pub to: AccountStatus,
+    /// ID of the admin who made the change, if any
+    pub actor: Option<String>,
+}
+
+/// Receives [`AccountEvent`]s
+pub trait AccountEventSink: Send + Sync + 'static {
+    fn on_event(&self, event: &AccountEvent);
+}
+
+impl<F> AccountEventSink for F
+where
+    F: Fn(&AccountEvent) + Send + Sync + 'static,
+{
+    fn on_event(&self, event: &AccountEvent) {
+        self(event)
+    }
+}
+
+/// Sink that logs events, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogAccountEvents;
+
+impl AccountEventSink for LogAccountEvents {
+    fn on_event(&self, event: &AccountEvent) {
+        tracing::info!(
+            user_id = %event.user_id,
+            from = ?event.from,
+            to = %event.to,
+            actor = ?event.actor,
+            "account status changed"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use AccountStatus::*;
+
+    #[test]
+    fn transitions_follow_the_lifecycle() {
+        assert!(Pending.can_transition_to(Active));
+        assert!(Active.can_transition_to(Suspended));
+        assert!(Suspended.can_transition_to(Active));
+        assert!(Suspended.can_transition_to(Deleted));
+        assert!(!Deleted.can_transition_to(Active));
+        assert!(!Suspended.can_transition_to(Pending));
+        assert!(!Active.can_transition_to(Active));
+    }
+
+    #[test]
+    fn statuses_round_trip_as_lowercase_strings() {
+        for status in [Pending, Active, Suspended, Deleted] {
+            assert_eq!(status.as_str().parse::<AccountStatus>(), Ok(status));
+            assert_eq!(serde_json::to_value(status).unwrap(), status.as_str());
+        }
+        assert!("banned".parse::<AccountStatus>().is_err());
+    }
+}
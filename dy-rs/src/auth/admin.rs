@@ -0,0 +1,642 @@
+//! Administrative user management endpoints
+//!
+//! Optional routes under `/admin/users` for listing and searching accounts,
+//! moving them through their [lifecycle](super::account), assigning roles
+//! and forcing a password reset. They need a store implementing [`AdminUserStore`] and are only
+//! reachable with the `admin` role.
+//!
+//! ```rust,ignore
+//! use dy_rs::auth::{AuthConfig, InMemoryUserStore, admin_openapi, admin_routes_with_store};
+//!
+//! App::new()
+//!     .auto_configure()
+//!     .mount(admin_routes_with_store(config, store))
+//!     .with_openapi(admin_openapi())
+//! ```
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    routing::{get, post, put},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use super::{
+    account::{AccountEvent, AccountStatus},
+    config::AuthConfig,
+    extractors::AuthUser,
+    handlers::{AuthAppState, InMemoryUserStore, StoredUser, UserStore},
+    middleware::{RequireRoles, inject_auth_config},
+};
+use crate::audit::{AuditContext, diff};
+use crate::error::ApiError;
+use crate::extractors::ValidatedJson;
+
+/// Role required by the admin routes
+pub const ADMIN_ROLE: &str = "admin";
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+/// Filters for listing users
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UserQuery {
+    /// Case-insensitive substring of the email or name
+    pub q: Option<String>,
+    /// Only users with this role
+    pub role: Option<String>,
+    /// Only users with this account status
+    pub status: Option<AccountStatus>,
+    pub offset: usize,
+    /// Page size, at most 200 (default 50)
+    pub limit: Option<usize>,
+}
+
+impl UserQuery {
+    /// The page size to use, with the default and maximum applied
+    pub fn page_size(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    /// Whether `user` passes the filters (pagination aside)
+    pub fn matches(&self, user: &StoredUser) -> bool {
+        let text = self.q.as_deref().map(str::to_lowercase);
+        text.is_none_or(|q| {
+            user.email.to_lowercase().contains(&q) || user.name.to_lowercase().contains(&q)
+        }) && self
+            .role
+            .as_ref()
+            .is_none_or(|role| user.roles.contains(role))
+            && self.status.is_none_or(|status| user.status == status)
+    }
+}
+
+/// A user as shown to admins
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AdminUserView {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    pub roles: Vec<String>,
+    pub status: AccountStatus,
+    pub password_reset_required: bool,
+    pub email_verified: bool,
+}
+
+impl From<StoredUser> for AdminUserView {
+    fn from(user: StoredUser) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            name: user.name,
+            roles: user.roles,
+            status: user.status,
+            password_reset_required: user.password_reset_required,
+            email_verified: user.email_verified,
+        }
+    }
+}
+
+/// One page of users
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserPage {
+    pub users: Vec<AdminUserView>,
+    /// Users matching the filters, across all pages
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// Roles to assign, replacing the current ones
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct RoleAssignment {
+    #[validate(length(min = 1, message = "At least one role is required"))]
+    pub roles: Vec<String>,
+}
+
+/// New account status
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct StatusChange {
+    pub status: AccountStatus,
+}
+
+/// User storage with the queries and updates the admin routes need
+#[async_trait::async_trait]
+pub trait AdminUserStore: UserStore {
+    /// Users matching `query`, one page at a time, ordered by email
+    async fn list_users(&self, query: &UserQuery) -> Result<UserPage, ApiError>;
+
+    /// Store a new account status; transitions are checked by the caller
+    async fn set_status(&self, id: &str, status: AccountStatus) -> Result<StoredUser, ApiError>;
+
+    /// Replace a user's roles
+    async fn set_roles(&self, id: &str, roles: Vec<String>) -> Result<StoredUser, ApiError>;
+
+    /// Require the user to change their password
+    async fn require_password_reset(&self, id: &str) -> Result<StoredUser, ApiError>;
+}
+
+impl InMemoryUserStore {
+    fn update(
+        &self,
+        id: &str,
+        change: impl FnOnce(&mut StoredUser),
+    ) -> Result<StoredUser, ApiError> {
+        let mut users = self.users.lock().unwrap();
+        let user = users
+            .get_mut(id)
+            .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+        change(user);
+        Ok(user.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl AdminUserStore for InMemoryUserStore {
+    async fn list_users(&self, query: &UserQuery) -> Result<UserPage, ApiError> {
+        let users = self.users.lock().unwrap();
+        let mut matching: Vec<&StoredUser> = users.values().filter(|u| query.matches(u)).collect();
+        matching.sort_by(|a, b| a.email.cmp(&b.email));
+
+        let limit = query.page_size();
+        Ok(UserPage {
+            total: matching.len(),
+            users: matching
+                .into_iter()
+                .skip(query.offset)
+                .take(limit)
+                .cloned()
+                .map(AdminUserView::from)
+                .collect(),
+            offset: query.offset,
+            limit,
+        })
+    }
+
+    async fn set_status(&self, id: &str, status: AccountStatus) -> Result<StoredUser, ApiError> {
+        self.update(id, |user| user.status = status)
+    }
+
+    async fn set_roles(&self, id: &str, roles: Vec<String>) -> Result<StoredUser, ApiError> {
+        self.update(id, |user| user.roles = roles)
+    }
+
+    async fn require_password_reset(&self, id: &str) -> Result<StoredUser, ApiError> {
+        self.update(id, |user| user.password_reset_required = true)
+    }
+}
+
+/// List and search users
+pub async fn list_users<S: AdminUserStore>(
+    State(state): State<AuthAppState<S>>,
+    Query(query): Query<UserQuery>,
+) -> Result<Json<UserPage>, ApiError> {
+    Ok(Json(state.user_store.list_users(&query).await?))
+}
+
+/// Get one user
+pub async fn get_user<S: AdminUserStore>(
+    State(state): State<AuthAppState<S>>,
+    Path(id): Path<String>,
+) -> Result<Json<AdminUserView>, ApiError> {
+    let user = state
+        .user_store
+        .find_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    Ok(Json(user.into()))
+}
+
+/// Move an account to a new status, e.g. suspend, reactivate or delete it
+///
+/// Setting the current status again is a no-op; transitions the lifecycle
+/// doesn't allow (such as out of `deleted`) are rejected.
+pub async fn set_status<S: AdminUserStore>(
+    admin: AuthUser,
+    context: AuditContext,
+    State(state): State<AuthAppState<S>>,
+    Path(id): Path<String>,
+    Json(change): Json<StatusChange>,
+) -> Result<Json<AdminUserView>, ApiError> {
+    let user = state
+        .user_store
+        .find_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    let from = user.status;
+    if from == change.status {
+        return Ok(Json(user.into()));
+    }
+    if !from.can_transition_to(change.status) {
+        return Err(ApiError::BadRequest(format!(
+            "Cannot change account status from {from} to {}",
+            change.status
+        )));
+    }
+
+    let user = state.user_store.set_status(&id, change.status).await?;
+    state.events.on_event(&AccountEvent {
+        user_id: user.id.clone(),
+        from: Some(from),
+        to: user.status,
+        actor: Some(admin.id),
+    });
+    state
+        .record(
+            context
+                .entry("account.status_changed", format!("user:{}", user.id))
+                .diff(diff(&from, &user.status)),
+        )
+        .await;
+    Ok(Json(user.into()))
+}
+
+/// Replace a user's roles
+pub async fn assign_roles<S: AdminUserStore>(
+    context: AuditContext,
+    State(state): State<AuthAppState<S>>,
+    Path(id): Path<String>,
+    ValidatedJson(payload): ValidatedJson<RoleAssignment>,
+) -> Result<Json<AdminUserView>, ApiError> {
+    let before = match state.audit {
+        Some(_) => state
+            .user_store
+            .find_by_id(&id)
+            .await?
+            .map(|user| user.roles),
+        None => None,
+    };
+    let user = state.user_store.set_roles(&id, payload.roles).await?;
+    if let Some(before) = before {
+        state
+            .record(
+                context
+                    .entry("account.roles_changed", format!("user:{id}"))
+                    .diff(diff(&before, &user.roles)),
+            )
+            .await;
+    }
+    tracing::info!(user_id = %id, roles = ?user.roles, "User roles assigned");
+    Ok(Json(user.into()))
+}
+
+/// Make the user change their password via `/auth/password`
+pub async fn force_password_reset<S: AdminUserStore>(
+    context: AuditContext,
+    State(state): State<AuthAppState<S>>,
+    Path(id): Path<String>,
+) -> Result<Json<AdminUserView>, ApiError> {
+    let user = state.user_store.require_password_reset(&id).await?;
+    state
+        .record(context.entry("account.password_reset_forced", format!("user:{id}")))
+        .await;
+    tracing::info!(user_id = %id, "Password reset forced");
+    Ok(Json(user.into()))
+}
+
+/// Create the admin routes, restricted to users with the `admin` role
+pub fn admin_routes_with_store<S: AdminUserStore + Clone>(
+    config: AuthConfig,
+    user_store: S,
+) -> Router {
+    admin_routes_with_state(AuthAppState::new(config, user_store))
+}
+
+/// Create the admin routes from a prepared state, e.g. one with an account
+/// event sink
+pub fn admin_routes_with_state<S: AdminUserStore + Clone>(state: AuthAppState<S>) -> Router {
+    let config = state.config.clone();
+    Router::new()
+        .route("/admin/users", get(list_users::<S>))
+        .route("/admin/users/{id}", get(get_user::<S>))
+        .route("/admin/users/{id}/status", put(set_status::<S>))
+        .route("/admin/users/{id}/roles", put(assign_roles::<S>))
+        .route(
+            "/admin/users/{id}/password-reset",
+            post(force_password_reset::<S>),
+        )
+        .with_state(state)
+        .layer(RequireRoles::any(vec![ADMIN_ROLE]))
+        .layer(axum::middleware::from_fn(move |request, next| {
+            inject_auth_config(config.clone(), request, next)
+        }))
+}
+
+/// OpenAPI document for the routes mounted by [`admin_routes_with_store`]
+pub fn admin_openapi() -> utoipa::openapi::OpenApi {
+    use crate::openapi::{BEARER_AUTH_SCHEME, bearer_security_scheme};
+    use utoipa::openapi::{
+        ComponentsBuilder, OpenApiBuilder, PathItem, PathsBuilder, Ref, RefOr, Required,
+        content::ContentBuilder,
+        path::{HttpMethod, OperationBuilder, ParameterBuilder, ParameterIn},
+        request_body::RequestBodyBuilder,
+        response::ResponseBuilder,
+        schema::{ObjectBuilder, Type},
+        security::SecurityRequirement,
+    };
+
+    let json = |schema: &str| {
+        ContentBuilder::new()
+            .schema(Some(RefOr::Ref(Ref::from_schema_name(schema))))
+            .build()
+    };
+    let param = |name: &str, location: ParameterIn, ty: Type, description: &str| {
+        ParameterBuilder::new()
+            .name(name)
+            .parameter_in(location.clone())
+            .required(if location == ParameterIn::Path {
+                Required::True
+            } else {
+                Required::False
+            })
+            .description(Some(description))
+            .schema(Some(ObjectBuilder::new().schema_type(ty)))
+            .build()
+    };
+    let operation = |id: &str, summary: &str, response: &str| {
+        OperationBuilder::new()
+            .operation_id(Some(id))
+            .tag("Admin")
+            .summary(Some(summary))
+            .security(SecurityRequirement::new(
+                BEARER_AUTH_SCHEME,
+                std::iter::empty::<String>(),
+            ))
+            .response(
+                "200",
+                ResponseBuilder::new()
+                    .description("Success")
+                    .content("application/json", json(response))
+                    .build(),
+            )
+            .response(
+                "401",
+                ResponseBuilder::new().description("Unauthorized").build(),
+            )
+            .response(
+                "403",
+                ResponseBuilder::new()
+                    .description("Admin role required")
+                    .build(),
+            )
+    };
+    let on_user = |id: &str, summary: &str| {
+        operation(id, summary, "AdminUserView")
+            .parameter(param("id", ParameterIn::Path, Type::String, "User ID"))
+            .response(
+                "404",
+                ResponseBuilder::new().description("Not found").build(),
+            )
+    };
+
+    let list = operation("adminListUsers", "List and search users", "UserPage")
+        .parameter(param(
+            "q",
+            ParameterIn::Query,
+            Type::String,
+            "Substring of the email or name",
+        ))
+        .parameter(param(
+            "role",
+            ParameterIn::Query,
+            Type::String,
+            "Only users with this role",
+        ))
+        .parameter(param(
+            "status",
+            ParameterIn::Query,
+            Type::String,
+            "Only users with this account status",
+        ))
+        .parameter(param(
+            "offset",
+            ParameterIn::Query,
+            Type::Integer,
+            "Users to skip",
+        ))
+        .parameter(param(
+            "limit",
+            ParameterIn::Query,
+            Type::Integer,
+            "Page size (default 50, at most 200)",
+        ));
+    let roles = on_user("adminAssignRoles", "Replace a user's roles").request_body(Some(
+        RequestBodyBuilder::new()
+            .content("application/json", json("RoleAssignment"))
+            .required(Some(Required::True))
+            .build(),
+    ));
+
+    let status = on_user("adminSetStatus", "Change a user's account status")
+        .request_body(Some(
+            RequestBodyBuilder::new()
+                .content("application/json", json("StatusChange"))
+                .required(Some(Required::True))
+                .build(),
+        ))
+        .response(
+            "400",
+            ResponseBuilder::new()
+                .description("Transition not allowed")
+                .build(),
+        );
+
+    let paths = PathsBuilder::new()
+        .path("/admin/users", PathItem::new(HttpMethod::Get, list))
+        .path(
+            "/admin/users/{id}",
+            PathItem::new(HttpMethod::Get, on_user("adminGetUser", "Get a user")),
+        )
+        .path(
+            "/admin/users/{id}/status",
+            PathItem::new(HttpMethod::Put, status),
+        )
+        .path(
+            "/admin/users/{id}/roles",
+            PathItem::new(HttpMethod::Put, roles),
+        )
+        .path(
+            "/admin/users/{id}/password-reset",
+            PathItem::new(
+                HttpMethod::Post,
+                on_user("adminForcePasswordReset", "Force a password reset"),
+            ),
+        );
+
+    let components = ComponentsBuilder::new()
+        .schema_from::<AdminUserView>()
+        .schema_from::<UserPage>()
+        .schema_from::<RoleAssignment>()
+        .schema_from::<StatusChange>()
+        .schema_from::<AccountStatus>()
+        .security_scheme(BEARER_AUTH_SCHEME, bearer_security_scheme())
+        .build();
+
+    OpenApiBuilder::new()
+        .paths(paths)
+        .components(Some(components))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{handlers::CreateUserData, jwt::create_token_pair};
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Request, StatusCode},
+    };
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    async fn seed(store: &InMemoryUserStore, email: &str, name: &str) -> StoredUser {
+        store
+            .create(CreateUserData {
+                email: email.to_string(),
+                name: name.to_string(),
+                password_hash: "x".to_string(),
+                status: AccountStatus::Active,
+            })
+            .await
+            .unwrap()
+    }
+
+    fn bearer(user: &StoredUser, roles: &[&str], config: &AuthConfig) -> String {
+        let roles = roles.iter().map(|r| r.to_string()).collect();
+        let tokens = create_token_pair(&user.id, &user.email, roles, config).unwrap();
+        format!("Bearer {}", tokens.access_token)
+    }
+
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+        let res = app.clone().oneshot(request).await.unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn admins_manage_users() {
+        let config = AuthConfig::default();
+        let store = InMemoryUserStore::new();
+        let admin = seed(&store, "root@example.com", "Root").await;
+        let alice = seed(&store, "alice@example.com", "Alice").await;
+        seed(&store, "bob@example.com", "Bob").await;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let app = admin_routes_with_state(
+            AuthAppState::new(config.clone(), store.clone()).on_account_event(
+                move |event: &AccountEvent| sink.lock().unwrap().push(event.clone()),
+            ),
+        );
+        let token = bearer(&admin, &["admin"], &config);
+        let request = |method: &str, uri: String, body: Option<Value>| {
+            let builder = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("authorization", &token)
+                .header("content-type", "application/json");
+            builder
+                .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+                .unwrap()
+        };
+
+        let (status, page) = send(&app, request("GET", "/admin/users?q=ALI".into(), None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["total"], 1);
+        assert_eq!(page["users"][0]["email"], "alice@example.com");
+
+        let uri = format!("/admin/users/{}/status", alice.id);
+        let (status, user) = send(
+            &app,
+            request("PUT", uri.clone(), Some(json!({ "status": "suspended" }))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["status"], "suspended");
+        assert_eq!(
+            *events.lock().unwrap(),
+            [AccountEvent {
+                user_id: alice.id.clone(),
+                from: Some(AccountStatus::Active),
+                to: AccountStatus::Suspended,
+                actor: Some(admin.id.clone()),
+            }]
+        );
+
+        let (_, page) = send(
+            &app,
+            request("GET", "/admin/users?status=suspended".into(), None),
+        )
+        .await;
+        assert_eq!(page["total"], 1);
+
+        let (status, _) = send(
+            &app,
+            request("PUT", uri.clone(), Some(json!({ "status": "deleted" }))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(
+            &app,
+            request("PUT", uri, Some(json!({ "status": "active" }))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(events.lock().unwrap().len(), 2);
+
+        let uri = format!("/admin/users/{}/roles", alice.id);
+        let (status, user) = send(
+            &app,
+            request("PUT", uri, Some(json!({ "roles": ["editor"] }))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["roles"], json!(["editor"]));
+
+        let uri = format!("/admin/users/{}/password-reset", alice.id);
+        let (_, user) = send(&app, request("POST", uri, None)).await;
+        assert_eq!(user["password_reset_required"], true);
+
+        let (status, _) = send(&app, request("GET", "/admin/users/nope".into(), None)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn non_admins_are_rejected() {
+        let config = AuthConfig::default();
+        let store = InMemoryUserStore::new();
+        let user = seed(&store, "user@example.com", "User").await;
+        let app = admin_routes_with_store(config.clone(), store);
+
+        let request = |auth: Option<String>| {
+            let mut builder = Request::get("/admin/users");
+            if let Some(auth) = auth {
+                builder = builder.header("authorization", auth);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        let (status, _) = send(&app, request(None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = send(&app, request(Some(bearer(&user, &["user"], &config)))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "FORBIDDEN");
+    }
+
+    #[test]
+    fn admin_openapi_documents_the_routes() {
+        let doc = serde_json::to_value(admin_openapi()).unwrap();
+        assert!(doc["paths"]["/admin/users"]["get"]["parameters"].is_array());
+        assert!(doc["paths"]["/admin/users/{id}/roles"]["put"]["requestBody"].is_object());
+        assert_eq!(
+            doc["paths"]["/admin/users/{id}/status"]["put"]["security"],
+            json!([{ "bearerAuth": [] }])
+        );
+        assert!(doc["components"]["schemas"]["UserPage"].is_object());
+    }
+}
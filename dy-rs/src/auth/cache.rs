@@ -0,0 +1,263 @@
+//! Short-lived cache of user records
+//!
+//! `/auth/me`, token refreshes and the admin routes look the user up by ID on
+//! every call. Wrapping the store in a [`CachedUserStore`] answers those
+//! lookups from memory for a few seconds:
+//!
+//! ```rust,ignore
+//! let store = CachedUserStore::new(PostgresUserStore::new(pool), Duration::from_secs(30));
+//! let routes = auth_routes_with_store(config, store.clone());
+//!
+//! // after changing a user outside the store
+//! store.invalidate(&user_id);
+//! ```
+//!
+//! Updates made through the wrapper evict the user once they're written.
+//! Changes made elsewhere show up once the entry expires, so keep the TTL
+//! short. Lookups by email always go to the wrapped store, so logins see the
+//! current password hash.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{
+    account::AccountStatus,
+    admin::{AdminUserStore, UserPage, UserQuery},
+    handlers::{CreateUserData, StoredUser, UserStore},
+};
+use crate::error::ApiError;
+
+/// [`UserStore`] wrapper that caches lookups by ID
+#[derive(Clone)]
+pub struct CachedUserStore<S> {
+    inner: S,
+    ttl: Duration,
+    max_entries: usize,
+    entries: Arc<Mutex<HashMap<String, (Instant, StoredUser)>>>,
+}
+
+impl<S: UserStore> CachedUserStore<S> {
+    /// Cache users read from `inner` for `ttl`
+    pub fn new(inner: S, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            max_entries: 10_000,
+            entries: Arc::default(),
+        }
+    }
+
+    /// Most users kept at once (default: 10,000)
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Forget the cached record for `id`
+    pub fn invalidate(&self, id: &str) {
+        self.entries.lock().unwrap().remove(id);
+    }
+
+    /// Forget every cached record
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    fn cached(&self, id: &str) -> Option<StoredUser> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(id) {
+            Some((stored_at, user)) if stored_at.elapsed() < self.ttl => Some(user.clone()),
+            Some(_) => {
+                entries.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, user: &StoredUser) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(&user.id) {
+            entries.retain(|_, (stored_at, _)| stored_at.elapsed() < self.ttl);
+            if entries.len() >= self.max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, (stored_at, _))| *stored_at)
+                    .map(|(id, _)| id.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(user.id.clone(), (Instant::now(), user.clone()));
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: UserStore> UserStore for CachedUserStore<S> {
+    async fn find_by_email(&self, email: &str) -> Result<Option<StoredUser>, ApiError> {
+        self.inner.find_by_email(email).await
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<StoredUser>, ApiError> {
+        if let Some(user) = self.cached(id) {
+            return Ok(Some(user));
+        }
+        let user = self.inner.find_by_id(id).await?;
+        if let Some(user) = &user {
+            self.store(user);
+        }
+        Ok(user)
+    }
+
+    async fn create(&self, user: CreateUserData) -> Result<StoredUser, ApiError> {
+        self.inner.create(user).await
+    }
+
+    async fn create_with_metadata(
+        &self,
+        user: CreateUserData,
+        metadata: serde_json::Value,
+    ) -> Result<StoredUser, ApiError> {
+        self.inner.create_with_metadata(user, metadata).await
+    }
+
+    async fn update_password(&self, id: &str, password_hash: &str) -> Result<(), ApiError> {
+        let result = self.inner.update_password(id, password_hash).await;
+        self.invalidate(id);
+        result
+    }
+
+    async fn email_exists(&self, email: &str) -> Result<bool, ApiError> {
+        self.inner.email_exists(email).await
+    }
+
+    async fn mark_email_verified(&self, id: &str) -> Result<(), ApiError> {
+        let result = self.inner.mark_email_verified(id).await;
+        self.invalidate(id);
+        result
+    }
+
+    async fn password_history(&self, id: &str, limit: usize) -> Result<Vec<String>, ApiError> {
+        self.inner.password_history(id, limit).await
+    }
+
+    async fn record_password_history(
+        &self,
+        id: &str,
+        password_hash: &str,
+        keep: usize,
+    ) -> Result<(), ApiError> {
+        self.inner
+            .record_password_history(id, password_hash, keep)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: AdminUserStore> AdminUserStore for CachedUserStore<S> {
+    async fn list_users(&self, query: &UserQuery) -> Result<UserPage, ApiError> {
+        self.inner.list_users(query).await
+    }
+
+    async fn set_status(&self, id: &str, status: AccountStatus) -> Result<StoredUser, ApiError> {
+        let result = self.inner.set_status(id, status).await;
+        self.invalidate(id);
+        result
+    }
+
+    async fn set_roles(&self, id: &str, roles: Vec<String>) -> Result<StoredUser, ApiError> {
+        let result = self.inner.set_roles(id, roles).await;
+        self.invalidate(id);
+        result
+    }
+
+    async fn require_password_reset(&self, id: &str) -> Result<StoredUser, ApiError> {
+        let result = self.inner.require_password_reset(id).await;
+        self.invalidate(id);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::handlers::InMemoryUserStore;
+
+    async fn seeded() -> (
+        InMemoryUserStore,
+        CachedUserStore<InMemoryUserStore>,
+        String,
+    ) {
+        let inner = InMemoryUserStore::new();
+        let cached = CachedUserStore::new(inner.clone(), Duration::from_secs(60));
+        let user = cached
+            .create(CreateUserData {
+                email: "cached@example.com".to_string(),
+                name: "Cached".to_string(),
+                password_hash: "x".to_string(),
+                status: AccountStatus::Active,
+            })
+            .await
+            .unwrap();
+        (inner, cached, user.id)
+    }
+
+    #[tokio::test]
+    async fn lookups_are_cached_until_updated_through_the_wrapper() {
+        let (inner, cached, id) = seeded().await;
+        cached.find_by_id(&id).await.unwrap();
+
+        // A change behind the cache's back isn't seen...
+        inner.users.lock().unwrap().get_mut(&id).unwrap().name = "Renamed".to_string();
+        let user = cached.find_by_id(&id).await.unwrap().unwrap();
+        assert_eq!(user.name, "Cached");
+
+        // ...but one through the wrapper is.
+        cached
+            .set_roles(&id, vec!["admin".to_string()])
+            .await
+            .unwrap();
+        let user = cached.find_by_id(&id).await.unwrap().unwrap();
+        assert_eq!(user.name, "Renamed");
+        assert_eq!(user.roles, ["admin"]);
+    }
+
+    #[tokio::test]
+    async fn entries_expire_and_stay_within_the_cap() {
+        let (inner, _, id) = seeded().await;
+        let cached = CachedUserStore::new(inner.clone(), Duration::ZERO);
+        cached.find_by_id(&id).await.unwrap();
+        inner.users.lock().unwrap().get_mut(&id).unwrap().name = "Renamed".to_string();
+        assert_eq!(
+            cached.find_by_id(&id).await.unwrap().unwrap().name,
+            "Renamed"
+        );
+
+        let capped = CachedUserStore::new(inner.clone(), Duration::from_secs(60)).max_entries(1);
+        let other = capped
+            .create(CreateUserData {
+                email: "other@example.com".to_string(),
+                name: "Other".to_string(),
+                password_hash: "x".to_string(),
+                status: AccountStatus::Active,
+            })
+            .await
+            .unwrap();
+        capped.find_by_id(&id).await.unwrap();
+        capped.find_by_id(&other.id).await.unwrap();
+        assert_eq!(capped.entries.lock().unwrap().len(), 1);
+        assert!(capped.cached(&other.id).is_some());
+    }
+}
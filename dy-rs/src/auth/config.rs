@@ -3,8 +3,20 @@
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+use super::guest::MergedGuests;
+use super::issuers::{JwksFetcher, JwksKeys, TrustedIssuer};
+use super::password::PasswordValidator;
+use crate::error::ApiError;
+
+/// Default `jwt_secret`, for development only; config validation rejects it
+/// in production
+pub const DEV_JWT_SECRET: &str = "dy-rs-dev-secret-change-me-in-production";
+
 /// Configuration for authentication
+///
+/// Also the `[auth]` configuration section, e.g. `APP__AUTH__JWT_SECRET`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AuthConfig {
     /// Secret key for signing JWT tokens (use a strong random string in production!)
     pub jwt_secret: String,
@@ -29,6 +41,24 @@ pub struct AuthConfig {
 
     /// Argon2 parallelism (default: 4 threads)
     pub argon2_parallelism: u32,
+
+    /// Rules for new passwords, enforced on registration and password changes
+    pub password_policy: PasswordValidator,
+
+    /// Include the reason in `403` responses from role checks (default: off;
+    /// meant for development, as it reveals the roles a route needs)
+    pub explain_denials: bool,
+
+    /// Other issuers whose access tokens are accepted, e.g. a corporate IdP
+    pub issuers: Vec<TrustedIssuer>,
+
+    /// Keys fetched from the JWKS URLs of [`issuers`](Self::issuers)
+    #[serde(skip)]
+    pub jwks: JwksKeys,
+
+    /// Guests merged into an account, whose tokens are no longer accepted
+    #[serde(skip)]
+    pub merged_guests: MergedGuests,
 }
 
 impl AuthConfig {
@@ -64,6 +94,39 @@ impl AuthConfig {
         self
     }
 
+    /// Set the password policy
+    pub fn password_policy(mut self, policy: PasswordValidator) -> Self {
+        self.password_policy = policy;
+        self
+    }
+
+    /// Explain role check failures in `403` responses
+    pub fn explain_denials(mut self, explain: bool) -> Self {
+        self.explain_denials = explain;
+        self
+    }
+
+    /// Also accept access tokens from `issuer`
+    pub fn trust_issuer(mut self, issuer: TrustedIssuer) -> Self {
+        self.issuers.push(issuer);
+        self
+    }
+
+    /// Fetch the keys of trusted issuers configured with a JWKS URL
+    pub async fn refresh_jwks(&self, fetcher: &impl JwksFetcher) -> Result<(), ApiError> {
+        self.jwks.refresh(&self.issuers, fetcher).await
+    }
+
+    /// Run [`refresh_jwks`](Self::refresh_jwks) every `interval`
+    pub fn spawn_jwks_refresh(
+        &self,
+        fetcher: impl JwksFetcher,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        self.jwks
+            .spawn_refresh(self.issuers.clone(), fetcher, interval)
+    }
+
     /// Load auth config from environment variables
     ///
     /// Environment variables:
@@ -79,16 +142,16 @@ impl AuthConfig {
             config.jwt_secret = secret;
         }
 
-        if let Ok(expiry) = std::env::var("AUTH_ACCESS_TOKEN_EXPIRY_SECS") {
-            if let Ok(secs) = expiry.parse() {
-                config.access_token_expiry_secs = secs;
-            }
+        if let Ok(expiry) = std::env::var("AUTH_ACCESS_TOKEN_EXPIRY_SECS")
+            && let Ok(secs) = expiry.parse()
+        {
+            config.access_token_expiry_secs = secs;
         }
 
-        if let Ok(expiry) = std::env::var("AUTH_REFRESH_TOKEN_EXPIRY_SECS") {
-            if let Ok(secs) = expiry.parse() {
-                config.refresh_token_expiry_secs = secs;
-            }
+        if let Ok(expiry) = std::env::var("AUTH_REFRESH_TOKEN_EXPIRY_SECS")
+            && let Ok(secs) = expiry.parse()
+        {
+            config.refresh_token_expiry_secs = secs;
         }
 
         if let Ok(issuer) = std::env::var("AUTH_ISSUER") {
@@ -107,7 +170,7 @@ impl Default for AuthConfig {
     fn default() -> Self {
         Self {
             // WARNING: Change this in production!
-            jwt_secret: "dy-rs-dev-secret-change-me-in-production".to_string(),
+            jwt_secret: DEV_JWT_SECRET.to_string(),
             access_token_expiry_secs: 15 * 60, // 15 minutes
             refresh_token_expiry_secs: 7 * 24 * 60 * 60, // 7 days
             issuer: "dy-rs".to_string(),
@@ -115,6 +178,11 @@ impl Default for AuthConfig {
             argon2_memory_cost: 65536, // 64 MB
             argon2_time_cost: 3,
             argon2_parallelism: 4,
+            password_policy: PasswordValidator::default(),
+            explain_denials: false,
+            issuers: Vec::new(),
+            jwks: JwksKeys::default(),
+            merged_guests: MergedGuests::default(),
         }
     }
 }
@@ -1,5 +1,7 @@
 //! Authentication extractors for Axum handlers
 
+use std::ops::Deref;
+
 use axum::{
     Json,
     extract::FromRequestParts,
@@ -11,9 +13,18 @@ use serde::Serialize;
 use super::{
     config::AuthConfig,
     jwt::{Claims, verify_access_token},
+    testing::MockedUser,
 };
+use crate::error::ApiError;
+
+/// The user or guest behind the request's token; guests merged into an
+/// account are rejected like any revoked token
+pub(crate) fn extract_auth_user_from_parts(parts: &mut Parts) -> Result<AuthUser, AuthError> {
+    // Set by the `MockAuth` test layer
+    if let Some(MockedUser(user)) = parts.extensions.get::<MockedUser>() {
+        return Ok(user.clone());
+    }
 
-fn extract_auth_user_from_parts(parts: &mut Parts) -> Result<AuthUser, AuthError> {
     // Get AuthConfig from extensions (set by middleware)
     let auth_config = parts
         .extensions
@@ -38,14 +49,20 @@ fn extract_auth_user_from_parts(parts: &mut Parts) -> Result<AuthUser, AuthError
 
     // Verify token and extract claims
     let claims = verify_access_token(token, &auth_config).map_err(|_| AuthError::InvalidToken)?;
+    if claims.guest && auth_config.merged_guests.contains(&claims.sub) {
+        return Err(AuthError::InvalidToken);
+    }
 
-    Ok(AuthUser::from_claims(claims))
+    let mut user = AuthUser::from_claims(claims);
+    user.explain_denials = auth_config.explain_denials;
+    Ok(user)
 }
 
 /// Authenticated user extracted from JWT token
 ///
 /// Use this extractor in your handlers to require authentication
-/// and access user information.
+/// and access user information. Anonymous guests are turned away with
+/// `403 ACCOUNT_REQUIRED`; take a [`GuestOrUser`] to let them in.
 ///
 /// # Example
 ///
@@ -73,8 +90,13 @@ pub struct AuthUser {
     /// User roles
     pub roles: Vec<String>,
 
+    /// Whether the email address was verified when the token was issued
+    pub email_verified: bool,
+
     /// Full JWT claims (for advanced use cases)
     pub claims: Claims,
+
+    explain_denials: bool,
 }
 
 impl AuthUser {
@@ -84,7 +106,9 @@ impl AuthUser {
             id: claims.sub.clone(),
             email: claims.email.clone(),
             roles: claims.roles.clone(),
+            email_verified: claims.email_verified,
             claims,
+            explain_denials: false,
         }
     }
 
@@ -108,7 +132,12 @@ impl AuthUser {
         if self.has_role(role) {
             Ok(())
         } else {
-            Err(AuthError::Forbidden(format!("Role '{}' required", role)))
+            Err(self.deny(
+                "require_role",
+                &[role],
+                "all",
+                format!("Role '{}' required", role),
+            ))
         }
     }
 
@@ -117,10 +146,35 @@ impl AuthUser {
         if self.has_any_role(roles) {
             Ok(())
         } else {
-            Err(AuthError::Forbidden(format!(
-                "One of roles {:?} required",
-                roles
-            )))
+            Err(self.deny(
+                "require_any_role",
+                roles,
+                "any",
+                format!("One of roles {:?} required", roles),
+            ))
+        }
+    }
+
+    /// Require a verified email address
+    pub fn require_verified_email(&self) -> Result<(), AuthError> {
+        if self.email_verified {
+            Ok(())
+        } else {
+            Err(AuthError::EmailNotVerified)
+        }
+    }
+
+    /// Whether this is an anonymous guest rather than a registered account
+    pub fn is_guest(&self) -> bool {
+        self.claims.guest
+    }
+
+    /// Require a registered account, turning guests away
+    pub fn require_account(&self) -> Result<(), AuthError> {
+        if self.is_guest() {
+            Err(AuthError::AccountRequired)
+        } else {
+            Ok(())
         }
     }
 
@@ -129,12 +183,57 @@ impl AuthUser {
         if self.has_all_roles(roles) {
             Ok(())
         } else {
-            Err(AuthError::Forbidden(format!(
-                "All of roles {:?} required",
-                roles
-            )))
+            Err(self.deny(
+                "require_all_roles",
+                roles,
+                "all",
+                format!("All of roles {:?} required", roles),
+            ))
         }
     }
+
+    /// Denial by `guard`, which needed `mode` of the `required` roles
+    pub(crate) fn deny(
+        &self,
+        guard: &'static str,
+        required: &[impl AsRef<str>],
+        mode: &'static str,
+        message: String,
+    ) -> AuthError {
+        AuthError::Denied(Box::new(Denial {
+            guard,
+            required: required.iter().map(|r| r.as_ref().to_string()).collect(),
+            mode,
+            user_id: self.id.clone(),
+            roles: self.roles.clone(),
+            message,
+            explain: self.explain_denials,
+        }))
+    }
+}
+
+/// Why a role check turned a request away
+///
+/// Every denial is logged: at `debug` level normally, and at `info` with
+/// [`AuthConfig::explain_denials`] on, which also adds it to the `403`
+/// response under `denial`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Denial {
+    /// The check that failed, e.g. `RequireRoles` or `require_role`
+    pub guard: &'static str,
+    /// Roles the check asked for
+    pub required: Vec<String>,
+    /// Whether `any` or `all` of the required roles were needed
+    #[serde(rename = "match")]
+    pub mode: &'static str,
+    /// The user that was denied
+    pub user_id: String,
+    /// Roles in the user's token
+    pub roles: Vec<String>,
+    #[serde(skip)]
+    message: String,
+    #[serde(skip)]
+    explain: bool,
 }
 
 /// Authentication error type
@@ -146,14 +245,34 @@ pub enum AuthError {
     InvalidToken,
     /// User lacks required permissions
     Forbidden(String),
+    /// User lacks the roles a guard asked for
+    Denied(Box<Denial>),
+    /// The account exists but hasn't been activated yet
+    AccountPending,
+    /// The account was suspended by an admin
+    AccountSuspended,
+    /// The route requires a verified email address
+    EmailNotVerified,
+    /// The route isn't open to guests
+    AccountRequired,
     /// Internal error during authentication
     Internal(String),
+    /// Any other API error, responded to as usual
+    Api(ApiError),
+}
+
+impl From<ApiError> for AuthError {
+    fn from(err: ApiError) -> Self {
+        AuthError::Api(err)
+    }
 }
 
 #[derive(Serialize)]
 struct AuthErrorResponse {
     code: String,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    denial: Option<Denial>,
 }
 
 impl IntoResponse for AuthError {
@@ -170,12 +289,61 @@ impl IntoResponse for AuthError {
                 "Invalid or expired token".to_string(),
             ),
             AuthError::Forbidden(msg) => (StatusCode::FORBIDDEN, "FORBIDDEN", msg),
+            AuthError::Denied(denial) => {
+                if denial.explain {
+                    tracing::info!(
+                        guard = denial.guard,
+                        user_id = %denial.user_id,
+                        required = ?denial.required,
+                        roles = ?denial.roles,
+                        "Authorization denied: {}",
+                        denial.message
+                    );
+                } else {
+                    tracing::debug!(
+                        guard = denial.guard,
+                        user_id = %denial.user_id,
+                        required = ?denial.required,
+                        roles = ?denial.roles,
+                        "Authorization denied: {}",
+                        denial.message
+                    );
+                }
+                let body = AuthErrorResponse {
+                    code: "FORBIDDEN".to_string(),
+                    message: denial.message.clone(),
+                    denial: denial.explain.then_some(*denial),
+                };
+                return (StatusCode::FORBIDDEN, Json(body)).into_response();
+            }
+            AuthError::AccountPending => (
+                StatusCode::FORBIDDEN,
+                "ACCOUNT_PENDING",
+                "Account is not activated yet".to_string(),
+            ),
+            AuthError::AccountSuspended => (
+                StatusCode::FORBIDDEN,
+                "ACCOUNT_SUSPENDED",
+                "Account is suspended".to_string(),
+            ),
+            AuthError::EmailNotVerified => (
+                StatusCode::FORBIDDEN,
+                "EMAIL_NOT_VERIFIED",
+                "Email address is not verified".to_string(),
+            ),
+            AuthError::AccountRequired => (
+                StatusCode::FORBIDDEN,
+                "ACCOUNT_REQUIRED",
+                "Sign up or log in to continue".to_string(),
+            ),
             AuthError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "AUTH_ERROR", msg),
+            AuthError::Api(err) => return err.into_response(),
         };
 
         let body = AuthErrorResponse {
             code: code.to_string(),
             message,
+            denial: None,
         };
 
         (status, Json(body)).into_response()
@@ -194,17 +362,55 @@ where
 {
     type Rejection = AuthError;
 
-    fn from_request_parts(
-        parts: &mut Parts,
-        _state: &S,
-    ) -> impl std::future::Future<Output = Result<Self, Self::Rejection>> + Send {
-        async move { extract_auth_user_from_parts(parts) }
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user = extract_auth_user_from_parts(parts)?;
+        user.require_account()?;
+        Ok(user)
+    }
+}
+
+/// Authenticated user or anonymous guest
+///
+/// Like [`AuthUser`], but also accepts tokens from
+/// [`GuestSessions`](super::GuestSessions), for routes such as carts that
+/// guests may use before signing up. Check [`AuthUser::is_guest`] to tell
+/// them apart.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use dy_rs::auth::GuestOrUser;
+///
+/// async fn add_to_cart(user: GuestOrUser) -> impl IntoResponse {
+///     format!("Added to the cart of {}", user.id)
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct GuestOrUser(pub AuthUser);
+
+impl Deref for GuestOrUser {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &AuthUser {
+        &self.0
+    }
+}
+
+impl<S> FromRequestParts<S> for GuestOrUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        extract_auth_user_from_parts(parts).map(GuestOrUser)
     }
 }
 
 /// Optional authenticated user - doesn't fail if not authenticated
 ///
 /// Useful for routes that behave differently for authenticated vs anonymous users.
+/// Guests count as anonymous, as they do for [`AuthUser`].
 ///
 /// # Example
 ///
@@ -227,15 +433,12 @@ where
 {
     type Rejection = std::convert::Infallible;
 
-    fn from_request_parts(
-        parts: &mut Parts,
-        _state: &S,
-    ) -> impl std::future::Future<Output = Result<Self, Self::Rejection>> + Send {
-        async move {
-            // Reuse the same extraction logic but swallow errors.
-            let user = extract_auth_user_from_parts(parts).ok();
-            Ok(OptionalAuthUser(user))
-        }
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Reuse the same extraction logic but swallow errors.
+        let user = extract_auth_user_from_parts(parts)
+            .ok()
+            .filter(|user| !user.is_guest());
+        Ok(OptionalAuthUser(user))
     }
 }
 
@@ -249,6 +452,8 @@ mod tests {
             sub: "user-123".to_string(),
             email: "test@example.com".to_string(),
             roles: vec!["user".to_string(), "editor".to_string()],
+            email_verified: false,
+            guest: false,
             token_type: "access".to_string(),
             iat: 0,
             exp: i64::MAX,
@@ -281,4 +486,129 @@ mod tests {
         assert!(user.require_role("user").is_ok());
         assert!(user.require_role("admin").is_err());
     }
+
+    #[test]
+    fn test_require_verified_email() {
+        let user = AuthUser::from_claims(mock_claims());
+        assert!(matches!(
+            user.require_verified_email(),
+            Err(AuthError::EmailNotVerified)
+        ));
+
+        let user = AuthUser::from_claims(mock_claims().with_email_verified(true));
+        assert!(user.require_verified_email().is_ok());
+    }
+
+    #[test]
+    fn guests_are_not_accounts() {
+        let user = AuthUser::from_claims(mock_claims());
+        assert!(!user.is_guest());
+        assert!(user.require_account().is_ok());
+
+        let guest = AuthUser::from_claims(Claims::new_guest(
+            std::time::Duration::from_secs(60),
+            &AuthConfig::default(),
+        ));
+        assert!(guest.is_guest());
+        assert!(guest.has_role("guest"));
+        assert!(matches!(
+            guest.require_account(),
+            Err(AuthError::AccountRequired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn guests_need_an_explicit_opt_in() {
+        use crate::auth::{GuestSessions, testing::TestUser};
+        use crate::testing::TestClient;
+        use axum::{Extension, Router, routing::get};
+
+        async fn account(user: AuthUser) -> String {
+            user.id
+        }
+        async fn cart(user: GuestOrUser) -> String {
+            user.id.clone()
+        }
+        async fn maybe(user: OptionalAuthUser) -> String {
+            user.0.map(|user| user.id).unwrap_or_default()
+        }
+
+        let config = AuthConfig::default();
+        let client = TestClient::from_router(
+            Router::new()
+                .route("/account", get(account))
+                .route("/cart", get(cart))
+                .route("/maybe", get(maybe))
+                .layer(Extension(config.clone())),
+        );
+        let guest = GuestSessions::new().issue(&config).unwrap();
+        let user = TestUser::new("user-1").access_token(&config);
+
+        let response = client
+            .get("/account")
+            .bearer(&guest.access_token)
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.json::<serde_json::Value>()["code"],
+            "ACCOUNT_REQUIRED"
+        );
+        client.get("/account").bearer(&user).await.assert_ok();
+
+        let response = client
+            .get("/cart")
+            .bearer(&guest.access_token)
+            .await
+            .assert_ok();
+        assert_eq!(response.text(), guest.guest_id);
+        client.get("/cart").bearer(&user).await.assert_ok();
+
+        let response = client
+            .get("/maybe")
+            .bearer(&guest.access_token)
+            .await
+            .assert_ok();
+        assert_eq!(response.text(), "");
+
+        // Once merged into an account, the guest's token is revoked
+        GuestSessions::new()
+            .merge(&guest.access_token, "user-1", &config)
+            .await
+            .unwrap();
+        client
+            .get("/cart")
+            .bearer(&guest.access_token)
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn denials_are_explained_only_when_enabled() {
+        async fn body(err: AuthError) -> serde_json::Value {
+            let response = err.into_response();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        }
+
+        let mut user = AuthUser::from_claims(mock_claims());
+        let quiet = body(user.require_any_role(&["admin", "owner"]).unwrap_err()).await;
+        assert_eq!(quiet["code"], "FORBIDDEN");
+        assert!(quiet.get("denial").is_none());
+
+        user.explain_denials = true;
+        let explained = body(user.require_any_role(&["admin", "owner"]).unwrap_err()).await;
+        assert_eq!(
+            explained["denial"],
+            serde_json::json!({
+                "guard": "require_any_role",
+                "required": ["admin", "owner"],
+                "match": "any",
+                "user_id": "user-123",
+                "roles": ["user", "editor"],
+            })
+        );
+    }
 }
@@ -0,0 +1,210 @@
+//! Anonymous guest sessions
+//!
+//! With [`GuestSessions`] on the [`AuthAppState`](super::AuthAppState), the
+//! auth routes gain `POST /auth/guest`, which hands out a short-lived access
+//! token for a new anonymous identity, so carts and drafts can be created
+//! before signing up. Guest tokens carry the `guest` role and no refresh
+//! token. [`AuthUser`](super::AuthUser) and
+//! [`RequireAuth`](super::RequireAuth) turn guests away with
+//! `403 ACCOUNT_REQUIRED`; routes open to guests take a
+//! [`GuestOrUser`](super::GuestOrUser) or sit behind
+//! [`RequireAuth::allow_guests`](super::RequireAuth::allow_guests).
+//!
+//! Once the visitor registers or logs in, the client calls
+//! `POST /auth/upgrade` with the account's access token and the old guest
+//! token in the body. The [`GuestMerge`] is passed to a [`GuestMergeSink`] to
+//! move the guest's data over. A guest can be merged only once: afterwards
+//! its token is revoked, and merging it again fails with `409`.
+//!
+//! ```rust,ignore
+//! let state = AuthAppState::new(config, store).guest_sessions(
+//!     GuestSessions::new().on_merge(move |merge: GuestMerge| {
+//!         let pool = pool.clone();
+//!         async move {
+//!             sqlx::query("UPDATE carts SET owner_id = $1 WHERE owner_id = $2")
+//!                 .bind(&merge.user_id)
+//!                 .bind(&merge.guest_id)
+//!                 .execute(&pool)
+//!                 .await?;
+//!             Ok(())
+//!         }
+//!     }),
+//! );
+//! ```
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use super::{
+    config::AuthConfig,
+    jwt::{Claims, encode_token, verify_access_token},
+    models::GuestResponse,
+};
+use crate::error::ApiError;
+
+/// A guest identity was merged into a registered account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GuestMerge {
+    /// ID from the guest token
+    pub guest_id: String,
+    /// The account the guest signed up or logged in as
+    pub user_id: String,
+}
+
+/// Moves a guest's data to the account it was merged into
+///
+/// An error fails the `/auth/upgrade` call, so the client can retry.
+#[async_trait]
+pub trait GuestMergeSink: Send + Sync + 'static {
+    async fn on_merge(&self, merge: GuestMerge) -> Result<(), ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> GuestMergeSink for F
+where
+    F: Fn(GuestMerge) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), ApiError>> + Send,
+{
+    async fn on_merge(&self, merge: GuestMerge) -> Result<(), ApiError> {
+        self(merge).await
+    }
+}
+
+/// Sink that logs merges, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogGuestMerges;
+
+#[async_trait]
+impl GuestMergeSink for LogGuestMerges {
+    async fn on_merge(&self, merge: GuestMerge) -> Result<(), ApiError> {
+        tracing::info!(
+            guest_id = %merge.guest_id,
+            user_id = %merge.user_id,
+            "guest merged into account"
+        );
+        Ok(())
+    }
+}
+
+/// Guests already merged into an account, shared by clones
+///
+/// Held by [`AuthConfig::merged_guests`] so that every extractor and layer
+/// treats a merged guest's token as revoked. Guest ids are kept until their
+/// token expires, in memory: with several instances, route each guest's
+/// upgrade to one of them or share the ids some other way.
+#[derive(Debug, Clone, Default)]
+pub struct MergedGuests(Arc<Mutex<HashMap<String, i64>>>);
+
+impl MergedGuests {
+    /// Whether guest `guest_id` has been merged into an account
+    pub fn contains(&self, guest_id: &str) -> bool {
+        self.0.lock().unwrap().contains_key(guest_id)
+    }
+
+    /// Record guest `guest_id`, whose token expires at `exp`; `false` if it
+    /// was already merged
+    fn insert(&self, guest_id: &str, exp: i64) -> bool {
+        let mut merged = self.0.lock().unwrap();
+        let now = chrono::Utc::now().timestamp();
+        merged.retain(|_, exp| *exp > now);
+        if merged.contains_key(guest_id) {
+            return false;
+        }
+        merged.insert(guest_id.to_string(), exp);
+        true
+    }
+
+    fn remove(&self, guest_id: &str) {
+        self.0.lock().unwrap().remove(guest_id);
+    }
+}
+
+/// Issues guest tokens and merges guests into accounts
+#[derive(Clone)]
+pub struct GuestSessions {
+    expiry: Duration,
+    merges: Arc<dyn GuestMergeSink>,
+}
+
+impl GuestSessions {
+    /// Guest tokens valid for 7 days, with merges logged
+    pub fn new() -> Self {
+        Self {
+            expiry: Duration::from_secs(7 * 24 * 60 * 60),
+            merges: Arc::new(LogGuestMerges),
+        }
+    }
+
+    /// How long guest tokens stay valid (default: 7 days)
+    pub fn expiry(mut self, expiry: Duration) -> Self {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Send merges to `sink` instead of the log
+    pub fn on_merge(mut self, sink: impl GuestMergeSink) -> Self {
+        self.merges = Arc::new(sink);
+        self
+    }
+
+    /// A token for a new guest
+    pub fn issue(&self, config: &AuthConfig) -> Result<GuestResponse, ApiError> {
+        let claims = Claims::new_guest(self.expiry, config);
+        let access_token = encode_token(&claims, config).map_err(|e| {
+            ApiError::InternalServerError(format!("Failed to create guest token: {}", e))
+        })?;
+
+        Ok(GuestResponse {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expires_in: self.expiry.as_secs(),
+            guest_id: claims.sub,
+        })
+    }
+
+    /// Merge the guest behind `guest_token` into the account `user_id`
+    ///
+    /// Each guest is merged once; a second merge fails with `409 Conflict`.
+    /// If the sink fails, the guest is left unmerged so the call can be
+    /// retried.
+    pub async fn merge(
+        &self,
+        guest_token: &str,
+        user_id: &str,
+        config: &AuthConfig,
+    ) -> Result<GuestMerge, ApiError> {
+        let claims = verify_access_token(guest_token, config)
+            .ok()
+            .filter(|claims| claims.guest)
+            .ok_or_else(|| ApiError::BadRequest("Invalid or expired guest token".to_string()))?;
+
+        if !config.merged_guests.insert(&claims.sub, claims.exp) {
+            return Err(ApiError::conflict(
+                "Guest was already merged into an account",
+            ));
+        }
+
+        let merge = GuestMerge {
+            guest_id: claims.sub,
+            user_id: user_id.to_string(),
+        };
+        if let Err(err) = self.merges.on_merge(merge.clone()).await {
+            config.merged_guests.remove(&merge.guest_id);
+            return Err(err);
+        }
+        Ok(merge)
+    }
+}
+
+impl Default for GuestSessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -7,12 +7,24 @@ use axum::{
     routing::{get, post},
 };
 
+use std::sync::Arc;
+
 use super::{
+    account::{AccountEvent, AccountEventSink, AccountStatus, LogAccountEvents},
     config::AuthConfig,
-    extractors::AuthUser,
-    jwt::{create_token_pair, verify_refresh_token},
+    extractors::{AuthError, AuthUser},
+    guest::GuestSessions,
+    jwt::{
+        Claims, create_token_pair_from_claims, verify_email_verification_token,
+        verify_magic_link_token, verify_password_reset_token, verify_refresh_token,
+    },
+    magic_link::MagicLink,
     models::*,
+    password_reset::{PasswordReset, fingerprint},
+    registration::PreRegister,
+    verification::EmailVerification,
 };
+use crate::audit::{AuditContext, AuditEntry, AuditSink, SharedAuditSink};
 use crate::error::ApiError;
 use crate::extractors::ValidatedJson;
 
@@ -34,16 +46,27 @@ use crate::extractors::ValidatedJson;
 /// #[async_trait]
 /// impl UserStore for PostgresUserStore {
 ///     async fn find_by_email(&self, email: &str) -> Result<Option<StoredUser>, ApiError> {
-///         let user = sqlx::query_as!(
-///             StoredUser,
-///             "SELECT id, email, name, password_hash, roles FROM users WHERE email = $1",
+///         let row = sqlx::query!(
+///             "SELECT id, email, name, password_hash, roles, status,
+///                     password_reset_required, email_verified, metadata
+///              FROM users WHERE email = $1",
 ///             email
 ///         )
 ///         .fetch_optional(&self.pool)
 ///         .await?;
-///         Ok(user)
+///         Ok(row.map(|row| StoredUser {
+///             id: row.id,
+///             email: row.email,
+///             name: row.name,
+///             password_hash: row.password_hash,
+///             roles: row.roles,
+///             status: row.status.parse().unwrap_or_default(),
+///             password_reset_required: row.password_reset_required,
+///             email_verified: row.email_verified,
+///             metadata: row.metadata,
+///         }))
 ///     }
-///     
+///
 ///     // ... implement other methods
 /// }
 /// ```
@@ -58,11 +81,61 @@ pub trait UserStore: Send + Sync + 'static {
     /// Create a new user
     async fn create(&self, user: CreateUserData) -> Result<StoredUser, ApiError>;
 
-    /// Update user's password hash
+    /// Create a new user along with the metadata a
+    /// [`PreRegister`](super::PreRegister) hook returned
+    ///
+    /// The default only accepts `null` metadata; override it to store custom
+    /// registration fields.
+    async fn create_with_metadata(
+        &self,
+        user: CreateUserData,
+        metadata: serde_json::Value,
+    ) -> Result<StoredUser, ApiError> {
+        if !metadata.is_null() {
+            return Err(ApiError::InternalServerError(
+                "User store can't save registration metadata".to_string(),
+            ));
+        }
+        self.create(user).await
+    }
+
+    /// Update user's password hash, clearing any forced password reset
     async fn update_password(&self, id: &str, password_hash: &str) -> Result<(), ApiError>;
 
     /// Check if email is already taken
     async fn email_exists(&self, email: &str) -> Result<bool, ApiError>;
+
+    /// Record that the user verified their email address
+    ///
+    /// Called by the email verification and sign-in link routes. The default
+    /// fails, for stores that don't keep track of verified addresses.
+    async fn mark_email_verified(&self, id: &str) -> Result<(), ApiError> {
+        let _ = id;
+        Err(ApiError::internal(
+            "Email verification is not supported by this user store",
+        ))
+    }
+
+    /// Up to `limit` of the user's previous password hashes, newest first
+    ///
+    /// Backs the password policy's `history` rule. The default keeps no
+    /// history, so only the current password is checked.
+    async fn password_history(&self, id: &str, limit: usize) -> Result<Vec<String>, ApiError> {
+        let _ = (id, limit);
+        Ok(Vec::new())
+    }
+
+    /// Remember a password hash the user just replaced, keeping at most
+    /// `keep` of them
+    async fn record_password_history(
+        &self,
+        id: &str,
+        password_hash: &str,
+        keep: usize,
+    ) -> Result<(), ApiError> {
+        let _ = (id, password_hash, keep);
+        Ok(())
+    }
 }
 
 /// Stored user data from database
@@ -73,6 +146,42 @@ pub struct StoredUser {
     pub name: String,
     pub password_hash: String,
     pub roles: Vec<String>,
+    /// Only active accounts can log in or refresh their tokens
+    pub status: AccountStatus,
+    /// Set by an admin to make the user change their password
+    pub password_reset_required: bool,
+    /// Whether the user followed an email verification link
+    pub email_verified: bool,
+    /// Custom registration data returned by a `PreRegister` hook
+    pub metadata: serde_json::Value,
+}
+
+impl StoredUser {
+    pub(crate) fn info(&self) -> AuthUserInfo {
+        AuthUserInfo {
+            id: self.id.clone(),
+            email: self.email.clone(),
+            name: self.name.clone(),
+            roles: self.roles.clone(),
+            password_reset_required: self.password_reset_required,
+            email_verified: self.email_verified,
+        }
+    }
+
+    /// Tokens and user info for a successful sign-in
+    pub(crate) fn auth_response(&self, config: &AuthConfig) -> Result<AuthResponse, ApiError> {
+        let claims = Claims::new_access(&self.id, &self.email, self.roles.clone(), config)
+            .with_email_verified(self.email_verified);
+        let token_pair = create_token_pair_from_claims(claims, config)?;
+
+        Ok(AuthResponse {
+            access_token: token_pair.access_token,
+            refresh_token: token_pair.refresh_token,
+            token_type: token_pair.token_type,
+            expires_in: token_pair.expires_in,
+            user: self.info(),
+        })
+    }
 }
 
 /// Data for creating a new user
@@ -81,6 +190,8 @@ pub struct CreateUserData {
     pub email: String,
     pub name: String,
     pub password_hash: String,
+    /// `Pending` when the account must be activated first (e.g. by email)
+    pub status: AccountStatus,
 }
 
 /// In-memory user store for development/testing
@@ -89,7 +200,10 @@ pub struct CreateUserData {
 /// This is only for development and testing purposes.
 #[derive(Clone, Default)]
 pub struct InMemoryUserStore {
-    users: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, StoredUser>>>,
+    pub(crate) users:
+        std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, StoredUser>>>,
+    password_history:
+        std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, Vec<String>>>>,
 }
 
 impl InMemoryUserStore {
@@ -119,15 +233,34 @@ impl UserStore for InMemoryUserStore {
             name: user.name,
             password_hash: user.password_hash,
             roles: vec!["user".to_string()],
+            status: user.status,
+            password_reset_required: false,
+            email_verified: false,
+            metadata: serde_json::Value::Null,
         };
         users.insert(id, stored.clone());
         Ok(stored)
     }
 
+    async fn create_with_metadata(
+        &self,
+        user: CreateUserData,
+        metadata: serde_json::Value,
+    ) -> Result<StoredUser, ApiError> {
+        let mut stored = self.create(user).await?;
+        stored.metadata = metadata;
+        self.users
+            .lock()
+            .unwrap()
+            .insert(stored.id.clone(), stored.clone());
+        Ok(stored)
+    }
+
     async fn update_password(&self, id: &str, password_hash: &str) -> Result<(), ApiError> {
         let mut users = self.users.lock().unwrap();
         if let Some(user) = users.get_mut(id) {
             user.password_hash = password_hash.to_string();
+            user.password_reset_required = false;
             Ok(())
         } else {
             Err(ApiError::NotFound("User not found".to_string()))
@@ -138,6 +271,37 @@ impl UserStore for InMemoryUserStore {
         let users = self.users.lock().unwrap();
         Ok(users.values().any(|u| u.email == email))
     }
+
+    async fn mark_email_verified(&self, id: &str) -> Result<(), ApiError> {
+        let mut users = self.users.lock().unwrap();
+        if let Some(user) = users.get_mut(id) {
+            user.email_verified = true;
+            Ok(())
+        } else {
+            Err(ApiError::NotFound("User not found".to_string()))
+        }
+    }
+
+    async fn password_history(&self, id: &str, limit: usize) -> Result<Vec<String>, ApiError> {
+        let history = self.password_history.lock().unwrap();
+        Ok(history
+            .get(id)
+            .map(|hashes| hashes.iter().take(limit).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn record_password_history(
+        &self,
+        id: &str,
+        password_hash: &str,
+        keep: usize,
+    ) -> Result<(), ApiError> {
+        let mut history = self.password_history.lock().unwrap();
+        let hashes = history.entry(id.to_string()).or_default();
+        hashes.insert(0, password_hash.to_string());
+        hashes.truncate(keep);
+        Ok(())
+    }
 }
 
 /// Application state for auth routes
@@ -145,15 +309,116 @@ impl UserStore for InMemoryUserStore {
 pub struct AuthAppState<S: UserStore> {
     pub config: AuthConfig,
     pub user_store: S,
+    /// Receives account creations and status changes
+    pub events: Arc<dyn AccountEventSink>,
+    /// Sends verification links on registration, when set
+    pub verification: Option<EmailVerification>,
+    /// Sends password reset links, when set
+    pub password_reset: Option<PasswordReset>,
+    /// Sends sign-in links, when set
+    pub magic_links: Option<MagicLink>,
+    /// Checks custom registration fields, when set
+    pub pre_register: Option<Arc<dyn PreRegister>>,
+    /// Issues guest tokens and merges guests into accounts, when set
+    pub guests: Option<GuestSessions>,
+    /// Records sign-ins, password changes and account changes, when set
+    pub audit: Option<SharedAuditSink>,
+}
+
+impl<S: UserStore> AuthAppState<S> {
+    /// State whose account events are logged
+    pub fn new(config: AuthConfig, user_store: S) -> Self {
+        Self {
+            config,
+            user_store,
+            events: Arc::new(LogAccountEvents),
+            verification: None,
+            password_reset: None,
+            magic_links: None,
+            pre_register: None,
+            guests: None,
+            audit: None,
+        }
+    }
+
+    /// Send account events to `sink` instead of the log
+    pub fn on_account_event(mut self, sink: impl AccountEventSink) -> Self {
+        self.events = Arc::new(sink);
+        self
+    }
+
+    /// Record sign-ins, failed logins, password changes and account changes,
+    /// including those made through the admin routes, in `sink`
+    pub fn audit(mut self, sink: impl AuditSink) -> Self {
+        self.audit = Some(SharedAuditSink::new(sink));
+        self
+    }
+
+    /// Record `entry` if auditing is on; a failing sink is logged rather than
+    /// failing the request
+    pub(crate) async fn record(&self, entry: AuditEntry) {
+        if let Some(audit) = &self.audit
+            && let Err(err) = audit.record(entry).await
+        {
+            tracing::warn!(error = ?err, "Failed to record audit entry");
+        }
+    }
+
+    /// Email new users a verification link and mount the verification routes
+    pub fn verify_emails(mut self, verification: EmailVerification) -> Self {
+        self.verification = Some(verification);
+        self
+    }
+
+    /// Mount the routes that email password reset links and set the new
+    /// password
+    pub fn reset_passwords(mut self, reset: PasswordReset) -> Self {
+        self.password_reset = Some(reset);
+        self
+    }
+
+    /// Mount the routes that email sign-in links and sign in with them
+    pub fn magic_links(mut self, links: MagicLink) -> Self {
+        self.magic_links = Some(links);
+        self
+    }
+
+    /// Run `hook` on each registration's extra fields before the account is
+    /// created, storing what it returns via
+    /// [`UserStore::create_with_metadata`]
+    pub fn pre_register(mut self, hook: impl PreRegister) -> Self {
+        self.pre_register = Some(Arc::new(hook));
+        self
+    }
+
+    /// Mount `/auth/guest` and `/auth/upgrade` for anonymous guest sessions
+    pub fn guest_sessions(mut self, guests: GuestSessions) -> Self {
+        self.guests = Some(guests);
+        self
+    }
+}
+
+/// Reject accounts that may not sign in, once their credentials are verified
+fn ensure_active(user: &StoredUser) -> Result<(), AuthError> {
+    match user.status {
+        AccountStatus::Active => Ok(()),
+        AccountStatus::Pending => Err(AuthError::AccountPending),
+        AccountStatus::Suspended => Err(AuthError::AccountSuspended),
+        // indistinguishable from an unknown account
+        AccountStatus::Deleted => Err(ApiError::Unauthorized.into()),
+    }
 }
 
 /// Login handler
 ///
 /// Authenticates a user with email and password, returns JWT tokens.
+/// Pending and suspended accounts are rejected with `ACCOUNT_PENDING` and
+/// `ACCOUNT_SUSPENDED`.
 pub async fn login<S: UserStore>(
+    context: AuditContext,
     State(state): State<AuthAppState<S>>,
     ValidatedJson(payload): ValidatedJson<LoginRequest>,
-) -> Result<Json<AuthResponse>, ApiError> {
+) -> Result<Json<AuthResponse>, AuthError> {
     // Find user by email
     let user = state
         .user_store
@@ -164,80 +429,105 @@ pub async fn login<S: UserStore>(
     // Verify password
     let password_valid = super::password::verify_password(&payload.password, &user.password_hash)?;
     if !password_valid {
-        return Err(ApiError::Unauthorized);
+        state
+            .record(context.entry("auth.login_failed", format!("user:{}", user.id)))
+            .await;
+        return Err(ApiError::Unauthorized.into());
     }
+    ensure_active(&user)?;
+    state
+        .record(
+            context
+                .entry("auth.login", format!("user:{}", user.id))
+                .actor(&user.id),
+        )
+        .await;
 
     // Generate tokens
-    let token_pair = create_token_pair(&user.id, &user.email, user.roles.clone(), &state.config)?;
-
-    Ok(Json(AuthResponse {
-        access_token: token_pair.access_token,
-        refresh_token: token_pair.refresh_token,
-        token_type: token_pair.token_type,
-        expires_in: token_pair.expires_in,
-        user: AuthUserInfo {
-            id: user.id,
-            email: user.email,
-            name: user.name,
-            roles: user.roles,
-        },
-    }))
+    Ok(Json(user.auth_response(&state.config)?))
 }
 
 /// Registration handler
 ///
-/// Creates a new user account and returns JWT tokens.
+/// Creates a new user account and returns JWT tokens. When email
+/// verification is enabled, the user is also sent a verification link.
+/// Fields beyond [`RegisterRequest`] go to the `PreRegister` hook, if any,
+/// and are ignored otherwise.
 pub async fn register<S: UserStore>(
+    context: AuditContext,
     State(state): State<AuthAppState<S>>,
-    ValidatedJson(payload): ValidatedJson<RegisterRequest>,
+    ValidatedJson(RegisterPayload {
+        request: payload,
+        extra,
+    }): ValidatedJson<RegisterPayload>,
 ) -> Result<Json<AuthResponse>, ApiError> {
     // Validate password strength
-    super::password::validate_password_strength(&payload.password)?;
+    state.config.password_policy.validate(&payload.password)?;
 
     // Check if email is already taken
     if state.user_store.email_exists(&payload.email).await? {
-        return Err(ApiError::BadRequest("Email already registered".to_string()));
+        return Err(ApiError::conflict("Email already registered"));
     }
 
+    let metadata = match &state.pre_register {
+        Some(hook) => Some(hook.pre_register(&payload, &extra).await?),
+        None => None,
+    };
+
     // Hash password
     let password_hash = super::password::hash_password(&payload.password, &state.config)?;
 
     // Create user
-    let user = state
-        .user_store
-        .create(CreateUserData {
-            email: payload.email,
-            name: payload.name,
-            password_hash,
-        })
-        .await?;
+    let data = CreateUserData {
+        email: payload.email,
+        name: payload.name,
+        password_hash,
+        status: AccountStatus::Active,
+    };
+    let user = match metadata {
+        Some(metadata) => {
+            state
+                .user_store
+                .create_with_metadata(data, metadata)
+                .await?
+        }
+        None => state.user_store.create(data).await?,
+    };
+    state.events.on_event(&AccountEvent {
+        user_id: user.id.clone(),
+        from: None,
+        to: user.status,
+        actor: None,
+    });
+    state
+        .record(
+            context
+                .entry("account.created", format!("user:{}", user.id))
+                .actor(&user.id)
+                .diff(crate::audit::diff(&None::<AccountStatus>, &user.status)),
+        )
+        .await;
 
-    // Generate tokens
-    let token_pair = create_token_pair(&user.id, &user.email, user.roles.clone(), &state.config)?;
+    // A failed send shouldn't fail the registration; the user can ask again.
+    if let Some(verification) = &state.verification
+        && let Err(err) = verification.send(&user, &state.config).await
+    {
+        tracing::warn!(user_id = %user.id, error = ?err, "Failed to send verification email");
+    }
 
     tracing::info!(user_id = %user.id, "New user registered");
 
-    Ok(Json(AuthResponse {
-        access_token: token_pair.access_token,
-        refresh_token: token_pair.refresh_token,
-        token_type: token_pair.token_type,
-        expires_in: token_pair.expires_in,
-        user: AuthUserInfo {
-            id: user.id,
-            email: user.email,
-            name: user.name,
-            roles: user.roles,
-        },
-    }))
+    Ok(Json(user.auth_response(&state.config)?))
 }
 
 /// Refresh token handler
 ///
-/// Exchanges a refresh token for a new access/refresh token pair.
+/// Exchanges a refresh token for a new access/refresh token pair, as long
+/// as the account is still active.
 pub async fn refresh_token<S: UserStore>(
     State(state): State<AuthAppState<S>>,
     ValidatedJson(payload): ValidatedJson<TokenRefreshRequest>,
-) -> Result<Json<AuthResponse>, ApiError> {
+) -> Result<Json<AuthResponse>, AuthError> {
     // Verify refresh token
     let claims = verify_refresh_token(&payload.refresh_token, &state.config)?;
 
@@ -247,22 +537,10 @@ pub async fn refresh_token<S: UserStore>(
         .find_by_id(&claims.sub)
         .await?
         .ok_or_else(|| ApiError::Unauthorized)?;
+    ensure_active(&user)?;
 
     // Generate new tokens
-    let token_pair = create_token_pair(&user.id, &user.email, user.roles.clone(), &state.config)?;
-
-    Ok(Json(AuthResponse {
-        access_token: token_pair.access_token,
-        refresh_token: token_pair.refresh_token,
-        token_type: token_pair.token_type,
-        expires_in: token_pair.expires_in,
-        user: AuthUserInfo {
-            id: user.id,
-            email: user.email,
-            name: user.name,
-            roles: user.roles,
-        },
-    }))
+    Ok(Json(user.auth_response(&state.config)?))
 }
 
 /// Logout handler
@@ -288,12 +566,279 @@ pub async fn me<S: UserStore>(
         .await?
         .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
 
-    Ok(Json(AuthUserInfo {
-        id: stored_user.id,
-        email: stored_user.email,
-        name: stored_user.name,
-        roles: stored_user.roles,
-    }))
+    Ok(Json(stored_user.info()))
+}
+
+/// Change password handler
+///
+/// Requires the current password; also completes a reset forced by an admin.
+/// The new password must satisfy [`AuthConfig::password_policy`], including
+/// its history rule.
+pub async fn change_password<S: UserStore>(
+    user: AuthUser,
+    context: AuditContext,
+    State(state): State<AuthAppState<S>>,
+    ValidatedJson(payload): ValidatedJson<ChangePasswordRequest>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let stored_user = state
+        .user_store
+        .find_by_id(&user.id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+
+    let password_valid =
+        super::password::verify_password(&payload.current_password, &stored_user.password_hash)?;
+    if !password_valid {
+        return Err(ApiError::Unauthorized);
+    }
+    replace_password(&state, &stored_user, &payload.new_password).await?;
+    state
+        .record(context.entry("auth.password_changed", format!("user:{}", stored_user.id)))
+        .await;
+
+    tracing::info!(user_id = %stored_user.id, "Password changed");
+    Ok(Json(MessageResponse::new("Password changed")))
+}
+
+/// Set `user`'s password to `new_password`, if the password policy allows it
+async fn replace_password<S: UserStore>(
+    state: &AuthAppState<S>,
+    user: &StoredUser,
+    new_password: &str,
+) -> Result<(), ApiError> {
+    let policy = &state.config.password_policy;
+    policy.validate(new_password)?;
+    // The current password counts towards the history depth.
+    let previous = match policy.history_depth() {
+        0 | 1 => Vec::new(),
+        depth => {
+            state
+                .user_store
+                .password_history(&user.id, depth - 1)
+                .await?
+        }
+    };
+    policy.check_history(
+        new_password,
+        std::iter::once(user.password_hash.as_str()).chain(previous.iter().map(String::as_str)),
+    )?;
+
+    let password_hash = super::password::hash_password(new_password, &state.config)?;
+    state
+        .user_store
+        .update_password(&user.id, &password_hash)
+        .await?;
+    if policy.history_depth() > 1 {
+        state
+            .user_store
+            .record_password_history(&user.id, &user.password_hash, policy.history_depth() - 1)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Password reset request handler
+///
+/// Emails a reset link if an account has the address. The response is the
+/// same either way, so it doesn't reveal which addresses have accounts.
+pub async fn request_password_reset<S: UserStore>(
+    State(state): State<AuthAppState<S>>,
+    ValidatedJson(payload): ValidatedJson<PasswordResetRequest>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let reset = state
+        .password_reset
+        .as_ref()
+        .ok_or_else(|| ApiError::NotFound("Password reset is not enabled".to_string()))?;
+
+    let user = state.user_store.find_by_email(&payload.email).await?;
+    if let Some(user) = user.filter(|user| {
+        !matches!(
+            user.status,
+            AccountStatus::Suspended | AccountStatus::Deleted
+        )
+    }) && let Err(err) = reset.send(&user, &state.config).await
+    {
+        tracing::warn!(user_id = %user.id, error = ?err, "Failed to send password reset email");
+    }
+    Ok(Json(MessageResponse::new(
+        "If the address has an account, a reset link was sent",
+    )))
+}
+
+/// Password reset confirmation handler
+///
+/// Sets a new password for the user the link was sent to, as long as the
+/// password hasn't changed since. The new password must satisfy
+/// [`AuthConfig::password_policy`].
+pub async fn confirm_password_reset<S: UserStore>(
+    context: AuditContext,
+    State(state): State<AuthAppState<S>>,
+    ValidatedJson(payload): ValidatedJson<PasswordResetConfirm>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let invalid = || ApiError::BadRequest("Invalid or expired reset token".to_string());
+
+    let claims =
+        verify_password_reset_token(&payload.token, &state.config).map_err(|_| invalid())?;
+    let user = state
+        .user_store
+        .find_by_id(&claims.sub)
+        .await?
+        .filter(|user| user.email == claims.email && fingerprint(&user.password_hash) == claims.jti)
+        .ok_or_else(invalid)?;
+
+    replace_password(&state, &user, &payload.new_password).await?;
+    state
+        .record(
+            context
+                .entry("auth.password_reset", format!("user:{}", user.id))
+                .actor(&user.id),
+        )
+        .await;
+
+    tracing::info!(user_id = %user.id, "Password reset");
+    Ok(Json(MessageResponse::new("Password reset")))
+}
+
+/// Sign-in link request handler
+///
+/// Emails a sign-in link if an active account has the address. Like
+/// [`request_password_reset`], the response doesn't reveal whether it does.
+pub async fn request_magic_link<S: UserStore>(
+    State(state): State<AuthAppState<S>>,
+    ValidatedJson(payload): ValidatedJson<MagicLinkRequest>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let links = state
+        .magic_links
+        .as_ref()
+        .ok_or_else(|| ApiError::NotFound("Sign-in links are not enabled".to_string()))?;
+
+    let user = state.user_store.find_by_email(&payload.email).await?;
+    if let Some(user) = user.filter(|user| user.status == AccountStatus::Active)
+        && let Err(err) = links.send(&user, &state.config).await
+    {
+        tracing::warn!(user_id = %user.id, error = ?err, "Failed to send sign-in link");
+    }
+    Ok(Json(MessageResponse::new(
+        "If the address has an account, a sign-in link was sent",
+    )))
+}
+
+/// Sign-in link handler
+///
+/// Exchanges the token from a sign-in link for a token pair, and marks the
+/// address as verified.
+pub async fn magic_link_login<S: UserStore>(
+    context: AuditContext,
+    State(state): State<AuthAppState<S>>,
+    ValidatedJson(payload): ValidatedJson<MagicLinkLogin>,
+) -> Result<Json<AuthResponse>, AuthError> {
+    let invalid = || ApiError::BadRequest("Invalid or expired sign-in token".to_string());
+
+    let claims = verify_magic_link_token(&payload.token, &state.config).map_err(|_| invalid())?;
+    let mut user = state
+        .user_store
+        .find_by_id(&claims.sub)
+        .await?
+        .filter(|user| user.email == claims.email)
+        .ok_or_else(invalid)?;
+    ensure_active(&user)?;
+
+    if !user.email_verified {
+        state.user_store.mark_email_verified(&user.id).await?;
+        user.email_verified = true;
+    }
+    state
+        .record(
+            context
+                .entry("auth.magic_link_login", format!("user:{}", user.id))
+                .actor(&user.id),
+        )
+        .await;
+    tracing::info!(user_id = %user.id, "Signed in with a sign-in link");
+    Ok(Json(user.auth_response(&state.config)?))
+}
+
+/// Email verification handler
+///
+/// Marks the address as verified if the link's token is valid and was issued
+/// for the user's current email. Clients should refresh their tokens
+/// afterwards to pick up the `email_verified` claim.
+pub async fn verify_email<S: UserStore>(
+    State(state): State<AuthAppState<S>>,
+    ValidatedJson(payload): ValidatedJson<VerifyEmailRequest>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let invalid = || ApiError::BadRequest("Invalid or expired verification token".to_string());
+
+    let claims =
+        verify_email_verification_token(&payload.token, &state.config).map_err(|_| invalid())?;
+    let user = state
+        .user_store
+        .find_by_id(&claims.sub)
+        .await?
+        .filter(|user| user.email == claims.email)
+        .ok_or_else(invalid)?;
+
+    if !user.email_verified {
+        state.user_store.mark_email_verified(&user.id).await?;
+        tracing::info!(user_id = %user.id, "Email verified");
+    }
+    Ok(Json(MessageResponse::new("Email verified")))
+}
+
+/// Resend the verification link to the current user
+pub async fn resend_verification<S: UserStore>(
+    user: AuthUser,
+    State(state): State<AuthAppState<S>>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let verification = state
+        .verification
+        .as_ref()
+        .ok_or_else(|| ApiError::NotFound("Email verification is not enabled".to_string()))?;
+    let stored_user = state
+        .user_store
+        .find_by_id(&user.id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    if stored_user.email_verified {
+        return Err(ApiError::conflict("Email already verified"));
+    }
+
+    verification.send(&stored_user, &state.config).await?;
+    Ok(Json(MessageResponse::new("Verification email sent")))
+}
+
+/// Guest session handler
+///
+/// Issues an access token for a new anonymous guest.
+pub async fn guest_session<S: UserStore>(
+    State(state): State<AuthAppState<S>>,
+) -> Result<Json<GuestResponse>, ApiError> {
+    let guests = state
+        .guests
+        .as_ref()
+        .ok_or_else(|| ApiError::NotFound("Guest sessions are not enabled".to_string()))?;
+    Ok(Json(guests.issue(&state.config)?))
+}
+
+/// Guest upgrade handler
+///
+/// Merges the guest behind `guest_token` into the calling account, which the
+/// visitor has just registered or logged in as.
+pub async fn upgrade_guest<S: UserStore>(
+    user: AuthUser,
+    State(state): State<AuthAppState<S>>,
+    ValidatedJson(payload): ValidatedJson<UpgradeGuestRequest>,
+) -> Result<Json<MessageResponse>, AuthError> {
+    let guests = state
+        .guests
+        .as_ref()
+        .ok_or_else(|| ApiError::NotFound("Guest sessions are not enabled".to_string()))?;
+
+    let merge = guests
+        .merge(&payload.guest_token, &user.id, &state.config)
+        .await?;
+    tracing::info!(guest_id = %merge.guest_id, user_id = %merge.user_id, "Guest upgraded");
+    Ok(Json(MessageResponse::new("Guest merged into account")))
 }
 
 /// Create auth routes with a custom user store
@@ -309,18 +854,43 @@ pub async fn me<S: UserStore>(
 /// let routes = auth_routes_with_store(config, store);
 /// ```
 pub fn auth_routes_with_store<S: UserStore + Clone>(config: AuthConfig, user_store: S) -> Router {
-    let state = AuthAppState {
-        config: config.clone(),
-        user_store,
-    };
+    auth_routes_with_state(AuthAppState::new(config, user_store))
+}
 
-    Router::new()
+/// Create auth routes from a prepared state, e.g. one with an account
+/// event sink or email verification
+pub fn auth_routes_with_state<S: UserStore + Clone>(state: AuthAppState<S>) -> Router {
+    let mut router = Router::new()
         .route("/auth/login", post(login::<S>))
         .route("/auth/register", post(register::<S>))
         .route("/auth/refresh", post(refresh_token::<S>))
         .route("/auth/logout", post(logout))
         .route("/auth/me", get(me::<S>))
-        .with_state(state)
+        .route("/auth/password", post(change_password::<S>));
+    if state.verification.is_some() {
+        router = router
+            .route("/auth/verify-email", post(verify_email::<S>))
+            .route("/auth/verify-email/resend", post(resend_verification::<S>));
+    }
+    if state.password_reset.is_some() {
+        router = router
+            .route("/auth/password-reset", post(request_password_reset::<S>))
+            .route(
+                "/auth/password-reset/confirm",
+                post(confirm_password_reset::<S>),
+            );
+    }
+    if state.magic_links.is_some() {
+        router = router
+            .route("/auth/magic-link", post(request_magic_link::<S>))
+            .route("/auth/magic-link/verify", post(magic_link_login::<S>));
+    }
+    if state.guests.is_some() {
+        router = router
+            .route("/auth/guest", post(guest_session::<S>))
+            .route("/auth/upgrade", post(upgrade_guest::<S>));
+    }
+    router.with_state(state)
 }
 
 /// Create auth routes with in-memory store (for development)
@@ -330,6 +900,231 @@ pub fn auth_routes(config: AuthConfig) -> Router {
     auth_routes_with_store(config, InMemoryUserStore::new())
 }
 
+/// OpenAPI document for the routes mounted by [`auth_routes`] and
+/// [`auth_routes_with_store`]
+///
+/// Pass it to [`App::with_openapi`](crate::App::with_openapi) to list the
+/// auth endpoints alongside your own, or to
+/// [`App::with_openapi_at`](crate::App::with_openapi_at) when the routes are
+/// mounted below a prefix with [`App::mount_at`](crate::App::mount_at). The `/auth/verify-email` routes are only
+/// mounted when email verification is enabled, the `/auth/password-reset`
+/// and `/auth/magic-link` routes only with password resets and sign-in
+/// links, and `/auth/guest` and `/auth/upgrade` only with guest sessions.
+pub fn auth_openapi() -> utoipa::openapi::OpenApi {
+    use crate::openapi::{BEARER_AUTH_SCHEME, bearer_security_scheme};
+    use utoipa::openapi::{
+        ComponentsBuilder, OpenApiBuilder, PathItem, PathsBuilder, Ref, RefOr, Required,
+        path::{HttpMethod, OperationBuilder},
+        request_body::RequestBodyBuilder,
+        response::ResponseBuilder,
+        security::SecurityRequirement,
+    };
+
+    let json = |schema: &str| {
+        utoipa::openapi::content::ContentBuilder::new()
+            .schema(Some(RefOr::Ref(Ref::from_schema_name(schema))))
+            .build()
+    };
+    let operation = |id: &str, summary: &str, request: Option<&str>, response: &str| {
+        let mut operation = OperationBuilder::new()
+            .operation_id(Some(id))
+            .tag("Auth")
+            .summary(Some(summary))
+            .response(
+                "200",
+                ResponseBuilder::new()
+                    .description("Success")
+                    .content("application/json", json(response))
+                    .build(),
+            )
+            .response(
+                "401",
+                ResponseBuilder::new().description("Unauthorized").build(),
+            );
+        if let Some(request) = request {
+            operation = operation.request_body(Some(
+                RequestBodyBuilder::new()
+                    .content("application/json", json(request))
+                    .required(Some(Required::True))
+                    .build(),
+            ));
+        }
+        operation
+    };
+
+    let bearer = || SecurityRequirement::new(BEARER_AUTH_SCHEME, std::iter::empty::<String>());
+    let me = operation("authMe", "Get the current user", None, "AuthUserInfo").security(bearer());
+    let change_password = operation(
+        "authChangePassword",
+        "Change the current user's password",
+        Some("ChangePasswordRequest"),
+        "MessageResponse",
+    )
+    .security(bearer());
+    let resend_verification = operation(
+        "authResendVerification",
+        "Resend the email verification link",
+        None,
+        "MessageResponse",
+    )
+    .security(bearer());
+    let upgrade_guest = operation(
+        "authUpgradeGuest",
+        "Merge a guest into the current account",
+        Some("UpgradeGuestRequest"),
+        "MessageResponse",
+    )
+    .security(bearer());
+
+    let paths = PathsBuilder::new()
+        .path(
+            "/auth/login",
+            PathItem::new(
+                HttpMethod::Post,
+                operation("authLogin", "Log in", Some("LoginRequest"), "AuthResponse"),
+            ),
+        )
+        .path(
+            "/auth/register",
+            PathItem::new(
+                HttpMethod::Post,
+                operation(
+                    "authRegister",
+                    "Register a new account",
+                    Some("RegisterRequest"),
+                    "AuthResponse",
+                ),
+            ),
+        )
+        .path(
+            "/auth/refresh",
+            PathItem::new(
+                HttpMethod::Post,
+                operation(
+                    "authRefresh",
+                    "Exchange a refresh token",
+                    Some("TokenRefreshRequest"),
+                    "AuthResponse",
+                ),
+            ),
+        )
+        .path(
+            "/auth/logout",
+            PathItem::new(
+                HttpMethod::Post,
+                operation("authLogout", "Log out", None, "MessageResponse"),
+            ),
+        )
+        .path("/auth/me", PathItem::new(HttpMethod::Get, me))
+        .path(
+            "/auth/password",
+            PathItem::new(HttpMethod::Post, change_password),
+        )
+        .path(
+            "/auth/verify-email",
+            PathItem::new(
+                HttpMethod::Post,
+                operation(
+                    "authVerifyEmail",
+                    "Verify an email address",
+                    Some("VerifyEmailRequest"),
+                    "MessageResponse",
+                ),
+            ),
+        )
+        .path(
+            "/auth/verify-email/resend",
+            PathItem::new(HttpMethod::Post, resend_verification),
+        )
+        .path(
+            "/auth/password-reset",
+            PathItem::new(
+                HttpMethod::Post,
+                operation(
+                    "authRequestPasswordReset",
+                    "Email a password reset link",
+                    Some("PasswordResetRequest"),
+                    "MessageResponse",
+                ),
+            ),
+        )
+        .path(
+            "/auth/password-reset/confirm",
+            PathItem::new(
+                HttpMethod::Post,
+                operation(
+                    "authConfirmPasswordReset",
+                    "Set a new password with a reset token",
+                    Some("PasswordResetConfirm"),
+                    "MessageResponse",
+                ),
+            ),
+        )
+        .path(
+            "/auth/magic-link",
+            PathItem::new(
+                HttpMethod::Post,
+                operation(
+                    "authRequestMagicLink",
+                    "Email a sign-in link",
+                    Some("MagicLinkRequest"),
+                    "MessageResponse",
+                ),
+            ),
+        )
+        .path(
+            "/auth/magic-link/verify",
+            PathItem::new(
+                HttpMethod::Post,
+                operation(
+                    "authMagicLinkLogin",
+                    "Sign in with a sign-in link token",
+                    Some("MagicLinkLogin"),
+                    "AuthResponse",
+                ),
+            ),
+        )
+        .path(
+            "/auth/guest",
+            PathItem::new(
+                HttpMethod::Post,
+                operation(
+                    "authGuest",
+                    "Start an anonymous guest session",
+                    None,
+                    "GuestResponse",
+                ),
+            ),
+        )
+        .path(
+            "/auth/upgrade",
+            PathItem::new(HttpMethod::Post, upgrade_guest),
+        );
+
+    let components = ComponentsBuilder::new()
+        .schema_from::<LoginRequest>()
+        .schema_from::<RegisterRequest>()
+        .schema_from::<TokenRefreshRequest>()
+        .schema_from::<ChangePasswordRequest>()
+        .schema_from::<VerifyEmailRequest>()
+        .schema_from::<UpgradeGuestRequest>()
+        .schema_from::<PasswordResetRequest>()
+        .schema_from::<PasswordResetConfirm>()
+        .schema_from::<MagicLinkRequest>()
+        .schema_from::<MagicLinkLogin>()
+        .schema_from::<GuestResponse>()
+        .schema_from::<AuthResponse>()
+        .schema_from::<AuthUserInfo>()
+        .schema_from::<MessageResponse>()
+        .security_scheme(BEARER_AUTH_SCHEME, bearer_security_scheme())
+        .build();
+
+    OpenApiBuilder::new()
+        .paths(paths)
+        .components(Some(components))
+        .build()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -450,6 +1245,644 @@ mod tests {
         assert_eq!(refreshed.user.email, "login@example.com");
     }
 
+    #[tokio::test]
+    async fn sign_ins_are_audited() {
+        use crate::audit::InMemoryAuditSink;
+
+        let config = AuthConfig::default();
+        let sink = InMemoryAuditSink::new();
+        let app = auth_routes_with_state(
+            AuthAppState::new(config.clone(), InMemoryUserStore::new()).audit(sink.clone()),
+        )
+        .layer(axum::Extension(config));
+
+        let credentials = |password: &str| serde_json::json!({ "email": "audit@example.com", "password": password });
+        let mut register = credentials("StrongPass1");
+        register["name"] = "Audit".into();
+        for (uri, body, status) in [
+            ("/auth/register", register, StatusCode::OK),
+            (
+                "/auth/login",
+                credentials("WrongPass1"),
+                StatusCode::UNAUTHORIZED,
+            ),
+            ("/auth/login", credentials("StrongPass1"), StatusCode::OK),
+        ] {
+            let mut request = json_req(uri, &body);
+            request
+                .headers_mut()
+                .insert("x-request-id", "req-1".parse().unwrap());
+            let res = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(res.status(), status);
+        }
+
+        let entries = sink.entries();
+        let actions: Vec<_> = entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(
+            actions,
+            ["account.created", "auth.login_failed", "auth.login"]
+        );
+        let user_id = entries[0].actor.clone().unwrap();
+        assert_eq!(entries[0].resource, format!("user:{user_id}"));
+        assert_eq!(
+            entries[0].diff,
+            Some(serde_json::json!({ "from": null, "to": "active" }))
+        );
+        assert_eq!(entries[1].actor, None);
+        assert_eq!(entries[2].actor.as_deref(), Some(user_id.as_str()));
+        assert!(
+            entries
+                .iter()
+                .all(|e| e.request_id.as_deref() == Some("req-1"))
+        );
+    }
+
+    #[tokio::test]
+    async fn suspended_users_cannot_log_in_and_resets_clear_on_change() {
+        let config = AuthConfig::default();
+        let store = InMemoryUserStore::new();
+        let app = auth_routes_with_store(config.clone(), store.clone()).layer(middleware::from_fn(
+            move |mut req: Request<Body>, next: Next| {
+                let cfg = config.clone();
+                async move {
+                    req.extensions_mut().insert(cfg);
+                    next.run(req).await
+                }
+            },
+        ));
+        let credentials = serde_json::json!({
+            "email": "reset@example.com",
+            "password": "StrongPass1",
+            "name": "Reset"
+        });
+        app.clone()
+            .oneshot(json_req("/auth/register", &credentials))
+            .await
+            .unwrap();
+        let id = store
+            .find_by_email("reset@example.com")
+            .await
+            .unwrap()
+            .unwrap()
+            .id;
+
+        store
+            .users
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .unwrap()
+            .password_reset_required = true;
+        let res = app
+            .clone()
+            .oneshot(json_req("/auth/login", &credentials))
+            .await
+            .unwrap();
+        let body: AuthResponse =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert!(body.user.password_reset_required);
+
+        let change = Request::builder()
+            .method("POST")
+            .uri("/auth/password")
+            .header("content-type", "application/json")
+            .header("authorization", format!("Bearer {}", body.access_token))
+            .body(Body::from(
+                serde_json::json!({
+                    "current_password": "StrongPass1",
+                    "new_password": "EvenStronger2"
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let res = app.clone().oneshot(change).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let stored = store.find_by_id(&id).await.unwrap().unwrap();
+        assert!(!stored.password_reset_required);
+
+        store.users.lock().unwrap().get_mut(&id).unwrap().status = AccountStatus::Suspended;
+        let login = serde_json::json!({
+            "email": "reset@example.com",
+            "password": "EvenStronger2"
+        });
+        let res = app.oneshot(json_req("/auth/login", &login)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["code"], "ACCOUNT_SUSPENDED");
+    }
+
+    #[derive(Clone, Default)]
+    struct Outbox(Arc<std::sync::Mutex<Vec<crate::mail::Email>>>);
+
+    #[async_trait::async_trait]
+    impl crate::mail::Mailer for Outbox {
+        async fn send(&self, email: crate::mail::Email) -> Result<(), ApiError> {
+            self.0.lock().unwrap().push(email);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn verified_email_unlocks_gated_routes() {
+        use super::super::middleware::RequireVerifiedEmail;
+
+        let config = AuthConfig::default();
+        let outbox = Outbox::default();
+        let state = AuthAppState::new(config.clone(), InMemoryUserStore::new()).verify_emails(
+            EmailVerification::new(outbox.clone(), "https://app.test/verify?token={token}"),
+        );
+        let gated = Router::new()
+            .route("/orders", post(|| async { "ordered" }))
+            .layer(RequireVerifiedEmail);
+        let app = auth_routes_with_state(state)
+            .merge(gated)
+            .layer(middleware::from_fn(
+                move |mut req: Request<Body>, next: Next| {
+                    let cfg = config.clone();
+                    async move {
+                        req.extensions_mut().insert(cfg);
+                        next.run(req).await
+                    }
+                },
+            ));
+        let order = |token: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/orders")
+                .header("authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let res = app
+            .clone()
+            .oneshot(json_req(
+                "/auth/register",
+                &serde_json::json!({
+                    "email": "verify@example.com",
+                    "password": "StrongPass1",
+                    "name": "Verify"
+                }),
+            ))
+            .await
+            .unwrap();
+        let registered: AuthResponse =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert!(!registered.user.email_verified);
+
+        let res = app
+            .clone()
+            .oneshot(order(&registered.access_token))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["code"], "EMAIL_NOT_VERIFIED");
+
+        let email = outbox.0.lock().unwrap().pop().expect("verification email");
+        assert_eq!(email.to, vec!["verify@example.com".to_string()]);
+        let token = email
+            .html
+            .split("token=")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .to_string();
+
+        let bad = app
+            .clone()
+            .oneshot(json_req(
+                "/auth/verify-email",
+                &serde_json::json!({ "token": registered.access_token }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(bad.status(), StatusCode::BAD_REQUEST);
+        let res = app
+            .clone()
+            .oneshot(json_req(
+                "/auth/verify-email",
+                &serde_json::json!({ "token": token }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app
+            .clone()
+            .oneshot(json_req(
+                "/auth/refresh",
+                &serde_json::json!({ "refresh_token": registered.refresh_token }),
+            ))
+            .await
+            .unwrap();
+        let refreshed: AuthResponse =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert!(refreshed.user.email_verified);
+        let res = app.oneshot(order(&refreshed.access_token)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn reset_and_sign_in_links_are_emailed() {
+        let config = AuthConfig::default();
+        let outbox = Outbox::default();
+        let state = AuthAppState::new(config.clone(), InMemoryUserStore::new())
+            .reset_passwords(PasswordReset::new(
+                outbox.clone(),
+                "https://app.test/reset?token={token}",
+            ))
+            .magic_links(MagicLink::new(
+                outbox.clone(),
+                "https://app.test/sign-in?token={token}",
+            ));
+        let app = auth_routes_with_state(state);
+        let link_token = || {
+            let email = outbox.0.lock().unwrap().pop().expect("email");
+            email
+                .html
+                .split("token=")
+                .nth(1)
+                .and_then(|rest| rest.split('"').next())
+                .unwrap()
+                .to_string()
+        };
+        let post = |uri: &'static str, body: Value| app.clone().oneshot(json_req(uri, &body));
+
+        post(
+            "/auth/register",
+            serde_json::json!({
+                "email": "reset@example.com",
+                "password": "StrongPass1",
+                "name": "Reset"
+            }),
+        )
+        .await
+        .unwrap();
+
+        let res = post(
+            "/auth/password-reset",
+            serde_json::json!({ "email": "nobody@example.com" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(outbox.0.lock().unwrap().is_empty());
+
+        post(
+            "/auth/password-reset",
+            serde_json::json!({ "email": "reset@example.com" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            outbox.0.lock().unwrap()[0].subject,
+            "Reset your password".to_string()
+        );
+        let reset_token = link_token();
+        let confirm = serde_json::json!({ "token": reset_token, "new_password": "NewStrongPass2" });
+        let res = post("/auth/password-reset/confirm", confirm.clone())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = post(
+            "/auth/login",
+            serde_json::json!({ "email": "reset@example.com", "password": "NewStrongPass2" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // The password changed, so the link is spent
+        let res = post("/auth/password-reset/confirm", confirm).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        post(
+            "/auth/magic-link",
+            serde_json::json!({ "email": "reset@example.com" }),
+        )
+        .await
+        .unwrap();
+        let sign_in_token = link_token();
+        let res = post(
+            "/auth/magic-link/verify",
+            serde_json::json!({ "token": reset_token }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = post(
+            "/auth/magic-link/verify",
+            serde_json::json!({ "token": sign_in_token }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let signed_in: AuthResponse =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(signed_in.user.email, "reset@example.com");
+        assert!(signed_in.user.email_verified);
+    }
+
+    #[tokio::test]
+    async fn guests_are_merged_into_the_account_they_sign_up_as() {
+        use crate::auth::guest::{GuestMerge, GuestSessions};
+
+        let config = AuthConfig::default();
+        let merges = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = merges.clone();
+        let state = AuthAppState::new(config.clone(), InMemoryUserStore::new()).guest_sessions(
+            GuestSessions::new().on_merge(move |merge: GuestMerge| {
+                let sink = sink.clone();
+                async move {
+                    sink.lock().unwrap().push(merge);
+                    Ok(())
+                }
+            }),
+        );
+        let app = auth_routes_with_state(state).layer(middleware::from_fn(
+            move |mut req: Request<Body>, next: Next| {
+                let cfg = config.clone();
+                async move {
+                    req.extensions_mut().insert(cfg);
+                    next.run(req).await
+                }
+            },
+        ));
+        let upgrade = |bearer: &str, guest_token: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/auth/upgrade")
+                .header("content-type", "application/json")
+                .header("authorization", format!("Bearer {bearer}"))
+                .body(Body::from(
+                    serde_json::json!({ "guest_token": guest_token }).to_string(),
+                ))
+                .unwrap()
+        };
+
+        let res = app
+            .clone()
+            .oneshot(json_req("/auth/guest", &serde_json::json!({})))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let guest: GuestResponse =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+
+        // A guest can't upgrade into itself
+        let res = app
+            .clone()
+            .oneshot(upgrade(&guest.access_token, &guest.access_token))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = app
+            .clone()
+            .oneshot(json_req(
+                "/auth/register",
+                &serde_json::json!({
+                    "email": "guest@example.com",
+                    "password": "StrongPass1",
+                    "name": "Guest"
+                }),
+            ))
+            .await
+            .unwrap();
+        let registered: AuthResponse =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+
+        // Only guest tokens can be merged
+        let res = app
+            .clone()
+            .oneshot(upgrade(&registered.access_token, &registered.access_token))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(merges.lock().unwrap().is_empty());
+
+        let res = app
+            .clone()
+            .oneshot(upgrade(&registered.access_token, &guest.access_token))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            *merges.lock().unwrap(),
+            [GuestMerge {
+                guest_id: guest.guest_id.clone(),
+                user_id: registered.user.id,
+            }]
+        );
+
+        // The guest token can't be merged again, into any account
+        let res = app
+            .clone()
+            .oneshot(json_req(
+                "/auth/register",
+                &serde_json::json!({
+                    "email": "other@example.com",
+                    "password": "StrongPass1",
+                    "name": "Other"
+                }),
+            ))
+            .await
+            .unwrap();
+        let other: AuthResponse =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        for account in [&registered.access_token, &other.access_token] {
+            let res = app
+                .clone()
+                .oneshot(upgrade(account, &guest.access_token))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::CONFLICT);
+        }
+        assert_eq!(merges.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_guest_merges_can_be_retried() {
+        use crate::auth::guest::{GuestMerge, GuestSessions};
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let config = AuthConfig::default();
+        let fail = Arc::new(AtomicBool::new(true));
+        let failing = fail.clone();
+        let guests = GuestSessions::new().on_merge(move |_: GuestMerge| {
+            let fail = failing.load(Ordering::SeqCst);
+            async move {
+                if fail {
+                    Err(ApiError::InternalServerError(
+                        "carts unavailable".to_string(),
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+        });
+        let guest = guests.issue(&config).unwrap();
+
+        assert!(
+            guests
+                .merge(&guest.access_token, "user-1", &config)
+                .await
+                .is_err()
+        );
+        assert!(!config.merged_guests.contains(&guest.guest_id));
+
+        fail.store(false, Ordering::SeqCst);
+        guests
+            .merge(&guest.access_token, "user-1", &config)
+            .await
+            .unwrap();
+        assert!(config.merged_guests.contains(&guest.guest_id));
+    }
+
+    #[tokio::test]
+    async fn password_changes_follow_the_policy() {
+        let config = AuthConfig {
+            argon2_memory_cost: 1024,
+            argon2_time_cost: 1,
+            argon2_parallelism: 1,
+            ..AuthConfig::default()
+        }
+        .password_policy(
+            super::super::password::PasswordValidator::new()
+                .ban(["Password123"])
+                .history(2),
+        );
+        let app = auth_routes_with_store(config.clone(), InMemoryUserStore::new()).layer(
+            middleware::from_fn(move |mut req: Request<Body>, next: Next| {
+                let cfg = config.clone();
+                async move {
+                    req.extensions_mut().insert(cfg);
+                    next.run(req).await
+                }
+            }),
+        );
+
+        let banned = serde_json::json!({
+            "email": "policy@example.com",
+            "password": "Password123",
+            "name": "Policy"
+        });
+        let res = app
+            .clone()
+            .oneshot(json_req("/auth/register", &banned))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let res = app
+            .clone()
+            .oneshot(json_req(
+                "/auth/register",
+                &serde_json::json!({
+                    "email": "policy@example.com",
+                    "password": "FirstPass1",
+                    "name": "Policy"
+                }),
+            ))
+            .await
+            .unwrap();
+        let body: AuthResponse =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let change = |current: &str, new: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/auth/password")
+                .header("content-type", "application/json")
+                .header("authorization", format!("Bearer {}", body.access_token))
+                .body(Body::from(
+                    serde_json::json!({ "current_password": current, "new_password": new })
+                        .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let statuses = [
+            ("FirstPass1", "FirstPass1"),  // current password
+            ("FirstPass1", "SecondPass2"), // ok
+            ("SecondPass2", "FirstPass1"), // still within the last 2
+            ("SecondPass2", "ThirdPass3"), // ok
+            ("ThirdPass3", "FirstPass1"),  // aged out
+        ];
+        let mut seen = Vec::new();
+        for (current, new) in statuses {
+            seen.push(
+                app.clone()
+                    .oneshot(change(current, new))
+                    .await
+                    .unwrap()
+                    .status(),
+            );
+        }
+        assert_eq!(
+            seen,
+            [
+                StatusCode::UNPROCESSABLE_ENTITY,
+                StatusCode::OK,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                StatusCode::OK,
+                StatusCode::OK,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pre_register_hook_sees_extra_fields_and_stores_metadata() {
+        let config = AuthConfig::default();
+        let store = InMemoryUserStore::new();
+        let state = AuthAppState::new(config.clone(), store.clone()).pre_register(
+            |_request: &RegisterRequest, extra: &serde_json::Map<String, Value>| {
+                if extra.get("invite_code") != Some(&Value::from("WELCOME")) {
+                    return Err(ApiError::BadRequest(
+                        "A valid invite code is required".to_string(),
+                    ));
+                }
+                Ok(serde_json::json!({ "company": extra["company"] }))
+            },
+        );
+        let app = auth_routes_with_state(state).layer(middleware::from_fn(
+            move |mut req: Request<Body>, next: Next| {
+                let cfg = config.clone();
+                async move {
+                    req.extensions_mut().insert(cfg);
+                    next.run(req).await
+                }
+            },
+        ));
+        let mut payload = serde_json::json!({
+            "email": "invited@example.com",
+            "password": "StrongPass1",
+            "name": "Invited",
+            "company": "Acme"
+        });
+
+        let res = app
+            .clone()
+            .oneshot(json_req("/auth/register", &payload))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        payload["invite_code"] = "WELCOME".into();
+        let res = app
+            .oneshot(json_req("/auth/register", &payload))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let user = store
+            .find_by_email("invited@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.metadata, serde_json::json!({ "company": "Acme" }));
+    }
+
     #[tokio::test]
     async fn logout_returns_message() {
         let app = test_app();
@@ -464,4 +1897,29 @@ mod tests {
             serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
         assert_eq!(msg.message, "Successfully logged out");
     }
+
+    #[test]
+    fn auth_openapi_documents_mounted_routes() {
+        let doc = serde_json::to_value(auth_openapi()).unwrap();
+
+        for path in [
+            "/auth/login",
+            "/auth/register",
+            "/auth/refresh",
+            "/auth/logout",
+        ] {
+            assert!(doc["paths"][path]["post"].is_object(), "{path}");
+        }
+        assert_eq!(
+            doc["paths"]["/auth/login"]["post"]["requestBody"]["content"]["application/json"]["schema"]
+                ["$ref"],
+            "#/components/schemas/LoginRequest"
+        );
+        assert_eq!(
+            doc["paths"]["/auth/me"]["get"]["security"],
+            serde_json::json!([{ "bearerAuth": [] }])
+        );
+        assert!(doc["components"]["schemas"]["AuthResponse"].is_object());
+        assert!(doc["components"]["securitySchemes"]["bearerAuth"].is_object());
+    }
 }
@@ -0,0 +1,275 @@
+//! Identity headers for upstream services
+//!
+//! A gateway that has verified the caller's token can tell the services
+//! behind it who the caller is with plain headers. [`ClaimHeaders`] copies
+//! selected claims of a verified access token into request headers, by
+//! default:
+//!
+//! | header        | claim       |
+//! |---------------|-------------|
+//! | `x-user-id`   | `sub`       |
+//! | `x-tenant-id` | `tenant_id` |
+//! | `x-scopes`    | `roles`     |
+//!
+//! The same headers are removed from every incoming request first, so a
+//! client can't claim an identity by sending them itself. Handlers that call
+//! other services pass the identity on with [`ForwardedIdentity`]:
+//!
+//! ```rust,ignore
+//! use dy_rs::auth::{ClaimHeaders, ForwardedIdentity};
+//!
+//! async fn list_orders(identity: ForwardedIdentity) -> ApiResult<Vec<Order>> {
+//!     let mut request = http::Request::get("http://orders.internal/orders").body(())?;
+//!     identity.apply(request.headers_mut());
+//!     // send `request` with your HTTP client
+//! }
+//!
+//! let router = Router::new()
+//!     .route("/orders", get(list_orders))
+//!     .layer(ClaimHeaders::new().claim("x-scopes", "scope"));
+//! ```
+//!
+//! Like [`RequireRoles`](super::RequireRoles), the layer reads the
+//! [`AuthConfig`](super::AuthConfig) from the request extensions. Requests
+//! without a valid token pass through with the headers stripped; guard routes
+//! that need a user with [`RequireAuth`](super::RequireAuth) or `AuthUser`.
+
+use std::{convert::Infallible, future::Future, pin::Pin, sync::Arc, task};
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{HeaderMap, HeaderName, HeaderValue, header::AUTHORIZATION, request::Parts},
+    response::Response,
+};
+use serde_json::Value;
+use tower::{Layer, Service};
+
+use super::extractors::extract_auth_user_from_parts;
+
+/// Layer that turns token claims into trusted request headers
+#[derive(Clone)]
+pub struct ClaimHeaders {
+    headers: Arc<Vec<(HeaderName, String)>>,
+}
+
+impl ClaimHeaders {
+    /// Propagate `sub`, `tenant_id` and `roles` as `x-user-id`,
+    /// `x-tenant-id` and `x-scopes`
+    pub fn new() -> Self {
+        Self::empty()
+            .claim("x-user-id", "sub")
+            .claim("x-tenant-id", "tenant_id")
+            .claim("x-scopes", "roles")
+    }
+
+    /// Propagate no claims; add them with [`claim`](Self::claim)
+    pub fn empty() -> Self {
+        Self {
+            headers: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Set `header` from `claim`, replacing any claim already mapped to it
+    ///
+    /// `claim` may be a dotted path into nested objects, e.g.
+    /// `realm_access.roles`. Arrays are joined with spaces.
+    ///
+    /// # Panics
+    ///
+    /// If `header` isn't a valid header name.
+    pub fn claim(mut self, header: &str, claim: impl Into<String>) -> Self {
+        let name = HeaderName::try_from(header).expect("invalid header name");
+        let headers = Arc::make_mut(&mut self.headers);
+        headers.retain(|(existing, _)| *existing != name);
+        headers.push((name, claim.into()));
+        self
+    }
+
+    /// Headers for the claims of the request's verified access token
+    fn identity(&self, parts: &mut Parts) -> Option<HeaderMap> {
+        extract_auth_user_from_parts(parts).ok()?;
+        // The signature was checked above; this only reads the claims again
+        // without the mapping applied to tokens from other issuers.
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?;
+        let claims = jsonwebtoken::dangerous::insecure_decode::<Value>(token)
+            .ok()?
+            .claims;
+
+        let mut identity = HeaderMap::new();
+        for (name, claim) in self.headers.iter() {
+            let value = claim
+                .split('.')
+                .try_fold(&claims, |value, key| value.get(key))
+                .and_then(header_value);
+            if let Some(value) = value {
+                identity.insert(name.clone(), value);
+            }
+        }
+        Some(identity)
+    }
+}
+
+impl Default for ClaimHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn header_value(claim: &Value) -> Option<HeaderValue> {
+    let text = match claim {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        Value::Null | Value::Object(_) => return None,
+    };
+    HeaderValue::from_str(&text).ok()
+}
+
+impl<S> Layer<S> for ClaimHeaders {
+    type Service = ClaimHeadersService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClaimHeadersService {
+            inner,
+            headers: self.clone(),
+        }
+    }
+}
+
+/// Service produced by the [`ClaimHeaders`] layer
+#[derive(Clone)]
+pub struct ClaimHeadersService<S> {
+    inner: S,
+    headers: ClaimHeaders,
+}
+
+impl<S> Service<Request> for ClaimHeadersService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let (mut parts, body) = request.into_parts();
+        for (name, _) in self.headers.headers.iter() {
+            parts.headers.remove(name);
+        }
+        if let Some(identity) = self.headers.identity(&mut parts) {
+            for (name, value) in &identity {
+                parts.headers.insert(name, value.clone());
+            }
+            parts.extensions.insert(ForwardedIdentity(identity));
+        }
+        Box::pin(self.inner.call(Request::from_parts(parts, body)))
+    }
+}
+
+/// Identity headers set by [`ClaimHeaders`], to pass on to other services
+///
+/// Empty when the request had no valid token or the layer isn't installed.
+#[derive(Debug, Clone, Default)]
+pub struct ForwardedIdentity(pub HeaderMap);
+
+impl ForwardedIdentity {
+    /// Copy the identity headers onto an outgoing request's headers
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        for (name, value) in &self.0 {
+            headers.insert(name, value.clone());
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for ForwardedIdentity
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AuthConfig, create_token_pair};
+    use axum::body::Body;
+    use tower::{ServiceBuilder, ServiceExt, service_fn};
+
+    async fn forwarded(request: Request) -> HeaderMap {
+        let svc = ServiceBuilder::new()
+            .layer(ClaimHeaders::new())
+            .service(service_fn(|req: Request| async move {
+                let mut response = Response::new(Body::empty());
+                *response.headers_mut() = req.headers().clone();
+                response.headers_mut().remove(AUTHORIZATION);
+                Ok::<_, Infallible>(response)
+            }));
+        svc.oneshot(request).await.unwrap().headers().clone()
+    }
+
+    #[tokio::test]
+    async fn claims_replace_client_supplied_headers() {
+        let config = AuthConfig::default();
+        let token = create_token_pair(
+            "user-123",
+            "ada@example.com",
+            vec!["reader".to_string(), "writer".to_string()],
+            &config,
+        )
+        .unwrap()
+        .access_token;
+
+        let mut request = Request::builder()
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .header("x-user-id", "admin")
+            .header("x-tenant-id", "someone-else")
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(config);
+
+        let headers = forwarded(request).await;
+        assert_eq!(headers["x-user-id"], "user-123");
+        assert_eq!(headers["x-scopes"], "reader writer");
+        // Our tokens carry no tenant, so the spoofed header is just dropped
+        assert!(headers.get("x-tenant-id").is_none());
+    }
+
+    #[tokio::test]
+    async fn anonymous_requests_lose_identity_headers() {
+        let mut request = Request::builder()
+            .header("x-user-id", "admin")
+            .header("x-scopes", "admin")
+            .header("x-request-id", "abc")
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(AuthConfig::default());
+
+        let headers = forwarded(request).await;
+        assert!(headers.get("x-user-id").is_none());
+        assert!(headers.get("x-scopes").is_none());
+        assert_eq!(headers["x-request-id"], "abc");
+    }
+}
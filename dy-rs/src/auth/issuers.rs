@@ -0,0 +1,393 @@
+//! Access tokens from other issuers
+//!
+//! Besides its own HS256 tokens, an app can accept access tokens signed by
+//! other issuers, such as a corporate identity provider. Each trusted issuer
+//! has its own key material, accepted audiences and claim mapping:
+//!
+//! ```toml
+//! [[auth.issuers]]
+//! issuer = "https://login.corp.example"
+//! audiences = ["api://shop"]
+//! key = { jwks_url = "https://login.corp.example/.well-known/jwks.json" }
+//! claims = { roles = "realm_access.roles" }
+//! ```
+//!
+//! Tokens are matched to an issuer by their `iss` claim, and to a JWKS key by
+//! the `kid` in their header. Verification doesn't block on the network, so
+//! JWKS keys are fetched ahead of time:
+//!
+//! ```rust,ignore
+//! let fetch = |url: String| async move { my_http_client.get_json(&url).await };
+//! config.refresh_jwks(&fetch).await?;
+//! config.spawn_jwks_refresh(fetch, Duration::from_secs(3600));
+//! ```
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, jwk::JwkSet};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::jwt::Claims;
+use crate::error::ApiError;
+
+/// Another issuer whose access tokens are accepted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedIssuer {
+    /// Expected `iss` claim
+    pub issuer: String,
+
+    /// Accepted `aud` values; tokens must carry at least one
+    pub audiences: Vec<String>,
+
+    /// Key used to check signatures
+    pub key: IssuerKey,
+
+    /// Accepted signing algorithms (default: `HS256` for secrets, `RS256`
+    /// for PEM keys and JWKS)
+    #[serde(default)]
+    pub algorithms: Vec<Algorithm>,
+
+    /// Where the user's email and roles are found in the token
+    #[serde(default)]
+    pub claims: ClaimMapping,
+}
+
+impl TrustedIssuer {
+    /// Trust tokens from `issuer` for `audience`, checked with `key`
+    pub fn new(issuer: impl Into<String>, audience: impl Into<String>, key: IssuerKey) -> Self {
+        Self {
+            issuer: issuer.into(),
+            audiences: vec![audience.into()],
+            key,
+            algorithms: Vec::new(),
+            claims: ClaimMapping::default(),
+        }
+    }
+
+    /// Also accept tokens for `audience`
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audiences.push(audience.into());
+        self
+    }
+
+    /// Accept only these signing algorithms
+    pub fn algorithms(mut self, algorithms: impl IntoIterator<Item = Algorithm>) -> Self {
+        self.algorithms = algorithms.into_iter().collect();
+        self
+    }
+
+    /// Read the email and roles from other claims
+    pub fn claims(mut self, claims: ClaimMapping) -> Self {
+        self.claims = claims;
+        self
+    }
+
+    fn accepted_algorithms(&self) -> Vec<Algorithm> {
+        if !self.algorithms.is_empty() {
+            return self.algorithms.clone();
+        }
+        match self.key {
+            IssuerKey::Secret(_) => vec![Algorithm::HS256],
+            IssuerKey::RsaPem(_) | IssuerKey::JwksUrl(_) => vec![Algorithm::RS256],
+        }
+    }
+}
+
+/// Key material for a [`TrustedIssuer`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssuerKey {
+    /// Shared HMAC secret
+    Secret(String),
+    /// PEM-encoded RSA public key
+    RsaPem(String),
+    /// JWKS endpoint; keys are picked by the token's `kid`
+    JwksUrl(String),
+}
+
+/// Claims holding the user's details in another issuer's tokens
+///
+/// Names may be dotted paths into nested objects, e.g. `realm_access.roles`.
+/// Roles may be an array or a space-separated string such as `scope`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClaimMapping {
+    /// Claim with the user's email (default: `email`)
+    pub email: String,
+    /// Claim with the user's roles (default: `roles`)
+    pub roles: String,
+    /// Claim saying whether the email is verified (default: `email_verified`)
+    pub email_verified: String,
+}
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        Self {
+            email: "email".to_string(),
+            roles: "roles".to_string(),
+            email_verified: "email_verified".to_string(),
+        }
+    }
+}
+
+impl ClaimMapping {
+    fn to_claims(&self, mut token: Value, issuer: &TrustedIssuer) -> Claims {
+        let audience = match token.get_mut("aud").map(Value::take) {
+            Some(Value::Array(auds)) => auds
+                .into_iter()
+                .filter_map(|aud| aud.as_str().map(str::to_string))
+                .find(|aud| issuer.audiences.contains(aud))
+                .unwrap_or_default(),
+            Some(Value::String(aud)) => aud,
+            _ => String::new(),
+        };
+        let string = |token: &Value, name: &str| {
+            lookup(token, name)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let number = |name: &str| token.get(name).and_then(Value::as_i64).unwrap_or_default();
+        let roles = match lookup(&token, &self.roles) {
+            Some(Value::Array(roles)) => roles
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            Some(Value::String(roles)) => roles.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+
+        Claims {
+            sub: string(&token, "sub"),
+            email: string(&token, &self.email),
+            roles,
+            email_verified: lookup(&token, &self.email_verified)
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            guest: false,
+            token_type: "access".to_string(),
+            iat: number("iat"),
+            exp: number("exp"),
+            nbf: number("nbf"),
+            iss: issuer.issuer.clone(),
+            aud: audience,
+            jti: string(&token, "jti"),
+        }
+    }
+}
+
+fn lookup<'a>(token: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(token, |value, key| value.get(key))
+}
+
+/// Verify an access token from `issuer` and map its claims
+pub(crate) fn verify_issued_by(
+    token: &str,
+    issuer: &TrustedIssuer,
+    jwks: &JwksKeys,
+) -> Result<Claims, ApiError> {
+    let key = match &issuer.key {
+        IssuerKey::Secret(secret) => DecodingKey::from_secret(secret.as_bytes()),
+        IssuerKey::RsaPem(pem) => DecodingKey::from_rsa_pem(pem.as_bytes()).map_err(|e| {
+            tracing::warn!(issuer = %issuer.issuer, "Invalid RSA key for issuer: {}", e);
+            ApiError::Unauthorized
+        })?,
+        IssuerKey::JwksUrl(url) => {
+            let kid = jsonwebtoken::decode_header(token)
+                .ok()
+                .and_then(|header| header.kid)
+                .ok_or(ApiError::Unauthorized)?;
+            jwks.key(url, &kid).ok_or_else(|| {
+                tracing::debug!(issuer = %issuer.issuer, %kid, "No JWKS key for token");
+                ApiError::Unauthorized
+            })?
+        }
+    };
+
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.algorithms = issuer.accepted_algorithms();
+    validation.set_issuer(&[&issuer.issuer]);
+    validation.set_audience(&issuer.audiences);
+    validation.validate_exp = true;
+    validation.validate_nbf = true;
+
+    let token_data = decode::<Value>(token, &key, &validation).map_err(|e| {
+        tracing::debug!(issuer = %issuer.issuer, "Token verification failed: {}", e);
+        ApiError::Unauthorized
+    })?;
+
+    Ok(issuer.claims.to_claims(token_data.claims, issuer))
+}
+
+/// Fetches a JWKS document
+#[async_trait]
+pub trait JwksFetcher: Send + Sync + 'static {
+    async fn fetch(&self, url: &str) -> Result<JwkSet, ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> JwksFetcher for F
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<JwkSet, ApiError>> + Send,
+{
+    async fn fetch(&self, url: &str) -> Result<JwkSet, ApiError> {
+        self(url.to_string()).await
+    }
+}
+
+/// Keys fetched from the JWKS URLs of trusted issuers, shared by clones
+#[derive(Debug, Clone, Default)]
+pub struct JwksKeys(Arc<RwLock<HashMap<String, JwkSet>>>);
+
+impl JwksKeys {
+    /// Use `keys` for tokens checked against `url`
+    pub fn insert(&self, url: impl Into<String>, keys: JwkSet) {
+        self.0.write().unwrap().insert(url.into(), keys);
+    }
+
+    fn key(&self, url: &str, kid: &str) -> Option<DecodingKey> {
+        let sets = self.0.read().unwrap();
+        let jwk = sets.get(url)?.find(kid)?;
+        DecodingKey::from_jwk(jwk).ok()
+    }
+
+    /// Fetch the keys of every issuer configured with a JWKS URL
+    ///
+    /// Every URL is tried; keys already held for a URL that fails are kept,
+    /// and the first error is returned.
+    pub async fn refresh(
+        &self,
+        issuers: &[TrustedIssuer],
+        fetcher: &impl JwksFetcher,
+    ) -> Result<(), ApiError> {
+        let mut result = Ok(());
+        for issuer in issuers {
+            let IssuerKey::JwksUrl(url) = &issuer.key else {
+                continue;
+            };
+            match fetcher.fetch(url).await {
+                Ok(keys) => self.insert(url.clone(), keys),
+                Err(err) => {
+                    tracing::warn!(issuer = %issuer.issuer, %url, "Failed to fetch JWKS: {}", err);
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Run [`refresh`](Self::refresh) every `interval`
+    pub fn spawn_refresh(
+        &self,
+        issuers: Vec<TrustedIssuer>,
+        fetcher: impl JwksFetcher,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let keys = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let _ = keys.refresh(&issuers, &fetcher).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AuthConfig, jwt::verify_access_token};
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+
+    fn corp_token(header: Header, secret: &[u8], aud: Value) -> String {
+        let now = chrono::Utc::now().timestamp();
+        let claims = json!({
+            "iss": "https://login.corp.example",
+            "aud": aud,
+            "sub": "corp-42",
+            "upn": "ada@corp.example",
+            "realm_access": { "roles": ["staff", "admin"] },
+            "iat": now,
+            "exp": now + 60,
+        });
+        encode(&header, &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn corp_issuer(key: IssuerKey) -> TrustedIssuer {
+        TrustedIssuer::new("https://login.corp.example", "api://shop", key).claims(ClaimMapping {
+            email: "upn".to_string(),
+            roles: "realm_access.roles".to_string(),
+            ..ClaimMapping::default()
+        })
+    }
+
+    #[test]
+    fn tokens_are_verified_by_their_issuer() {
+        let config = AuthConfig::default()
+            .trust_issuer(corp_issuer(IssuerKey::Secret("corp-secret".to_string())));
+
+        let token = corp_token(
+            Header::default(),
+            b"corp-secret",
+            json!(["other", "api://shop"]),
+        );
+        let claims = verify_access_token(&token, &config).unwrap();
+        assert_eq!(claims.sub, "corp-42");
+        assert_eq!(claims.email, "ada@corp.example");
+        assert_eq!(claims.roles, ["staff", "admin"]);
+        assert_eq!(claims.aud, "api://shop");
+        assert!(claims.is_access_token());
+
+        let wrong_key = corp_token(Header::default(), b"not-it", json!("api://shop"));
+        assert!(verify_access_token(&wrong_key, &config).is_err());
+        let wrong_audience = corp_token(Header::default(), b"corp-secret", json!("api://other"));
+        assert!(verify_access_token(&wrong_audience, &config).is_err());
+
+        // Our own tokens are still accepted
+        let ours = crate::auth::create_token_pair("user-1", "u@example.com", vec![], &config)
+            .unwrap()
+            .access_token;
+        assert_eq!(verify_access_token(&ours, &config).unwrap().sub, "user-1");
+    }
+
+    #[tokio::test]
+    async fn jwks_keys_are_picked_by_kid() {
+        let url = "https://login.corp.example/jwks";
+        let config = AuthConfig::default().trust_issuer(
+            corp_issuer(IssuerKey::JwksUrl(url.to_string())).algorithms([Algorithm::HS256]),
+        );
+        let header = |kid: &str| Header {
+            kid: Some(kid.to_string()),
+            ..Header::default()
+        };
+        let token = corp_token(header("k1"), b"jwks-secret", json!("api://shop"));
+
+        // Keys haven't been fetched yet
+        assert!(verify_access_token(&token, &config).is_err());
+
+        let fetch = |requested: String| async move {
+            assert_eq!(requested, "https://login.corp.example/jwks");
+            let keys = json!({ "keys": [{ "kty": "oct", "kid": "k1", "k": "andrcy1zZWNyZXQ" }] });
+            Ok(serde_json::from_value::<JwkSet>(keys).unwrap())
+        };
+        config.refresh_jwks(&fetch).await.unwrap();
+
+        assert_eq!(verify_access_token(&token, &config).unwrap().sub, "corp-42");
+        let unknown_kid = corp_token(header("k2"), b"jwks-secret", json!("api://shop"));
+        assert!(verify_access_token(&unknown_kid, &config).is_err());
+    }
+}
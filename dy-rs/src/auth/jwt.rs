@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::config::AuthConfig;
+use super::issuers::{TrustedIssuer, verify_issued_by};
 use crate::error::ApiError;
 
 /// JWT Claims structure
@@ -23,6 +24,14 @@ pub struct Claims {
     #[serde(default)]
     pub roles: Vec<String>,
 
+    /// Whether the user's email address has been verified
+    #[serde(default)]
+    pub email_verified: bool,
+
+    /// Whether this is an anonymous guest's token rather than an account's
+    #[serde(default)]
+    pub guest: bool,
+
     /// Token type: "access" or "refresh"
     pub token_type: String,
 
@@ -60,6 +69,8 @@ impl Claims {
             sub: user_id.into(),
             email: email.into(),
             roles,
+            email_verified: false,
+            guest: false,
             token_type: "access".to_string(),
             iat: now.timestamp(),
             exp: exp.timestamp(),
@@ -83,6 +94,8 @@ impl Claims {
             sub: user_id.into(),
             email: email.into(),
             roles: vec![],
+            email_verified: false,
+            guest: false,
             token_type: "refresh".to_string(),
             iat: now.timestamp(),
             exp: exp.timestamp(),
@@ -93,6 +106,98 @@ impl Claims {
         }
     }
 
+    /// Create claims for an email verification link, valid for `expiry`
+    pub fn new_email_verification(
+        user_id: impl Into<String>,
+        email: impl Into<String>,
+        expiry: std::time::Duration,
+        config: &AuthConfig,
+    ) -> Self {
+        Self::new_link("email_verification", user_id, email, expiry, config)
+    }
+
+    /// Create claims for a password reset link, valid for `expiry`
+    ///
+    /// `jti` is a fingerprint of the password hash at the time, so the link
+    /// stops working once the password has changed.
+    pub fn new_password_reset(
+        user_id: impl Into<String>,
+        email: impl Into<String>,
+        fingerprint: impl Into<String>,
+        expiry: std::time::Duration,
+        config: &AuthConfig,
+    ) -> Self {
+        Self {
+            jti: fingerprint.into(),
+            ..Self::new_link("password_reset", user_id, email, expiry, config)
+        }
+    }
+
+    /// Create claims for a sign-in link, valid for `expiry`
+    pub fn new_magic_link(
+        user_id: impl Into<String>,
+        email: impl Into<String>,
+        expiry: std::time::Duration,
+        config: &AuthConfig,
+    ) -> Self {
+        Self::new_link("magic_link", user_id, email, expiry, config)
+    }
+
+    fn new_link(
+        token_type: &str,
+        user_id: impl Into<String>,
+        email: impl Into<String>,
+        expiry: std::time::Duration,
+        config: &AuthConfig,
+    ) -> Self {
+        let now = Utc::now();
+        let exp = now + Duration::seconds(expiry.as_secs() as i64);
+
+        Self {
+            sub: user_id.into(),
+            email: email.into(),
+            roles: vec![],
+            email_verified: false,
+            guest: false,
+            token_type: token_type.to_string(),
+            iat: now.timestamp(),
+            exp: exp.timestamp(),
+            nbf: now.timestamp(),
+            iss: config.issuer.clone(),
+            aud: config.audience.clone(),
+            jti: Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Create claims for an anonymous guest, valid for `expiry`
+    ///
+    /// Guests get a fresh ID and the `guest` role, and no email.
+    pub fn new_guest(expiry: std::time::Duration, config: &AuthConfig) -> Self {
+        let now = Utc::now();
+        let exp = now + Duration::seconds(expiry.as_secs() as i64);
+
+        Self {
+            sub: Uuid::new_v4().to_string(),
+            email: String::new(),
+            roles: vec!["guest".to_string()],
+            email_verified: false,
+            guest: true,
+            token_type: "access".to_string(),
+            iat: now.timestamp(),
+            exp: exp.timestamp(),
+            nbf: now.timestamp(),
+            iss: config.issuer.clone(),
+            aud: config.audience.clone(),
+            jti: Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Set the `email_verified` claim
+    pub fn with_email_verified(mut self, verified: bool) -> Self {
+        self.email_verified = verified;
+        self
+    }
+
     /// Check if this is an access token
     pub fn is_access_token(&self) -> bool {
         self.token_type == "access"
@@ -103,6 +208,21 @@ impl Claims {
         self.token_type == "refresh"
     }
 
+    /// Check if this is an email verification token
+    pub fn is_email_verification_token(&self) -> bool {
+        self.token_type == "email_verification"
+    }
+
+    /// Check if this is a password reset token
+    pub fn is_password_reset_token(&self) -> bool {
+        self.token_type == "password_reset"
+    }
+
+    /// Check if this is a sign-in link token
+    pub fn is_magic_link_token(&self) -> bool {
+        self.token_type == "magic_link"
+    }
+
     /// Check if the user has a specific role
     pub fn has_role(&self, role: &str) -> bool {
         self.roles.iter().any(|r| r == role)
@@ -142,26 +262,26 @@ pub fn create_token_pair(
     roles: Vec<String>,
     config: &AuthConfig,
 ) -> Result<TokenPair, ApiError> {
-    let user_id = user_id.into();
-    let email = email.into();
+    create_token_pair_from_claims(Claims::new_access(user_id, email, roles, config), config)
+}
 
+/// Create a token pair whose access token carries `access_claims`
+///
+/// The refresh token is issued for the same subject and email.
+pub fn create_token_pair_from_claims(
+    access_claims: Claims,
+    config: &AuthConfig,
+) -> Result<TokenPair, ApiError> {
     // Create access token
-    let access_claims = Claims::new_access(&user_id, &email, roles, config);
-    let access_token = encode(
-        &Header::new(Algorithm::HS256),
-        &access_claims,
-        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
-    )
-    .map_err(|e| ApiError::InternalServerError(format!("Failed to create access token: {}", e)))?;
+    let access_token = encode_token(&access_claims, config).map_err(|e| {
+        ApiError::InternalServerError(format!("Failed to create access token: {}", e))
+    })?;
 
     // Create refresh token
-    let refresh_claims = Claims::new_refresh(&user_id, &email, config);
-    let refresh_token = encode(
-        &Header::new(Algorithm::HS256),
-        &refresh_claims,
-        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
-    )
-    .map_err(|e| ApiError::InternalServerError(format!("Failed to create refresh token: {}", e)))?;
+    let refresh_claims = Claims::new_refresh(&access_claims.sub, &access_claims.email, config);
+    let refresh_token = encode_token(&refresh_claims, config).map_err(|e| {
+        ApiError::InternalServerError(format!("Failed to create refresh token: {}", e))
+    })?;
 
     Ok(TokenPair {
         access_token,
@@ -171,6 +291,18 @@ pub fn create_token_pair(
     })
 }
 
+/// Sign claims that aren't part of a token pair, e.g. an email verification link
+pub fn encode_token(
+    claims: &Claims,
+    config: &AuthConfig,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    encode(
+        &Header::new(Algorithm::HS256),
+        claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+    )
+}
+
 /// Verify a JWT token and return the claims
 pub fn verify_token(token: &str, config: &AuthConfig) -> Result<Claims, ApiError> {
     let mut validation = Validation::new(Algorithm::HS256);
@@ -197,7 +329,14 @@ pub fn verify_token(token: &str, config: &AuthConfig) -> Result<Claims, ApiError
 }
 
 /// Verify that a token is an access token
+///
+/// Tokens whose `iss` is one of [`AuthConfig::issuers`] are checked with that
+/// issuer's key and claim mapping; any other token must be one of ours.
 pub fn verify_access_token(token: &str, config: &AuthConfig) -> Result<Claims, ApiError> {
+    if let Some(issuer) = trusted_issuer(token, config) {
+        return verify_issued_by(token, issuer, &config.jwks);
+    }
+
     let claims = verify_token(token, config)?;
 
     if !claims.is_access_token() {
@@ -207,6 +346,19 @@ pub fn verify_access_token(token: &str, config: &AuthConfig) -> Result<Claims, A
     Ok(claims)
 }
 
+/// The trusted issuer named by the token's (not yet verified) `iss` claim
+fn trusted_issuer<'a>(token: &str, config: &'a AuthConfig) -> Option<&'a TrustedIssuer> {
+    if config.issuers.is_empty() {
+        return None;
+    }
+    let unverified = jsonwebtoken::dangerous::insecure_decode::<serde_json::Value>(token).ok()?;
+    let iss = unverified.claims.get("iss")?.as_str()?;
+    if iss == config.issuer {
+        return None;
+    }
+    config.issuers.iter().find(|issuer| issuer.issuer == iss)
+}
+
 /// Verify that a token is a refresh token
 pub fn verify_refresh_token(token: &str, config: &AuthConfig) -> Result<Claims, ApiError> {
     let claims = verify_token(token, config)?;
@@ -218,6 +370,42 @@ pub fn verify_refresh_token(token: &str, config: &AuthConfig) -> Result<Claims,
     Ok(claims)
 }
 
+/// Verify that a token is an email verification token
+pub fn verify_email_verification_token(
+    token: &str,
+    config: &AuthConfig,
+) -> Result<Claims, ApiError> {
+    let claims = verify_token(token, config)?;
+
+    if !claims.is_email_verification_token() {
+        return Err(ApiError::Unauthorized);
+    }
+
+    Ok(claims)
+}
+
+/// Verify that a token is a password reset token
+pub fn verify_password_reset_token(token: &str, config: &AuthConfig) -> Result<Claims, ApiError> {
+    let claims = verify_token(token, config)?;
+
+    if !claims.is_password_reset_token() {
+        return Err(ApiError::Unauthorized);
+    }
+
+    Ok(claims)
+}
+
+/// Verify that a token is a sign-in link token
+pub fn verify_magic_link_token(token: &str, config: &AuthConfig) -> Result<Claims, ApiError> {
+    let claims = verify_token(token, config)?;
+
+    if !claims.is_magic_link_token() {
+        return Err(ApiError::Unauthorized);
+    }
+
+    Ok(claims)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,4 +437,20 @@ mod tests {
         assert_eq!(claims.sub, "user-123");
         assert!(claims.is_refresh_token());
     }
+
+    #[test]
+    fn email_verification_tokens_are_not_access_tokens() {
+        let config = AuthConfig::default();
+        let claims = Claims::new_email_verification(
+            "user-123",
+            "test@example.com",
+            std::time::Duration::from_secs(60),
+            &config,
+        );
+        let token = encode_token(&claims, &config).unwrap();
+
+        assert!(verify_access_token(&token, &config).is_err());
+        let claims = verify_email_verification_token(&token, &config).unwrap();
+        assert_eq!(claims.email, "test@example.com");
+    }
 }
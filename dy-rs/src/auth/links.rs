@@ -0,0 +1,95 @@
+//! Signed links emailed to users: verification, password reset, sign-in
+
+use std::{sync::Arc, time::Duration};
+
+use super::{
+    config::AuthConfig,
+    handlers::StoredUser,
+    jwt::{Claims, encode_token},
+};
+use crate::{
+    error::ApiError,
+    mail::{Mailer, Templates},
+};
+
+/// Renders a [`Templates`] entry with a link to `link` and sends it
+#[derive(Clone)]
+pub(super) struct LinkMailer {
+    mailer: Arc<dyn Mailer>,
+    link: String,
+    template: &'static str,
+    pub(super) templates: Templates,
+    pub(super) subject: Option<String>,
+    pub(super) expiry: Duration,
+}
+
+impl LinkMailer {
+    pub(super) fn new(
+        mailer: impl Mailer,
+        link: String,
+        template: &'static str,
+        expiry: Duration,
+    ) -> Self {
+        Self {
+            mailer: Arc::new(mailer),
+            link,
+            template,
+            templates: Templates::default(),
+            subject: None,
+            expiry,
+        }
+    }
+
+    /// Email `user` a link carrying `claims`, in which `{token}` is replaced
+    /// by the signed claims
+    pub(super) async fn send(
+        &self,
+        user: &StoredUser,
+        claims: &Claims,
+        config: &AuthConfig,
+    ) -> Result<(), ApiError> {
+        let token = encode_token(claims, config).map_err(|e| {
+            ApiError::InternalServerError(format!("Failed to create link token: {}", e))
+        })?;
+        let link = self.link.replace("{token}", &token);
+
+        let mut email = self.templates.render(
+            self.template,
+            &[
+                ("name", &user.name),
+                ("link", &link),
+                ("expires_in", &describe(self.expiry)),
+            ],
+        )?;
+        if let Some(subject) = &self.subject {
+            email.subject = subject.clone();
+        }
+        self.mailer.send(email.to(&user.email)).await
+    }
+}
+
+/// `expiry` in words, e.g. "24 hours"
+fn describe(expiry: Duration) -> String {
+    let secs = expiry.as_secs();
+    let (count, unit) = match secs {
+        s if s >= 86_400 && s % 86_400 == 0 => (s / 86_400, "day"),
+        s if s >= 3_600 && s % 3_600 == 0 => (s / 3_600, "hour"),
+        s if s >= 60 && s % 60 == 0 => (s / 60, "minute"),
+        s => (s, "second"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} {unit}{plural}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiries_read_naturally() {
+        assert_eq!(describe(Duration::from_secs(24 * 3_600)), "1 day");
+        assert_eq!(describe(Duration::from_secs(2 * 3_600)), "2 hours");
+        assert_eq!(describe(Duration::from_secs(15 * 60)), "15 minutes");
+        assert_eq!(describe(Duration::from_secs(90)), "90 seconds");
+    }
+}
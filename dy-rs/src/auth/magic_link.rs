@@ -0,0 +1,70 @@
+//! Passwordless sign-in links
+//!
+//! With a [`MagicLink`] on the [`AuthAppState`](super::AuthAppState), the
+//! auth routes gain `POST /auth/magic-link`, which emails a sign-in link,
+//! and `POST /auth/magic-link/verify`, which exchanges its token for a token
+//! pair:
+//!
+//! ```rust,ignore
+//! let state = AuthAppState::new(config, store).magic_links(MagicLink::new(
+//!     app_config.mail.mailer(),
+//!     "https://app.example.com/sign-in?token={token}",
+//! ));
+//! ```
+//!
+//! Following a link also verifies the address it was sent to. Links can be
+//! used more than once until they expire, so keep the expiry short. The
+//! email is the `magic_link` entry of the [`Templates`].
+
+use std::time::Duration;
+
+use super::{config::AuthConfig, handlers::StoredUser, jwt::Claims, links::LinkMailer};
+use crate::{
+    error::ApiError,
+    mail::{Mailer, Templates},
+};
+
+/// Sends sign-in links
+#[derive(Clone)]
+pub struct MagicLink {
+    links: LinkMailer,
+}
+
+impl MagicLink {
+    /// Send links built from `link`, in which `{token}` is replaced by the
+    /// sign-in token
+    pub fn new(mailer: impl Mailer, link: impl Into<String>) -> Self {
+        Self {
+            links: LinkMailer::new(
+                mailer,
+                link.into(),
+                "magic_link",
+                Duration::from_secs(15 * 60),
+            ),
+        }
+    }
+
+    /// How long links stay valid (default: 15 minutes)
+    pub fn expiry(mut self, expiry: Duration) -> Self {
+        self.links.expiry = expiry;
+        self
+    }
+
+    /// Set the email subject, instead of the template's
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.links.subject = Some(subject.into());
+        self
+    }
+
+    /// Render the email from `templates`
+    pub fn templates(mut self, templates: Templates) -> Self {
+        self.links.templates = templates;
+        self
+    }
+
+    /// Email `user` a sign-in link
+    pub async fn send(&self, user: &StoredUser, config: &AuthConfig) -> Result<(), ApiError> {
+        let claims = Claims::new_magic_link(&user.id, &user.email, self.links.expiry, config);
+        self.links.send(user, &claims, config).await
+    }
+}
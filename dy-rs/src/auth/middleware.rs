@@ -1,9 +1,19 @@
 //! Authentication middleware for protecting routes
 
-use axum::{Json, extract::Request, http::StatusCode, middleware::Next, response::IntoResponse};
+use std::{future::Future, pin::Pin, sync::Arc, task};
+
+use axum::{
+    Json,
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use serde::Serialize;
+use tower::{Layer, Service};
 
 use super::config::AuthConfig;
+use super::extractors::{AuthError, AuthUser, extract_auth_user_from_parts};
 use super::jwt::verify_access_token;
 
 /// Middleware that injects AuthConfig into request extensions
@@ -48,11 +58,42 @@ struct AuthErrorResponse {
 
 impl RequireAuth {
     /// Middleware function that requires a valid JWT token
+    ///
+    /// Anonymous guests are turned away with `403 ACCOUNT_REQUIRED`; use
+    /// [`allow_guests`](Self::allow_guests) for routes open to them.
     pub async fn middleware(
         config: axum::extract::State<AuthConfig>,
         request: Request,
         next: Next,
     ) -> impl IntoResponse {
+        Self::authenticate(&config, request, next, false).await
+    }
+
+    /// Middleware function that requires a valid JWT token, from an account
+    /// or from a guest of [`GuestSessions`](super::GuestSessions)
+    ///
+    /// ```rust,ignore
+    /// let cart_routes = Router::new()
+    ///     .route("/cart", get(get_cart))
+    ///     .layer(middleware::from_fn_with_state(
+    ///         config.clone(),
+    ///         RequireAuth::allow_guests,
+    ///     ));
+    /// ```
+    pub async fn allow_guests(
+        config: axum::extract::State<AuthConfig>,
+        request: Request,
+        next: Next,
+    ) -> impl IntoResponse {
+        Self::authenticate(&config, request, next, true).await
+    }
+
+    async fn authenticate(
+        config: &AuthConfig,
+        request: Request,
+        next: Next,
+        allow_guests: bool,
+    ) -> Response {
         // Extract Authorization header
         let auth_header = request
             .headers()
@@ -73,8 +114,14 @@ impl RequireAuth {
             }
         };
 
-        // Verify token
-        match verify_access_token(token, &config) {
+        // Verify token; merged guests' tokens are revoked
+        match verify_access_token(token, config) {
+            Ok(claims) if claims.guest && config.merged_guests.contains(&claims.sub) => {
+                AuthError::InvalidToken.into_response()
+            }
+            Ok(claims) if claims.guest && !allow_guests => {
+                AuthError::AccountRequired.into_response()
+            }
             Ok(_claims) => {
                 // Token is valid, proceed with request
                 next.run(request).await
@@ -93,6 +140,10 @@ impl RequireAuth {
 
 /// Middleware that requires specific roles
 ///
+/// As a layer it reads the [`AuthConfig`] from the request extensions, like
+/// the [`AuthUser`](super::AuthUser) extractor, so it must run inside the
+/// layer that injects the config.
+///
 /// # Example
 ///
 /// ```rust,ignore
@@ -101,12 +152,11 @@ impl RequireAuth {
 ///
 /// let admin_routes = Router::new()
 ///     .route("/admin/users", get(list_users))
-///     .layer(RequireRoles::new(vec!["admin"]));
+///     .layer(RequireRoles::any(vec!["admin"]));
 /// ```
 #[derive(Clone)]
-#[allow(dead_code)]
 pub struct RequireRoles {
-    roles: Vec<String>,
+    roles: Arc<Vec<String>>,
     require_all: bool,
 }
 
@@ -114,7 +164,7 @@ impl RequireRoles {
     /// Create a new RequireRoles middleware requiring any of the specified roles
     pub fn any(roles: Vec<impl Into<String>>) -> Self {
         Self {
-            roles: roles.into_iter().map(|r| r.into()).collect(),
+            roles: Arc::new(roles.into_iter().map(|r| r.into()).collect()),
             require_all: false,
         }
     }
@@ -122,12 +172,34 @@ impl RequireRoles {
     /// Create a new RequireRoles middleware requiring all of the specified roles
     pub fn all(roles: Vec<impl Into<String>>) -> Self {
         Self {
-            roles: roles.into_iter().map(|r| r.into()).collect(),
+            roles: Arc::new(roles.into_iter().map(|r| r.into()).collect()),
             require_all: true,
         }
     }
 
+    fn check(&self, user: &AuthUser) -> Result<(), AuthError> {
+        let has_required_roles = if self.require_all {
+            self.roles.iter().all(|role| user.roles.contains(role))
+        } else {
+            self.roles.iter().any(|role| user.roles.contains(role))
+        };
+        if has_required_roles {
+            Ok(())
+        } else {
+            let mode = if self.require_all { "all" } else { "any" };
+            Err(user.deny(
+                "RequireRoles",
+                &self.roles,
+                mode,
+                format!("Required roles: {:?} ({})", self.roles, mode),
+            ))
+        }
+    }
+
     /// Middleware function
+    ///
+    /// Guests are turned away with `403 ACCOUNT_REQUIRED`, whatever roles
+    /// their token carries.
     pub async fn middleware(
         roles: Vec<String>,
         require_all: bool,
@@ -155,8 +227,13 @@ impl RequireRoles {
             }
         };
 
-        // Verify token
+        // Verify token; merged guests' tokens are revoked, and roles are only
+        // granted to accounts
         let claims = match verify_access_token(token, &config) {
+            Ok(claims) if claims.guest && config.merged_guests.contains(&claims.sub) => {
+                return AuthError::InvalidToken.into_response();
+            }
+            Ok(claims) if claims.guest => return AuthError::AccountRequired.into_response(),
             Ok(claims) => claims,
             Err(_) => {
                 return (
@@ -196,6 +273,105 @@ impl RequireRoles {
     }
 }
 
+impl<S> Layer<S> for RequireRoles {
+    type Service = RequireRolesService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireRolesService {
+            inner,
+            roles: self.clone(),
+        }
+    }
+}
+
+/// Service produced by the [`RequireRoles`] layer
+#[derive(Clone)]
+pub struct RequireRolesService<S> {
+    inner: S,
+    roles: RequireRoles,
+}
+
+impl<S> Service<Request> for RequireRolesService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let (mut parts, body) = request.into_parts();
+        let allowed =
+            extract_auth_user_from_parts(&mut parts).and_then(|user| self.roles.check(&user));
+        match allowed {
+            Ok(()) => Box::pin(self.inner.call(Request::from_parts(parts, body))),
+            Err(err) => Box::pin(async move { Ok(err.into_response()) }),
+        }
+    }
+}
+
+/// Middleware that requires a verified email address
+///
+/// Rejects users whose access token doesn't carry `email_verified` with
+/// `403 EMAIL_NOT_VERIFIED`. Like [`RequireRoles`] it reads the
+/// [`AuthConfig`] from the request extensions. Users who just verified need a
+/// fresh token, which `/auth/refresh` issues.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use dy_rs::auth::RequireVerifiedEmail;
+///
+/// let checkout = Router::new()
+///     .route("/orders", post(create_order))
+///     .layer(RequireVerifiedEmail);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequireVerifiedEmail;
+
+impl<S> Layer<S> for RequireVerifiedEmail {
+    type Service = RequireVerifiedEmailService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireVerifiedEmailService { inner }
+    }
+}
+
+/// Service produced by the [`RequireVerifiedEmail`] layer
+#[derive(Clone)]
+pub struct RequireVerifiedEmailService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for RequireVerifiedEmailService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let (mut parts, body) = request.into_parts();
+        let allowed =
+            extract_auth_user_from_parts(&mut parts).and_then(|user| user.require_verified_email());
+        match allowed {
+            Ok(()) => Box::pin(self.inner.call(Request::from_parts(parts, body))),
+            Err(err) => Box::pin(async move { Ok(err.into_response()) }),
+        }
+    }
+}
+
 /// Extension trait for Router to easily add auth protection
 pub trait AuthRouterExt {
     /// Protect all routes with authentication
@@ -204,3 +380,99 @@ pub trait AuthRouterExt {
     /// Protect all routes requiring specific roles
     fn require_roles(self, config: AuthConfig, roles: Vec<&str>, require_all: bool) -> Self;
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, middleware, routing::get};
+
+    use super::*;
+    use crate::auth::{GuestSessions, testing::TestUser};
+    use crate::testing::TestClient;
+
+    #[tokio::test]
+    async fn require_auth_turns_guests_away_unless_allowed() {
+        let config = AuthConfig::default();
+        let router = Router::new()
+            .route(
+                "/profile",
+                get(|| async { "profile" }).layer(middleware::from_fn_with_state(
+                    config.clone(),
+                    RequireAuth::middleware,
+                )),
+            )
+            .route(
+                "/cart",
+                get(|| async { "cart" }).layer(middleware::from_fn_with_state(
+                    config.clone(),
+                    RequireAuth::allow_guests,
+                )),
+            );
+        let client = TestClient::from_router(router);
+        let guests = GuestSessions::new();
+        let guest = guests.issue(&config).unwrap().access_token;
+        let user = TestUser::new("user-1").access_token(&config);
+
+        client.get("/profile").bearer(&user).await.assert_ok();
+        let response = client
+            .get("/profile")
+            .bearer(&guest)
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.json::<serde_json::Value>()["code"],
+            "ACCOUNT_REQUIRED"
+        );
+
+        client.get("/cart").bearer(&user).await.assert_ok();
+        client.get("/cart").bearer(&guest).await.assert_ok();
+
+        guests.merge(&guest, "user-1", &config).await.unwrap();
+        client
+            .get("/cart")
+            .bearer(&guest)
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn require_roles_middleware_turns_guests_away() {
+        let config = AuthConfig::default();
+        let router = Router::new().route(
+            "/guestbook",
+            get(|| async { "guestbook" }).layer(middleware::from_fn_with_state(
+                config.clone(),
+                |config, request, next| {
+                    RequireRoles::middleware(
+                        vec!["guest".to_string()],
+                        false,
+                        config,
+                        request,
+                        next,
+                    )
+                },
+            )),
+        );
+        let client = TestClient::from_router(router);
+        let guests = GuestSessions::new();
+        let guest = guests.issue(&config).unwrap().access_token;
+
+        let response = client
+            .get("/guestbook")
+            .bearer(&guest)
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.json::<serde_json::Value>()["code"],
+            "ACCOUNT_REQUIRED"
+        );
+        let user = TestUser::new("user-1").role("guest").access_token(&config);
+        client.get("/guestbook").bearer(&user).await.assert_ok();
+
+        guests.merge(&guest, "user-1", &config).await.unwrap();
+        client
+            .get("/guestbook")
+            .bearer(&guest)
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
@@ -20,21 +20,53 @@
 //! }
 //! ```
 
+pub mod account;
+pub mod admin;
+pub mod cache;
 pub mod config;
 pub mod extractors;
+pub mod guest;
 pub mod handlers;
+pub mod identity;
+pub mod issuers;
 pub mod jwt;
+mod links;
+pub mod magic_link;
 pub mod middleware;
 pub mod models;
 pub mod password;
+pub mod password_reset;
+pub mod registration;
+pub mod testing;
+pub mod verification;
 
-pub use config::AuthConfig;
-pub use extractors::AuthUser;
+pub use account::{AccountEvent, AccountEventSink, AccountStatus, LogAccountEvents};
+pub use admin::{
+    AdminUserStore, AdminUserView, RoleAssignment, StatusChange, UserPage, UserQuery,
+    admin_openapi, admin_routes_with_state, admin_routes_with_store,
+};
+pub use cache::CachedUserStore;
+pub use config::{AuthConfig, DEV_JWT_SECRET};
+pub use extractors::{AuthUser, Denial, GuestOrUser};
+pub use guest::{GuestMerge, GuestMergeSink, GuestSessions, LogGuestMerges, MergedGuests};
 pub use handlers::{
-    AuthAppState, CreateUserData, InMemoryUserStore, StoredUser, UserStore, auth_routes,
-    auth_routes_with_store, login, logout, refresh_token, register,
+    AuthAppState, CreateUserData, InMemoryUserStore, StoredUser, UserStore, auth_openapi,
+    auth_routes, auth_routes_with_state, auth_routes_with_store, change_password,
+    confirm_password_reset, guest_session, login, logout, magic_link_login, refresh_token,
+    register, request_magic_link, request_password_reset, resend_verification, upgrade_guest,
+    verify_email,
 };
+pub use identity::{ClaimHeaders, ForwardedIdentity};
+pub use issuers::{ClaimMapping, IssuerKey, JwksFetcher, TrustedIssuer};
 pub use jwt::{Claims, TokenPair, create_token_pair, verify_token};
-pub use middleware::RequireAuth;
-pub use models::{AuthResponse, LoginRequest, RegisterRequest, TokenRefreshRequest};
+pub use magic_link::MagicLink;
+pub use middleware::{RequireAuth, RequireRoles, RequireVerifiedEmail};
+pub use models::{
+    AuthResponse, GuestResponse, LoginRequest, MagicLinkLogin, MagicLinkRequest,
+    PasswordResetConfirm, PasswordResetRequest, RegisterPayload, RegisterRequest,
+    TokenRefreshRequest, UpgradeGuestRequest, VerifyEmailRequest,
+};
 pub use password::{hash_password, verify_password};
+pub use password_reset::PasswordReset;
+pub use registration::PreRegister;
+pub use verification::EmailVerification;
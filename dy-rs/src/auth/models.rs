@@ -1,8 +1,9 @@
 //! Authentication request and response models
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use utoipa::ToSchema;
-use validator::Validate;
+use validator::{Validate, ValidationErrors};
 
 /// Login request payload
 #[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
@@ -36,6 +37,24 @@ pub struct RegisterRequest {
     pub name: String,
 }
 
+/// Registration body as received: the standard fields plus any others,
+/// which are handed to a [`PreRegister`](super::PreRegister) hook
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegisterPayload {
+    #[serde(flatten)]
+    pub request: RegisterRequest,
+
+    /// Fields beyond those of [`RegisterRequest`], e.g. `invite_code`
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Validate for RegisterPayload {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        self.request.validate()
+    }
+}
+
 /// Token refresh request
 #[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
 pub struct TokenRefreshRequest {
@@ -77,6 +96,38 @@ pub struct AuthUserInfo {
 
     /// User roles
     pub roles: Vec<String>,
+
+    /// Whether the user must change their password (see `/auth/password`)
+    #[serde(default)]
+    pub password_reset_required: bool,
+
+    /// Whether the user has verified their email address
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+/// Token for an anonymous guest
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct GuestResponse {
+    /// JWT access token for the guest; there is no refresh token
+    pub access_token: String,
+
+    /// Token type (always "Bearer")
+    pub token_type: String,
+
+    /// Access token expiration time in seconds
+    pub expires_in: u64,
+
+    /// ID of the new guest, to own carts, drafts, etc.
+    pub guest_id: String,
+}
+
+/// Guest upgrade request, sent with the account's access token
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct UpgradeGuestRequest {
+    /// The guest's access token
+    #[validate(length(min = 1, message = "Guest token is required"))]
+    pub guest_token: String,
 }
 
 /// Logout request (optional - for refresh token invalidation)
@@ -86,6 +137,14 @@ pub struct LogoutRequest {
     pub refresh_token: Option<String>,
 }
 
+/// Email verification request
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct VerifyEmailRequest {
+    /// Token from the verification email
+    #[validate(length(min = 1, message = "Verification token is required"))]
+    pub token: String,
+}
+
 /// Password change request
 #[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
 pub struct ChangePasswordRequest {
@@ -118,6 +177,22 @@ pub struct PasswordResetConfirm {
     pub new_password: String,
 }
 
+/// Sign-in link request
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct MagicLinkRequest {
+    /// Email address to send the link to
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+/// Sign-in with the token from a sign-in link
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct MagicLinkLogin {
+    /// Token from the sign-in email
+    #[validate(length(min = 1, message = "Sign-in token is required"))]
+    pub token: String,
+}
+
 /// Generic message response
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct MessageResponse {
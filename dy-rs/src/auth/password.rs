@@ -5,6 +5,8 @@ use argon2::{
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
 
+use serde::{Deserialize, Serialize};
+
 use super::config::AuthConfig;
 use crate::error::ApiError;
 
@@ -104,12 +106,31 @@ pub fn validate_password_strength(password: &str) -> Result<(), ApiError> {
 }
 
 /// Validate password strength with custom rules
+///
+/// Also the `[auth.password_policy]` configuration section, read by the
+/// register and change-password handlers:
+///
+/// ```toml
+/// [auth.password_policy]
+/// min_length = 12
+/// require_special = true
+/// banned = ["password1", "companyname2024"]
+/// history = 5   # the current password and the 4 before it can't be reused
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PasswordValidator {
     min_length: usize,
+    max_length: usize,
     require_uppercase: bool,
     require_lowercase: bool,
     require_digit: bool,
     require_special: bool,
+    /// Rejected regardless of case
+    banned: Vec<String>,
+    /// How many recent passwords, counting the current one, can't be reused;
+    /// `0` turns the check off
+    history: usize,
 }
 
 impl PasswordValidator {
@@ -122,6 +143,12 @@ impl PasswordValidator {
         self
     }
 
+    /// Longest accepted password, which bounds the hashing cost (default: 128)
+    pub fn max_length(mut self, length: usize) -> Self {
+        self.max_length = length;
+        self
+    }
+
     pub fn require_uppercase(mut self, required: bool) -> Self {
         self.require_uppercase = required;
         self
@@ -142,16 +169,41 @@ impl PasswordValidator {
         self
     }
 
+    /// Reject these passwords, ignoring case
+    pub fn ban(mut self, passwords: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.banned.extend(passwords.into_iter().map(Into::into));
+        self
+    }
+
+    /// Refuse to reuse the last `depth` passwords, counting the current one
+    pub fn history(mut self, depth: usize) -> Self {
+        self.history = depth;
+        self
+    }
+
+    /// How many recent passwords can't be reused
+    pub fn history_depth(&self) -> usize {
+        self.history
+    }
+
     pub fn validate(&self, password: &str) -> Result<(), ApiError> {
         let mut errors = Vec::new();
+        let length = password.chars().count();
 
-        if password.len() < self.min_length {
+        if length < self.min_length {
             errors.push(format!(
                 "Password must be at least {} characters long",
                 self.min_length
             ));
         }
 
+        if length > self.max_length {
+            errors.push(format!(
+                "Password must be at most {} characters long",
+                self.max_length
+            ));
+        }
+
         if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
             errors.push("Password must contain at least one uppercase letter".to_string());
         }
@@ -168,22 +220,50 @@ impl PasswordValidator {
             errors.push("Password must contain at least one special character".to_string());
         }
 
+        let lowercase = password.to_lowercase();
+        if self.banned.iter().any(|b| b.to_lowercase() == lowercase) {
+            errors.push("Password is too common".to_string());
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
             Err(ApiError::ValidationError(errors.join("; ")))
         }
     }
+
+    /// Reject `password` if it matches one of `recent_hashes`, newest first
+    ///
+    /// Only the first [`history_depth`](Self::history_depth) hashes are
+    /// checked.
+    pub fn check_history<'a>(
+        &self,
+        password: &str,
+        recent_hashes: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), ApiError> {
+        for hash in recent_hashes.into_iter().take(self.history) {
+            if verify_password(password, hash)? {
+                return Err(ApiError::ValidationError(format!(
+                    "Password must differ from the last {} passwords",
+                    self.history
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Default for PasswordValidator {
     fn default() -> Self {
         Self {
             min_length: 8,
+            max_length: 128,
             require_uppercase: true,
             require_lowercase: true,
             require_digit: true,
             require_special: false,
+            banned: Vec::new(),
+            history: 0,
         }
     }
 }
@@ -221,4 +301,45 @@ mod tests {
         assert!(validator.validate("SecurePass1!").is_ok());
         assert!(validator.validate("SecurePass1").is_err()); // No special char
     }
+
+    #[test]
+    fn policy_loads_from_config_with_defaults() {
+        let policy: PasswordValidator = serde_json::from_value(serde_json::json!({
+            "min_length": 10,
+            "max_length": 16,
+            "banned": ["Password1234"],
+        }))
+        .unwrap();
+
+        assert!(policy.validate("Secure12345").is_ok());
+        assert!(policy.validate("Secure123").is_err()); // too short
+        assert!(policy.validate("Secure1234567890x").is_err()); // too long
+        assert!(policy.validate("PASSWORD1234").is_err()); // banned, any case
+        assert!(policy.validate("secure12345").is_err()); // default rules still apply
+    }
+
+    #[test]
+    fn history_rejects_recent_passwords_only() {
+        let config = AuthConfig {
+            argon2_memory_cost: 1024,
+            argon2_time_cost: 1,
+            argon2_parallelism: 1,
+            ..AuthConfig::default()
+        };
+        let hashes: Vec<String> = ["Newest1pass", "Older1pass", "Oldest1pass"]
+            .iter()
+            .map(|p| hash_password(p, &config).unwrap())
+            .collect();
+        let policy = PasswordValidator::new().history(2);
+
+        let recent = || hashes.iter().map(String::as_str);
+        assert!(policy.check_history("Newest1pass", recent()).is_err());
+        assert!(policy.check_history("Older1pass", recent()).is_err());
+        assert!(policy.check_history("Oldest1pass", recent()).is_ok());
+        assert!(
+            PasswordValidator::new()
+                .check_history("Newest1pass", recent())
+                .is_ok()
+        );
+    }
 }
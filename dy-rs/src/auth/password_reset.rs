@@ -0,0 +1,82 @@
+//! Password reset by email
+//!
+//! With a [`PasswordReset`] on the [`AuthAppState`](super::AuthAppState), the
+//! auth routes gain `POST /auth/password-reset`, which emails a reset link,
+//! and `POST /auth/password-reset/confirm`, which sets the new password:
+//!
+//! ```rust,ignore
+//! let state = AuthAppState::new(config, store).reset_passwords(PasswordReset::new(
+//!     app_config.mail.mailer(),
+//!     "https://app.example.com/reset-password?token={token}",
+//! ));
+//! ```
+//!
+//! The link carries a fingerprint of the password hash, so it only works
+//! until the password changes. The email is the `password_reset` entry of
+//! the [`Templates`].
+
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use super::{config::AuthConfig, handlers::StoredUser, jwt::Claims, links::LinkMailer};
+use crate::{
+    error::ApiError,
+    mail::{Mailer, Templates},
+};
+
+/// Sends password reset links
+#[derive(Clone)]
+pub struct PasswordReset {
+    links: LinkMailer,
+}
+
+impl PasswordReset {
+    /// Send links built from `link`, in which `{token}` is replaced by the
+    /// reset token
+    pub fn new(mailer: impl Mailer, link: impl Into<String>) -> Self {
+        Self {
+            links: LinkMailer::new(
+                mailer,
+                link.into(),
+                "password_reset",
+                Duration::from_secs(60 * 60),
+            ),
+        }
+    }
+
+    /// How long links stay valid (default: 1 hour)
+    pub fn expiry(mut self, expiry: Duration) -> Self {
+        self.links.expiry = expiry;
+        self
+    }
+
+    /// Set the email subject, instead of the template's
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.links.subject = Some(subject.into());
+        self
+    }
+
+    /// Render the email from `templates`
+    pub fn templates(mut self, templates: Templates) -> Self {
+        self.links.templates = templates;
+        self
+    }
+
+    /// Email `user` a reset link
+    pub async fn send(&self, user: &StoredUser, config: &AuthConfig) -> Result<(), ApiError> {
+        let claims = Claims::new_password_reset(
+            &user.id,
+            &user.email,
+            fingerprint(&user.password_hash),
+            self.links.expiry,
+            config,
+        );
+        self.links.send(user, &claims, config).await
+    }
+}
+
+/// Identifies a password hash without revealing it
+pub(super) fn fingerprint(password_hash: &str) -> String {
+    hex::encode(&Sha256::digest(password_hash.as_bytes())[..16])
+}
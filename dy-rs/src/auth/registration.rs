@@ -0,0 +1,49 @@
+//! Custom registration fields
+//!
+//! Registration bodies may carry fields beyond [`RegisterRequest`]. A
+//! [`PreRegister`] hook receives them, rejects the registration or returns
+//! metadata that is stored with the new user through
+//! [`UserStore::create_with_metadata`](super::UserStore::create_with_metadata):
+//!
+//! ```rust,ignore
+//! let state = AuthAppState::new(config, store).pre_register(
+//!     |_request: &RegisterRequest, extra: &Map<String, Value>| {
+//!         let invite = extra.get("invite_code").and_then(Value::as_str);
+//!         if invite != Some("WELCOME") {
+//!             return Err(ApiError::BadRequest("A valid invite code is required".into()));
+//!         }
+//!         Ok(json!({ "company": extra.get("company") }))
+//!     },
+//! );
+//! ```
+
+use serde_json::{Map, Value};
+
+use super::models::RegisterRequest;
+use crate::error::ApiError;
+
+/// Runs before an account is created, with the registration's extra fields
+#[async_trait::async_trait]
+pub trait PreRegister: Send + Sync + 'static {
+    /// Check the registration and return the metadata to store with the
+    /// user, or an error to reject it
+    async fn pre_register(
+        &self,
+        request: &RegisterRequest,
+        extra: &Map<String, Value>,
+    ) -> Result<Value, ApiError>;
+}
+
+#[async_trait::async_trait]
+impl<F> PreRegister for F
+where
+    F: Fn(&RegisterRequest, &Map<String, Value>) -> Result<Value, ApiError> + Send + Sync + 'static,
+{
+    async fn pre_register(
+        &self,
+        request: &RegisterRequest,
+        extra: &Map<String, Value>,
+    ) -> Result<Value, ApiError> {
+        self(request, extra)
+    }
+}
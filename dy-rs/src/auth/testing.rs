@@ -0,0 +1,274 @@
+//! Helpers for testing protected handlers
+//!
+//! A [`TestUser`] mints valid tokens for any user and roles, signed with the
+//! app's [`AuthConfig`], so tests don't go through registration and login:
+//!
+//! ```rust,ignore
+//! let config = AuthConfig::new("test-secret");
+//! let admin = TestUser::new("user-1").role("admin");
+//!
+//! let client = TestClient::new(app(config.clone())).bearer(admin.access_token(&config));
+//! client.get("/admin/users").await.assert_ok();
+//! ```
+//!
+//! Handler tests that don't care about tokens at all can skip them: routes
+//! wrapped in [`MockAuth`] see its user as the authenticated one, without an
+//! `Authorization` header or an `AuthConfig`:
+//!
+//! ```rust,ignore
+//! let router = Router::new()
+//!     .route("/me", get(me))
+//!     .layer(MockAuth::new(TestUser::new("user-1").role("editor")));
+//! ```
+//!
+//! [`AuthUser`], [`OptionalAuthUser`](super::extractors::OptionalAuthUser),
+//! [`RequireRoles`](super::RequireRoles) and
+//! [`RequireVerifiedEmail`](super::RequireVerifiedEmail) all accept the mocked
+//! user. Never add [`MockAuth`] outside tests.
+
+use std::{future::Future, pin::Pin, task};
+
+use axum::{extract::Request, response::Response};
+use chrono::Utc;
+use tower::{Layer, Service};
+
+use super::{
+    config::AuthConfig,
+    extractors::AuthUser,
+    jwt::{Claims, TokenPair, create_token_pair_from_claims, encode_token},
+};
+
+/// A user to mint tokens for, or to mock as authenticated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestUser {
+    id: String,
+    email: String,
+    roles: Vec<String>,
+    email_verified: bool,
+    guest: bool,
+    expired: bool,
+}
+
+impl TestUser {
+    /// User `id`, with the email `{id}@example.com` and no roles
+    pub fn new(id: impl Into<String>) -> Self {
+        let id = id.into();
+        Self {
+            email: format!("{id}@example.com"),
+            id,
+            roles: Vec::new(),
+            email_verified: false,
+            guest: false,
+            expired: false,
+        }
+    }
+
+    pub fn email(mut self, email: impl Into<String>) -> Self {
+        self.email = email.into();
+        self
+    }
+
+    pub fn role(mut self, role: impl Into<String>) -> Self {
+        self.roles.push(role.into());
+        self
+    }
+
+    pub fn roles<I>(mut self, roles: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.roles.extend(roles.into_iter().map(Into::into));
+        self
+    }
+
+    /// With a verified email address
+    pub fn verified(mut self) -> Self {
+        self.email_verified = true;
+        self
+    }
+
+    /// An anonymous guest rather than an account
+    pub fn guest(mut self) -> Self {
+        self.guest = true;
+        self
+    }
+
+    /// With tokens that expired an hour ago, to test rejections
+    pub fn expired(mut self) -> Self {
+        self.expired = true;
+        self
+    }
+
+    /// The claims of an access token for the user
+    pub fn claims(&self, config: &AuthConfig) -> Claims {
+        let mut claims = Claims::new_access(&self.id, &self.email, self.roles.clone(), config)
+            .with_email_verified(self.email_verified);
+        claims.guest = self.guest;
+        if self.expired {
+            expire(&mut claims);
+        }
+        claims
+    }
+
+    /// A signed access token, as sent in `Authorization: Bearer`
+    pub fn access_token(&self, config: &AuthConfig) -> String {
+        encode_token(&self.claims(config), config).expect("test token should sign")
+    }
+
+    /// A signed refresh token
+    pub fn refresh_token(&self, config: &AuthConfig) -> String {
+        let mut claims = Claims::new_refresh(&self.id, &self.email, config);
+        if self.expired {
+            expire(&mut claims);
+        }
+        encode_token(&claims, config).expect("test token should sign")
+    }
+
+    /// Access and refresh tokens, as login answers them
+    pub fn token_pair(&self, config: &AuthConfig) -> TokenPair {
+        create_token_pair_from_claims(self.claims(config), config).expect("test tokens should sign")
+    }
+
+    /// The user as the [`AuthUser`] extractor yields it, for calling
+    /// handlers directly
+    pub fn auth_user(&self) -> AuthUser {
+        AuthUser::from_claims(self.claims(&AuthConfig::default()))
+    }
+}
+
+/// Make `claims` expire an hour ago, well past any leeway
+fn expire(claims: &mut Claims) {
+    claims.exp = (Utc::now() - chrono::Duration::hours(1)).timestamp();
+}
+
+/// The user a [`MockAuth`] layer authenticated
+#[derive(Debug, Clone)]
+pub(crate) struct MockedUser(pub(crate) AuthUser);
+
+/// Layer authenticating every request as a fixed user; for tests only
+#[derive(Debug, Clone)]
+pub struct MockAuth {
+    user: AuthUser,
+}
+
+impl MockAuth {
+    pub fn new(user: TestUser) -> Self {
+        Self::from_auth_user(user.auth_user())
+    }
+
+    pub fn from_auth_user(user: AuthUser) -> Self {
+        Self { user }
+    }
+}
+
+impl<S> Layer<S> for MockAuth {
+    type Service = MockAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MockAuthService {
+            inner,
+            user: self.user.clone(),
+        }
+    }
+}
+
+/// Service produced by the [`MockAuth`] layer
+#[derive(Clone)]
+pub struct MockAuthService<S> {
+    inner: S,
+    user: AuthUser,
+}
+
+impl<S> Service<Request> for MockAuthService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        request
+            .extensions_mut()
+            .insert(MockedUser(self.user.clone()));
+        Box::pin(self.inner.call(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{RequireRoles, jwt::verify_access_token, middleware::inject_auth_config};
+    use crate::testing::TestClient;
+    use axum::{Router, http::StatusCode, middleware, routing::get};
+
+    async fn whoami(user: AuthUser) -> String {
+        format!("{} {}", user.id, user.roles.join(","))
+    }
+
+    #[tokio::test]
+    async fn minted_tokens_authenticate() {
+        let config = AuthConfig::new("test-secret");
+        let editor = TestUser::new("user-1")
+            .roles(["editor", "viewer"])
+            .verified();
+        let claims = verify_access_token(&editor.access_token(&config), &config).unwrap();
+        assert_eq!(claims.email, "user-1@example.com");
+        assert!(claims.email_verified);
+
+        let layer_config = config.clone();
+        let router = Router::new()
+            .route("/whoami", get(whoami))
+            .layer(middleware::from_fn(move |request, next| {
+                inject_auth_config(layer_config.clone(), request, next)
+            }));
+        let client = TestClient::from_router(router);
+
+        let response = client
+            .get("/whoami")
+            .bearer(editor.access_token(&config))
+            .await
+            .assert_ok();
+        assert_eq!(response.text(), "user-1 editor,viewer");
+
+        client
+            .get("/whoami")
+            .bearer(editor.clone().expired().access_token(&config))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        client
+            .get("/whoami")
+            .bearer(editor.access_token(&AuthConfig::new("other-secret")))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn mocked_users_skip_tokens() {
+        let router = Router::new()
+            .route("/whoami", get(whoami))
+            .layer(RequireRoles::any(vec!["admin".to_string()]));
+
+        let admin = TestClient::from_router(
+            router
+                .clone()
+                .layer(MockAuth::new(TestUser::new("user-2").role("admin"))),
+        );
+        let response = admin.get("/whoami").await.assert_ok();
+        assert_eq!(response.text(), "user-2 admin");
+
+        let viewer = TestClient::from_router(
+            router.layer(MockAuth::new(TestUser::new("user-3").role("viewer"))),
+        );
+        viewer
+            .get("/whoami")
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+    }
+}
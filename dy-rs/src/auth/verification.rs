@@ -0,0 +1,76 @@
+//! Email verification
+//!
+//! With an [`EmailVerification`] on the [`AuthAppState`](super::AuthAppState),
+//! registering sends a verification link and the auth routes gain
+//! `POST /auth/verify-email` and `POST /auth/verify-email/resend`. Routes that
+//! need a verified address are wrapped in
+//! [`RequireVerifiedEmail`](super::RequireVerifiedEmail):
+//!
+//! ```rust,ignore
+//! let state = AuthAppState::new(config, store).verify_emails(EmailVerification::new(
+//!     app_config.mail.mailer(),
+//!     "https://app.example.com/verify-email?token={token}",
+//! ));
+//!
+//! let app = Router::new()
+//!     .merge(auth_routes_with_state(state))
+//!     .merge(checkout_routes().layer(RequireVerifiedEmail));
+//! ```
+//!
+//! Links are signed tokens, so nothing has to be stored until the user
+//! follows one. The email is the `verify_email` entry of the
+//! [`Templates`].
+
+use std::time::Duration;
+
+use super::{config::AuthConfig, handlers::StoredUser, jwt::Claims, links::LinkMailer};
+use crate::{
+    error::ApiError,
+    mail::{Mailer, Templates},
+};
+
+/// Sends verification links to newly registered users
+#[derive(Clone)]
+pub struct EmailVerification {
+    links: LinkMailer,
+}
+
+impl EmailVerification {
+    /// Send links built from `link`, in which `{token}` is replaced by the
+    /// verification token
+    pub fn new(mailer: impl Mailer, link: impl Into<String>) -> Self {
+        Self {
+            links: LinkMailer::new(
+                mailer,
+                link.into(),
+                "verify_email",
+                Duration::from_secs(24 * 60 * 60),
+            ),
+        }
+    }
+
+    /// How long links stay valid (default: 24 hours)
+    pub fn expiry(mut self, expiry: Duration) -> Self {
+        self.links.expiry = expiry;
+        self
+    }
+
+    /// Set the email subject, instead of the template's
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.links.subject = Some(subject.into());
+        self
+    }
+
+    /// Render the email from `templates`
+    pub fn templates(mut self, templates: Templates) -> Self {
+        self.links.templates = templates;
+        self
+    }
+
+    /// Email `user` a fresh verification link
+    pub async fn send(&self, user: &StoredUser, config: &AuthConfig) -> Result<(), ApiError> {
+        let claims =
+            Claims::new_email_verification(&user.id, &user.email, self.links.expiry, config);
+        self.links.send(user, &claims, config).await
+    }
+}
@@ -0,0 +1,457 @@
+//! Bulk write endpoints (batch create/update/delete)
+//!
+//! [`BulkJson`] accepts a JSON array and deserializes and validates every
+//! item on its own, so one bad item does not reject the whole request.
+//! [`BulkWriter`] hands the valid items to a [`BulkSink`] in chunks, each
+//! applied atomically, and collects a per-item outcome into a
+//! [`BulkResponse`]. The response is `200 OK` when every item succeeded and
+//! `207 Multi-Status` otherwise.
+//!
+//! `#[dy_api]` documents a `BulkJson<T>` argument as an array of `T` and a
+//! `BulkResponse<R>` return type as a `207` response.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use dy_rs::bulk::{BulkJson, BulkResponse, BulkWriter};
+//!
+//! #[dy_api(method = post, path = "/users/bulk", tag = "Users")]
+//! async fn create_users(
+//!     State(pool): State<PgPool>,
+//!     payload: BulkJson<CreateUserRequest>,
+//! ) -> BulkResponse<User> {
+//!     BulkWriter::new()
+//!         .chunk_size(100)
+//!         .run(payload, &|items: Vec<CreateUserRequest>| async {
+//!             let mut tx = pool.begin().await?;
+//!             let mut users = Vec::with_capacity(items.len());
+//!             for item in items {
+//!                 users.push(insert_user(&mut tx, item).await?);
+//!             }
+//!             tx.commit().await?;
+//!             Ok(users)
+//!         })
+//!         .await
+//! }
+//! ```
+
+use std::future::Future;
+
+use async_trait::async_trait;
+use axum::{
+    Json,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::error::ApiError;
+
+/// A field that failed validation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct BulkFieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Why a single item was not written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct BulkItemError {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<BulkFieldError>,
+}
+
+impl From<&ApiError> for BulkItemError {
+    fn from(err: &ApiError) -> Self {
+        Self {
+            code: err.error_code().to_string(),
+            message: err.public_message(),
+            errors: err
+                .field_errors()
+                .iter()
+                .map(|e| BulkFieldError {
+                    field: e.field.clone(),
+                    message: e.message.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Outcome for one item of a bulk request
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct BulkItemResult<R> {
+    /// Position of the item in the request array
+    pub index: usize,
+    /// HTTP status the item would have had as a single request
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<R>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<BulkItemError>,
+}
+
+impl<R> BulkItemResult<R> {
+    fn failed(index: usize, status: StatusCode, error: BulkItemError) -> Self {
+        Self {
+            index,
+            status: status.as_u16(),
+            data: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Multi-status response for a bulk request
+///
+/// Responds with `200 OK` when every item succeeded and `207 Multi-Status`
+/// otherwise. Results are ordered by item index.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct BulkResponse<R> {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItemResult<R>>,
+}
+
+impl<R> BulkResponse<R> {
+    fn from_results(mut results: Vec<BulkItemResult<R>>) -> Self {
+        results.sort_by_key(|result| result.index);
+        let failed = results.iter().filter(|r| r.error.is_some()).count();
+        Self {
+            succeeded: results.len() - failed,
+            failed,
+            results,
+        }
+    }
+
+    /// Status code this response is sent with
+    pub fn status(&self) -> StatusCode {
+        if self.failed == 0 {
+            StatusCode::OK
+        } else {
+            StatusCode::MULTI_STATUS
+        }
+    }
+}
+
+impl<R: Serialize> IntoResponse for BulkResponse<R> {
+    fn into_response(self) -> Response {
+        (self.status(), Json(self)).into_response()
+    }
+}
+
+/// Extractor for a JSON array of items, validated one by one
+///
+/// The request is rejected only if the body is not a JSON array; items that
+/// fail to deserialize or validate are kept as rejections and reported by
+/// [`BulkWriter::run`].
+pub struct BulkJson<T> {
+    items: Vec<(usize, T)>,
+    rejected: Vec<(usize, BulkItemError)>,
+}
+
+impl<T> BulkJson<T> {
+    /// Total number of items in the request
+    pub fn len(&self) -> usize {
+        self.items.len() + self.rejected.len()
+    }
+
+    /// Whether the request array was empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Valid items with their index in the request array
+    pub fn items(&self) -> &[(usize, T)] {
+        &self.items
+    }
+
+    /// Items that failed to deserialize or validate
+    pub fn rejected(&self) -> &[(usize, BulkItemError)] {
+        &self.rejected
+    }
+}
+
+impl<T: DeserializeOwned + Validate> BulkJson<T> {
+    fn from_values(values: Vec<serde_json::Value>) -> Self {
+        let mut items = Vec::with_capacity(values.len());
+        let mut rejected = Vec::new();
+
+        for (index, value) in values.into_iter().enumerate() {
+            match parse_item::<T>(value) {
+                Ok(item) => items.push((index, item)),
+                Err(error) => rejected.push((index, error)),
+            }
+        }
+
+        Self { items, rejected }
+    }
+}
+
+impl<T, S> FromRequest<S> for BulkJson<T>
+where
+    T: DeserializeOwned + Validate + Send + 'static,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(values) = Json::<Vec<serde_json::Value>>::from_request(req, state)
+            .await
+            .map_err(|rejection| {
+                tracing::error!("Bulk payload rejected: {:?}", rejection);
+                ApiError::BadRequest("Expected a JSON array of items".to_string())
+            })?;
+
+        Ok(Self::from_values(values))
+    }
+}
+
+fn parse_item<T: DeserializeOwned + Validate>(
+    value: serde_json::Value,
+) -> Result<T, BulkItemError> {
+    let item: T = serde_json::from_value(value).map_err(|e| BulkItemError {
+        code: "INVALID_JSON".to_string(),
+        message: e.to_string(),
+        errors: Vec::new(),
+    })?;
+
+    item.validate().map_err(|errors| BulkItemError {
+        code: "VALIDATION_ERROR".to_string(),
+        message: "Item validation failed".to_string(),
+        errors: errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| BulkFieldError {
+                    field: field.to_string(),
+                    message: error
+                        .message
+                        .as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| error.code.to_string()),
+                })
+            })
+            .collect(),
+    })?;
+
+    Ok(item)
+}
+
+/// Destination for a chunk of valid items
+///
+/// Each call should apply its chunk atomically, typically in a single
+/// database transaction, and return one result per item in order. Closures
+/// `Fn(Vec<T>) -> impl Future` are sinks too.
+#[async_trait]
+pub trait BulkSink<T, R>: Send + Sync {
+    async fn write_chunk(&self, items: Vec<T>) -> Result<Vec<R>, ApiError>;
+}
+
+#[async_trait]
+impl<T, R, F, Fut> BulkSink<T, R> for F
+where
+    T: Send + 'static,
+    F: Fn(Vec<T>) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Vec<R>, ApiError>> + Send,
+{
+    async fn write_chunk(&self, items: Vec<T>) -> Result<Vec<R>, ApiError> {
+        self(items).await
+    }
+}
+
+/// Writes [`BulkJson`] items to a [`BulkSink`] in chunks
+#[derive(Debug, Clone)]
+pub struct BulkWriter {
+    chunk_size: usize,
+}
+
+impl BulkWriter {
+    /// Create a writer with chunks of 100 items
+    pub fn new() -> Self {
+        Self { chunk_size: 100 }
+    }
+
+    /// Set the number of items per chunk
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Write every valid item of `input` to `sink`
+    ///
+    /// When a chunk fails, every item in it is reported with the chunk's
+    /// error; other chunks are unaffected.
+    pub async fn run<T, R, S>(&self, input: BulkJson<T>, sink: &S) -> BulkResponse<R>
+    where
+        S: BulkSink<T, R> + ?Sized,
+    {
+        let BulkJson { items, rejected } = input;
+        let mut results: Vec<BulkItemResult<R>> = rejected
+            .into_iter()
+            .map(|(index, error)| {
+                BulkItemResult::failed(index, StatusCode::UNPROCESSABLE_ENTITY, error)
+            })
+            .collect();
+
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            let (indices, chunk): (Vec<usize>, Vec<T>) =
+                items.by_ref().take(self.chunk_size).unzip();
+            write_chunk(sink, indices, chunk, &mut results).await;
+        }
+
+        BulkResponse::from_results(results)
+    }
+}
+
+impl Default for BulkWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn write_chunk<T, R, S>(
+    sink: &S,
+    indices: Vec<usize>,
+    chunk: Vec<T>,
+    results: &mut Vec<BulkItemResult<R>>,
+) where
+    S: BulkSink<T, R> + ?Sized,
+{
+    let err =
+        match sink.write_chunk(chunk).await {
+            Ok(written) if written.len() == indices.len() => {
+                results.extend(indices.into_iter().zip(written).map(|(index, data)| {
+                    BulkItemResult {
+                        index,
+                        status: StatusCode::OK.as_u16(),
+                        data: Some(data),
+                        error: None,
+                    }
+                }));
+                return;
+            }
+            Ok(written) => ApiError::InternalServerError(format!(
+                "bulk sink returned {} results for {} items",
+                written.len(),
+                indices.len()
+            )),
+            Err(err) => err,
+        };
+
+    tracing::warn!(error = %err, items = indices.len(), "bulk chunk rejected");
+    let status = err.status_code();
+    let error = BulkItemError::from(&err);
+    results.extend(
+        indices
+            .into_iter()
+            .map(|index| BulkItemResult::failed(index, status, error.clone())),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{self, Body};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct NewTag {
+        #[validate(length(min = 2))]
+        name: String,
+    }
+
+    async fn extract(body: &str) -> Result<BulkJson<NewTag>, ApiError> {
+        let req = Request::builder()
+            .uri("/")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        BulkJson::from_request(req, &()).await
+    }
+
+    #[tokio::test]
+    async fn extractor_validates_items_individually() {
+        let input = extract(r#"[{"name":"ok"},{"name":"x"},{"nom":"y"},{"name":"fine"}]"#)
+            .await
+            .unwrap();
+
+        assert_eq!(input.len(), 4);
+        let indices: Vec<_> = input.items().iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, [0, 3]);
+        assert_eq!(input.rejected()[0].0, 1);
+        assert_eq!(input.rejected()[0].1.code, "VALIDATION_ERROR");
+        assert_eq!(input.rejected()[0].1.errors[0].field, "name");
+        assert_eq!(input.rejected()[1].1.code, "INVALID_JSON");
+    }
+
+    #[tokio::test]
+    async fn extractor_rejects_non_array_bodies() {
+        assert!(matches!(
+            extract(r#"{"name":"ok"}"#).await,
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn writes_in_chunks_and_reports_failed_chunks() {
+        let input =
+            extract(r#"[{"name":"aa"},{"name":"bb"},{"name":"x"},{"name":"fail"},{"name":"ee"}]"#)
+                .await
+                .unwrap();
+
+        let response = BulkWriter::new()
+            .chunk_size(2)
+            .run(input, &|items: Vec<NewTag>| async move {
+                if items.iter().any(|t| t.name == "fail") {
+                    return Err(ApiError::BadRequest("duplicate".to_string()));
+                }
+                Ok(items.into_iter().map(|t| t.name).collect())
+            })
+            .await;
+
+        assert_eq!(response.succeeded, 2);
+        assert_eq!(response.failed, 3);
+        let statuses: Vec<_> = response.results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, [200, 200, 422, 400, 400]);
+        assert_eq!(response.results[1].data.as_deref(), Some("bb"));
+        assert_eq!(
+            response.results[4].error.as_ref().unwrap().code,
+            "BAD_REQUEST"
+        );
+
+        let resp = response.into_response();
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let bytes = body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(json["results"][0]["data"], "aa");
+        assert!(json["results"][0].get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn all_successful_items_respond_ok() {
+        let input = extract(r#"[{"name":"aa"}]"#).await.unwrap();
+        let response = BulkWriter::new()
+            .run(input, &|items: Vec<NewTag>| async move {
+                Ok(vec![(); items.len()])
+            })
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn mismatched_sink_results_fail_the_chunk() {
+        let input = extract(r#"[{"name":"aa"},{"name":"bb"}]"#).await.unwrap();
+        let response = BulkWriter::new()
+            .run(input, &|_items: Vec<NewTag>| async move { Ok(vec![1]) })
+            .await;
+        assert_eq!(response.failed, 2);
+        assert_eq!(response.results[0].status, 500);
+    }
+}
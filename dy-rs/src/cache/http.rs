@@ -0,0 +1,458 @@
+//! Server-side HTTP response caching
+//!
+//! [`HttpCache`] stores successful `GET` responses in a [`Cache`] and replays
+//! them until they expire, so expensive endpoints don't need a caching proxy
+//! in front:
+//!
+//! ```rust,ignore
+//! use dy_rs::cache::{HttpCache, LruCache, VaryBy};
+//!
+//! let cache = HttpCache::new(LruCache::new())
+//!     .ttl(Duration::from_secs(30))
+//!     .route("/reports", Duration::from_secs(300))
+//!     .route("/reports/live", Duration::ZERO) // never cached
+//!     .vary_by(VaryBy::Query);
+//!
+//! let app = Router::new()
+//!     .route("/reports", get(list_reports))
+//!     .route("/reports/live", get(live_report))
+//!     .layer(cache);
+//! ```
+//!
+//! Responses are keyed by path, plus whatever [`VaryBy`] adds. Requests with
+//! an `Authorization` header bypass the cache unless it varies by
+//! [`VaryBy::User`], so one user's data is never served to another.
+//! Responses marked `Cache-Control: no-store` or `private` by the handler
+//! aren't stored. Replayed responses carry `X-Cache: HIT` and an `Age`, and
+//! every cacheable response gets a matching `Cache-Control: max-age`.
+//!
+//! Use a [`RedisCache`](super::RedisCache) to share entries between
+//! instances.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header},
+    response::Response,
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tower::{Layer, Service};
+
+use super::{Cache, CacheExt, SharedCache};
+
+/// Header telling whether a response came from the cache
+pub const X_CACHE: HeaderName = HeaderName::from_static("x-cache");
+
+/// A stored response, kept under `http:{key}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "base64_bytes")]
+    pub body: Bytes,
+    /// Seconds since the Unix epoch
+    pub stored_at: u64,
+}
+
+mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<S: serde::Serializer>(
+        bytes: &Bytes,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Bytes, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD
+            .decode(text)
+            .map(Bytes::from)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// What else besides the path distinguishes cached responses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VaryBy {
+    /// The query string
+    Query,
+    /// The caller, by a hash of the `Authorization` header
+    User,
+    /// A request header, e.g. `Accept-Language`
+    Header(HeaderName),
+}
+
+/// Layer caching `GET` responses
+#[derive(Clone)]
+pub struct HttpCache {
+    cache: SharedCache,
+    ttl: Duration,
+    routes: Arc<Vec<(String, Duration)>>,
+    vary: Arc<Vec<VaryBy>>,
+    max_body: usize,
+}
+
+impl HttpCache {
+    /// Cache responses in `cache` for 60 seconds
+    pub fn new(cache: impl Cache) -> Self {
+        Self {
+            cache: SharedCache::new(cache),
+            ttl: Duration::from_secs(60),
+            routes: Arc::default(),
+            vary: Arc::default(),
+            max_body: 1024 * 1024,
+        }
+    }
+
+    /// How long responses are cached unless a [`route`](Self::route)
+    /// says otherwise (default: 60 seconds)
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Cache responses under the path `prefix` for `ttl`; `Duration::ZERO`
+    /// turns caching off. The longest matching prefix wins.
+    pub fn route(mut self, prefix: impl Into<String>, ttl: Duration) -> Self {
+        Arc::make_mut(&mut self.routes).push((prefix.into(), ttl));
+        self
+    }
+
+    /// Key responses by `vary` too
+    pub fn vary_by(mut self, vary: VaryBy) -> Self {
+        let list = Arc::make_mut(&mut self.vary);
+        if !list.contains(&vary) {
+            list.push(vary);
+        }
+        self
+    }
+
+    /// Largest body stored, in bytes (default: 1 MiB)
+    pub fn max_body(mut self, bytes: usize) -> Self {
+        self.max_body = bytes;
+        self
+    }
+
+    fn ttl_for(&self, path: &str) -> Duration {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str()).is_some_and(|rest| {
+                    rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/')
+                })
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.ttl, |(_, ttl)| *ttl)
+    }
+
+    /// The cache key for `request`, or `None` if it must not be cached
+    fn key(&self, request: &Request) -> Option<String> {
+        let headers = request.headers();
+        let varies_by_user = self.vary.contains(&VaryBy::User);
+        if headers.contains_key(header::AUTHORIZATION) && !varies_by_user {
+            return None;
+        }
+
+        let uri = request.uri();
+        let mut key = format!("http:{}", uri.path());
+        for vary in self.vary.iter() {
+            match vary {
+                VaryBy::Query => {
+                    key.push('?');
+                    key.push_str(uri.query().unwrap_or_default());
+                }
+                VaryBy::User => {
+                    let auth = headers
+                        .get(header::AUTHORIZATION)
+                        .map(|value| value.as_bytes())
+                        .unwrap_or_default();
+                    let digest = hex::encode(Sha256::digest(auth));
+                    key.push_str("|user=");
+                    key.push_str(&digest[..16]);
+                }
+                VaryBy::Header(name) => {
+                    let value = headers
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default();
+                    key.push_str(&format!("|{name}={value}"));
+                }
+            }
+        }
+        Some(key)
+    }
+
+    fn cache_control(&self, ttl: Duration) -> HeaderValue {
+        let scope = if self.vary.contains(&VaryBy::User) {
+            "private"
+        } else {
+            "public"
+        };
+        HeaderValue::try_from(format!("{scope}, max-age={}", ttl.as_secs()))
+            .expect("valid Cache-Control")
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Whether the handler asked for the response not to be shared
+fn forbids_storing(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| {
+            let directive = directive.trim();
+            directive.eq_ignore_ascii_case("no-store")
+                || directive.eq_ignore_ascii_case("private")
+                || directive.eq_ignore_ascii_case("no-cache")
+        })
+}
+
+fn replay(cached: CachedResponse, cache_control: HeaderValue) -> Response {
+    let mut response = Response::new(Body::from(cached.body));
+    *response.status_mut() = StatusCode::from_u16(cached.status).unwrap_or(StatusCode::OK);
+    let headers = response.headers_mut();
+    for (name, value) in &cached.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::try_from(value.as_str()),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    let age = now_secs().saturating_sub(cached.stored_at);
+    headers.insert(header::AGE, HeaderValue::from(age));
+    headers.insert(header::CACHE_CONTROL, cache_control);
+    headers.insert(X_CACHE, HeaderValue::from_static("HIT"));
+    response
+}
+
+impl<S> Layer<S> for HttpCache {
+    type Service = HttpCacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HttpCacheService {
+            inner,
+            cache: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpCacheService<S> {
+    inner: S,
+    cache: HttpCache,
+}
+
+impl<S> Service<Request> for HttpCacheService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let ttl = self.cache.ttl_for(req.uri().path());
+        let key = (req.method() == Method::GET && !ttl.is_zero())
+            .then(|| self.cache.key(&req))
+            .flatten();
+        let Some(key) = key else {
+            return Box::pin(self.inner.call(req));
+        };
+
+        // The ready service goes into the future; a fresh clone stays here
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let cache = self.cache.clone();
+
+        Box::pin(async move {
+            match cache.cache.get::<CachedResponse>(&key).await {
+                Ok(Some(cached)) => return Ok(replay(cached, cache.cache_control(ttl))),
+                Ok(None) => {}
+                Err(err) => tracing::warn!(error = %err, key, "http cache read failed"),
+            }
+
+            let response = inner.call(req).await?;
+            if response.status() != StatusCode::OK || forbids_storing(response.headers()) {
+                return Ok(response);
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let body = match to_bytes(body, usize::MAX).await {
+                Ok(body) => body,
+                Err(err) => {
+                    tracing::warn!(error = %err, "failed to buffer response for caching");
+                    parts.status = StatusCode::INTERNAL_SERVER_ERROR;
+                    return Ok(Response::from_parts(parts, Body::empty()));
+                }
+            };
+            parts
+                .headers
+                .insert(header::CACHE_CONTROL, cache.cache_control(ttl));
+            parts
+                .headers
+                .insert(X_CACHE, HeaderValue::from_static("MISS"));
+
+            if body.len() <= cache.max_body {
+                let stored = CachedResponse {
+                    status: parts.status.as_u16(),
+                    headers: parts
+                        .headers
+                        .iter()
+                        .filter(|(name, _)| {
+                            ![header::CACHE_CONTROL, header::SET_COOKIE, X_CACHE].contains(name)
+                        })
+                        .filter_map(|(name, value)| {
+                            Some((name.to_string(), value.to_str().ok()?.to_string()))
+                        })
+                        .collect(),
+                    body: body.clone(),
+                    stored_at: now_secs(),
+                };
+                if let Err(err) = cache.cache.set(&key, &stored, Some(ttl)).await {
+                    tracing::warn!(error = %err, key, "http cache write failed");
+                }
+            }
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::LruCache;
+    use axum::{Router, extract::Query, routing::get};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    fn app(cache: HttpCache, calls: Arc<AtomicUsize>) -> Router {
+        let count = move || {
+            let calls = calls.clone();
+            async move { calls.fetch_add(1, Ordering::SeqCst).to_string() }
+        };
+        Router::new()
+            .route("/reports", get(count.clone()))
+            .route("/reports/live", get(count.clone()))
+            .route(
+                "/search",
+                get(|Query(q): Query<HashMap<String, String>>| async move {
+                    q.get("q").cloned().unwrap_or_default()
+                }),
+            )
+            .route(
+                "/private",
+                get(move || {
+                    let count = count.clone();
+                    async move { ([(header::CACHE_CONTROL, "private")], count().await) }
+                }),
+            )
+            .layer(cache)
+    }
+
+    async fn send(app: &Router, uri: &str, auth: Option<&str>) -> (Option<String>, String) {
+        let mut request = Request::builder().uri(uri);
+        if let Some(auth) = auth {
+            request = request.header(header::AUTHORIZATION, auth);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let x_cache = response
+            .headers()
+            .get(X_CACHE)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (x_cache, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn replays_until_expiry_with_per_route_ttls() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache = HttpCache::new(LruCache::new())
+            .route("/reports/live", Duration::ZERO)
+            .route("/reports", Duration::from_millis(50));
+        let app = app(cache, calls.clone());
+
+        assert_eq!(
+            send(&app, "/reports", None).await,
+            (Some("MISS".into()), "0".into())
+        );
+        assert_eq!(
+            send(&app, "/reports", None).await,
+            (Some("HIT".into()), "0".into())
+        );
+        assert_eq!(send(&app, "/reports/live", None).await, (None, "1".into()));
+        assert_eq!(send(&app, "/reports/live", None).await, (None, "2".into()));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(
+            send(&app, "/reports", None).await,
+            (Some("MISS".into()), "3".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_vary_by_query_and_user() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let plain = app(HttpCache::new(LruCache::new()), calls.clone());
+        // Without VaryBy::Query the first query's answer is replayed
+        send(&plain, "/search?q=a", None).await;
+        assert_eq!(send(&plain, "/search?q=b", None).await.1, "a");
+        // Authorized requests skip a cache that doesn't vary by user
+        send(&plain, "/reports", Some("Bearer alice")).await;
+        assert_eq!(send(&plain, "/reports", Some("Bearer bob")).await.1, "1");
+
+        let varied = app(
+            HttpCache::new(LruCache::new())
+                .vary_by(VaryBy::Query)
+                .vary_by(VaryBy::User),
+            calls.clone(),
+        );
+        send(&varied, "/search?q=a", None).await;
+        assert_eq!(send(&varied, "/search?q=b", None).await.1, "b");
+        let alice = send(&varied, "/reports", Some("Bearer alice")).await.1;
+        assert_eq!(send(&varied, "/reports", Some("Bearer bob")).await.1, "3");
+        assert_eq!(
+            send(&varied, "/reports", Some("Bearer alice")).await.1,
+            alice
+        );
+    }
+
+    #[tokio::test]
+    async fn private_responses_are_not_stored() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(HttpCache::new(LruCache::new()), calls);
+        assert_eq!(send(&app, "/private", None).await.1, "0");
+        assert_eq!(send(&app, "/private", None).await.1, "1");
+    }
+}
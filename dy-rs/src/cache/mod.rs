@@ -0,0 +1,468 @@
+//! Caching
+//!
+//! [`Cache`] is a key/value store with expiry that handlers, services and
+//! the framework's own layers share. Values are stored as bytes; [`CacheExt`]
+//! adds typed access through serde:
+//!
+//! ```rust,ignore
+//! use dy_rs::cache::{CacheExt, LruCache, SharedCache};
+//!
+//! async fn get_rates(cache: SharedCache) -> ApiResult<Rates> {
+//!     let rates = cache
+//!         .get_or_set_with("fx:rates", Some(Duration::from_secs(600)), fetch_rates)
+//!         .await?;
+//!     Ok(Json(rates))
+//! }
+//!
+//! let cache = SharedCache::new(LruCache::new().max_entries(50_000));
+//! App::new()
+//!     .auto_configure()
+//!     .with_cache(cache.clone())
+//!     .mount(reports.layer(HttpCache::new(cache)));
+//! ```
+//!
+//! [`LruCache`] keeps values in the process, evicting the least recently used
+//! when full. [`RedisCache`] shares them between instances; like the CDN
+//! purgers, it only builds commands and leaves sending them to a
+//! [`RedisTransport`], usually a closure around the app's Redis client:
+//!
+//! ```rust,ignore
+//! let cache = RedisCache::new(move |args: Vec<Vec<u8>>| {
+//!     let mut conn = conn.clone();
+//!     async move {
+//!         let mut cmd = redis::cmd(std::str::from_utf8(&args[0]).unwrap());
+//!         for arg in &args[1..] {
+//!             cmd.arg(arg.as_slice());
+//!         }
+//!         cmd.query_async(&mut conn)
+//!             .await
+//!             .map_err(ApiError::external)
+//!     }
+//! });
+//! ```
+
+mod http;
+
+pub use http::{CachedResponse, HttpCache, HttpCacheService, VaryBy, X_CACHE};
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::error::ApiError;
+
+/// A key/value store with expiry
+#[async_trait]
+pub trait Cache: Send + Sync + 'static {
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, ApiError>;
+
+    /// Store `value` under `key`, for `ttl` or until evicted
+    async fn set_bytes(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), ApiError>;
+
+    async fn delete(&self, key: &str) -> Result<(), ApiError>;
+}
+
+/// Typed access to any [`Cache`], with values stored as JSON
+#[async_trait]
+pub trait CacheExt: Cache {
+    /// The value under `key`; a value that no longer deserializes as `T`
+    /// counts as missing
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, ApiError> {
+        let Some(bytes) = self.get_bytes(key).await? else {
+            return Ok(None);
+        };
+        match serde_json::from_slice(&bytes) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => {
+                tracing::debug!(key, error = %err, "ignoring undecodable cache entry");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn set<T: Serialize + Sync>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> Result<(), ApiError> {
+        let bytes = serde_json::to_vec(value).map_err(|e| {
+            ApiError::InternalServerError(format!("Failed to serialize cache value: {e}"))
+        })?;
+        self.set_bytes(key, bytes, ttl).await
+    }
+
+    /// The value under `key`, or the result of `load` stored for `ttl`
+    async fn get_or_set_with<T, F, Fut>(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+        load: F,
+    ) -> Result<T, ApiError>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<T, ApiError>> + Send,
+    {
+        if let Some(value) = self.get(key).await? {
+            return Ok(value);
+        }
+        let value = load().await?;
+        self.set(key, &value, ttl).await?;
+        Ok(value)
+    }
+}
+
+impl<C: Cache + ?Sized> CacheExt for C {}
+
+/// A cloneable handle to the app's [`Cache`]
+///
+/// Added to requests by [`App::with_cache`](crate::App::with_cache), and
+/// usable as an extractor or as a field of the app state.
+#[derive(Clone)]
+pub struct SharedCache(Arc<dyn Cache>);
+
+impl SharedCache {
+    pub fn new(cache: impl Cache) -> Self {
+        Self(Arc::new(cache))
+    }
+}
+
+#[async_trait]
+impl Cache for SharedCache {
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        self.0.get_bytes(key).await
+    }
+
+    async fn set_bytes(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), ApiError> {
+        self.0.set_bytes(key, value, ttl).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ApiError> {
+        self.0.delete(key).await
+    }
+}
+
+/// Rejects with `500` when no cache was added with `App::with_cache`
+impl<S> FromRequestParts<S> for SharedCache
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Self>().cloned().ok_or_else(|| {
+            ApiError::InternalServerError(
+                "SharedCache needs a cache added with App::with_cache".to_string(),
+            )
+        })
+    }
+}
+
+/// In-process cache evicting the least recently used entry when full
+#[derive(Clone)]
+pub struct LruCache {
+    max_entries: usize,
+    inner: Arc<Mutex<Lru>>,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, LruEntry>,
+    clock: u64,
+}
+
+struct LruEntry {
+    value: Vec<u8>,
+    expires: Option<Instant>,
+    used: u64,
+}
+
+impl LruCache {
+    /// A cache of at most 10,000 entries
+    pub fn new() -> Self {
+        Self {
+            max_entries: 10_000,
+            inner: Arc::default(),
+        }
+    }
+
+    /// Most entries kept at once (default: 10,000)
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Number of entries, including expired ones not yet dropped
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget every entry
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+}
+
+impl Default for LruCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Cache for LruCache {
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        let mut lru = self.inner.lock().unwrap();
+        lru.clock += 1;
+        let clock = lru.clock;
+        let Some(entry) = lru.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        if entry
+            .expires
+            .is_some_and(|expires| expires <= Instant::now())
+        {
+            lru.entries.remove(key);
+            return Ok(None);
+        }
+        entry.used = clock;
+        Ok(Some(entry.value.clone()))
+    }
+
+    async fn set_bytes(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), ApiError> {
+        let mut lru = self.inner.lock().unwrap();
+        let now = Instant::now();
+        if !lru.entries.contains_key(key) && lru.entries.len() >= self.max_entries {
+            lru.entries
+                .retain(|_, entry| entry.expires.is_none_or(|expires| expires > now));
+            if lru.entries.len() >= self.max_entries {
+                let oldest = lru
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    lru.entries.remove(&oldest);
+                }
+            }
+        }
+        lru.clock += 1;
+        let used = lru.clock;
+        lru.entries.insert(
+            key.to_string(),
+            LruEntry {
+                value,
+                expires: ttl.map(|ttl| now + ttl),
+                used,
+            },
+        );
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ApiError> {
+        self.inner.lock().unwrap().entries.remove(key);
+        Ok(())
+    }
+}
+
+/// Runs a Redis command and returns its reply: bulk strings as bytes,
+/// integers as their decimal text, nil as `None`
+#[async_trait]
+pub trait RedisTransport: Send + Sync + 'static {
+    async fn command(&self, args: Vec<Vec<u8>>) -> Result<Option<Vec<u8>>, ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> RedisTransport for F
+where
+    F: Fn(Vec<Vec<u8>>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Option<Vec<u8>>, ApiError>> + Send,
+{
+    async fn command(&self, args: Vec<Vec<u8>>) -> Result<Option<Vec<u8>>, ApiError> {
+        self(args).await
+    }
+}
+
+/// Cache in Redis, expiring entries with `PX`
+pub struct RedisCache<T> {
+    prefix: String,
+    transport: T,
+}
+
+impl<T: RedisTransport> RedisCache<T> {
+    /// Keys are prefixed with `dy:`
+    pub fn new(transport: T) -> Self {
+        Self {
+            prefix: "dy:".to_string(),
+            transport,
+        }
+    }
+
+    /// Prefix keys with `prefix` instead, e.g. to share a Redis database
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The `GET` command reading `key`
+    pub fn get_command(&self, key: &str) -> Vec<Vec<u8>> {
+        vec![b"GET".to_vec(), self.redis_key(key)]
+    }
+
+    /// The `SET` command storing `value` under `key`
+    pub fn set_command(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Vec<Vec<u8>> {
+        let mut command = vec![b"SET".to_vec(), self.redis_key(key), value];
+        if let Some(ttl) = ttl {
+            command.push(b"PX".to_vec());
+            command.push(ttl.as_millis().max(1).to_string().into_bytes());
+        }
+        command
+    }
+
+    /// The `DEL` command removing `key`
+    pub fn delete_command(&self, key: &str) -> Vec<Vec<u8>> {
+        vec![b"DEL".to_vec(), self.redis_key(key)]
+    }
+
+    fn redis_key(&self, key: &str) -> Vec<u8> {
+        format!("{}{}", self.prefix, key).into_bytes()
+    }
+}
+
+#[async_trait]
+impl<T: RedisTransport> Cache for RedisCache<T> {
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        self.transport.command(self.get_command(key)).await
+    }
+
+    async fn set_bytes(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), ApiError> {
+        self.transport
+            .command(self.set_command(key, value, ttl))
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ApiError> {
+        self.transport.command(self.delete_command(key)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Rates {
+        usd: f64,
+    }
+
+    #[tokio::test]
+    async fn typed_values_expire() {
+        let cache = LruCache::new();
+        cache
+            .set("fx", &Rates { usd: 1.1 }, Some(Duration::from_millis(30)))
+            .await
+            .unwrap();
+        assert_eq!(cache.get("fx").await.unwrap(), Some(Rates { usd: 1.1 }));
+        // A different shape is a miss, not an error
+        assert_eq!(cache.get::<Vec<u8>>("fx").await.unwrap(), None);
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(cache.get::<Rates>("fx").await.unwrap(), None);
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_entries_are_evicted() {
+        let cache = LruCache::new().max_entries(2);
+        cache.set("a", &1, None).await.unwrap();
+        cache.set("b", &2, None).await.unwrap();
+        cache.get::<i32>("a").await.unwrap();
+        cache.set("c", &3, None).await.unwrap();
+
+        assert_eq!(cache.get::<i32>("a").await.unwrap(), Some(1));
+        assert_eq!(cache.get::<i32>("b").await.unwrap(), None);
+        assert_eq!(cache.get::<i32>("c").await.unwrap(), Some(3));
+
+        cache.delete("a").await.unwrap();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn get_or_set_with_loads_once() {
+        let cache = SharedCache::new(LruCache::new());
+        let load = |usd| move || async move { Ok(Rates { usd }) };
+        let first = cache.get_or_set_with("fx", None, load(1.0)).await.unwrap();
+        let second = cache.get_or_set_with("fx", None, load(2.0)).await.unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn redis_cache_sends_commands_through_the_transport() {
+        let store: Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>> = Arc::default();
+        let redis = {
+            let store = store.clone();
+            move |args: Vec<Vec<u8>>| {
+                let store = store.clone();
+                async move {
+                    let mut store = store.lock().unwrap();
+                    Ok(match args[0].as_slice() {
+                        b"GET" => store.get(&args[1]).cloned(),
+                        b"SET" => {
+                            assert_eq!(args.get(3).map(Vec::as_slice), Some(b"PX".as_slice()));
+                            store.insert(args[1].clone(), args[2].clone());
+                            None
+                        }
+                        b"DEL" => store.remove(&args[1]).map(|_| b"1".to_vec()),
+                        _ => unreachable!(),
+                    })
+                }
+            }
+        };
+        let cache = RedisCache::new(redis).prefix("test:");
+
+        cache
+            .set("fx", &Rates { usd: 1.1 }, Some(Duration::from_secs(5)))
+            .await
+            .unwrap();
+        assert!(store.lock().unwrap().contains_key(b"test:fx".as_slice()));
+        assert_eq!(cache.get("fx").await.unwrap(), Some(Rates { usd: 1.1 }));
+
+        cache.delete("fx").await.unwrap();
+        assert_eq!(cache.get::<Rates>("fx").await.unwrap(), None);
+    }
+}
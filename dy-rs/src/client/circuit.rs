@@ -0,0 +1,135 @@
+//! Circuit breaking per host
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// When calls to a host are cut short, the `[http_client.circuit_breaker]`
+/// config section
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Failures in a row that open the circuit; `0` never opens it
+    pub failure_threshold: u32,
+    /// How long an open circuit fails calls before letting one through
+    pub open_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// One trial call is on its way
+    HalfOpen,
+}
+
+/// Circuits of every host a client calls
+#[derive(Debug)]
+pub(crate) struct Circuits {
+    config: CircuitBreakerConfig,
+    states: Mutex<HashMap<String, State>>,
+}
+
+impl Circuits {
+    pub(crate) fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a call to `host` may go out, or how long its circuit stays
+    /// open
+    pub(crate) fn allow(&self, host: &str, now: Instant) -> Result<(), Duration> {
+        let mut states = self.states.lock().unwrap();
+        let state = states
+            .entry(host.to_string())
+            .or_insert(State::Closed { failures: 0 });
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now >= until => {
+                *state = State::HalfOpen;
+                Ok(())
+            }
+            State::Open { until } => Err(until - now),
+            State::HalfOpen => Err(Duration::from_secs(1)),
+        }
+    }
+
+    pub(crate) fn succeeded(&self, host: &str) {
+        self.states
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), State::Closed { failures: 0 });
+    }
+
+    pub(crate) fn failed(&self, host: &str, now: Instant) {
+        let mut states = self.states.lock().unwrap();
+        let state = states
+            .entry(host.to_string())
+            .or_insert(State::Closed { failures: 0 });
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            State::Open { .. } | State::HalfOpen => self.config.failure_threshold,
+        };
+        *state = if self.config.failure_threshold > 0 && failures >= self.config.failure_threshold {
+            tracing::warn!(host, failures, "Circuit opened");
+            State::Open {
+                until: now + Duration::from_secs(self.config.open_secs),
+            }
+        } else {
+            State::Closed { failures }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circuits_open_after_failures_and_close_after_a_trial() {
+        let circuits = Circuits::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            open_secs: 10,
+        });
+        let start = Instant::now();
+
+        circuits.failed("api.example.com", start);
+        assert!(circuits.allow("api.example.com", start).is_ok());
+        circuits.failed("api.example.com", start);
+        assert_eq!(
+            circuits.allow("api.example.com", start + Duration::from_secs(4)),
+            Err(Duration::from_secs(6))
+        );
+        assert!(circuits.allow("other.example.com", start).is_ok());
+
+        let later = start + Duration::from_secs(10);
+        assert!(circuits.allow("api.example.com", later).is_ok());
+        assert!(circuits.allow("api.example.com", later).is_err());
+        circuits.failed("api.example.com", later);
+        assert!(circuits.allow("api.example.com", later).is_err());
+
+        let trial = later + Duration::from_secs(10);
+        assert!(circuits.allow("api.example.com", trial).is_ok());
+        circuits.succeeded("api.example.com");
+        assert!(circuits.allow("api.example.com", trial).is_ok());
+    }
+}
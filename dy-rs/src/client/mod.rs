@@ -0,0 +1,642 @@
+//! Outbound HTTP calls with the same observability as inbound ones
+//!
+//! An [`HttpClient`] sends requests through an [`HttpTransport`], typically a
+//! closure around the app's HTTP client (reqwest, hyper, ...) built from the
+//! `[http_client]` settings, and adds:
+//!
+//! - the calling request's `x-request-id`, and a W3C `traceparent` and
+//!   `tracestate` continuing its [trace](crate::trace_context) (or starting
+//!   one), when taken as an extractor;
+//! - a tracing span per call, and a log line with its status and duration;
+//! - a timeout per attempt, and retries with exponential backoff of
+//!   idempotent requests after transport errors, timeouts, `429`, `502`,
+//!   `503` and `504`;
+//! - a circuit breaker per host, failing calls fast with a `503` after
+//!   repeated failures.
+//!
+//! ```toml
+//! [http_client]
+//! timeout_ms = 5000
+//! connect_timeout_ms = 1000
+//! proxy = "http://proxy.internal:3128"
+//! retry = { max_attempts = 3, initial_delay_ms = 100, max_delay_ms = 2000 }
+//! circuit_breaker = { failure_threshold = 5, open_secs = 30 }
+//! ```
+//!
+//! ```rust,ignore
+//! let settings = app.config().unwrap().http_client.clone();
+//! let http = reqwest::Client::builder()
+//!     .connect_timeout(settings.connect_timeout())
+//!     .proxy(reqwest::Proxy::all(settings.proxy.as_deref().unwrap())?)
+//!     .build()?;
+//! let app = app.with_http_client(move |request: http::Request<Bytes>| {
+//!     let http = http.clone();
+//!     async move {
+//!         let response = http.execute(request.try_into().map_err(ApiError::external)?).await.map_err(ApiError::external)?;
+//!         let mut builder = http::Response::builder().status(response.status());
+//!         *builder.headers_mut().unwrap() = response.headers().clone();
+//!         builder.body(response.bytes().await.map_err(ApiError::external)?).map_err(ApiError::external)
+//!     }
+//! });
+//!
+//! async fn shipping_quote(client: HttpClient, Json(order): Json<Order>) -> ApiResult<Quote> {
+//!     let quote = client.post("https://shipping.internal/quotes").json(&order).send().await?;
+//!     Ok(Json(quote.error_for_status()?.json()?))
+//! }
+//! ```
+
+mod circuit;
+
+pub use circuit::CircuitBreakerConfig;
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::FromRequestParts,
+    http::{
+        self, HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+        header::{CONTENT_TYPE, USER_AGENT},
+        request::Parts,
+    },
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tracing::Instrument;
+
+pub use crate::trace_context::TRACEPARENT_HEADER;
+use crate::{audit::REQUEST_ID_HEADER, error::ApiError, trace_context::TraceContext};
+use circuit::Circuits;
+
+/// Outbound HTTP settings, the `[http_client]` config section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpClientConfig {
+    /// Longest one attempt may take
+    pub timeout_ms: u64,
+    /// Longest connecting may take, for the transport's client
+    pub connect_timeout_ms: u64,
+    /// Proxy for the transport's client, e.g. `http://proxy.internal:3128`
+    pub proxy: Option<String>,
+    /// Hosts the transport's client reaches without the proxy
+    pub no_proxy: Vec<String>,
+    /// `User-Agent` of requests that don't set one
+    pub user_agent: String,
+    pub retry: ClientRetryConfig,
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 10_000,
+            connect_timeout_ms: 2_000,
+            proxy: None,
+            no_proxy: Vec::new(),
+            user_agent: concat!("dy-rs/", env!("CARGO_PKG_VERSION")).to_string(),
+            retry: ClientRetryConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+        }
+    }
+}
+
+impl HttpClientConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+}
+
+/// How often and how long failed calls are retried, the
+/// `[http_client.retry]` config section
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientRetryConfig {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    /// Wait before the second attempt; doubled for each one after
+    pub initial_delay_ms: u64,
+    /// Longest wait between attempts
+    pub max_delay_ms: u64,
+}
+
+impl Default for ClientRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay_ms: 100,
+            max_delay_ms: 2_000,
+        }
+    }
+}
+
+impl ClientRetryConfig {
+    /// Wait after failed attempt number `attempt` (1-based)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.initial_delay_ms.saturating_mul(factor);
+        Duration::from_millis(delay.min(self.max_delay_ms))
+    }
+}
+
+/// Sends an HTTP request and returns the response
+#[async_trait]
+pub trait HttpTransport: Send + Sync + 'static {
+    async fn send(&self, request: http::Request<Bytes>) -> Result<http::Response<Bytes>, ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> HttpTransport for F
+where
+    F: Fn(http::Request<Bytes>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<http::Response<Bytes>, ApiError>> + Send,
+{
+    async fn send(&self, request: http::Request<Bytes>) -> Result<http::Response<Bytes>, ApiError> {
+        self(request).await
+    }
+}
+
+/// Client for outbound calls; see [`client`](crate::client)
+///
+/// Added to requests by [`App::with_http_client`](crate::App::with_http_client).
+/// As an extractor, it carries the calling request's ID and trace context.
+/// Clones share the transport and circuit breakers.
+#[derive(Clone)]
+pub struct HttpClient {
+    transport: Arc<dyn HttpTransport>,
+    config: Arc<HttpClientConfig>,
+    circuits: Arc<Circuits>,
+    request_id: Option<String>,
+    trace: Option<TraceContext>,
+}
+
+impl HttpClient {
+    pub fn new(config: HttpClientConfig, transport: impl HttpTransport) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            circuits: Arc::new(Circuits::new(config.circuit_breaker)),
+            config: Arc::new(config),
+            request_id: None,
+            trace: None,
+        }
+    }
+
+    /// Send `request_id` and a child of `traceparent` with every request
+    pub fn with_context(mut self, request_id: Option<String>, traceparent: Option<String>) -> Self {
+        self.request_id = request_id;
+        self.trace = traceparent.as_deref().and_then(TraceContext::parse);
+        self
+    }
+
+    /// Send a child of `trace`, and its `tracestate`, with every request
+    pub fn with_trace(mut self, trace: TraceContext) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    pub fn config(&self) -> &HttpClientConfig {
+        &self.config
+    }
+
+    pub fn request(&self, method: Method, url: impl Into<String>) -> ClientRequest {
+        ClientRequest {
+            client: self.clone(),
+            method,
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            error: None,
+        }
+    }
+
+    pub fn get(&self, url: impl Into<String>) -> ClientRequest {
+        self.request(Method::GET, url)
+    }
+
+    pub fn post(&self, url: impl Into<String>) -> ClientRequest {
+        self.request(Method::POST, url)
+    }
+
+    pub fn put(&self, url: impl Into<String>) -> ClientRequest {
+        self.request(Method::PUT, url)
+    }
+
+    pub fn patch(&self, url: impl Into<String>) -> ClientRequest {
+        self.request(Method::PATCH, url)
+    }
+
+    pub fn delete(&self, url: impl Into<String>) -> ClientRequest {
+        self.request(Method::DELETE, url)
+    }
+
+    /// Send `request` with the client's headers, timeout, retries and
+    /// circuit breaker
+    pub async fn send(
+        &self,
+        mut request: http::Request<Bytes>,
+    ) -> Result<ClientResponse, ApiError> {
+        self.add_context(request.headers_mut());
+        let host = request.uri().host().unwrap_or_default().to_string();
+        let span = tracing::info_span!(
+            "http_client",
+            method = %request.method(),
+            host = %host,
+            path = %request.uri().path(),
+        );
+        self.send_with_retries(request, &host)
+            .instrument(span)
+            .await
+    }
+
+    fn add_context(&self, headers: &mut HeaderMap) {
+        if !headers.contains_key(USER_AGENT)
+            && let Ok(agent) = HeaderValue::from_str(&self.config.user_agent)
+        {
+            headers.insert(USER_AGENT, agent);
+        }
+        if let Some(id) = &self.request_id
+            && let Ok(id) = HeaderValue::from_str(id)
+        {
+            headers
+                .entry(HeaderName::from_static(REQUEST_ID_HEADER))
+                .or_insert(id);
+        }
+        self.trace
+            .as_ref()
+            .map(TraceContext::child)
+            .unwrap_or_else(TraceContext::new_root)
+            .inject(headers);
+    }
+
+    async fn send_with_retries(
+        &self,
+        request: http::Request<Bytes>,
+        host: &str,
+    ) -> Result<ClientResponse, ApiError> {
+        let retry = self.config.retry;
+        let idempotent = is_idempotent(request.method());
+        let mut attempt = 1;
+        loop {
+            if let Err(open_for) = self.circuits.allow(host, Instant::now()) {
+                return Err(ApiError::service_unavailable(
+                    format!("Calls to {host} are failing; circuit open"),
+                    open_for,
+                ));
+            }
+            let started = Instant::now();
+            let outcome = tokio::time::timeout(
+                self.config.timeout(),
+                self.transport.send(clone_request(&request)),
+            )
+            .await
+            .unwrap_or_else(|_| {
+                Err(ApiError::external(format!(
+                    "Request to {host} timed out after {:?}",
+                    self.config.timeout()
+                )))
+            });
+            let elapsed = started.elapsed();
+
+            let retryable = match &outcome {
+                Ok(response) => {
+                    tracing::debug!(status = %response.status(), ?elapsed, attempt, "HTTP call");
+                    if response.status().is_server_error() {
+                        self.circuits.failed(host, Instant::now());
+                    } else {
+                        self.circuits.succeeded(host);
+                    }
+                    is_retryable_status(response.status())
+                }
+                Err(err) => {
+                    tracing::warn!(error = %err, ?elapsed, attempt, "HTTP call failed");
+                    self.circuits.failed(host, Instant::now());
+                    true
+                }
+            };
+            if !(retryable && idempotent && attempt < retry.max_attempts) {
+                return outcome.map(ClientResponse);
+            }
+            let delay = retry.delay(attempt);
+            tracing::warn!(attempt, ?delay, "Retrying HTTP call");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Rejects with `500` when no transport was added with
+/// `App::with_http_client`
+impl<S> FromRequestParts<S> for HttpClient
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let client = parts.extensions.get::<Self>().cloned().ok_or_else(|| {
+            ApiError::InternalServerError(
+                "HttpClient needs a transport added with App::with_http_client".to_string(),
+            )
+        })?;
+        let request_id = parts
+            .headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let client = client.with_context(request_id, None);
+        // The request's own span if traced by the app, the caller's otherwise
+        let trace = parts
+            .extensions
+            .get::<TraceContext>()
+            .cloned()
+            .or_else(|| TraceContext::from_headers(&parts.headers));
+        Ok(match trace {
+            Some(trace) => client.with_trace(trace),
+            None => client,
+        })
+    }
+}
+
+/// A request being built by an [`HttpClient`]
+pub struct ClientRequest {
+    client: HttpClient,
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: Bytes,
+    /// Why the request can't be sent, reported by [`send`](Self::send)
+    error: Option<String>,
+}
+
+impl ClientRequest {
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Send `token` in the `Authorization` header
+    pub fn bearer_auth(self, token: &str) -> Self {
+        match HeaderValue::from_str(&format!("Bearer {token}")) {
+            Ok(value) => self.header(http::header::AUTHORIZATION, value),
+            Err(_) => Self {
+                error: Some("Invalid bearer token".to_string()),
+                ..self
+            },
+        }
+    }
+
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Send `body` as JSON
+    pub fn json(mut self, body: &impl Serialize) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(body) => {
+                self.headers
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                self.body = body.into();
+            }
+            Err(e) => self.error = Some(format!("Failed to serialize the request body: {e}")),
+        }
+        self
+    }
+
+    pub async fn send(self) -> Result<ClientResponse, ApiError> {
+        if let Some(error) = self.error {
+            return Err(ApiError::InternalServerError(error));
+        }
+        let mut request = http::Request::builder()
+            .method(self.method)
+            .uri(&self.url)
+            .body(self.body)
+            .map_err(|e| {
+                ApiError::InternalServerError(format!("Invalid request to {}: {e}", self.url))
+            })?;
+        *request.headers_mut() = self.headers;
+        self.client.send(request).await
+    }
+}
+
+/// A response received by an [`HttpClient`]
+#[derive(Debug)]
+pub struct ClientResponse(pub http::Response<Bytes>);
+
+impl ClientResponse {
+    pub fn status(&self) -> StatusCode {
+        self.0.status()
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        self.0.headers()
+    }
+
+    pub fn bytes(&self) -> &Bytes {
+        self.0.body()
+    }
+
+    /// The body parsed as JSON
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ApiError> {
+        serde_json::from_slice(self.0.body()).map_err(ApiError::external)
+    }
+
+    /// The response, or an error for a `4xx` or `5xx` status
+    pub fn error_for_status(self) -> Result<Self, ApiError> {
+        let status = self.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(ApiError::external(format!(
+                "Upstream answered {status}: {}",
+                String::from_utf8_lossy(self.bytes())
+            )));
+        }
+        Ok(self)
+    }
+
+    pub fn into_inner(self) -> http::Response<Bytes> {
+        self.0
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    [
+        Method::GET,
+        Method::HEAD,
+        Method::PUT,
+        Method::DELETE,
+        Method::OPTIONS,
+        Method::TRACE,
+    ]
+    .contains(method)
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn clone_request(request: &http::Request<Bytes>) -> http::Request<Bytes> {
+    let mut clone = http::Request::new(request.body().clone());
+    *clone.method_mut() = request.method().clone();
+    *clone.uri_mut() = request.uri().clone();
+    *clone.version_mut() = request.version();
+    *clone.headers_mut() = request.headers().clone();
+    clone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    fn config() -> HttpClientConfig {
+        HttpClientConfig {
+            timeout_ms: 50,
+            retry: ClientRetryConfig {
+                max_attempts: 3,
+                initial_delay_ms: 1,
+                max_delay_ms: 1,
+            },
+            ..HttpClientConfig::default()
+        }
+    }
+
+    fn respond(status: StatusCode, body: &'static str) -> http::Response<Bytes> {
+        http::Response::builder()
+            .status(status)
+            .body(Bytes::from_static(body.as_bytes()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_carry_the_request_id_and_trace() {
+        let seen = Arc::new(Mutex::new(HeaderMap::new()));
+        let headers = seen.clone();
+        let client = HttpClient::new(config(), move |request: http::Request<Bytes>| {
+            *headers.lock().unwrap() = request.headers().clone();
+            async { Ok(respond(StatusCode::OK, r#"{"price":12}"#)) }
+        })
+        .with_context(
+            Some("req-1".to_string()),
+            Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string()),
+        );
+
+        let response = client
+            .post("https://shipping.example.com/quotes")
+            .json(&serde_json::json!({ "weight": 2 }))
+            .send()
+            .await
+            .unwrap();
+        let quote: serde_json::Value = response.json().unwrap();
+        assert_eq!(quote["price"], 12);
+
+        let headers = seen.lock().unwrap();
+        assert_eq!(headers[REQUEST_ID_HEADER], "req-1");
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+        let traceparent = headers[TRACEPARENT_HEADER].to_str().unwrap();
+        assert!(traceparent.starts_with("00-0af7651916cd43dd8448eb211c80319c-"));
+        assert!(!traceparent.contains("b7ad6b7169203331"));
+        assert!(traceparent.ends_with("-01"));
+    }
+
+    #[tokio::test]
+    async fn idempotent_requests_are_retried() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let client = HttpClient::new(config(), move |_: http::Request<Bytes>| {
+            let call = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match call {
+                    0 => Err(ApiError::external("connection reset")),
+                    1 => Ok(respond(StatusCode::SERVICE_UNAVAILABLE, "")),
+                    _ => Ok(respond(StatusCode::OK, "ok")),
+                }
+            }
+        });
+
+        let response = client
+            .get("https://api.example.com/a")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        calls.store(1, Ordering::SeqCst);
+        let response = client
+            .post("https://api.example.com/a")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn slow_calls_time_out_and_open_the_circuit() {
+        let client = HttpClient::new(
+            HttpClientConfig {
+                retry: ClientRetryConfig {
+                    max_attempts: 1,
+                    ..ClientRetryConfig::default()
+                },
+                circuit_breaker: CircuitBreakerConfig {
+                    failure_threshold: 2,
+                    open_secs: 60,
+                },
+                ..config()
+            },
+            |_: http::Request<Bytes>| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(respond(StatusCode::OK, ""))
+            },
+        );
+
+        for _ in 0..2 {
+            let err = client
+                .get("https://slow.example.com/")
+                .send()
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("timed out"), "{err}");
+        }
+        let err = client
+            .get("https://slow.example.com/")
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::ServiceUnavailable { .. }), "{err}");
+    }
+
+    #[tokio::test]
+    async fn calls_start_a_trace_without_a_parent() {
+        let seen = Arc::new(Mutex::new(HeaderMap::new()));
+        let headers = seen.clone();
+        let client = HttpClient::new(config(), move |request: http::Request<Bytes>| {
+            *headers.lock().unwrap() = request.headers().clone();
+            async { Ok(respond(StatusCode::OK, "")) }
+        })
+        .with_context(None, Some("garbage".to_string()));
+
+        client.get("https://a.example.com/").send().await.unwrap();
+        let traceparent = seen.lock().unwrap()[TRACEPARENT_HEADER].clone();
+        let trace = TraceContext::parse(traceparent.to_str().unwrap()).unwrap();
+        assert!(trace.is_sampled());
+        assert!(!seen.lock().unwrap().contains_key("tracestate"));
+    }
+}
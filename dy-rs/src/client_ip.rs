@@ -0,0 +1,390 @@
+//! Client IP addresses behind proxies
+//!
+//! [`ClientIp`] is the address of the client that made a request. Behind a
+//! load balancer or CDN the TCP peer is the proxy, so the client is read from
+//! `Forwarded`, `X-Forwarded-For` or `X-Real-IP` instead, but only when the
+//! peer is one of the trusted proxies; otherwise anyone could pick their own
+//! address by sending those headers.
+//!
+//! The trusted proxies, and which of those headers they set, are configured
+//! in [`[server.proxy]`](crate::proxy):
+//!
+//! ```toml
+//! [server.proxy]
+//! trusted_proxies = ["10.0.0.0/8", "127.0.0.1"]
+//! ```
+//!
+//! ```rust,ignore
+//! async fn login(ip: ClientIp, Json(req): Json<LoginRequest>) -> ApiResult<Token> {
+//!     tracing::info!(client_ip = %ip, "login attempt");
+//!     // ...
+//! }
+//! ```
+//!
+//! [`App`](crate::App) serves with connection info and resolves the address
+//! with a [`ProxyLayer`](crate::proxy::ProxyLayer); routers served some other
+//! way need `into_make_service_with_connect_info::<SocketAddr>()`, and that
+//! layer or an `Extension(ProxyConfig { .. })` to trust any proxies.
+//!
+//! The `[client_ip]` section is a deprecated alias: its `trusted_proxies`
+//! are moved into `[server.proxy]` when the config loads.
+
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{HeaderMap, request::Parts},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ApiError,
+    proxy::{ForwardedHeader, ProxyConfig, RequestOrigin},
+};
+
+/// The deprecated `[client_ip]` section, moved into
+/// [`ProxyConfig`] when the config loads
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientIpConfig {
+    /// Addresses or CIDR ranges of trusted proxies, e.g. `10.0.0.0/8`
+    pub trusted_proxies: Vec<ProxyRange>,
+}
+
+/// The client address for a request from `peer` with `headers`, reading
+/// only the `honored` headers; see [`ProxyConfig::client_ip`]
+pub(crate) fn resolve(
+    trusts: impl Fn(IpAddr) -> bool,
+    honored: &[ForwardedHeader],
+    peer: IpAddr,
+    headers: &HeaderMap,
+) -> IpAddr {
+    if !trusts(peer) {
+        return peer;
+    }
+
+    let chain = honored
+        .contains(&ForwardedHeader::Forwarded)
+        .then(|| forwarded_for(headers))
+        .flatten()
+        .or_else(|| {
+            honored
+                .contains(&ForwardedHeader::XForwardedFor)
+                .then(|| x_forwarded_for(headers))
+                .flatten()
+        });
+    let Some(chain) = chain else {
+        if !honored.contains(&ForwardedHeader::XRealIp) {
+            return peer;
+        }
+        return headers
+            .get("x-real-ip")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_node(value.trim()))
+            .unwrap_or(peer);
+    };
+
+    let mut client = peer;
+    for hop in chain.iter().rev() {
+        // An unknown or obfuscated hop hides everything before it
+        let Some(ip) = hop else {
+            break;
+        };
+        client = *ip;
+        if !trusts(client) {
+            break;
+        }
+    }
+    client
+}
+
+/// An address, or a range of addresses in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyRange {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl ProxyRange {
+    /// Whether `ip` is in the range
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for ProxyRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid proxy address or range `{s}`");
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for ProxyRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl Serialize for ProxyRange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProxyRange {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// `for=` addresses from `Forwarded` headers, oldest hop first
+fn forwarded_for(headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+    let mut hops = Vec::new();
+    for value in headers.get_all("forwarded") {
+        let value = value.to_str().ok()?;
+        for element in value.split(',') {
+            let node = element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| value.trim().trim_matches('"'))
+            });
+            if let Some(node) = node {
+                hops.push(parse_node(node));
+            }
+        }
+    }
+    (!hops.is_empty()).then_some(hops)
+}
+
+/// Addresses from `X-Forwarded-For` headers, oldest hop first
+fn x_forwarded_for(headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+    let mut hops = Vec::new();
+    for value in headers.get_all("x-forwarded-for") {
+        let value = value.to_str().ok()?;
+        hops.extend(value.split(',').map(|node| parse_node(node.trim())));
+    }
+    (!hops.is_empty()).then_some(hops)
+}
+
+/// An address with an optional port: `192.0.2.1`, `192.0.2.1:80`,
+/// `2001:db8::1` or `[2001:db8::1]:80`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+/// Address of the client that made the request
+///
+/// Rejects with `500` when the server wasn't started with connection info.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+impl fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(ip) = parts
+            .extensions
+            .get::<RequestOrigin>()
+            .and_then(|origin| origin.client_ip)
+        {
+            return Ok(Self(ip));
+        }
+        let peer = match ConnectInfo::<SocketAddr>::from_request_parts(parts, state).await {
+            Ok(ConnectInfo(peer)) => peer.ip(),
+            // A process on this host, such as a sidecar proxy
+            Err(_) if crate::listen::is_unix_peer(&parts.extensions) => {
+                IpAddr::from([127, 0, 0, 1])
+            }
+            Err(_) => {
+                return Err(ApiError::InternalServerError(
+                    "ClientIp needs a server started with connect info".to_string(),
+                ));
+            }
+        };
+        let ip = match parts.extensions.get::<ProxyConfig>() {
+            Some(config) => config.client_ip(peer, &parts.headers),
+            None => peer,
+        };
+        Ok(Self(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn config(proxies: &[&str]) -> ProxyConfig {
+        ProxyConfig {
+            trusted_proxies: proxies.iter().map(|p| p.parse().unwrap()).collect(),
+            ..ProxyConfig::default()
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn ranges_match_addresses() {
+        let range: ProxyRange = "10.0.0.0/8".parse().unwrap();
+        assert!(range.contains(ip("10.1.2.3")));
+        assert!(range.contains(ip("::ffff:10.1.2.3")));
+        assert!(!range.contains(ip("11.0.0.1")));
+
+        let range: ProxyRange = "2001:db8::/32".parse().unwrap();
+        assert!(range.contains(ip("2001:db8:1::1")));
+        assert!(!range.contains(ip("10.0.0.1")));
+
+        assert!(
+            "0.0.0.0/0"
+                .parse::<ProxyRange>()
+                .unwrap()
+                .contains(ip("8.8.8.8"))
+        );
+        assert!("10.0.0.0/33".parse::<ProxyRange>().is_err());
+        assert!("proxy.internal".parse::<ProxyRange>().is_err());
+    }
+
+    #[test]
+    fn headers_from_untrusted_peers_are_ignored() {
+        let config = config(&["10.0.0.0/8"]);
+        let spoofed = headers(&[("x-forwarded-for", "1.2.3.4"), ("x-real-ip", "1.2.3.4")]);
+        assert_eq!(
+            config.client_ip(ip("203.0.113.9"), &spoofed),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn chains_are_walked_past_trusted_proxies() {
+        let config = config(&["10.0.0.0/8"]);
+        let peer = ip("10.0.0.2");
+
+        // The client made up the first entry; the first untrusted hop from
+        // the right is the one the edge proxy saw.
+        let xff = headers(&[
+            ("x-forwarded-for", "6.6.6.6, 198.51.100.7"),
+            ("x-forwarded-for", "10.0.0.1"),
+        ]);
+        assert_eq!(config.client_ip(peer, &xff), ip("198.51.100.7"));
+
+        let forwarded = headers(&[
+            (
+                "forwarded",
+                r#"for="[2001:db8::17]:4711";proto=https, for=10.0.0.1"#,
+            ),
+            ("x-forwarded-for", "6.6.6.6"),
+        ]);
+        assert_eq!(config.client_ip(peer, &forwarded), ip("2001:db8::17"));
+
+        let hidden = headers(&[("forwarded", "for=198.51.100.7, for=_hidden, for=10.0.0.1")]);
+        assert_eq!(config.client_ip(peer, &hidden), ip("10.0.0.1"));
+
+        let real_ip = headers(&[("x-real-ip", "198.51.100.7")]);
+        assert_eq!(config.client_ip(peer, &real_ip), ip("198.51.100.7"));
+        assert_eq!(config.client_ip(peer, &HeaderMap::new()), peer);
+    }
+
+    #[tokio::test]
+    async fn extractor_uses_the_configured_proxies() {
+        use axum::{
+            Extension, Router, body::Body, extract::connect_info::MockConnectInfo, http::Request,
+            routing::get,
+        };
+        use tower::ServiceExt;
+
+        let app = |config: ProxyConfig| {
+            Router::new()
+                .route("/", get(|ip: ClientIp| async move { ip.to_string() }))
+                .layer(Extension(config))
+                .layer(MockConnectInfo(SocketAddr::from(([10, 0, 0, 2], 4000))))
+        };
+        let request = || {
+            Request::builder()
+                .header("x-forwarded-for", "198.51.100.7")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let body = |res: axum::response::Response| async move {
+            let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        };
+
+        let res = app(config(&["10.0.0.0/8"]))
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(body(res).await, "198.51.100.7");
+        let res = app(ProxyConfig::default())
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(body(res).await, "10.0.0.2");
+
+        // Only the headers listed in `server.proxy.headers` are read
+        let real_ip_only = ProxyConfig {
+            headers: vec![ForwardedHeader::XRealIp],
+            ..config(&["10.0.0.0/8"])
+        };
+        let res = app(real_ip_only).oneshot(request()).await.unwrap();
+        assert_eq!(body(res).await, "10.0.0.2");
+    }
+}
@@ -0,0 +1,467 @@
+//! Primitives for soft real-time collaborative editing
+//!
+//! A [`CollabHub`] keeps one channel per document. Clients join a channel with
+//! their [`Presence`], receive every [`CollabEvent`] on it, and submit
+//! [`Update`]s tagged with the [`VersionVector`] they were based on. The hub
+//! orders updates, and when one was made without seeing changes from other
+//! sites it asks a [`ConflictHandler`] what to apply. Payloads are opaque JSON,
+//! so the hub works with operational transforms and CRDTs alike: transform or
+//! merge in the handler, or accept everything and merge on the clients.
+//!
+//! [`collab_routes`] exposes a hub over a WebSocket per client (with the `ws`
+//! feature, on by default), and over server-sent events plus plain requests
+//! for clients that can't open one. Other transports only need
+//! [`CollabHub::join`] and [`CollabHub::submit`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use dy_rs::collab::{CollabHub, collab_routes};
+//!
+//! App::new()
+//!     .auto_configure()
+//!     .mount(collab_routes("/collab", CollabHub::new()))
+//!     .run()
+//!     .await?;
+//! ```
+
+mod routes;
+mod sse;
+mod version;
+#[cfg(feature = "ws")]
+mod ws;
+
+pub use routes::collab_routes;
+pub use version::{Causality, VersionVector};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::error::ApiError;
+
+/// A change submitted by a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Update {
+    /// Site that made the change
+    pub site: String,
+    /// Version of the document the change was made against
+    #[serde(default)]
+    pub base: VersionVector,
+    pub payload: serde_json::Value,
+}
+
+/// A change accepted into a document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedUpdate {
+    pub site: String,
+    /// Sequence number of this change among those from `site`
+    pub seq: u64,
+    /// Document version including this change
+    pub version: VersionVector,
+    pub payload: serde_json::Value,
+}
+
+/// Who is in a document and what they are doing there (cursor, selection...)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Presence {
+    pub site: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub state: serde_json::Value,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl Presence {
+    /// Presence for `site` with no state yet
+    pub fn new(site: impl Into<String>) -> Self {
+        Self {
+            site: site.into(),
+            user: None,
+            state: serde_json::Value::Null,
+            last_seen: Utc::now(),
+        }
+    }
+
+    /// Attach the user behind the site
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+}
+
+/// Something that happened in a document channel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CollabEvent {
+    Update(AppliedUpdate),
+    Presence(Presence),
+    Left { site: String },
+}
+
+impl CollabEvent {
+    /// Event name, as used for server-sent events
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CollabEvent::Update(_) => "update",
+            CollabEvent::Presence(_) => "presence",
+            CollabEvent::Left { .. } => "left",
+        }
+    }
+}
+
+/// What to do with an update made concurrently with others
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// Apply this payload, e.g. the incoming one transformed against the
+    /// changes it missed
+    Apply(serde_json::Value),
+    /// Refuse the update; the client should catch up and retry
+    Reject(String),
+}
+
+/// Resolves updates whose author had not seen every change in the document
+pub trait ConflictHandler: Send + Sync + 'static {
+    /// `missed` holds the changes accepted since `incoming.base`, oldest
+    /// first, as far back as the hub's history goes.
+    fn resolve(&self, doc: &str, missed: &[AppliedUpdate], incoming: &Update) -> Resolution;
+}
+
+impl<F> ConflictHandler for F
+where
+    F: Fn(&str, &[AppliedUpdate], &Update) -> Resolution + Send + Sync + 'static,
+{
+    fn resolve(&self, doc: &str, missed: &[AppliedUpdate], incoming: &Update) -> Resolution {
+        self(doc, missed, incoming)
+    }
+}
+
+/// Applies concurrent updates unchanged, leaving merging to the clients
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AcceptAll;
+
+impl ConflictHandler for AcceptAll {
+    fn resolve(&self, _doc: &str, _missed: &[AppliedUpdate], incoming: &Update) -> Resolution {
+        Resolution::Apply(incoming.payload.clone())
+    }
+}
+
+/// A client's view of a document when joining it
+#[derive(Debug)]
+pub struct Subscription {
+    pub version: VersionVector,
+    pub presence: Vec<Presence>,
+    pub events: broadcast::Receiver<CollabEvent>,
+}
+
+struct Channel {
+    version: VersionVector,
+    history: VecDeque<AppliedUpdate>,
+    presence: HashMap<String, Presence>,
+    sender: broadcast::Sender<CollabEvent>,
+}
+
+impl Channel {
+    fn new(capacity: usize) -> Self {
+        Self {
+            version: VersionVector::new(),
+            history: VecDeque::new(),
+            presence: HashMap::new(),
+            sender: broadcast::channel(capacity).0,
+        }
+    }
+
+    fn publish(&self, event: CollabEvent) {
+        // No subscribers is fine; the change is still recorded.
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Document channels shared by every connection
+#[derive(Clone)]
+pub struct CollabHub {
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
+    handler: Arc<dyn ConflictHandler>,
+    history_limit: usize,
+    channel_capacity: usize,
+}
+
+impl CollabHub {
+    /// Create a hub that accepts concurrent updates as-is, keeps the last 1000
+    /// changes per document and buffers 256 events per subscriber
+    pub fn new() -> Self {
+        Self {
+            channels: Arc::default(),
+            handler: Arc::new(AcceptAll),
+            history_limit: 1000,
+            channel_capacity: 256,
+        }
+    }
+
+    /// Set the handler for concurrent updates
+    pub fn conflict_handler(mut self, handler: impl ConflictHandler) -> Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+
+    /// Set how many accepted changes are kept per document
+    pub fn history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        self
+    }
+
+    /// Set how many events a slow subscriber may fall behind by before it
+    /// starts missing them
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = capacity.max(1);
+        self
+    }
+
+    fn with_channel<R>(&self, doc: &str, f: impl FnOnce(&mut Channel) -> R) -> R {
+        let mut channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        let channel = channels
+            .entry(doc.to_string())
+            .or_insert_with(|| Channel::new(self.channel_capacity));
+        f(channel)
+    }
+
+    /// Join `doc`, announcing `presence` to the other participants
+    pub fn join(&self, doc: &str, presence: Presence) -> Subscription {
+        self.with_channel(doc, |channel| {
+            let events = channel.sender.subscribe();
+            channel
+                .presence
+                .insert(presence.site.clone(), presence.clone());
+            channel.publish(CollabEvent::Presence(presence));
+            Subscription {
+                version: channel.version.clone(),
+                presence: channel.presence.values().cloned().collect(),
+                events,
+            }
+        })
+    }
+
+    /// Remove `site` from `doc`, dropping the channel once it is empty
+    pub fn leave(&self, doc: &str, site: &str) {
+        let mut channels = self.channels.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(channel) = channels.get_mut(doc) else {
+            return;
+        };
+        if channel.presence.remove(site).is_some() {
+            channel.publish(CollabEvent::Left {
+                site: site.to_string(),
+            });
+        }
+        if channel.presence.is_empty() && channel.sender.receiver_count() == 0 {
+            channels.remove(doc);
+        }
+    }
+
+    /// Replace the presence state of `site` in `doc`
+    pub fn update_presence(
+        &self,
+        doc: &str,
+        site: &str,
+        state: serde_json::Value,
+    ) -> Result<(), ApiError> {
+        self.with_channel(doc, |channel| {
+            let presence = channel
+                .presence
+                .get_mut(site)
+                .ok_or_else(|| ApiError::NotFound(format!("Site {site} has not joined {doc}")))?;
+            presence.state = state;
+            presence.last_seen = Utc::now();
+            let presence = presence.clone();
+            channel.publish(CollabEvent::Presence(presence));
+            Ok(())
+        })
+    }
+
+    /// Current version of `doc`
+    pub fn version(&self, doc: &str) -> VersionVector {
+        self.with_channel(doc, |channel| channel.version.clone())
+    }
+
+    /// Participants currently in `doc`
+    pub fn presence(&self, doc: &str) -> Vec<Presence> {
+        self.with_channel(doc, |channel| channel.presence.values().cloned().collect())
+    }
+
+    /// Changes to `doc` not yet seen at `base`, oldest first, as far back as
+    /// the history goes
+    pub fn changes_since(&self, doc: &str, base: &VersionVector) -> Vec<AppliedUpdate> {
+        self.with_channel(doc, |channel| missed(channel, base))
+    }
+
+    /// Accept `update` into `doc` and broadcast it
+    ///
+    /// Updates made against the current version are applied directly. If
+    /// other changes were accepted since `update.base`, the conflict handler
+    /// decides what to apply. Updates claiming a version the document has not
+    /// reached are rejected.
+    pub fn submit(&self, doc: &str, update: Update) -> Result<AppliedUpdate, ApiError> {
+        self.with_channel(doc, |channel| {
+            match update.base.compare(&channel.version) {
+                Causality::Equal | Causality::Before => {}
+                Causality::After | Causality::Concurrent => {
+                    return Err(ApiError::BadRequest(
+                        "Update is based on a version this document has not reached".to_string(),
+                    ));
+                }
+            }
+
+            let missed = missed(channel, &update.base);
+            let payload = if missed.is_empty() {
+                update.payload
+            } else {
+                match self.handler.resolve(doc, &missed, &update) {
+                    Resolution::Apply(payload) => payload,
+                    Resolution::Reject(reason) => return Err(ApiError::BadRequest(reason)),
+                }
+            };
+
+            let seq = channel.version.increment(&update.site);
+            let applied = AppliedUpdate {
+                site: update.site,
+                seq,
+                version: channel.version.clone(),
+                payload,
+            };
+
+            channel.history.push_back(applied.clone());
+            while channel.history.len() > self.history_limit {
+                channel.history.pop_front();
+            }
+            channel.publish(CollabEvent::Update(applied.clone()));
+            Ok(applied)
+        })
+    }
+}
+
+impl Default for CollabHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn missed(channel: &Channel, base: &VersionVector) -> Vec<AppliedUpdate> {
+    channel
+        .history
+        .iter()
+        .filter(|applied| !base.contains(&applied.site, applied.seq))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn update(site: &str, base: &VersionVector, payload: serde_json::Value) -> Update {
+        Update {
+            site: site.to_string(),
+            base: base.clone(),
+            payload,
+        }
+    }
+
+    #[tokio::test]
+    async fn broadcasts_updates_and_presence() {
+        let hub = CollabHub::new();
+        let mut alice = hub.join("doc", Presence::new("alice"));
+        let bob = hub.join("doc", Presence::new("bob").user("Bob"));
+        assert_eq!(bob.presence.len(), 2);
+
+        let applied = hub
+            .submit("doc", update("bob", &bob.version, json!({"insert": "hi"})))
+            .unwrap();
+        assert_eq!(applied.seq, 1);
+        assert_eq!(applied.version.get("bob"), 1);
+
+        assert!(matches!(
+            alice.events.recv().await.unwrap(),
+            CollabEvent::Presence(p) if p.site == "alice"
+        ));
+        assert!(matches!(
+            alice.events.recv().await.unwrap(),
+            CollabEvent::Presence(p) if p.user.as_deref() == Some("Bob")
+        ));
+        assert_eq!(
+            alice.events.recv().await.unwrap(),
+            CollabEvent::Update(applied)
+        );
+
+        hub.update_presence("doc", "bob", json!({"cursor": 2}))
+            .unwrap();
+        hub.leave("doc", "bob");
+        assert!(matches!(
+            alice.events.recv().await.unwrap(),
+            CollabEvent::Presence(p) if p.state == json!({"cursor": 2})
+        ));
+        assert_eq!(
+            alice.events.recv().await.unwrap(),
+            CollabEvent::Left {
+                site: "bob".to_string()
+            }
+        );
+        assert!(hub.update_presence("doc", "bob", json!(null)).is_err());
+    }
+
+    #[test]
+    fn concurrent_updates_go_through_the_conflict_handler() {
+        let hub = CollabHub::new().conflict_handler(
+            |_doc: &str, missed: &[AppliedUpdate], incoming: &Update| {
+                if incoming.payload == json!("reject") {
+                    return Resolution::Reject("conflict".to_string());
+                }
+                Resolution::Apply(json!({ "rebased_over": missed.len() }))
+            },
+        );
+        let base = hub.version("doc");
+
+        hub.submit("doc", update("a", &base, json!(1))).unwrap();
+        hub.submit("doc", update("a", &hub.version("doc"), json!(2)))
+            .unwrap();
+
+        let applied = hub.submit("doc", update("b", &base, json!(3))).unwrap();
+        assert_eq!(applied.payload, json!({ "rebased_over": 2 }));
+        assert!(matches!(
+            hub.submit("doc", update("c", &base, json!("reject"))),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        assert_eq!(hub.changes_since("doc", &base).len(), 3);
+        assert_eq!(hub.changes_since("doc", &hub.version("doc")).len(), 0);
+    }
+
+    #[test]
+    fn rejects_updates_from_the_future() {
+        let hub = CollabHub::new();
+        let mut ahead = VersionVector::new();
+        ahead.increment("a");
+        assert!(hub.submit("doc", update("a", &ahead, json!(1))).is_err());
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let hub = CollabHub::new().history_limit(2);
+        let base = hub.version("doc");
+        for i in 0..5 {
+            hub.submit("doc", update("a", &hub.version("doc"), json!(i)))
+                .unwrap();
+        }
+        let payloads: Vec<_> = hub
+            .changes_since("doc", &base)
+            .into_iter()
+            .map(|u| u.payload)
+            .collect();
+        assert_eq!(payloads, [json!(3), json!(4)]);
+    }
+}
@@ -0,0 +1,152 @@
+//! HTTP endpoints for a [`CollabHub`]
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post, put},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{AppliedUpdate, CollabHub, Presence, Update, VersionVector};
+use crate::error::ApiError;
+
+#[derive(Deserialize)]
+pub(super) struct JoinParams {
+    pub(super) site: String,
+    pub(super) user: Option<String>,
+}
+
+impl JoinParams {
+    pub(super) fn presence(&self) -> Presence {
+        let mut presence = Presence::new(&self.site);
+        presence.user = self.user.clone();
+        presence
+    }
+}
+
+#[derive(Serialize)]
+pub(super) struct Snapshot {
+    pub(super) version: VersionVector,
+    pub(super) presence: Vec<Presence>,
+}
+
+/// Mount the collaboration endpoints for `hub` under `base`:
+///
+/// - `GET {base}/{doc}` – current version and participants
+/// - `GET {base}/{doc}/ws?site=..&user=..` – join over a WebSocket, with the
+///   `ws` feature. The server sends a `snapshot` message, then `update`,
+///   `presence` and `left` events, each a JSON object with a `type`. Clients
+///   send `{"type":"update","base":{..},"payload":..}` and
+///   `{"type":"presence","state":..}` as the joined site; a refused message
+///   is answered with `{"type":"error","message":..}`. A `lagged` message
+///   means some events were missed and the client should reconnect.
+///   Leaving happens when the socket closes.
+/// - `GET {base}/{doc}/events?site=..&user=..` – join and stream the same
+///   events as server-sent events, for clients that can't open a
+///   WebSocket; changes go through the two routes below
+/// - `POST {base}/{doc}/updates` – submit an [`Update`]
+/// - `PUT {base}/{doc}/presence/{site}` – replace a participant's presence state
+pub fn collab_routes(base: &str, hub: CollabHub) -> Router {
+    let base = base.trim_end_matches('/');
+    let router = Router::new()
+        .route(&format!("{base}/{{doc}}"), get(snapshot))
+        .route(&format!("{base}/{{doc}}/events"), get(super::sse::events))
+        .route(&format!("{base}/{{doc}}/updates"), post(submit))
+        .route(&format!("{base}/{{doc}}/presence/{{site}}"), put(presence));
+    #[cfg(feature = "ws")]
+    let router = router.route(&format!("{base}/{{doc}}/ws"), get(super::ws::socket));
+    router.with_state(hub)
+}
+
+async fn snapshot(State(hub): State<CollabHub>, Path(doc): Path<String>) -> Json<Snapshot> {
+    Json(Snapshot {
+        version: hub.version(&doc),
+        presence: hub.presence(&doc),
+    })
+}
+
+async fn submit(
+    State(hub): State<CollabHub>,
+    Path(doc): Path<String>,
+    Json(update): Json<Update>,
+) -> Result<Json<AppliedUpdate>, ApiError> {
+    hub.submit(&doc, update).map(Json)
+}
+
+async fn presence(
+    State(hub): State<CollabHub>,
+    Path((doc, site)): Path<(String, String)>,
+    Json(state): Json<serde_json::Value>,
+) -> Result<StatusCode, ApiError> {
+    hub.update_presence(&doc, &site, state)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Leaves the document when a connection ends
+///
+/// Drop the connection's event receiver first, so that the channel is
+/// removed once nobody is left in it.
+pub(super) struct Session {
+    pub(super) hub: CollabHub,
+    pub(super) doc: String,
+    pub(super) site: String,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.hub.leave(&self.doc, &self.site);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{self, Body};
+    use axum::http::Request;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn submits_updates_and_reports_the_snapshot() {
+        let hub = CollabHub::new();
+        let app = collab_routes("/collab/", hub.clone());
+        let _sub = hub.join("notes", Presence::new("a"));
+
+        let res = app
+            .clone()
+            .oneshot(
+                Request::post("/collab/notes/updates")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(r#"{"site":"a","payload":{"insert":"x"}}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app
+            .oneshot(Request::get("/collab/notes").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let bytes = body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let json: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(json["version"], json!({ "a": 1 }));
+        assert_eq!(json["presence"][0]["site"], "a");
+    }
+
+    #[tokio::test]
+    async fn unknown_sites_cannot_set_presence() {
+        let app = collab_routes("/collab", CollabHub::new());
+        let res = app
+            .oneshot(
+                Request::put("/collab/notes/presence/ghost")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
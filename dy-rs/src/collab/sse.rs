@@ -0,0 +1,69 @@
+//! Server-sent events transport for a [`CollabHub`]
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::{self, Stream};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::routes::{JoinParams, Session, Snapshot};
+use super::{CollabEvent, CollabHub};
+
+pub(super) async fn events(
+    State(hub): State<CollabHub>,
+    Path(doc): Path<String>,
+    Query(params): Query<JoinParams>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let subscription = hub.join(&doc, params.presence());
+
+    let first = Event::default()
+        .event("snapshot")
+        .json_data(Snapshot {
+            version: subscription.version,
+            presence: subscription.presence,
+        })
+        .ok();
+    let session = Session {
+        hub,
+        doc,
+        site: params.site,
+    };
+
+    // The receiver comes first so it is dropped before `Session::drop`
+    // checks whether the channel still has subscribers.
+    let stream = stream::unfold(
+        (subscription.events, session, first),
+        |(mut events, session, first)| async move {
+            if let Some(event) = first {
+                return Some((Ok(event), (events, session, None)));
+            }
+            let event = next_event(&mut events).await?;
+            Some((Ok(event), (events, session, None)))
+        },
+    );
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn next_event(events: &mut broadcast::Receiver<CollabEvent>) -> Option<Event> {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if let Ok(sse) = Event::default().event(event.kind()).json_data(&event) {
+                    return Some(sse);
+                }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                return Some(
+                    Event::default()
+                        .event("lagged")
+                        .data(format!("{{\"missed\":{missed}}}")),
+                );
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
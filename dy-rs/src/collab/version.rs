@@ -0,0 +1,111 @@
+//! Version vectors for tracking causality between editing sites
+
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use serde::{Deserialize, Serialize};
+
+/// How two versions relate to each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Causality {
+    Equal,
+    /// Every change in `self` is also in `other`, which has more
+    Before,
+    /// Every change in `other` is also in `self`, which has more
+    After,
+    /// Each side has changes the other has not seen
+    Concurrent,
+}
+
+/// Number of changes seen from each site (client, tab or replica)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VersionVector(BTreeMap<String, u64>);
+
+impl VersionVector {
+    /// An empty version, before any change
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of changes seen from `site`
+    pub fn get(&self, site: &str) -> u64 {
+        self.0.get(site).copied().unwrap_or(0)
+    }
+
+    /// Record one more change from `site` and return its sequence number
+    pub fn increment(&mut self, site: &str) -> u64 {
+        let counter = self.0.entry(site.to_string()).or_default();
+        *counter += 1;
+        *counter
+    }
+
+    /// Whether the change numbered `seq` from `site` has been seen
+    pub fn contains(&self, site: &str, seq: u64) -> bool {
+        self.get(site) >= seq
+    }
+
+    /// Take the per-site maximum of both versions
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (site, &counter) in &other.0 {
+            let entry = self.0.entry(site.clone()).or_default();
+            *entry = (*entry).max(counter);
+        }
+    }
+
+    /// Compare the changes seen by `self` and `other`
+    pub fn compare(&self, other: &VersionVector) -> Causality {
+        let mut ordering = Ordering::Equal;
+        for site in self.0.keys().chain(other.0.keys()) {
+            match (ordering, self.get(site).cmp(&other.get(site))) {
+                (_, Ordering::Equal) => {}
+                (Ordering::Equal, cmp) => ordering = cmp,
+                (current, cmp) if current != cmp => return Causality::Concurrent,
+                _ => {}
+            }
+        }
+
+        match ordering {
+            Ordering::Equal => Causality::Equal,
+            Ordering::Less => Causality::Before,
+            Ordering::Greater => Causality::After,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(entries: &[(&str, u64)]) -> VersionVector {
+        VersionVector(entries.iter().map(|(s, c)| (s.to_string(), *c)).collect())
+    }
+
+    #[test]
+    fn compares_causality() {
+        let a = version(&[("a", 2), ("b", 1)]);
+
+        assert_eq!(a.compare(&a.clone()), Causality::Equal);
+        assert_eq!(version(&[("a", 1)]).compare(&a), Causality::Before);
+        assert_eq!(a.compare(&version(&[("a", 1)])), Causality::After);
+        assert_eq!(
+            a.compare(&version(&[("a", 1), ("b", 1), ("c", 1)])),
+            Causality::Concurrent
+        );
+        assert_eq!(
+            VersionVector::new().compare(&version(&[("a", 0)])),
+            Causality::Equal
+        );
+    }
+
+    #[test]
+    fn increments_and_merges() {
+        let mut a = VersionVector::new();
+        assert_eq!(a.increment("a"), 1);
+        assert_eq!(a.increment("a"), 2);
+        assert!(a.contains("a", 2));
+        assert!(!a.contains("b", 1));
+
+        a.merge(&version(&[("a", 1), ("b", 3)]));
+        assert_eq!(a, version(&[("a", 2), ("b", 3)]));
+    }
+}
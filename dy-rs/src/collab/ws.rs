@@ -0,0 +1,222 @@
+//! WebSocket transport for a [`CollabHub`]
+
+use axum::{
+    extract::{
+        Path, Query, State, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::routes::{JoinParams, Session, Snapshot};
+use super::{CollabHub, Update, VersionVector};
+
+/// A message from a client, always on behalf of the site it joined as
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Update {
+        #[serde(default)]
+        base: VersionVector,
+        payload: serde_json::Value,
+    },
+    Presence {
+        #[serde(default)]
+        state: serde_json::Value,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Snapshot(Snapshot),
+    Error { message: String },
+}
+
+pub(super) async fn socket(
+    State(hub): State<CollabHub>,
+    Path(doc): Path<String>,
+    Query(params): Query<JoinParams>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| serve(socket, hub, doc, params))
+}
+
+async fn serve(mut socket: WebSocket, hub: CollabHub, doc: String, params: JoinParams) {
+    let subscription = hub.join(&doc, params.presence());
+    let mut events = subscription.events;
+    let session = Session {
+        hub,
+        doc,
+        site: params.site,
+    };
+
+    let snapshot = ServerMessage::Snapshot(Snapshot {
+        version: subscription.version,
+        presence: subscription.presence,
+    });
+    if send(&mut socket, &snapshot).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let sent = match event {
+                    Ok(event) => send(&mut socket, &event).await,
+                    Err(RecvError::Lagged(missed)) => {
+                        send(&mut socket, &json!({ "type": "lagged", "missed": missed })).await
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if sent.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // Pings are answered by axum
+                    Some(Ok(_)) => continue,
+                };
+                if let Err(message) = apply(&session, &text)
+                    && send(&mut socket, &ServerMessage::Error { message }).await.is_err()
+                {
+                    break;
+                }
+            }
+        }
+    }
+
+    // See `Session`: the receiver goes first
+    drop(events);
+    drop(session);
+}
+
+/// Apply a client message; accepted changes reach the client as events
+fn apply(session: &Session, text: &str) -> Result<(), String> {
+    let message: ClientMessage =
+        serde_json::from_str(text).map_err(|e| format!("Invalid message: {e}"))?;
+    let result = match message {
+        ClientMessage::Update { base, payload } => session
+            .hub
+            .submit(
+                &session.doc,
+                Update {
+                    site: session.site.clone(),
+                    base,
+                    payload,
+                },
+            )
+            .map(|_| ()),
+        ClientMessage::Presence { state } => {
+            session
+                .hub
+                .update_presence(&session.doc, &session.site, state)
+        }
+    };
+    result.map_err(|e| e.to_string())
+}
+
+async fn send(socket: &mut WebSocket, message: &impl Serialize) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).expect("collab messages serialize");
+    socket.send(Message::Text(text.into())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use tokio_tungstenite::{connect_async, tungstenite};
+
+    use crate::App;
+    use crate::collab::{CollabHub, collab_routes};
+    use crate::testing::TestServer;
+
+    type Client = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    async fn next(client: &mut Client) -> Value {
+        loop {
+            match client.next().await.unwrap().unwrap() {
+                tungstenite::Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+                _ => continue,
+            }
+        }
+    }
+
+    async fn send(client: &mut Client, message: Value) {
+        client
+            .send(tungstenite::Message::Text(message.to_string().into()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sockets_sync_updates_and_presence() {
+        let hub = CollabHub::new();
+        let server = TestServer::start(App::new().mount(collab_routes("/collab", hub.clone())))
+            .await
+            .unwrap();
+
+        let (mut alice, _) = connect_async(server.ws_url("/collab/notes/ws?site=a&user=alice"))
+            .await
+            .unwrap();
+        let snapshot = next(&mut alice).await;
+        assert_eq!(snapshot["type"], "snapshot");
+        assert_eq!(snapshot["presence"][0]["user"], "alice");
+        // Joining is announced to everyone, the new participant included
+        assert_eq!(next(&mut alice).await["site"], "a");
+
+        let (mut bob, _) = connect_async(server.ws_url("/collab/notes/ws?site=b"))
+            .await
+            .unwrap();
+        assert_eq!(next(&mut bob).await["type"], "snapshot");
+        assert_eq!(next(&mut bob).await["site"], "b");
+        let joined = next(&mut alice).await;
+        assert_eq!(
+            (&joined["type"], &joined["site"]),
+            (&json!("presence"), &json!("b"))
+        );
+
+        // Updates are made as the joined site, whatever the message claims
+        send(
+            &mut alice,
+            json!({ "type": "update", "site": "b", "payload": { "insert": "x" } }),
+        )
+        .await;
+        for client in [&mut alice, &mut bob] {
+            let update = next(client).await;
+            assert_eq!(update["type"], "update");
+            assert_eq!(update["site"], "a");
+            assert_eq!(update["version"], json!({ "a": 1 }));
+        }
+
+        send(
+            &mut bob,
+            json!({ "type": "presence", "state": { "cursor": 3 } }),
+        )
+        .await;
+        for client in [&mut alice, &mut bob] {
+            let moved = next(client).await;
+            assert_eq!(moved["state"], json!({ "cursor": 3 }));
+        }
+
+        send(&mut bob, json!({ "type": "delete" })).await;
+        let error = next(&mut bob).await;
+        assert_eq!(error["type"], "error");
+
+        bob.close(None).await.unwrap();
+        let left = next(&mut alice).await;
+        assert_eq!(
+            (&left["type"], &left["site"]),
+            (&json!("left"), &json!("b"))
+        );
+        assert_eq!(hub.presence("notes").len(), 1);
+    }
+}
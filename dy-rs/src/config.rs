@@ -1,67 +1,866 @@
-use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::Deref, sync::Arc};
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::client::HttpClientConfig;
+use crate::client_ip::ClientIpConfig;
+use crate::cors::{ANY_ORIGIN, CorsConfig};
+
+use crate::decompression::DecompressionConfig;
+use crate::docs::{DocsUi, SpecPaths};
+use crate::error::ApiError;
+use crate::mail::MailConfig;
+use crate::maintenance::MaintenanceConfig;
+use crate::messaging::MessagingConfig;
+use crate::openapi::DocSettings;
+use crate::pagination::PaginationConfig;
+use crate::proxy::ProxyConfig;
+use crate::secrets::{ConfigCipher, MASTER_KEY_ENV, MasterKey, Secrets};
+use crate::slow_requests::SlowRequestConfig;
+use crate::storage::StorageConfig;
+#[cfg(feature = "sqlx")]
+use crate::tenancy::TenantPoolConfig;
+use crate::warmup::WarmupConfig;
+
+/// Environment used when neither `--env` nor `APP_ENV` names one
+pub const DEFAULT_ENVIRONMENT: &str = "development";
 
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
+    /// Active environment, e.g. `development` or `production`, chosen with
+    /// `--env` or `APP_ENV`
+    #[serde(default = "default_environment")]
+    pub environment: String,
     pub server: ServerConfig,
     pub database: DatabaseConfig,
+    #[serde(default)]
+    pub docs: DocsConfig,
+    /// Title, version, servers and other settings for the OpenAPI document
+    #[serde(default)]
+    pub openapi: DocSettings,
+    /// Requests sent to the app on startup, before it reports ready
+    #[serde(default)]
+    pub warmup: WarmupConfig,
+    /// Whether routes answer `503` for maintenance
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// When requests are logged as slow
+    #[serde(default)]
+    pub slow_requests: SlowRequestConfig,
+    /// Per-tenant connection pools
+    #[cfg(feature = "sqlx")]
+    #[serde(default)]
+    pub tenancy: TenantPoolConfig,
+    /// Page size defaults and caps for the `Pagination` extractor
+    #[serde(default)]
+    pub pagination: PaginationConfig,
+    /// Deprecated alias of `server.proxy`: its proxies are moved there when
+    /// the config loads, and this section is left empty
+    #[deprecated(note = "set `server.proxy.trusted_proxies` instead")]
+    #[serde(default)]
+    pub client_ip: ClientIpConfig,
+    /// How outgoing mail is sent
+    #[serde(default)]
+    pub mail: MailConfig,
+    /// Timeouts, proxy, retries and circuit breaking of outbound HTTP calls
+    #[serde(default)]
+    pub http_client: HttpClientConfig,
+    /// Where objects are stored
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// Retries, dead letters and broker connection for consumer groups
+    #[serde(default)]
+    pub messaging: MessagingConfig,
+    /// Log filter, reloaded without a restart under `App::watch_config`
+    #[serde(default)]
+    pub log: LogConfig,
+    /// JWT settings and password policy for the auth routes
+    #[cfg(feature = "auth")]
+    #[serde(default)]
+    pub auth: crate::auth::AuthConfig,
+    /// Everything that was loaded, for [`AppConfig::section`]
+    #[serde(skip)]
+    sources: Option<config::Config>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
+    /// Address to listen on, an IP address or a name such as `localhost`
     pub host: String,
+    /// Port to listen on; the `PORT` variable set by Heroku, Render and
+    /// Cloud Run overrides the files, and `APP__SERVER__PORT` overrides that
     pub port: u16,
+    /// Unix socket to listen on instead of `host` and `port`; see
+    /// [`listen`](crate::listen)
+    #[serde(default)]
+    pub unix_socket: Option<std::path::PathBuf>,
+    /// How long background tasks get to finish on shutdown
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Whether `5xx` responses show internal error messages; by default they
+    /// do, except in the `production` environment. See
+    /// [`internal_errors`](crate::internal_errors).
+    #[serde(default)]
+    pub expose_internal_errors: Option<bool>,
+    /// How compressed request bodies are decoded
+    #[serde(default)]
+    pub decompression: DecompressionConfig,
+    /// Proxies trusted to report the client, scheme and host
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Which origins may call the API from a browser; see
+    /// [`cors`](crate::cors)
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// Whether the route table is logged on startup; see
+    /// [`App::routes`](crate::App::routes)
+    #[serde(default)]
+    pub print_routes: bool,
+}
+
+impl ServerConfig {
+    /// The socket address `host` and `port` resolve to
+    pub async fn socket_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::AddrNotAvailable,
+                    format!("server.host `{}` has no address", self.host),
+                )
+            })
+    }
+}
+
+fn default_environment() -> String {
+    DEFAULT_ENVIRONMENT.to_string()
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
     pub url: String,
     pub max_connections: u32,
+    /// Read replicas for [`DbPools`](crate::db::DbPools), e.g.
+    /// `APP__DATABASE__REPLICAS=postgres://replica-1/app,postgres://replica-2/app`
+    #[serde(default)]
+    pub replicas: Vec<String>,
+    /// Retries of transient errors for [`db::retry`](crate::db::retry)
+    #[serde(default)]
+    pub retry: DatabaseRetryConfig,
+    /// Seconds `App::run` waits for the database passed to
+    /// `App::with_database` to answer before serving; `0` doesn't wait
+    #[serde(default)]
+    pub wait_on_startup_secs: u64,
+}
+
+/// How often and how long transient database errors are retried, the
+/// `[database.retry]` config section
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseRetryConfig {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    /// Wait before the second attempt; doubled for each one after
+    pub initial_delay_ms: u64,
+    /// Longest wait between attempts
+    pub max_delay_ms: u64,
+}
+
+impl Default for DatabaseRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay_ms: 50,
+            max_delay_ms: 2_000,
+        }
+    }
+}
+
+impl DatabaseRetryConfig {
+    /// Wait after failed attempt number `attempt` (1-based)
+    pub fn delay(&self, attempt: u32) -> std::time::Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.initial_delay_ms.saturating_mul(factor);
+        std::time::Duration::from_millis(delay.min(self.max_delay_ms))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocsConfig {
+    /// Whether the docs UIs and the OpenAPI document are served; by default
+    /// they are, except in the `production` environment
+    pub enabled: Option<bool>,
+    /// Docs UIs to serve, e.g. `APP__DOCS__UI=redoc,scalar`
+    pub ui: Vec<DocsUi>,
+    /// Where the OpenAPI document is served, e.g.
+    /// `APP__DOCS__SPEC__YAML=/openapi.yaml`
+    pub spec: SpecPaths,
+}
+
+impl Default for DocsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: None,
+            ui: vec![DocsUi::Swagger],
+            spec: SpecPaths::default(),
+        }
+    }
+}
+
+/// Logging settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Filter directives such as `info,dy_rs=debug`, used unless `RUST_LOG`
+    /// is set
+    pub level: Option<String>,
+    /// How log lines are written; by default JSON in `production` and text
+    /// elsewhere
+    pub format: Option<LogFormat>,
+    pub redact: RedactConfig,
+}
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+impl LogFormat {
+    /// The format used in `environment` unless `log.format` says otherwise
+    pub fn default_for(environment: &str) -> Self {
+        if environment == "production" {
+            Self::Json
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// Names of fields and headers to keep out of logs, traces and audit
+/// records, on top of the defaults; see [`redact`](crate::redact)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactConfig {
+    /// Fields whose name contains one of these, e.g. `card_number`
+    pub fields: Vec<String>,
+    /// Headers, e.g. `x-session`
+    pub headers: Vec<String>,
 }
 
 impl AppConfig {
     /// Load configuration from files and environment variables
     ///
-    /// Loads in this order:
+    /// Loads in this order, later sources overriding earlier ones:
     /// 1. config/default.toml (if exists)
-    /// 2. config/local.toml (if exists)
-    /// 3. Environment variables (prefixed with APP_)
+    /// 2. config/{environment}.toml (if exists), e.g. config/production.toml
+    /// 3. config/local.toml (if exists)
+    /// 4. Environment variables (prefixed with APP__)
+    ///
+    /// Each file can also be YAML (`.yaml` or `.yml`) or JSON (`.json`); if
+    /// several formats of one file exist, they are merged in that order.
+    ///
+    /// The environment comes from the `--env` argument, then the `APP_ENV`
+    /// variable, and is `development` otherwise.
+    ///
+    /// `ENC(...)` values are decrypted with the [`MasterKey`] in the
+    /// `APP_CONFIG_KEY` variable, if it is set.
     pub fn load() -> Result<Self, config::ConfigError> {
-        let config = config::Config::builder()
+        Self::load_environment(&active_environment())
+    }
+
+    /// Load configuration like [`AppConfig::load`] for `environment`
+    pub fn load_environment(environment: &str) -> Result<Self, config::ConfigError> {
+        Self::load_from("config", environment)
+    }
+
+    pub(crate) fn load_from(dir: &str, environment: &str) -> Result<Self, config::ConfigError> {
+        let mut builder = config::Config::builder()
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 3000)?
             .set_default("database.url", "postgres://localhost/dy_rs")?
-            .set_default("database.max_connections", 10)?
-            // Try to load config files (won't fail if they don't exist)
-            .add_source(config::File::with_name("config/default").required(false))
-            .add_source(config::File::with_name("config/local").required(false))
+            .set_default("database.max_connections", 10)?;
+        // Load the config files that exist, in any supported format
+        for name in ["default", environment, "local"] {
+            builder = add_files(builder, &format!("{dir}/{name}"));
+        }
+        // The platform's PORT wins over the files, but not over APP__SERVER__PORT
+        if let Some(port) = platform_port(
+            std::env::var("PORT").ok(),
+            std::env::var_os("APP__SERVER__PORT").is_some(),
+        ) {
+            let port = config::Value::new(Some(&"PORT".to_string()), port);
+            builder = builder.set_override("server.port", port)?;
+        }
+        let config = builder
             // Environment variables override everything
-            // APP_SERVER__PORT=8080 -> server.port
-            .add_source(config::Environment::with_prefix("APP").separator("__"))
+            // APP__SERVER__PORT=8080 -> server.port
+            .add_source(
+                config::Environment::with_prefix("APP")
+                    .separator("__")
+                    .try_parsing(true)
+                    .list_separator(",")
+                    .with_list_parse_key("docs.ui")
+                    .with_list_parse_key("database.replicas")
+                    .with_list_parse_key("openapi.security")
+                    .with_list_parse_key("auth.password_policy.banned")
+                    .with_list_parse_key("client_ip.trusted_proxies")
+                    .with_list_parse_key("server.proxy.trusted_proxies")
+                    .with_list_parse_key("server.proxy.headers")
+                    .with_list_parse_key("server.cors.allowed_origins")
+                    .with_list_parse_key("server.cors.allowed_headers"),
+            )
+            .set_override("environment", environment)?
             .build()?;
 
-        config.try_deserialize()
+        let mut app_config: Self = config.clone().try_deserialize()?;
+        app_config.sources = Some(config);
+        if app_config.adopt_client_ip_proxies() {
+            tracing::warn!(
+                "`[client_ip] trusted_proxies` is deprecated; set `[server.proxy] trusted_proxies` instead"
+            );
+        }
+        if !app_config.encrypted_keys().is_empty()
+            && let Some(key) =
+                MasterKey::from_env().map_err(|e| config::ConfigError::Message(e.to_string()))?
+        {
+            app_config.decrypt_values(&key)?;
+        }
+        Ok(app_config)
+    }
+
+    /// Deserialize the application's own `[name]` section from the same
+    /// files and `APP__{NAME}__*` variables as the framework settings, e.g.
+    /// `config.section::<StripeConfig>("stripe")`. Nested sections are
+    /// named with dots, e.g. `"features.beta"`.
+    ///
+    /// Fails if the section is missing or doesn't match `T`, and always for
+    /// a config that wasn't loaded from files.
+    pub fn section<T: DeserializeOwned>(&self, name: &str) -> Result<T, config::ConfigError> {
+        self.sources
+            .as_ref()
+            .ok_or_else(|| config::ConfigError::NotFound(name.to_string()))?
+            .get(name)
+    }
+
+    /// Replace `${secret:<source>:<name>}` placeholders in every setting,
+    /// application sections included, with secrets from `secrets`
+    pub async fn resolve_secrets(&mut self, secrets: &Secrets) -> Result<(), config::ConfigError> {
+        let mut value = self.to_value()?;
+        secrets
+            .resolve_value(&mut value)
+            .await
+            .map_err(|e| config::ConfigError::Message(e.to_string()))?;
+        self.replace_with(value)
+    }
+
+    /// Decrypt every `ENC(...)` setting, application sections included, with
+    /// `cipher`
+    pub fn decrypt_values(&mut self, cipher: &dyn ConfigCipher) -> Result<(), config::ConfigError> {
+        let mut value = self.to_value()?;
+        let mut pending = vec![&mut value];
+        while let Some(value) = pending.pop() {
+            match value {
+                serde_json::Value::String(s) => {
+                    if let Some(ciphertext) = crate::secrets::encrypted(s) {
+                        *s = cipher
+                            .decrypt(ciphertext)
+                            .map_err(|e| config::ConfigError::Message(e.to_string()))?;
+                    }
+                }
+                serde_json::Value::Array(items) => pending.extend(items.iter_mut()),
+                serde_json::Value::Object(fields) => pending.extend(fields.values_mut()),
+                _ => {}
+            }
+        }
+        self.replace_with(value)
+    }
+
+    /// Every setting, application sections included, as JSON
+    fn to_value(&self) -> Result<serde_json::Value, config::ConfigError> {
+        match &self.sources {
+            Some(sources) => sources.clone().try_deserialize(),
+            None => {
+                serde_json::to_value(self).map_err(|e| config::ConfigError::Message(e.to_string()))
+            }
+        }
+    }
+
+    /// Replace the settings with `value`, as from [`to_value`](Self::to_value)
+    fn replace_with(&mut self, value: serde_json::Value) -> Result<(), config::ConfigError> {
+        let sources = config::Config::builder()
+            .add_source(config::File::from_str(
+                &value.to_string(),
+                config::FileFormat::Json,
+            ))
+            .build()?;
+        let mut resolved: Self = sources.clone().try_deserialize()?;
+        resolved.sources = Some(sources);
+        // The sources still have them under `client_ip`
+        resolved.adopt_client_ip_proxies();
+        *self = resolved;
+        Ok(())
+    }
+
+    /// Check the settings that can't be wrong without the app misbehaving,
+    /// reporting every problem at once
+    pub fn validate(&self) -> Result<(), ConfigReport> {
+        const POSTGRES: &[&str] = &["postgres", "postgresql"];
+        let mut report = ConfigReport {
+            problems: Vec::new(),
+        };
+
+        report.check(!self.environment.is_empty(), "environment", "is required");
+        report.check(!self.server.host.is_empty(), "server.host", "is required");
+        report.check(
+            self.server.port != 0,
+            "server.port",
+            "must be between 1 and 65535",
+        );
+
+        let database = &self.database;
+        report.check_url("database.url", &database.url, POSTGRES);
+        for (i, replica) in database.replicas.iter().enumerate() {
+            report.check_url(&format!("database.replicas[{i}]"), replica, POSTGRES);
+        }
+        report.check(
+            database.max_connections > 0,
+            "database.max_connections",
+            "must be at least 1",
+        );
+        report.check(
+            database.retry.max_attempts > 0,
+            "database.retry.max_attempts",
+            "must be at least 1",
+        );
+        report.check(
+            database.retry.initial_delay_ms <= database.retry.max_delay_ms,
+            "database.retry.initial_delay_ms",
+            "must not exceed database.retry.max_delay_ms",
+        );
+
+        for (key, path) in [
+            ("docs.spec.json", &self.docs.spec.json),
+            ("docs.spec.yaml", &self.docs.spec.yaml),
+        ] {
+            report.check(path.starts_with('/'), key, "must start with `/`");
+        }
+
+        let pagination = &self.pagination;
+        report.check(
+            pagination.default_per_page > 0,
+            "pagination.default_per_page",
+            "must be at least 1",
+        );
+        report.check(
+            pagination.default_per_page <= pagination.max_per_page,
+            "pagination.default_per_page",
+            "must not exceed pagination.max_per_page",
+        );
+
+        if let Some(level) = &self.log.level {
+            report.check(
+                tracing_subscriber::EnvFilter::try_new(level).is_ok(),
+                "log.level",
+                "must be a valid log filter, e.g. `info,dy_rs=debug`",
+            );
+        }
+
+        for (i, origin) in self
+            .server
+            .cors
+            .allowed_origins
+            .iter()
+            .flatten()
+            .enumerate()
+        {
+            if origin != ANY_ORIGIN {
+                report.check_url(
+                    &format!("server.cors.allowed_origins[{i}]"),
+                    origin,
+                    &["http", "https"],
+                );
+            }
+        }
+
+        #[cfg(feature = "sqlx")]
+        if !self.tenancy.url.is_empty() {
+            report.check_url("tenancy.url", &self.tenancy.url, POSTGRES);
+        }
+
+        #[cfg(feature = "auth")]
+        {
+            let auth = &self.auth;
+            report.check(
+                !auth.jwt_secret.is_empty(),
+                "auth.jwt_secret",
+                "is required",
+            );
+            report.check(
+                !(self.is_production() && auth.jwt_secret == crate::auth::DEV_JWT_SECRET),
+                "auth.jwt_secret",
+                "must not be the development default in production",
+            );
+            report.check(
+                auth.access_token_expiry_secs > 0,
+                "auth.access_token_expiry_secs",
+                "must be at least 1",
+            );
+        }
+
+        for key in self.encrypted_keys() {
+            report.check(
+                false,
+                &key,
+                format!(
+                    "is encrypted; set {MASTER_KEY_ENV} to decrypt it, or load the configuration with a cipher through App::auto_configure_with_secrets"
+                ),
+            );
+        }
+
+        if report.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(report)
+        }
+    }
+
+    /// Move the deprecated `[client_ip]` proxies into `[server.proxy]`, the
+    /// only section requests are resolved with; `false` if there were none
+    #[allow(deprecated)]
+    fn adopt_client_ip_proxies(&mut self) -> bool {
+        let proxies = std::mem::take(&mut self.client_ip.trusted_proxies);
+        if proxies.is_empty() {
+            return false;
+        }
+        let trusted = &mut self.server.proxy.trusted_proxies;
+        for proxy in proxies {
+            if !trusted.contains(&proxy) {
+                trusted.push(proxy);
+            }
+        }
+        true
+    }
+
+    /// Keys whose values are still `ENC(...)`
+    fn encrypted_keys(&self) -> Vec<String> {
+        fn collect(value: &serde_json::Value, key: &str, keys: &mut Vec<String>) {
+            match value {
+                serde_json::Value::String(s) if s.trim().starts_with("ENC(") => {
+                    keys.push(key.to_string());
+                }
+                serde_json::Value::Object(fields) => {
+                    for (name, value) in fields {
+                        let key = match key {
+                            "" => name.clone(),
+                            _ => format!("{key}.{name}"),
+                        };
+                        collect(value, &key, keys);
+                    }
+                }
+                serde_json::Value::Array(items) => {
+                    for (i, item) in items.iter().enumerate() {
+                        collect(item, &format!("{key}[{i}]"), keys);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let value = match &self.sources {
+            Some(sources) => sources.clone().try_deserialize().ok(),
+            None => serde_json::to_value(self).ok(),
+        };
+        let mut keys = Vec::new();
+        if let Some(value) = value {
+            collect(&value, "", &mut keys);
+        }
+        keys
+    }
+
+    pub fn is_production(&self) -> bool {
+        self.environment == "production"
+    }
+
+    pub fn is_development(&self) -> bool {
+        self.environment == DEFAULT_ENVIRONMENT
+    }
+
+    /// Where each loaded setting came from, by dotted key: the path of a
+    /// config file, `the environment` for `APP__` variables, `PORT`, or
+    /// `None` for built-in defaults. Empty for a config that wasn't loaded
+    /// from files.
+    pub fn origins(&self) -> BTreeMap<String, Option<String>> {
+        fn collect(
+            value: &config::Value,
+            key: &str,
+            origins: &mut BTreeMap<String, Option<String>>,
+        ) {
+            match &value.kind {
+                config::ValueKind::Table(table) if !table.is_empty() => {
+                    for (name, value) in table {
+                        let key = match key {
+                            "" => name.clone(),
+                            _ => format!("{key}.{name}"),
+                        };
+                        collect(value, &key, origins);
+                    }
+                }
+                _ => {
+                    origins.insert(key.to_string(), value.origin().map(str::to_string));
+                }
+            }
+        }
+
+        let mut origins = BTreeMap::new();
+        if let Some(sources) = &self.sources {
+            collect(&sources.cache, "", &mut origins);
+        }
+        origins
+    }
+
+    /// Whether `5xx` responses show internal error messages:
+    /// `server.expose_internal_errors`, or outside production
+    pub fn exposes_internal_errors(&self) -> bool {
+        self.server
+            .expose_internal_errors
+            .unwrap_or(!self.is_production())
     }
+
+    /// Whether docs are served: `docs.enabled`, or outside production
+    pub fn docs_enabled(&self) -> bool {
+        self.docs.enabled.unwrap_or(!self.is_production())
+    }
+
+    /// How log lines are written: `log.format`, or JSON in production
+    pub fn log_format(&self) -> LogFormat {
+        self.log
+            .format
+            .unwrap_or_else(|| LogFormat::default_for(&self.environment))
+    }
+}
+
+/// Config file extensions and their formats, in the order files of the same
+/// name are merged
+const FILE_FORMATS: &[(&str, config::FileFormat)] = &[
+    ("toml", config::FileFormat::Toml),
+    ("yaml", config::FileFormat::Yaml),
+    ("yml", config::FileFormat::Yaml),
+    ("json", config::FileFormat::Json),
+];
+
+/// Add those of `{base}.toml`, `{base}.yaml`, `{base}.yml` and
+/// `{base}.json` that exist
+fn add_files(
+    mut builder: config::ConfigBuilder<config::builder::DefaultState>,
+    base: &str,
+) -> config::ConfigBuilder<config::builder::DefaultState> {
+    for (extension, format) in FILE_FORMATS {
+        let path = format!("{base}.{extension}");
+        if std::path::Path::new(&path).is_file() {
+            builder = builder.add_source(config::File::new(&path, *format));
+        }
+    }
+    builder
+}
+
+/// Everything wrong with a configuration, found by [`AppConfig::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigReport {
+    /// `(key, problem)` pairs, e.g. `("server.port", "must be between 1 and 65535")`
+    pub problems: Vec<(String, String)>,
+}
+
+impl ConfigReport {
+    fn check(&mut self, ok: bool, key: &str, problem: impl Into<String>) {
+        if !ok {
+            self.problems.push((key.to_string(), problem.into()));
+        }
+    }
+
+    fn check_url(&mut self, key: &str, url: &str, schemes: &[&str]) {
+        let valid = url
+            .split_once("://")
+            .is_some_and(|(scheme, rest)| schemes.contains(&scheme) && !rest.is_empty());
+        self.check(
+            valid,
+            key,
+            format!("`{url}` is not a {} URL", schemes.join("/")),
+        );
+    }
+}
+
+impl std::fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for (key, problem) in &self.problems {
+            write!(f, "\n  - {key}: {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigReport {}
+
+/// The environment named by `--env <name>` (or `--env=<name>`), then by
+/// `APP_ENV`, defaulting to [`DEFAULT_ENVIRONMENT`]
+pub fn active_environment() -> String {
+    environment_from(std::env::args().skip(1), std::env::var("APP_ENV").ok())
+}
+
+fn environment_from(mut args: impl Iterator<Item = String>, app_env: Option<String>) -> String {
+    let mut from_args = None;
+    while let Some(arg) = args.next() {
+        if arg == "--env" {
+            from_args = args.next();
+        } else if let Some(env) = arg.strip_prefix("--env=") {
+            from_args = Some(env.to_string());
+        }
+    }
+    from_args
+        .or(app_env)
+        .filter(|env| !env.is_empty())
+        .unwrap_or_else(default_environment)
+}
+
+/// `PORT` as set by hosting platforms, unless `APP__SERVER__PORT` is set
+fn platform_port(port: Option<String>, app_port_set: bool) -> Option<String> {
+    port.filter(|port| !app_port_set && !port.trim().is_empty())
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            environment: default_environment(),
             server: ServerConfig {
                 host: "0.0.0.0".to_string(),
                 port: 3000,
+                unix_socket: None,
+                shutdown_timeout_secs: default_shutdown_timeout_secs(),
+                expose_internal_errors: None,
+                decompression: DecompressionConfig::default(),
+                proxy: ProxyConfig::default(),
+                cors: CorsConfig::default(),
+                print_routes: false,
             },
             database: DatabaseConfig {
                 url: "postgres://localhost/dy_rs".to_string(),
                 max_connections: 10,
+                replicas: Vec::new(),
+                retry: DatabaseRetryConfig::default(),
+                wait_on_startup_secs: 0,
             },
+            docs: DocsConfig::default(),
+            openapi: DocSettings::default(),
+            warmup: WarmupConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            slow_requests: SlowRequestConfig::default(),
+            #[cfg(feature = "sqlx")]
+            tenancy: TenantPoolConfig::default(),
+            pagination: PaginationConfig::default(),
+            #[allow(deprecated)]
+            client_ip: ClientIpConfig::default(),
+            mail: MailConfig::default(),
+            http_client: HttpClientConfig::default(),
+            storage: StorageConfig::default(),
+            messaging: MessagingConfig::default(),
+            log: LogConfig::default(),
+            #[cfg(feature = "auth")]
+            auth: crate::auth::AuthConfig::default(),
+            sources: None,
         }
     }
 }
 
+/// Extractor for the configuration loaded by `auto_configure`; derefs to
+/// [`AppConfig`]. Application sections have [`Section`].
+///
+/// Rejects with `500` when the app wasn't auto-configured.
+#[derive(Debug, Clone)]
+pub struct Config(pub Arc<AppConfig>);
+
+impl Deref for Config {
+    type Target = AppConfig;
+
+    fn deref(&self) -> &AppConfig {
+        &self.0
+    }
+}
+
+impl<S> FromRequestParts<S> for Config
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Config>().cloned().ok_or_else(|| {
+            ApiError::InternalServerError(
+                "No configuration loaded; call App::auto_configure".to_string(),
+            )
+        })
+    }
+}
+
+/// Extractor for a config section registered with
+/// [`App::config_section`](crate::app::App::config_section)
+///
+/// Rejects with `500` when no section of type `T` was registered.
+#[derive(Debug)]
+pub struct Section<T>(pub Arc<T>);
+
+impl<T> Clone for Section<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Section<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<S, T> FromRequestParts<S> for Section<T>
+where
+    S: Send + Sync,
+    T: Send + Sync + 'static,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Section<T>>()
+            .cloned()
+            .ok_or_else(|| {
+                ApiError::InternalServerError(format!(
+                    "Config section {} not registered; call App::config_section",
+                    std::any::type_name::<T>()
+                ))
+            })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::AppConfig;
+    use super::{AppConfig, ServerConfig, environment_from, platform_port};
+    use crate::docs::DocsUi;
+    use crate::secrets::{MASTER_KEY_ENV, MasterKey};
     use std::env;
 
     fn clear_app_env() {
@@ -70,6 +869,14 @@ mod tests {
             "APP__SERVER__PORT",
             "APP__DATABASE__URL",
             "APP__DATABASE__MAX_CONNECTIONS",
+            "APP__DOCS__UI",
+            "APP__DOCS__SPEC__YAML",
+            "APP__OPENAPI__TITLE",
+            "APP__OPENAPI__SECURITY",
+            "APP__AUTH__PASSWORD_POLICY__MIN_LENGTH",
+            "APP__AUTH__PASSWORD_POLICY__BANNED",
+            "APP__CLIENT_IP__TRUSTED_PROXIES",
+            "APP__DATABASE__REPLICAS",
         ] {
             unsafe { env::remove_var(key) };
         }
@@ -83,6 +890,11 @@ mod tests {
         assert_eq!(cfg.server.port, 3000);
         assert_eq!(cfg.database.url, "postgres://localhost/dy_rs");
         assert_eq!(cfg.database.max_connections, 10);
+        assert_eq!(cfg.database.wait_on_startup_secs, 0);
+        assert_eq!(cfg.database.retry.delay(1).as_millis(), 50);
+        assert_eq!(cfg.database.retry.delay(3).as_millis(), 200);
+        assert_eq!(cfg.database.retry.delay(40).as_millis(), 2_000);
+        assert_eq!(cfg.docs.ui, [DocsUi::Swagger]);
     }
 
     #[test]
@@ -93,6 +905,17 @@ mod tests {
             env::set_var("APP__SERVER__PORT", "4242");
             env::set_var("APP__DATABASE__URL", "postgres://example/db");
             env::set_var("APP__DATABASE__MAX_CONNECTIONS", "42");
+            env::set_var(
+                "APP__DATABASE__REPLICAS",
+                "postgres://r1/db,postgres://r2/db",
+            );
+            env::set_var("APP__DOCS__UI", "redoc,scalar");
+            env::set_var("APP__DOCS__SPEC__YAML", "/openapi.yaml");
+            env::set_var("APP__OPENAPI__TITLE", "Shop API");
+            env::set_var("APP__OPENAPI__SECURITY", "bearerAuth");
+            env::set_var("APP__AUTH__PASSWORD_POLICY__MIN_LENGTH", "12");
+            env::set_var("APP__AUTH__PASSWORD_POLICY__BANNED", "hunter2,letmein");
+            env::set_var("APP__CLIENT_IP__TRUSTED_PROXIES", "10.0.0.0/8,127.0.0.1");
         }
 
         let cfg = AppConfig::load().expect("config should load from env");
@@ -100,7 +923,372 @@ mod tests {
         assert_eq!(cfg.server.port, 4242);
         assert_eq!(cfg.database.url, "postgres://example/db");
         assert_eq!(cfg.database.max_connections, 42);
+        assert_eq!(
+            cfg.database.replicas,
+            ["postgres://r1/db", "postgres://r2/db"]
+        );
+        assert_eq!(cfg.docs.ui, [DocsUi::Redoc, DocsUi::Scalar]);
+        assert_eq!(cfg.docs.spec.json, "/api-docs/openapi.json");
+        assert_eq!(cfg.docs.spec.yaml, "/openapi.yaml");
+        assert_eq!(cfg.openapi.title.as_deref(), Some("Shop API"));
+        assert_eq!(cfg.openapi.security, ["bearerAuth"]);
+        // The deprecated section is moved into `server.proxy`
+        assert_eq!(
+            cfg.server.proxy.trusted_proxies,
+            ["10.0.0.0/8".parse().unwrap(), "127.0.0.1".parse().unwrap()]
+        );
+        #[allow(deprecated)]
+        let legacy = &cfg.client_ip.trusted_proxies;
+        assert!(legacy.is_empty());
+        #[cfg(feature = "auth")]
+        assert_eq!(
+            cfg.auth.password_policy,
+            crate::auth::password::PasswordValidator::new()
+                .min_length(12)
+                .ban(["hunter2", "letmein"])
+        );
 
         clear_app_env();
     }
+
+    #[test]
+    fn env_argument_wins_over_app_env() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let prod = Some("production".to_string());
+
+        assert_eq!(environment_from(args(&[]).into_iter(), None), "development");
+        assert_eq!(
+            environment_from(args(&[]).into_iter(), prod.clone()),
+            "production"
+        );
+        assert_eq!(
+            environment_from(args(&["--env", "staging"]).into_iter(), prod.clone()),
+            "staging"
+        );
+        assert_eq!(
+            environment_from(args(&["serve", "--env=test"]).into_iter(), prod),
+            "test"
+        );
+    }
+
+    #[test]
+    fn platform_port_yields_to_app_server_port() {
+        let port = Some("8080".to_string());
+        assert_eq!(platform_port(port.clone(), false), port);
+        assert_eq!(platform_port(port, true), None);
+        assert_eq!(platform_port(Some(" ".to_string()), false), None);
+        assert_eq!(platform_port(None, false), None);
+    }
+
+    #[tokio::test]
+    async fn binds_to_the_configured_host() {
+        let server = |host: &str| ServerConfig {
+            host: host.to_string(),
+            port: 8080,
+            unix_socket: None,
+            shutdown_timeout_secs: 30,
+            expose_internal_errors: None,
+            decompression: Default::default(),
+            proxy: Default::default(),
+            cors: Default::default(),
+            print_routes: false,
+        };
+        assert_eq!(
+            server("127.0.0.1").socket_addr().await.unwrap(),
+            "127.0.0.1:8080".parse().unwrap()
+        );
+        assert_eq!(
+            server("::").socket_addr().await.unwrap(),
+            "[::]:8080".parse().unwrap()
+        );
+        assert!(
+            server("localhost")
+                .socket_addr()
+                .await
+                .unwrap()
+                .ip()
+                .is_loopback()
+        );
+    }
+
+    #[test]
+    fn environment_profile_sits_between_default_and_local() {
+        let dir = env::temp_dir().join(format!("dy-rs-profiles-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, toml: &str| std::fs::write(dir.join(name), toml).unwrap();
+        write(
+            "default.toml",
+            "[server]\nshutdown_timeout_secs = 10\n[warmup]\nrequests = [{ path = \"/a\" }]\n",
+        );
+        write("production.toml", "[server]\nshutdown_timeout_secs = 20\n");
+        write("local.toml", "[warmup]\nrequests = [{ path = \"/b\" }]\n");
+        let dir_name = dir.to_str().unwrap();
+
+        let cfg = AppConfig::load_from(dir_name, "production").unwrap();
+        assert_eq!(cfg.environment, "production");
+        assert!(cfg.is_production());
+        assert!(!cfg.docs_enabled());
+        assert!(!cfg.exposes_internal_errors());
+        assert_eq!(cfg.server.shutdown_timeout_secs, 20);
+        assert_eq!(cfg.warmup.requests[0].path, "/b");
+
+        let cfg = AppConfig::load_from(dir_name, "development").unwrap();
+        assert_eq!(cfg.environment, "development");
+        assert!(cfg.docs_enabled());
+        assert!(cfg.exposes_internal_errors());
+        assert_eq!(cfg.server.shutdown_timeout_secs, 10);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn yaml_and_json_files_load_like_toml() {
+        let dir = env::temp_dir().join(format!("dy-rs-formats-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, contents: &str| std::fs::write(dir.join(name), contents).unwrap();
+        write(
+            "default.yaml",
+            "server:\n  port: 4000\n  shutdown_timeout_secs: 5\ndatabase:\n  replicas:\n    - postgres://replica/app\n",
+        );
+        write("production.json", r#"{"server": {"port": 5000}}"#);
+        write("local.yml", "pagination:\n  default_per_page: 7\n");
+        let dir_name = dir.to_str().unwrap();
+
+        let cfg = AppConfig::load_from(dir_name, "production").unwrap();
+        assert_eq!(cfg.server.port, 5000);
+        assert_eq!(cfg.server.shutdown_timeout_secs, 5);
+        assert_eq!(cfg.database.replicas, ["postgres://replica/app"]);
+        assert_eq!(cfg.pagination.default_per_page, 7);
+
+        // TOML and YAML files of the same name are merged, YAML last
+        write(
+            "default.toml",
+            "[server]\nport = 3500\nshutdown_timeout_secs = 9\n",
+        );
+        let cfg = AppConfig::load_from(dir_name, "development").unwrap();
+        assert_eq!(cfg.server.port, 4000);
+        assert_eq!(cfg.server.shutdown_timeout_secs, 5);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn client_ip_proxies_are_moved_into_server_proxy() {
+        use crate::secrets::Secrets;
+
+        let cipher = MasterKey::new(&MasterKey::generate()).unwrap();
+        let dir = env::temp_dir().join(format!("dy-rs-proxies-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("default.toml"),
+            format!(
+                "[server.proxy]\ntrusted_proxies = [\"10.0.0.0/8\"]\n\
+                 [client_ip]\ntrusted_proxies = [\"10.0.0.0/8\", \"192.0.2.1\"]\n\
+                 [stripe]\napi_key = \"{}\"\n",
+                cipher.encrypt("sk_live"),
+            ),
+        )
+        .unwrap();
+        let mut cfg = AppConfig::load_from(dir.to_str().unwrap(), "development").unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let expected: Vec<crate::client_ip::ProxyRange> =
+            vec!["10.0.0.0/8".parse().unwrap(), "192.0.2.1".parse().unwrap()];
+        let check = |cfg: &AppConfig| {
+            assert_eq!(cfg.server.proxy.trusted_proxies, expected);
+            #[allow(deprecated)]
+            let legacy = &cfg.client_ip.trusted_proxies;
+            assert!(legacy.is_empty());
+        };
+        check(&cfg);
+
+        // Decrypting and resolving secrets rebuild the config from its sources
+        cfg.decrypt_values(&cipher).unwrap();
+        assert_eq!(cfg.section::<String>("stripe.api_key").unwrap(), "sk_live");
+        check(&cfg);
+        cfg.resolve_secrets(&Secrets::new()).await.unwrap();
+        check(&cfg);
+    }
+
+    #[tokio::test]
+    async fn application_sections_come_from_the_same_sources() {
+        use super::Section;
+        use axum::{Extension, Router, body::Body, http::Request, routing::get};
+        use std::sync::Arc;
+        use tower::ServiceExt;
+
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Stripe {
+            api_key: String,
+            webhook_tolerance_secs: u64,
+        }
+
+        let dir = env::temp_dir().join(format!("dy-rs-sections-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("default.toml"),
+            "[stripe]\napi_key = \"sk_test\"\nwebhook_tolerance_secs = 300\n[features]\nbeta = { search = true }\n",
+        )
+        .unwrap();
+        let cfg = AppConfig::load_from(dir.to_str().unwrap(), "development").unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let stripe: Stripe = cfg.section("stripe").unwrap();
+        assert_eq!(stripe.api_key, "sk_test");
+        assert_eq!(stripe.webhook_tolerance_secs, 300);
+        assert!(cfg.section::<bool>("features.beta.search").unwrap());
+        assert!(cfg.section::<Stripe>("paypal").is_err());
+        assert!(AppConfig::default().section::<Stripe>("stripe").is_err());
+
+        let router = Router::new().route(
+            "/",
+            get(|stripe: Section<Stripe>| async move { stripe.api_key.clone() }),
+        );
+        let request = || Request::get("/").body(Body::empty()).unwrap();
+        let res = router.clone().oneshot(request()).await.unwrap();
+        assert_eq!(res.status(), axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        let res = router
+            .layer(Extension(Section(Arc::new(stripe))))
+            .oneshot(request())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(res.into_body(), 64).await.unwrap();
+        assert_eq!(body, "sk_test");
+    }
+
+    #[tokio::test]
+    async fn secrets_are_resolved_in_every_section() {
+        use crate::secrets::{FileSecrets, Secrets};
+
+        let dir = env::temp_dir().join(format!("dy-rs-config-secrets-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("secrets")).unwrap();
+        std::fs::write(dir.join("secrets/db_password"), "hunter2\n").unwrap();
+        std::fs::write(
+            dir.join("default.toml"),
+            "[database]\nurl = \"postgres://app:${secret:file:db_password}@db/app\"\n\
+             [stripe]\napi_key = \"${secret:file:db_password}\"\n",
+        )
+        .unwrap();
+        let secrets = Secrets::new().source("file", FileSecrets::new(dir.join("secrets")));
+
+        let mut cfg = AppConfig::load_from(dir.to_str().unwrap(), "development").unwrap();
+        cfg.resolve_secrets(&secrets).await.unwrap();
+        assert_eq!(cfg.database.url, "postgres://app:hunter2@db/app");
+        assert_eq!(cfg.section::<String>("stripe.api_key").unwrap(), "hunter2");
+        assert_eq!(cfg.environment, "development");
+
+        let mut missing = AppConfig::default();
+        missing.database.url = "${secret:file:missing}".to_string();
+        assert!(missing.resolve_secrets(&secrets).await.is_err());
+
+        let mut encrypted = AppConfig::default();
+        encrypted.database.url = "ENC(ppa/bd@:sv)".to_string();
+        let report = encrypted.validate().unwrap_err();
+        assert_eq!(report.problems[0].0, "database.url");
+        let secrets = secrets.cipher(
+            |ciphertext: &str| -> Result<String, crate::error::ApiError> {
+                Ok(format!(
+                    "postgres://{}",
+                    ciphertext.chars().rev().collect::<String>()
+                ))
+            },
+        );
+        encrypted.resolve_secrets(&secrets).await.unwrap();
+        assert_eq!(encrypted.database.url, "postgres://vs:@db/app");
+        assert_eq!(encrypted.validate(), Ok(()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn encrypted_values_are_decrypted_with_the_master_key() {
+        let key = MasterKey::generate();
+        let cipher = MasterKey::new(&key).unwrap();
+        let dir = env::temp_dir().join(format!("dy-rs-config-enc-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("default.toml"),
+            format!(
+                "[database]\nurl = \"{}\"\n[stripe]\napi_key = \"{}\"\n",
+                cipher.encrypt("postgres://app:hunter2@db/app"),
+                cipher.encrypt("sk_live"),
+            ),
+        )
+        .unwrap();
+        let dir = dir.to_str().unwrap();
+
+        // Without the key the values stay encrypted, and validation says why
+        let cfg = AppConfig::load_from(dir, "development").unwrap();
+        let report = cfg.validate().unwrap_err();
+        assert!(
+            report
+                .problems
+                .iter()
+                .any(|(key, problem)| key == "database.url" && problem.contains(MASTER_KEY_ENV))
+        );
+
+        unsafe { env::set_var(MASTER_KEY_ENV, &key) };
+        let cfg = AppConfig::load_from(dir, "development");
+        unsafe { env::set_var(MASTER_KEY_ENV, MasterKey::generate()) };
+        let wrong_key = AppConfig::load_from(dir, "development");
+        unsafe { env::remove_var(MASTER_KEY_ENV) };
+
+        let cfg = cfg.unwrap();
+        assert_eq!(cfg.database.url, "postgres://app:hunter2@db/app");
+        assert_eq!(cfg.section::<String>("stripe.api_key").unwrap(), "sk_live");
+        assert_eq!(cfg.validate(), Ok(()));
+        assert!(wrong_key.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        assert_eq!(AppConfig::default().validate(), Ok(()));
+
+        let mut cfg = AppConfig::default();
+        cfg.server.port = 0;
+        cfg.database.url = "localhost/dy_rs".to_string();
+        cfg.database.replicas = vec!["mysql://replica/db".to_string()];
+        cfg.docs.spec.json = "openapi.json".to_string();
+        cfg.pagination.default_per_page = 500;
+        cfg.log.level = Some("info,dy_rs=loud".to_string());
+        let report = cfg.validate().unwrap_err();
+        let keys: Vec<&str> = report
+            .problems
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "server.port",
+                "database.url",
+                "database.replicas[0]",
+                "docs.spec.json",
+                "pagination.default_per_page",
+                "log.level",
+            ]
+        );
+        assert!(
+            report.to_string().starts_with(
+                "Invalid configuration:\n  - server.port: must be between 1 and 65535\n"
+            )
+        );
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn production_rejects_the_dev_jwt_secret() {
+        let mut cfg = AppConfig {
+            environment: "production".to_string(),
+            ..AppConfig::default()
+        };
+        let report = cfg.validate().unwrap_err();
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].0, "auth.jwt_secret");
+
+        cfg.auth.jwt_secret = "a-real-secret-from-the-vault".to_string();
+        assert_eq!(cfg.validate(), Ok(()));
+    }
 }
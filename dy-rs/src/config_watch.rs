@@ -0,0 +1,258 @@
+//! Hot-reloadable configuration
+//!
+//! [`ConfigWatcher`] re-reads the configuration when a file in `config/`
+//! changes and hands the new settings to typed subscriptions, so rate
+//! limits, feature flags and the log level change without a restart:
+//!
+//! ```rust,ignore
+//! let app = App::new().auto_configure().watch_config();
+//! let mut limits = app
+//!     .config_watcher()
+//!     .unwrap()
+//!     .subscribe::<RateLimits>("rate_limits")?;
+//! tokio::spawn(async move {
+//!     while let Some(limits) = limits.changed().await {
+//!         limiter.set(&limits);
+//!     }
+//! });
+//! app.run().await
+//! ```
+//!
+//! Changes are found by polling the files' modification times, every two
+//! seconds by default. A change that fails to load or validate is logged and
+//! skipped, and subscribers keep the last good settings. Under
+//! [`App::watch_config`](crate::App::watch_config), `log.level` is applied
+//! as soon as it changes.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use serde::de::DeserializeOwned;
+use tokio::sync::watch;
+
+use crate::{config::AppConfig, secrets::Secrets};
+
+/// How often [`ConfigWatcher`] checks for changes unless told otherwise
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reloads the configuration when its files change
+pub struct ConfigWatcher {
+    dir: PathBuf,
+    interval: Duration,
+    secrets: Option<Secrets>,
+    current: watch::Sender<Arc<AppConfig>>,
+}
+
+impl ConfigWatcher {
+    /// Watch `config/` for the environment `config` was loaded for
+    pub fn new(config: AppConfig) -> Self {
+        Self::in_dir("config", config)
+    }
+
+    pub(crate) fn in_dir(dir: impl Into<PathBuf>, config: AppConfig) -> Self {
+        Self {
+            dir: dir.into(),
+            interval: DEFAULT_INTERVAL,
+            secrets: None,
+            current: watch::Sender::new(Arc::new(config)),
+        }
+    }
+
+    /// Check for changes every `interval`
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Resolve `${secret:...}` placeholders with `secrets` on every reload
+    pub fn secrets(mut self, secrets: Secrets) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    /// The last good configuration
+    pub fn current(&self) -> Arc<AppConfig> {
+        self.current.borrow().clone()
+    }
+
+    /// Every reload of the whole configuration
+    pub fn subscribe_config(&self) -> watch::Receiver<Arc<AppConfig>> {
+        self.current.subscribe()
+    }
+
+    /// The `[name]` section as a `T`, updated when it changes. See
+    /// [`AppConfig::section`].
+    pub fn subscribe<T>(&self, name: &str) -> Result<ConfigSubscription<T>, config::ConfigError>
+    where
+        T: DeserializeOwned + PartialEq,
+    {
+        let configs = self.current.subscribe();
+        let current = configs.borrow().section(name)?;
+        Ok(ConfigSubscription {
+            configs,
+            name: name.to_string(),
+            current: Arc::new(current),
+        })
+    }
+
+    /// Poll for changes until `shutdown` completes
+    pub async fn run(&self, shutdown: impl Future<Output = ()>) {
+        tokio::pin!(shutdown);
+        let mut seen = fingerprint(&self.dir);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => return,
+                _ = tokio::time::sleep(self.interval) => {}
+            }
+            let files = fingerprint(&self.dir);
+            if files != seen {
+                seen = files;
+                self.reload().await;
+            }
+        }
+    }
+
+    /// Load the configuration again and publish it if it's valid
+    pub async fn reload(&self) -> bool {
+        let environment = self.current().environment.clone();
+        let dir = self.dir.to_string_lossy();
+        let mut config = match AppConfig::load_from(&dir, &environment) {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to reload configuration, keeping the previous one");
+                return false;
+            }
+        };
+        if let Some(secrets) = &self.secrets
+            && let Err(e) = config.resolve_secrets(secrets).await
+        {
+            tracing::error!(error = %e, "Failed to resolve reloaded secrets, keeping the previous configuration");
+            return false;
+        }
+        if let Err(report) = config.validate() {
+            tracing::error!("{report}; keeping the previous configuration");
+            return false;
+        }
+        tracing::info!("🔄 Configuration reloaded");
+        self.current.send_replace(Arc::new(config));
+        true
+    }
+}
+
+/// A config section kept up to date by a [`ConfigWatcher`]
+pub struct ConfigSubscription<T> {
+    configs: watch::Receiver<Arc<AppConfig>>,
+    name: String,
+    current: Arc<T>,
+}
+
+impl<T> Clone for ConfigSubscription<T> {
+    fn clone(&self) -> Self {
+        Self {
+            configs: self.configs.clone(),
+            name: self.name.clone(),
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + PartialEq> ConfigSubscription<T> {
+    /// The section as of the last reload that changed it
+    pub fn current(&self) -> Arc<T> {
+        self.current.clone()
+    }
+
+    /// Wait for a reload that changes the section and return the new value;
+    /// `None` once the watcher is gone. A reload leaving the section invalid
+    /// is logged and skipped.
+    pub async fn changed(&mut self) -> Option<Arc<T>> {
+        loop {
+            self.configs.changed().await.ok()?;
+            let config = self.configs.borrow_and_update().clone();
+            match config.section::<T>(&self.name) {
+                Ok(section) if section != *self.current => {
+                    self.current = Arc::new(section);
+                    return Some(self.current.clone());
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!(section = %self.name, error = %e, "Invalid reloaded config section, keeping the previous one");
+                }
+            }
+        }
+    }
+}
+
+/// Name, size and modification time of every file in `dir`
+fn fingerprint(dir: &Path) -> Vec<(PathBuf, u64, Option<SystemTime>)> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata
+                .is_file()
+                .then(|| (entry.path(), metadata.len(), metadata.modified().ok()))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Limits {
+        per_minute: u32,
+    }
+
+    #[tokio::test]
+    async fn subscriptions_see_valid_changes() {
+        let dir = std::env::temp_dir().join(format!("dy-rs-watch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |per_minute: u32, port: u16| {
+            std::fs::write(
+                dir.join("default.toml"),
+                format!("[server]\nport = {port}\n[limits]\nper_minute = {per_minute}\n"),
+            )
+            .unwrap();
+        };
+        write(60, 3000);
+        let config = AppConfig::load_from(dir.to_str().unwrap(), "development").unwrap();
+        let watcher = ConfigWatcher::in_dir(&dir, config).interval(Duration::from_millis(10));
+        let mut limits = watcher.subscribe::<Limits>("limits").unwrap();
+        assert_eq!(limits.current().per_minute, 60);
+        assert!(watcher.subscribe::<Limits>("missing").is_err());
+
+        // An invalid configuration is not published
+        write(120, 0);
+        assert!(!watcher.reload().await);
+        assert_eq!(watcher.current().server.port, 3000);
+
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let changed = async {
+            // Once the watcher has looked at the files
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            write(120, 3000);
+            let changed = limits.changed().await.unwrap();
+            stop.send(()).unwrap();
+            changed
+        };
+        let ((), changed) = tokio::join!(
+            watcher.run(async {
+                stopped.await.ok();
+            }),
+            changed
+        );
+        assert_eq!(changed.per_minute, 120);
+        assert_eq!(limits.current().per_minute, 120);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
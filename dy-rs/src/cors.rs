@@ -0,0 +1,168 @@
+//! Cross-origin requests
+//!
+//! Auto-configured apps answer CORS preflights as the `[server.cors]` config
+//! section says. Outside `production` any origin may call the API; in
+//! `production` none may until `allowed_origins` lists them:
+//!
+//! ```toml
+//! [server.cors]
+//! allowed_origins = ["https://app.example.com"]
+//! allowed_headers = ["authorization", "content-type"]
+//! allow_credentials = true
+//! max_age_secs = 600
+//! ```
+//!
+//! `"*"` allows any origin. With `allow_credentials`, the allowed origin and
+//! headers are those of the request, as browsers reject wildcards there.
+
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use serde::{Deserialize, Serialize};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+/// Any origin, in `allowed_origins`
+pub const ANY_ORIGIN: &str = "*";
+
+/// CORS settings, the `[server.cors]` config section
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API, such as `https://app.example.com`, or
+    /// `*`; by default any outside `production` and none in it
+    pub allowed_origins: Option<Vec<String>>,
+    /// Request headers allowed; by default any
+    pub allowed_headers: Option<Vec<String>>,
+    /// Whether browsers send cookies and `Authorization` along
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight answer
+    pub max_age_secs: Option<u64>,
+}
+
+impl CorsConfig {
+    /// The allowed origins, `allowed_origins` or the environment's default
+    pub fn origins(&self, production: bool) -> Vec<String> {
+        self.allowed_origins.clone().unwrap_or_else(|| {
+            if production {
+                Vec::new()
+            } else {
+                vec![ANY_ORIGIN.to_string()]
+            }
+        })
+    }
+
+    /// The layer answering cross-origin requests
+    pub fn layer(&self, production: bool) -> CorsLayer {
+        let origins = self.origins(production);
+        let any_origin = origins.iter().any(|origin| origin == ANY_ORIGIN);
+        let origin = if any_origin && self.allow_credentials {
+            AllowOrigin::mirror_request()
+        } else if any_origin {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(
+                origins
+                    .iter()
+                    .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+            )
+        };
+        let headers = match &self.allowed_headers {
+            Some(headers) => AllowHeaders::list(
+                headers
+                    .iter()
+                    .filter_map(|header| HeaderName::try_from(header.as_str()).ok()),
+            ),
+            None if self.allow_credentials => AllowHeaders::mirror_request(),
+            None => AllowHeaders::any(),
+        };
+
+        let mut layer = CorsLayer::new()
+            .allow_methods([
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::DELETE,
+                Method::PATCH,
+            ])
+            .allow_origin(origin)
+            .allow_headers(headers)
+            .allow_credentials(self.allow_credentials);
+        if let Some(secs) = self.max_age_secs {
+            layer = layer.max_age(Duration::from_secs(secs));
+        }
+        layer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::Body,
+        extract::Request,
+        http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, ORIGIN},
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    async fn allowed_origin(config: &CorsConfig, production: bool, origin: &str) -> Option<String> {
+        let router = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(config.layer(production));
+        let request = Request::get("/")
+            .header(ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        response
+            .headers()
+            .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn production_allows_only_listed_origins() {
+        let defaults = CorsConfig::default();
+        assert_eq!(
+            allowed_origin(&defaults, false, "https://any.example")
+                .await
+                .as_deref(),
+            Some("*")
+        );
+        assert_eq!(
+            allowed_origin(&defaults, true, "https://any.example").await,
+            None
+        );
+
+        let listed = CorsConfig {
+            allowed_origins: Some(vec!["https://app.example.com".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            allowed_origin(&listed, true, "https://app.example.com")
+                .await
+                .as_deref(),
+            Some("https://app.example.com")
+        );
+        assert_eq!(
+            allowed_origin(&listed, true, "https://evil.example").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials_mirror_the_origin() {
+        let config = CorsConfig {
+            allowed_origins: Some(vec![ANY_ORIGIN.to_string()]),
+            allow_credentials: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            allowed_origin(&config, true, "https://app.example.com")
+                .await
+                .as_deref(),
+            Some("https://app.example.com")
+        );
+    }
+}
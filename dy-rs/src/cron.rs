@@ -0,0 +1,251 @@
+//! Cron expressions for recurring tasks
+//!
+//! Standard five-field expressions (`minute hour day-of-month month
+//! day-of-week`) evaluated in UTC. Fields accept `*`, values, ranges (`1-5`),
+//! steps (`*/15`, `0-30/10`) and comma-separated lists; months and weekdays
+//! also accept names (`JAN`, `MON`). As in Vixie cron, when both day fields
+//! are restricted a day matching either one is used.
+//!
+//! ```rust
+//! use dy_rs::cron::CronSchedule;
+//!
+//! let weekday_mornings: CronSchedule = "0 8 * * MON-FRI".parse().unwrap();
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+
+const MONTHS: &[&str] = &[
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// An invalid cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronError(String);
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cron expression: {}", self.0)
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// Parse a five-field cron expression
+    pub fn parse(expr: &str) -> Result<Self, CronError> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(CronError(format!(
+                "expected 5 fields, found {} in `{expr}`",
+                fields.len()
+            )));
+        };
+
+        // Sunday is both 0 and 7.
+        let mut weekdays = parse_field(weekday, 0, 7, WEEKDAYS)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            source: expr.trim().to_string(),
+            minutes: parse_field(minute, 0, 59, &[])?,
+            hours: parse_field(hour, 0, 23, &[])?,
+            days: parse_field(day, 1, 31, &[])?,
+            months: parse_field(month, 1, 12, MONTHS)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Whether the schedule fires at the minute containing `time`
+    pub fn matches(&self, time: DateTime<Utc>) -> bool {
+        self.day_matches(time) && bit(self.hours, time.hour()) && bit(self.minutes, time.minute())
+    }
+
+    /// First time strictly after `after` the schedule fires, if any within
+    /// the next five years
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = after + Duration::days(5 * 366);
+
+        while time <= limit {
+            if !self.day_matches(time) {
+                time = (time + Duration::days(1)).with_hour(0)?.with_minute(0)?;
+            } else if !bit(self.hours, time.hour()) {
+                time = (time + Duration::hours(1)).with_minute(0)?;
+            } else if !bit(self.minutes, time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, time: DateTime<Utc>) -> bool {
+        if !bit(self.months, time.month()) {
+            return false;
+        }
+        let day = bit(self.days, time.day());
+        let weekday = bit(self.weekdays, time.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, CronError> {
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| CronError(format!("invalid step in `{part}`")))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                parse_value(start, names, min)?,
+                parse_value(end, names, min)?,
+            )
+        } else {
+            let value = parse_value(range, names, min)?;
+            // `5/10` means every 10 starting at 5.
+            (value, if step > 1 { max } else { value })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(CronError(format!("`{part}` is outside {min}-{max}")));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(value: &str, names: &[&str], min: u32) -> Result<u32, CronError> {
+    if let Some(index) = names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+    {
+        return Ok(index as u32 + min);
+    }
+    value
+        .parse()
+        .map_err(|_| CronError(format!("invalid value `{value}`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn finds_the_next_run() {
+        let daily: CronSchedule = "30 8 * * *".parse().unwrap();
+        assert_eq!(
+            daily.next_after(at(2024, 1, 1, 8, 30)),
+            Some(at(2024, 1, 2, 8, 30))
+        );
+        assert_eq!(
+            daily.next_after(at(2024, 1, 1, 7, 59)),
+            Some(at(2024, 1, 1, 8, 30))
+        );
+
+        let quarter_hours: CronSchedule = "*/15 * * * *".parse().unwrap();
+        assert_eq!(
+            quarter_hours.next_after(at(2024, 1, 1, 23, 50)),
+            Some(at(2024, 1, 2, 0, 0))
+        );
+
+        // 2024-01-01 is a Monday.
+        let mondays: CronSchedule = "0 9 * * MON".parse().unwrap();
+        assert_eq!(
+            mondays.next_after(at(2024, 1, 1, 10, 0)),
+            Some(at(2024, 1, 8, 9, 0))
+        );
+
+        let leap_day: CronSchedule = "0 0 29 FEB *".parse().unwrap();
+        assert_eq!(
+            leap_day.next_after(at(2024, 3, 1, 0, 0)),
+            Some(at(2028, 2, 29, 0, 0))
+        );
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        let schedule: CronSchedule = "0 0 1 * SUN".parse().unwrap();
+        // 2024-01-07 is a Sunday, 2024-02-01 a Thursday.
+        assert!(schedule.matches(at(2024, 1, 7, 0, 0)));
+        assert!(schedule.matches(at(2024, 2, 1, 0, 0)));
+        assert!(!schedule.matches(at(2024, 2, 2, 0, 0)));
+
+        let sunday_as_seven: CronSchedule = "0 0 * * 7".parse().unwrap();
+        assert!(sunday_as_seven.matches(at(2024, 1, 7, 0, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "* * * * MON-XYZ",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(CronSchedule::parse(expr).is_err(), "{expr}");
+        }
+    }
+}
@@ -1,4 +0,0 @@
-// Database utilities and helpers
-// TODO: Add repository pattern, query helpers, etc.
-
-pub use sqlx::{PgPool, Postgres, Transaction};
@@ -0,0 +1,53 @@
+//! diesel-async Postgres pools as a [`Database`]
+
+use async_trait::async_trait;
+use diesel_async::{
+    AsyncPgConnection, SimpleAsyncConnection,
+    pooled_connection::{AsyncDieselConnectionManager, deadpool},
+};
+
+use super::Database;
+use crate::{config::DatabaseConfig, error::ApiError};
+
+/// A deadpool of diesel-async Postgres connections
+pub type DieselPool = deadpool::Pool<AsyncPgConnection>;
+
+/// A [`DieselPool`] for `config.url`, connecting on first use
+pub fn diesel_pool(config: &DatabaseConfig) -> Result<DieselPool, ApiError> {
+    let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(&config.url);
+    deadpool::Pool::builder(manager)
+        .max_size(config.max_connections as usize)
+        .build()
+        .map_err(ApiError::database)
+}
+
+#[async_trait]
+impl Database for DieselPool {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        let mut connection = self.get().await?;
+        connection.batch_execute("SELECT 1").await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unreachable_databases_fail_the_ping() {
+        let config = DatabaseConfig {
+            url: "postgres://dy:dy@127.0.0.1:1/dy".to_string(),
+            max_connections: 1,
+            ..crate::config::AppConfig::default().database
+        };
+        let pool = diesel_pool(&config).unwrap();
+        assert_eq!(pool.backend(), "postgres");
+        let err = pool.ping().await.unwrap_err();
+        assert!(matches!(err, ApiError::DatabaseError(_)));
+    }
+}
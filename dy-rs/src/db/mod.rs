@@ -0,0 +1,293 @@
+//! Database access
+//!
+//! [`App::with_database`](crate::app::App::with_database) shares a database
+//! handle with handlers through the [`Db`] extractor and makes `/ready`
+//! check that the database answers. Any handle implementing [`Database`]
+//! works; with the default `sqlx` feature that includes `PgPool`:
+//!
+//! ```rust,ignore
+//! async fn count_users(Db(pool): Db<PgPool>) -> ApiResult<i64> {
+//!     let count = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+//!         .fetch_one(&pool)
+//!         .await?;
+//!     Ok(Json(count))
+//! }
+//!
+//! App::new().with_database(pool).mount(router);
+//! ```
+//!
+//! Apps on SeaORM or diesel-async don't need sqlx: build without default
+//! features and enable `sea-orm` or `diesel-async` instead. The first makes
+//! SeaORM's `DatabaseConnection` a [`Database`], the second a [`DieselPool`]
+//! of diesel-async Postgres connections, and both turn their errors into
+//! [`ApiError`] with `?`. A timed-out pool acquire answers `503`, like
+//! sqlx's:
+//!
+//! ```rust,ignore
+//! async fn list_users(Db(db): Db<DatabaseConnection>) -> ApiResult<Vec<user::Model>> {
+//!     Ok(Json(user::Entity::find().all(&db).await?))
+//! }
+//!
+//! let connection = sea_orm::Database::connect(&config.database.url).await?;
+//! App::new().with_database(connection).mount(router);
+//!
+//! async fn count_users(Db(pool): Db<DieselPool>) -> ApiResult<i64> {
+//!     let mut conn = pool.get().await?;
+//!     Ok(Json(users::table.count().get_result(&mut conn).await?))
+//! }
+//!
+//! App::new().with_database(diesel_pool(&config.database)?).mount(router);
+//! ```
+//!
+//! The SeaORM driver and runtime features are the app's to pick, in its own
+//! `sea-orm` dependency. Other libraries plug in through a wrapper
+//! implementing [`Database`] in the app, and turn their errors into
+//! [`ApiError::DatabaseError`] with [`ApiError::database`].
+//!
+//! With `database.wait_on_startup_secs` set, `App::run` waits for the
+//! database to answer before serving, which helps containers that start
+//! alongside it.
+//!
+//! # sqlx
+//!
+//! [`Repository`] runs the usual queries against the table an [`Entity`]
+//! maps to, so simple models need no hand-written SQL. Derive the mapping
+//! next to sqlx's `FromRow`:
+//!
+//! ```rust,ignore
+//! use dy_rs::db::{Entity, Repository};
+//!
+//! #[derive(Serialize, sqlx::FromRow, Entity)]
+//! #[entity(table = "users")]
+//! struct User {
+//!     id: Uuid,
+//!     email: String,
+//!     name: String,
+//!     /// Filled in by the database default, never written
+//!     #[entity(generated)]
+//!     created_at: DateTime<Utc>,
+//! }
+//!
+//! let users = Repository::<User, Uuid>::new(pool);
+//! let user = users.insert(&user).await?;
+//! let page = users.find_page(&pagination).await?;
+//! ```
+//!
+//! The ID column is the `id` field unless another one is marked
+//! `#[entity(id)]`. Table and column names are written into the SQL as
+//! they are, so they must be trusted identifiers.
+//!
+//! Handlers that need several statements to apply together take a [`Tx`]
+//! behind a [`TxLayer`], which commits when they succeed and rolls back
+//! when they fail. [`DbPools`] sends reads to replicas and writes to the
+//! primary, and [`retry`] tries work again after transient errors.
+//! [`testing::TestDatabase`] gives each test its own migrated schema.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+use crate::{config::DatabaseRetryConfig, error::ApiError};
+
+#[cfg(feature = "diesel-async")]
+mod diesel_pg;
+#[cfg(feature = "sqlx")]
+mod pools;
+#[cfg(feature = "sqlx")]
+mod repository;
+#[cfg(feature = "sqlx")]
+mod retry;
+#[cfg(feature = "sea-orm")]
+mod sea;
+#[cfg(feature = "sqlx")]
+pub mod testing;
+#[cfg(feature = "sqlx")]
+mod tx;
+
+#[cfg(feature = "diesel-async")]
+pub use diesel_pg::{DieselPool, diesel_pool};
+#[cfg(feature = "sqlx")]
+pub use dy_rs_macros::Entity;
+#[cfg(feature = "sqlx")]
+pub use pools::{DbPools, ReadDb, WriteDb};
+#[cfg(feature = "sqlx")]
+pub use repository::{Entity, Repository};
+#[cfg(feature = "sqlx")]
+pub use retry::{is_transient, retry};
+#[cfg(feature = "sqlx")]
+pub use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+#[cfg(feature = "sqlx")]
+pub use tx::{Tx, TxLayer, TxService};
+
+/// A database handle the app can check on
+#[async_trait]
+pub trait Database: Send + Sync + 'static {
+    /// Short name of the backend for logs, e.g. `postgres`
+    fn backend(&self) -> &'static str;
+
+    /// Check that the database answers
+    async fn ping(&self) -> Result<(), ApiError>;
+}
+
+/// Type-erased [`Database`], kept by the app for the readiness probe
+#[derive(Clone)]
+pub struct SharedDatabase(Arc<dyn Database>);
+
+impl SharedDatabase {
+    pub fn new(database: impl Database) -> Self {
+        Self(Arc::new(database))
+    }
+}
+
+impl From<Arc<dyn Database>> for SharedDatabase {
+    fn from(database: Arc<dyn Database>) -> Self {
+        Self(database)
+    }
+}
+
+#[async_trait]
+impl Database for SharedDatabase {
+    fn backend(&self) -> &'static str {
+        self.0.backend()
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.0.ping().await
+    }
+}
+
+#[cfg(feature = "sqlx")]
+#[async_trait]
+impl Database for PgPool {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        sqlx::query("SELECT 1").execute(self).await?;
+        Ok(())
+    }
+}
+
+/// Ping `database` until it answers, backing off as `policy` says between
+/// attempts, and give up with the last error once `timeout` has passed
+pub async fn wait_for(
+    database: &impl Database,
+    timeout: Duration,
+    policy: &DatabaseRetryConfig,
+) -> Result<(), ApiError> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut attempt = 1;
+    loop {
+        let Err(err) = database.ping().await else {
+            return Ok(());
+        };
+        let delay = policy.delay(attempt);
+        if tokio::time::Instant::now() + delay > deadline {
+            return Err(err);
+        }
+        tracing::info!(backend = database.backend(), error = %err, "Waiting for the database");
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Extractor for the handle passed to
+/// [`App::with_database`](crate::app::App::with_database)
+///
+/// Rejects with `500` when the app has no database of type `D`.
+#[derive(Debug, Clone)]
+pub struct Db<D>(pub D);
+
+impl<S, D> FromRequestParts<S> for Db<D>
+where
+    S: Send + Sync,
+    D: Clone + Send + Sync + 'static,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<D>().cloned().map(Db).ok_or_else(|| {
+            ApiError::InternalServerError(
+                "No database configured; call App::with_database".to_string(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Extension, Router, body::Body, http::Request, http::StatusCode, routing::get};
+    use tower::ServiceExt;
+
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[derive(Clone)]
+    struct Fake(&'static str);
+
+    #[async_trait]
+    impl Database for Fake {
+        fn backend(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn ping(&self) -> Result<(), ApiError> {
+            Err(ApiError::database(self.0))
+        }
+    }
+
+    #[tokio::test]
+    async fn handlers_get_the_typed_handle() {
+        let database = SharedDatabase::new(Fake("down"));
+        assert_eq!(database.backend(), "fake");
+        let err = database.ping().await.unwrap_err();
+        assert_eq!(err.to_string(), "Database error: down");
+
+        let router = Router::new().route("/", get(|Db(db): Db<Fake>| async move { db.0 }));
+        let request = || Request::get("/").body(Body::empty()).unwrap();
+        let status = router.clone().oneshot(request()).await.unwrap().status();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let res = router
+            .layer(Extension(Fake("up")))
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn waits_until_the_database_answers() {
+        struct Starting(AtomicU32);
+
+        #[async_trait]
+        impl Database for Starting {
+            fn backend(&self) -> &'static str {
+                "starting"
+            }
+
+            async fn ping(&self) -> Result<(), ApiError> {
+                match self.0.fetch_add(1, Ordering::SeqCst) {
+                    0..3 => Err(ApiError::database("not yet")),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let policy = DatabaseRetryConfig {
+            max_attempts: 1,
+            initial_delay_ms: 1,
+            max_delay_ms: 5,
+        };
+        let starting = Starting(AtomicU32::new(0));
+        wait_for(&starting, Duration::from_secs(5), &policy)
+            .await
+            .unwrap();
+        assert_eq!(starting.0.load(Ordering::SeqCst), 4);
+
+        let down = Fake("down");
+        let err = wait_for(&down, Duration::from_millis(20), &policy).await;
+        assert_eq!(err.unwrap_err().to_string(), "Database error: down");
+    }
+}
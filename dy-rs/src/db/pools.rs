@@ -0,0 +1,266 @@
+//! Read replicas
+//!
+//! [`DbPools`] pairs the primary pool with read replicas. Writes go to
+//! [`DbPools::primary`]; reads take a connection from [`DbPools::reader`],
+//! which cycles through the replicas and falls back to the primary when
+//! none of them hands out a connection. Handlers choose per query through
+//! those methods, or per handler with the [`ReadDb`] and [`WriteDb`]
+//! extractors:
+//!
+//! ```rust,ignore
+//! async fn list_posts(mut db: ReadDb) -> ApiResult<Vec<Post>> {
+//!     let posts = sqlx::query_as("SELECT * FROM posts")
+//!         .fetch_all(&mut *db)
+//!         .await?;
+//!     Ok(Json(posts))
+//! }
+//!
+//! let pools = DbPools::connect_lazy(&config.database)?;
+//! App::new().with_database(pools).mount(router);
+//! ```
+//!
+//! Replication lag means a read right after a write may not see it yet;
+//! read from the primary when that matters.
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use sqlx::{
+    PgConnection, PgPool, Postgres,
+    pool::PoolConnection,
+    postgres::{PgConnectOptions, PgPoolOptions},
+};
+
+use super::Database;
+use crate::{config::DatabaseConfig, error::ApiError};
+
+/// The primary pool and its read replicas
+#[derive(Debug, Clone)]
+pub struct DbPools {
+    primary: PgPool,
+    replicas: Arc<Vec<PgPool>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl DbPools {
+    pub fn new(primary: PgPool) -> Self {
+        Self {
+            primary,
+            replicas: Arc::default(),
+            next: Arc::default(),
+        }
+    }
+
+    /// Add a read replica
+    pub fn replica(mut self, pool: PgPool) -> Self {
+        Arc::make_mut(&mut self.replicas).push(pool);
+        self
+    }
+
+    /// Pools for `config.url` and `config.replicas`, connecting on first use
+    pub fn connect_lazy(config: &DatabaseConfig) -> Result<Self, ApiError> {
+        let connect = |url: &str| -> Result<PgPool, ApiError> {
+            let options: PgConnectOptions = url.parse()?;
+            Ok(PgPoolOptions::new()
+                .max_connections(config.max_connections)
+                .connect_lazy_with(options))
+        };
+        config
+            .replicas
+            .iter()
+            .try_fold(Self::new(connect(&config.url)?), |pools, url| {
+                Ok(pools.replica(connect(url)?))
+            })
+    }
+
+    /// The pool for writes, and for reads that must see them
+    pub fn primary(&self) -> &PgPool {
+        &self.primary
+    }
+
+    pub fn replicas(&self) -> &[PgPool] {
+        &self.replicas
+    }
+
+    /// A connection for read-only queries, from the next replica that hands
+    /// one out, or from the primary if none does
+    pub async fn reader(&self) -> Result<PoolConnection<Postgres>, ApiError> {
+        for replica in self.read_order() {
+            match replica.acquire().await {
+                Ok(connection) => return Ok(connection),
+                Err(err) => {
+                    tracing::warn!(error = %err, "Read replica unavailable, trying the next one");
+                }
+            }
+        }
+        Ok(self.primary.acquire().await?)
+    }
+
+    /// Replicas in the order the next read tries them, rotating by one per read
+    fn read_order(&self) -> impl Iterator<Item = &PgPool> {
+        let start = match self.replicas.len() {
+            0 => 0,
+            len => self.next.fetch_add(1, Ordering::Relaxed) % len,
+        };
+        self.replicas[start..].iter().chain(&self.replicas[..start])
+    }
+
+    fn from_parts(parts: &Parts) -> Result<&Self, ApiError> {
+        parts.extensions.get::<DbPools>().ok_or_else(|| {
+            ApiError::InternalServerError(
+                "No DbPools configured; call App::with_database".to_string(),
+            )
+        })
+    }
+}
+
+/// The primary is checked; reads fall back to it when replicas are down
+#[async_trait]
+impl Database for DbPools {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.primary.ping().await
+    }
+}
+
+/// Extractor for a read-only connection from [`DbPools::reader`]
+///
+/// Derefs to the connection; rejects with `500` when the app has no
+/// [`DbPools`], and with `503` when no pool hands out a connection in time.
+pub struct ReadDb(PoolConnection<Postgres>);
+
+impl Deref for ReadDb {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        &self.0
+    }
+}
+
+impl DerefMut for ReadDb {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        &mut self.0
+    }
+}
+
+impl<S> FromRequestParts<S> for ReadDb
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let pools = DbPools::from_parts(parts)?.clone();
+        Ok(Self(pools.reader().await?))
+    }
+}
+
+/// Extractor for the primary pool of [`DbPools`], derefs to [`PgPool`]
+pub struct WriteDb(PgPool);
+
+impl Deref for WriteDb {
+    type Target = PgPool;
+
+    fn deref(&self) -> &PgPool {
+        &self.0
+    }
+}
+
+impl<S> FromRequestParts<S> for WriteDb
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(DbPools::from_parts(parts)?.primary.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Extension, Router, body::Body, http::Request, http::StatusCode, routing::get};
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    fn pool(database: &str) -> PgPool {
+        PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy(&format!("postgres://dy_rs@127.0.0.1:1/{database}"))
+            .unwrap()
+    }
+
+    fn names<'a>(pools: impl Iterator<Item = &'a PgPool>) -> Vec<String> {
+        pools
+            .map(|pool| pool.connect_options().get_database().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reads_rotate_through_the_replicas() {
+        let pools = DbPools::new(pool("primary"))
+            .replica(pool("r1"))
+            .replica(pool("r2"));
+
+        let clone = pools.clone();
+
+        assert_eq!(names(pools.read_order()), ["r1", "r2"]);
+        // Clones share the rotation
+        assert_eq!(names(clone.read_order()), ["r2", "r1"]);
+        assert_eq!(names(pools.read_order()), ["r1", "r2"]);
+        assert_eq!(names(DbPools::new(pool("primary")).read_order()).len(), 0);
+    }
+
+    #[tokio::test]
+    async fn builds_pools_from_config() {
+        let config = DatabaseConfig {
+            url: "postgres://localhost/app".to_string(),
+            max_connections: 4,
+            replicas: vec!["postgres://replica/app".to_string()],
+            ..crate::config::AppConfig::default().database
+        };
+        let pools = DbPools::connect_lazy(&config).unwrap();
+        assert_eq!(pools.replicas().len(), 1);
+        assert_eq!(pools.primary().options().get_max_connections(), 4);
+
+        let invalid = DatabaseConfig {
+            replicas: vec!["not a url".to_string()],
+            ..config
+        };
+        assert!(DbPools::connect_lazy(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn extractors_need_the_pools() {
+        let router = Router::new()
+            .route("/read", get(|_db: ReadDb| async { "ok" }))
+            .route("/write", get(|_db: WriteDb| async { "ok" }));
+        let status = |router: Router, uri: &'static str| async move {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            router.oneshot(request).await.unwrap().status()
+        };
+
+        assert_eq!(
+            status(router.clone(), "/write").await,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        let router = router.layer(Extension(DbPools::new(pool("primary")).replica(pool("r1"))));
+        assert_eq!(status(router.clone(), "/write").await, StatusCode::OK);
+        // Neither the replica nor the primary is reachable
+        assert_eq!(
+            status(router, "/read").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
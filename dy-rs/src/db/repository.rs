@@ -0,0 +1,226 @@
+//! Queries on the table an [`Entity`] maps to
+
+use std::marker::PhantomData;
+
+use sqlx::{Encode, FromRow, PgPool, Postgres, QueryBuilder, Type, postgres::PgRow};
+
+use crate::{error::ApiError, pagination::Pagination};
+
+/// A model stored as a row of a table
+///
+/// Usually derived; see the [module docs](super).
+pub trait Entity: for<'r> FromRow<'r, PgRow> + Send + Sync + Unpin + 'static {
+    /// Table name, optionally schema-qualified
+    const TABLE: &'static str;
+    /// Primary key column
+    const ID_COLUMN: &'static str;
+    /// Columns written by inserts and updates, leaving out the ones the
+    /// database fills in
+    const COLUMNS: &'static [&'static str];
+
+    /// Bind this entity's value for `column`, one of [`COLUMNS`](Self::COLUMNS)
+    fn push_bind<'q>(&'q self, column: &str, query: &mut QueryBuilder<'q, Postgres>);
+}
+
+/// Queries on the table of `T`, whose primary key is an `Id`
+pub struct Repository<T, Id> {
+    pool: PgPool,
+    _marker: PhantomData<fn() -> (T, Id)>,
+}
+
+impl<T, Id> Clone for Repository<T, Id> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, Id> Repository<T, Id>
+where
+    T: Entity,
+    Id: for<'q> Encode<'q, Postgres> + Type<Postgres> + Send + Sync,
+{
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub async fn find(&self, id: &Id) -> Result<Option<T>, ApiError> {
+        let mut query = select::<T>();
+        query.push(" WHERE ").push(T::ID_COLUMN).push(" = ");
+        query.push_bind(id);
+        Ok(query
+            .build_query_as::<T>()
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Every row, ordered by ID
+    pub async fn find_all(&self) -> Result<Vec<T>, ApiError> {
+        let mut query = select::<T>();
+        query.push(" ORDER BY ").push(T::ID_COLUMN);
+        Ok(query.build_query_as::<T>().fetch_all(&self.pool).await?)
+    }
+
+    /// The rows `pagination` asks for, ordered by ID, and the total count,
+    /// ready for [`Page::new`](crate::pagination::Page::new)
+    pub async fn find_page(&self, pagination: &Pagination) -> Result<(Vec<T>, u64), ApiError> {
+        let mut query = page::<T>(pagination);
+        let rows = query.build_query_as::<T>().fetch_all(&self.pool).await?;
+        Ok((rows, self.count().await?))
+    }
+
+    /// Insert `entity` and return the stored row, with generated columns
+    /// filled in
+    pub async fn insert(&self, entity: &T) -> Result<T, ApiError> {
+        let mut query = insert(entity);
+        Ok(query.build_query_as::<T>().fetch_one(&self.pool).await?)
+    }
+
+    /// Overwrite the row with ID `id` with `entity`, returning the stored
+    /// row, or `None` if there is no such row
+    ///
+    /// The ID column itself is never changed.
+    pub async fn update(&self, id: &Id, entity: &T) -> Result<Option<T>, ApiError> {
+        let mut query = update(entity);
+        query.push_bind(id).push(" RETURNING *");
+        Ok(query
+            .build_query_as::<T>()
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Whether there was a row to delete
+    pub async fn delete(&self, id: &Id) -> Result<bool, ApiError> {
+        let mut query = QueryBuilder::new("DELETE FROM ");
+        query
+            .push(T::TABLE)
+            .push(" WHERE ")
+            .push(T::ID_COLUMN)
+            .push(" = ");
+        query.push_bind(id);
+        let result = query.build().execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn count(&self) -> Result<u64, ApiError> {
+        let mut query = QueryBuilder::new("SELECT COUNT(*) FROM ");
+        query.push(T::TABLE);
+        let count: i64 = query.build_query_scalar().fetch_one(&self.pool).await?;
+        Ok(count as u64)
+    }
+}
+
+fn select<'q, T: Entity>() -> QueryBuilder<'q, Postgres> {
+    let mut query = QueryBuilder::new("SELECT * FROM ");
+    query.push(T::TABLE);
+    query
+}
+
+fn page<'q, T: Entity>(pagination: &Pagination) -> QueryBuilder<'q, Postgres> {
+    let mut query = select::<T>();
+    query.push(" ORDER BY ").push(T::ID_COLUMN).push(" LIMIT ");
+    query.push_bind(pagination.limit as i64).push(" OFFSET ");
+    query.push_bind(pagination.offset as i64);
+    query
+}
+
+fn insert<T: Entity>(entity: &T) -> QueryBuilder<'_, Postgres> {
+    let mut query = QueryBuilder::new("INSERT INTO ");
+    query.push(T::TABLE).push(" (");
+    query.push(T::COLUMNS.join(", ")).push(") VALUES (");
+    for (i, column) in T::COLUMNS.iter().enumerate() {
+        if i > 0 {
+            query.push(", ");
+        }
+        entity.push_bind(column, &mut query);
+    }
+    query.push(") RETURNING *");
+    query
+}
+
+/// `UPDATE .. WHERE id = `, waiting for the ID to be bound
+fn update<T: Entity>(entity: &T) -> QueryBuilder<'_, Postgres> {
+    let mut query = QueryBuilder::new("UPDATE ");
+    query.push(T::TABLE).push(" SET ");
+    let columns = T::COLUMNS.iter().filter(|column| **column != T::ID_COLUMN);
+    for (i, column) in columns.enumerate() {
+        if i > 0 {
+            query.push(", ");
+        }
+        query.push(column).push(" = ");
+        entity.push_bind(column, &mut query);
+    }
+    query.push(" WHERE ").push(T::ID_COLUMN).push(" = ");
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(sqlx::FromRow)]
+    struct Tag {
+        slug: String,
+        label: String,
+    }
+
+    impl Entity for Tag {
+        const TABLE: &'static str = "blog.tags";
+        const ID_COLUMN: &'static str = "slug";
+        const COLUMNS: &'static [&'static str] = &["slug", "label"];
+
+        fn push_bind<'q>(&'q self, column: &str, query: &mut QueryBuilder<'q, Postgres>) {
+            match column {
+                "slug" => query.push_bind(&self.slug),
+                "label" => query.push_bind(&self.label),
+                _ => unreachable!("tags have no column {column}"),
+            };
+        }
+    }
+
+    #[test]
+    fn builds_sql_for_the_mapped_table() {
+        let tag = Tag {
+            slug: "rust".to_string(),
+            label: "Rust".to_string(),
+        };
+
+        assert_eq!(
+            insert(&tag).sql(),
+            "INSERT INTO blog.tags (slug, label) VALUES ($1, $2) RETURNING *"
+        );
+        let mut query = update(&tag);
+        query.push_bind("rust").push(" RETURNING *");
+        assert_eq!(
+            query.sql(),
+            "UPDATE blog.tags SET label = $1 WHERE slug = $2 RETURNING *"
+        );
+    }
+
+    #[tokio::test]
+    async fn repository_queries_can_run_on_any_thread() {
+        fn send<F: Send>(_: &F) {}
+
+        let pool = PgPool::connect_lazy("postgres://localhost/dy_rs").unwrap();
+        let tags = Repository::<Tag, String>::new(pool);
+        let tag = Tag {
+            slug: "rust".to_string(),
+            label: "Rust".to_string(),
+        };
+        let slug = tag.slug.clone();
+        send(&tags.find(&slug));
+        send(&tags.insert(&tag));
+        send(&tags.update(&slug, &tag));
+        send(&tags.delete(&slug));
+        send(&tags.count());
+    }
+}
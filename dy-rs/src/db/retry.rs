@@ -0,0 +1,115 @@
+//! Retrying transient sqlx errors
+//!
+//! Dropped connections, serialization failures and deadlocks usually go
+//! away when the work is simply tried again. [`retry`] does that with the
+//! backoff of the `[database.retry]` config section:
+//!
+//! ```rust,ignore
+//! let order = db::retry(&config.database.retry, || async {
+//!     let mut tx = pool.begin().await?;
+//!     let order = place_order(&mut tx, &cart).await?;
+//!     tx.commit().await?;
+//!     Ok(order)
+//! })
+//! .await?;
+//! ```
+//!
+//! Retry whole units of work: a statement that failed inside a transaction
+//! leaves the transaction aborted, so retrying just that statement can't
+//! succeed.
+
+use std::future::Future;
+
+use crate::config::DatabaseRetryConfig;
+
+/// Whether `err` is likely to go away on its own, so the work that failed
+/// is worth trying again
+pub fn is_transient(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+        sqlx::Error::Database(err) => err.code().is_some_and(|code| {
+            // Connection exceptions, serialization failures, deadlocks,
+            // too many connections and server shutdowns
+            code.starts_with("08")
+                || matches!(
+                    code.as_ref(),
+                    "40001" | "40P01" | "53300" | "57P01" | "57P02" | "57P03"
+                )
+        }),
+        _ => false,
+    }
+}
+
+/// Run `operation` until it succeeds, fails with an error that isn't
+/// [transient](is_transient), or has been attempted `policy.max_attempts`
+/// times, waiting [`DatabaseRetryConfig::delay`] between attempts
+pub async fn retry<T, F, Fut>(
+    policy: &DatabaseRetryConfig,
+    mut operation: F,
+) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(err) if attempt < policy.max_attempts && is_transient(&err) => {
+                let delay = policy.delay(attempt);
+                tracing::warn!(error = %err, attempt, ?delay, "Transient database error, retrying");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy(max_attempts: u32) -> DatabaseRetryConfig {
+        DatabaseRetryConfig {
+            max_attempts,
+            initial_delay_ms: 1,
+            max_delay_ms: 1,
+        }
+    }
+
+    fn reset() -> sqlx::Error {
+        sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into())
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_only() {
+        let attempts = AtomicU32::new(0);
+        let result = retry(&policy(3), || async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err(reset()),
+                n => Ok(n),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry(&policy(3), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(reset())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = retry(&policy(3), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,32 @@
+//! SeaORM connections as a [`Database`]
+
+use async_trait::async_trait;
+use sea_orm::DatabaseConnection;
+
+use super::Database;
+use crate::error::ApiError;
+
+#[async_trait]
+impl Database for DatabaseConnection {
+    fn backend(&self) -> &'static str {
+        "sea-orm"
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        DatabaseConnection::ping(self).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn disconnected_connections_fail_the_ping() {
+        let connection = DatabaseConnection::Disconnected;
+        assert_eq!(Database::backend(&connection), "sea-orm");
+        let err = Database::ping(&connection).await.unwrap_err();
+        assert!(matches!(err, ApiError::DatabaseError(_)));
+    }
+}
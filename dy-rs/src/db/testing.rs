@@ -0,0 +1,311 @@
+//! Isolated databases for tests
+//!
+//! A [`TestDatabase`] is a fresh schema in a real Postgres database, named
+//! `test_` and a random suffix. Every connection of its pool works in that
+//! schema, so tests running in parallel don't see each other's rows:
+//!
+//! ```rust,ignore
+//! let db = TestDatabase::from_env().await?;
+//! db.migrate("migrations").await?;
+//! db.load_fixtures("tests/fixtures").await?;
+//!
+//! let client = TestClient::new(App::new().with_database(db.pool().clone()).mount(routes()));
+//! client.get("/users").await.assert_ok();
+//!
+//! db.cleanup().await?;
+//! ```
+//!
+//! [`TestDatabase::from_env`] connects to `TEST_DATABASE_URL`, or
+//! `DATABASE_URL` when that isn't set. Tests that only talk to the database
+//! can instead work in a [`TestDatabase::transaction`], rolled back when
+//! it's dropped.
+//!
+//! Fixtures are `.sql` files, run as they are, or `.json` files mapping
+//! tables to the rows to insert. Columns left out of a row get their
+//! defaults, and values are converted to the column types by Postgres:
+//!
+//! ```json
+//! {
+//!   "users": [
+//!     { "id": "6d0c1f0e-5f8a-4c3e-9a43-1c5b2a8c9d10", "email": "ana@example.com" }
+//!   ],
+//!   "orders": [{ "user_id": "6d0c1f0e-5f8a-4c3e-9a43-1c5b2a8c9d10", "total": 12.5 }]
+//! }
+//! ```
+//!
+//! Tables are filled in the order the file lists them. As in [`Repository`](super::Repository),
+//! table and column names are written into the SQL as they are.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{MapAccess, Visitor},
+};
+use serde_json::Value;
+use sqlx::{
+    PgPool, Postgres, Transaction,
+    postgres::{PgConnectOptions, PgPoolOptions},
+};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+
+/// A schema of its own for one test; see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct TestDatabase {
+    pool: PgPool,
+    schema: String,
+}
+
+impl TestDatabase {
+    /// A new schema in the database at `TEST_DATABASE_URL`, or
+    /// `DATABASE_URL`
+    pub async fn from_env() -> Result<Self, ApiError> {
+        let url = std::env::var("TEST_DATABASE_URL")
+            .or_else(|_| std::env::var("DATABASE_URL"))
+            .map_err(|_| {
+                ApiError::InternalServerError(
+                    "Set TEST_DATABASE_URL or DATABASE_URL to run database tests".to_string(),
+                )
+            })?;
+        Self::new(&url).await
+    }
+
+    /// A new schema in the database at `url`
+    pub async fn new(url: &str) -> Result<Self, ApiError> {
+        let schema = schema_name();
+        let options = PgConnectOptions::from_str(url)?;
+        let admin = PgPoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.clone())
+            .await?;
+        sqlx::query(&format!("CREATE SCHEMA \"{schema}\""))
+            .execute(&admin)
+            .await?;
+        admin.close().await;
+
+        let pool = PgPoolOptions::new()
+            .connect_with(options.options([("search_path", schema.as_str())]))
+            .await?;
+        Ok(Self { pool, schema })
+    }
+
+    /// Pool whose connections work in the test's schema
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub fn schema(&self) -> &str {
+        &self.schema
+    }
+
+    /// Apply the sqlx migrations in `dir`, e.g. `migrations`
+    pub async fn migrate(&self, dir: impl AsRef<Path>) -> Result<(), ApiError> {
+        let migrator = sqlx::migrate::Migrator::new(dir.as_ref())
+            .await
+            .map_err(ApiError::database)?;
+        migrator.run(&self.pool).await.map_err(ApiError::database)
+    }
+
+    /// A transaction in the test's schema, rolled back when dropped unless
+    /// committed
+    pub async fn transaction(&self) -> Result<Transaction<'static, Postgres>, ApiError> {
+        Ok(self.pool.begin().await?)
+    }
+
+    /// Load a `.sql` or `.json` fixture file
+    pub async fn load_fixture(&self, path: impl AsRef<Path>) -> Result<(), ApiError> {
+        let path = path.as_ref();
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| fixture_error(path, e))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("sql") => {
+                sqlx::raw_sql(&contents).execute(&self.pool).await?;
+                Ok(())
+            }
+            Some("json") => {
+                let FixtureTables(tables) =
+                    serde_json::from_str(&contents).map_err(|e| fixture_error(path, e))?;
+                for (table, rows) in tables {
+                    self.insert_rows(&table, &rows).await?;
+                }
+                Ok(())
+            }
+            _ => Err(fixture_error(path, "not a .sql or .json file")),
+        }
+    }
+
+    /// Load every `.sql` and `.json` file in `dir`, in file name order
+    pub async fn load_fixtures(&self, dir: impl AsRef<Path>) -> Result<(), ApiError> {
+        let dir = dir.as_ref();
+        let mut entries = tokio::fs::read_dir(dir)
+            .await
+            .map_err(|e| fixture_error(dir, e))?;
+        let mut paths = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| fixture_error(dir, e))?
+        {
+            paths.push(entry.path());
+        }
+        for path in fixture_files(paths) {
+            self.load_fixture(&path).await?;
+        }
+        Ok(())
+    }
+
+    /// Insert JSON objects into `table`, e.g.
+    /// `db.insert_rows("users", &[json!({ "email": "ana@example.com" })])`
+    pub async fn insert_rows(&self, table: &str, rows: &[Value]) -> Result<(), ApiError> {
+        let mut tx = self.pool.begin().await?;
+        for row in rows {
+            let row = row.as_object().ok_or_else(|| {
+                ApiError::InternalServerError(format!("Fixture rows of {table} must be objects"))
+            })?;
+            let columns: Vec<&str> = row.keys().map(String::as_str).collect();
+            sqlx::query(&insert_sql(table, &columns))
+                .bind(Value::Object(row.clone()))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Drop the schema and everything in it, and close the pool
+    pub async fn cleanup(self) -> Result<(), ApiError> {
+        sqlx::query(&format!("DROP SCHEMA \"{}\" CASCADE", self.schema))
+            .execute(&self.pool)
+            .await?;
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+/// Tables of a JSON fixture and their rows, in the order the file lists them
+struct FixtureTables(Vec<(String, Vec<Value>)>);
+
+impl<'de> Deserialize<'de> for FixtureTables {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TablesVisitor;
+
+        impl<'de> Visitor<'de> for TablesVisitor {
+            type Value = FixtureTables;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an object mapping tables to arrays of rows")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut tables = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    tables.push(entry);
+                }
+                Ok(FixtureTables(tables))
+            }
+        }
+
+        deserializer.deserialize_map(TablesVisitor)
+    }
+}
+
+/// `test_` and a random suffix, a valid unquoted identifier
+fn schema_name() -> String {
+    format!("test_{}", Uuid::new_v4().simple())
+}
+
+/// The `.sql` and `.json` files of `paths`, sorted by file name
+fn fixture_files(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = paths
+        .into_iter()
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("sql" | "json")
+            )
+        })
+        .collect();
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    files
+}
+
+/// Insert of one JSON row, `$1`, into `columns` of `table`, converting its
+/// values to the column types
+fn insert_sql(table: &str, columns: &[&str]) -> String {
+    if columns.is_empty() {
+        return format!("INSERT INTO {table} DEFAULT VALUES");
+    }
+    let columns = columns.join(", ");
+    format!(
+        "INSERT INTO {table} ({columns}) \
+         SELECT {columns} FROM json_populate_record(NULL::{table}, $1::json)"
+    )
+}
+
+fn fixture_error(path: &Path, err: impl fmt::Display) -> ApiError {
+    ApiError::InternalServerError(format!("Fixture {}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_rows_insert_their_columns() {
+        assert_eq!(
+            insert_sql("users", &["id", "email"]),
+            "INSERT INTO users (id, email) \
+             SELECT id, email FROM json_populate_record(NULL::users, $1::json)"
+        );
+        assert_eq!(
+            insert_sql("audit_log", &[]),
+            "INSERT INTO audit_log DEFAULT VALUES"
+        );
+
+        let schema = schema_name();
+        assert!(schema.starts_with("test_"));
+        assert!(
+            schema
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        );
+        assert_ne!(schema, schema_name());
+    }
+
+    #[test]
+    fn json_fixtures_keep_their_table_order() {
+        let FixtureTables(tables) =
+            serde_json::from_str(r#"{ "users": [{ "id": 1 }], "orders": [], "audit": [{}] }"#)
+                .unwrap();
+        let names: Vec<_> = tables.iter().map(|(table, _)| table.as_str()).collect();
+        assert_eq!(names, ["users", "orders", "audit"]);
+        assert_eq!(tables[0].1, [serde_json::json!({ "id": 1 })]);
+        assert!(serde_json::from_str::<FixtureTables>(r#"{ "users": {} }"#).is_err());
+    }
+
+    #[test]
+    fn fixture_files_load_in_name_order() {
+        let paths = ["b_orders.json", "README.md", "a_users.sql", "c_more.json"]
+            .into_iter()
+            .map(|name| PathBuf::from("fixtures").join(name))
+            .collect();
+        let names: Vec<_> = fixture_files(paths)
+            .into_iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["a_users.sql", "b_orders.json", "c_more.json"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_urls_are_errors() {
+        let err = TestDatabase::new("not a url").await.unwrap_err();
+        assert!(matches!(err, ApiError::DatabaseError(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,224 @@
+//! A transaction per request
+//!
+//! [`TxLayer`] lets handlers take a [`Tx`], which begins a transaction on
+//! first use and derefs to the connection to run queries on. Once the
+//! handler has responded the layer commits if the status is below `400` and
+//! rolls back otherwise, so returning an error undoes everything the handler
+//! wrote. A panicking handler drops the transaction, which rolls it back too.
+//!
+//! ```rust,ignore
+//! async fn transfer(mut tx: Tx, Json(t): Json<Transfer>) -> ApiResult<Receipt> {
+//!     sqlx::query("UPDATE accounts SET balance = balance - $1 WHERE id = $2")
+//!         .bind(t.amount)
+//!         .bind(t.from)
+//!         .execute(&mut *tx)
+//!         .await?;
+//!     sqlx::query("UPDATE accounts SET balance = balance + $1 WHERE id = $2")
+//!         .bind(t.amount)
+//!         .bind(t.to)
+//!         .execute(&mut *tx)
+//!         .await?;
+//!     Ok(Json(Receipt::for_transfer(&t)))
+//! }
+//!
+//! let router = Router::new()
+//!     .route("/transfers", post(transfer))
+//!     .layer(TxLayer::new(pool));
+//! ```
+
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::Arc,
+    task,
+};
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tower::{Layer, Service};
+
+use crate::error::ApiError;
+
+type Slot = Option<Transaction<'static, Postgres>>;
+
+/// The request's transaction, if a handler began one
+#[derive(Clone)]
+struct TxSlot {
+    pool: PgPool,
+    tx: Arc<Mutex<Slot>>,
+}
+
+/// Extractor for the request's database transaction
+///
+/// Needs a [`TxLayer`]; rejects with `500` without one, and with `503` when
+/// no connection frees up to start the transaction. A request has a single
+/// transaction, so take `Tx` once per handler.
+pub struct Tx(OwnedMutexGuard<Slot>);
+
+impl Deref for Tx {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        self.0.as_ref().expect("Tx holds a transaction")
+    }
+}
+
+impl DerefMut for Tx {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        self.0.as_mut().expect("Tx holds a transaction")
+    }
+}
+
+impl<S> FromRequestParts<S> for Tx
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let slot = parts
+            .extensions
+            .get::<TxSlot>()
+            .cloned()
+            .ok_or_else(|| ApiError::InternalServerError("Tx needs a TxLayer".to_string()))?;
+        let mut guard = slot.tx.try_lock_owned().map_err(|_| {
+            ApiError::InternalServerError("The request's Tx is already taken".to_string())
+        })?;
+        if guard.is_none() {
+            *guard = Some(slot.pool.begin().await?);
+        }
+        Ok(Self(guard))
+    }
+}
+
+/// Layer that provides [`Tx`] to handlers and settles it after they respond
+#[derive(Clone)]
+pub struct TxLayer {
+    pool: PgPool,
+}
+
+impl TxLayer {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl<S> Layer<S> for TxLayer {
+    type Service = TxService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TxService {
+            inner,
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+/// Service produced by [`TxLayer`]
+#[derive(Clone)]
+pub struct TxService<S> {
+    inner: S,
+    pool: PgPool,
+}
+
+impl<S> Service<Request> for TxService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let slot = TxSlot {
+            pool: self.pool.clone(),
+            tx: Arc::default(),
+        };
+        request.extensions_mut().insert(slot.clone());
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            let tx = match slot.tx.try_lock() {
+                Ok(mut tx) => tx.take(),
+                Err(_) => {
+                    // Still held, e.g. by a task the handler spawned; it is
+                    // rolled back when that drops it
+                    tracing::error!("Tx outlived its handler and was not committed");
+                    return Ok(ApiError::InternalServerError(
+                        "Transaction not committed".to_string(),
+                    )
+                    .into_response());
+                }
+            };
+            let Some(tx) = tx else {
+                return Ok(response);
+            };
+
+            let status = response.status();
+            if status.is_client_error() || status.is_server_error() {
+                if let Err(err) = tx.rollback().await {
+                    tracing::error!(error = %err, "Failed to roll back transaction");
+                }
+                return Ok(response);
+            }
+            match tx.commit().await {
+                Ok(()) => Ok(response),
+                Err(err) => Ok(ApiError::from(err).into_response()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode, routing::get};
+    use sqlx::postgres::PgPoolOptions;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    fn unreachable_pool() -> PgPool {
+        PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy("postgres://dy_rs@127.0.0.1:1/dy_rs")
+            .unwrap()
+    }
+
+    async fn status(router: Router, uri: &str) -> StatusCode {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn transactions_begin_only_when_taken() {
+        let router = Router::new()
+            .route("/plain", get(|| async { "ok" }))
+            .route("/tx", get(|_tx: Tx| async { "ok" }))
+            .layer(TxLayer::new(unreachable_pool()));
+
+        // The database is never contacted for handlers without a Tx
+        assert_eq!(status(router.clone(), "/plain").await, StatusCode::OK);
+        assert_eq!(status(router, "/tx").await, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn tx_needs_the_layer() {
+        let router = Router::new().route("/tx", get(|_tx: Tx| async { "ok" }));
+        assert_eq!(
+            status(router, "/tx").await,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Compressed request bodies
+//!
+//! [`RequestDecompressionLayer`] decodes bodies sent with a
+//! `Content-Encoding` before extractors see them, so `Json` and friends work
+//! with clients that compress their payloads. `gzip` and `deflate` are built
+//! in; other encodings, such as `br`, are decoded by a [`Decoder`] added with
+//! [`decoder`](RequestDecompressionLayer::decoder), typically a closure around
+//! the compression crate the app already uses:
+//!
+//! ```rust,ignore
+//! let decompression = RequestDecompressionLayer::new().decoder("br", |input: &[u8], limit: usize| {
+//!     let mut output = Vec::new();
+//!     brotli::Decompressor::new(input, 4096).take(limit as u64 + 1).read_to_end(&mut output)?;
+//!     Ok(output)
+//! });
+//! ```
+//!
+//! Bodies decoding past the size cap are answered with `413`, corrupt ones
+//! with `400` and unknown encodings with `415` and an `Accept-Encoding` header
+//! listing the known ones. Auto-configured apps decode requests as the
+//! `[server.decompression]` config section says:
+//!
+//! ```toml
+//! [server.decompression]
+//! enabled = true
+//! max_size = 10485760
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    io::{self, Read},
+    pin::Pin,
+    sync::Arc,
+    task,
+};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        HeaderValue,
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH},
+    },
+    response::{IntoResponse, Response},
+};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+
+use crate::error::ApiError;
+
+/// Request decompression settings, the `[server.decompression]` config
+/// section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecompressionConfig {
+    /// Whether compressed bodies are decoded
+    pub enabled: bool,
+    /// Largest body accepted, compressed or decoded, in bytes
+    pub max_size: usize,
+}
+
+impl Default for DecompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// Decodes one content coding
+///
+/// Implemented for closures taking the encoded body and the size cap. A
+/// decoder may stop once past `limit`: anything longer is rejected.
+pub trait Decoder: Send + Sync + 'static {
+    fn decode(&self, input: &[u8], limit: usize) -> io::Result<Vec<u8>>;
+}
+
+impl<F> Decoder for F
+where
+    F: Fn(&[u8], usize) -> io::Result<Vec<u8>> + Send + Sync + 'static,
+{
+    fn decode(&self, input: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+        self(input, limit)
+    }
+}
+
+/// Up to `limit + 1` bytes of `reader`
+fn read_capped(reader: impl Read, limit: usize) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    reader.take(limit as u64 + 1).read_to_end(&mut output)?;
+    Ok(output)
+}
+
+fn gzip(input: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    read_capped(GzDecoder::new(input), limit)
+}
+
+/// `deflate` is zlib-wrapped, but some clients send raw deflate streams
+fn deflate(input: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    read_capped(ZlibDecoder::new(input), limit)
+        .or_else(|_| read_capped(DeflateDecoder::new(input), limit))
+}
+
+/// Decodes compressed request bodies; see the [module docs](self)
+#[derive(Clone)]
+pub struct RequestDecompressionLayer {
+    decoders: Arc<BTreeMap<String, Arc<dyn Decoder>>>,
+    max_size: usize,
+}
+
+impl RequestDecompressionLayer {
+    /// Decoding `gzip` and `deflate`, up to the default size cap
+    pub fn new() -> Self {
+        Self::from_config(&DecompressionConfig::default())
+    }
+
+    pub fn from_config(config: &DecompressionConfig) -> Self {
+        Self {
+            decoders: Arc::new(BTreeMap::new()),
+            max_size: config.max_size,
+        }
+        .decoder("gzip", gzip)
+        .decoder("x-gzip", gzip)
+        .decoder("deflate", deflate)
+    }
+
+    /// Decode `encoding` with `decoder`, replacing any built-in one
+    pub fn decoder(mut self, encoding: &str, decoder: impl Decoder) -> Self {
+        Arc::make_mut(&mut self.decoders).insert(encoding.to_ascii_lowercase(), Arc::new(decoder));
+        self
+    }
+
+    /// Largest body accepted, compressed or decoded
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    fn unsupported(&self, encoding: &str) -> Response {
+        let known = self
+            .decoders
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut response = ApiError::unsupported_media_type(format!(
+            "Content-Encoding `{encoding}` is not supported"
+        ))
+        .into_response();
+        if let Ok(known) = HeaderValue::from_str(&known) {
+            response.headers_mut().insert(ACCEPT_ENCODING, known);
+        }
+        response
+    }
+
+    /// `body` decoded by each of `encodings`, last applied first
+    fn decode(&self, mut body: Vec<u8>, encodings: &[String]) -> Result<Vec<u8>, ApiError> {
+        for encoding in encodings.iter().rev() {
+            let decoder = &self.decoders[encoding];
+            body = decoder.decode(&body, self.max_size).map_err(|e| {
+                ApiError::bad_request(format!("Body is not valid `{encoding}`: {e}"))
+            })?;
+            if body.len() > self.max_size {
+                return Err(too_large(self.max_size));
+            }
+        }
+        Ok(body)
+    }
+}
+
+impl Default for RequestDecompressionLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn too_large(max_size: usize) -> ApiError {
+    ApiError::payload_too_large(format!("Decompressed body exceeds {max_size} bytes"))
+}
+
+impl<S> Layer<S> for RequestDecompressionLayer {
+    type Service = RequestDecompressionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestDecompressionService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service produced by [`RequestDecompressionLayer`]
+#[derive(Clone)]
+pub struct RequestDecompressionService<S> {
+    inner: S,
+    layer: RequestDecompressionLayer,
+}
+
+impl<S> Service<Request> for RequestDecompressionService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let encodings: Vec<String> = request
+            .headers()
+            .get_all(CONTENT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|encoding| encoding.trim().to_ascii_lowercase())
+            .filter(|encoding| !encoding.is_empty() && encoding != "identity")
+            .collect();
+        if encodings.is_empty() {
+            return Box::pin(self.inner.call(request));
+        }
+        if let Some(unknown) = encodings
+            .iter()
+            .find(|encoding| !self.layer.decoders.contains_key(*encoding))
+        {
+            let response = self.layer.unsupported(unknown);
+            return Box::pin(async move { Ok(response) });
+        }
+        // The ready service goes into the future; a fresh clone stays here
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            let Ok(body) = axum::body::to_bytes(body, layer.max_size).await else {
+                return Ok(too_large(layer.max_size).into_response());
+            };
+            let decoded = tokio::task::spawn_blocking(move || {
+                let decoded = layer.decode(body.to_vec(), &encodings);
+                (decoded, encodings)
+            })
+            .await;
+            let (decoded, encodings) = match decoded {
+                Ok(decoded) => decoded,
+                Err(e) => return Ok(ApiError::InternalServerError(e.to_string()).into_response()),
+            };
+            let body = match decoded {
+                Ok(body) => body,
+                Err(e) => return Ok(e.into_response()),
+            };
+            tracing::debug!(
+                encoding = %encodings.join(", "),
+                size = body.len(),
+                "Decompressed request body"
+            );
+            parts.headers.remove(CONTENT_ENCODING);
+            parts
+                .headers
+                .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+            inner
+                .call(Request::from_parts(parts, Body::from(body)))
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, http::StatusCode, routing::post};
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+    use tower::ServiceExt;
+
+    fn gzipped(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn router(layer: RequestDecompressionLayer) -> Router {
+        Router::new()
+            .route(
+                "/echo",
+                post(|Json(value): Json<serde_json::Value>| async move { Json(value) }),
+            )
+            .layer(layer)
+    }
+
+    async fn post_body(router: &Router, encoding: &str, body: Vec<u8>) -> Response {
+        let request = Request::post("/echo")
+            .header("content-type", "application/json")
+            .header(CONTENT_ENCODING, encoding)
+            .body(Body::from(body))
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn compressed_json_reaches_the_extractor() {
+        let router = router(RequestDecompressionLayer::new());
+        let response = post_body(&router, "gzip", gzipped(br#"{"id":7}"#)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, r#"{"id":7}"#.as_bytes());
+
+        let response = post_body(&router, "identity", br#"{"id":8}"#.to_vec()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn oversized_corrupt_and_unknown_bodies_are_rejected() {
+        let router = router(RequestDecompressionLayer::new().max_size(64));
+        let bomb = gzipped(&[b' '; 10_000]);
+        assert!(bomb.len() < 64);
+        assert_eq!(
+            post_body(&router, "gzip", bomb).await.status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            post_body(&router, "gzip", b"not gzip".to_vec())
+                .await
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        let response = post_body(&router, "br", b"{}".to_vec()).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(response.headers()[ACCEPT_ENCODING], "deflate, gzip, x-gzip");
+    }
+
+    #[tokio::test]
+    async fn added_decoders_handle_their_encoding() {
+        let reverse = |input: &[u8], _limit: usize| -> io::Result<Vec<u8>> {
+            Ok(input.iter().rev().copied().collect())
+        };
+        let router = router(RequestDecompressionLayer::new().decoder("rev", reverse));
+        // Gzipped, then reversed
+        let mut body = gzipped(br#"{"id":7}"#);
+        body.reverse();
+        let response = post_body(&router, "gzip, rev", body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,712 @@
+//! Serving of the OpenAPI document and the interactive docs UIs.
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::Query,
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::openapi::OpenApi;
+
+use crate::openapi::{self, DocFilter, filter_openapi};
+
+/// Default path of the full OpenAPI document.
+pub const OPENAPI_JSON_PATH: &str = "/api-docs/openapi.json";
+/// Default path of the full OpenAPI document in YAML.
+pub const OPENAPI_YAML_PATH: &str = "/api-docs/openapi.yaml";
+
+/// Paths the OpenAPI document is served at, in JSON and YAML.
+///
+/// Views are served from a directory named after them next to the file,
+/// e.g. `/api-docs/public/openapi.yaml`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpecPaths {
+    pub json: String,
+    pub yaml: String,
+}
+
+impl Default for SpecPaths {
+    fn default() -> Self {
+        Self {
+            json: OPENAPI_JSON_PATH.to_string(),
+            yaml: OPENAPI_YAML_PATH.to_string(),
+        }
+    }
+}
+
+impl SpecPaths {
+    pub fn new(json: impl Into<String>, yaml: impl Into<String>) -> Self {
+        Self {
+            json: json.into(),
+            yaml: yaml.into(),
+        }
+    }
+
+    /// Paths of the document for the view `name`.
+    pub fn for_view(&self, name: &str) -> Self {
+        let nest = |path: &str| match path.rsplit_once('/') {
+            Some((dir, file)) => format!("{dir}/{name}/{file}"),
+            None => format!("{name}/{path}"),
+        };
+        Self {
+            json: nest(&self.json),
+            yaml: nest(&self.yaml),
+        }
+    }
+}
+
+/// Renderer for the interactive API reference.
+///
+/// Swagger UI is bundled with the `swagger-ui` feature; the others are
+/// single HTML pages loading the renderer from a CDN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocsUi {
+    Swagger,
+    Redoc,
+    RapiDoc,
+    Scalar,
+}
+
+impl DocsUi {
+    /// Path the UI is served at; views are served below it.
+    pub fn path(self) -> &'static str {
+        match self {
+            DocsUi::Swagger => "/docs",
+            DocsUi::Redoc => "/redoc",
+            DocsUi::RapiDoc => "/rapidoc",
+            DocsUi::Scalar => "/scalar",
+        }
+    }
+
+    fn router(
+        self,
+        ui_path: &str,
+        spec_path: String,
+        title: &str,
+        picker: &VersionPicker,
+    ) -> Router {
+        let body = match self {
+            DocsUi::Swagger => return swagger_ui(ui_path, spec_path, picker),
+            DocsUi::Redoc => format!(
+                r#"<redoc spec-url="{spec_path}"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/latest/bundles/redoc.standalone.js"></script>"#
+            ),
+            DocsUi::RapiDoc => format!(
+                r#"<rapi-doc spec-url="{spec_path}" render-style="read"></rapi-doc>
+    <script type="module" src="https://unpkg.com/rapidoc/dist/rapidoc-min.js"></script>"#
+            ),
+            DocsUi::Scalar => format!(
+                r#"<script id="api-reference" data-url="{spec_path}"></script>
+    <script src="https://cdn.jsdelivr.net/npm/@scalar/api-reference"></script>"#
+            ),
+        };
+        let page = format!(
+            r#"<!doctype html>
+<html>
+  <head>
+    <title>{}</title>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+  </head>
+  <body>
+    {}{body}
+  </body>
+</html>
+"#,
+            escape_html(title),
+            picker.html()
+        );
+        Router::new().route(ui_path, get(move || async move { Html(page) }))
+    }
+}
+
+/// Version selector shown on the pages of a UI when API versions are
+/// registered.
+#[derive(Clone, Debug, Default)]
+struct VersionPicker {
+    /// `(label, spec path, UI path)`, the full document first.
+    entries: Vec<(String, String, String)>,
+    current: usize,
+}
+
+impl VersionPicker {
+    /// `versions` pairs each version view with the URL of its spec.
+    fn new(ui: DocsUi, spec_url: &str, versions: &[(&DocView, &str)]) -> Self {
+        if versions.is_empty() {
+            return Self::default();
+        }
+        let all = (
+            "All versions".to_string(),
+            spec_url.to_string(),
+            ui.path().to_string(),
+        );
+        let entries = std::iter::once(all)
+            .chain(
+                versions
+                    .iter()
+                    .map(|(view, url)| (view.name.clone(), url.to_string(), view.ui_path_for(ui))),
+            )
+            .collect();
+        Self {
+            entries,
+            current: 0,
+        }
+    }
+
+    fn select(&self, label: &str) -> Self {
+        Self {
+            entries: self.entries.clone(),
+            current: self
+                .entries
+                .iter()
+                .position(|(l, _, _)| l == label)
+                .unwrap_or(0),
+        }
+    }
+
+    fn html(&self) -> String {
+        if self.entries.is_empty() {
+            return String::new();
+        }
+        let options: String = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, (label, _, ui_path))| {
+                format!(
+                    r#"<option value="{}"{}>{}</option>"#,
+                    escape_html(ui_path),
+                    if i == self.current { " selected" } else { "" },
+                    escape_html(label)
+                )
+            })
+            .collect();
+        format!(
+            r#"<nav style="padding: 8px 16px; font-family: sans-serif">
+      <label>Version <select onchange="location.href = this.value">{options}</select></label>
+    </nav>
+    "#
+        )
+    }
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An extra docs UI restricted to a subset of operations.
+#[derive(Clone, Debug)]
+pub struct DocView {
+    pub name: String,
+    pub filter: DocFilter,
+    /// Whether the view is an API version, listed in the version selector.
+    pub version: bool,
+}
+
+impl DocView {
+    pub fn new(name: impl Into<String>, filter: DocFilter) -> Self {
+        Self {
+            name: name.into(),
+            filter,
+            version: false,
+        }
+    }
+
+    /// View of the operations of API `version` (and unversioned ones).
+    pub fn version(version: impl Into<String>) -> Self {
+        let version = version.into();
+        Self {
+            filter: DocFilter::new().version(version.clone()),
+            name: version,
+            version: true,
+        }
+    }
+
+    /// Path the Swagger UI for this view is served at.
+    pub fn ui_path(&self) -> String {
+        self.ui_path_for(DocsUi::Swagger)
+    }
+
+    /// Path the given UI for this view is served at.
+    pub fn ui_path_for(&self, ui: DocsUi) -> String {
+        format!("{}/{}", ui.path(), self.name)
+    }
+
+    /// Path the filtered document for this view is served at by default.
+    pub fn spec_path(&self) -> String {
+        self.spec_paths(&SpecPaths::default()).json
+    }
+
+    /// Paths the filtered document for this view is served at, given the
+    /// paths of the full document.
+    pub fn spec_paths(&self, base: &SpecPaths) -> SpecPaths {
+        base.for_view(&self.name)
+    }
+}
+
+/// Query parameters accepted by the spec endpoint, e.g.
+/// `/api-docs/openapi.json?tag=Users,Orders&prefix=/users`.
+#[derive(Debug, Default, Deserialize)]
+pub struct DocQuery {
+    /// Comma-separated list of tags to keep.
+    pub tag: Option<String>,
+    /// Only keep paths starting with this prefix.
+    pub prefix: Option<String>,
+    /// Only keep operations of this API version (and unversioned ones).
+    pub version: Option<String>,
+    /// Hash of the document, set by the docs UIs so the response can be
+    /// cached as immutable.
+    pub v: Option<String>,
+}
+
+impl DocQuery {
+    fn into_filter(self) -> DocFilter {
+        let mut filter = DocFilter::new();
+        if let Some(tags) = self.tag {
+            for tag in tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                filter = filter.tag(tag);
+            }
+        }
+        if let Some(prefix) = self.prefix {
+            filter = filter.path_prefix(prefix);
+        }
+        if let Some(version) = self.version {
+            filter = filter.version(version);
+        }
+        filter
+    }
+}
+
+/// Router serving the document in JSON and YAML (with query filtering) plus
+/// one spec per configured view, and each UI for the document and every
+/// view. Swagger UI is only mounted with the `swagger-ui` feature.
+pub(crate) fn docs_router(
+    doc: Arc<OpenApi>,
+    views: &[DocView],
+    uis: &[DocsUi],
+    paths: &SpecPaths,
+) -> Router {
+    let title = doc.info.title.clone();
+    let (mut router, spec_url) = spec_routes(&doc, DocFilter::new(), paths);
+    let mut view_urls = Vec::new();
+    for view in views {
+        let (routes, url) = spec_routes(&doc, view.filter.clone(), &view.spec_paths(paths));
+        router = router.merge(routes);
+        view_urls.push(url);
+    }
+
+    let versions: Vec<(&DocView, &str)> = views
+        .iter()
+        .zip(&view_urls)
+        .filter(|(view, _)| view.version)
+        .map(|(view, url)| (view, url.as_str()))
+        .collect();
+    let pickers: Vec<VersionPicker> = uis
+        .iter()
+        .map(|ui| VersionPicker::new(*ui, &spec_url, &versions))
+        .collect();
+
+    for (ui, picker) in uis.iter().zip(&pickers) {
+        router = router.merge(ui.router(ui.path(), spec_url.clone(), &title, picker));
+    }
+
+    for (view, view_url) in views.iter().zip(&view_urls) {
+        for (ui, picker) in uis.iter().zip(&pickers) {
+            let picker = if view.version {
+                picker.select(&view.name)
+            } else {
+                VersionPicker::default()
+            };
+            router = router.merge(ui.router(
+                &view.ui_path_for(*ui),
+                view_url.clone(),
+                &format!("{title} ({})", view.name),
+                &picker,
+            ));
+        }
+    }
+
+    router
+}
+
+/// Swagger UI switches between versions with its own spec dropdown.
+#[cfg(feature = "swagger-ui")]
+fn swagger_ui(ui_path: &str, spec_path: String, picker: &VersionPicker) -> Router {
+    use utoipa_swagger_ui::{Config, SwaggerUi, Url};
+
+    let config = if picker.entries.is_empty() {
+        Config::new([spec_path])
+    } else {
+        // Swagger UI only takes `'static` names; the router lives for the
+        // rest of the program anyway.
+        let leak = |s: &str| -> &'static str { Box::leak(s.to_string().into_boxed_str()) };
+        Config::new(
+            picker
+                .entries
+                .iter()
+                .enumerate()
+                .map(|(i, (label, spec, _))| {
+                    Url::with_primary(leak(label), leak(spec), i == picker.current)
+                })
+                .collect::<Vec<_>>(),
+        )
+    };
+    SwaggerUi::new(ui_path.to_string()).config(config).into()
+}
+
+#[cfg(not(feature = "swagger-ui"))]
+fn swagger_ui(_ui_path: &str, _spec_path: String, _picker: &VersionPicker) -> Router {
+    Router::new()
+}
+
+/// A serialized document, rendered once and served with an `ETag`.
+struct RenderedSpec {
+    body: Bytes,
+    etag: String,
+    content_type: &'static str,
+}
+
+impl RenderedSpec {
+    fn json(doc: &OpenApi) -> Self {
+        let body = serde_json::to_vec(doc).expect("OpenAPI documents serialize to JSON");
+        Self::new(body, "application/json")
+    }
+
+    fn yaml(doc: &OpenApi) -> Self {
+        Self::new(openapi::to_yaml(doc).into_bytes(), "application/yaml")
+    }
+
+    fn new(body: Vec<u8>, content_type: &'static str) -> Self {
+        let digest = hex::encode(Sha256::digest(&body));
+        Self {
+            body: Bytes::from(body),
+            etag: format!("\"{}\"", &digest[..16]),
+            content_type,
+        }
+    }
+
+    /// `pinned` responses were requested through a content-addressed URL,
+    /// so they never change and may be cached forever.
+    fn respond(&self, request_headers: &HeaderMap, pinned: bool) -> Response {
+        let cache_control = if pinned {
+            "public, max-age=31536000, immutable"
+        } else {
+            "no-cache"
+        };
+        let fresh = request_headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == self.etag
+            });
+        let headers = [
+            (header::ETAG, self.etag.clone()),
+            (header::CACHE_CONTROL, cache_control.to_string()),
+        ];
+        if fresh {
+            (StatusCode::NOT_MODIFIED, headers).into_response()
+        } else {
+            (
+                headers,
+                [(header::CONTENT_TYPE, self.content_type)],
+                self.body.clone(),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Routes serving the document selected by `base` in JSON and YAML, and the
+/// content-addressed URL of the JSON document (`?v=` plus its hash).
+///
+/// Both renderings are serialized once here; only requests adding their own
+/// query filters are rendered per request.
+fn spec_routes(doc: &Arc<OpenApi>, base: DocFilter, paths: &SpecPaths) -> (Router, String) {
+    let selected = if base.is_empty() {
+        doc.clone()
+    } else {
+        Arc::new(filter_openapi(doc, &base))
+    };
+    let json = Arc::new(RenderedSpec::json(&selected));
+    let yaml = Arc::new(RenderedSpec::yaml(&selected));
+    // The JSON hash identifies the document for both renderings.
+    let hash = json.etag.trim_matches('"').to_string();
+    let pinned_url = format!("{}?v={hash}", paths.json);
+
+    let router = Router::new()
+        .route(
+            &paths.json,
+            spec_route(selected.clone(), json, hash.clone(), RenderedSpec::json),
+        )
+        .route(
+            &paths.yaml,
+            spec_route(selected, yaml, hash, RenderedSpec::yaml),
+        );
+    (router, pinned_url)
+}
+
+fn spec_route(
+    doc: Arc<OpenApi>,
+    rendered: Arc<RenderedSpec>,
+    hash: String,
+    render: fn(&OpenApi) -> RenderedSpec,
+) -> axum::routing::MethodRouter {
+    get(move |Query(query): Query<DocQuery>, headers: HeaderMap| {
+        let doc = doc.clone();
+        let rendered = rendered.clone();
+        let pinned = query.v.as_deref() == Some(hash.as_str());
+        async move {
+            let filter = query.into_filter();
+            if filter.is_empty() {
+                rendered.respond(&headers, pinned)
+            } else {
+                render(&filter_openapi(&doc, &filter)).respond(&headers, false)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Request, StatusCode},
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+    use utoipa::openapi::{
+        InfoBuilder, OpenApiBuilder, PathItem, PathsBuilder,
+        path::{HttpMethod, OperationBuilder},
+    };
+
+    fn doc() -> Arc<OpenApi> {
+        let op = |tag: &str| OperationBuilder::new().tag(tag).build();
+        Arc::new(
+            OpenApiBuilder::new()
+                .info(InfoBuilder::new().title("t").version("1").build())
+                .paths(
+                    PathsBuilder::new()
+                        .path("/users", PathItem::new(HttpMethod::Get, op("Users")))
+                        .path(
+                            "/admin/stats",
+                            PathItem::new(HttpMethod::Get, op("Internal")),
+                        ),
+                )
+                .build(),
+        )
+    }
+
+    async fn paths(router: Router, uri: &str) -> Vec<String> {
+        let res = router
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let json: Value =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        json["paths"].as_object().unwrap().keys().cloned().collect()
+    }
+
+    #[tokio::test]
+    async fn spec_endpoint_filters_by_query() {
+        let router = docs_router(doc(), &[], &[DocsUi::Swagger], &SpecPaths::default());
+        assert_eq!(paths(router.clone(), OPENAPI_JSON_PATH).await.len(), 2);
+        assert_eq!(
+            paths(router.clone(), "/api-docs/openapi.json?tag=Users").await,
+            vec!["/users"]
+        );
+        assert_eq!(
+            paths(router, "/api-docs/openapi.json?prefix=/admin").await,
+            vec!["/admin/stats"]
+        );
+    }
+
+    #[tokio::test]
+    async fn views_serve_their_own_filtered_spec() {
+        let public = DocView::new("public", DocFilter::new().exclude_tag("Internal"));
+        let router = docs_router(doc(), &[public], &[DocsUi::Swagger], &SpecPaths::default());
+        assert_eq!(
+            paths(router, "/api-docs/public/openapi.json").await,
+            vec!["/users"]
+        );
+    }
+
+    #[tokio::test]
+    async fn alternative_uis_point_at_their_spec() {
+        let public = DocView::new("public", DocFilter::new());
+        let router = docs_router(
+            doc(),
+            &[public],
+            &[DocsUi::Redoc, DocsUi::Scalar],
+            &SpecPaths::default(),
+        );
+
+        for (uri, spec) in [
+            ("/redoc", OPENAPI_JSON_PATH),
+            ("/scalar", OPENAPI_JSON_PATH),
+            ("/redoc/public", "/api-docs/public/openapi.json"),
+        ] {
+            let res = router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{uri}");
+            let html = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+            let html = String::from_utf8(html.to_vec()).unwrap();
+            assert!(html.contains(&format!("\"{spec}?v=")), "{uri}");
+        }
+
+        let res = router
+            .oneshot(Request::get("/rapidoc").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn serves_yaml_at_configurable_paths() {
+        let public = DocView::new("public", DocFilter::new().exclude_tag("Internal"));
+        let spec = SpecPaths::new("/openapi.json", "/spec/openapi.yaml");
+        let router = docs_router(doc(), &[public], &[DocsUi::Redoc], &spec);
+
+        assert_eq!(paths(router.clone(), "/openapi.json").await.len(), 2);
+        assert_eq!(
+            paths(router.clone(), "/public/openapi.json").await,
+            vec!["/users"]
+        );
+
+        for (uri, has_admin) in [
+            ("/spec/openapi.yaml", true),
+            ("/spec/public/openapi.yaml", false),
+            ("/spec/openapi.yaml?tag=Users", false),
+        ] {
+            let res = router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{uri}");
+            assert_eq!(res.headers()[header::CONTENT_TYPE], "application/yaml");
+            let yaml = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+            let yaml = String::from_utf8(yaml.to_vec()).unwrap();
+            assert!(yaml.contains("/users:"), "{uri}");
+            assert_eq!(yaml.contains("/admin/stats:"), has_admin, "{uri}");
+        }
+
+        let res = router
+            .oneshot(Request::get(OPENAPI_JSON_PATH).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn version_views_get_a_selector() {
+        let mut doc = doc().as_ref().clone();
+        let users = doc.paths.paths.get_mut("/users").unwrap();
+        crate::openapi::set_operation_version(users.get.as_mut().unwrap(), "v2");
+        let views = [
+            DocView::version("v1"),
+            DocView::version("v2"),
+            DocView::new("public", DocFilter::new().exclude_tag("Internal")),
+        ];
+        let router = docs_router(
+            Arc::new(doc),
+            &views,
+            &[DocsUi::Redoc],
+            &SpecPaths::default(),
+        );
+
+        assert_eq!(
+            paths(router.clone(), "/api-docs/v1/openapi.json").await,
+            vec!["/admin/stats"]
+        );
+        assert_eq!(
+            paths(router.clone(), "/api-docs/v2/openapi.json")
+                .await
+                .len(),
+            2
+        );
+        assert_eq!(
+            paths(router.clone(), "/api-docs/openapi.json?version=v1").await,
+            vec!["/admin/stats"]
+        );
+
+        let page = |uri: &'static str| {
+            let router = router.clone();
+            async move {
+                let res = router
+                    .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let html = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+                String::from_utf8(html.to_vec()).unwrap()
+            }
+        };
+        let v2 = page("/redoc/v2").await;
+        assert!(v2.contains(r#"<option value="/redoc/v1">v1</option>"#));
+        assert!(v2.contains(r#"<option value="/redoc/v2" selected>v2</option>"#));
+        assert!(page("/redoc").await.contains(r#"value="/redoc" selected"#));
+        assert!(!page("/redoc/public").await.contains("<select"));
+    }
+
+    #[tokio::test]
+    async fn specs_are_served_with_validators() {
+        let router = docs_router(doc(), &[], &[DocsUi::Redoc], &SpecPaths::default());
+        let send = |uri: String, etag: Option<String>| {
+            let router = router.clone();
+            async move {
+                let mut req = Request::get(uri);
+                if let Some(etag) = etag {
+                    req = req.header(header::IF_NONE_MATCH, etag);
+                }
+                router
+                    .oneshot(req.body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let res = send(OPENAPI_JSON_PATH.to_string(), None).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+        let etag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+
+        let res = send(OPENAPI_JSON_PATH.to_string(), Some(etag.clone())).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], etag.as_str());
+
+        // The UI links to the content-addressed URL, which never changes.
+        let html = send("/redoc".to_string(), None).await;
+        let html = to_bytes(html.into_body(), usize::MAX).await.unwrap();
+        let html = String::from_utf8(html.to_vec()).unwrap();
+        let pinned = format!("{OPENAPI_JSON_PATH}?v={}", etag.trim_matches('"'));
+        assert!(html.contains(&pinned));
+        let res = send(pinned, None).await;
+        assert_eq!(
+            res.headers()[header::CACHE_CONTROL],
+            "public, max-age=31536000, immutable"
+        );
+
+        let res = send(format!("{OPENAPI_JSON_PATH}?v=stale"), None).await;
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+        let res = send(format!("{OPENAPI_JSON_PATH}?tag=Users"), Some(etag)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
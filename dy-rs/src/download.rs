@@ -0,0 +1,550 @@
+//! File downloads with HTTP Range support
+//!
+//! [`FileDownload`] serves a file from disk honouring `Range`, `If-Range` and
+//! `If-None-Match`, so browsers can seek through media and download managers
+//! can resume large files.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use axum::http::HeaderMap;
+//! use dy_rs::download::FileDownload;
+//!
+//! async fn report(headers: HeaderMap) -> Result<Response, ApiError> {
+//!     FileDownload::new("reports/2024.pdf")
+//!         .attachment()
+//!         .file_name("annual-report.pdf")
+//!         .serve(&headers)
+//!         .await
+//! }
+//! ```
+
+use std::{
+    io::SeekFrom,
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::{self, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use crate::error::ApiError;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const SNIFF_LEN: usize = 512;
+
+/// How the browser should present a downloaded file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Disposition {
+    /// Display in the browser when possible (default)
+    #[default]
+    Inline,
+    /// Always offer the file as a download
+    Attachment,
+}
+
+/// A file served from disk with Range support
+#[derive(Debug, Clone)]
+pub struct FileDownload {
+    path: PathBuf,
+    disposition: Disposition,
+    file_name: Option<String>,
+    content_type: Option<String>,
+}
+
+impl FileDownload {
+    /// Serve the file at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            disposition: Disposition::Inline,
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// Set the content disposition
+    pub fn disposition(mut self, disposition: Disposition) -> Self {
+        self.disposition = disposition;
+        self
+    }
+
+    /// Display the file in the browser
+    pub fn inline(self) -> Self {
+        self.disposition(Disposition::Inline)
+    }
+
+    /// Offer the file as a download
+    pub fn attachment(self) -> Self {
+        self.disposition(Disposition::Attachment)
+    }
+
+    /// Override the file name reported to the client
+    pub fn file_name(mut self, name: impl Into<String>) -> Self {
+        self.file_name = Some(name.into());
+        self
+    }
+
+    /// Override the detected content type
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Build the response for a request with the given headers
+    pub async fn serve(self, headers: &HeaderMap) -> Result<Response, ApiError> {
+        let mut file = File::open(&self.path).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ApiError::NotFound("File not found".to_string()),
+            _ => ApiError::InternalServerError(format!("Failed to open file: {e}")),
+        })?;
+        let metadata = file
+            .metadata()
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to read file: {e}")))?;
+        if !metadata.is_file() {
+            return Err(ApiError::NotFound("File not found".to_string()));
+        }
+
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+        let mtime = modified
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let etag = format!("\"{len:x}-{mtime:x}\"");
+        let last_modified = modified.map(httpdate::fmt_http_date);
+
+        let mut response_headers = HeaderMap::new();
+        response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        response_headers.insert(header::ETAG, header_value(&etag));
+        if let Some(last_modified) = &last_modified {
+            response_headers.insert(header::LAST_MODIFIED, header_value(last_modified));
+        }
+
+        if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH)
+            && if_none_match
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        {
+            return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+        }
+
+        let content_type = match self.content_type {
+            Some(content_type) => content_type,
+            None => self.detect_content_type(&mut file).await?,
+        };
+        response_headers.insert(header::CONTENT_TYPE, header_value(&content_type));
+
+        let file_name = self.file_name.clone().or_else(|| {
+            self.path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        });
+        response_headers.insert(
+            header::CONTENT_DISPOSITION,
+            content_disposition(self.disposition, file_name.as_deref()),
+        );
+
+        let range_allowed = match header_str(headers, header::IF_RANGE) {
+            None => true,
+            Some(validator) => validator == etag || Some(validator) == last_modified.as_deref(),
+        };
+        let range = match header_str(headers, header::RANGE) {
+            Some(value) if range_allowed => parse_range(value, len),
+            _ => ByteRange::Full,
+        };
+
+        let (status, start, end) = match range {
+            ByteRange::Full => (StatusCode::OK, 0, len),
+            ByteRange::Partial { start, end } => {
+                response_headers.insert(
+                    header::CONTENT_RANGE,
+                    header_value(&format!("bytes {start}-{}/{len}", end - 1)),
+                );
+                (StatusCode::PARTIAL_CONTENT, start, end)
+            }
+            ByteRange::Unsatisfiable => {
+                response_headers.insert(
+                    header::CONTENT_RANGE,
+                    header_value(&format!("bytes */{len}")),
+                );
+                return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response());
+            }
+        };
+
+        response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(end - start));
+        file.seek(SeekFrom::Start(start))
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to read file: {e}")))?;
+
+        Ok((status, response_headers, file_body(file, end - start)).into_response())
+    }
+
+    async fn detect_content_type(&self, file: &mut File) -> Result<String, ApiError> {
+        if let Some(mime) = mime_guess::from_path(&self.path).first() {
+            return Ok(mime.to_string());
+        }
+
+        let mut head = Vec::with_capacity(SNIFF_LEN);
+        (&mut *file)
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut head)
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to read file: {e}")))?;
+        Ok(sniff_content_type(&head).to_string())
+    }
+}
+
+/// Result of evaluating a `Range` header against a resource length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// Serve the whole resource
+    Full,
+    /// Serve bytes `start..end` (end exclusive)
+    Partial { start: u64, end: u64 },
+    /// The range lies outside the resource
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header for a resource of `len` bytes
+///
+/// Only single byte ranges are honoured; malformed headers and multi-range
+/// requests fall back to serving the full resource.
+pub fn parse_range(value: &str, len: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return ByteRange::Full,
+        // Suffix range: the last N bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(n) => (len.saturating_sub(n), len),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, len),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.saturating_add(1).min(len)),
+            _ => return ByteRange::Full,
+        },
+    };
+
+    if start >= len || start >= end {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial { start, end }
+    }
+}
+
+/// Guess a content type from the leading bytes of a file
+pub fn sniff_content_type(head: &[u8]) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(sig, _)| head.starts_with(sig)) {
+        return mime;
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return "video/mp4";
+    }
+    if !head.is_empty() && std::str::from_utf8(head).is_ok() && !head.contains(&0) {
+        return "text/plain; charset=utf-8";
+    }
+    "application/octet-stream"
+}
+
+/// Serve files below `root` at `GET /{*path}`
+///
+/// Paths containing `..` or absolute components are rejected with 404.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// App::new()
+///     .auto_configure()
+///     .mount(Router::new().nest("/static", serve_dir("public", Disposition::Inline)))
+/// ```
+pub fn serve_dir(root: impl Into<PathBuf>, disposition: Disposition) -> Router {
+    Router::new()
+        .route("/{*path}", get(serve_dir_handler))
+        .with_state((root.into(), disposition))
+}
+
+async fn serve_dir_handler(
+    State((root, disposition)): State<(PathBuf, Disposition)>,
+    extract::Path(path): extract::Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let relative = Path::new(&path);
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(ApiError::NotFound("File not found".to_string()));
+    }
+
+    FileDownload::new(root.join(relative))
+        .disposition(disposition)
+        .serve(&headers)
+        .await
+}
+
+fn file_body(file: File, len: u64) -> Body {
+    let stream = futures_util::stream::unfold(
+        (file.take(len), vec![0u8; CHUNK_SIZE]),
+        |(mut reader, mut buf)| async move {
+            match reader.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => Some((Ok(Bytes::copy_from_slice(&buf[..n])), (reader, buf))),
+                Err(e) => Some((Err(e), (reader, buf))),
+            }
+        },
+    );
+    Body::from_stream(stream)
+}
+
+fn content_disposition(disposition: Disposition, file_name: Option<&str>) -> HeaderValue {
+    let kind = match disposition {
+        Disposition::Inline => "inline",
+        Disposition::Attachment => "attachment",
+    };
+    let Some(name) = file_name else {
+        return HeaderValue::from_static(kind);
+    };
+
+    let fallback: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let value = if fallback == name {
+        format!("{kind}; filename=\"{name}\"")
+    } else {
+        format!(
+            "{kind}; filename=\"{fallback}\"; filename*=UTF-8''{}",
+            percent_encode(name)
+        )
+    };
+    header_value(&value)
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dy-rs-download-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join(name);
+        tokio::fs::write(&path, contents).await.unwrap();
+        path
+    }
+
+    fn range_headers(range: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, range.parse().unwrap());
+        headers
+    }
+
+    async fn body_bytes(response: Response) -> Bytes {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-4", 10),
+            ByteRange::Partial { start: 0, end: 5 }
+        );
+        assert_eq!(
+            parse_range("bytes=6-", 10),
+            ByteRange::Partial { start: 6, end: 10 }
+        );
+        assert_eq!(
+            parse_range("bytes=-3", 10),
+            ByteRange::Partial { start: 7, end: 10 }
+        );
+        assert_eq!(
+            parse_range("bytes=5-100", 10),
+            ByteRange::Partial { start: 5, end: 10 }
+        );
+        assert_eq!(
+            parse_range(&format!("bytes=0-{}", u64::MAX), 10),
+            ByteRange::Partial { start: 0, end: 10 }
+        );
+        assert_eq!(
+            parse_range(&format!("bytes={0}-{0}", u64::MAX), 10),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=10-", 10), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-0", 0), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 10), ByteRange::Full);
+    }
+
+    #[test]
+    fn sniffs_common_formats() {
+        assert_eq!(sniff_content_type(b"\x89PNG\r\n\x1a\n...."), "image/png");
+        assert_eq!(sniff_content_type(b"%PDF-1.7"), "application/pdf");
+        assert_eq!(
+            sniff_content_type(b"plain text"),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(
+            sniff_content_type(b"\x00\x01\x02"),
+            "application/octet-stream"
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_partial_content() {
+        let path = temp_file("video.mp4", b"0123456789").await;
+
+        let response = FileDownload::new(&path)
+            .serve(&range_headers("bytes=2-5"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "video/mp4");
+        assert_eq!(body_bytes(response).await, "2345");
+
+        let response = FileDownload::new(&path)
+            .serve(&range_headers("bytes=20-"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+
+        tokio::fs::remove_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale_if_range_serves_full_file() {
+        let path = temp_file("data.bin", b"0123456789").await;
+
+        let mut headers = range_headers("bytes=2-5");
+        headers.insert(header::IF_RANGE, "\"stale\"".parse().unwrap());
+        let response = FileDownload::new(&path).serve(&headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[header::ETAG].clone();
+        assert_eq!(body_bytes(response).await, "0123456789");
+
+        headers.insert(header::IF_RANGE, etag);
+        let response = FileDownload::new(&path).serve(&headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+
+        tokio::fs::remove_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sets_content_disposition() {
+        let path = temp_file("report", b"%PDF-1.7 ...").await;
+
+        let response = FileDownload::new(&path)
+            .attachment()
+            .file_name("résumé.pdf")
+            .serve(&HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/pdf");
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+        );
+
+        tokio::fs::remove_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn serve_dir_rejects_traversal() {
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let path = temp_file("index.txt", b"hello").await;
+        let app = serve_dir(path.parent().unwrap(), Disposition::Inline);
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/index.txt").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(Request::get("/../etc/passwd").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        tokio::fs::remove_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+    }
+}
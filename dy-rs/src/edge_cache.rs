@@ -0,0 +1,487 @@
+//! CDN cache tagging and purging
+//!
+//! Handlers tag responses with [`CacheTags`], emitted as both `Surrogate-Key`
+//! (Fastly) and `Cache-Tag` (Cloudflare) headers. [`PurgeOnMutationLayer`]
+//! watches successful `POST`/`PUT`/`PATCH`/`DELETE` responses and purges their
+//! tags through a [`Purger`], so a CDN never serves a resource that changed.
+//!
+//! ```rust,ignore
+//! async fn get_user(Path(id): Path<Uuid>) -> impl IntoResponse {
+//!     (CacheTags::new().tag("users").tag(format!("user:{id}")), Json(user))
+//! }
+//!
+//! async fn update_user(Path(id): Path<Uuid>, Json(input): Json<UpdateUser>) -> impl IntoResponse {
+//!     // Purges every cached response tagged `user:{id}` or `users`.
+//!     (CacheTags::new().tag("users").tag(format!("user:{id}")), Json(user))
+//! }
+//!
+//! let purger = FastlyPurger::new(service_id, token, transport);
+//! let app = Router::new()
+//!     .route("/users/{id}", get(get_user).put(update_user))
+//!     .layer(PurgeOnMutationLayer::new(purger));
+//! ```
+//!
+//! Routes served with [`crud_routes`](crate::resource::crud_routes) or
+//! [`App::resource`](crate::App::resource) tag their responses already, so
+//! the layer alone keeps the CDN in step with CRUD changes.
+//!
+//! The purgers only build the provider's API request; sending it is left to a
+//! [`PurgeTransport`], usually a closure around the app's HTTP client.
+
+use std::{convert::Infallible, future::Future, pin::Pin, sync::Arc, task};
+
+use async_trait::async_trait;
+use axum::{
+    extract::Request,
+    http::{self, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponseParts, Response, ResponseParts},
+};
+use tower::{Layer, Service};
+
+use crate::error::ApiError;
+
+/// Header read by Fastly (space-separated keys)
+pub const SURROGATE_KEY: HeaderName = HeaderName::from_static("surrogate-key");
+/// Header read by Cloudflare (comma-separated tags)
+pub const CACHE_TAG: HeaderName = HeaderName::from_static("cache-tag");
+
+/// Cache tags attached to a response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheTags(Vec<String>);
+
+impl CacheTags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tag. Tags can't contain whitespace or commas; such characters
+    /// are replaced with `_`.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        let tag: String = tag
+            .into()
+            .chars()
+            .map(|c| {
+                if c.is_whitespace() || c == ',' {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect();
+        if !tag.is_empty() && !self.0.contains(&tag) {
+            self.0.push(tag);
+        }
+        self
+    }
+
+    pub fn as_slice(&self) -> &[String] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Tags found in `Surrogate-Key` and `Cache-Tag` headers
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let surrogate = headers
+            .get_all(SURROGATE_KEY)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(str::split_whitespace);
+        let cache_tag = headers
+            .get_all(CACHE_TAG)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim);
+        surrogate.chain(cache_tag).fold(Self::new(), Self::tag)
+    }
+}
+
+impl<T: Into<String>> FromIterator<T> for CacheTags {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::new(), Self::tag)
+    }
+}
+
+impl IntoResponseParts for CacheTags {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        if self.is_empty() {
+            return Ok(res);
+        }
+        let tags = Self::from_headers(res.headers())
+            .0
+            .into_iter()
+            .chain(self.0)
+            .collect::<Self>();
+        let headers = res.headers_mut();
+        if let Ok(value) = HeaderValue::from_str(&tags.0.join(" ")) {
+            headers.insert(SURROGATE_KEY, value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&tags.0.join(",")) {
+            headers.insert(CACHE_TAG, value);
+        }
+        Ok(res)
+    }
+}
+
+/// Invalidates CDN-cached responses by tag
+#[async_trait]
+pub trait Purger: Send + Sync + 'static {
+    async fn purge(&self, tags: &[String]) -> Result<(), ApiError>;
+}
+
+/// Sends a purge API request and returns the response status
+#[async_trait]
+pub trait PurgeTransport: Send + Sync + 'static {
+    async fn send(&self, request: http::Request<String>) -> Result<StatusCode, ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> PurgeTransport for F
+where
+    F: Fn(http::Request<String>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<StatusCode, ApiError>> + Send,
+{
+    async fn send(&self, request: http::Request<String>) -> Result<StatusCode, ApiError> {
+        self(request).await
+    }
+}
+
+async fn send_checked<T: PurgeTransport>(
+    transport: &T,
+    provider: &str,
+    request: http::Request<String>,
+) -> Result<(), ApiError> {
+    let status = transport.send(request).await?;
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(ApiError::InternalServerError(format!(
+            "{provider} purge failed with status {status}"
+        )))
+    }
+}
+
+fn invalid_request(provider: &str, error: http::Error) -> ApiError {
+    ApiError::InternalServerError(format!("Invalid {provider} purge request: {error}"))
+}
+
+/// Purges by surrogate key through the Fastly API
+pub struct FastlyPurger<T> {
+    service_id: String,
+    token: String,
+    soft: bool,
+    transport: T,
+}
+
+impl<T: PurgeTransport> FastlyPurger<T> {
+    /// Fastly accepts at most this many keys per request
+    pub const MAX_KEYS: usize = 256;
+
+    pub fn new(service_id: impl Into<String>, token: impl Into<String>, transport: T) -> Self {
+        Self {
+            service_id: service_id.into(),
+            token: token.into(),
+            soft: false,
+            transport,
+        }
+    }
+
+    /// Mark content stale instead of evicting it
+    pub fn soft(mut self) -> Self {
+        self.soft = true;
+        self
+    }
+
+    /// The API request purging `keys`; fails if a key or the token isn't a
+    /// valid header value
+    pub fn request(&self, keys: &[String]) -> Result<http::Request<String>, ApiError> {
+        let mut builder = http::Request::post(format!(
+            "https://api.fastly.com/service/{}/purge",
+            self.service_id
+        ))
+        .header("fastly-key", &self.token)
+        .header(SURROGATE_KEY, keys.join(" "));
+        if self.soft {
+            builder = builder.header("fastly-soft-purge", "1");
+        }
+        builder
+            .body(String::new())
+            .map_err(|e| invalid_request("Fastly", e))
+    }
+}
+
+#[async_trait]
+impl<T: PurgeTransport> Purger for FastlyPurger<T> {
+    async fn purge(&self, tags: &[String]) -> Result<(), ApiError> {
+        for keys in tags.chunks(Self::MAX_KEYS) {
+            send_checked(&self.transport, "Fastly", self.request(keys)?).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Purges by cache tag through the Cloudflare API
+pub struct CloudflarePurger<T> {
+    zone_id: String,
+    token: String,
+    transport: T,
+}
+
+impl<T: PurgeTransport> CloudflarePurger<T> {
+    /// Cloudflare accepts at most this many tags per request
+    pub const MAX_TAGS: usize = 30;
+
+    pub fn new(zone_id: impl Into<String>, token: impl Into<String>, transport: T) -> Self {
+        Self {
+            zone_id: zone_id.into(),
+            token: token.into(),
+            transport,
+        }
+    }
+
+    /// The API request purging `tags`; fails if the token isn't a valid
+    /// header value
+    pub fn request(&self, tags: &[String]) -> Result<http::Request<String>, ApiError> {
+        http::Request::post(format!(
+            "https://api.cloudflare.com/client/v4/zones/{}/purge_cache",
+            self.zone_id
+        ))
+        .header(
+            http::header::AUTHORIZATION,
+            format!("Bearer {}", self.token),
+        )
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "tags": tags }).to_string())
+        .map_err(|e| invalid_request("Cloudflare", e))
+    }
+}
+
+#[async_trait]
+impl<T: PurgeTransport> Purger for CloudflarePurger<T> {
+    async fn purge(&self, tags: &[String]) -> Result<(), ApiError> {
+        for tags in tags.chunks(Self::MAX_TAGS) {
+            send_checked(&self.transport, "Cloudflare", self.request(tags)?).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Purger that logs tags instead of purging them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogPurger;
+
+#[async_trait]
+impl Purger for LogPurger {
+    async fn purge(&self, tags: &[String]) -> Result<(), ApiError> {
+        tracing::info!(?tags, "cache tags not purged (LogPurger)");
+        Ok(())
+    }
+}
+
+/// Layer purging the [`CacheTags`] of successful mutating responses.
+///
+/// The purge completes before the response is returned, so a client that
+/// reads back what it wrote doesn't get the stale copy. Purge failures are
+/// logged and don't fail the request.
+pub struct PurgeOnMutationLayer {
+    purger: Arc<dyn Purger>,
+}
+
+impl PurgeOnMutationLayer {
+    pub fn new(purger: impl Purger) -> Self {
+        Self {
+            purger: Arc::new(purger),
+        }
+    }
+}
+
+impl Clone for PurgeOnMutationLayer {
+    fn clone(&self) -> Self {
+        Self {
+            purger: self.purger.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for PurgeOnMutationLayer {
+    type Service = PurgeOnMutationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PurgeOnMutationService {
+            inner,
+            purger: self.purger.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PurgeOnMutationService<S> {
+    inner: S,
+    purger: Arc<dyn Purger>,
+}
+
+impl<S> Service<Request> for PurgeOnMutationService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let mutates = !matches!(
+            *req.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+        );
+        let purger = self.purger.clone();
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = future.await?;
+            if mutates && response.status().is_success() {
+                let tags = CacheTags::from_headers(response.headers());
+                if !tags.is_empty()
+                    && let Err(err) = purger.purge(tags.as_slice()).await
+                {
+                    tracing::warn!(error = %err, tags = ?tags.as_slice(), "cache purge failed");
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Json, Router,
+        body::Body,
+        response::IntoResponse,
+        routing::{get, put},
+    };
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Vec<String>>>>);
+
+    #[async_trait]
+    impl Purger for Recorder {
+        async fn purge(&self, tags: &[String]) -> Result<(), ApiError> {
+            self.0.lock().unwrap().push(tags.to_vec());
+            Ok(())
+        }
+    }
+
+    fn tags() -> CacheTags {
+        CacheTags::new().tag("users").tag("user:1")
+    }
+
+    #[tokio::test]
+    async fn tags_are_emitted_for_both_cdns() {
+        let response = (tags(), CacheTags::new().tag("users").tag("team 7"), "ok").into_response();
+        assert_eq!(response.headers()[SURROGATE_KEY], "users user:1 team_7");
+        assert_eq!(response.headers()[CACHE_TAG], "users,user:1,team_7");
+    }
+
+    #[tokio::test]
+    async fn successful_mutations_purge_their_tags() {
+        let recorder = Recorder::default();
+        let app = Router::new()
+            .route(
+                "/users/1",
+                get(|| async { (tags(), Json("alice")) }).put(|| async { (tags(), Json("bob")) }),
+            )
+            .route(
+                "/users/2",
+                put(|| async { (tags(), ApiError::BadRequest("invalid".into())) }),
+            )
+            .layer(PurgeOnMutationLayer::new(recorder.clone()));
+
+        for (method, uri) in [
+            ("GET", "/users/1"),
+            ("PUT", "/users/2"),
+            ("PUT", "/users/1"),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        assert_eq!(*recorder.0.lock().unwrap(), [["users", "user:1"]]);
+    }
+
+    #[tokio::test]
+    async fn provider_requests() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let transport = {
+            let sent = sent.clone();
+            move |request: http::Request<String>| {
+                sent.lock().unwrap().push(request);
+                async { Ok(StatusCode::OK) }
+            }
+        };
+
+        let fastly = FastlyPurger::new("svc", "secret", transport.clone()).soft();
+        fastly.purge(tags().as_slice()).await.unwrap();
+        let keys: Vec<String> = (0..40).map(|i| format!("k{i}")).collect();
+        CloudflarePurger::new("zone", "secret", transport)
+            .purge(&keys)
+            .await
+            .unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[0].uri(), "https://api.fastly.com/service/svc/purge");
+        assert_eq!(sent[0].headers()["fastly-key"], "secret");
+        assert_eq!(sent[0].headers()[SURROGATE_KEY], "users user:1");
+        assert_eq!(sent[0].headers()["fastly-soft-purge"], "1");
+        assert_eq!(
+            sent[1].uri(),
+            "https://api.cloudflare.com/client/v4/zones/zone/purge_cache"
+        );
+        assert_eq!(sent[1].headers()["authorization"], "Bearer secret");
+        let body: serde_json::Value = serde_json::from_str(sent[2].body()).unwrap();
+        assert_eq!(body["tags"].as_array().unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn failed_purges_are_errors() {
+        let purger = FastlyPurger::new("svc", "secret", |_: http::Request<String>| async {
+            Ok(StatusCode::UNAUTHORIZED)
+        });
+        assert!(purger.purge(tags().as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn invalid_header_values_are_errors() {
+        let transport = |_: http::Request<String>| async { Ok(StatusCode::OK) };
+        let keys = ["user:1\nx-injected: 1".to_string()];
+        assert!(
+            FastlyPurger::new("svc", "secret", transport)
+                .purge(&keys)
+                .await
+                .is_err()
+        );
+        assert!(
+            CloudflarePurger::new("zone", "secret\n", transport)
+                .purge(&keys)
+                .await
+                .is_err()
+        );
+    }
+}
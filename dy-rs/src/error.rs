@@ -1,10 +1,16 @@
+use std::time::Duration;
+
 use axum::{
     Json,
-    http::StatusCode,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header::RETRY_AFTER},
     response::{IntoResponse, Response},
 };
 use serde::Serialize;
+use serde_json::Value;
 use thiserror::Error;
+use utoipa::ToSchema;
+
+pub use dy_rs_macros::ApiErrorKind;
 
 /// Standard API error type
 #[derive(Debug, Error)]
@@ -21,82 +27,644 @@ pub enum ApiError {
     #[error("Forbidden")]
     Forbidden,
 
+    /// The request clashes with the resource's current state, e.g. a
+    /// duplicate email
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// The resource existed but was removed for good
+    #[error("Gone: {0}")]
+    Gone(String),
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+
+    /// A well-formed request that breaks a business rule; see
+    /// [`ValidationError`](ApiError::ValidationError) for invalid input
+    #[error("Unprocessable entity: {0}")]
+    UnprocessableEntity(String),
+
     #[error("Internal server error: {0}")]
     InternalServerError(String),
 
     #[error("Validation error: {0}")]
     ValidationError(String),
 
+    /// Answered like [`ValidatedJson`](crate::ValidatedJson) rejections, with
+    /// what's wrong with each field
+    #[error("Validation error: {}", describe_fields(.0))]
+    InvalidFields(Vec<ValidationFieldError>),
+
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+
+    #[error("Precondition required: {0}")]
+    PreconditionRequired(String),
+
+    /// Answered with a `Retry-After` header of `retry_after` seconds
+    #[error("Too many requests: {message}")]
+    TooManyRequests { message: String, retry_after: u64 },
+
+    /// Answered with a `Retry-After` header of `retry_after` seconds
+    #[error("Service unavailable: {message}")]
+    ServiceUnavailable { message: String, retry_after: u64 },
+
     #[error("Database error: {0}")]
-    DatabaseError(#[from] sqlx::Error),
+    DatabaseError(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// A failure of another library or service, answered with a `500`; see
+    /// [`external`](ApiError::external)
+    #[error("External error: {0}")]
+    External(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// An application error; see [`ApiErrorKind`]
+    #[error("{0}")]
+    Custom(Box<dyn ApiErrorKind>),
+
+    /// `error` answered with `details` in the body; see
+    /// [`with_details`](ApiError::with_details)
+    #[error("{error}")]
+    WithDetails {
+        error: Box<ApiError>,
+        details: Value,
+    },
+
+    /// `error` answered with extra `headers`; see
+    /// [`with_header`](ApiError::with_header)
+    #[error("{error}")]
+    WithHeaders {
+        error: Box<ApiError>,
+        headers: HeaderMap,
+    },
+}
+
+/// `X-RateLimit-Limit` header: requests allowed per window
+pub const X_RATELIMIT_LIMIT: &str = "x-ratelimit-limit";
+/// `X-RateLimit-Remaining` header: requests left before limiting
+pub const X_RATELIMIT_REMAINING: &str = "x-ratelimit-remaining";
+/// `X-RateLimit-Reset` header: seconds until the allowance is full again
+pub const X_RATELIMIT_RESET: &str = "x-ratelimit-reset";
+
+/// A rate limiter's state for one client after a request
+///
+/// Sent as `X-RateLimit-*` headers; see [`ApiError::rate_limited`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed per window
+    pub limit: u32,
+    /// Requests left before limiting
+    pub remaining: u32,
+    /// How long until the allowance is full again
+    pub reset: Duration,
+    /// How long until the next request is allowed, once none remain
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimit {
+    /// `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset`,
+    /// the reset rounded up to seconds
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(
+            X_RATELIMIT_RESET,
+            HeaderValue::from(whole_seconds(self.reset)),
+        );
+        headers
+    }
+}
+
+/// What's wrong with one field of a request
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ValidationFieldError {
+    pub field: String,
+    pub message: String,
+    /// The broken rule, to translate the message with
+    #[serde(skip)]
+    pub(crate) rule: Option<validator::ValidationError>,
+}
+
+impl ValidationFieldError {
+    /// `message` is also looked up as a key in the request's locale; see
+    /// [`i18n`](crate::i18n)
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+            rule: None,
+        }
+    }
+}
+
+fn describe_fields(errors: &[ValidationFieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("{}: {}", e.field, e.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// `duration` in seconds, rounded up so clients don't retry too early
+fn whole_seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// Lets handlers reject a payload with `payload.validate()?`
+impl From<validator::ValidationErrors> for ApiError {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut fields: Vec<ValidationFieldError> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| ValidationFieldError {
+                    field: field.to_string(),
+                    message: error
+                        .message
+                        .as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| "Validation failed".to_string()),
+                    rule: Some(error.clone()),
+                })
+            })
+            .collect();
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+        ApiError::InvalidFields(fields)
+    }
+}
+
+/// An application error with its own status, code and client-facing message
+///
+/// Errors converted into [`ApiError`] (with `?` in handlers) get the
+/// framework's JSON error body and logging. The derive covers the usual
+/// cases, with `#[api_error(status = ...)]` on each variant and optionally
+/// `code` (the variant name in `SCREAMING_SNAKE_CASE` by default) and
+/// `message`:
+///
+/// ```rust,ignore
+/// #[derive(Debug, thiserror::Error, ApiErrorKind)]
+/// enum OrderError {
+///     #[error("order {0} not found")]
+///     #[api_error(status = 404)]
+///     NotFound(Uuid),
+///     #[error("not enough stock of {sku}")]
+///     #[api_error(status = 409, code = "OUT_OF_STOCK")]
+///     OutOfStock { sku: String },
+///     #[error("payment gateway failed: {0}")]
+///     #[api_error(status = 502, message = "Payment provider unavailable")]
+///     Gateway(String),
+/// }
+/// ```
+///
+/// `#[dy_api(errors = OrderError)]` documents the responses in OpenAPI.
+pub trait ApiErrorKind: std::error::Error + Send + Sync + 'static {
+    fn status(&self) -> StatusCode;
+
+    /// Machine-readable code, e.g. `OUT_OF_STOCK`
+    fn code(&self) -> &str;
+
+    /// What clients are told; see [`default_public_message`]. The full
+    /// error is only logged.
+    fn public_message(&self) -> String {
+        default_public_message(self.status(), &self)
+    }
+
+    /// The statuses and codes this type answers with, for OpenAPI
+    fn documented() -> Vec<ErrorDoc>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+}
+
+/// A status and code an [`ApiErrorKind`] answers with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorDoc {
+    pub status: u16,
+    pub code: &'static str,
+}
+
+/// The error itself for client errors, and only the status's reason phrase
+/// for server errors, whose details shouldn't leak
+pub fn default_public_message(status: StatusCode, error: &dyn std::fmt::Display) -> String {
+    if status.is_server_error() {
+        status
+            .canonical_reason()
+            .unwrap_or("Internal Server Error")
+            .to_string()
+    } else {
+        error.to_string()
+    }
+}
+
+impl<E: ApiErrorKind> From<E> for ApiError {
+    fn from(err: E) -> Self {
+        ApiError::Custom(Box::new(err))
+    }
+}
+
+/// Seconds clients are asked to wait when no database connection was free
+#[cfg(any(feature = "sqlx", feature = "sea-orm", feature = "diesel-async"))]
+const POOL_TIMEOUT_RETRY_AFTER: u64 = 1;
+
+/// A timed-out wait for a pooled connection
+#[cfg(any(feature = "sqlx", feature = "sea-orm", feature = "diesel-async"))]
+fn pool_timed_out() -> ApiError {
+    ApiError::ServiceUnavailable {
+        message: "Timed out waiting for a database connection".to_string(),
+        retry_after: POOL_TIMEOUT_RETRY_AFTER,
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::PoolTimedOut => pool_timed_out(),
+            err => ApiError::DatabaseError(Box::new(err)),
+        }
+    }
+}
+
+#[cfg(feature = "sea-orm")]
+impl From<sea_orm::DbErr> for ApiError {
+    fn from(err: sea_orm::DbErr) -> Self {
+        match err {
+            sea_orm::DbErr::ConnectionAcquire(sea_orm::ConnAcquireErr::Timeout) => pool_timed_out(),
+            err => ApiError::DatabaseError(Box::new(err)),
+        }
+    }
+}
+
+#[cfg(feature = "diesel-async")]
+impl From<diesel::result::Error> for ApiError {
+    fn from(err: diesel::result::Error) -> Self {
+        ApiError::DatabaseError(Box::new(err))
+    }
+}
+
+#[cfg(feature = "diesel-async")]
+impl From<diesel_async::pooled_connection::deadpool::PoolError> for ApiError {
+    fn from(err: diesel_async::pooled_connection::deadpool::PoolError) -> Self {
+        use diesel_async::pooled_connection::deadpool::PoolError;
+
+        match err {
+            PoolError::Timeout(_) => pool_timed_out(),
+            err => ApiError::DatabaseError(Box::new(err)),
+        }
+    }
 }
 
 impl ApiError {
-    fn status_code(&self) -> StatusCode {
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::NotFound(message.into())
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::BadRequest(message.into())
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        ApiError::Conflict(message.into())
+    }
+
+    pub fn gone(message: impl Into<String>) -> Self {
+        ApiError::Gone(message.into())
+    }
+
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        ApiError::PayloadTooLarge(message.into())
+    }
+
+    pub fn unsupported_media_type(message: impl Into<String>) -> Self {
+        ApiError::UnsupportedMediaType(message.into())
+    }
+
+    pub fn unprocessable(message: impl Into<String>) -> Self {
+        ApiError::UnprocessableEntity(message.into())
+    }
+
+    /// A `429` asking clients to wait `retry_after`, rounded up to seconds
+    pub fn too_many_requests(message: impl Into<String>, retry_after: Duration) -> Self {
+        ApiError::TooManyRequests {
+            message: message.into(),
+            retry_after: whole_seconds(retry_after),
+        }
+    }
+
+    /// A `429` for a client that used up its allowance, with the limiter's
+    /// state in `X-RateLimit-*` headers and its `retry_after` (or else its
+    /// `reset`) in `Retry-After`
+    pub fn rate_limited(message: impl Into<String>, limit: &RateLimit) -> Self {
+        ApiError::too_many_requests(message, limit.retry_after.unwrap_or(limit.reset))
+            .with_headers(limit.headers())
+    }
+
+    /// A `503` asking clients to wait `retry_after`, rounded up to seconds
+    pub fn service_unavailable(message: impl Into<String>, retry_after: Duration) -> Self {
+        ApiError::ServiceUnavailable {
+            message: message.into(),
+            retry_after: whole_seconds(retry_after),
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        ApiError::InternalServerError(message.into())
+    }
+
+    /// Wrap an error from any database library, e.g. with
+    /// `.map_err(ApiError::database)`
+    pub fn database(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        ApiError::DatabaseError(err.into())
+    }
+
+    /// Wrap an error from any other library, e.g. an HTTP client's with
+    /// `.map_err(ApiError::external)`. Clients are only told it failed; the
+    /// error is logged.
+    pub fn external(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        ApiError::External(err.into())
+    }
+
+    /// Answer with `details` in the body's `details` field
+    ///
+    /// ```rust,ignore
+    /// Err(ApiError::Forbidden.with_details(json!({ "required_role": "admin" })))
+    /// ```
+    pub fn with_details(self, details: impl Serialize) -> Self {
+        let error = match self {
+            ApiError::WithDetails { error, .. } => error,
+            error => Box::new(error),
+        };
+        match serde_json::to_value(details) {
+            Ok(details) => ApiError::WithDetails { error, details },
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to serialize error details");
+                *error
+            }
+        }
+    }
+
+    /// Answer with the header `name: value`, replacing any the error sets
+    ///
+    /// ```rust,ignore
+    /// Err(ApiError::Unauthorized.with_header(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer")))
+    /// ```
+    pub fn with_header(self, name: HeaderName, value: HeaderValue) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value);
+        self.with_headers(headers)
+    }
+
+    /// Answer with `headers`, replacing any the error sets
+    pub fn with_headers(self, headers: HeaderMap) -> Self {
+        match self {
+            ApiError::WithHeaders {
+                error,
+                headers: mut existing,
+            } => {
+                existing.extend(headers);
+                ApiError::WithHeaders {
+                    error,
+                    headers: existing,
+                }
+            }
+            error => ApiError::WithHeaders {
+                error: Box::new(error),
+                headers,
+            },
+        }
+    }
+
+    /// The error under any details and headers
+    fn kind(&self) -> &ApiError {
+        match self {
+            ApiError::WithDetails { error, .. } | ApiError::WithHeaders { error, .. } => {
+                error.kind()
+            }
+            error => error,
+        }
+    }
+
+    /// The outermost details
+    fn details(&self) -> Option<&Value> {
+        match self {
+            ApiError::WithDetails { details, .. } => Some(details),
+            ApiError::WithHeaders { error, .. } => error.details(),
+            _ => None,
+        }
+    }
+
+    /// Headers added with [`with_headers`](ApiError::with_headers), inner
+    /// ones first so outer ones replace them
+    fn extra_headers(&self, into: &mut HeaderMap) {
+        match self {
+            ApiError::WithDetails { error, .. } => error.extra_headers(into),
+            ApiError::WithHeaders { error, headers } => {
+                error.extra_headers(into);
+                into.extend(headers.clone());
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn status_code(&self) -> StatusCode {
         match self {
             ApiError::NotFound(_) => StatusCode::NOT_FOUND,
             ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
             ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
             ApiError::Forbidden => StatusCode::FORBIDDEN,
-            ApiError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Gone(_) => StatusCode::GONE,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::ValidationError(_) | ApiError::InvalidFields(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            ApiError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             ApiError::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            ApiError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::DatabaseError(_) | ApiError::External(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Custom(err) => err.status(),
+            ApiError::WithDetails { error, .. } | ApiError::WithHeaders { error, .. } => {
+                error.status_code()
+            }
         }
     }
 
-    fn error_code(&self) -> &str {
+    pub(crate) fn error_code(&self) -> &str {
         match self {
             ApiError::NotFound(_) => "NOT_FOUND",
             ApiError::BadRequest(_) => "BAD_REQUEST",
             ApiError::Unauthorized => "UNAUTHORIZED",
             ApiError::Forbidden => "FORBIDDEN",
-            ApiError::ValidationError(_) => "VALIDATION_ERROR",
+            ApiError::Conflict(_) => "CONFLICT",
+            ApiError::Gone(_) => "GONE",
+            ApiError::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            ApiError::UnsupportedMediaType(_) => "UNSUPPORTED_MEDIA_TYPE",
+            ApiError::UnprocessableEntity(_) => "UNPROCESSABLE_ENTITY",
+            ApiError::ValidationError(_) | ApiError::InvalidFields(_) => "VALIDATION_ERROR",
+            ApiError::PreconditionFailed(_) => "PRECONDITION_FAILED",
+            ApiError::PreconditionRequired(_) => "PRECONDITION_REQUIRED",
+            ApiError::TooManyRequests { .. } => "TOO_MANY_REQUESTS",
             ApiError::InternalServerError(_) => "INTERNAL_SERVER_ERROR",
+            ApiError::ServiceUnavailable { .. } => "SERVICE_UNAVAILABLE",
             ApiError::DatabaseError(_) => "DATABASE_ERROR",
+            ApiError::External(_) => "INTERNAL_SERVER_ERROR",
+            ApiError::Custom(err) => err.code(),
+            ApiError::WithDetails { error, .. } | ApiError::WithHeaders { error, .. } => {
+                error.error_code()
+            }
+        }
+    }
+
+    /// The message sent to clients
+    pub(crate) fn public_message(&self) -> String {
+        match self {
+            ApiError::InvalidFields(_) => "Request validation failed".to_string(),
+            // Driver errors can name tables and queries; the reference in
+            // `details` leads to them in the logs
+            ApiError::DatabaseError(_) => "Database error".to_string(),
+            ApiError::External(_) => "Internal server error".to_string(),
+            ApiError::Custom(err) => err.public_message(),
+            ApiError::WithDetails { error, .. } | ApiError::WithHeaders { error, .. } => {
+                error.public_message()
+            }
+            err => err.to_string(),
+        }
+    }
+
+    /// Per-field errors, for validation failures
+    pub(crate) fn field_errors(&self) -> &[ValidationFieldError] {
+        match self {
+            ApiError::InvalidFields(errors) => errors,
+            ApiError::WithDetails { error, .. } | ApiError::WithHeaders { error, .. } => {
+                error.field_errors()
+            }
+            _ => &[],
         }
     }
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    code: String,
-    message: String,
+/// The error behind an [`ApiError`] response, for
+/// [`InternalErrorLayer`](crate::internal_errors::InternalErrorLayer) and
+/// [`LocalizeErrorsLayer`](crate::i18n::LocalizeErrorsLayer)
+#[derive(Clone, Debug)]
+pub(crate) struct ErrorInfo {
+    pub(crate) code: String,
+    /// The full error, as logged
+    pub(crate) message: String,
+    /// Whether the response's message was written for clients, and is kept
+    pub(crate) public: bool,
+    /// The message in the response
+    pub(crate) public_message: String,
+    /// The response's per-field errors, with the rules they broke
+    pub(crate) fields: Vec<ValidationFieldError>,
+}
+
+/// JSON body of every [`ApiError`] response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    /// What's wrong with each field, for `VALIDATION_ERROR`s
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ValidationFieldError>,
+    /// Anything else the error carries, such as the `reference` of a
+    /// database error to quote when reporting it
     #[serde(skip_serializing_if = "Option::is_none")]
-    details: Option<String>,
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let status_code = self.status_code();
         let error_code = self.error_code().to_string();
-        let message = self.to_string();
+        let message = self.public_message();
+        let errors = self.field_errors().to_vec();
+        let inner = self.kind();
+        let mut details = self.details().cloned();
+        let retry_after = match inner {
+            ApiError::TooManyRequests { retry_after, .. }
+            | ApiError::ServiceUnavailable { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        };
+        let reference =
+            matches!(inner, ApiError::DatabaseError(_)).then(|| uuid::Uuid::new_v4().to_string());
+        let public = matches!(inner, ApiError::DatabaseError(_) | ApiError::Custom(_));
+        if let Some(reference) = &reference {
+            match details.get_or_insert_with(|| Value::Object(Default::default())) {
+                Value::Object(fields) => {
+                    fields.insert("reference".to_string(), reference.clone().into());
+                }
+                _ => tracing::warn!("Non-object details hide the database error reference"),
+            }
+        }
 
         // Log the error
         tracing::error!(
             error_code = %error_code,
             status = %status_code,
-            message = %message,
+            message = %self,
+            reference = reference.as_deref(),
             "API error occurred"
         );
 
         let error_response = ErrorResponse {
             code: error_code,
-            message,
-            details: None,
+            message: message.clone(),
+            errors: errors.clone(),
+            details,
         };
 
-        (status_code, Json(error_response)).into_response()
+        let mut response = (status_code, Json(error_response)).into_response();
+        if let Some(retry_after) = retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        if matches!(inner, ApiError::TooManyRequests { .. }) {
+            response
+                .headers_mut()
+                .insert(X_RATELIMIT_REMAINING, HeaderValue::from(0));
+        }
+        self.extra_headers(response.headers_mut());
+        response.extensions_mut().insert(ErrorInfo {
+            code: self.error_code().to_string(),
+            message: self.to_string(),
+            public,
+            public_message: message,
+            fields: errors,
+        });
+        response
     }
 }
 
 /// Convenient Result type for API handlers
 pub type ApiResult<T> = Result<Json<T>, ApiError>;
 
+/// Support code for `#[derive(ApiErrorKind)]` expansions. Not public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use axum::{
+        http::StatusCode,
+        response::{IntoResponse, Response},
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use super::ApiError;
     use axum::{body, http::StatusCode, response::IntoResponse};
     use serde_json::Value;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn maps_variants_to_status_and_code() {
@@ -122,11 +690,71 @@ mod tests {
                 StatusCode::UNPROCESSABLE_ENTITY,
                 "VALIDATION_ERROR",
             ),
+            (
+                ApiError::PreconditionFailed("x".into()),
+                StatusCode::PRECONDITION_FAILED,
+                "PRECONDITION_FAILED",
+            ),
+            (
+                ApiError::PreconditionRequired("x".into()),
+                StatusCode::PRECONDITION_REQUIRED,
+                "PRECONDITION_REQUIRED",
+            ),
+            (
+                ApiError::too_many_requests("x", Duration::from_secs(1)),
+                StatusCode::TOO_MANY_REQUESTS,
+                "TOO_MANY_REQUESTS",
+            ),
+            (ApiError::conflict("x"), StatusCode::CONFLICT, "CONFLICT"),
+            (ApiError::gone("x"), StatusCode::GONE, "GONE"),
+            (
+                ApiError::payload_too_large("x"),
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "PAYLOAD_TOO_LARGE",
+            ),
+            (
+                ApiError::unsupported_media_type("x"),
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "UNSUPPORTED_MEDIA_TYPE",
+            ),
+            (
+                ApiError::unprocessable("x"),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "UNPROCESSABLE_ENTITY",
+            ),
             (
                 ApiError::InternalServerError("x".into()),
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "INTERNAL_SERVER_ERROR",
             ),
+            (
+                ApiError::ServiceUnavailable {
+                    message: "x".into(),
+                    retry_after: 1,
+                },
+                StatusCode::SERVICE_UNAVAILABLE,
+                "SERVICE_UNAVAILABLE",
+            ),
+            (
+                ApiError::database("x"),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "DATABASE_ERROR",
+            ),
+            (
+                ApiError::external(std::io::Error::other("x")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL_SERVER_ERROR",
+            ),
+            (
+                ApiError::InvalidFields(Vec::new()),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "VALIDATION_ERROR",
+            ),
+            (
+                ApiError::Forbidden.with_details("x"),
+                StatusCode::FORBIDDEN,
+                "FORBIDDEN",
+            ),
         ];
 
         for (err, expected_status, expected_code) in cases {
@@ -138,4 +766,150 @@ mod tests {
             assert_eq!(json.get("code").unwrap(), expected_code);
         }
     }
+
+    async fn json_of(err: ApiError) -> Value {
+        let body = body::to_bytes(err.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn validation_errors_list_each_field() {
+        #[derive(validator::Validate)]
+        struct Signup {
+            #[validate(email(message = "Not an email"))]
+            email: String,
+            #[validate(length(min = 8))]
+            password: String,
+        }
+        let signup = Signup {
+            email: "nope".into(),
+            password: "short".into(),
+        };
+        let err = ApiError::from(validator::Validate::validate(&signup).unwrap_err());
+        assert_eq!(
+            err.to_string(),
+            "Validation error: email: Not an email; password: Validation failed"
+        );
+
+        let json = json_of(err).await;
+        assert_eq!(json["message"], "Request validation failed");
+        assert_eq!(
+            json["errors"],
+            serde_json::json!([
+                { "field": "email", "message": "Not an email" },
+                { "field": "password", "message": "Validation failed" }
+            ])
+        );
+        assert!(json.get("details").is_none());
+    }
+
+    #[tokio::test]
+    async fn details_are_attached_and_database_errors_get_a_reference() {
+        let json = json_of(
+            ApiError::Forbidden
+                .with_details(serde_json::json!({ "required_role": "admin" }))
+                .with_details(serde_json::json!({ "required_role": "owner" })),
+        )
+        .await;
+        assert_eq!(json["code"], "FORBIDDEN");
+        assert_eq!(
+            json["details"],
+            serde_json::json!({ "required_role": "owner" })
+        );
+
+        let json = json_of(ApiError::database("relation \"users\" does not exist")).await;
+        assert_eq!(json["message"], "Database error");
+        let reference = json["details"]["reference"].as_str().unwrap();
+        assert!(uuid::Uuid::parse_str(reference).is_ok());
+
+        let json = json_of(ApiError::external("token endpoint returned 502")).await;
+        assert_eq!(json["message"], "Internal server error");
+
+        let json =
+            json_of(ApiError::database("down").with_details(serde_json::json!({ "shard": 3 })))
+                .await;
+        assert_eq!(json["details"]["shard"], 3);
+        assert!(json["details"]["reference"].is_string());
+    }
+
+    #[test]
+    fn retry_after_is_rounded_up_to_seconds() {
+        let res =
+            ApiError::too_many_requests("slow down", Duration::from_millis(1500)).into_response();
+        assert_eq!(res.headers()[axum::http::header::RETRY_AFTER], "2");
+
+        let res = ApiError::service_unavailable("maintenance", Duration::from_secs(30))
+            .with_details(serde_json::json!({ "until": "02:00" }))
+            .into_response();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[axum::http::header::RETRY_AFTER], "30");
+    }
+
+    #[tokio::test]
+    async fn headers_are_added_and_rate_limits_reported() {
+        use axum::http::{HeaderValue, header::WWW_AUTHENTICATE};
+
+        let res = ApiError::Unauthorized
+            .with_header(WWW_AUTHENTICATE, HeaderValue::from_static("Basic"))
+            .with_details(serde_json::json!({ "realm": "api" }))
+            .with_header(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))
+            .into_response();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[WWW_AUTHENTICATE], "Bearer");
+        let body = body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["code"], "UNAUTHORIZED");
+        assert_eq!(json["details"]["realm"], "api");
+
+        let limit = super::RateLimit {
+            limit: 100,
+            remaining: 0,
+            reset: Duration::from_millis(59_500),
+            retry_after: Some(Duration::from_millis(600)),
+        };
+        let res = ApiError::rate_limited("slow down", &limit).into_response();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[axum::http::header::RETRY_AFTER], "1");
+        assert_eq!(res.headers()["x-ratelimit-limit"], "100");
+        assert_eq!(res.headers()["x-ratelimit-remaining"], "0");
+        assert_eq!(res.headers()["x-ratelimit-reset"], "60");
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[test]
+    fn pool_timeouts_ask_clients_to_retry() {
+        let res = ApiError::from(sqlx::Error::PoolTimedOut).into_response();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[axum::http::header::RETRY_AFTER], "1");
+
+        let res = ApiError::from(sqlx::Error::RowNotFound).into_response();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!res.headers().contains_key(axum::http::header::RETRY_AFTER));
+    }
+
+    #[cfg(feature = "sea-orm")]
+    #[test]
+    fn sea_orm_errors_convert() {
+        use sea_orm::{ConnAcquireErr, DbErr};
+
+        let res = ApiError::from(DbErr::ConnectionAcquire(ConnAcquireErr::Timeout)).into_response();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[axum::http::header::RETRY_AFTER], "1");
+
+        let err = ApiError::from(DbErr::RecordNotFound("user 1".to_string()));
+        assert!(matches!(err, ApiError::DatabaseError(_)));
+    }
+
+    #[cfg(feature = "diesel-async")]
+    #[test]
+    fn diesel_errors_convert() {
+        use diesel_async::pooled_connection::deadpool::PoolError;
+
+        let err = ApiError::from(PoolError::Closed);
+        assert!(matches!(err, ApiError::DatabaseError(_)));
+        let err = ApiError::from(diesel::result::Error::NotFound);
+        assert!(matches!(err, ApiError::DatabaseError(_)));
+    }
 }
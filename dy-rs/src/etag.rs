@@ -0,0 +1,428 @@
+//! ETags and conditional requests
+//!
+//! [`ETagLayer`] gives `GET` JSON responses an `ETag` computed from their
+//! body and answers `304 Not Modified` when the client's `If-None-Match`
+//! already has it, saving the transfer. Handlers that know their entity's
+//! version can set the tag themselves, which the layer keeps:
+//!
+//! ```rust,ignore
+//! async fn get_user(Path(id): Path<Uuid>) -> ApiResult<impl IntoResponse> {
+//!     let user = find_user(id).await?;
+//!     Ok((ETag::from_version(user.version), Json(user)))
+//! }
+//! ```
+//!
+//! For optimistic concurrency, [`IfMatch`] rejects `PUT`/`PATCH` requests
+//! whose `If-Match` doesn't name the current version, so a client can't
+//! overwrite changes it hasn't seen:
+//!
+//! ```rust,ignore
+//! async fn update_user(
+//!     Path(id): Path<Uuid>,
+//!     if_match: IfMatch,
+//!     Json(input): Json<UpdateUser>,
+//! ) -> ApiResult<impl IntoResponse> {
+//!     let user = find_user(id).await?;
+//!     if_match.require(&ETag::from_version(user.version))?;
+//!     let user = save(user.apply(input)).await?;
+//!     Ok((ETag::from_version(user.version), Json(user)))
+//! }
+//!
+//! let app = Router::new()
+//!     .route("/users/{id}", get(get_user).put(update_user))
+//!     .layer(ETagLayer::new());
+//! ```
+
+use std::{convert::Infallible, fmt, future::Future, pin::Pin, str::FromStr, task};
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{FromRequestParts, Request},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header, request::Parts},
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tower::{Layer, Service};
+
+use crate::error::ApiError;
+
+/// An entity tag identifying one version of a resource
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ETag {
+    value: String,
+    weak: bool,
+}
+
+impl ETag {
+    /// A tag promising byte-for-byte identical representations
+    ///
+    /// # Panics
+    ///
+    /// If `value` contains `"` or characters not allowed in headers.
+    pub fn strong(value: impl Into<String>) -> Self {
+        Self::new(value.into(), false)
+    }
+
+    /// A tag promising only semantically equivalent representations
+    ///
+    /// # Panics
+    ///
+    /// If `value` contains `"` or characters not allowed in headers.
+    pub fn weak(value: impl Into<String>) -> Self {
+        Self::new(value.into(), true)
+    }
+
+    fn new(value: String, weak: bool) -> Self {
+        assert!(
+            value
+                .bytes()
+                .all(|b| b == b'!' || (b'#'..=b'~').contains(&b)),
+            "invalid entity tag `{value}`"
+        );
+        Self { value, weak }
+    }
+
+    /// A tag from an entity's version column, revision counter or
+    /// `updated_at` timestamp
+    pub fn from_version(version: impl fmt::Display) -> Self {
+        Self::strong(format!("v{version}"))
+    }
+
+    /// A tag from a hash of `bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let digest = hex::encode(Sha256::digest(bytes));
+        Self::strong(&digest[..16])
+    }
+
+    /// A tag from a hash of `value` serialized as JSON
+    pub fn from_json<T: Serialize>(value: &T) -> Self {
+        let json = serde_json::to_vec(value).expect("value serializes to JSON");
+        Self::from_bytes(&json)
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Same version, ignoring weakness (used by `If-None-Match`)
+    pub fn weak_eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+
+    /// Same version and both strong (used by `If-Match`)
+    pub fn strong_eq(&self, other: &Self) -> bool {
+        !self.weak && !other.weak && self.value == other.value
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.value)
+    }
+}
+
+impl FromStr for ETag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (weak, quoted) = match s.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let value = quoted
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .filter(|value| !value.contains('"'))
+            .ok_or_else(|| format!("invalid entity tag `{s}`"))?;
+        Ok(Self {
+            value: value.to_string(),
+            weak,
+        })
+    }
+}
+
+impl IntoResponseParts for ETag {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let value = HeaderValue::try_from(self.to_string()).expect("entity tags are valid headers");
+        res.headers_mut().insert(header::ETAG, value);
+        Ok(res)
+    }
+}
+
+/// Tags listed in a conditional header; `None` for `*`
+fn listed_tags(headers: &HeaderMap, name: header::HeaderName) -> Option<Option<Vec<ETag>>> {
+    let mut tags = Vec::new();
+    let mut present = false;
+    for value in headers.get_all(name) {
+        present = true;
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for item in value.split(',') {
+            if item.trim() == "*" {
+                return Some(None);
+            }
+            tags.extend(item.parse::<ETag>().ok());
+        }
+    }
+    present.then_some(Some(tags))
+}
+
+/// Whether `If-None-Match` in `headers` already has `etag`
+fn not_modified(headers: &HeaderMap, etag: &ETag) -> bool {
+    match listed_tags(headers, header::IF_NONE_MATCH) {
+        Some(None) => true,
+        Some(Some(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        None => false,
+    }
+}
+
+/// The request's `If-Match` precondition
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IfMatch(Option<Option<Vec<ETag>>>);
+
+impl IfMatch {
+    /// Whether the client sent `If-Match`
+    pub fn is_present(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Fail with `412 Precondition Failed` unless `If-Match` is absent, `*`,
+    /// or lists `current`
+    pub fn check(&self, current: &ETag) -> Result<(), ApiError> {
+        let matched = match &self.0 {
+            None | Some(None) => true,
+            Some(Some(tags)) => tags.iter().any(|tag| tag.strong_eq(current)),
+        };
+        if matched {
+            Ok(())
+        } else {
+            Err(ApiError::PreconditionFailed(
+                "The resource was modified; fetch it again and retry".to_string(),
+            ))
+        }
+    }
+
+    /// Like [`check`](Self::check), but fail with `428 Precondition Required`
+    /// when `If-Match` is absent
+    pub fn require(&self, current: &ETag) -> Result<(), ApiError> {
+        if !self.is_present() {
+            return Err(ApiError::PreconditionRequired(
+                "Send If-Match with the resource's ETag".to_string(),
+            ));
+        }
+        self.check(current)
+    }
+}
+
+impl<S> FromRequestParts<S> for IfMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(listed_tags(&parts.headers, header::IF_MATCH)))
+    }
+}
+
+/// Layer adding ETags to `GET` JSON responses and answering `If-None-Match`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ETagLayer;
+
+impl ETagLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for ETagLayer {
+    type Service = ETagService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ETagService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct ETagService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for ETagService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let cacheable = matches!(*req.method(), Method::GET | Method::HEAD);
+        let request_headers = req.headers().clone();
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = future.await?;
+            if !cacheable || response.status() != StatusCode::OK {
+                return Ok(response);
+            }
+
+            let existing = response
+                .headers()
+                .get(header::ETAG)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<ETag>().ok());
+            let (etag, response) = match existing {
+                Some(etag) => (etag, response),
+                None if is_json(response.headers()) => {
+                    let (mut parts, body) = response.into_parts();
+                    let bytes = match to_bytes(body, usize::MAX).await {
+                        Ok(bytes) => bytes,
+                        Err(err) => {
+                            return Ok(ApiError::InternalServerError(format!(
+                                "Failed to read response body: {err}"
+                            ))
+                            .into_response());
+                        }
+                    };
+                    let etag = ETag::from_bytes(&bytes);
+                    parts.headers.insert(
+                        header::ETAG,
+                        HeaderValue::try_from(etag.to_string()).expect("valid entity tag"),
+                    );
+                    (etag, Response::from_parts(parts, Body::from(bytes)))
+                }
+                None => return Ok(response),
+            };
+
+            if not_modified(&request_headers, &etag) {
+                let (mut parts, _) = response.into_parts();
+                parts.status = StatusCode::NOT_MODIFIED;
+                parts.headers.remove(header::CONTENT_TYPE);
+                parts.headers.remove(header::CONTENT_LENGTH);
+                return Ok(Response::from_parts(parts, Body::empty()));
+            }
+            Ok(response)
+        })
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            let mime = value.split(';').next().unwrap_or_default().trim();
+            mime == "application/json" || mime.ends_with("+json")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, routing::get};
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/users/1",
+                get(|| async { Json(serde_json::json!({"id": 1})) }),
+            )
+            .route(
+                "/users/2",
+                get(|| async { (ETag::from_version(7), Json(serde_json::json!({"id": 2}))) }),
+            )
+            .route("/text", get(|| async { "plain" }))
+            .layer(ETagLayer::new())
+    }
+
+    async fn get_with(uri: &str, if_none_match: Option<&str>) -> Response {
+        let mut request = Request::builder().uri(uri);
+        if let Some(tag) = if_none_match {
+            request = request.header(header::IF_NONE_MATCH, tag);
+        }
+        app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn tags_round_trip_and_compare() {
+        let strong: ETag = "\"abc\"".parse().unwrap();
+        let weak: ETag = "W/\"abc\"".parse().unwrap();
+        assert_eq!(strong.to_string(), "\"abc\"");
+        assert_eq!(weak.to_string(), "W/\"abc\"");
+        assert!(strong.weak_eq(&weak));
+        assert!(!strong.strong_eq(&weak));
+        assert!("abc".parse::<ETag>().is_err());
+        assert_eq!(ETag::from_version(3).to_string(), "\"v3\"");
+    }
+
+    #[tokio::test]
+    async fn json_responses_get_etags_and_304s() {
+        let first = get_with("/users/1", None).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = first.headers()[header::ETAG].to_str().unwrap().to_string();
+
+        let again = get_with("/users/1", Some(&format!("\"other\", W/{etag}"))).await;
+        assert_eq!(again.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(again.headers()[header::ETAG], etag.as_str());
+        let body = to_bytes(again.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+
+        let changed = get_with("/users/1", Some("\"other\"")).await;
+        assert_eq!(changed.status(), StatusCode::OK);
+
+        let text = get_with("/text", None).await;
+        assert!(text.headers().get(header::ETAG).is_none());
+    }
+
+    #[tokio::test]
+    async fn handler_tags_are_kept() {
+        let res = get_with("/users/2", None).await;
+        assert_eq!(res.headers()[header::ETAG], "\"v7\"");
+        let res = get_with("/users/2", Some("\"v7\"")).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn if_match_guards_updates() {
+        let parse = |value: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(value) = value {
+                headers.insert(header::IF_MATCH, HeaderValue::from_str(value).unwrap());
+            }
+            IfMatch(listed_tags(&headers, header::IF_MATCH))
+        };
+        let current = ETag::from_version(4);
+
+        assert!(parse(Some("\"v3\", \"v4\"")).check(&current).is_ok());
+        assert!(parse(Some("*")).require(&current).is_ok());
+        assert!(parse(None).check(&current).is_ok());
+        assert!(matches!(
+            parse(Some("\"v3\"")).check(&current),
+            Err(ApiError::PreconditionFailed(_))
+        ));
+        // Weak tags never satisfy If-Match
+        assert!(parse(Some("W/\"v4\"")).check(&current).is_err());
+        assert!(matches!(
+            parse(None).require(&current),
+            Err(ApiError::PreconditionRequired(_))
+        ));
+    }
+}
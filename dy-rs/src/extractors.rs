@@ -1,12 +1,18 @@
 use axum::{
-    Json,
-    extract::{FromRequest, Request},
-    http::StatusCode,
+    Form, Json,
+    extract::{
+        FromRequest, FromRequestParts, RawPathParams, Request,
+        path::{ErrorKind, FailedToDeserializePathParams},
+        rejection::PathRejection,
+    },
+    http::{StatusCode, request::Parts},
     response::{IntoResponse, Response},
 };
 use serde::{Serialize, de::DeserializeOwned};
 use validator::Validate;
 
+use crate::{error::ValidationFieldError, i18n::Locale};
+
 /// Extractor that deserializes and validates JSON payloads
 ///
 /// # Example
@@ -32,6 +38,71 @@ use validator::Validate;
 /// ```
 pub struct ValidatedJson<T>(pub T);
 
+/// Extractor that deserializes and validates `application/x-www-form-urlencoded`
+/// bodies, e.g. HTML form posts or OAuth token requests
+///
+/// Rejections use the same envelope as [`ValidatedJson`], with the code
+/// `INVALID_FORM` when the body can't be decoded.
+///
+/// ```rust,ignore
+/// #[derive(Deserialize, Validate)]
+/// struct TokenRequest {
+///     #[validate(length(min = 1))]
+///     grant_type: String,
+///     code: Option<String>,
+/// }
+///
+/// async fn token(ValidatedForm(req): ValidatedForm<TokenRequest>) -> ApiResult<Token> {
+///     // ...
+/// }
+/// ```
+pub struct ValidatedForm<T>(pub T);
+
+/// Drop-in replacement for axum's `Path` whose rejections use the JSON error
+/// envelope, naming the parameter that failed to parse
+///
+/// The prelude exports this one, so `Path(id): Path<Uuid>` answers a
+/// malformed ID with `400 INVALID_PATH` instead of a plain-text error:
+///
+/// ```json
+/// {"code": "INVALID_PATH", "message": "Invalid path parameters",
+///  "errors": [{"field": "id", "message": "`abc` is not a valid Uuid"}]}
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+impl<T> std::ops::Deref for Path<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for Path<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Extractor that deserializes and validates path parameters
+///
+/// Parse failures are rejected like [`Path`]; values that parse but fail
+/// validation, such as an out-of-range ID, get `422 VALIDATION_ERROR`.
+///
+/// ```rust,ignore
+/// #[derive(Deserialize, Validate)]
+/// struct PageParams {
+///     #[validate(range(min = 1, max = 500))]
+///     page: u32,
+/// }
+///
+/// async fn page(ValidatedPath(params): ValidatedPath<PageParams>) -> ApiResult<Page> {
+///     // ...
+/// }
+/// ```
+pub struct ValidatedPath<T>(pub T);
+
 #[derive(Serialize)]
 struct ValidationErrorResponse {
     code: String,
@@ -39,11 +110,231 @@ struct ValidationErrorResponse {
     errors: Vec<ValidationFieldError>,
 }
 
+/// The rule's message looked up as a key, then `validation-{code}`, with the
+/// field and the rule's parameters as arguments
+pub(crate) fn localize_validation_error(
+    field: &str,
+    error: &validator::ValidationError,
+    locale: &Locale,
+) -> String {
+    let params: Vec<(&str, String)> = error
+        .params
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (name.as_ref(), value)
+        })
+        .collect();
+    let mut args: Vec<(&str, &dyn std::fmt::Display)> = vec![("field", &field)];
+    args.extend(params.iter().map(|(name, value)| (*name, value as _)));
+
+    error
+        .message
+        .as_ref()
+        .and_then(|message| locale.lookup(message, &args))
+        .or_else(|| locale.lookup(&format!("validation-{}", error.code), &args))
+        .or_else(|| error.message.as_ref().map(|m| m.to_string()))
+        .unwrap_or_else(|| "Validation failed".to_string())
+}
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate + Send + 'static,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let locale = Locale::negotiate(req.headers(), req.extensions());
+
+        // First, extract JSON
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| {
+                tracing::error!("JSON deserialization failed: {:?}", rejection);
+                invalid_payload("INVALID_JSON", "Invalid JSON payload", &locale)
+            })?;
+
+        // Then validate
+        value
+            .validate()
+            .map_err(|errors| validation_failed(errors, &locale))?;
+
+        Ok(ValidatedJson(value))
+    }
+}
+
+impl<T, S> FromRequest<S> for ValidatedForm<T>
+where
+    T: DeserializeOwned + Validate + Send + 'static,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let locale = Locale::negotiate(req.headers(), req.extensions());
+        let Form(value) = Form::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| {
+                tracing::error!("Form deserialization failed: {:?}", rejection);
+                invalid_payload("INVALID_FORM", "Invalid form payload", &locale)
+            })?;
+
+        value
+            .validate()
+            .map_err(|errors| validation_failed(errors, &locale))?;
+
+        Ok(ValidatedForm(value))
+    }
+}
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Path(value)),
+            Err(PathRejection::FailedToDeserializePathParams(err)) => {
+                let raw = RawPathParams::from_request_parts(parts, state).await.ok();
+                Err(invalid_path(err, raw))
+            }
+            // Missing params mean the route is misconfigured, not a bad request.
+            Err(rejection) => Err(rejection.into_response()),
+        }
+    }
+}
+
+impl<T, S> FromRequestParts<S> for ValidatedPath<T>
+where
+    T: DeserializeOwned + Validate + Send,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state).await?;
+
+        let locale = Locale::negotiate(&parts.headers, &parts.extensions);
+        value
+            .validate()
+            .map_err(|errors| validation_failed(errors, &locale))?;
+
+        Ok(ValidatedPath(value))
+    }
+}
+
+fn invalid_path(err: FailedToDeserializePathParams, raw: Option<RawPathParams>) -> Response {
+    tracing::debug!("Path deserialization failed: {}", err.body_text());
+
+    // Errors for a bare `Path<Uuid>` don't carry the key, but then there is
+    // only one parameter it can be.
+    let only_key = || {
+        raw.as_ref()
+            .and_then(|raw| {
+                let mut keys = raw.iter().map(|(key, _)| key.to_string());
+                keys.next().filter(|_| keys.next().is_none())
+            })
+            .unwrap_or_else(|| "path".to_string())
+    };
+
+    let invalid = |value: &str, ty: &str| {
+        let ty = ty.rsplit("::").next().unwrap_or(ty);
+        format!("`{value}` is not a valid {ty}")
+    };
+    let (field, message) = match err.kind() {
+        ErrorKind::ParseErrorAtKey {
+            key,
+            value,
+            expected_type,
+        } => (key.clone(), invalid(value, expected_type)),
+        ErrorKind::ParseErrorAtIndex {
+            index,
+            value,
+            expected_type,
+        } => (index.to_string(), invalid(value, expected_type)),
+        ErrorKind::ParseError {
+            value,
+            expected_type,
+        } => (only_key(), invalid(value, expected_type)),
+        ErrorKind::DeserializeError { key, message, .. } => (key.clone(), message.clone()),
+        ErrorKind::InvalidUtf8InPathParam { key } => (key.clone(), "Invalid UTF-8".to_string()),
+        ErrorKind::Message(message) => (only_key(), message.clone()),
+        // The remaining kinds are programmer errors, answered with a 500.
+        _ => return err.into_response(),
+    };
+
+    let error_response = ValidationErrorResponse {
+        code: "INVALID_PATH".to_string(),
+        message: "Invalid path parameters".to_string(),
+        errors: vec![ValidationFieldError::new(field, message)],
+    };
+
+    (StatusCode::BAD_REQUEST, Json(error_response)).into_response()
+}
+
+/// `message`, or its translation under `code` as a key (`INVALID_JSON` is
+/// looked up as `invalid-json`)
+fn invalid_payload(code: &str, message: &str, locale: &Locale) -> Response {
+    let key = code.to_ascii_lowercase().replace('_', "-");
+    let error_response = ValidationErrorResponse {
+        code: code.to_string(),
+        message: locale
+            .lookup(&key, &[])
+            .unwrap_or_else(|| message.to_string()),
+        errors: vec![],
+    };
+
+    (StatusCode::BAD_REQUEST, Json(error_response)).into_response()
+}
+
+fn validation_failed(validation_errors: validator::ValidationErrors, locale: &Locale) -> Response {
+    tracing::error!("Validation failed: {:?}", validation_errors);
+
+    let errors: Vec<ValidationFieldError> = validation_errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |error| {
+                ValidationFieldError::new(
+                    field.to_string(),
+                    localize_validation_error(&field, error, locale),
+                )
+            })
+        })
+        .collect();
+
+    let error_response = ValidationErrorResponse {
+        code: "VALIDATION_ERROR".to_string(),
+        message: locale
+            .lookup("validation-failed", &[])
+            .unwrap_or_else(|| "Request validation failed".to_string()),
+        errors,
+    };
+
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(error_response)).into_response()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::ValidatedJson;
-    use axum::{body::Body, extract::FromRequest, http::Request};
+    use super::{Path, ValidatedForm, ValidatedJson, ValidatedPath};
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        extract::FromRequest,
+        http::{Request, StatusCode},
+        response::Response,
+        routing::get,
+    };
     use serde::Deserialize;
+    use serde_json::Value;
+    use tower::ServiceExt;
     use validator::Validate;
 
     #[derive(Debug, Deserialize, Validate)]
@@ -76,70 +367,129 @@ mod tests {
         let result = ValidatedJson::<TestPayload>::from_request(req, &()).await;
         assert!(result.is_err(), "expected validation error for short name");
     }
-}
 
-#[derive(Serialize)]
-struct ValidationFieldError {
-    field: String,
-    message: String,
-}
+    fn form_request(body: &'static str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap()
+    }
 
-impl<T, S> FromRequest<S> for ValidatedJson<T>
-where
-    T: DeserializeOwned + Validate + Send + 'static,
-    S: Send + Sync,
-{
-    type Rejection = Response;
+    #[tokio::test]
+    async fn validated_form_accepts_valid_payload() {
+        let result =
+            ValidatedForm::<TestPayload>::from_request(form_request("name=abc+d"), &()).await;
+        assert_eq!(result.ok().unwrap().0.name, "abc d");
+    }
 
-    fn from_request(
-        req: Request,
-        state: &S,
-    ) -> impl std::future::Future<Output = Result<Self, Self::Rejection>> + Send {
-        async move {
-            // First, extract JSON
-            let Json(value) = Json::<T>::from_request(req, state)
-                .await
-                .map_err(|rejection| {
-                    tracing::error!("JSON deserialization failed: {:?}", rejection);
-
-                    let error_response = ValidationErrorResponse {
-                        code: "INVALID_JSON".to_string(),
-                        message: "Invalid JSON payload".to_string(),
-                        errors: vec![],
-                    };
-
-                    (StatusCode::BAD_REQUEST, Json(error_response)).into_response()
-                })?;
-
-            // Then validate
-            value.validate().map_err(|validation_errors| {
-                tracing::error!("Validation failed: {:?}", validation_errors);
-
-                let errors: Vec<ValidationFieldError> = validation_errors
-                    .field_errors()
-                    .into_iter()
-                    .flat_map(|(field, errors)| {
-                        errors.iter().map(move |error| ValidationFieldError {
-                            field: field.to_string(),
-                            message: error
-                                .message
-                                .as_ref()
-                                .map(|m| m.to_string())
-                                .unwrap_or_else(|| "Validation failed".to_string()),
-                        })
-                    })
-                    .collect();
-
-                let error_response = ValidationErrorResponse {
-                    code: "VALIDATION_ERROR".to_string(),
-                    message: "Request validation failed".to_string(),
-                    errors,
-                };
-
-                (StatusCode::UNPROCESSABLE_ENTITY, Json(error_response)).into_response()
-            })?;
+    #[tokio::test]
+    async fn validated_form_rejects_invalid_and_malformed_payloads() {
+        let invalid = ValidatedForm::<TestPayload>::from_request(form_request("name=a"), &()).await;
+        assert_eq!(
+            invalid.err().unwrap().status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
 
-            Ok(ValidatedJson(value))
-        }
+        let missing =
+            ValidatedForm::<TestPayload>::from_request(form_request("other=x"), &()).await;
+        assert_eq!(missing.err().unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct PageParams {
+        #[validate(range(min = 1, max = 500))]
+        page: u32,
+    }
+
+    fn path_app() -> Router {
+        Router::new()
+            .route(
+                "/users/{id}",
+                get(|Path(id): Path<uuid::Uuid>| async move { id.to_string() }),
+            )
+            .route(
+                "/pages/{page}",
+                get(
+                    |ValidatedPath(params): ValidatedPath<PageParams>| async move {
+                        params.page.to_string()
+                    },
+                ),
+            )
+    }
+
+    async fn get_json(uri: &str) -> (StatusCode, Value) {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = path_app().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn path_rejections_name_the_parameter() {
+        let (status, body) = get_json("/users/not-a-uuid").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "INVALID_PATH");
+        assert_eq!(body["errors"][0]["field"], "id");
+
+        let (status, body) = get_json("/pages/99999999999").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["errors"][0]["field"], "page");
+        assert_eq!(
+            body["errors"][0]["message"],
+            "`99999999999` is not a valid u32"
+        );
+    }
+
+    #[tokio::test]
+    async fn validated_path_checks_ranges() {
+        let (status, _) = get_json("/pages/12").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = get_json("/pages/0").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errors"][0]["field"], "page");
+    }
+
+    #[tokio::test]
+    async fn validation_errors_follow_accept_language() {
+        let catalog = crate::i18n::Catalog::new("en")
+            .add("de", "validation-failed = Ungültige Anfrage\nvalidation-length = { $field } braucht mindestens { $min } Zeichen\n")
+            .unwrap();
+        let request = |language: &str| {
+            let mut req = Request::builder()
+                .uri("/")
+                .header("Content-Type", "application/json")
+                .header("Accept-Language", language)
+                .body(Body::from(r#"{"name":"a"}"#))
+                .unwrap();
+            req.extensions_mut().insert(catalog.clone());
+            req
+        };
+        let body = |res: Response| async move {
+            let bytes = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<Value>(&bytes).unwrap()
+        };
+
+        let res = ValidatedJson::<TestPayload>::from_request(request("de-AT"), &())
+            .await
+            .err()
+            .unwrap();
+        let de = body(res).await;
+        assert_eq!(de["message"], "Ungültige Anfrage");
+        assert_eq!(
+            de["errors"][0]["message"],
+            "name braucht mindestens 3 Zeichen"
+        );
+
+        let res = ValidatedJson::<TestPayload>::from_request(request("fr"), &())
+            .await
+            .err()
+            .unwrap();
+        let fallback = body(res).await;
+        assert_eq!(fallback["message"], "Request validation failed");
+        assert_eq!(fallback["errors"][0]["message"], "Validation failed");
     }
 }
@@ -0,0 +1,560 @@
+//! Localized messages
+//!
+//! A [`Catalog`] holds the messages of each locale, loaded from one file per
+//! locale named after it (`locales/en.ftl`, `locales/pt-BR.ftl`, ...). Files
+//! use the plain-message subset of [Fluent](https://projectfluent.org):
+//!
+//! ```text
+//! # comments start with a hash
+//! order-not-found = Order { $id } was not found
+//! validation-length = Must be between { $min } and { $max } characters
+//! ```
+//!
+//! The [`Locale`] extractor picks the best available locale from the
+//! request's `Accept-Language` header:
+//!
+//! ```rust,ignore
+//! async fn get_order(locale: Locale, Path(id): Path<Uuid>) -> ApiResult<Order> {
+//!     let order = find(id).await?.ok_or_else(|| {
+//!         ApiError::NotFound(locale.t_with("order-not-found", &[("id", &id)]))
+//!     })?;
+//!     Ok(Json(order))
+//! }
+//!
+//! App::new().auto_configure().with_i18n("locales/")
+//! ```
+//!
+//! Validation errors from [`ValidatedJson`](crate::ValidatedJson) and the
+//! other validating extractors are localized too: a rule's custom message is
+//! looked up as a key, then `validation-{code}` (e.g. `validation-email`),
+//! with the rule's parameters as arguments. `validation-failed` replaces the
+//! overall "Request validation failed".
+//!
+//! So are [`ApiError`] responses: the message is looked up by the error's
+//! code (`NOT_FOUND` as `error-not-found`, `OUT_OF_STOCK` as
+//! `error-out-of-stock`) with the English message as `$message`, and the
+//! fields of [`ApiError::InvalidFields`] (`payload.validate()?` in a handler)
+//! like those of `ValidatedJson`:
+//!
+//! ```text
+//! error-not-found = Não encontrado
+//! error-out-of-stock = Produto esgotado ({ $message })
+//! validation-length = { $field } deve ter entre { $min } e { $max } caracteres
+//! ```
+//!
+//! Messages missing from a locale fall back to its language (`pt` for
+//! `pt-BR`), then to the default locale, then to the key itself.
+
+use std::{collections::HashMap, fmt, fs, io, path::Path, pin::Pin, sync::Arc, task};
+
+use axum::{
+    body::Body,
+    extract::{FromRequestParts, Request},
+    http::{
+        Extensions, HeaderMap, HeaderValue,
+        header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_LENGTH},
+        request::Parts,
+    },
+    response::Response,
+};
+use serde_json::Value;
+use tower::{Layer, Service};
+
+use crate::{
+    error::{ErrorInfo, ValidationFieldError},
+    extractors::localize_validation_error,
+    internal_errors::MAX_ERROR_BODY,
+};
+
+/// Messages for every locale
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    default_locale: String,
+    messages: Arc<HashMap<String, HashMap<String, String>>>,
+}
+
+impl Catalog {
+    /// An empty catalog falling back to `default_locale`
+    pub fn new(default_locale: impl Into<String>) -> Self {
+        Self {
+            default_locale: default_locale.into(),
+            messages: Arc::default(),
+        }
+    }
+
+    /// Load every `{locale}.ftl` file in `dir`
+    ///
+    /// The default locale is `en` if there is one, otherwise the first
+    /// locale in alphabetical order.
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "ftl")
+                && let Some(locale) = path.file_stem().and_then(|stem| stem.to_str())
+            {
+                files.push((locale.to_string(), path));
+            }
+        }
+        files.sort();
+
+        let default_locale = files
+            .iter()
+            .map(|(locale, _)| locale.as_str())
+            .find(|locale| *locale == "en")
+            .or_else(|| files.first().map(|(locale, _)| locale.as_str()))
+            .unwrap_or("en")
+            .to_string();
+        let mut catalog = Self::new(default_locale);
+        for (locale, path) in &files {
+            catalog = catalog.add(locale, &fs::read_to_string(path)?)?;
+        }
+        Ok(catalog)
+    }
+
+    /// Add the messages in `source` to `locale`, e.g. from `include_str!`
+    pub fn add(mut self, locale: &str, source: &str) -> io::Result<Self> {
+        let parsed = parse(source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{locale}: {e}")))?;
+        Arc::make_mut(&mut self.messages)
+            .entry(locale.to_string())
+            .or_default()
+            .extend(parsed);
+        Ok(self)
+    }
+
+    /// Fall back to `locale` for messages other locales lack
+    pub fn default_locale(mut self, locale: impl Into<String>) -> Self {
+        self.default_locale = locale.into();
+        self
+    }
+
+    /// Locales with messages
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.messages.keys().map(String::as_str)
+    }
+
+    /// The message `key` in `locale`, falling back to its language and then
+    /// the default locale
+    pub fn message(&self, locale: &str, key: &str) -> Option<&str> {
+        let language = locale.split('-').next().unwrap_or(locale);
+        [locale, language, self.default_locale.as_str()]
+            .into_iter()
+            .find_map(|locale| self.messages.get(locale)?.get(key))
+            .map(String::as_str)
+    }
+
+    /// The message `key` in `locale` with `args` filled in
+    pub fn format(
+        &self,
+        locale: &str,
+        key: &str,
+        args: &[(&str, &dyn fmt::Display)],
+    ) -> Option<String> {
+        self.message(locale, key)
+            .map(|message| interpolate(message, args))
+    }
+
+    /// The available locale that best matches an `Accept-Language` value
+    pub fn negotiate(&self, accept_language: &str) -> &str {
+        for tag in preferred_languages(accept_language) {
+            if tag == "*" {
+                break;
+            }
+            let language = tag.split('-').next().unwrap_or(&tag);
+            let matched = self
+                .locales()
+                .find(|locale| locale.eq_ignore_ascii_case(&tag))
+                .or_else(|| {
+                    self.locales()
+                        .find(|locale| locale.eq_ignore_ascii_case(language))
+                })
+                .or_else(|| {
+                    self.locales().find(|locale| {
+                        locale
+                            .split('-')
+                            .next()
+                            .is_some_and(|l| l.eq_ignore_ascii_case(language))
+                    })
+                });
+            if let Some(locale) = matched {
+                return locale;
+            }
+        }
+        &self.default_locale
+    }
+}
+
+fn parse(source: &str) -> Result<HashMap<String, String>, String> {
+    let mut messages = HashMap::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected `key = message`", number + 1))?;
+        messages.insert(key.trim().to_string(), value.trim().to_string());
+    }
+    Ok(messages)
+}
+
+/// Replace `{ $name }` placeables with their argument; unknown ones are kept
+fn interpolate(message: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(len) = rest.find('}') else {
+            break;
+        };
+        let placeable = &rest[..=len];
+        let name = placeable[1..placeable.len() - 1].trim();
+        match name
+            .strip_prefix('$')
+            .and_then(|name| args.iter().find(|(arg, _)| *arg == name))
+        {
+            Some((_, value)) => out.push_str(&value.to_string()),
+            None => out.push_str(placeable),
+        }
+        rest = &rest[len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Language tags from an `Accept-Language` value, most preferred first
+fn preferred_languages(accept_language: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = accept_language
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let q = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && q > 0.0).then(|| (tag.to_string(), q))
+        })
+        .collect();
+    // stable, so equally weighted tags keep their order
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// The locale a request asked for, among those of the app's [`Catalog`]
+///
+/// Without a catalog, it is the first language in `Accept-Language` (or
+/// `en`), and [`t`](Self::t) returns keys unchanged.
+#[derive(Debug, Clone)]
+pub struct Locale {
+    tag: String,
+    catalog: Option<Catalog>,
+}
+
+impl Locale {
+    /// Negotiate from a request's headers and the catalog in its extensions
+    pub fn negotiate(headers: &HeaderMap, extensions: &Extensions) -> Self {
+        let accept_language = headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let catalog = extensions.get::<Catalog>().cloned();
+        let tag = match &catalog {
+            Some(catalog) => catalog.negotiate(accept_language).to_string(),
+            None => preferred_languages(accept_language)
+                .into_iter()
+                .find(|tag| tag != "*")
+                .unwrap_or_else(|| "en".to_string()),
+        };
+        Self { tag, catalog }
+    }
+
+    /// Language tag, e.g. `pt-BR`
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// The message `key`, or the key itself if no locale has it
+    pub fn t(&self, key: &str) -> String {
+        self.t_with(key, &[])
+    }
+
+    /// The message `key` with `args` filled in
+    pub fn t_with(&self, key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+        self.lookup(key, args).unwrap_or_else(|| key.to_string())
+    }
+
+    /// The message `key` with `args` filled in, if there is one
+    pub fn lookup(&self, key: &str, args: &[(&str, &dyn fmt::Display)]) -> Option<String> {
+        self.catalog.as_ref()?.format(&self.tag, key, args)
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.tag)
+    }
+}
+
+impl<S> FromRequestParts<S> for Locale
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::negotiate(&parts.headers, &parts.extensions))
+    }
+}
+
+/// Layer translating [`ApiError`](crate::ApiError) responses into the
+/// request's [`Locale`]; [`App::with_i18n`](crate::App::with_i18n) adds it
+/// inside the layer providing the [`Catalog`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalizeErrorsLayer;
+
+impl<S> Layer<S> for LocalizeErrorsLayer {
+    type Service = LocalizeErrorsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LocalizeErrorsService { inner }
+    }
+}
+
+/// Service produced by [`LocalizeErrorsLayer`]
+#[derive(Clone)]
+pub struct LocalizeErrorsService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for LocalizeErrorsService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The ready service goes into the future; a fresh clone stays here
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let locale = Locale::negotiate(request.headers(), request.extensions());
+
+        Box::pin(async move {
+            let response = inner.call(request).await?;
+            match response.extensions().get::<ErrorInfo>().cloned() {
+                Some(info) if locale.catalog.is_some() => {
+                    Ok(localize_error(response, &info, &locale).await)
+                }
+                _ => Ok(response),
+            }
+        })
+    }
+}
+
+/// The message of `info` in `locale`, if the catalog has it
+fn localized_message(info: &ErrorInfo, locale: &Locale) -> Option<String> {
+    let key = if info.fields.is_empty() {
+        format!("error-{}", info.code.to_ascii_lowercase().replace('_', "-"))
+    } else {
+        "validation-failed".to_string()
+    };
+    locale.lookup(&key, &[("message", &info.public_message)])
+}
+
+fn localized_field(error: &ValidationFieldError, locale: &Locale) -> ValidationFieldError {
+    let message = match &error.rule {
+        Some(rule) => localize_validation_error(&error.field, rule, locale),
+        None => locale
+            .lookup(&error.message, &[("field", &error.field)])
+            .unwrap_or_else(|| error.message.clone()),
+    };
+    ValidationFieldError::new(error.field.clone(), message)
+}
+
+/// `response` with its message and field errors in `locale`
+async fn localize_error(response: Response, info: &ErrorInfo, locale: &Locale) -> Response {
+    let message = localized_message(info, locale);
+    let fields: Vec<ValidationFieldError> = info
+        .fields
+        .iter()
+        .map(|error| localized_field(error, locale))
+        .collect();
+    if message.is_none() && fields == info.fields {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Some(mut body) = axum::body::to_bytes(body, MAX_ERROR_BODY)
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+    else {
+        tracing::warn!("Failed to read an error response to localize");
+        return Response::from_parts(parts, Body::empty());
+    };
+    if let Some(message) = message {
+        body["message"] = message.into();
+    }
+    if !fields.is_empty() {
+        body["errors"] = serde_json::to_value(&fields).unwrap_or_default();
+    }
+
+    parts.headers.remove(CONTENT_LENGTH);
+    if let Ok(tag) = HeaderValue::from_str(locale.tag()) {
+        parts.headers.insert(CONTENT_LANGUAGE, tag);
+    }
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Catalog {
+        Catalog::new("en")
+            .add(
+                "en",
+                "# greetings\nhello = Hello, { $name }!\nbye = Goodbye\n",
+            )
+            .unwrap()
+            .add("pt", "hello = Olá, { $name }!\n")
+            .unwrap()
+            .add("pt-BR", "bye = Tchau\n")
+            .unwrap()
+    }
+
+    #[test]
+    fn accept_language_picks_the_best_available_locale() {
+        let catalog = catalog();
+        assert_eq!(catalog.negotiate("pt-BR,pt;q=0.9,en;q=0.8"), "pt-BR");
+        assert_eq!(catalog.negotiate("de, pt-PT;q=0.5"), "pt");
+        assert_eq!(catalog.negotiate("en-US"), "en");
+        assert_eq!(catalog.negotiate("fr;q=0.9, pt;q=0"), "en");
+        assert_eq!(catalog.negotiate(""), "en");
+    }
+
+    #[test]
+    fn messages_fall_back_to_language_then_default() {
+        let catalog = catalog();
+        let name: &dyn fmt::Display = &"Ana";
+        assert_eq!(catalog.format("pt-BR", "bye", &[]).unwrap(), "Tchau");
+        assert_eq!(
+            catalog.format("pt-BR", "hello", &[("name", name)]).unwrap(),
+            "Olá, Ana!"
+        );
+        assert_eq!(catalog.format("de", "bye", &[]).unwrap(), "Goodbye");
+        assert!(catalog.message("en", "missing").is_none());
+    }
+
+    #[test]
+    fn placeables_without_arguments_are_kept() {
+        assert_eq!(
+            interpolate("{ $a } and {$b} {", &[("a", &1)]),
+            "1 and {$b} {"
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let err = Catalog::new("en")
+            .add("en", "ok = fine\nnot a message")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn locale_without_catalog_returns_keys() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_LANGUAGE, "de-CH, en;q=0.5".parse().unwrap());
+        let locale = Locale::negotiate(&headers, &Extensions::new());
+        assert_eq!(locale.tag(), "de-CH");
+        assert_eq!(locale.t("hello"), "hello");
+
+        let mut extensions = Extensions::new();
+        extensions.insert(catalog());
+        let locale = Locale::negotiate(&headers, &extensions);
+        assert_eq!(locale.tag(), "en");
+        assert_eq!(locale.t_with("hello", &[("name", &"Jo")]), "Hello, Jo!");
+    }
+
+    async fn error_in(accept_language: &str, uri: &str) -> (Option<HeaderValue>, Value) {
+        use axum::{Router, routing::get};
+        use tower::ServiceExt;
+
+        let catalog = Catalog::new("en")
+            .add(
+                "pt",
+                "error-not-found = Não encontrado ({ $message })\n\
+                 validation-failed = Validação falhou\n\
+                 validation-length = { $field } deve ter no mínimo { $min } caracteres\n\
+                 name-taken = { $field } já existe\n",
+            )
+            .unwrap();
+        let app = Router::new()
+            .route(
+                "/missing",
+                get(|| async { Err::<(), _>(crate::ApiError::not_found("order 7")) }),
+            )
+            .route(
+                "/invalid",
+                get(|| async {
+                    let mut rule = validator::ValidationError::new("length");
+                    rule.add_param("min".into(), &3);
+                    Err::<(), _>(crate::ApiError::InvalidFields(vec![
+                        ValidationFieldError {
+                            rule: Some(rule),
+                            ..ValidationFieldError::new("code", "too short")
+                        },
+                        ValidationFieldError::new("name", "name-taken"),
+                    ]))
+                }),
+            )
+            .layer(LocalizeErrorsLayer)
+            .layer(axum::Extension(catalog));
+
+        let request = Request::builder()
+            .uri(uri)
+            .header(ACCEPT_LANGUAGE, accept_language)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let language = response.headers().get(CONTENT_LANGUAGE).cloned();
+        let body = axum::body::to_bytes(response.into_body(), MAX_ERROR_BODY)
+            .await
+            .unwrap();
+        (language, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_translated() {
+        let (language, body) = error_in("pt-BR", "/missing").await;
+        assert_eq!(language.unwrap(), "pt");
+        assert_eq!(body["code"], "NOT_FOUND");
+        assert_eq!(body["message"], "Não encontrado (Not found: order 7)");
+
+        let (_, body) = error_in("pt", "/invalid").await;
+        assert_eq!(body["message"], "Validação falhou");
+        assert_eq!(
+            body["errors"][0]["message"],
+            "code deve ter no mínimo 3 caracteres"
+        );
+        assert_eq!(body["errors"][1]["message"], "name já existe");
+    }
+
+    #[tokio::test]
+    async fn untranslated_errors_are_unchanged() {
+        let (language, body) = error_in("de", "/missing").await;
+        assert!(language.is_none());
+        assert_eq!(body["message"], "Not found: order 7");
+    }
+}
@@ -0,0 +1,389 @@
+//! Spreadsheet imports with row-level validation
+//!
+//! An [`Importer`] reads records from a [`RowSource`], deserializes each row
+//! into a DTO using the header row as field names, runs its `validator`
+//! checks, and hands valid rows to an [`ImportSink`] in batches. Rows that
+//! fail are collected into an [`ImportReport`] instead of aborting the import.
+//!
+//! CSV is supported out of the box via [`CsvReader`]. Other formats (e.g.
+//! XLSX via `calamine`) can be plugged in by implementing [`RowSource`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use dy_rs::import::{ImportReport, Importer, upload_source};
+//!
+//! #[derive(Deserialize, Validate)]
+//! struct ContactRow {
+//!     #[validate(email)]
+//!     email: String,
+//!     name: String,
+//! }
+//!
+//! async fn import_contacts(
+//!     State(pool): State<PgPool>,
+//!     headers: HeaderMap,
+//!     body: Bytes,
+//! ) -> ApiResult<ImportReport> {
+//!     let source = upload_source(headers.get(CONTENT_TYPE), body)?;
+//!     let report = Importer::new()
+//!         .batch_size(500)
+//!         .run(source, &|rows: Vec<ContactRow>| async {
+//!             let mut tx = pool.begin().await?;
+//!             for row in rows {
+//!                 sqlx::query("INSERT INTO contacts (email, name) VALUES ($1, $2)")
+//!                     .bind(row.email)
+//!                     .bind(row.name)
+//!                     .execute(&mut *tx)
+//!                     .await?;
+//!             }
+//!             tx.commit().await?;
+//!             Ok(())
+//!         })
+//!         .await?;
+//!     Ok(Json(report))
+//! }
+//! ```
+
+pub mod csv;
+pub mod row;
+
+pub use self::csv::CsvReader;
+pub use row::{RowDeError, from_record};
+
+use std::future::Future;
+
+use async_trait::async_trait;
+use axum::{body::Bytes, http::HeaderValue};
+use serde::{Serialize, de::DeserializeOwned};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::error::ApiError;
+
+/// A source of spreadsheet records; the first record is the header row
+#[async_trait]
+pub trait RowSource: Send {
+    /// Next record, or `None` at the end of the input
+    async fn next_record(&mut self) -> Result<Option<Vec<String>>, ApiError>;
+}
+
+#[async_trait]
+impl RowSource for Box<dyn RowSource> {
+    async fn next_record(&mut self) -> Result<Option<Vec<String>>, ApiError> {
+        (**self).next_record().await
+    }
+}
+
+/// Destination for validated rows
+///
+/// Each call receives one batch and should apply it atomically, typically in
+/// a single database transaction. Closures `Fn(Vec<T>) -> impl Future` are
+/// sinks too.
+#[async_trait]
+pub trait ImportSink<T>: Send + Sync {
+    async fn apply_batch(&self, rows: Vec<T>) -> Result<(), ApiError>;
+}
+
+#[async_trait]
+impl<T, F, Fut> ImportSink<T> for F
+where
+    T: Send + 'static,
+    F: Fn(Vec<T>) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<(), ApiError>> + Send,
+{
+    async fn apply_batch(&self, rows: Vec<T>) -> Result<(), ApiError> {
+        self(rows).await
+    }
+}
+
+/// A row that could not be imported
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct RowError {
+    /// Spreadsheet row number; the header is row 1
+    pub row: usize,
+    /// Column the error refers to, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub message: String,
+}
+
+/// Outcome of an import
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ImportReport {
+    /// Data rows read, excluding the header and blank rows
+    pub total_rows: usize,
+    pub imported: usize,
+    pub failed: usize,
+    /// Whether the import stopped early after reaching `max_errors`
+    pub aborted: bool,
+    pub errors: Vec<RowError>,
+}
+
+/// Runs imports from a [`RowSource`] into an [`ImportSink`]
+#[derive(Debug, Clone)]
+pub struct Importer {
+    batch_size: usize,
+    max_errors: Option<usize>,
+}
+
+impl Importer {
+    /// Create an importer with batches of 500 rows and no error limit
+    pub fn new() -> Self {
+        Self {
+            batch_size: 500,
+            max_errors: None,
+        }
+    }
+
+    /// Set the number of rows per batch
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Stop reading once this many rows have failed
+    pub fn max_errors(mut self, max: usize) -> Self {
+        self.max_errors = Some(max);
+        self
+    }
+
+    /// Import every row of `source` into `sink`
+    ///
+    /// Returns an error only if the source itself is unreadable; row and
+    /// batch failures are reported in the [`ImportReport`].
+    pub async fn run<T, R, S>(&self, mut source: R, sink: &S) -> Result<ImportReport, ApiError>
+    where
+        T: DeserializeOwned + Validate + Send + 'static,
+        R: RowSource,
+        S: ImportSink<T> + ?Sized,
+    {
+        let headers: Vec<String> = source
+            .next_record()
+            .await?
+            .ok_or_else(|| ApiError::BadRequest("Import file is empty".to_string()))?
+            .into_iter()
+            .map(|h| h.trim().to_string())
+            .collect();
+
+        let mut report = ImportReport::default();
+        let mut batch: Vec<(usize, T)> = Vec::with_capacity(self.batch_size);
+        let mut row = 1;
+
+        while let Some(record) = source.next_record().await? {
+            row += 1;
+            if record.iter().all(|cell| cell.trim().is_empty()) {
+                continue;
+            }
+            report.total_rows += 1;
+
+            match parse_row::<T>(&headers, &record, row) {
+                Ok(item) => batch.push((row, item)),
+                Err(errors) => {
+                    report.failed += 1;
+                    report.errors.extend(errors);
+                }
+            }
+
+            if batch.len() >= self.batch_size {
+                flush(sink, &mut batch, &mut report).await;
+            }
+            if self.max_errors.is_some_and(|max| report.failed >= max) {
+                report.aborted = true;
+                break;
+            }
+        }
+
+        flush(sink, &mut batch, &mut report).await;
+        Ok(report)
+    }
+}
+
+impl Default for Importer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_row<T: DeserializeOwned + Validate>(
+    headers: &[String],
+    record: &[String],
+    row: usize,
+) -> Result<T, Vec<RowError>> {
+    let item: T = from_record(headers, record).map_err(|e| {
+        vec![RowError {
+            row,
+            field: e.field,
+            message: e.message,
+        }]
+    })?;
+
+    item.validate().map_err(|errors| {
+        errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| RowError {
+                    row,
+                    field: Some(field.to_string()),
+                    message: error
+                        .message
+                        .as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| error.code.to_string()),
+                })
+            })
+            .collect::<Vec<_>>()
+    })?;
+
+    Ok(item)
+}
+
+async fn flush<T, S>(sink: &S, batch: &mut Vec<(usize, T)>, report: &mut ImportReport)
+where
+    S: ImportSink<T> + ?Sized,
+{
+    if batch.is_empty() {
+        return;
+    }
+
+    let (rows, items): (Vec<usize>, Vec<T>) = std::mem::take(batch).into_iter().unzip();
+    match sink.apply_batch(items).await {
+        Ok(()) => report.imported += rows.len(),
+        Err(err) => {
+            tracing::warn!(error = %err, rows = rows.len(), "import batch rejected");
+            report.failed += rows.len();
+            report.errors.extend(rows.into_iter().map(|row| RowError {
+                row,
+                field: None,
+                message: format!("Batch rejected: {err}"),
+            }));
+        }
+    }
+}
+
+/// Pick a [`RowSource`] for an uploaded file based on its content type
+///
+/// CSV (`text/csv`, `application/csv`, or no content type) is parsed with
+/// [`CsvReader`]; `text/tab-separated-values` uses tabs. Other formats are
+/// rejected.
+pub fn upload_source(
+    content_type: Option<&HeaderValue>,
+    body: Bytes,
+) -> Result<Box<dyn RowSource>, ApiError> {
+    let essence = content_type
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase());
+
+    let reader = CsvReader::new(std::io::Cursor::new(body));
+    match essence.as_deref() {
+        None | Some("text/csv" | "application/csv" | "text/plain") => Ok(Box::new(reader)),
+        Some("text/tab-separated-values") => Ok(Box::new(reader.delimiter('\t'))),
+        Some(other) => Err(ApiError::BadRequest(format!(
+            "Unsupported import format `{other}`; upload a CSV file"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct Contact {
+        #[validate(email(message = "Invalid email"))]
+        email: String,
+        #[allow(dead_code)]
+        age: u32,
+    }
+
+    fn csv(input: &'static str) -> CsvReader<&'static [u8]> {
+        CsvReader::new(input.as_bytes())
+    }
+
+    #[tokio::test]
+    async fn imports_valid_rows_and_reports_invalid_ones() {
+        let batches = Mutex::new(Vec::new());
+        let sink = |rows: Vec<Contact>| {
+            batches
+                .lock()
+                .unwrap()
+                .push(rows.iter().map(|c| c.email.clone()).collect::<Vec<_>>());
+            async { Ok(()) }
+        };
+
+        let report = Importer::new()
+            .batch_size(2)
+            .run(
+                csv("email,age\na@x.io,30\nnot-an-email,20\n\nb@x.io,abc\nc@x.io,40\nd@x.io,50\n"),
+                &sink,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(report.total_rows, 5);
+        assert_eq!(report.imported, 3);
+        assert_eq!(report.failed, 2);
+        assert_eq!(
+            report.errors,
+            vec![
+                RowError {
+                    row: 3,
+                    field: Some("email".to_string()),
+                    message: "Invalid email".to_string(),
+                },
+                RowError {
+                    row: 5,
+                    field: Some("age".to_string()),
+                    message: "expected a non-negative integer, found `abc`".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            *batches.lock().unwrap(),
+            vec![vec!["a@x.io", "c@x.io"], vec!["d@x.io"]]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_batches_fail_their_rows() {
+        let sink =
+            |_rows: Vec<Contact>| async { Err(ApiError::InternalServerError("boom".into())) };
+
+        let report = Importer::new()
+            .run(csv("email,age\na@x.io,30\nb@x.io,31\n"), &sink)
+            .await
+            .unwrap();
+
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.failed, 2);
+        assert_eq!(report.errors[1].row, 3);
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_errors() {
+        let sink = |_rows: Vec<Contact>| async { Ok(()) };
+
+        let report = Importer::new()
+            .max_errors(1)
+            .run(csv("email,age\nbad,1\nbad,2\na@x.io,3\n"), &sink)
+            .await
+            .unwrap();
+
+        assert!(report.aborted);
+        assert_eq!(report.total_rows, 1);
+        assert_eq!(report.imported, 0);
+    }
+
+    #[test]
+    fn rejects_unknown_upload_formats() {
+        let xlsx = HeaderValue::from_static(
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        );
+        assert!(upload_source(Some(&xlsx), Bytes::new()).is_err());
+        assert!(upload_source(None, Bytes::new()).is_ok());
+    }
+}
@@ -0,0 +1,267 @@
+//! Deserializing spreadsheet rows into typed DTOs
+
+use std::fmt;
+
+use serde::de::{
+    self, Deserialize, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor,
+};
+
+/// Error produced while deserializing a row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowDeError {
+    /// Column the error refers to, when known
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for RowDeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{field}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for RowDeError {}
+
+impl de::Error for RowDeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        let message = msg.to_string();
+        // Point serde's "missing field `x`" at the column it refers to.
+        let field = message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split_once('`'))
+            .map(|(field, _)| field.to_string());
+        RowDeError { field, message }
+    }
+}
+
+/// Deserialize `record` into `T`, using `headers` as field names
+///
+/// Cells are parsed according to the target field type: empty cells become
+/// `None` for `Option` fields, and numbers and booleans are parsed from text.
+pub fn from_record<'de, T: Deserialize<'de>>(
+    headers: &'de [String],
+    record: &'de [String],
+) -> Result<T, RowDeError> {
+    T::deserialize(RowDeserializer {
+        headers,
+        record,
+        index: 0,
+    })
+}
+
+struct RowDeserializer<'de> {
+    headers: &'de [String],
+    record: &'de [String],
+    index: usize,
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer<'de> {
+    type Error = RowDeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for RowDeserializer<'de> {
+    type Error = RowDeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        // Skip headers without a cell so short rows surface as missing fields.
+        while self.index < self.headers.len() && self.index >= self.record.len() {
+            self.index += 1;
+        }
+        match self.headers.get(self.index) {
+            Some(header) => seed
+                .deserialize(header.as_str().into_deserializer())
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let cell = Cell {
+            header: &self.headers[self.index],
+            value: &self.record[self.index],
+        };
+        self.index += 1;
+        seed.deserialize(cell)
+    }
+}
+
+/// A single cell, deserialized according to the requested type
+struct Cell<'de> {
+    header: &'de str,
+    value: &'de str,
+}
+
+impl Cell<'_> {
+    fn error(&self, expected: &str) -> RowDeError {
+        RowDeError {
+            field: Some(self.header.to_string()),
+            message: format!("expected {expected}, found `{}`", self.value),
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(&self, expected: &str) -> Result<T, RowDeError> {
+        self.value.trim().parse().map_err(|_| self.error(expected))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $ty:ty, $expected:literal;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit(self.parse::<$ty>($expected)?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Cell<'de> {
+    type Error = RowDeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.value)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" => visitor.visit_bool(true),
+            "false" | "no" | "n" | "0" => visitor.visit_bool(false),
+            _ => Err(self.error("a boolean")),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8: i8, "an integer";
+        deserialize_i16 => visit_i16: i16, "an integer";
+        deserialize_i32 => visit_i32: i32, "an integer";
+        deserialize_i64 => visit_i64: i64, "an integer";
+        deserialize_i128 => visit_i128: i128, "an integer";
+        deserialize_u8 => visit_u8: u8, "a non-negative integer";
+        deserialize_u16 => visit_u16: u16, "a non-negative integer";
+        deserialize_u32 => visit_u32: u32, "a non-negative integer";
+        deserialize_u64 => visit_u64: u64, "a non-negative integer";
+        deserialize_u128 => visit_u128: u128, "a non-negative integer";
+        deserialize_f32 => visit_f32: f32, "a number";
+        deserialize_f64 => visit_f64: f64, "a number";
+        deserialize_char => visit_char: char, "a single character";
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.value.trim().is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.value.trim().is_empty() {
+            visitor.visit_unit()
+        } else {
+            Err(self.error("an empty cell"))
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let header = self.header;
+        visitor
+            .visit_enum(self.value.trim().into_deserializer())
+            .map_err(|e: RowDeError| RowDeError {
+                field: Some(header.to_string()),
+                message: e.message,
+            })
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Role {
+        Admin,
+        Member,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Person {
+        name: String,
+        age: u32,
+        active: bool,
+        role: Role,
+        nickname: Option<String>,
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_cells_by_field_type() {
+        let headers = strings(&["name", "age", "active", "role", "nickname"]);
+        let record = strings(&["Ada", " 36 ", "yes", "admin", ""]);
+
+        let person: Person = from_record(&headers, &record).unwrap();
+        assert_eq!(
+            person,
+            Person {
+                name: "Ada".to_string(),
+                age: 36,
+                active: true,
+                role: Role::Admin,
+                nickname: None,
+            }
+        );
+    }
+
+    #[test]
+    fn reports_the_failing_column() {
+        let headers = strings(&["name", "age", "active", "role", "nickname"]);
+
+        let record = strings(&["Ada", "old", "yes", "member", ""]);
+        let err = from_record::<Person>(&headers, &record).unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("age"));
+
+        let record = strings(&["Ada", "36", "yes"]);
+        let err = from_record::<Person>(&headers, &record).unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("role"));
+    }
+}
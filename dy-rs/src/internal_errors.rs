@@ -0,0 +1,459 @@
+//! Internal errors, as clients see them
+//!
+//! [`InternalErrorLayer`] gives every request an `x-request-id` and logs
+//! under it. Handler panics are answered with a `500` instead of dropping
+//! the connection, logged with their backtrace and counted in
+//! [`ErrorMetrics`]. Unless exposing internal errors, the message of a `5xx`
+//! response is replaced with the status's reason phrase and the request ID,
+//! so driver errors and panic messages stay in the logs:
+//!
+//! ```json
+//! {
+//!   "code": "INTERNAL_SERVER_ERROR",
+//!   "message": "Internal Server Error",
+//!   "details": { "request_id": "5f0c5e1e-..." }
+//! }
+//! ```
+//!
+//! Auto-configured apps expose them everywhere but in the `production`
+//! environment, or as `server.expose_internal_errors` says. Messages chosen
+//! for clients, those of [`ApiErrorKind`](crate::error::ApiErrorKind)
+//! errors and database errors, are kept.
+//!
+//! Given an [`ErrorReporter`], `5xx` responses and panics are also reported
+//! to an error tracker along with the request they answered.
+
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::RefCell,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        Arc, Once,
+        atomic::{AtomicU64, Ordering},
+    },
+    task,
+};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        HeaderValue,
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+    },
+    response::{IntoResponse, Response},
+};
+use futures_util::FutureExt;
+use serde::Serialize;
+use serde_json::{Value, json};
+use tower::{Layer, Service};
+use tracing::Instrument;
+
+use crate::{
+    audit::REQUEST_ID_HEADER,
+    error::{ApiError, ErrorInfo, ErrorResponse},
+    reporting::{ErrorReport, ErrorReporter},
+};
+
+/// Largest error body read to keep its code
+pub(crate) const MAX_ERROR_BODY: usize = 64 * 1024;
+
+thread_local! {
+    /// Backtrace of the last panic on this thread, taken by the panic hook
+    static PANIC_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Keep a backtrace of every panic for [`InternalErrorService`] to log,
+/// leaving the existing hook in place
+fn install_backtrace_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            PANIC_BACKTRACE.with(|backtrace| {
+                *backtrace.borrow_mut() = Some(Backtrace::force_capture());
+            });
+            previous(info);
+        }));
+    });
+}
+
+/// Counts of [`InternalErrorLayer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct ErrorMetrics {
+    /// Handler panics answered with a `500`
+    pub panics: u64,
+}
+
+/// Tags requests with an ID, turns panics into `500`s and hides internal
+/// error messages; see the [module docs](self)
+///
+/// Clones share their [`metrics`](Self::metrics).
+#[derive(Clone)]
+pub struct InternalErrorLayer {
+    expose: bool,
+    panics: Arc<AtomicU64>,
+    reporter: Option<Arc<dyn ErrorReporter>>,
+}
+
+impl InternalErrorLayer {
+    /// Hiding internal error messages
+    pub fn new() -> Self {
+        install_backtrace_hook();
+        Self {
+            expose: false,
+            panics: Arc::default(),
+            reporter: None,
+        }
+    }
+
+    /// Whether `5xx` responses keep their messages
+    pub fn expose(mut self, expose: bool) -> Self {
+        self.expose = expose;
+        self
+    }
+
+    /// Report `5xx` responses and panics to `reporter`
+    pub fn reporter(mut self, reporter: impl ErrorReporter) -> Self {
+        self.reporter = Some(Arc::new(reporter));
+        self
+    }
+
+    pub fn metrics(&self) -> ErrorMetrics {
+        ErrorMetrics {
+            panics: self.panics.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for InternalErrorLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for InternalErrorLayer {
+    type Service = InternalErrorService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        InternalErrorService {
+            inner,
+            expose: self.expose,
+            panics: self.panics.clone(),
+            reporter: self.reporter.clone(),
+        }
+    }
+}
+
+/// Service produced by [`InternalErrorLayer`]
+#[derive(Clone)]
+pub struct InternalErrorService<S> {
+    inner: S,
+    expose: bool,
+    panics: Arc<AtomicU64>,
+    reporter: Option<Arc<dyn ErrorReporter>>,
+}
+
+impl<S> Service<Request> for InternalErrorService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| {
+                let id = uuid::Uuid::new_v4().to_string();
+                if let Ok(value) = HeaderValue::from_str(&id) {
+                    request.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                id
+            });
+        let span = tracing::error_span!("request", request_id = %request_id);
+        // What a report needs of the request, before the handler takes it
+        let report = self.reporter.as_ref().map(|reporter| {
+            let report = ErrorReport::new(&request, &request_id);
+            (reporter.clone(), report)
+        });
+        let handled = AssertUnwindSafe(self.inner.call(request)).catch_unwind();
+        let expose = self.expose;
+        let panics = self.panics.clone();
+
+        Box::pin(async move {
+            let mut panicked = false;
+            let mut backtrace = None;
+            let mut response = match handled.instrument(span.clone()).await {
+                Ok(response) => response?,
+                Err(panic) => span.in_scope(|| {
+                    panics.fetch_add(1, Ordering::Relaxed);
+                    panicked = true;
+                    let message = panic_message(&*panic);
+                    backtrace = PANIC_BACKTRACE
+                        .with(|backtrace| backtrace.borrow_mut().take())
+                        .map(|b| b.to_string());
+                    tracing::error!(
+                        panic = %message,
+                        backtrace = %backtrace.as_deref().unwrap_or_default(),
+                        "Handler panicked"
+                    );
+                    let message = format!("Handler panicked: {message}");
+                    ApiError::InternalServerError(message).into_response()
+                }),
+            };
+            let info = response.extensions().get::<ErrorInfo>().cloned();
+            if let Some((reporter, mut report)) = report
+                && response.status().is_server_error()
+            {
+                report.status = response.status().as_u16();
+                report.panic = panicked;
+                report.backtrace = backtrace;
+                if let Some(info) = &info {
+                    report.code = Some(info.code.clone());
+                    report.message = info.message.clone();
+                }
+                tokio::spawn(
+                    async move {
+                        if let Err(e) = reporter.report(&report).await {
+                            tracing::warn!(error = %e, "Failed to report an error");
+                        }
+                    }
+                    .instrument(span),
+                );
+            }
+            if !expose
+                && response.status().is_server_error()
+                && !info.is_some_and(|info| info.public)
+            {
+                response = hide_message(response, &request_id).await;
+            }
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+            Ok(response)
+        })
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// `response` with a generic message, keeping its status, headers, error
+/// code and database error reference
+async fn hide_message(response: Response, request_id: &str) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let original: Value = axum::body::to_bytes(body, MAX_ERROR_BODY)
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+
+    let mut details = json!({ "request_id": request_id });
+    if let Some(reference) = original["details"].get("reference") {
+        details["reference"] = reference.clone();
+    }
+    let body = ErrorResponse {
+        code: original["code"]
+            .as_str()
+            .unwrap_or("INTERNAL_SERVER_ERROR")
+            .to_string(),
+        message: parts
+            .status
+            .canonical_reason()
+            .unwrap_or("Internal Server Error")
+            .to_string(),
+        errors: Vec::new(),
+        details: Some(details),
+    };
+
+    parts.headers.remove(CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let body = serde_json::to_vec(&body).unwrap_or_default();
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::to_bytes,
+        http::{StatusCode, header::RETRY_AFTER},
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    fn router(expose: bool) -> Router {
+        router_with(InternalErrorLayer::new().expose(expose))
+    }
+
+    fn router_with(layer: InternalErrorLayer) -> Router {
+        Router::new()
+            .route(
+                "/internal",
+                get(|| async {
+                    Err::<(), _>(ApiError::InternalServerError(
+                        "connection refused to 10.0.0.5:5432".to_string(),
+                    ))
+                }),
+            )
+            .route(
+                "/database",
+                get(|| async { Err::<(), _>(ApiError::database("relation \"users\"")) }),
+            )
+            .route(
+                "/busy",
+                get(|| async {
+                    Err::<(), _>(ApiError::ServiceUnavailable {
+                        message: "replica lag".to_string(),
+                        retry_after: 5,
+                    })
+                }),
+            )
+            .route(
+                "/panic",
+                get(|| async {
+                    if true {
+                        panic!("index out of bounds");
+                    }
+                }),
+            )
+            .route(
+                "/missing",
+                get(|| async { Err::<(), _>(ApiError::NotFound("user 7".to_string())) }),
+            )
+            .layer(layer)
+    }
+
+    async fn call(router: Router, path: &str, request_id: Option<&str>) -> (Response, Value) {
+        let mut request = Request::get(path);
+        if let Some(id) = request_id {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let (parts, body) = response.into_parts();
+        let body = to_bytes(body, usize::MAX).await.unwrap();
+        let json = serde_json::from_slice(&body).unwrap();
+        (Response::from_parts(parts, Body::empty()), json)
+    }
+
+    #[tokio::test]
+    async fn hides_internal_messages_behind_the_request_id() {
+        let (response, json) = call(router(false), "/internal", Some("req-1")).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "req-1");
+        assert_eq!(json["code"], "INTERNAL_SERVER_ERROR");
+        assert_eq!(json["message"], "Internal Server Error");
+        assert_eq!(json["details"], json!({ "request_id": "req-1" }));
+
+        let (response, json) = call(router(false), "/panic", None).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert_eq!(json["details"]["request_id"], request_id);
+        assert!(!json.to_string().contains("index out of bounds"));
+
+        let (response, json) = call(router(false), "/busy", None).await;
+        assert_eq!(response.headers()[RETRY_AFTER], "5");
+        assert_eq!(json["code"], "SERVICE_UNAVAILABLE");
+        assert_eq!(json["message"], "Service Unavailable");
+
+        let (_, json) = call(router(false), "/database", None).await;
+        assert_eq!(json["message"], "Database error");
+        assert!(json["details"]["reference"].is_string());
+
+        let (_, json) = call(router(false), "/missing", None).await;
+        assert_eq!(json["message"], "Not found: user 7");
+    }
+
+    #[tokio::test]
+    async fn counts_panics_and_keeps_serving() {
+        let layer = InternalErrorLayer::new();
+        let router = router_with(layer.clone());
+        for _ in 0..2 {
+            let (response, json) = call(router.clone(), "/panic", None).await;
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(json["code"], "INTERNAL_SERVER_ERROR");
+        }
+        call(router, "/missing", None).await;
+        assert_eq!(layer.metrics(), ErrorMetrics { panics: 2 });
+    }
+
+    struct Channel(tokio::sync::mpsc::UnboundedSender<ErrorReport>);
+
+    #[async_trait::async_trait]
+    impl ErrorReporter for Channel {
+        async fn report(&self, report: &ErrorReport) -> Result<(), ApiError> {
+            self.0.send(report.clone()).ok();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_server_errors_with_the_request() {
+        let (sender, mut reports) = tokio::sync::mpsc::unbounded_channel();
+        let router = router_with(InternalErrorLayer::new().reporter(Channel(sender)));
+
+        call(router.clone(), "/missing", None).await;
+        call(router.clone(), "/internal", Some("req-1")).await;
+        let report = reports.recv().await.unwrap();
+        assert_eq!(report.status, 500);
+        assert_eq!(report.code.as_deref(), Some("INTERNAL_SERVER_ERROR"));
+        assert_eq!(
+            report.message,
+            "Internal server error: connection refused to 10.0.0.5:5432"
+        );
+        assert_eq!(report.request_id, "req-1");
+        assert_eq!(
+            (report.method.as_str(), report.uri.as_str()),
+            ("GET", "/internal")
+        );
+        assert!(!report.panic);
+
+        call(router, "/panic", None).await;
+        let report = reports.recv().await.unwrap();
+        assert!(report.panic);
+        assert!(report.message.contains("index out of bounds"));
+        assert!(report.backtrace.is_some());
+        assert!(reports.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn exposes_them_when_asked() {
+        let (_, json) = call(router(true), "/internal", None).await;
+        assert_eq!(
+            json["message"],
+            "Internal server error: connection refused to 10.0.0.5:5432"
+        );
+
+        let (response, json) = call(router(true), "/panic", None).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(response.headers().contains_key(REQUEST_ID_HEADER));
+        assert_eq!(
+            json["message"],
+            "Internal server error: Handler panicked: index out of bounds"
+        );
+    }
+}
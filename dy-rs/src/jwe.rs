@@ -0,0 +1,336 @@
+//! JWE-encrypted request and response bodies
+//!
+//! For partner APIs that require encrypted payloads. Routes behind a
+//! [`JweLayer`] have their JSON responses encrypted to the calling
+//! consumer's key and served as `application/jose` (compact serialization);
+//! [`JweJson`] decrypts and deserializes an inbound JWE body.
+//!
+//! The cryptography is left to a [`JweCipher`], so the algorithms and key
+//! handling follow whichever JOSE library the app already uses (for example
+//! `josekit`):
+//!
+//! ```rust,ignore
+//! let jwe = JweLayer::new(MyCipher::new(private_key))
+//!     .recipient("partner-a", RecipientKey::new("partner-a-2024", partner_a_jwk))
+//!     .consumer_header("x-partner-id");
+//!
+//! #[dy_api(method = post, path = "/partners/orders", response_content_type = "application/jose")]
+//! async fn create_order(JweJson(order): JweJson<NewOrder>) -> ApiResult<Order> { ... }
+//!
+//! let partners = Router::new()
+//!     .route("/partners/orders", post(create_order))
+//!     .layer(jwe);
+//! ```
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc, task};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Request},
+    http::{HeaderName, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+use tower::{Layer, Service};
+
+use crate::error::ApiError;
+
+/// Media type of a compact-serialized JWE
+pub const JOSE_CONTENT_TYPE: &str = "application/jose";
+
+/// A consumer's public key, as a JWK
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecipientKey {
+    pub key_id: String,
+    pub jwk: serde_json::Value,
+}
+
+impl RecipientKey {
+    pub fn new(key_id: impl Into<String>, jwk: serde_json::Value) -> Self {
+        Self {
+            key_id: key_id.into(),
+            jwk,
+        }
+    }
+}
+
+/// Encrypts and decrypts compact JWEs
+pub trait JweCipher: Send + Sync + 'static {
+    /// Encrypt `plaintext` to `recipient`, returning the compact serialization
+    fn encrypt(&self, recipient: &RecipientKey, plaintext: &[u8]) -> Result<String, ApiError>;
+
+    /// Decrypt a compact JWE addressed to this service
+    fn decrypt(&self, jwe: &str) -> Result<Vec<u8>, ApiError>;
+}
+
+struct JweInner {
+    cipher: Box<dyn JweCipher>,
+    recipients: HashMap<String, RecipientKey>,
+    consumer_header: HeaderName,
+}
+
+/// Layer encrypting JSON responses to the calling consumer.
+///
+/// The consumer is named by a request header (`x-consumer-id` by default).
+/// Requests from consumers without a registered key are rejected with
+/// `403 Forbidden` before reaching the handler. Non-JSON responses, such as
+/// empty `204`s, are passed through unencrypted.
+#[derive(Clone)]
+pub struct JweLayer {
+    inner: Arc<JweInner>,
+}
+
+impl JweLayer {
+    pub fn new(cipher: impl JweCipher) -> Self {
+        Self {
+            inner: Arc::new(JweInner {
+                cipher: Box::new(cipher),
+                recipients: HashMap::new(),
+                consumer_header: HeaderName::from_static("x-consumer-id"),
+            }),
+        }
+    }
+
+    /// Register the key responses to `consumer` are encrypted with
+    pub fn recipient(mut self, consumer: impl Into<String>, key: RecipientKey) -> Self {
+        self.inner_mut().recipients.insert(consumer.into(), key);
+        self
+    }
+
+    /// Header identifying the consumer
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid header name.
+    pub fn consumer_header(mut self, name: &str) -> Self {
+        self.inner_mut().consumer_header =
+            HeaderName::try_from(name).expect("invalid consumer header name");
+        self
+    }
+
+    fn inner_mut(&mut self) -> &mut JweInner {
+        Arc::get_mut(&mut self.inner).expect("JweLayer is configured before it is cloned")
+    }
+}
+
+impl<S> Layer<S> for JweLayer {
+    type Service = JweService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        JweService {
+            inner,
+            jwe: self.inner.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JweService<S> {
+    inner: S,
+    jwe: Arc<JweInner>,
+}
+
+/// Handle to the layer's cipher, read by [`JweJson`]
+#[derive(Clone)]
+struct JweDecrypter(Arc<JweInner>);
+
+impl<S> Service<Request> for JweService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let jwe = self.jwe.clone();
+        let recipient = req
+            .headers()
+            .get(&jwe.consumer_header)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|consumer| jwe.recipients.get(consumer))
+            .cloned();
+        let Some(recipient) = recipient else {
+            return Box::pin(async { Ok(ApiError::Forbidden.into_response()) });
+        };
+
+        req.extensions_mut().insert(JweDecrypter(jwe.clone()));
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = future.await?;
+            Ok(encrypt_response(&jwe, &recipient, response).await)
+        })
+    }
+}
+
+async fn encrypt_response(
+    jwe: &JweInner,
+    recipient: &RecipientKey,
+    response: Response,
+) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let encrypted = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(plaintext) => jwe.cipher.encrypt(recipient, &plaintext),
+        Err(err) => Err(ApiError::InternalServerError(format!(
+            "Failed to read response body: {err}"
+        ))),
+    };
+    match encrypted {
+        Ok(compact) => {
+            parts.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(JOSE_CONTENT_TYPE),
+            );
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(compact))
+        }
+        Err(err) => {
+            tracing::error!(error = %err, "response encryption failed");
+            err.into_response()
+        }
+    }
+}
+
+/// Extractor that decrypts an `application/jose` body and deserializes the
+/// JSON plaintext. Only works on routes behind a [`JweLayer`].
+pub struct JweJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for JweJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Some(JweDecrypter(jwe)) = req.extensions().get::<JweDecrypter>().cloned() else {
+            return Err(ApiError::InternalServerError(
+                "JweJson used on a route without JweLayer".to_string(),
+            ));
+        };
+        let is_jose = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with(JOSE_CONTENT_TYPE));
+        if !is_jose {
+            return Err(ApiError::BadRequest(format!(
+                "Expected a {JOSE_CONTENT_TYPE} body"
+            )));
+        }
+
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|err| ApiError::BadRequest(err.body_text()))?;
+        let compact = std::str::from_utf8(&body)
+            .map_err(|_| ApiError::BadRequest("JWE body is not valid UTF-8".to_string()))?;
+        let plaintext = jwe.cipher.decrypt(compact.trim())?;
+        serde_json::from_slice(&plaintext)
+            .map(JweJson)
+            .map_err(|err| ApiError::BadRequest(format!("Invalid decrypted payload: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, body::to_bytes, http::StatusCode, routing::post};
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    /// Not encryption: `kid.base64(plaintext)`, enough to check the plumbing.
+    struct FakeCipher;
+
+    impl JweCipher for FakeCipher {
+        fn encrypt(&self, recipient: &RecipientKey, plaintext: &[u8]) -> Result<String, ApiError> {
+            Ok(format!(
+                "{}.{}",
+                recipient.key_id,
+                URL_SAFE_NO_PAD.encode(plaintext)
+            ))
+        }
+
+        fn decrypt(&self, jwe: &str) -> Result<Vec<u8>, ApiError> {
+            let (_, payload) = jwe
+                .split_once('.')
+                .ok_or_else(|| ApiError::BadRequest("malformed JWE".to_string()))?;
+            URL_SAFE_NO_PAD
+                .decode(payload)
+                .map_err(|_| ApiError::BadRequest("malformed JWE".to_string()))
+        }
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/orders",
+                post(|JweJson(order): JweJson<Value>| async move {
+                    Json(json!({ "received": order["sku"] }))
+                }),
+            )
+            .layer(JweLayer::new(FakeCipher).recipient(
+                "partner-a",
+                RecipientKey::new("a-2024", json!({ "kty": "RSA" })),
+            ))
+    }
+
+    fn request(consumer: Option<&str>, body: &str) -> Request {
+        let mut builder = Request::post("/orders").header(header::CONTENT_TYPE, JOSE_CONTENT_TYPE);
+        if let Some(consumer) = consumer {
+            builder = builder.header("x-consumer-id", consumer);
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trips_encrypted_payloads() {
+        let body = format!("k.{}", URL_SAFE_NO_PAD.encode(r#"{"sku":"A1"}"#));
+        let res = app()
+            .oneshot(request(Some("partner-a"), &body))
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], JOSE_CONTENT_TYPE);
+        let compact = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let compact = std::str::from_utf8(&compact).unwrap();
+        assert!(compact.starts_with("a-2024."));
+        let plaintext: Value =
+            serde_json::from_slice(&FakeCipher.decrypt(compact).unwrap()).unwrap();
+        assert_eq!(plaintext, json!({ "received": "A1" }));
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_consumers_and_bad_bodies() {
+        let res = app()
+            .oneshot(request(Some("partner-b"), "x"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = app().oneshot(request(None, "x")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = app()
+            .oneshot(request(Some("partner-a"), "not-a-jwe"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -38,16 +38,63 @@
 //! ```
 
 pub mod app;
+pub mod audit;
+pub mod bulk;
+pub mod cache;
+pub mod client;
+pub mod client_ip;
+pub mod collab;
 pub mod config;
+pub mod config_watch;
+pub mod cors;
+pub mod cron;
+pub mod db;
+pub mod debug;
+pub mod decompression;
+pub mod docs;
+pub mod download;
+pub mod edge_cache;
 pub mod error;
+pub mod etag;
 pub mod extractors;
+pub mod i18n;
+pub mod import;
+pub mod internal_errors;
+pub mod jwe;
+pub mod listen;
+pub mod mail;
+pub mod maintenance;
+pub mod media;
+pub mod messaging;
+pub mod mock;
 pub mod openapi;
+pub mod pagination;
 pub mod prelude;
+pub mod proxy;
+pub mod redact;
+pub mod redirects;
+pub mod reporting;
+pub mod resource;
+pub mod routes;
+pub mod secrets;
+pub mod slow_requests;
+pub mod storage;
+pub mod tasks;
+pub mod tenancy;
+pub mod testing;
+pub mod trace_context;
+pub mod upload;
+pub mod versioning;
+pub mod warmup;
+pub mod webhooks;
 
 #[cfg(feature = "auth")]
 pub mod auth;
 
-pub use app::App;
-pub use dy_rs_macros::dy_api;
-pub use error::{ApiError, ApiResult};
-pub use extractors::ValidatedJson;
+#[cfg(feature = "reports")]
+pub mod report;
+
+pub use app::{App, BuildError, SubscriberOptions};
+pub use dy_rs_macros::{dy_api, dy_controller};
+pub use error::{ApiError, ApiErrorKind, ApiResult, RateLimit};
+pub use extractors::{ValidatedForm, ValidatedJson, ValidatedPath};
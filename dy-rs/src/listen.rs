@@ -0,0 +1,246 @@
+//! Where the app listens
+//!
+//! The app's routes are served on `server.host` and `server.port`, or on
+//! `server.unix_socket` when it's set, e.g. for an nginx or Envoy sidecar:
+//!
+//! ```toml
+//! [server]
+//! unix_socket = "/run/app/app.sock"
+//! ```
+//!
+//! [`App::listen`](crate::App::listen) serves other routers next to them, on
+//! their own address or socket, such as an admin or metrics port kept off
+//! the public one. They are served as given, without the app's middleware,
+//! and stop with it:
+//!
+//! ```rust,ignore
+//! App::new()
+//!     .auto_configure()
+//!     .mount(api)
+//!     .listen("127.0.0.1:9090", metrics_router)
+//!     .listen("unix:/run/app/admin.sock", admin_router)
+//!     .run()
+//!     .await
+//! ```
+//!
+//! Connections over a Unix socket come from a process on the same host, so
+//! [`ClientIp`](crate::client_ip::ClientIp) sees them as coming from
+//! `127.0.0.1`, and trusts their forwarding headers when it's a trusted
+//! proxy.
+
+use std::{fmt, future::Future, io, net::SocketAddr, path::PathBuf};
+
+use axum::{Router, extract::ConnectInfo};
+#[cfg(unix)]
+use axum::{extract::connect_info::Connected, serve::IncomingStream};
+
+/// An address to listen on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listener {
+    /// A TCP address such as `0.0.0.0:8080` or `localhost:9090`
+    Tcp(String),
+    /// A Unix domain socket, created on startup and removed on shutdown
+    Unix(PathBuf),
+}
+
+impl Listener {
+    pub fn tcp(addr: impl Into<String>) -> Self {
+        Self::Tcp(addr.into())
+    }
+
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self::Unix(path.into())
+    }
+
+    pub(crate) async fn bind(&self) -> io::Result<BoundListener> {
+        match self {
+            Self::Tcp(addr) => Ok(BoundListener::Tcp(
+                tokio::net::TcpListener::bind(addr.as_str()).await?,
+            )),
+            #[cfg(unix)]
+            Self::Unix(path) => {
+                // Left behind by a previous run that didn't shut down
+                if tokio::fs::symlink_metadata(path).await.is_ok_and(|meta| {
+                    use std::os::unix::fs::FileTypeExt;
+                    meta.file_type().is_socket()
+                }) {
+                    tokio::fs::remove_file(path).await?;
+                }
+                let listener = tokio::net::UnixListener::bind(path)?;
+                Ok(BoundListener::Unix(listener, path.clone()))
+            }
+            #[cfg(not(unix))]
+            Self::Unix(path) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("can't listen on {}: no Unix sockets here", path.display()),
+            )),
+        }
+    }
+}
+
+/// `unix:` followed by a path names a Unix socket, anything else a TCP
+/// address
+impl From<&str> for Listener {
+    fn from(addr: &str) -> Self {
+        match addr.strip_prefix("unix:") {
+            Some(path) => Self::unix(path),
+            None => Self::tcp(addr),
+        }
+    }
+}
+
+impl From<String> for Listener {
+    fn from(addr: String) -> Self {
+        Self::from(addr.as_str())
+    }
+}
+
+impl From<SocketAddr> for Listener {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr.to_string())
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "http://{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Connection info of requests received over a Unix socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnixPeer;
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for UnixPeer {
+    fn connect_info(_stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        Self
+    }
+}
+
+/// A [`Listener`] accepting connections
+pub(crate) enum BoundListener {
+    Tcp(tokio::net::TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, PathBuf),
+}
+
+impl BoundListener {
+    /// The address connections are accepted on, for logs
+    pub(crate) fn local(&self) -> io::Result<Listener> {
+        match self {
+            Self::Tcp(listener) => Ok(listener.local_addr()?.into()),
+            #[cfg(unix)]
+            Self::Unix(_, path) => Ok(Listener::Unix(path.clone())),
+        }
+    }
+
+    /// Serve `router` until `shutdown` completes and open connections close
+    pub(crate) async fn serve(
+        self,
+        router: Router,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> io::Result<()> {
+        match self {
+            Self::Tcp(listener) => {
+                let service = router.into_make_service_with_connect_info::<SocketAddr>();
+                axum::serve(listener, service)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }
+            #[cfg(unix)]
+            Self::Unix(listener, path) => {
+                let service = router.into_make_service_with_connect_info::<UnixPeer>();
+                let served = axum::serve(listener, service)
+                    .with_graceful_shutdown(shutdown)
+                    .await;
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    tracing::warn!(path = %path.display(), error = %e, "Failed to remove the socket");
+                }
+                served
+            }
+        }
+    }
+}
+
+/// Whether the request came in over a Unix socket
+pub(crate) fn is_unix_peer(extensions: &axum::http::Extensions) -> bool {
+    extensions.get::<ConnectInfo<UnixPeer>>().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn addresses_name_their_listener() {
+        assert_eq!(
+            Listener::from("unix:/run/app.sock"),
+            Listener::Unix(PathBuf::from("/run/app.sock"))
+        );
+        assert_eq!(
+            Listener::from("127.0.0.1:9090"),
+            Listener::Tcp("127.0.0.1:9090".to_string())
+        );
+        assert_eq!(
+            Listener::unix("/run/app.sock").to_string(),
+            "unix:/run/app.sock"
+        );
+    }
+
+    async fn get_over(
+        mut stream: impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    ) -> String {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: app\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn routers_are_served_over_tcp() {
+        let listener = Listener::tcp("127.0.0.1:0").bind().await.unwrap();
+        let Listener::Tcp(addr) = listener.local().unwrap() else {
+            panic!("not a TCP listener");
+        };
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let router = Router::new().route("/", get(|| async { "over tcp" }));
+        let server = tokio::spawn(listener.serve(router, async {
+            stopped.await.ok();
+        }));
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        assert!(get_over(stream).await.ends_with("over tcp"));
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn routers_are_served_over_unix_sockets() {
+        let path = std::env::temp_dir().join(format!("dy-rs-{}.sock", uuid::Uuid::new_v4()));
+        let listener = Listener::unix(&path).bind().await.unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let router = Router::new().route(
+            "/",
+            get(|ConnectInfo(_): ConnectInfo<UnixPeer>| async { "over a socket" }),
+        );
+        let server = tokio::spawn(listener.serve(router, async {
+            stopped.await.ok();
+        }));
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        assert!(get_over(stream).await.ends_with("over a socket"));
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+}
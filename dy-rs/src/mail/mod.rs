@@ -0,0 +1,202 @@
+//! Outgoing email
+//!
+//! Features that send mail do so through a [`Mailer`]. [`SmtpMailer`] hands
+//! messages to an SMTP server and [`LogMailer`] only logs them, for
+//! development; implement the trait for any other provider (SES,
+//! Postmark...). Which one the app uses is configured under `[mail]`:
+//!
+//! ```toml
+//! [mail]
+//! transport = "smtp"          # or "log" (the default)
+//! from = "Acme <no-reply@acme.test>"
+//!
+//! [mail.smtp]
+//! host = "smtp.internal"
+//! port = 25
+//! username = "acme"           # optional, sent with AUTH PLAIN
+//! password = "..."
+//! ```
+//!
+//! ```rust,ignore
+//! let mailer = config.mail.mailer();
+//! let state = AuthAppState::new(config.auth.clone(), store)
+//!     .verify_emails(EmailVerification::new(mailer.clone(), "https://app.test/verify?token={token}"))
+//!     .reset_passwords(PasswordReset::new(mailer, "https://app.test/reset?token={token}"));
+//! ```
+//!
+//! Message bodies come from [`Templates`], which ship defaults for the auth
+//! emails and can be overridden from a directory of HTML files.
+
+mod smtp;
+mod template;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+
+pub use smtp::{SmtpConfig, SmtpMailer};
+pub use template::{Template, Templates};
+
+/// A file attached to an [`Email`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn new(
+        file_name: impl Into<String>,
+        content_type: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Self {
+        Self {
+            file_name: file_name.into(),
+            content_type: content_type.into(),
+            bytes,
+        }
+    }
+}
+
+/// An email message
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Email {
+    /// Sender, e.g. `Acme <no-reply@acme.test>`; the mailer's default when
+    /// unset
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub html: String,
+    pub attachments: Vec<Attachment>,
+}
+
+impl Email {
+    /// Create an empty message with `subject`
+    pub fn new(subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the sender
+    pub fn from(mut self, address: impl Into<String>) -> Self {
+        self.from = Some(address.into());
+        self
+    }
+
+    /// Add a recipient
+    pub fn to(mut self, address: impl Into<String>) -> Self {
+        self.to.push(address.into());
+        self
+    }
+
+    /// Set the HTML body
+    pub fn html(mut self, html: impl Into<String>) -> Self {
+        self.html = html.into();
+        self
+    }
+
+    /// Attach a file
+    pub fn attach(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+}
+
+/// Delivers [`Email`]s
+#[async_trait]
+pub trait Mailer: Send + Sync + 'static {
+    async fn send(&self, email: Email) -> Result<(), ApiError>;
+}
+
+#[async_trait]
+impl Mailer for Arc<dyn Mailer> {
+    async fn send(&self, email: Email) -> Result<(), ApiError> {
+        (**self).send(email).await
+    }
+}
+
+/// Mailer that logs messages instead of sending them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), ApiError> {
+        tracing::info!(
+            to = ?email.to,
+            subject = %email.subject,
+            attachments = email.attachments.len(),
+            "email not sent (LogMailer)"
+        );
+        tracing::debug!(html = %email.html, "email body");
+        Ok(())
+    }
+}
+
+/// How mail leaves the app
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    /// Log messages instead of sending them
+    #[default]
+    Log,
+    /// Send through the `[mail.smtp]` server
+    Smtp,
+}
+
+/// The `[mail]` section of the app config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MailConfig {
+    pub transport: MailTransport,
+    /// Sender of messages that don't set their own
+    pub from: String,
+    pub smtp: SmtpConfig,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            transport: MailTransport::Log,
+            from: "no-reply@localhost".to_string(),
+            smtp: SmtpConfig::default(),
+        }
+    }
+}
+
+impl MailConfig {
+    /// The configured mailer
+    pub fn mailer(&self) -> Arc<dyn Mailer> {
+        match self.transport {
+            MailTransport::Log => Arc::new(LogMailer),
+            MailTransport::Smtp => Arc::new(SmtpMailer::new(self.smtp.clone(), &self.from)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_picks_the_transport() {
+        let config: MailConfig = serde_json::from_value(serde_json::json!({
+            "transport": "smtp",
+            "from": "Acme <no-reply@acme.test>",
+            "smtp": { "host": "mail.acme.test", "port": 2525 }
+        }))
+        .unwrap();
+        assert_eq!(config.transport, MailTransport::Smtp);
+        assert_eq!(config.smtp.host, "mail.acme.test");
+        assert_eq!(config.smtp.port, 2525);
+        assert_eq!(config.smtp.timeout_secs, 30);
+
+        assert_eq!(MailConfig::default().transport, MailTransport::Log);
+    }
+}
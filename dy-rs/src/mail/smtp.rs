@@ -0,0 +1,435 @@
+//! A small SMTP client
+//!
+//! [`SmtpMailer`] speaks just enough SMTP to hand messages to a relay: `EHLO`,
+//! `AUTH PLAIN` when credentials are configured, then `MAIL`/`RCPT`/`DATA`.
+//! The connection is plain TCP, with no `STARTTLS`, so point it at a relay on
+//! a trusted network (a local Postfix, a provider's sidecar agent) rather
+//! than across the internet.
+
+use std::{io, net::IpAddr, time::Duration};
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use uuid::Uuid;
+
+use super::{Email, Mailer};
+use crate::error::ApiError;
+
+/// The `[mail.smtp]` section of the app config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    /// Sent with `AUTH PLAIN` when both are set
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Name the client greets the server with
+    pub hello: String,
+    /// Limit for delivering one message, connection included
+    pub timeout_secs: u64,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 25,
+            username: None,
+            password: None,
+            hello: "localhost".to_string(),
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Sends mail through an SMTP relay, one connection per message
+#[derive(Debug, Clone)]
+pub struct SmtpMailer {
+    config: SmtpConfig,
+    from: String,
+}
+
+impl SmtpMailer {
+    /// Send through the server in `config`, from `from` unless a message
+    /// sets its own sender
+    pub fn new(config: SmtpConfig, from: impl Into<String>) -> Self {
+        let loopback = config.host == "localhost"
+            || config
+                .host
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback());
+        if config.username.is_some() && !loopback {
+            tracing::warn!(
+                host = %config.host,
+                "SMTP credentials will be sent over an unencrypted connection"
+            );
+        }
+        Self {
+            config,
+            from: from.into(),
+        }
+    }
+
+    async fn deliver(&self, email: &Email) -> io::Result<()> {
+        let from = email.from.as_deref().unwrap_or(&self.from);
+        if email.to.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "email has no recipients",
+            ));
+        }
+        let sender = envelope_address(from)?;
+        let recipients = email
+            .to
+            .iter()
+            .map(|to| envelope_address(to))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let stream = TcpStream::connect((self.config.host.as_str(), self.config.port)).await?;
+        let mut session = Session {
+            stream: BufReader::new(stream),
+        };
+        session.expect("greeting", 2).await?;
+        let hello = &self.config.hello;
+        if session
+            .command(&format!("EHLO {hello}"), "EHLO", 2)
+            .await
+            .is_err()
+        {
+            session.command(&format!("HELO {hello}"), "HELO", 2).await?;
+        }
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            let credentials = STANDARD.encode(format!("\0{username}\0{password}"));
+            session
+                .command(&format!("AUTH PLAIN {credentials}"), "AUTH", 2)
+                .await?;
+        }
+
+        session
+            .command(&format!("MAIL FROM:<{sender}>"), "MAIL FROM", 2)
+            .await?;
+        for recipient in &recipients {
+            session
+                .command(&format!("RCPT TO:<{recipient}>"), "RCPT TO", 2)
+                .await?;
+        }
+        session.command("DATA", "DATA", 3).await?;
+        // Lines starting with a dot are escaped by doubling it
+        let message = format_message(email, from).replace("\r\n.", "\r\n..");
+        session.write(&message).await?;
+        session.write(".\r\n").await?;
+        session.expect("message", 2).await?;
+        // The message is accepted; a failed goodbye doesn't matter
+        let _ = session.command("QUIT", "QUIT", 2).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), ApiError> {
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let result = match tokio::time::timeout(timeout, self.deliver(&email)).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+        };
+        result.map_err(|err| {
+            tracing::warn!(to = ?email.to, error = %err, "Failed to send email");
+            ApiError::InternalServerError(format!("Failed to send email: {err}"))
+        })
+    }
+}
+
+struct Session {
+    stream: BufReader<TcpStream>,
+}
+
+impl Session {
+    async fn write(&mut self, data: &str) -> io::Result<()> {
+        self.stream.get_mut().write_all(data.as_bytes()).await
+    }
+
+    /// Send `line` and check the reply is in `class` (2 for `2xx`...)
+    ///
+    /// `label` names the command in errors, so credentials aren't logged.
+    async fn command(&mut self, line: &str, label: &str, class: u16) -> io::Result<String> {
+        self.write(&format!("{line}\r\n")).await?;
+        self.expect(label, class).await
+    }
+
+    async fn expect(&mut self, label: &str, class: u16) -> io::Result<String> {
+        let (code, text) = self.reply().await?;
+        if code / 100 != class {
+            return Err(io::Error::other(format!(
+                "SMTP server rejected {label}: {code} {text}"
+            )));
+        }
+        Ok(text)
+    }
+
+    /// Read a possibly multi-line reply, e.g. `250-first` ... `250 last`
+    async fn reply(&mut self) -> io::Result<(u16, String)> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "SMTP server closed the connection",
+                ));
+            }
+            let line = line.trim_end();
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("malformed SMTP reply: {line}"),
+                    )
+                })?;
+            text.push_str(line.get(4..).unwrap_or_default());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text));
+            }
+            text.push('\n');
+        }
+    }
+}
+
+/// The bare address of `Name <user@host>` or `user@host`
+fn envelope_address(mailbox: &str) -> io::Result<&str> {
+    let address = match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    };
+    if address.is_empty() || address.contains(|c: char| c.is_control() || "<> ".contains(c)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid email address: {mailbox:?}"),
+        ));
+    }
+    Ok(address)
+}
+
+/// Render `email` as a MIME message, with every part base64-encoded
+fn format_message(email: &Email, from: &str) -> String {
+    let domain = envelope_address(from)
+        .ok()
+        .and_then(|address| address.rsplit_once('@'))
+        .map_or("localhost", |(_, domain)| domain);
+
+    let mut message = String::new();
+    push_header(&mut message, "From", from);
+    push_header(&mut message, "To", &email.to.join(", "));
+    push_header(&mut message, "Subject", &encode_word(&email.subject));
+    push_header(&mut message, "Date", &chrono::Utc::now().to_rfc2822());
+    push_header(
+        &mut message,
+        "Message-ID",
+        &format!("<{}@{domain}>", Uuid::new_v4()),
+    );
+    push_header(&mut message, "MIME-Version", "1.0");
+
+    let html = email.html.as_bytes();
+    if email.attachments.is_empty() {
+        push_part(&mut message, "text/html; charset=utf-8", None, html);
+        return message;
+    }
+
+    let boundary = format!("dy-{}", Uuid::new_v4().simple());
+    push_header(
+        &mut message,
+        "Content-Type",
+        &format!("multipart/mixed; boundary=\"{boundary}\""),
+    );
+    message.push_str("\r\n");
+    message.push_str(&format!("--{boundary}\r\n"));
+    push_part(&mut message, "text/html; charset=utf-8", None, html);
+    for attachment in &email.attachments {
+        message.push_str(&format!("--{boundary}\r\n"));
+        push_part(
+            &mut message,
+            &attachment.content_type,
+            Some(&attachment.file_name),
+            &attachment.bytes,
+        );
+    }
+    message.push_str(&format!("--{boundary}--\r\n"));
+    message
+}
+
+fn push_header(message: &mut String, name: &str, value: &str) {
+    // Line breaks in a value would start new headers
+    let value: String = value
+        .chars()
+        .filter(|c| !matches!(c, '\r' | '\n'))
+        .collect();
+    message.push_str(&format!("{name}: {value}\r\n"));
+}
+
+fn push_part(message: &mut String, content_type: &str, file_name: Option<&str>, bytes: &[u8]) {
+    push_header(message, "Content-Type", content_type);
+    push_header(message, "Content-Transfer-Encoding", "base64");
+    if let Some(file_name) = file_name {
+        let file_name = encode_word(file_name).replace(['"', '\\'], "_");
+        push_header(
+            message,
+            "Content-Disposition",
+            &format!("attachment; filename=\"{file_name}\""),
+        );
+    }
+    message.push_str("\r\n");
+    let encoded = STANDARD.encode(bytes);
+    for line in encoded.as_bytes().chunks(76) {
+        // base64 output is ASCII
+        message.push_str(std::str::from_utf8(line).unwrap_or_default());
+        message.push_str("\r\n");
+    }
+}
+
+/// `text` as-is if it's ASCII, else as an RFC 2047 encoded word
+fn encode_word(text: &str) -> String {
+    if text.is_ascii() {
+        text.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::Attachment;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    /// Accepts one session, rejecting `RCPT TO` for `reject`, and returns the
+    /// commands and message it received
+    async fn fake_server(
+        reject: &'static str,
+    ) -> (u16, tokio::task::JoinHandle<(Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut commands = Vec::new();
+            let mut data = String::new();
+            stream
+                .get_mut()
+                .write_all(b"220 fake ESMTP\r\n")
+                .await
+                .unwrap();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = match line.split(' ').next().unwrap() {
+                    "EHLO" => b"250-fake\r\n250 AUTH PLAIN\r\n",
+                    "AUTH" => b"235 ok\r\n",
+                    "RCPT" if line.contains(reject) => b"550 no such user\r\n",
+                    "DATA" => {
+                        stream
+                            .get_mut()
+                            .write_all(b"354 go ahead\r\n")
+                            .await
+                            .unwrap();
+                        loop {
+                            let mut line = String::new();
+                            stream.read_line(&mut line).await.unwrap();
+                            if line == ".\r\n" {
+                                break;
+                            }
+                            data.push_str(&line);
+                        }
+                        b"250 queued\r\n"
+                    }
+                    "QUIT" => {
+                        commands.push(line);
+                        stream.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 ok\r\n",
+                };
+                commands.push(line);
+                stream.get_mut().write_all(reply).await.unwrap();
+            }
+            (commands, data)
+        });
+        (port, server)
+    }
+
+    fn mailer(port: u16) -> SmtpMailer {
+        SmtpMailer::new(
+            SmtpConfig {
+                host: "127.0.0.1".to_string(),
+                port,
+                username: Some("acme".to_string()),
+                password: Some("secret".to_string()),
+                ..SmtpConfig::default()
+            },
+            "Acme <no-reply@acme.test>",
+        )
+    }
+
+    #[tokio::test]
+    async fn delivers_a_mime_message() {
+        let (port, server) = fake_server("nobody").await;
+        let email = Email::new("Grüße")
+            .to("Ada <ada@example.com>")
+            .html("<p>Hello</p>")
+            .attach(Attachment::new(
+                "report.csv",
+                "text/csv",
+                b"a,b\n1,2\n".to_vec(),
+            ));
+        mailer(port).send(email).await.unwrap();
+
+        let (commands, data) = server.await.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "EHLO localhost".to_string(),
+                format!("AUTH PLAIN {}", STANDARD.encode("\0acme\0secret")),
+                "MAIL FROM:<no-reply@acme.test>".to_string(),
+                "RCPT TO:<ada@example.com>".to_string(),
+                "DATA".to_string(),
+                "QUIT".to_string(),
+            ]
+        );
+        assert!(data.contains("From: Acme <no-reply@acme.test>\r\n"));
+        assert!(data.contains(&format!(
+            "Subject: =?UTF-8?B?{}?=\r\n",
+            STANDARD.encode("Grüße")
+        )));
+        assert!(data.contains("Message-ID: <"));
+        assert!(data.contains("Content-Type: multipart/mixed; boundary="));
+        assert!(data.contains(&STANDARD.encode("<p>Hello</p>")));
+        assert!(data.contains("Content-Disposition: attachment; filename=\"report.csv\""));
+        assert!(data.contains(&STANDARD.encode("a,b\n1,2\n")));
+    }
+
+    #[tokio::test]
+    async fn rejected_recipients_fail_the_send() {
+        let (port, server) = fake_server("nobody@example.com").await;
+        let err = mailer(port)
+            .send(Email::new("Hi").to("nobody@example.com").html("hi"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("550"), "{err}");
+        drop(server);
+
+        assert!(envelope_address("evil@example.com>\r\nRCPT TO:<x@y").is_err());
+    }
+}
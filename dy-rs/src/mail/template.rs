@@ -0,0 +1,208 @@
+//! Named email templates
+//!
+//! A template is a subject and an HTML body with `{{ name }}` placeholders.
+//! Values are HTML-escaped in the body and inserted as-is in the subject.
+//! [`Templates::default`] holds the emails the auth flows send:
+//!
+//! | Name             | Variables                       |
+//! |------------------|---------------------------------|
+//! | `verify_email`   | `name`, `link`, `expires_in`    |
+//! | `password_reset` | `name`, `link`, `expires_in`    |
+//! | `magic_link`     | `name`, `link`, `expires_in`    |
+//!
+//! [`Templates::load`] overrides them, or adds new ones, from `<name>.html`
+//! files whose first line is the subject:
+//!
+//! ```text
+//! Subject: Welcome to {{ product }}
+//!
+//! <p>Hi {{ name }}, ...</p>
+//! ```
+
+use std::{collections::HashMap, fs, io, path::Path, sync::Arc};
+
+use super::Email;
+use crate::{docs::escape_html, error::ApiError};
+
+/// A subject and HTML body with `{{ name }}` placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub subject: String,
+    pub html: String,
+}
+
+impl Template {
+    pub fn new(subject: impl Into<String>, html: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            html: html.into(),
+        }
+    }
+
+    /// Fill in the placeholders, leaving unknown ones as they are
+    pub fn render(&self, vars: &[(&str, &str)]) -> Email {
+        Email::new(fill(&self.subject, vars, |value| value.to_string())).html(fill(
+            &self.html,
+            vars,
+            escape_html,
+        ))
+    }
+}
+
+/// Templates by name
+#[derive(Debug, Clone)]
+pub struct Templates {
+    templates: Arc<HashMap<String, Template>>,
+}
+
+impl Templates {
+    /// No templates, not even the built-in ones
+    pub fn empty() -> Self {
+        Self {
+            templates: Arc::default(),
+        }
+    }
+
+    /// The built-in templates, overridden and extended by the `*.html`
+    /// files in `dir`
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut templates = Self::default();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "html")
+                && let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+            {
+                let source = fs::read_to_string(&path)?;
+                let template = parse(&source).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: missing `Subject:` line", path.display()),
+                    )
+                })?;
+                templates = templates.insert(name, template);
+            }
+        }
+        Ok(templates)
+    }
+
+    /// Add or replace the template called `name`
+    pub fn insert(mut self, name: impl Into<String>, template: Template) -> Self {
+        Arc::make_mut(&mut self.templates).insert(name.into(), template);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Template> {
+        self.templates.get(name)
+    }
+
+    /// Render the template called `name`
+    pub fn render(&self, name: &str, vars: &[(&str, &str)]) -> Result<Email, ApiError> {
+        self.get(name)
+            .map(|template| template.render(vars))
+            .ok_or_else(|| ApiError::InternalServerError(format!("No email template `{name}`")))
+    }
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self::empty()
+            .insert(
+                "verify_email",
+                Template::new(
+                    "Verify your email address",
+                    "<p>Hi {{ name }},</p><p>Please confirm your email address by opening \
+                     <a href=\"{{ link }}\">this link</a>. It expires in {{ expires_in }}.</p>",
+                ),
+            )
+            .insert(
+                "password_reset",
+                Template::new(
+                    "Reset your password",
+                    "<p>Hi {{ name }},</p><p>To choose a new password, open \
+                     <a href=\"{{ link }}\">this link</a>. It expires in {{ expires_in }}.</p>\
+                     <p>If you didn't ask for this, you can ignore this email.</p>",
+                ),
+            )
+            .insert(
+                "magic_link",
+                Template::new(
+                    "Your sign-in link",
+                    "<p>Hi {{ name }},</p><p>Sign in by opening \
+                     <a href=\"{{ link }}\">this link</a>. It expires in {{ expires_in }}.</p>\
+                     <p>If you didn't ask for this, you can ignore this email.</p>",
+                ),
+            )
+    }
+}
+
+/// A template file: a `Subject:` line, then the body
+fn parse(source: &str) -> Option<Template> {
+    let (first, body) = source.split_once('\n').unwrap_or((source, ""));
+    let subject = first.trim().strip_prefix("Subject:")?;
+    Some(Template::new(subject.trim(), body.trim()))
+}
+
+fn fill(text: &str, vars: &[(&str, &str)], encode: impl Fn(&str) -> String) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(len) = rest.find("}}") else {
+            break;
+        };
+        let placeholder = &rest[..len + 2];
+        let name = placeholder[2..len].trim();
+        match vars.iter().find(|(var, _)| *var == name) {
+            Some((_, value)) => out.push_str(&encode(value)),
+            None => out.push_str(placeholder),
+        }
+        rest = &rest[len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_escaped_in_the_body_only() {
+        let template = Template::new("Hello {{ name }}", "<p>{{name}} {{ missing }}</p>");
+        let email = template.render(&[("name", "<Ada & co>")]);
+        assert_eq!(email.subject, "Hello <Ada & co>");
+        assert_eq!(email.html, "<p>&lt;Ada &amp; co&gt; {{ missing }}</p>");
+
+        let unclosed = Template::new("{{ name", "");
+        assert_eq!(unclosed.render(&[("name", "Ada")]).subject, "{{ name");
+    }
+
+    #[test]
+    fn files_override_the_built_in_templates() {
+        let dir = std::env::temp_dir().join(format!("dy-mail-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("password_reset.html"),
+            "Subject: New password for {{ name }}\n\n<p>{{ link }}</p>\n",
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a template").unwrap();
+
+        let templates = Templates::load(&dir).unwrap();
+        let email = templates
+            .render(
+                "password_reset",
+                &[("name", "Ada"), ("link", "https://x.test")],
+            )
+            .unwrap();
+        assert_eq!(email.subject, "New password for Ada");
+        assert_eq!(email.html, "<p>https://x.test</p>");
+        assert!(templates.get("magic_link").is_some());
+        assert!(templates.render("welcome", &[]).is_err());
+
+        fs::write(dir.join("broken.html"), "<p>no subject</p>").unwrap();
+        assert!(Templates::load(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
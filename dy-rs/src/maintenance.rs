@@ -0,0 +1,371 @@
+//! Maintenance mode
+//!
+//! While the app is in maintenance, every route answers `503` with the usual
+//! JSON error body and a `Retry-After` header, except `/health`, `/ready`,
+//! [`MAINTENANCE_PATH`] and the paths listed in `allow`. Auto-configured apps
+//! read the `[maintenance]` section, and re-read it under
+//! [`App::watch_config`](crate::App::watch_config):
+//!
+//! ```toml
+//! [maintenance]
+//! enabled = false
+//! # on while this file exists, e.g. `touch` it from a deploy script
+//! sentinel_file = "/srv/app/MAINTENANCE"
+//! retry_after_secs = 120
+//! message = "Back at 02:00 UTC"
+//! allow = ["/webhooks"]
+//! ```
+//!
+//! The switch can also be flipped through [`App::maintenance`](crate::App::maintenance),
+//! or over HTTP with [`admin_router`], mounted behind your admin
+//! authentication:
+//!
+//! ```rust,ignore
+//! let app = App::new().auto_configure();
+//! let admin = maintenance::admin_router(app.maintenance())
+//!     .layer(RequireRoles::any(vec![ADMIN_ROLE]));
+//! app.mount(admin).run().await
+//! ```
+
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    task,
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    extract::Request,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+
+use crate::{error::ApiError, warmup::READY_PATH};
+
+/// Path of the endpoints served by [`admin_router`]
+pub const MAINTENANCE_PATH: &str = "/admin/maintenance";
+
+/// Paths served during maintenance whatever the configuration
+const ALWAYS_SERVED: &[&str] = &["/health", READY_PATH, MAINTENANCE_PATH];
+
+/// Maintenance settings, the `[maintenance]` config section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Whether the app is in maintenance
+    pub enabled: bool,
+    /// The app is also in maintenance while this file exists
+    pub sentinel_file: Option<PathBuf>,
+    /// `Retry-After` of the `503` responses
+    pub retry_after_secs: u64,
+    /// Message of the `503` responses
+    pub message: String,
+    /// Paths still served, with everything under them
+    pub allow: Vec<String>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sentinel_file: None,
+            retry_after_secs: 60,
+            message: "The service is down for maintenance".to_string(),
+            allow: Vec::new(),
+        }
+    }
+}
+
+/// The maintenance switch, and the layer answering `503` while it's on
+///
+/// Clones share the switch.
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance {
+    enabled: Arc<AtomicBool>,
+    settings: Arc<RwLock<MaintenanceConfig>>,
+}
+
+/// Where maintenance stands, as answered by [`admin_router`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceStatus {
+    /// Whether requests are answered `503`
+    pub active: bool,
+    /// Whether the switch is on
+    pub enabled: bool,
+    /// Whether the sentinel file exists
+    pub sentinel: bool,
+    pub message: String,
+    pub retry_after_secs: u64,
+}
+
+/// Body of `PUT` [`MAINTENANCE_PATH`], overriding the configured response
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EnableMaintenance {
+    pub message: Option<String>,
+    pub retry_after_secs: Option<u64>,
+}
+
+impl Maintenance {
+    pub fn new(config: MaintenanceConfig) -> Self {
+        let maintenance = Self::default();
+        maintenance.configure(config);
+        maintenance
+    }
+
+    /// Apply `config`, switching maintenance on or off as it says
+    pub fn configure(&self, config: MaintenanceConfig) {
+        self.enabled.store(config.enabled, Ordering::Release);
+        *self.settings.write().unwrap() = config;
+    }
+
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::Release);
+    }
+
+    /// Whether the switch is on; see [`is_active`](Self::is_active)
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// Whether requests are answered `503`: the switch is on or the sentinel
+    /// file exists
+    pub async fn is_active(&self) -> bool {
+        self.is_enabled() || self.sentinel_exists().await
+    }
+
+    pub async fn status(&self) -> MaintenanceStatus {
+        let sentinel = self.sentinel_exists().await;
+        let settings = self.settings.read().unwrap().clone();
+        MaintenanceStatus {
+            active: self.is_enabled() || sentinel,
+            enabled: self.is_enabled(),
+            sentinel,
+            message: settings.message,
+            retry_after_secs: settings.retry_after_secs,
+        }
+    }
+
+    async fn sentinel_exists(&self) -> bool {
+        let sentinel = self.settings.read().unwrap().sentinel_file.clone();
+        match sentinel {
+            Some(path) => tokio::fs::try_exists(path).await.unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Whether `path` is served during maintenance
+    fn serves(&self, path: &str) -> bool {
+        let settings = self.settings.read().unwrap();
+        ALWAYS_SERVED
+            .iter()
+            .copied()
+            .chain(settings.allow.iter().map(String::as_str))
+            .any(|allowed| {
+                let allowed = allowed.trim_end_matches('/');
+                path == allowed
+                    || path
+                        .strip_prefix(allowed)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+    }
+
+    fn unavailable(&self) -> Response {
+        let settings = self.settings.read().unwrap();
+        ApiError::service_unavailable(
+            settings.message.clone(),
+            Duration::from_secs(settings.retry_after_secs),
+        )
+        .into_response()
+    }
+}
+
+impl<S> Layer<S> for Maintenance {
+    type Service = MaintenanceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MaintenanceService {
+            inner,
+            maintenance: self.clone(),
+        }
+    }
+}
+
+/// Service produced by [`Maintenance`]
+#[derive(Clone)]
+pub struct MaintenanceService<S> {
+    inner: S,
+    maintenance: Maintenance,
+}
+
+impl<S> Service<Request> for MaintenanceService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        if self.maintenance.serves(request.uri().path()) {
+            return Box::pin(self.inner.call(request));
+        }
+        // The ready service goes into the future; a fresh clone stays here
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let maintenance = self.maintenance.clone();
+
+        Box::pin(async move {
+            if maintenance.is_active().await {
+                return Ok(maintenance.unavailable());
+            }
+            inner.call(request).await
+        })
+    }
+}
+
+/// Router serving the maintenance switch at [`MAINTENANCE_PATH`]: `GET` for
+/// its [`MaintenanceStatus`], `PUT` (with an optional [`EnableMaintenance`]
+/// body) to switch it on and `DELETE` to switch it off
+pub fn admin_router(maintenance: Maintenance) -> Router {
+    let (enable, disable) = (maintenance.clone(), maintenance.clone());
+    Router::new().route(
+        MAINTENANCE_PATH,
+        get(move || {
+            let maintenance = maintenance.clone();
+            async move { Json(maintenance.status().await) }
+        })
+        .put(move |body: Option<Json<EnableMaintenance>>| {
+            let maintenance = enable.clone();
+            async move {
+                let Json(body) = body.unwrap_or_default();
+                {
+                    let mut settings = maintenance.settings.write().unwrap();
+                    if let Some(message) = body.message {
+                        settings.message = message;
+                    }
+                    if let Some(retry_after_secs) = body.retry_after_secs {
+                        settings.retry_after_secs = retry_after_secs;
+                    }
+                }
+                maintenance.enable();
+                tracing::warn!("🚧 Maintenance mode on");
+                Json(maintenance.status().await)
+            }
+        })
+        .delete(move || {
+            let maintenance = disable.clone();
+            async move {
+                maintenance.disable();
+                tracing::info!("✅ Maintenance mode off");
+                Json(maintenance.status().await)
+            }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{StatusCode, header::RETRY_AFTER},
+    };
+    use tower::ServiceExt;
+
+    fn router(maintenance: &Maintenance) -> Router {
+        Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route("/orders", get(|| async { "orders" }))
+            .route("/webhooks/stripe", get(|| async { "hook" }))
+            .merge(admin_router(maintenance.clone()))
+            .layer(maintenance.clone())
+    }
+
+    async fn status_of(router: &Router, method: &str, path: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn maintenance_answers_503_except_for_allowed_paths() {
+        let maintenance = Maintenance::new(MaintenanceConfig {
+            enabled: true,
+            retry_after_secs: 120,
+            allow: vec!["/webhooks/".to_string()],
+            ..MaintenanceConfig::default()
+        });
+        let router = router(&maintenance);
+
+        let response = router
+            .clone()
+            .oneshot(Request::get("/orders").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER], "120");
+        assert_eq!(status_of(&router, "GET", "/health").await, StatusCode::OK);
+        assert_eq!(
+            status_of(&router, "GET", "/webhooks/stripe").await,
+            StatusCode::OK
+        );
+
+        assert_eq!(
+            status_of(&router, "DELETE", MAINTENANCE_PATH).await,
+            StatusCode::OK
+        );
+        assert!(!maintenance.is_enabled());
+        assert_eq!(status_of(&router, "GET", "/orders").await, StatusCode::OK);
+        assert_eq!(
+            status_of(&router, "PUT", MAINTENANCE_PATH).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status_of(&router, "GET", "/orders").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn sentinel_file_turns_maintenance_on() {
+        let sentinel =
+            std::env::temp_dir().join(format!("dy-rs-maintenance-{}", uuid::Uuid::new_v4()));
+        let maintenance = Maintenance::new(MaintenanceConfig {
+            sentinel_file: Some(sentinel.clone()),
+            ..MaintenanceConfig::default()
+        });
+        let router = router(&maintenance);
+
+        assert_eq!(status_of(&router, "GET", "/orders").await, StatusCode::OK);
+        std::fs::write(&sentinel, "").unwrap();
+        let status = maintenance.status().await;
+        assert!(status.active && status.sentinel && !status.enabled);
+        assert_eq!(
+            status_of(&router, "GET", "/orders").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        std::fs::remove_file(&sentinel).unwrap();
+        assert_eq!(status_of(&router, "GET", "/orders").await, StatusCode::OK);
+    }
+}
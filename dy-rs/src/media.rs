@@ -0,0 +1,404 @@
+//! On-the-fly image transformations
+//!
+//! [`media_routes`] serves `GET /media/{id}?w=300&h=300&fit=cover&sig=...`.
+//! Transformed variants are produced by an [`ImageProcessor`], cached on disk
+//! and served through [`FileDownload`](crate::download::FileDownload), so
+//! repeat requests never touch the processor. Parameters are signed with
+//! HMAC-SHA256 so clients cannot request arbitrary sizes.
+//!
+//! dy-rs does not bundle an image codec; implement [`ImageProcessor`] on top
+//! of the library of your choice (e.g. the `image` crate).
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use dy_rs::media::{ImageProcessor, ImageTransform, MediaConfig, media_routes, signed_url};
+//!
+//! struct Resizer;
+//!
+//! impl ImageProcessor for Resizer {
+//!     fn transform(&self, source: &[u8], transform: &ImageTransform) -> Result<Vec<u8>, ApiError> {
+//!         // decode, resize and re-encode
+//!     }
+//! }
+//!
+//! let config = MediaConfig::new("uploads", "cache/media", "change-me");
+//! let url = signed_url(&config, "cat.jpg", &ImageTransform::new(300, 300));
+//! App::new().auto_configure().mount(media_routes(config, Resizer));
+//! ```
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    Router,
+    extract::{self, Query, State},
+    http::HeaderMap,
+    response::Response,
+    routing::get,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{download::FileDownload, error::ApiError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How an image is fitted into the requested box
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Scale to fit inside the box, preserving aspect ratio (default)
+    #[default]
+    Contain,
+    /// Scale and crop to fill the box, preserving aspect ratio
+    Cover,
+    /// Stretch to exactly the box size
+    Fill,
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Fit::Contain => "contain",
+            Fit::Cover => "cover",
+            Fit::Fill => "fill",
+        })
+    }
+}
+
+/// Requested image transformation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImageTransform {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: Fit,
+}
+
+impl ImageTransform {
+    /// Resize into a `width` x `height` box
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: Some(width),
+            height: Some(height),
+            fit: Fit::Contain,
+        }
+    }
+
+    /// Set the fit mode
+    pub fn fit(mut self, fit: Fit) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// Whether the original image should be served unchanged
+    pub fn is_identity(&self) -> bool {
+        self.width.is_none() && self.height.is_none()
+    }
+
+    /// Canonical query string, used for signing and cache keys
+    pub fn canonical(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(w) = self.width {
+            parts.push(format!("w={w}"));
+        }
+        if let Some(h) = self.height {
+            parts.push(format!("h={h}"));
+        }
+        parts.push(format!("fit={}", self.fit));
+        parts.join("&")
+    }
+}
+
+/// Image transformation backend
+///
+/// Called on the blocking thread pool; implementations may do CPU-heavy work.
+pub trait ImageProcessor: Send + Sync + 'static {
+    /// Transform the encoded `source` image, returning the encoded result
+    ///
+    /// The output should keep the source format so the cached variant is
+    /// served with the same content type.
+    fn transform(&self, source: &[u8], transform: &ImageTransform) -> Result<Vec<u8>, ApiError>;
+}
+
+/// Configuration for the media endpoint
+#[derive(Debug, Clone)]
+pub struct MediaConfig {
+    /// Directory holding original images
+    pub source_dir: PathBuf,
+
+    /// Directory where transformed variants are cached
+    pub cache_dir: PathBuf,
+
+    /// Secret used to sign transformation parameters
+    pub secret: String,
+
+    /// Largest accepted width or height (default: 4096)
+    pub max_dimension: u32,
+}
+
+impl MediaConfig {
+    /// Create a new media configuration
+    pub fn new(
+        source_dir: impl Into<PathBuf>,
+        cache_dir: impl Into<PathBuf>,
+        secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            source_dir: source_dir.into(),
+            cache_dir: cache_dir.into(),
+            secret: secret.into(),
+            max_dimension: 4096,
+        }
+    }
+
+    /// Set the largest accepted width or height
+    pub fn max_dimension(mut self, max: u32) -> Self {
+        self.max_dimension = max;
+        self
+    }
+}
+
+fn mac(secret: &str, id: &str, transform: &ImageTransform) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(id.as_bytes());
+    mac.update(b"?");
+    mac.update(transform.canonical().as_bytes());
+    mac
+}
+
+/// Sign the transformation of image `id`
+pub fn sign(secret: &str, id: &str, transform: &ImageTransform) -> String {
+    hex::encode(mac(secret, id, transform).finalize().into_bytes())
+}
+
+/// Check a signature produced by [`sign`] in constant time
+pub fn verify(secret: &str, id: &str, transform: &ImageTransform, signature: &str) -> bool {
+    hex::decode(signature)
+        .map(|sig| mac(secret, id, transform).verify_slice(&sig).is_ok())
+        .unwrap_or(false)
+}
+
+/// Build a signed `/media/{id}` URL for the given transformation
+pub fn signed_url(config: &MediaConfig, id: &str, transform: &ImageTransform) -> String {
+    format!(
+        "/media/{id}?{}&sig={}",
+        transform.canonical(),
+        sign(&config.secret, id, transform)
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaQuery {
+    w: Option<u32>,
+    h: Option<u32>,
+    #[serde(default)]
+    fit: Fit,
+    sig: Option<String>,
+}
+
+struct MediaState<P> {
+    config: MediaConfig,
+    processor: Arc<P>,
+}
+
+impl<P> Clone for MediaState<P> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            processor: self.processor.clone(),
+        }
+    }
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Path of the cached variant of `id` for `transform`
+fn variant_path(cache_dir: &Path, id: &str, transform: &ImageTransform) -> PathBuf {
+    let key = hex::encode(&Sha256::digest(transform.canonical().as_bytes())[..8]);
+    let file_name = match Path::new(id).extension() {
+        Some(ext) => format!("{key}.{}", ext.to_string_lossy()),
+        None => key,
+    };
+    cache_dir.join(id).join(file_name)
+}
+
+async fn media_handler<P: ImageProcessor>(
+    State(state): State<MediaState<P>>,
+    extract::Path(id): extract::Path<String>,
+    Query(query): Query<MediaQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if !is_valid_id(&id) {
+        return Err(ApiError::NotFound("Image not found".to_string()));
+    }
+
+    let config = &state.config;
+    let source = config.source_dir.join(&id);
+    let transform = ImageTransform {
+        width: query.w,
+        height: query.h,
+        fit: query.fit,
+    };
+    if transform.is_identity() {
+        return FileDownload::new(source).serve(&headers).await;
+    }
+
+    let signature = query.sig.as_deref().unwrap_or_default();
+    if !verify(&config.secret, &id, &transform, signature) {
+        return Err(ApiError::Forbidden);
+    }
+    let too_large = |d: Option<u32>| d.is_some_and(|d| d == 0 || d > config.max_dimension);
+    if too_large(transform.width) || too_large(transform.height) {
+        return Err(ApiError::BadRequest(format!(
+            "Image dimensions must be between 1 and {}",
+            config.max_dimension
+        )));
+    }
+
+    let variant = variant_path(&config.cache_dir, &id, &transform);
+    if !tokio::fs::try_exists(&variant).await.unwrap_or(false) {
+        let original = tokio::fs::read(&source).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ApiError::NotFound("Image not found".to_string()),
+            _ => ApiError::InternalServerError(format!("Failed to read image: {e}")),
+        })?;
+
+        let processor = state.processor.clone();
+        let output =
+            tokio::task::spawn_blocking(move || processor.transform(&original, &transform))
+                .await
+                .map_err(|e| {
+                    ApiError::InternalServerError(format!("Image processing failed: {e}"))
+                })??;
+
+        write_variant(&variant, &output)
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to cache image: {e}")))?;
+        tracing::debug!(image = %id, variant = %transform.canonical(), "cached image variant");
+    }
+
+    FileDownload::new(variant)
+        .file_name(id)
+        .serve(&headers)
+        .await
+}
+
+/// Write via a temporary file so concurrent readers never see partial output
+async fn write_variant(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    tokio::fs::write(&tmp, data).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+/// Create the `GET /media/{id}` route
+pub fn media_routes<P: ImageProcessor>(config: MediaConfig, processor: P) -> Router {
+    Router::new()
+        .route("/media/{id}", get(media_handler::<P>))
+        .with_state(MediaState {
+            config,
+            processor: Arc::new(processor),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    /// Fake processor that tags its output and counts invocations
+    struct Tagger(Arc<AtomicUsize>);
+
+    impl ImageProcessor for Tagger {
+        fn transform(
+            &self,
+            source: &[u8],
+            transform: &ImageTransform,
+        ) -> Result<Vec<u8>, ApiError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let mut out = source.to_vec();
+            out.extend_from_slice(transform.canonical().as_bytes());
+            Ok(out)
+        }
+    }
+
+    async fn setup() -> (Router, MediaConfig, Arc<AtomicUsize>) {
+        let root = std::env::temp_dir().join(format!("dy-rs-media-{}", uuid::Uuid::new_v4()));
+        let config = MediaConfig::new(root.join("src"), root.join("cache"), "secret");
+        tokio::fs::create_dir_all(&config.source_dir).await.unwrap();
+        tokio::fs::write(config.source_dir.join("cat.png"), b"PNG:")
+            .await
+            .unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = media_routes(config.clone(), Tagger(calls.clone()));
+        (app, config, calls)
+    }
+
+    async fn get(app: &Router, uri: &str) -> Response {
+        app.clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn transforms_and_caches_signed_requests() {
+        let (app, config, calls) = setup().await;
+        let transform = ImageTransform::new(300, 200).fit(Fit::Cover);
+        let url = signed_url(&config, "cat.png", &transform);
+
+        for _ in 0..2 {
+            let response = get(&app, &url).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["content-type"], "image/png");
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, "PNG:w=300&h=200&fit=cover");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::fs::remove_dir_all(config.source_dir.parent().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_unsigned_or_tampered_parameters() {
+        let (app, config, calls) = setup().await;
+        let url = signed_url(&config, "cat.png", &ImageTransform::new(300, 200));
+
+        let response = get(&app, "/media/cat.png?w=300&h=200").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let tampered = url.replace("w=300", "w=3000");
+        assert_eq!(get(&app, &tampered).await.status(), StatusCode::FORBIDDEN);
+
+        // The original is served as-is without a signature.
+        assert_eq!(get(&app, "/media/cat.png").await.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        tokio::fs::remove_dir_all(config.source_dir.parent().unwrap())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+//! A broker inside the process
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+
+use super::{Consumer, Message, Producer, Subscription, validate_topic};
+use crate::error::ApiError;
+
+/// Passes messages between producers and consumers of the same process
+///
+/// For tests and single-instance apps. Like NATS, messages published while
+/// nobody is subscribed are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBroker {
+    topics: Arc<Mutex<HashMap<String, Vec<Group>>>>,
+}
+
+#[derive(Debug)]
+struct Group {
+    name: String,
+    members: Vec<mpsc::UnboundedSender<Message>>,
+    /// Member the next message goes to
+    next: usize,
+}
+
+impl MemoryBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Producer for MemoryBroker {
+    async fn publish(&self, topic: &str, payload: Bytes) -> Result<(), ApiError> {
+        validate_topic(topic)?;
+        let mut topics = self.topics.lock().unwrap();
+        let Some(groups) = topics.get_mut(topic) else {
+            return Ok(());
+        };
+        for group in groups.iter_mut() {
+            group.members.retain(|member| !member.is_closed());
+            if group.members.is_empty() {
+                continue;
+            }
+            let member = &group.members[group.next % group.members.len()];
+            let _ = member.send(Message::new(topic, payload.clone()));
+            group.next = group.next.wrapping_add(1);
+        }
+        groups.retain(|group| !group.members.is_empty());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Consumer for MemoryBroker {
+    async fn subscribe(&self, topic: &str, group: &str) -> Result<Subscription, ApiError> {
+        validate_topic(topic)?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut topics = self.topics.lock().unwrap();
+        let groups = topics.entry(topic.to_string()).or_default();
+        match groups.iter_mut().find(|g| g.name == group) {
+            Some(existing) => existing.members.push(sender),
+            None => groups.push(Group {
+                name: group.to_string(),
+                members: vec![sender],
+                next: 0,
+            }),
+        }
+        Ok(
+            futures_util::stream::unfold(receiver, |mut receiver| async move {
+                receiver.recv().await.map(|message| (message, receiver))
+            })
+            .boxed(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn groups_share_messages_and_each_group_gets_all() {
+        let broker = MemoryBroker::new();
+        let mut a1 = broker.subscribe("jobs", "a").await.unwrap();
+        let mut a2 = broker.subscribe("jobs", "a").await.unwrap();
+        let mut b = broker.subscribe("jobs", "b").await.unwrap();
+
+        for n in ["1", "2"] {
+            broker.publish("jobs", Bytes::from(n)).await.unwrap();
+        }
+        assert_eq!(a1.next().await.unwrap().payload, "1");
+        assert_eq!(a2.next().await.unwrap().payload, "2");
+        assert_eq!(b.next().await.unwrap().payload, "1");
+        assert_eq!(b.next().await.unwrap().payload, "2");
+
+        // Dropped subscribers stop receiving
+        drop(a2);
+        broker.publish("jobs", Bytes::from("3")).await.unwrap();
+        assert_eq!(a1.next().await.unwrap().payload, "3");
+        broker.publish("nobody", Bytes::from("lost")).await.unwrap();
+    }
+}
@@ -0,0 +1,422 @@
+//! Message broker integration
+//!
+//! Services publish through a [`Producer`] and receive through a
+//! [`Consumer`]; a [`Broker`] is both. [`MemoryBroker`] passes messages
+//! within the process, and with the `nats` feature [`NatsBroker`] talks to a
+//! NATS server.
+//!
+//! A [`ConsumerGroup`] runs a handler for every message on a topic. Apps
+//! register groups with [`App::consume`](crate::App::consume), which starts
+//! them with the server and stops them on shutdown. Instances subscribed with
+//! the same group name split the topic's messages between them:
+//!
+//! ```rust,ignore
+//! let broker = SharedBroker::new(NatsBroker::connect(&config.messaging.nats).await?);
+//!
+//! let invoices = ConsumerGroup::new(broker.clone(), "orders.paid", "invoicing", |message: Message| async move {
+//!     let order: OrderPaid = message.json()?;
+//!     send_invoice(order).await
+//! })
+//! .config(&config.messaging);
+//!
+//! App::new()
+//!     .auto_configure()
+//!     .with_broker(broker)
+//!     .consume(invoices)
+//!     .run()
+//!     .await?;
+//! ```
+//!
+//! A message whose handler fails is delivered again, up to
+//! `max_deliveries` times in all, then published to the group's dead-letter
+//! topic (`<topic>.dlq` unless set otherwise) for inspection or replay.
+//!
+//! ```toml
+//! [messaging]
+//! max_deliveries = 5
+//! retry_delay_ms = 1000
+//! dead_letter_suffix = ".dlq"
+//!
+//! [messaging.nats]
+//! url = "nats://127.0.0.1:4222"
+//! ```
+
+mod memory;
+#[cfg(feature = "nats")]
+mod nats;
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use axum::{body::Bytes, extract::FromRequestParts, http::request::Parts};
+use futures_util::{StreamExt, stream::BoxStream};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{error::ApiError, tasks::TaskScope};
+
+pub use memory::MemoryBroker;
+#[cfg(feature = "nats")]
+pub use nats::NatsBroker;
+
+/// A message received from a topic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Bytes,
+    /// 1 on the first delivery, counting up as a failed message is retried
+    pub delivery: u32,
+}
+
+impl Message {
+    pub fn new(topic: impl Into<String>, payload: impl Into<Bytes>) -> Self {
+        Self {
+            topic: topic.into(),
+            payload: payload.into(),
+            delivery: 1,
+        }
+    }
+
+    /// The payload decoded from JSON
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ApiError> {
+        serde_json::from_slice(&self.payload)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid message on {}: {e}", self.topic)))
+    }
+}
+
+/// Messages of a subscription, until the broker connection closes
+pub type Subscription = BoxStream<'static, Message>;
+
+/// Publishes messages to topics
+#[async_trait]
+pub trait Producer: Send + Sync + 'static {
+    async fn publish(&self, topic: &str, payload: Bytes) -> Result<(), ApiError>;
+}
+
+/// Subscribes to topics
+#[async_trait]
+pub trait Consumer: Send + Sync + 'static {
+    /// Messages published to `topic`
+    ///
+    /// Each group receives every message, and within a group each message
+    /// goes to one subscriber.
+    async fn subscribe(&self, topic: &str, group: &str) -> Result<Subscription, ApiError>;
+}
+
+/// Both ends of a message broker
+pub trait Broker: Producer + Consumer {}
+
+impl<T: Producer + Consumer> Broker for T {}
+
+/// A [`Broker`] shared between handlers and consumer groups
+///
+/// Added to requests by [`App::with_broker`](crate::App::with_broker), and
+/// usable as an extractor or as a field of the app state.
+#[derive(Clone)]
+pub struct SharedBroker(Arc<dyn Broker>);
+
+impl SharedBroker {
+    pub fn new(broker: impl Broker) -> Self {
+        Self(Arc::new(broker))
+    }
+
+    /// Publish `value` as JSON
+    pub async fn publish_json<T: Serialize + ?Sized>(
+        &self,
+        topic: &str,
+        value: &T,
+    ) -> Result<(), ApiError> {
+        let payload = serde_json::to_vec(value)
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to encode message: {e}")))?;
+        self.0.publish(topic, payload.into()).await
+    }
+}
+
+impl From<Arc<dyn Broker>> for SharedBroker {
+    fn from(broker: Arc<dyn Broker>) -> Self {
+        Self(broker)
+    }
+}
+
+#[async_trait]
+impl Producer for SharedBroker {
+    async fn publish(&self, topic: &str, payload: Bytes) -> Result<(), ApiError> {
+        self.0.publish(topic, payload).await
+    }
+}
+
+#[async_trait]
+impl Consumer for SharedBroker {
+    async fn subscribe(&self, topic: &str, group: &str) -> Result<Subscription, ApiError> {
+        self.0.subscribe(topic, group).await
+    }
+}
+
+/// Rejects with `500` when no broker was added with `App::with_broker`
+impl<S> FromRequestParts<S> for SharedBroker
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Self>().cloned().ok_or_else(|| {
+            ApiError::InternalServerError(
+                "SharedBroker needs a broker added with App::with_broker".to_string(),
+            )
+        })
+    }
+}
+
+/// Handles the messages of a [`ConsumerGroup`]
+///
+/// Closures `Fn(Message) -> impl Future` are handlers too.
+#[async_trait]
+pub trait MessageHandler: Send + Sync + 'static {
+    async fn handle(&self, message: Message) -> Result<(), ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> MessageHandler for F
+where
+    F: Fn(Message) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), ApiError>> + Send,
+{
+    async fn handle(&self, message: Message) -> Result<(), ApiError> {
+        self(message).await
+    }
+}
+
+/// The `[messaging]` section of the app config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessagingConfig {
+    /// Deliveries of a failing message before it is dead-lettered
+    pub max_deliveries: u32,
+    /// Wait between deliveries of a failing message
+    pub retry_delay_ms: u64,
+    /// Appended to a topic to name its dead-letter topic
+    pub dead_letter_suffix: String,
+    pub nats: NatsConfig,
+}
+
+impl Default for MessagingConfig {
+    fn default() -> Self {
+        Self {
+            max_deliveries: 5,
+            retry_delay_ms: 1000,
+            dead_letter_suffix: ".dlq".to_string(),
+            nats: NatsConfig::default(),
+        }
+    }
+}
+
+/// The `[messaging.nats]` section of the app config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NatsConfig {
+    /// `nats://[user:password@]host[:port]`
+    pub url: String,
+    /// Sent with `CONNECT`; `token` is used when there's no user
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub token: Option<String>,
+    /// Connection name shown in the server's monitoring
+    pub name: String,
+}
+
+impl Default for NatsConfig {
+    fn default() -> Self {
+        Self {
+            url: "nats://127.0.0.1:4222".to_string(),
+            user: None,
+            password: None,
+            token: None,
+            name: "dy-rs".to_string(),
+        }
+    }
+}
+
+/// A handler consuming a topic as a member of a group
+pub struct ConsumerGroup {
+    broker: SharedBroker,
+    topic: String,
+    group: String,
+    handler: Arc<dyn MessageHandler>,
+    max_deliveries: u32,
+    retry_delay: Duration,
+    dead_letter: Option<String>,
+}
+
+impl ConsumerGroup {
+    pub fn new(
+        broker: SharedBroker,
+        topic: impl Into<String>,
+        group: impl Into<String>,
+        handler: impl MessageHandler,
+    ) -> Self {
+        let topic = topic.into();
+        let config = MessagingConfig::default();
+        Self {
+            broker,
+            dead_letter: Some(format!("{topic}{}", config.dead_letter_suffix)),
+            topic,
+            group: group.into(),
+            handler: Arc::new(handler),
+            max_deliveries: config.max_deliveries,
+            retry_delay: Duration::from_millis(config.retry_delay_ms),
+        }
+    }
+
+    /// Take retry and dead-letter settings from `[messaging]`
+    pub fn config(mut self, config: &MessagingConfig) -> Self {
+        self.max_deliveries = config.max_deliveries;
+        self.retry_delay = Duration::from_millis(config.retry_delay_ms);
+        self.dead_letter = Some(format!("{}{}", self.topic, config.dead_letter_suffix));
+        self
+    }
+
+    /// Deliveries of a failing message before it is dead-lettered
+    pub fn max_deliveries(mut self, max: u32) -> Self {
+        self.max_deliveries = max.max(1);
+        self
+    }
+
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Publish messages that keep failing to `topic`
+    pub fn dead_letter(mut self, topic: impl Into<String>) -> Self {
+        self.dead_letter = Some(topic.into());
+        self
+    }
+
+    /// Drop messages that keep failing, after logging them
+    pub fn without_dead_letter(mut self) -> Self {
+        self.dead_letter = None;
+        self
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Subscribe, then handle messages in `tasks` until shutdown starts
+    pub async fn start(self, tasks: &TaskScope) -> Result<(), ApiError> {
+        let mut subscription = self.broker.subscribe(&self.topic, &self.group).await?;
+        tracing::info!(topic = %self.topic, group = %self.group, "consumer started");
+        let cancel = tasks.clone();
+        tasks.spawn("consumer", async move {
+            loop {
+                tokio::select! {
+                    message = subscription.next() => match message {
+                        Some(message) => self.process(message).await,
+                        None => {
+                            tracing::warn!(topic = %self.topic, group = %self.group, "subscription closed");
+                            break;
+                        }
+                    },
+                    _ = cancel.cancelled() => break,
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Handle `message`, retrying and finally dead-lettering it on failure
+    async fn process(&self, mut message: Message) {
+        loop {
+            let Err(e) = self.handler.handle(message.clone()).await else {
+                return;
+            };
+            if message.delivery < self.max_deliveries {
+                tracing::warn!(topic = %message.topic, delivery = message.delivery, "message handler failed: {e}");
+                message.delivery += 1;
+                tokio::time::sleep(self.retry_delay).await;
+                continue;
+            }
+
+            match &self.dead_letter {
+                Some(topic) => {
+                    tracing::error!(topic = %message.topic, dead_letter = %topic, "message dead-lettered after {} deliveries: {e}", message.delivery);
+                    if let Err(e) = self.broker.publish(topic, message.payload).await {
+                        tracing::error!(dead_letter = %topic, "failed to dead-letter message: {e}");
+                    }
+                }
+                None => {
+                    tracing::error!(topic = %message.topic, "message dropped after {} deliveries: {e}", message.delivery);
+                }
+            }
+            return;
+        }
+    }
+}
+
+/// Reject topics a broker can't route, such as ones containing spaces
+pub(crate) fn validate_topic(topic: &str) -> Result<(), ApiError> {
+    if topic.is_empty() || topic.contains(|c: char| c.is_whitespace() || c.is_control()) {
+        return Err(ApiError::BadRequest(format!("Invalid topic: {topic:?}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn failing_messages_are_retried_then_dead_lettered() {
+        let broker = SharedBroker::new(MemoryBroker::new());
+        let mut dead_letters = broker.subscribe("orders.dlq", "ops").await.unwrap();
+        let calls = Arc::new(AtomicU32::new(0));
+        let handler = {
+            let calls = calls.clone();
+            move |message: Message| {
+                let calls = calls.clone();
+                async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    let order: serde_json::Value = message.json()?;
+                    if order["id"] == 2 {
+                        return Err(ApiError::InternalServerError("boom".into()));
+                    }
+                    Ok(())
+                }
+            }
+        };
+        let tasks = TaskScope::new();
+        ConsumerGroup::new(broker.clone(), "orders", "billing", handler)
+            .max_deliveries(3)
+            .retry_delay(Duration::from_millis(1))
+            .start(&tasks)
+            .await
+            .unwrap();
+
+        broker
+            .publish_json("orders", &serde_json::json!({ "id": 1 }))
+            .await
+            .unwrap();
+        broker
+            .publish_json("orders", &serde_json::json!({ "id": 2 }))
+            .await
+            .unwrap();
+
+        let dead = tokio::time::timeout(Duration::from_secs(1), dead_letters.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dead.topic, "orders.dlq");
+        assert_eq!(dead.payload, r#"{"id":2}"#);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        assert!(tasks.drain(Duration::from_secs(1)).await);
+    }
+
+    #[test]
+    fn topics_are_validated() {
+        assert!(validate_topic("orders.paid").is_ok());
+        assert!(validate_topic("").is_err());
+        assert!(validate_topic("orders paid").is_err());
+    }
+}
@@ -0,0 +1,425 @@
+//! Mock responses generated from an OpenAPI document
+//!
+//! Lets frontend teams build against the contract before the handlers exist.
+//! Every documented operation gets a canned response: the first `2xx` (or
+//! `default`) response's example if it has one, otherwise a value generated
+//! from its schema. Real routes always win; the mock only answers requests
+//! that no route matched. (A path with some of its methods routed answers
+//! `405` for the others, as usual.)
+//!
+//! ```rust,ignore
+//! App::new()
+//!     .auto_configure()
+//!     .mock_from_openapi(openapi::from_json(include_str!("../openapi.json"))?)
+//!     .mount(implemented_so_far())
+//! ```
+//!
+//! or, without any code, `dy mock openapi.yaml`.
+
+use axum::{
+    Router,
+    body::Body,
+    extract::Request,
+    http::{HeaderName, HeaderValue, Method, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value};
+
+use crate::error::ApiError;
+
+/// Header set on every mocked response
+pub const MOCK_HEADER: &str = "x-dy-mock";
+
+/// How deep `$ref`s and nested schemas are followed before giving up with `null`
+const MAX_DEPTH: usize = 8;
+
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+#[derive(Debug, Clone)]
+struct MockOperation {
+    method: Method,
+    /// Path segments, `None` for a `{param}`
+    segments: Vec<Option<String>>,
+    status: StatusCode,
+    content_type: Option<String>,
+    body: Option<Value>,
+}
+
+impl MockOperation {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let mut parts = path.trim_matches('/').split('/').filter(|s| !s.is_empty());
+        self.method == method
+            && self
+                .segments
+                .iter()
+                .all(|segment| match (segment, parts.next()) {
+                    (Some(literal), Some(part)) => literal == part,
+                    (None, Some(_)) => true,
+                    (_, None) => false,
+                })
+            && parts.next().is_none()
+    }
+
+    fn literals(&self) -> usize {
+        self.segments.iter().filter(|s| s.is_some()).count()
+    }
+
+    fn response(&self) -> Response {
+        let mut response = match (&self.body, &self.content_type) {
+            (Some(body), Some(content_type)) => {
+                let bytes = match body {
+                    Value::String(text) if !is_json(content_type) => text.clone(),
+                    body => body.to_string(),
+                };
+                let mut response = Response::new(Body::from(bytes));
+                if let Ok(value) = HeaderValue::from_str(content_type) {
+                    response.headers_mut().insert(header::CONTENT_TYPE, value);
+                }
+                response
+            }
+            _ => Response::new(Body::empty()),
+        };
+        *response.status_mut() = self.status;
+        response.headers_mut().insert(
+            HeaderName::from_static(MOCK_HEADER),
+            HeaderValue::from_static("true"),
+        );
+        response
+    }
+}
+
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+/// Canned responses for every operation of a document
+#[derive(Debug, Clone)]
+pub struct MockServer {
+    operations: Vec<MockOperation>,
+}
+
+impl MockServer {
+    pub fn new(doc: &utoipa::openapi::OpenApi) -> Self {
+        let spec = serde_json::to_value(doc).expect("OpenAPI documents serialize to JSON");
+        Self::from_value(&spec)
+    }
+
+    /// Build from a document in its JSON form
+    pub fn from_value(spec: &Value) -> Self {
+        let mut operations = Vec::new();
+        for (path, item) in spec["paths"].as_object().into_iter().flatten() {
+            let segments = path
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .map(|s| (!s.starts_with('{')).then(|| s.to_string()))
+                .collect::<Vec<_>>();
+            for method in METHODS {
+                let Some(operation) = item.get(method) else {
+                    continue;
+                };
+                let (status, content_type, body) = mock_response(spec, operation);
+                operations.push(MockOperation {
+                    method: Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                        .expect("standard method"),
+                    segments: segments.clone(),
+                    status,
+                    content_type,
+                    body,
+                });
+            }
+        }
+        // `/users/me` before `/users/{id}`
+        operations.sort_by_key(|op| std::cmp::Reverse(op.literals()));
+        Self { operations }
+    }
+
+    /// The mocked response for a request, if an operation matches it
+    pub fn respond(&self, method: &Method, path: &str) -> Option<Response> {
+        self.operations
+            .iter()
+            .find(|op| op.matches(method, path))
+            .map(MockOperation::response)
+    }
+
+    /// Router answering every request with its mocked response, or `404`
+    pub fn into_router(self) -> Router {
+        Router::new().fallback(move |request: Request| {
+            let response = self.respond(request.method(), request.uri().path());
+            async move {
+                response.unwrap_or_else(|| {
+                    ApiError::NotFound("No such operation in the mocked API".to_string())
+                        .into_response()
+                })
+            }
+        })
+    }
+}
+
+/// Status, content type and example body of an operation's success response
+fn mock_response(spec: &Value, operation: &Value) -> (StatusCode, Option<String>, Option<Value>) {
+    let responses = operation["responses"].as_object();
+    let chosen = responses
+        .into_iter()
+        .flatten()
+        .filter(|(code, _)| code.starts_with('2'))
+        .min_by_key(|(code, _)| code.as_str())
+        .or_else(|| {
+            responses
+                .into_iter()
+                .flatten()
+                .find(|(code, _)| code.as_str() == "default")
+        });
+    let Some((code, response)) = chosen else {
+        return (StatusCode::OK, None, None);
+    };
+    let status = code
+        .parse::<u16>()
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .unwrap_or(StatusCode::OK);
+
+    let response = resolve(spec, response, 0);
+    let Some(content) = response["content"].as_object() else {
+        return (status, None, None);
+    };
+    let media = content
+        .iter()
+        .find(|(content_type, _)| is_json(content_type))
+        .or_else(|| content.iter().next());
+    let Some((content_type, media)) = media else {
+        return (status, None, None);
+    };
+
+    let named_example = media["examples"]
+        .as_object()
+        .and_then(|examples| examples.values().next())
+        .map(|example| resolve(spec, example, 0)["value"].clone())
+        .filter(|value| !value.is_null());
+    let body = media
+        .get("example")
+        .cloned()
+        .or(named_example)
+        .unwrap_or_else(|| example(spec, &media["schema"], 0));
+    (status, Some(content_type.clone()), Some(body))
+}
+
+/// Follow a local `$ref` chain
+fn resolve<'a>(spec: &'a Value, value: &'a Value, depth: usize) -> &'a Value {
+    match value["$ref"].as_str() {
+        Some(reference) if depth < MAX_DEPTH => {
+            let target = reference
+                .strip_prefix('#')
+                .and_then(|pointer| spec.pointer(pointer))
+                .unwrap_or(&Value::Null);
+            resolve(spec, target, depth + 1)
+        }
+        Some(_) => &Value::Null,
+        None => value,
+    }
+}
+
+/// An example value matching `schema`
+fn example(spec: &Value, schema: &Value, depth: usize) -> Value {
+    if depth > MAX_DEPTH {
+        return Value::Null;
+    }
+    let schema = resolve(spec, schema, 0);
+    for key in ["example", "default", "const"] {
+        if let Some(value) = schema.get(key) {
+            return value.clone();
+        }
+    }
+    for key in ["examples", "enum"] {
+        if let Some(value) = schema[key].as_array().and_then(|values| values.first()) {
+            return value.clone();
+        }
+    }
+    if let Some(parts) = schema["allOf"].as_array() {
+        let mut merged = Map::new();
+        for part in parts {
+            match example(spec, part, depth + 1) {
+                Value::Object(fields) => merged.extend(fields),
+                other if parts.len() == 1 => return other,
+                _ => {}
+            }
+        }
+        return Value::Object(merged);
+    }
+    for key in ["oneOf", "anyOf"] {
+        if let Some(first) = schema[key].as_array().and_then(|variants| {
+            variants
+                .iter()
+                .find(|v| v["type"] != "null")
+                .or(variants.first())
+        }) {
+            return example(spec, first, depth + 1);
+        }
+    }
+
+    let ty = match &schema["type"] {
+        Value::String(ty) => ty.as_str(),
+        Value::Array(types) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|ty| *ty != "null")
+            .unwrap_or("null"),
+        _ if schema.get("properties").is_some() => "object",
+        _ if schema.get("items").is_some() => "array",
+        _ => return Value::Null,
+    };
+    match ty {
+        "object" => Value::Object(
+            schema["properties"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(name, property)| (name.clone(), example(spec, property, depth + 1)))
+                .collect(),
+        ),
+        "array" => Value::Array(vec![example(spec, &schema["items"], depth + 1)]),
+        "string" => Value::from(match schema["format"].as_str() {
+            Some("date-time") => "2024-01-01T00:00:00Z",
+            Some("date") => "2024-01-01",
+            Some("time") => "00:00:00",
+            Some("uuid") => "00000000-0000-0000-0000-000000000000",
+            Some("email") => "user@example.com",
+            Some("uri" | "url") => "https://example.com",
+            Some("ipv4") => "127.0.0.1",
+            Some("byte") => "ZXhhbXBsZQ==",
+            _ => "string",
+        }),
+        "integer" => schema
+            .get("minimum")
+            .filter(|min| min.is_number())
+            .map(|min| Value::from(min.as_f64().unwrap_or_default().ceil() as i64))
+            .unwrap_or(Value::from(0)),
+        "number" => schema
+            .get("minimum")
+            .filter(|min| min.is_number())
+            .cloned()
+            .unwrap_or(Value::from(0.0)),
+        "boolean" => Value::Bool(true),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    fn spec() -> Value {
+        json!({
+            "openapi": "3.1.0",
+            "info": { "title": "Shop", "version": "1" },
+            "paths": {
+                "/users/{id}": {
+                    "get": { "responses": { "200": {
+                        "description": "",
+                        "content": { "application/json": {
+                            "schema": { "$ref": "#/components/schemas/User" }
+                        } }
+                    } } },
+                    "delete": { "responses": { "204": { "description": "" } } }
+                },
+                "/users/me": {
+                    "get": { "responses": {
+                        "404": { "description": "" },
+                        "200": {
+                            "description": "",
+                            "content": { "application/json": {
+                                "example": { "id": "me" }
+                            } }
+                        }
+                    } }
+                },
+                "/users": {
+                    "post": { "responses": { "201": { "$ref": "#/components/responses/Created" } } }
+                }
+            },
+            "components": {
+                "schemas": {
+                    "User": {
+                        "type": "object",
+                        "properties": {
+                            "id": { "type": "string", "format": "uuid" },
+                            "age": { "type": "integer", "minimum": 18 },
+                            "nickname": { "type": ["string", "null"] },
+                            "roles": { "type": "array", "items": {
+                                "type": "string", "enum": ["admin", "member"]
+                            } },
+                            "manager": { "$ref": "#/components/schemas/User" }
+                        }
+                    }
+                },
+                "responses": {
+                    "Created": {
+                        "description": "",
+                        "content": { "application/json": { "examples": {
+                            "first": { "value": { "created": true } }
+                        } } }
+                    }
+                }
+            }
+        })
+    }
+
+    async fn call(server: &MockServer, method: Method, path: &str) -> (StatusCode, Value) {
+        let res = server
+            .clone()
+            .into_router()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(path)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[test]
+    fn generates_values_from_schemas() {
+        let spec = spec();
+        let user = example(&spec, &json!({ "$ref": "#/components/schemas/User" }), 0);
+        assert_eq!(user["id"], "00000000-0000-0000-0000-000000000000");
+        assert_eq!(user["age"], 18);
+        assert_eq!(user["nickname"], "string");
+        assert_eq!(user["roles"], json!(["admin"]));
+        // recursive refs bottom out instead of overflowing
+        assert!(user["manager"]["manager"].is_object());
+    }
+
+    #[tokio::test]
+    async fn serves_examples_for_documented_operations() {
+        let server = MockServer::from_value(&spec());
+
+        let (status, body) = call(&server, Method::GET, "/users/me").await;
+        assert_eq!((status, body), (StatusCode::OK, json!({ "id": "me" })));
+
+        let (status, body) = call(&server, Method::GET, "/users/42").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["age"], 18);
+
+        let (status, body) = call(&server, Method::POST, "/users").await;
+        assert_eq!(
+            (status, body),
+            (StatusCode::CREATED, json!({ "created": true }))
+        );
+
+        let (status, _) = call(&server, Method::DELETE, "/users/42").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, _) = call(&server, Method::GET, "/orders").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -1,12 +1,24 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
 
+use serde::{Deserialize, Serialize};
 use utoipa::openapi::{
-    self, ComponentsBuilder, InfoBuilder, OpenApiBuilder, PathsBuilder, RefOr,
-    path::{HttpMethod, Operation, PathItemBuilder},
+    self, ComponentsBuilder, ContactBuilder, InfoBuilder, LicenseBuilder, OpenApiBuilder,
+    PathsBuilder, RefOr,
+    content::{Content, ContentBuilder},
+    path::{HttpMethod, Operation, OperationBuilder, PathItem, PathItemBuilder},
+    response::{ResponseBuilder, ResponsesBuilder},
+    schema::{KnownFormat, ObjectBuilder, Schema, SchemaFormat, Type},
+    security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme},
+    server::ServerBuilder,
+    tag::{Tag, TagBuilder},
 };
+use yaml_rust2::{Yaml, YamlEmitter, YamlLoader};
+
+use crate::error::ErrorDoc;
 
 /// Metadata needed to build an OpenAPI document.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DocInfo {
     pub title: &'static str,
     pub version: &'static str,
@@ -23,6 +35,59 @@ impl Default for DocInfo {
     }
 }
 
+/// Name of the HTTP bearer (JWT) security scheme registered automatically
+/// when an operation declares `security = "bearerAuth"`.
+pub const BEARER_AUTH_SCHEME: &str = "bearerAuth";
+
+/// Operation extension holding the API version set with
+/// `#[dy_api(version = "v1")]`.
+pub const API_VERSION_EXTENSION: &str = "x-api-version";
+
+/// API version an operation belongs to, if any.
+pub fn operation_version(operation: &Operation) -> Option<&str> {
+    operation
+        .extensions
+        .as_ref()
+        .and_then(|extensions| extensions.get(API_VERSION_EXTENSION))
+        .and_then(serde_json::Value::as_str)
+}
+
+/// Assign `operation` to API `version`.
+pub fn set_operation_version(operation: &mut Operation, version: &str) {
+    operation
+        .extensions
+        .get_or_insert_with(Default::default)
+        .insert(API_VERSION_EXTENSION.to_string(), version.into());
+}
+
+/// Assign every operation of `doc` that has no version yet to `version`.
+pub fn set_document_version(doc: &mut openapi::OpenApi, version: &str) {
+    for item in doc.paths.paths.values_mut() {
+        for operation in operations_mut(item) {
+            if operation_version(operation).is_none() {
+                set_operation_version(operation, version);
+            }
+        }
+    }
+}
+
+/// Assign the operations of `doc` below `/{version}` to `version`, unless
+/// they carry one, and mark those of `version` deprecated if `deprecated`.
+pub fn set_path_version(doc: &mut openapi::OpenApi, version: &str, deprecated: bool) {
+    let prefix = format!("/{version}");
+    for (path, item) in doc.paths.paths.iter_mut() {
+        let below = is_below(path, &prefix);
+        for operation in operations_mut(item) {
+            if below && operation_version(operation).is_none() {
+                set_operation_version(operation, version);
+            }
+            if deprecated && operation_version(operation) == Some(version) {
+                operation.deprecated = Some(openapi::Deprecated::True);
+            }
+        }
+    }
+}
+
 /// Represents a single documented endpoint gathered from `#[dy_api]`.
 pub struct AutoOperation {
     pub path: &'static str,
@@ -34,17 +99,79 @@ pub struct AutoOperation {
 // Collect all documented routes from `#[dy_api]` attributes.
 inventory::collect!(AutoOperation);
 
+/// Build a media type entry for `content_type`.
+///
+/// Without an explicit schema, `text/*` bodies are documented as a string and
+/// other non-JSON, non-form bodies (files, images, octet streams) as a binary
+/// string.
+pub fn content_for(content_type: &str, schema: Option<RefOr<Schema>>) -> Content {
+    let schema = schema.or_else(|| default_schema(content_type));
+    ContentBuilder::new().schema(schema).build()
+}
+
+/// Add a response per status of `errors`, listing its codes and referring to
+/// the [`ErrorResponse`](crate::error::ErrorResponse) schema.
+pub fn error_responses(mut responses: ResponsesBuilder, errors: &[ErrorDoc]) -> ResponsesBuilder {
+    let mut codes: BTreeMap<u16, Vec<&str>> = BTreeMap::new();
+    for error in errors {
+        let codes = codes.entry(error.status).or_default();
+        if !codes.contains(&error.code) {
+            codes.push(error.code);
+        }
+    }
+    for (status, codes) in codes {
+        let schema = RefOr::Ref(openapi::Ref::from_schema_name("ErrorResponse"));
+        responses = responses.response(
+            status.to_string(),
+            ResponseBuilder::new()
+                .description(format!("Error: {}", codes.join(", ")))
+                .content(
+                    "application/json",
+                    ContentBuilder::new().schema(Some(schema)).build(),
+                )
+                .build(),
+        );
+    }
+    responses
+}
+
+fn default_schema(content_type: &str) -> Option<RefOr<Schema>> {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    let is_structured = essence == "application/json"
+        || essence.ends_with("+json")
+        || essence == "application/x-www-form-urlencoded"
+        || essence.starts_with("multipart/");
+    if is_structured {
+        return None;
+    }
+
+    // A compact JWE is plain text despite its `application/` type.
+    let mut schema = ObjectBuilder::new().schema_type(Type::String);
+    if !essence.starts_with("text/") && essence != "application/jose" {
+        schema = schema.format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary)));
+    }
+    Some(RefOr::T(Schema::Object(schema.build())))
+}
+
 /// Build an OpenAPI document from all routes annotated with `#[dy_api]`.
 pub fn build_auto_openapi(info: DocInfo) -> openapi::OpenApi {
     let mut path_items: BTreeMap<String, PathItemBuilder> = BTreeMap::new();
+    let mut uses_bearer_auth = false;
 
     for entry in inventory::iter::<AutoOperation>() {
-        let builder = path_items
-            .entry(entry.path.to_string())
-            .or_insert_with(PathItemBuilder::new);
+        let builder = path_items.entry(entry.path.to_string()).or_default();
+
+        let operation = (entry.operation)();
+        uses_bearer_auth |= uses_scheme(&operation, BEARER_AUTH_SCHEME);
 
         let updated = std::mem::replace(builder, PathItemBuilder::new())
-            .operation(entry.method.clone(), (entry.operation)());
+            .operation(entry.method.clone(), operation);
         *builder = updated;
     }
 
@@ -62,6 +189,10 @@ pub fn build_auto_openapi(info: DocInfo) -> openapi::OpenApi {
     for (name, schema) in schemas {
         components_builder = components_builder.schema(name, schema);
     }
+    if uses_bearer_auth {
+        components_builder =
+            components_builder.security_scheme(BEARER_AUTH_SCHEME, bearer_security_scheme());
+    }
     let components = components_builder.build();
 
     let mut info_builder = InfoBuilder::new().title(info.title).version(info.version);
@@ -73,22 +204,1221 @@ pub fn build_auto_openapi(info: DocInfo) -> openapi::OpenApi {
         .info(info_builder.build())
         .paths(paths.build());
 
-    // Only attach components if we actually collected schemas.
-    if !components.schemas.is_empty() {
+    // Only attach components if we actually collected something.
+    if !components.schemas.is_empty() || !components.security_schemes.is_empty() {
         builder = builder.components(Some(components));
     }
 
     builder.build()
 }
 
+/// Selects a subset of operations from a document by tag, path prefix and
+/// API version.
+///
+/// An empty filter keeps everything. Operations without tags are dropped as
+/// soon as `tags` is non-empty, while operations without a version are kept
+/// in every version.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DocFilter {
+    pub tags: Vec<String>,
+    pub exclude_tags: Vec<String>,
+    pub path_prefix: Option<String>,
+    pub versions: Vec<String>,
+}
+
+impl DocFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep operations carrying this tag.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Drop operations carrying this tag.
+    pub fn exclude_tag(mut self, tag: impl Into<String>) -> Self {
+        self.exclude_tags.push(tag.into());
+        self
+    }
+
+    /// Keep only `prefix` and the paths below it, matched on whole
+    /// segments: `/users` keeps `/users/{id}` but not `/users-admin`.
+    pub fn path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.path_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Keep operations of API `version`, plus unversioned ones.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.versions.push(version.into());
+        self
+    }
+
+    /// Returns true if the filter keeps every operation.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+            && self.exclude_tags.is_empty()
+            && self.path_prefix.is_none()
+            && self.versions.is_empty()
+    }
+
+    /// Combine two filters; an operation must satisfy both.
+    ///
+    /// Returns `None` when no operation can: the filters keep disjoint tags,
+    /// versions or path prefixes.
+    pub fn and(mut self, other: DocFilter) -> Option<Self> {
+        self.tags = intersect(self.tags, other.tags)?;
+        self.versions = intersect(self.versions, other.versions)?;
+        self.exclude_tags.extend(other.exclude_tags);
+        self.path_prefix = match (self.path_prefix, other.path_prefix) {
+            (Some(mine), Some(theirs)) if is_below(&theirs, &mine) => Some(theirs),
+            (Some(mine), Some(theirs)) if is_below(&mine, &theirs) => Some(mine),
+            (Some(_), Some(_)) => return None,
+            (mine, theirs) => mine.or(theirs),
+        };
+        Some(self)
+    }
+
+    fn keeps_path(&self, path: &str) -> bool {
+        self.path_prefix
+            .as_deref()
+            .is_none_or(|prefix| is_below(path, prefix))
+    }
+
+    fn keeps_operation(&self, operation: &Operation) -> bool {
+        let tags = operation.tags.as_deref().unwrap_or_default();
+        if tags.iter().any(|t| self.exclude_tags.contains(t)) {
+            return false;
+        }
+        let version = operation_version(operation);
+        if !self.versions.is_empty()
+            && version.is_some_and(|v| !self.versions.iter().any(|w| w == v))
+        {
+            return false;
+        }
+        self.tags.is_empty() || tags.iter().any(|t| self.tags.contains(t))
+    }
+}
+
+/// Intersect two allow-lists where an empty list allows everything; `None`
+/// if they have nothing in common.
+fn intersect(mut mine: Vec<String>, theirs: Vec<String>) -> Option<Vec<String>> {
+    if mine.is_empty() {
+        return Some(theirs);
+    }
+    if !theirs.is_empty() {
+        mine.retain(|item| theirs.contains(item));
+        if mine.is_empty() {
+            return None;
+        }
+    }
+    Some(mine)
+}
+
+/// Returns true if `path` is `prefix` or one of the paths below it, so that
+/// `/users/{id}` is below `/users` but `/users-admin` is not.
+fn is_below(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Return a copy of `doc` containing only the operations kept by `filter`.
+///
+/// Paths left without operations are removed, as are tag definitions no
+/// remaining operation refers to. Components are kept as-is.
+pub fn filter_openapi(doc: &openapi::OpenApi, filter: &DocFilter) -> openapi::OpenApi {
+    let mut doc = doc.clone();
+
+    doc.paths.paths.retain(|path, item| {
+        if !filter.keeps_path(path) {
+            return false;
+        }
+        for slot in [
+            &mut item.get,
+            &mut item.put,
+            &mut item.post,
+            &mut item.delete,
+            &mut item.options,
+            &mut item.head,
+            &mut item.patch,
+            &mut item.trace,
+        ] {
+            if slot.as_ref().is_some_and(|op| !filter.keeps_operation(op)) {
+                *slot = None;
+            }
+        }
+        operations(item).next().is_some()
+    });
+
+    if let Some(tags) = doc.tags.as_mut() {
+        let used: Vec<String> = doc
+            .paths
+            .paths
+            .values()
+            .flat_map(operations)
+            .flat_map(|op| op.tags.clone().unwrap_or_default())
+            .collect();
+        tags.retain(|tag| used.contains(&tag.name));
+    }
+
+    doc
+}
+
+fn operations(item: &PathItem) -> impl Iterator<Item = &Operation> {
+    [
+        &item.get,
+        &item.put,
+        &item.post,
+        &item.delete,
+        &item.options,
+        &item.head,
+        &item.patch,
+        &item.trace,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// The documented operations of `item` with their method
+pub(crate) fn method_operations(
+    item: &PathItem,
+) -> impl Iterator<Item = (&'static str, &Operation)> {
+    [
+        ("GET", &item.get),
+        ("PUT", &item.put),
+        ("POST", &item.post),
+        ("DELETE", &item.delete),
+        ("OPTIONS", &item.options),
+        ("HEAD", &item.head),
+        ("PATCH", &item.patch),
+        ("TRACE", &item.trace),
+    ]
+    .into_iter()
+    .filter_map(|(method, operation)| Some((method, operation.as_ref()?)))
+}
+
+fn operations_mut(item: &mut PathItem) -> impl Iterator<Item = &mut Operation> {
+    [
+        &mut item.get,
+        &mut item.put,
+        &mut item.post,
+        &mut item.delete,
+        &mut item.options,
+        &mut item.head,
+        &mut item.patch,
+        &mut item.trace,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+// Document built from the inventory, keyed by the `DocInfo` it was built with.
+static AUTO_OPENAPI_CACHE: RwLock<Option<(DocInfo, Arc<openapi::OpenApi>)>> = RwLock::new(None);
+
+/// Return the auto-generated OpenAPI document, building it only once.
+///
+/// The inventory of `#[dy_api]` operations is fixed at link time, so the
+/// document is cached after the first call. A call with a different `DocInfo`
+/// rebuilds and replaces the cached document.
+pub fn cached_auto_openapi(info: DocInfo) -> Arc<openapi::OpenApi> {
+    if let Some((cached_info, doc)) = AUTO_OPENAPI_CACHE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        && *cached_info == info
+    {
+        return doc.clone();
+    }
+
+    let doc = Arc::new(build_auto_openapi(info.clone()));
+    *AUTO_OPENAPI_CACHE
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = Some((info, doc.clone()));
+    doc
+}
+
+/// Drop the cached document so the next `cached_auto_openapi` call rebuilds it.
+pub fn invalidate_openapi_cache() {
+    *AUTO_OPENAPI_CACHE
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Serialize the auto-generated document to `path` as pretty-printed JSON.
+///
+/// Run this from a small dump step (a test, or a `--dump-openapi` flag in your
+/// binary) and embed the result with [`crate::App::with_embedded_openapi`] to
+/// skip building the document at startup.
+pub fn write_auto_openapi(path: impl AsRef<std::path::Path>, info: DocInfo) -> std::io::Result<()> {
+    let json = cached_auto_openapi(info)
+        .to_pretty_json()
+        .map_err(std::io::Error::other)?;
+    std::fs::write(path, json)
+}
+
+/// The HTTP bearer (JWT) scheme registered as [`BEARER_AUTH_SCHEME`].
+pub(crate) fn bearer_security_scheme() -> SecurityScheme {
+    SecurityScheme::Http(
+        HttpBuilder::new()
+            .scheme(HttpAuthScheme::Bearer)
+            .bearer_format("JWT")
+            .build(),
+    )
+}
+
+/// `prefix` without a trailing slash and with a leading one, e.g. `/api/v1`;
+/// empty for the root.
+pub fn normalize_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("/{prefix}")
+    }
+}
+
+/// `doc` with every path moved below `prefix`, as when its routes are nested
+/// there.
+pub fn prefix_paths(mut doc: openapi::OpenApi, prefix: &str) -> openapi::OpenApi {
+    let prefix = normalize_prefix(prefix);
+    if prefix.is_empty() {
+        return doc;
+    }
+    doc.paths.paths = std::mem::take(&mut doc.paths.paths)
+        .into_iter()
+        .map(|(path, item)| match path.as_str() {
+            "/" | "" => (prefix.clone(), item),
+            _ => (format!("{prefix}{path}"), item),
+        })
+        .collect();
+    doc
+}
+
+/// Merge `others` into `base`, producing a single document.
+///
+/// Paths, operations, schemas, security schemes and tags missing from `base`
+/// are added. On conflicts (the same path and method, or the same component
+/// name) the entry already in `base` is kept, as is its `info`.
+pub fn merge_openapi(
+    mut base: openapi::OpenApi,
+    others: impl IntoIterator<Item = openapi::OpenApi>,
+) -> openapi::OpenApi {
+    for other in others {
+        base.merge(other);
+    }
+    base
+}
+
+/// A server listed in the document
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocServer {
+    pub url: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Contact details for the API
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocContact {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub url: Option<String>,
+}
+
+/// License the API is offered under
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocLicense {
+    pub name: String,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// A tag description; tags are listed in the order they are declared.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocTag {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Customizations applied to the served OpenAPI document.
+///
+/// Set them with [`crate::App::openapi`] or in the `[openapi]` config
+/// section; values set on the `App` win over configured ones.
+///
+/// ```toml
+/// [openapi]
+/// title = "Shop API"
+/// version = "2.1.0"
+/// security = ["bearerAuth"]
+/// servers = [{ url = "https://api.example.com" }]
+/// tags = [{ name = "Orders", description = "Order management" }, { name = "Users" }]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocSettings {
+    pub title: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub servers: Vec<DocServer>,
+    pub contact: Option<DocContact>,
+    pub license: Option<DocLicense>,
+    pub tags: Vec<DocTag>,
+    /// Security schemes required by every operation unless it says otherwise
+    pub security: Vec<String>,
+}
+
+impl DocSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Add a server URL, e.g. `https://api.example.com`
+    pub fn server(mut self, url: impl Into<String>, description: Option<&str>) -> Self {
+        self.servers.push(DocServer {
+            url: url.into(),
+            description: description.map(str::to_string),
+        });
+        self
+    }
+
+    pub fn contact(mut self, name: impl Into<String>, email: impl Into<String>) -> Self {
+        let contact = self.contact.get_or_insert_with(DocContact::default);
+        contact.name = Some(name.into());
+        contact.email = Some(email.into());
+        self
+    }
+
+    pub fn license(mut self, name: impl Into<String>, url: Option<&str>) -> Self {
+        self.license = Some(DocLicense {
+            name: name.into(),
+            url: url.map(str::to_string),
+        });
+        self
+    }
+
+    /// Describe a tag. Declared tags are listed first, in declaration order,
+    /// followed by any other tags in use.
+    pub fn tag(mut self, name: impl Into<String>, description: Option<&str>) -> Self {
+        self.tags.push(DocTag {
+            name: name.into(),
+            description: description.map(str::to_string),
+        });
+        self
+    }
+
+    /// Require `scheme` on every operation by default
+    pub fn security(mut self, scheme: impl Into<String>) -> Self {
+        self.security.push(scheme.into());
+        self
+    }
+
+    /// Layer `overrides` on top of these settings: its values replace single
+    /// values and extend lists.
+    pub fn merge(mut self, overrides: DocSettings) -> Self {
+        self.title = overrides.title.or(self.title);
+        self.version = overrides.version.or(self.version);
+        self.description = overrides.description.or(self.description);
+        self.contact = overrides.contact.or(self.contact);
+        self.license = overrides.license.or(self.license);
+        for server in overrides.servers {
+            if !self.servers.contains(&server) {
+                self.servers.push(server);
+            }
+        }
+        for tag in overrides.tags {
+            match self.tags.iter_mut().find(|t| t.name == tag.name) {
+                Some(existing) => {
+                    existing.description = tag.description.or(existing.description.take())
+                }
+                None => self.tags.push(tag),
+            }
+        }
+        for scheme in overrides.security {
+            if !self.security.contains(&scheme) {
+                self.security.push(scheme);
+            }
+        }
+        self
+    }
+
+    /// Apply the settings to `doc`
+    pub fn apply(&self, doc: &mut openapi::OpenApi) {
+        let info = &mut doc.info;
+        if let Some(title) = &self.title {
+            info.title = title.clone();
+        }
+        if let Some(version) = &self.version {
+            info.version = version.clone();
+        }
+        if let Some(description) = &self.description {
+            info.description = Some(description.clone());
+        }
+        if let Some(contact) = &self.contact {
+            info.contact = Some(
+                ContactBuilder::new()
+                    .name(contact.name.clone())
+                    .email(contact.email.clone())
+                    .url(contact.url.clone())
+                    .build(),
+            );
+        }
+        if let Some(license) = &self.license {
+            info.license = Some(
+                LicenseBuilder::new()
+                    .name(license.name.clone())
+                    .url(license.url.clone())
+                    .build(),
+            );
+        }
+
+        if !self.servers.is_empty() {
+            let servers = doc.servers.get_or_insert_with(Vec::new);
+            for server in &self.servers {
+                if !servers.iter().any(|s| s.url == server.url) {
+                    servers.push(
+                        ServerBuilder::new()
+                            .url(server.url.clone())
+                            .description(server.description.clone())
+                            .build(),
+                    );
+                }
+            }
+        }
+
+        if !self.tags.is_empty() {
+            doc.tags = Some(self.ordered_tags(doc));
+        }
+
+        if !self.security.is_empty() {
+            let security = doc.security.get_or_insert_with(Vec::new);
+            for scheme in &self.security {
+                let requirement = SecurityRequirement::new(scheme, std::iter::empty::<String>());
+                if !security.contains(&requirement) {
+                    security.push(requirement);
+                }
+            }
+            if self.security.iter().any(|s| s == BEARER_AUTH_SCHEME) {
+                let components = doc.components.get_or_insert_with(Default::default);
+                components
+                    .security_schemes
+                    .entry(BEARER_AUTH_SCHEME.to_string())
+                    .or_insert_with(bearer_security_scheme);
+            }
+        }
+    }
+
+    /// Declared tags first, then other documented tags, then tags only
+    /// found on operations (alphabetically).
+    fn ordered_tags(&self, doc: &openapi::OpenApi) -> Vec<Tag> {
+        let mut tags: Vec<Tag> = self
+            .tags
+            .iter()
+            .map(|tag| {
+                let existing = doc
+                    .tags
+                    .iter()
+                    .flatten()
+                    .find(|t| t.name == tag.name)
+                    .and_then(|t| t.description.clone());
+                TagBuilder::new()
+                    .name(tag.name.clone())
+                    .description(tag.description.clone().or(existing))
+                    .build()
+            })
+            .collect();
+
+        let mut seen: BTreeSet<String> = tags.iter().map(|t| t.name.clone()).collect();
+        for tag in doc.tags.iter().flatten() {
+            if seen.insert(tag.name.clone()) {
+                tags.push(tag.clone());
+            }
+        }
+        let used: BTreeSet<String> = doc
+            .paths
+            .paths
+            .values()
+            .flat_map(operations)
+            .flat_map(|op| op.tags.iter().flatten().cloned())
+            .collect();
+        for name in used {
+            if seen.insert(name.clone()) {
+                tags.push(Tag::new(name));
+            }
+        }
+        tags
+    }
+}
+
+/// Document for the `/health` and `/ready` endpoints served by
+/// auto-configured apps.
+pub fn health_openapi() -> openapi::OpenApi {
+    let string = |format: Option<KnownFormat>| {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .format(format.map(SchemaFormat::KnownFormat))
+    };
+    let body = ObjectBuilder::new()
+        .property("status", string(None))
+        .property("timestamp", string(Some(KnownFormat::DateTime)))
+        .required("status")
+        .required("timestamp");
+
+    let operation = OperationBuilder::new()
+        .operation_id(Some("health"))
+        .tag("Health")
+        .summary(Some("Health check"))
+        .response(
+            "200",
+            ResponseBuilder::new()
+                .description("Service is healthy")
+                .content(
+                    "application/json",
+                    content_for(
+                        "application/json",
+                        Some(RefOr::T(Schema::Object(body.build()))),
+                    ),
+                )
+                .build(),
+        )
+        .build();
+
+    let ready_body = |statuses: &[&str]| {
+        ObjectBuilder::new()
+            .property(
+                "status",
+                string(None).enum_values(Some(statuses.iter().copied())),
+            )
+            .required("status")
+            .build()
+    };
+    let ready_response = |description: &str, statuses: &[&str]| {
+        ResponseBuilder::new()
+            .description(description)
+            .content(
+                "application/json",
+                content_for(
+                    "application/json",
+                    Some(RefOr::T(Schema::Object(ready_body(statuses)))),
+                ),
+            )
+            .build()
+    };
+    let ready = OperationBuilder::new()
+        .operation_id(Some("ready"))
+        .tag("Health")
+        .summary(Some("Readiness check"))
+        .response("200", ready_response("Service is ready", &["ready"]))
+        .response(
+            "503",
+            ready_response(
+                "Service is still warming up or its database is unavailable",
+                &["warming_up", "database_unavailable"],
+            ),
+        )
+        .build();
+
+    OpenApiBuilder::new()
+        .paths(
+            PathsBuilder::new()
+                .path(
+                    "/health",
+                    PathItemBuilder::new()
+                        .operation(HttpMethod::Get, operation)
+                        .build(),
+                )
+                .path(
+                    crate::warmup::READY_PATH,
+                    PathItemBuilder::new()
+                        .operation(HttpMethod::Get, ready)
+                        .build(),
+                ),
+        )
+        .build()
+}
+
+/// Parse a previously generated OpenAPI JSON document.
+pub fn from_json(json: &str) -> Result<openapi::OpenApi, serde_json::Error> {
+    serde_json::from_str(json)
+}
+
+/// Render a document as YAML, keeping the key order of its JSON form.
+pub fn to_yaml(doc: &openapi::OpenApi) -> String {
+    fn convert(value: serde_json::Value) -> Yaml {
+        match value {
+            serde_json::Value::Null => Yaml::Null,
+            serde_json::Value::Bool(b) => Yaml::Boolean(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Yaml::Integer(i),
+                None => Yaml::Real(n.to_string()),
+            },
+            serde_json::Value::String(s) => Yaml::String(s),
+            serde_json::Value::Array(items) => {
+                Yaml::Array(items.into_iter().map(convert).collect())
+            }
+            serde_json::Value::Object(map) => Yaml::Hash(
+                map.into_iter()
+                    .map(|(key, value)| (Yaml::String(key), convert(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    let value = serde_json::to_value(doc).expect("OpenAPI documents serialize to JSON");
+    let mut out = String::new();
+    YamlEmitter::new(&mut out)
+        .dump(&convert(value))
+        .expect("writing to a String cannot fail");
+    out.push('\n');
+    out
+}
+
+/// Parse an OpenAPI YAML document, such as one written by [`to_yaml`].
+pub fn from_yaml(yaml: &str) -> Result<openapi::OpenApi, String> {
+    fn convert(yaml: Yaml) -> Result<serde_json::Value, String> {
+        Ok(match yaml {
+            Yaml::Null => serde_json::Value::Null,
+            Yaml::Boolean(b) => serde_json::Value::Bool(b),
+            Yaml::Integer(i) => serde_json::Value::from(i),
+            Yaml::Real(r) => r
+                .parse::<f64>()
+                .map(serde_json::Value::from)
+                .map_err(|_| format!("invalid number `{r}`"))?,
+            Yaml::String(s) => serde_json::Value::String(s),
+            Yaml::Array(items) => items
+                .into_iter()
+                .map(convert)
+                .collect::<Result<_, _>>()
+                .map(serde_json::Value::Array)?,
+            Yaml::Hash(map) => {
+                let mut object = serde_json::Map::new();
+                for (key, value) in map {
+                    let key = match key {
+                        Yaml::String(s) => s,
+                        Yaml::Integer(i) => i.to_string(),
+                        Yaml::Boolean(b) => b.to_string(),
+                        Yaml::Real(r) => r,
+                        other => return Err(format!("unsupported mapping key {other:?}")),
+                    };
+                    object.insert(key, convert(value)?);
+                }
+                serde_json::Value::Object(object)
+            }
+            Yaml::Alias(_) | Yaml::BadValue => {
+                return Err("unsupported YAML value".to_string());
+            }
+        })
+    }
+
+    let mut docs = YamlLoader::load_from_str(yaml).map_err(|err| err.to_string())?;
+    if docs.is_empty() {
+        return Err("empty YAML document".to_string());
+    }
+    let value = convert(docs.swap_remove(0))?;
+    serde_json::from_value(value).map_err(|err| err.to_string())
+}
+
+fn uses_scheme(operation: &Operation, scheme: &str) -> bool {
+    // SecurityRequirement keeps its map private; inspect the serialized form.
+    operation.security.iter().flatten().any(|requirement| {
+        serde_json::to_value(requirement)
+            .ok()
+            .and_then(|value| value.as_object().map(|map| map.contains_key(scheme)))
+            .unwrap_or(false)
+    })
+}
+
 /// Returns true if any routes have been documented via `#[dy_api]`.
 pub fn has_auto_operations() -> bool {
-    inventory::iter::<AutoOperation>()
-        .into_iter()
-        .next()
-        .is_some()
+    inventory::iter::<AutoOperation>().next().is_some()
 }
 
 // Re-export inventory so the macro expansion can reference it without adding
 // an explicit dependency in downstream crates.
 pub use inventory;
+
+/// Support code for `#[dy_api]` expansions. Not public API.
+///
+/// Types inferred from handler signatures may not implement the utoipa
+/// traits, so the macro calls these through autoref specialization: the
+/// richest available impl is picked for the concrete type, falling back to a
+/// no-op instead of a compile error.
+#[doc(hidden)]
+pub mod __private {
+    use std::marker::PhantomData;
+
+    use utoipa::openapi::{
+        RefOr, Required,
+        path::{Parameter, ParameterBuilder, ParameterIn},
+        schema::{KnownFormat, ObjectBuilder, Schema, SchemaFormat, Type},
+    };
+    use utoipa::{IntoParams, PartialSchema, ToSchema};
+
+    pub struct Probe<T: ?Sized>(PhantomData<T>);
+
+    impl<T: ?Sized> Probe<T> {
+        pub const fn new() -> Self {
+            Probe(PhantomData)
+        }
+    }
+
+    impl<T: ?Sized> Default for Probe<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<T: ?Sized> Clone for Probe<T> {
+        fn clone(&self) -> Self {
+            *self
+        }
+    }
+
+    impl<T: ?Sized> Copy for Probe<T> {}
+
+    pub type Schemas = Vec<(String, RefOr<Schema>)>;
+
+    /// A required path parameter; untyped parameters are documented as strings.
+    pub fn path_parameter(name: &str, schema: Option<RefOr<Schema>>) -> Parameter {
+        let schema = schema.unwrap_or_else(|| {
+            RefOr::T(Schema::Object(
+                ObjectBuilder::new().schema_type(Type::String).build(),
+            ))
+        });
+        ParameterBuilder::new()
+            .name(name)
+            .parameter_in(ParameterIn::Path)
+            .required(Required::True)
+            .schema(Some(schema))
+            .build()
+    }
+
+    /// Schemas for types utoipa only understands inside its derive macros.
+    pub fn known_schema(type_name: &str) -> Option<RefOr<Schema>> {
+        let format = match type_name {
+            "Uuid" => KnownFormat::Uuid,
+            "DateTime" | "NaiveDateTime" => KnownFormat::DateTime,
+            "NaiveDate" => KnownFormat::Date,
+            _ => return None,
+        };
+        Some(RefOr::T(Schema::Object(
+            ObjectBuilder::new()
+                .schema_type(Type::String)
+                .format(Some(SchemaFormat::KnownFormat(format)))
+                .build(),
+        )))
+    }
+
+    // `(&Probe::<T>::new()).schema()`
+    pub trait SchemaViaPartial {
+        fn schema(self) -> Option<RefOr<Schema>>;
+    }
+    impl<T: PartialSchema + ?Sized> SchemaViaPartial for &Probe<T> {
+        fn schema(self) -> Option<RefOr<Schema>> {
+            Some(T::schema())
+        }
+    }
+    pub trait SchemaFallback {
+        fn schema(self) -> Option<RefOr<Schema>>;
+    }
+    impl<T: ?Sized> SchemaFallback for Probe<T> {
+        fn schema(self) -> Option<RefOr<Schema>> {
+            None
+        }
+    }
+
+    // `(&Probe::<T>::new()).register(acc)` registers `T` as a component;
+    // `register_nested` only registers the components `T` refers to.
+    pub trait RegisterViaToSchema {
+        fn register(self, acc: &mut Schemas);
+        fn register_nested(self, acc: &mut Schemas);
+    }
+    impl<T: ToSchema + ?Sized> RegisterViaToSchema for &Probe<T> {
+        fn register(self, acc: &mut Schemas) {
+            acc.push((T::name().into(), T::schema()));
+            T::schemas(acc);
+        }
+        fn register_nested(self, acc: &mut Schemas) {
+            T::schemas(acc);
+        }
+    }
+    pub trait RegisterFallback {
+        fn register(self, acc: &mut Schemas);
+        fn register_nested(self, acc: &mut Schemas);
+    }
+    impl<T: ?Sized> RegisterFallback for Probe<T> {
+        fn register(self, _acc: &mut Schemas) {}
+        fn register_nested(self, _acc: &mut Schemas) {}
+    }
+
+    // `(&&Probe::<T>::new()).path_params(names)` for `Path<T>`
+    pub trait PathParamsViaIntoParams {
+        fn path_params(self, names: &[&str]) -> Vec<Parameter>;
+    }
+    impl<T: IntoParams> PathParamsViaIntoParams for &&Probe<T> {
+        fn path_params(self, _names: &[&str]) -> Vec<Parameter> {
+            T::into_params(|| Some(ParameterIn::Path))
+        }
+    }
+    pub trait PathParamsViaSchema {
+        fn path_params(self, names: &[&str]) -> Vec<Parameter>;
+    }
+    impl<T: PartialSchema> PathParamsViaSchema for &Probe<T> {
+        fn path_params(self, names: &[&str]) -> Vec<Parameter> {
+            match names {
+                [name] => vec![path_parameter(name, Some(T::schema()))],
+                _ => names.iter().map(|n| path_parameter(n, None)).collect(),
+            }
+        }
+    }
+    pub trait PathParamsFallback {
+        fn path_params(self, names: &[&str]) -> Vec<Parameter>;
+    }
+    impl<T> PathParamsFallback for Probe<T> {
+        fn path_params(self, names: &[&str]) -> Vec<Parameter> {
+            names.iter().map(|n| path_parameter(n, None)).collect()
+        }
+    }
+
+    // `(&Probe::<T>::new()).query_params()` for `Query<T>`
+    pub trait QueryParamsViaIntoParams {
+        fn query_params(self) -> Vec<Parameter>;
+    }
+    impl<T: IntoParams> QueryParamsViaIntoParams for &Probe<T> {
+        fn query_params(self) -> Vec<Parameter> {
+            T::into_params(|| Some(ParameterIn::Query))
+        }
+    }
+    pub trait QueryParamsFallback {
+        fn query_params(self) -> Vec<Parameter>;
+    }
+    impl<T> QueryParamsFallback for Probe<T> {
+        fn query_params(self) -> Vec<Parameter> {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_document_is_reused_until_invalidated() {
+        let first = cached_auto_openapi(DocInfo::default());
+        let second = cached_auto_openapi(DocInfo::default());
+        assert!(Arc::ptr_eq(&first, &second));
+
+        invalidate_openapi_cache();
+        let third = cached_auto_openapi(DocInfo::default());
+        assert!(!Arc::ptr_eq(&first, &third));
+    }
+
+    #[test]
+    fn prefixed_documents_move_every_path() {
+        let doc = OpenApiBuilder::new()
+            .paths(
+                PathsBuilder::new()
+                    .path("/", PathItem::new(HttpMethod::Get, Operation::new()))
+                    .path(
+                        "/auth/login",
+                        PathItem::new(HttpMethod::Post, Operation::new()),
+                    ),
+            )
+            .build();
+        let doc = prefix_paths(doc, "api/v1/");
+        let paths: Vec<&str> = doc.paths.paths.keys().map(String::as_str).collect();
+        assert_eq!(paths, ["/api/v1", "/api/v1/auth/login"]);
+        assert_eq!(normalize_prefix("/"), "");
+    }
+
+    #[test]
+    fn versioned_paths_are_tagged_and_deprecated() {
+        let mut doc = OpenApiBuilder::new()
+            .paths(
+                PathsBuilder::new()
+                    .path(
+                        "/v1/users",
+                        PathItem::new(HttpMethod::Get, Operation::new()),
+                    )
+                    .path(
+                        "/v10/users",
+                        PathItem::new(HttpMethod::Get, Operation::new()),
+                    )
+                    .path("/health", PathItem::new(HttpMethod::Get, Operation::new())),
+            )
+            .build();
+        set_path_version(&mut doc, "v1", true);
+
+        let v1 = doc.paths.paths["/v1/users"].get.as_ref().unwrap();
+        assert_eq!(operation_version(v1), Some("v1"));
+        assert!(matches!(v1.deprecated, Some(openapi::Deprecated::True)));
+        for path in ["/v10/users", "/health"] {
+            let operation = doc.paths.paths[path].get.as_ref().unwrap();
+            assert_eq!(operation_version(operation), None, "{path}");
+            assert!(operation.deprecated.is_none(), "{path}");
+        }
+    }
+
+    #[test]
+    fn filter_keeps_matching_operations() {
+        use utoipa::openapi::path::OperationBuilder;
+
+        let doc = OpenApiBuilder::new()
+            .paths(
+                PathsBuilder::new()
+                    .path(
+                        "/users",
+                        PathItemBuilder::new()
+                            .operation(
+                                HttpMethod::Get,
+                                OperationBuilder::new().tag("Users").build(),
+                            )
+                            .operation(
+                                HttpMethod::Delete,
+                                OperationBuilder::new().tag("Internal").build(),
+                            )
+                            .build(),
+                    )
+                    .path(
+                        "/orders",
+                        PathItem::new(
+                            HttpMethod::Get,
+                            OperationBuilder::new().tag("Orders").build(),
+                        ),
+                    ),
+            )
+            .build();
+
+        let public = filter_openapi(&doc, &DocFilter::new().exclude_tag("Internal"));
+        let users = &public.paths.paths["/users"];
+        assert!(users.get.is_some());
+        assert!(users.delete.is_none());
+        assert!(public.paths.paths.contains_key("/orders"));
+
+        let orders = filter_openapi(&doc, &DocFilter::new().tag("Orders"));
+        assert_eq!(
+            orders.paths.paths.keys().collect::<Vec<_>>(),
+            vec!["/orders"]
+        );
+
+        let prefixed = filter_openapi(&doc, &DocFilter::new().path_prefix("/users"));
+        assert_eq!(prefixed.paths.paths.len(), 1);
+    }
+
+    #[test]
+    fn combined_filters_intersect_path_prefixes() {
+        let admin = DocFilter::new().path_prefix("/admin");
+        let and = |prefix: &str| admin.clone().and(DocFilter::new().path_prefix(prefix));
+        let prefix = |filter: Option<DocFilter>| filter.unwrap().path_prefix;
+
+        // A broader prefix can't widen the view
+        assert_eq!(prefix(and("/")).as_deref(), Some("/admin"));
+        assert_eq!(prefix(and("/admin/users")).as_deref(), Some("/admin/users"));
+        assert_eq!(
+            prefix(admin.clone().and(DocFilter::new())).as_deref(),
+            Some("/admin")
+        );
+        assert_eq!(
+            prefix(DocFilter::new().and(admin.clone())).as_deref(),
+            Some("/admin")
+        );
+
+        assert_eq!(and("/users"), None);
+        assert_eq!(and("/admins"), None);
+    }
+
+    #[test]
+    fn combined_filters_intersect_tags_and_versions() {
+        let users = DocFilter::new().tag("Users").tag("Orders").version("v1");
+        let both = users
+            .clone()
+            .and(DocFilter::new().tag("Orders").exclude_tag("Internal"))
+            .unwrap();
+        assert_eq!(both.tags, ["Orders"]);
+        assert_eq!(both.versions, ["v1"]);
+        assert_eq!(both.exclude_tags, ["Internal"]);
+
+        assert_eq!(users.clone().and(DocFilter::new().tag("Billing")), None);
+        assert_eq!(users.and(DocFilter::new().version("v2")), None);
+    }
+
+    #[test]
+    fn path_prefixes_match_whole_segments() {
+        let users = DocFilter::new().path_prefix("/users");
+        assert!(users.keeps_path("/users"));
+        assert!(users.keeps_path("/users/{id}"));
+        assert!(!users.keeps_path("/users-admin"));
+        assert!(!users.keeps_path("/user"));
+
+        let slash = DocFilter::new().path_prefix("/users/");
+        assert!(slash.keeps_path("/users/{id}"));
+        assert!(!slash.keeps_path("/usersx"));
+        assert!(DocFilter::new().path_prefix("/").keeps_path("/orders"));
+    }
+
+    #[test]
+    fn detects_security_scheme_usage() {
+        use utoipa::openapi::{path::OperationBuilder, security::SecurityRequirement};
+
+        let secured = OperationBuilder::new()
+            .security(SecurityRequirement::new(
+                BEARER_AUTH_SCHEME,
+                [] as [&str; 0],
+            ))
+            .build();
+        assert!(uses_scheme(&secured, BEARER_AUTH_SCHEME));
+        assert!(!uses_scheme(&secured, "apiKey"));
+        assert!(!uses_scheme(
+            &OperationBuilder::new().build(),
+            BEARER_AUTH_SCHEME
+        ));
+    }
+
+    #[test]
+    fn written_document_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("dy-rs-openapi-{}.json", uuid::Uuid::new_v4()));
+        write_auto_openapi(&path, DocInfo::default()).unwrap();
+
+        let json = std::fs::read_to_string(&path).unwrap();
+        let doc = from_json(&json).unwrap();
+        assert_eq!(doc.info.title, "dy-rs API");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn yaml_round_trips() {
+        let doc = merge_openapi(build_auto_openapi(DocInfo::default()), [health_openapi()]);
+        let parsed = from_yaml(&to_yaml(&doc)).unwrap();
+        assert_eq!(
+            serde_json::to_value(parsed).unwrap(),
+            serde_json::to_value(doc).unwrap()
+        );
+        assert!(from_yaml("").is_err());
+    }
+
+    #[test]
+    fn merge_keeps_base_info_and_adds_missing_operations() {
+        use utoipa::openapi::path::OperationBuilder;
+
+        let doc = |title: &str, method: HttpMethod, id: &str| {
+            OpenApiBuilder::new()
+                .info(InfoBuilder::new().title(title).version("1").build())
+                .paths(
+                    PathsBuilder::new().path(
+                        "/users",
+                        PathItemBuilder::new()
+                            .operation(
+                                method,
+                                OperationBuilder::new().operation_id(Some(id)).build(),
+                            )
+                            .build(),
+                    ),
+                )
+                .build()
+        };
+
+        let merged = merge_openapi(
+            doc("Manual", HttpMethod::Get, "manualList"),
+            [
+                doc("Auto", HttpMethod::Get, "autoList"),
+                doc("Auto", HttpMethod::Post, "autoCreate"),
+                health_openapi(),
+            ],
+        );
+
+        assert_eq!(merged.info.title, "Manual");
+        let users = &merged.paths.paths["/users"];
+        assert_eq!(
+            users.get.as_ref().unwrap().operation_id.as_deref(),
+            Some("manualList")
+        );
+        assert_eq!(
+            users.post.as_ref().unwrap().operation_id.as_deref(),
+            Some("autoCreate")
+        );
+        assert!(merged.paths.paths["/health"].get.is_some());
+    }
+
+    #[test]
+    fn doc_settings_customize_the_document() {
+        let mut doc = health_openapi();
+        let tagged = OperationBuilder::new().tag("Users").tag("Admin").build();
+        doc.paths
+            .paths
+            .insert("/users".into(), PathItem::new(HttpMethod::Get, tagged));
+
+        let configured = DocSettings::new()
+            .title("Configured")
+            .version("1.0.0")
+            .tag("Users", Some("From config"));
+        let settings = configured.merge(
+            DocSettings::new()
+                .title("Shop API")
+                .server("https://api.example.com", Some("Production"))
+                .contact("Ops", "ops@example.com")
+                .license("MIT", None)
+                .tag("Admin", Some("Back office"))
+                .security(BEARER_AUTH_SCHEME),
+        );
+        settings.apply(&mut doc);
+
+        assert_eq!(doc.info.title, "Shop API");
+        assert_eq!(doc.info.version, "1.0.0");
+        assert_eq!(
+            doc.info.contact.as_ref().unwrap().email.as_deref(),
+            Some("ops@example.com")
+        );
+        assert_eq!(doc.info.license.as_ref().unwrap().name, "MIT");
+        assert_eq!(
+            doc.servers.as_ref().unwrap()[0].url,
+            "https://api.example.com"
+        );
+
+        let tags: Vec<_> = doc
+            .tags
+            .iter()
+            .flatten()
+            .map(|t| (t.name.as_str(), t.description.as_deref()))
+            .collect();
+        assert_eq!(
+            tags,
+            [
+                ("Users", Some("From config")),
+                ("Admin", Some("Back office")),
+                ("Health", None),
+            ]
+        );
+
+        let security = serde_json::to_value(doc.security.as_ref().unwrap()).unwrap();
+        assert_eq!(security, serde_json::json!([{ "bearerAuth": [] }]));
+        assert!(
+            doc.components
+                .as_ref()
+                .unwrap()
+                .security_schemes
+                .contains_key(BEARER_AUTH_SCHEME)
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! Pagination
+//!
+//! [`Pagination`] reads either `page`/`per_page` or `limit`/`offset` from the
+//! query string, and [`Page`] is the matching response envelope, with the
+//! total count and links to the neighbouring pages:
+//!
+//! ```rust,ignore
+//! #[dy_api(method = get, path = "/users")]
+//! async fn list_users(State(db): State<Database>, pagination: Pagination) -> ApiResult<Page<User>> {
+//!     let (users, total) = db.users(pagination.offset, pagination.limit).await?;
+//!     Ok(Json(Page::new(users, total, &pagination)))
+//! }
+//! ```
+//!
+//! Page sizes default to 20 and are capped at 100; change both in the
+//! `[pagination]` configuration section, or for a group of routes with an
+//! `Extension(PaginationConfig { .. })` layer.
+
+use axum::{
+    Json,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::ApiError;
+
+/// Page size defaults and caps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaginationConfig {
+    /// Page size when the request doesn't give one
+    pub default_per_page: usize,
+    /// Larger requested page sizes are reduced to this
+    pub max_per_page: usize,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        Self {
+            default_per_page: 20,
+            max_per_page: 100,
+        }
+    }
+}
+
+/// Query parameters read by [`Pagination`]
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaginationQuery {
+    /// Page number, starting at 1
+    pub page: Option<usize>,
+    /// Items per page
+    pub per_page: Option<usize>,
+    /// Items to return, as an alternative to `per_page`
+    pub limit: Option<usize>,
+    /// Items to skip, as an alternative to `page`
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Page,
+    Offset,
+}
+
+/// Extractor for the requested slice of a list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pagination {
+    /// Items to skip
+    pub offset: usize,
+    /// Items to return
+    pub limit: usize,
+    style: Style,
+    path: String,
+    /// Query parameters other than the pagination ones, as received
+    other_params: Vec<String>,
+}
+
+impl Pagination {
+    /// Page number, starting at 1
+    pub fn page(&self) -> usize {
+        self.offset / self.limit + 1
+    }
+
+    fn from_query(
+        query: &PaginationQuery,
+        config: &PaginationConfig,
+        path: &str,
+        raw_query: Option<&str>,
+    ) -> Result<Self, ApiError> {
+        let by_page = query.page.is_some() || query.per_page.is_some();
+        let by_offset = query.limit.is_some() || query.offset.is_some();
+        if by_page && by_offset {
+            return Err(ApiError::BadRequest(
+                "Use either page/per_page or limit/offset, not both".to_string(),
+            ));
+        }
+
+        let size = query
+            .per_page
+            .or(query.limit)
+            .unwrap_or(config.default_per_page);
+        if size == 0 {
+            return Err(ApiError::BadRequest(
+                "Page size must be at least 1".to_string(),
+            ));
+        }
+        let limit = size.min(config.max_per_page.max(1));
+
+        let offset = match query.page {
+            Some(0) => {
+                return Err(ApiError::BadRequest("page must be at least 1".to_string()));
+            }
+            Some(page) => (page - 1).saturating_mul(limit),
+            None => query.offset.unwrap_or(0),
+        };
+
+        let other_params = raw_query
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| {
+                let key = pair.split('=').next().unwrap_or_default();
+                !pair.is_empty() && !matches!(key, "page" | "per_page" | "limit" | "offset")
+            })
+            .map(str::to_string)
+            .collect();
+
+        Ok(Self {
+            offset,
+            limit,
+            style: if by_offset {
+                Style::Offset
+            } else {
+                Style::Page
+            },
+            path: path.to_string(),
+            other_params,
+        })
+    }
+
+    /// Link to the same request starting at `offset`, in the style the
+    /// client used
+    fn link(&self, offset: usize) -> String {
+        let mut params = self.other_params.clone();
+        match self.style {
+            Style::Page => {
+                params.push(format!("page={}", offset / self.limit + 1));
+                params.push(format!("per_page={}", self.limit));
+            }
+            Style::Offset => {
+                params.push(format!("limit={}", self.limit));
+                params.push(format!("offset={offset}"));
+            }
+        }
+        format!("{}?{}", self.path, params.join("&"))
+    }
+}
+
+impl<S> FromRequestParts<S> for Pagination
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<PaginationQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+        let config = parts
+            .extensions
+            .get::<PaginationConfig>()
+            .copied()
+            .unwrap_or_default();
+
+        Self::from_query(&query, &config, parts.uri.path(), parts.uri.query())
+    }
+}
+
+/// Links to the neighbouring pages, absent at either end
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PageLinks {
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+/// One page of a list
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Items across all pages
+    pub total: u64,
+    /// Page number, starting at 1
+    pub page: usize,
+    pub per_page: usize,
+    pub links: PageLinks,
+}
+
+impl<T> Page<T> {
+    /// The page `pagination` asked for, out of `total` items
+    pub fn new(items: Vec<T>, total: u64, pagination: &Pagination) -> Self {
+        let end = pagination.offset.saturating_add(pagination.limit);
+        let links = PageLinks {
+            next: ((end as u64) < total).then(|| pagination.link(end)),
+            prev: (pagination.offset > 0)
+                .then(|| pagination.link(pagination.offset.saturating_sub(pagination.limit))),
+        };
+
+        Self {
+            items,
+            total,
+            page: pagination.page(),
+            per_page: pagination.limit,
+            links,
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Page<T> {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paginate(query: &str, config: &PaginationConfig) -> Result<Pagination, ApiError> {
+        let uri: axum::http::Uri = format!("/users?{query}").parse().unwrap();
+        let Query(parsed) = Query::<PaginationQuery>::try_from_uri(&uri).unwrap();
+        Pagination::from_query(&parsed, config, "/users", Some(query))
+    }
+
+    #[test]
+    fn page_and_offset_styles_resolve_to_the_same_slice() {
+        let config = PaginationConfig::default();
+        let by_page = paginate("page=3&per_page=10", &config).unwrap();
+        let by_offset = paginate("limit=10&offset=20", &config).unwrap();
+
+        assert_eq!((by_page.offset, by_page.limit), (20, 10));
+        assert_eq!((by_offset.offset, by_offset.limit), (20, 10));
+        assert_eq!(by_offset.page(), 3);
+    }
+
+    #[test]
+    fn sizes_default_and_are_capped() {
+        let config = PaginationConfig {
+            default_per_page: 5,
+            max_per_page: 50,
+        };
+        assert_eq!(paginate("", &config).unwrap().limit, 5);
+        assert_eq!(paginate("per_page=500", &config).unwrap().limit, 50);
+        assert!(paginate("page=0", &config).is_err());
+        assert!(paginate("limit=0", &config).is_err());
+        assert!(paginate("page=2&offset=10", &config).is_err());
+    }
+
+    #[test]
+    fn links_keep_other_params_and_the_clients_style() {
+        let config = PaginationConfig::default();
+        let pagination = paginate("status=active&page=2&per_page=10", &config).unwrap();
+        let page = Page::new(vec![1, 2, 3], 35, &pagination);
+
+        assert_eq!(
+            page.links.next.as_deref(),
+            Some("/users?status=active&page=3&per_page=10")
+        );
+        assert_eq!(
+            page.links.prev.as_deref(),
+            Some("/users?status=active&page=1&per_page=10")
+        );
+
+        let pagination = paginate("limit=10&offset=30", &config).unwrap();
+        let page = Page::new(vec![1; 5], 35, &pagination);
+        assert_eq!(page.links.next, None);
+        assert_eq!(
+            page.links.prev.as_deref(),
+            Some("/users?limit=10&offset=20")
+        );
+    }
+}
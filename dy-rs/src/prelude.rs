@@ -4,14 +4,23 @@
 
 pub use crate::{
     app::App,
-    error::{ApiError, ApiResult},
-    extractors::ValidatedJson,
+    client_ip::ClientIp,
+    db::Db,
+    error::{ApiError, ApiErrorKind, ApiResult},
+    etag::{ETag, IfMatch},
+    extractors::{Path, ValidatedForm, ValidatedJson, ValidatedPath},
+    i18n::Locale,
+    pagination::{Page, Pagination},
+    proxy::RequestOrigin,
+    resource::{CrudService, Resource},
+    tasks::TaskScope,
+    versioning::{ApiVersion, Version},
 };
 
 // Re-export commonly used types from dependencies
 pub use axum::{
     Router,
-    extract::{Extension, Path, Query, State},
+    extract::{Extension, Query, State},
     response::Json,
     routing::{delete, get, patch, post, put},
 };
@@ -22,8 +31,9 @@ pub use validator::Validate;
 pub use chrono::{DateTime, Utc};
 pub use uuid::Uuid;
 
-pub use crate::openapi::DocInfo;
-pub use dy_rs_macros::dy_api;
+pub use crate::docs::{DocsUi, SpecPaths};
+pub use crate::openapi::{DocFilter, DocInfo, DocSettings};
+pub use dy_rs_macros::{dy_api, dy_controller};
 pub use utoipa::{OpenApi, ToSchema};
 
 // Auth re-exports (when auth feature is enabled)
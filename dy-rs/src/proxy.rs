@@ -0,0 +1,372 @@
+//! Requests as sent by the client, behind proxies
+//!
+//! Behind a load balancer or reverse proxy the TCP peer, scheme and `Host`
+//! are the proxy's. [`ProxyLayer`] reads what the client sent from the
+//! forwarding headers, only when the peer is a trusted proxy and only from
+//! the headers listed, and makes it the request's [`RequestOrigin`]:
+//!
+//! ```toml
+//! [server.proxy]
+//! trusted_proxies = ["10.0.0.0/8", "127.0.0.1"]
+//! # default: all of them
+//! headers = ["x-forwarded-for", "x-forwarded-proto"]
+//! ```
+//!
+//! The origin gives [`ClientIp`](crate::client_ip::ClientIp) its address,
+//! the request log span its `client_ip`, subdomain tenants their host, and
+//! handlers the absolute URLs of redirects and links:
+//!
+//! ```rust,ignore
+//! async fn moved(origin: RequestOrigin) -> Redirect {
+//!     Redirect::permanent(&origin.url("/v2/orders"))
+//! }
+//! ```
+//!
+//! This is the only place proxies are trusted. The deprecated
+//! `[client_ip] trusted_proxies` are moved here when the config loads.
+
+use std::{net::IpAddr, net::SocketAddr, task};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request},
+    http::{HeaderMap, Uri, header::HOST, request::Parts},
+};
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+
+use crate::client_ip::{self, ProxyRange};
+
+/// A forwarding header proxies set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForwardedHeader {
+    /// `Forwarded`, with `for`, `proto` and `host` (RFC 7239)
+    Forwarded,
+    XForwardedFor,
+    XForwardedProto,
+    XForwardedHost,
+    XRealIp,
+}
+
+impl ForwardedHeader {
+    pub const ALL: &[Self] = &[
+        Self::Forwarded,
+        Self::XForwardedFor,
+        Self::XForwardedProto,
+        Self::XForwardedHost,
+        Self::XRealIp,
+    ];
+}
+
+/// Trusted proxies, the `[server.proxy]` config section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Addresses or CIDR ranges of trusted proxies, e.g. `10.0.0.0/8`
+    pub trusted_proxies: Vec<ProxyRange>,
+    /// Headers read from trusted proxies
+    pub headers: Vec<ForwardedHeader>,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            trusted_proxies: Vec::new(),
+            headers: ForwardedHeader::ALL.to_vec(),
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// Whether `ip` belongs to a trusted proxy
+    pub fn trusts(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|range| range.contains(ip))
+    }
+
+    fn honors(&self, header: ForwardedHeader) -> bool {
+        self.headers.contains(&header)
+    }
+
+    /// The client address for a request from `peer` with `headers`
+    ///
+    /// Proxies append the address they received a request from, so the
+    /// forwarded chain is walked from the right, skipping trusted proxies.
+    /// `Forwarded` is preferred over `X-Forwarded-For`, and `X-Real-IP` is
+    /// used when neither is present; headers not listed are ignored.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        client_ip::resolve(|ip| self.trusts(ip), &self.headers, peer, headers)
+    }
+
+    /// What the client sent a request from `peer` to, as far as trusted
+    /// proxies tell
+    pub fn origin(&self, peer: Option<IpAddr>, uri: &Uri, headers: &HeaderMap) -> RequestOrigin {
+        let mut origin = RequestOrigin::direct(peer, uri, headers);
+        let Some(peer) = peer.filter(|peer| self.trusts(*peer)) else {
+            return origin;
+        };
+        origin.client_ip = Some(self.client_ip(peer, headers));
+        // The values of the nearest proxy, which is trusted
+        let forwarded = if self.honors(ForwardedHeader::Forwarded) {
+            last_forwarded(headers)
+        } else {
+            ForwardedElement::default()
+        };
+        let scheme = forwarded.proto.or_else(|| {
+            self.honors(ForwardedHeader::XForwardedProto)
+                .then(|| last_value(headers, "x-forwarded-proto"))
+                .flatten()
+        });
+        if let Some(scheme) = scheme.filter(|s| s == "http" || s == "https") {
+            origin.scheme = scheme;
+        }
+        let host = forwarded.host.or_else(|| {
+            self.honors(ForwardedHeader::XForwardedHost)
+                .then(|| last_value(headers, "x-forwarded-host"))
+                .flatten()
+        });
+        if let Some(host) = host {
+            origin.host = Some(host);
+        }
+        origin
+    }
+}
+
+#[derive(Debug, Default)]
+struct ForwardedElement {
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// `proto` and `host` of the last `Forwarded` element
+fn last_forwarded(headers: &HeaderMap) -> ForwardedElement {
+    let Some(element) = headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .next_back()
+    else {
+        return ForwardedElement::default();
+    };
+    let mut parsed = ForwardedElement::default();
+    for pair in element.split(';') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        match key.trim().to_ascii_lowercase().as_str() {
+            "proto" => parsed.proto = Some(value.to_ascii_lowercase()),
+            "host" => parsed.host = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    parsed
+}
+
+/// Last value of a comma-separated header
+fn last_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .rfind(|value| !value.is_empty())
+        .map(str::to_ascii_lowercase)
+}
+
+/// The client, scheme and host of a request as the client sent it
+///
+/// Extracting it never fails: without [`ProxyLayer`] it's the request as
+/// received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestOrigin {
+    /// The client's address, when the server was started with connect info
+    pub client_ip: Option<IpAddr>,
+    /// `http` or `https`
+    pub scheme: String,
+    /// Host and port, if any, the client asked for
+    pub host: Option<String>,
+}
+
+impl RequestOrigin {
+    /// The request as received from `peer`
+    fn direct(peer: Option<IpAddr>, uri: &Uri, headers: &HeaderMap) -> Self {
+        Self {
+            client_ip: peer,
+            scheme: uri.scheme_str().unwrap_or("http").to_string(),
+            host: uri.authority().map(|a| a.to_string()).or_else(|| {
+                headers
+                    .get(HOST)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            }),
+        }
+    }
+
+    pub fn is_https(&self) -> bool {
+        self.scheme == "https"
+    }
+
+    /// The host without its port
+    pub fn hostname(&self) -> Option<&str> {
+        let host = self.host.as_deref()?;
+        Some(match host.rsplit_once(':') {
+            Some((name, port)) if !port.contains(']') => name,
+            _ => host,
+        })
+    }
+
+    /// Absolute URL of `path` on this origin, or `path` itself without a host
+    pub fn url(&self, path: &str) -> String {
+        match &self.host {
+            Some(host) => format!("{}://{host}{path}", self.scheme),
+            None => path.to_string(),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for RequestOrigin
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_else(|| {
+            RequestOrigin::direct(peer(&parts.extensions), &parts.uri, &parts.headers)
+        }))
+    }
+}
+
+fn peer(extensions: &axum::http::Extensions) -> Option<IpAddr> {
+    match extensions.get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => Some(addr.ip()),
+        // A process on this host, such as a sidecar proxy
+        None if crate::listen::is_unix_peer(extensions) => Some(IpAddr::from([127, 0, 0, 1])),
+        None => None,
+    }
+}
+
+/// Gives every request its [`RequestOrigin`]; see the [module docs](self)
+#[derive(Debug, Clone, Default)]
+pub struct ProxyLayer {
+    config: std::sync::Arc<ProxyConfig>,
+}
+
+impl ProxyLayer {
+    pub fn new(config: ProxyConfig) -> Self {
+        Self {
+            config: std::sync::Arc::new(config),
+        }
+    }
+}
+
+impl<S> Layer<S> for ProxyLayer {
+    type Service = ProxyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ProxyService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Service produced by [`ProxyLayer`]
+#[derive(Clone)]
+pub struct ProxyService<S> {
+    inner: S,
+    config: std::sync::Arc<ProxyConfig>,
+}
+
+impl<S> Service<Request> for ProxyService<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let peer = peer(request.extensions());
+        let origin = self.config.origin(peer, request.uri(), request.headers());
+        request.extensions_mut().insert(origin);
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn config(honored: &[ForwardedHeader]) -> ProxyConfig {
+        ProxyConfig {
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+            headers: honored.to_vec(),
+        }
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn trusted_proxies_set_the_client_scheme_and_host() {
+        let headers = headers(&[
+            ("host", "app.internal:8080"),
+            ("x-forwarded-for", "198.51.100.7"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "api.example.com"),
+        ]);
+        let uri = Uri::from_static("/orders");
+
+        let origin = config(ForwardedHeader::ALL).origin(Some(ip("10.0.0.2")), &uri, &headers);
+        assert_eq!(origin.client_ip, Some(ip("198.51.100.7")));
+        assert!(origin.is_https());
+        assert_eq!(origin.url("/v2"), "https://api.example.com/v2");
+
+        let origin = config(ForwardedHeader::ALL).origin(Some(ip("203.0.113.9")), &uri, &headers);
+        assert_eq!(origin.client_ip, Some(ip("203.0.113.9")));
+        assert_eq!(origin.url("/v2"), "http://app.internal:8080/v2");
+        assert_eq!(origin.hostname(), Some("app.internal"));
+    }
+
+    #[test]
+    fn only_the_listed_headers_are_read() {
+        let headers = headers(&[
+            ("host", "app.internal"),
+            ("x-forwarded-for", "198.51.100.7"),
+            ("x-forwarded-host", "evil.example.com"),
+            (
+                "forwarded",
+                r#"for=192.0.2.1;proto=https;host="api.example.com""#,
+            ),
+        ]);
+        let uri = Uri::from_static("/");
+
+        let origin =
+            config(&[ForwardedHeader::XForwardedFor]).origin(Some(ip("10.0.0.2")), &uri, &headers);
+        assert_eq!(origin.client_ip, Some(ip("198.51.100.7")));
+        assert_eq!(origin.host.as_deref(), Some("app.internal"));
+        assert_eq!(origin.scheme, "http");
+
+        let origin =
+            config(&[ForwardedHeader::Forwarded]).origin(Some(ip("10.0.0.2")), &uri, &headers);
+        assert_eq!(origin.client_ip, Some(ip("192.0.2.1")));
+        assert_eq!(origin.url("/"), "https://api.example.com/");
+    }
+}
@@ -0,0 +1,622 @@
+//! Keeping credentials out of logs, traces and audit records
+//!
+//! A [`Redactor`] knows which field and header names hold secrets. The app's
+//! redactor, set from `[log.redact]` by
+//! [`App::auto_configure`](crate::App::auto_configure) or with [`set`],
+//! replaces their values with `***` in:
+//!
+//! - log fields, e.g. `tracing::info!(password = %input, ...)`, and passwords
+//!   in URLs logged as strings;
+//! - the access log span of each request, which records the method, the URI
+//!   with sensitive query parameters redacted, and the headers;
+//! - the `diff` of audit entries recorded through a
+//!   [`SharedAuditSink`](crate::audit::SharedAuditSink), at any depth.
+//!
+//! ```toml
+//! [log.redact]
+//! fields = ["ssn", "card_number"]
+//! headers = ["x-session"]
+//! ```
+//!
+//! Names add to [`DEFAULT_FIELDS`] and [`DEFAULT_HEADERS`]. A field is
+//! sensitive if its name contains one of the field names, ignoring case and
+//! `-`/`_`, so `password` also covers `new_password` and `password_hash`;
+//! headers must match exactly. Both the plain text and the [`RedactedJson`]
+//! formats are redacted; text interpolated into a log message is not.
+
+use std::{
+    fmt,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use axum::http::{HeaderMap, HeaderValue, Request, Uri};
+use serde_json::Value;
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{
+    field::{MakeVisitor, VisitFmt, VisitOutput},
+    fmt::{
+        FmtContext, FormatEvent, FormatFields, FormattedFields,
+        format::{DefaultFields, Writer},
+    },
+    registry::LookupSpan,
+};
+
+use crate::config::RedactConfig;
+
+/// What redacted values are replaced with
+pub const REDACTED: &str = "***";
+
+/// Field names redacted unless the redactor is built with [`Redactor::empty`]
+pub const DEFAULT_FIELDS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "authorization",
+    "api_key",
+    "apikey",
+    "cookie",
+    "credential",
+    "private_key",
+];
+
+/// Header names redacted unless the redactor is built with [`Redactor::empty`]
+pub const DEFAULT_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+];
+
+static CURRENT: LazyLock<RwLock<Arc<Redactor>>> = LazyLock::new(RwLock::default);
+
+/// The app's redactor
+pub fn current() -> Arc<Redactor> {
+    CURRENT.read().unwrap().clone()
+}
+
+/// Make `redactor` the app's redactor
+pub fn set(redactor: Redactor) {
+    *CURRENT.write().unwrap() = Arc::new(redactor);
+}
+
+/// Which field and header names hold secrets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redactor {
+    /// Normalized with [`normalize`]
+    fields: Vec<String>,
+    /// Lowercase
+    headers: Vec<String>,
+}
+
+impl Default for Redactor {
+    /// [`DEFAULT_FIELDS`] and [`DEFAULT_HEADERS`]
+    fn default() -> Self {
+        DEFAULT_FIELDS
+            .iter()
+            .fold(Self::empty(), |redactor, field| redactor.field(field))
+            .headers(DEFAULT_HEADERS.iter().copied())
+    }
+}
+
+impl Redactor {
+    /// A redactor that redacts nothing until given names
+    pub fn empty() -> Self {
+        Self {
+            fields: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// The defaults plus the names in `config`
+    pub fn from_config(config: &RedactConfig) -> Self {
+        config
+            .fields
+            .iter()
+            .fold(Self::default(), |redactor, field| redactor.field(field))
+            .headers(config.headers.iter().map(String::as_str))
+    }
+
+    /// Also redact fields whose name contains `name`
+    pub fn field(mut self, name: &str) -> Self {
+        let name = normalize(name);
+        if !name.is_empty() && !self.fields.contains(&name) {
+            self.fields.push(name);
+        }
+        self
+    }
+
+    /// Also redact the header `name`
+    pub fn header(mut self, name: &str) -> Self {
+        let name = name.to_ascii_lowercase();
+        if !self.headers.contains(&name) {
+            self.headers.push(name);
+        }
+        self
+    }
+
+    fn headers<'a>(self, names: impl IntoIterator<Item = &'a str>) -> Self {
+        names
+            .into_iter()
+            .fold(self, |redactor, name| redactor.header(name))
+    }
+
+    /// Whether values of the field `name` are redacted
+    pub fn is_sensitive_field(&self, name: &str) -> bool {
+        let name = normalize(name);
+        self.fields
+            .iter()
+            .any(|field| name.contains(field.as_str()))
+    }
+
+    /// Whether values of the header `name` are redacted
+    pub fn is_sensitive_header(&self, name: &str) -> bool {
+        self.headers
+            .iter()
+            .any(|header| header.eq_ignore_ascii_case(name))
+    }
+
+    /// `value` with the values of sensitive object fields and the passwords
+    /// of URLs redacted, at any depth
+    pub fn value(&self, value: Value) -> Value {
+        match value {
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let value = if self.is_sensitive_field(&name) && !value.is_null() {
+                            Value::String(REDACTED.to_string())
+                        } else {
+                            self.value(value)
+                        };
+                        (name, value)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.into_iter().map(|v| self.value(v)).collect()),
+            Value::String(s) => Value::String(redact_url_password(&s)),
+            value => value,
+        }
+    }
+
+    /// A copy of `headers` with sensitive values redacted
+    pub fn header_map(&self, headers: &HeaderMap) -> HeaderMap {
+        let mut redacted = headers.clone();
+        for (name, value) in redacted.iter_mut() {
+            if self.is_sensitive_header(name.as_str()) {
+                *value = HeaderValue::from_static(REDACTED);
+            }
+        }
+        redacted
+    }
+
+    /// `uri` with the values of sensitive query parameters redacted, e.g.
+    /// `/reset?token=***`
+    pub fn uri(&self, uri: &Uri) -> String {
+        let Some(query) = uri.query() else {
+            return uri.to_string();
+        };
+        let query: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if self.is_sensitive_field(name) => format!("{name}={REDACTED}"),
+                _ => pair.to_string(),
+            })
+            .collect();
+        format!("{}?{}", uri.path(), query.join("&"))
+    }
+}
+
+/// Lowercase, with `-` as `_`
+fn normalize(name: &str) -> String {
+    name.to_ascii_lowercase().replace('-', "_")
+}
+
+/// `url` with the password of its `user:password@` part replaced
+pub fn redact_url_password(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let Some(at) = rest[..authority_end].rfind('@') else {
+        return url.to_string();
+    };
+    match rest[..at].split_once(':') {
+        Some((user, _)) => format!("{scheme}://{user}:{REDACTED}{}", &rest[at..]),
+        None => url.to_string(),
+    }
+}
+
+/// Field formatter for `tracing_subscriber::fmt` layers, redacting values
+/// with the app's [`Redactor`] before `inner` writes them
+///
+/// ```rust,ignore
+/// tracing_subscriber::fmt::layer().fmt_fields(RedactFields::default())
+/// ```
+#[derive(Debug)]
+pub struct RedactFields<F = DefaultFields> {
+    inner: F,
+}
+
+impl Default for RedactFields {
+    fn default() -> Self {
+        Self::new(DefaultFields::new())
+    }
+}
+
+impl<F> RedactFields<F> {
+    pub fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, F> MakeVisitor<Writer<'a>> for RedactFields<F>
+where
+    F: MakeVisitor<Writer<'a>>,
+{
+    type Visitor = RedactVisitor<F::Visitor>;
+
+    fn make_visitor(&self, target: Writer<'a>) -> Self::Visitor {
+        RedactVisitor {
+            inner: self.inner.make_visitor(target),
+            redactor: current(),
+        }
+    }
+}
+
+/// Visitor made by [`RedactFields`]
+pub struct RedactVisitor<V> {
+    inner: V,
+    redactor: Arc<Redactor>,
+}
+
+impl<V: Visit> RedactVisitor<V> {
+    /// Record `***` for a sensitive field; `false` for others
+    fn redacted(&mut self, field: &Field) -> bool {
+        let sensitive = self.redactor.is_sensitive_field(field.name());
+        if sensitive {
+            self.inner.record_str(field, REDACTED);
+        }
+        sensitive
+    }
+}
+
+impl<V: Visit> Visit for RedactVisitor<V> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.redacted(field) {
+            self.inner.record_debug(field, value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if !self.redacted(field) {
+            self.inner.record_str(field, &redact_url_password(value));
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if !self.redacted(field) {
+            self.inner.record_i64(field, value);
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if !self.redacted(field) {
+            self.inner.record_u64(field, value);
+        }
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if !self.redacted(field) {
+            self.inner.record_f64(field, value);
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if !self.redacted(field) {
+            self.inner.record_bool(field, value);
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        if !self.redacted(field) {
+            self.inner.record_error(field, value);
+        }
+    }
+}
+
+impl<V: VisitOutput<fmt::Result>> VisitOutput<fmt::Result> for RedactVisitor<V> {
+    fn finish(self) -> fmt::Result {
+        self.inner.finish()
+    }
+}
+
+impl<V: VisitFmt> VisitFmt for RedactVisitor<V> {
+    fn writer(&mut self) -> &mut dyn fmt::Write {
+        self.inner.writer()
+    }
+}
+
+/// Field formatter writing fields as a JSON object, for wrapping in
+/// [`RedactFields`] under [`RedactedJson`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonObjectFields;
+
+impl<'a> MakeVisitor<Writer<'a>> for JsonObjectFields {
+    type Visitor = JsonObjectVisitor<'a>;
+
+    fn make_visitor(&self, target: Writer<'a>) -> Self::Visitor {
+        JsonObjectVisitor {
+            writer: target,
+            fields: serde_json::Map::new(),
+        }
+    }
+}
+
+/// Visitor made by [`JsonObjectFields`]
+pub struct JsonObjectVisitor<'a> {
+    writer: Writer<'a>,
+    fields: serde_json::Map<String, Value>,
+}
+
+impl Visit for JsonObjectVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields
+            .insert(field.name().to_string(), Value::from(format!("{value:?}")));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields
+            .insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields
+            .insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields
+            .insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields
+            .insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields
+            .insert(field.name().to_string(), Value::from(value));
+    }
+}
+
+impl VisitOutput<fmt::Result> for JsonObjectVisitor<'_> {
+    fn finish(mut self) -> fmt::Result {
+        write!(self.writer, "{}", Value::Object(self.fields))
+    }
+}
+
+impl VisitFmt for JsonObjectVisitor<'_> {
+    fn writer(&mut self) -> &mut dyn fmt::Write {
+        &mut self.writer
+    }
+}
+
+/// Event formatter writing one JSON object per line, with the event's
+/// fields and those of its spans redacted
+///
+/// ```rust,ignore
+/// tracing_subscriber::fmt::layer()
+///     .event_format(RedactedJson)
+///     .fmt_fields(RedactFields::new(JsonObjectFields))
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RedactedJson;
+
+impl<S, N> FormatEvent<S, N> for RedactedJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut fields = String::new();
+        ctx.format_fields(Writer::new(&mut fields), event)?;
+
+        let mut spans = Vec::new();
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let mut object = serde_json::Map::new();
+                object.insert("name".to_string(), Value::from(span.name()));
+                let extensions = span.extensions();
+                if let Some(formatted) = extensions.get::<FormattedFields<N>>()
+                    && let Value::Object(fields) = json_fields(formatted)
+                {
+                    object.extend(fields);
+                }
+                spans.push(Value::Object(object));
+            }
+        }
+
+        let metadata = event.metadata();
+        let line = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "fields": json_fields(&fields),
+            "spans": spans,
+        });
+        writeln!(writer, "{line}")
+    }
+}
+
+/// Fields written by [`JsonObjectFields`], merging the objects of spans
+/// recorded more than once
+fn json_fields(formatted: &str) -> Value {
+    let mut fields = serde_json::Map::new();
+    for object in serde_json::Deserializer::from_str(formatted).into_iter::<Value>() {
+        match object {
+            Ok(Value::Object(object)) => fields.extend(object),
+            _ => break,
+        }
+    }
+    Value::Object(fields)
+}
+
+/// Access log span for `tower_http`'s `TraceLayer`, with the URI and
+/// headers redacted by the app's [`Redactor`], and the client's address
+/// once a [`ProxyLayer`](crate::proxy::ProxyLayer) has resolved it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RedactedSpan;
+
+impl<B> tower_http::trace::MakeSpan<B> for RedactedSpan {
+    fn make_span(&mut self, request: &Request<B>) -> tracing::Span {
+        let redactor = current();
+        let client_ip = request
+            .extensions()
+            .get::<crate::proxy::RequestOrigin>()
+            .and_then(|origin| origin.client_ip);
+        tracing::debug_span!(
+            "request",
+            method = %request.method(),
+            uri = %redactor.uri(request.uri()),
+            version = ?request.version(),
+            client_ip = client_ip.map(tracing::field::display),
+            headers = ?redactor.header_map(request.headers()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sensitive_fields_are_redacted_at_any_depth() {
+        let redactor = Redactor::default().field("ssn");
+        assert_eq!(
+            redactor.value(json!({
+                "password_hash": { "from": "a", "to": "b" },
+                "user": { "SSN": "123", "name": "Ana", "Api-Key": null },
+                "links": ["postgres://app:pw@db/app"],
+            })),
+            json!({
+                "password_hash": REDACTED,
+                "user": { "SSN": REDACTED, "name": "Ana", "Api-Key": null },
+                "links": ["postgres://app:***@db/app"],
+            })
+        );
+        assert!(!Redactor::empty().is_sensitive_field("password"));
+    }
+
+    #[test]
+    fn headers_and_query_parameters_are_redacted() {
+        let redactor = Redactor::default().header("X-Session");
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer abc"));
+        headers.insert("x-session", HeaderValue::from_static("s1"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        let redacted = redactor.header_map(&headers);
+        assert_eq!(redacted["authorization"], REDACTED);
+        assert_eq!(redacted["x-session"], REDACTED);
+        assert_eq!(redacted["accept"], "*/*");
+
+        let uri: Uri = "/reset?email=a%40b.c&reset_token=xyz".parse().unwrap();
+        assert_eq!(redactor.uri(&uri), "/reset?email=a%40b.c&reset_token=***");
+        assert_eq!(redactor.uri(&"/users".parse().unwrap()), "/users");
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        fn output(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn log_fields_are_redacted() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .fmt_fields(RedactFields::default())
+                .with_writer(buffer.clone())
+                .with_ansi(false),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(
+                user = "ana",
+                password = "hunter2",
+                db = "postgres://app:pw@db/app",
+                "signed in"
+            );
+        });
+
+        let output = buffer.output();
+        assert!(output.contains("signed in"), "{output}");
+        assert!(output.contains("user=\"ana\""), "{output}");
+        assert!(output.contains("password=\"***\""), "{output}");
+        assert!(output.contains("postgres://app:***@db/app"), "{output}");
+        assert!(!output.contains("hunter2"), "{output}");
+    }
+
+    #[test]
+    fn json_log_fields_are_redacted() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .event_format(RedactedJson)
+                .fmt_fields(RedactFields::new(JsonObjectFields))
+                .with_writer(buffer.clone()),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let _span =
+                tracing::info_span!("request", api_token = "abc", path = "/login").entered();
+            tracing::warn!(user = "ana", password = "hunter2", "signed in");
+        });
+
+        let output = buffer.output();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["fields"]["message"], "signed in");
+        assert_eq!(line["fields"]["user"], "ana");
+        assert_eq!(line["fields"]["password"], REDACTED);
+        assert_eq!(line["spans"][0]["name"], "request");
+        assert_eq!(line["spans"][0]["api_token"], REDACTED);
+        assert_eq!(line["spans"][0]["path"], "/login");
+        assert!(!output.contains("hunter2"), "{output}");
+    }
+}
@@ -0,0 +1,506 @@
+//! Short links and managed redirects
+//!
+//! Redirects map a code (`promo24`) or a vanity/legacy path
+//! (`blog/2019/launch`) to a target URL, with optional expiry and a hit
+//! counter. They live in a [`RedirectStore`]; [`InMemoryRedirectStore`] is
+//! provided for development.
+//!
+//! - [`redirect_routes`] serves `GET {prefix}/{code}` short links
+//! - [`redirect_fallback`] resolves any otherwise unmatched path, for legacy
+//!   path migrations
+//! - [`redirect_admin_routes`] lists, creates, updates and deletes redirects
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use dy_rs::auth::RequireAuth;
+//! use dy_rs::redirects::{InMemoryRedirectStore, redirect_admin_routes, redirect_routes};
+//!
+//! let store = InMemoryRedirectStore::new();
+//!
+//! App::new()
+//!     .auto_configure()
+//!     .mount(redirect_routes("/go", store.clone()))
+//!     .mount(redirect_admin_routes("/admin/redirects", store).layer(RequireAuth::new(auth_config)))
+//!     .run()
+//!     .await?;
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    Json, Router,
+    extract::{OriginalUri, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect as RedirectResponse, Response},
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::{error::ApiError, extractors::ValidatedJson};
+
+/// Length of generated short codes
+const GENERATED_CODE_LEN: usize = 7;
+
+/// A managed redirect
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Redirect {
+    /// Short code or path (without the leading `/`)
+    pub code: String,
+    /// URL or absolute path to redirect to
+    pub target: String,
+    /// Whether clients may cache the redirect (308 rather than 307)
+    pub permanent: bool,
+    /// Number of times the redirect was followed
+    pub hits: u64,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Redirect {
+    /// Check if the redirect has expired
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Utc::now())
+    }
+}
+
+/// Request body for creating a redirect
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct CreateRedirect {
+    /// Code to use; a random one is generated when omitted
+    #[validate(length(min = 1, max = 200), custom(function = "validate_code"))]
+    pub code: Option<String>,
+    #[validate(custom(function = "validate_target"))]
+    pub target: String,
+    #[serde(default)]
+    pub permanent: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Request body for updating a redirect; omitted fields are unchanged
+#[derive(Debug, Clone, Default, Deserialize, Validate, ToSchema)]
+pub struct UpdateRedirect {
+    #[validate(custom(function = "validate_target"))]
+    pub target: Option<String>,
+    pub permanent: Option<bool>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn validate_code(code: &str) -> Result<(), ValidationError> {
+    let valid = !code.starts_with('/')
+        && !code.ends_with('/')
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./".contains(c))
+        && !code
+            .split('/')
+            .any(|segment| segment.is_empty() || segment == "..");
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("code")
+            .with_message("Code may only contain letters, digits, '-', '_', '.' and '/'".into()))
+    }
+}
+
+fn validate_target(target: &str) -> Result<(), ValidationError> {
+    // Browsers read `/\host` as `//host`, and drop tabs and newlines, so a
+    // path with either could still lead to another site
+    let valid = target.starts_with("https://")
+        || target.starts_with("http://")
+        || (target.starts_with('/')
+            && !target.starts_with("//")
+            && !target.contains(|c: char| c == '\\' || c.is_control()));
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("target")
+            .with_message("Target must be an http(s) URL or an absolute path".into()))
+    }
+}
+
+/// Redirect storage trait - implement this to keep redirects in your database
+#[async_trait::async_trait]
+pub trait RedirectStore: Clone + Send + Sync + 'static {
+    /// Get a redirect by code
+    async fn get(&self, code: &str) -> Result<Option<Redirect>, ApiError>;
+
+    /// All redirects, ordered by code
+    async fn list(&self) -> Result<Vec<Redirect>, ApiError>;
+
+    /// Store a new redirect; fails if the code is taken
+    async fn create(&self, redirect: Redirect) -> Result<Redirect, ApiError>;
+
+    /// Replace an existing redirect
+    async fn update(&self, redirect: Redirect) -> Result<Redirect, ApiError>;
+
+    /// Delete a redirect, returning whether it existed
+    async fn delete(&self, code: &str) -> Result<bool, ApiError>;
+
+    /// Count a visit and return the redirect, if it exists
+    async fn record_hit(&self, code: &str) -> Result<Option<Redirect>, ApiError>;
+}
+
+/// In-memory redirect store (for development/testing)
+#[derive(Clone, Default)]
+pub struct InMemoryRedirectStore {
+    redirects: Arc<Mutex<BTreeMap<String, Redirect>>>,
+}
+
+impl InMemoryRedirectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl RedirectStore for InMemoryRedirectStore {
+    async fn get(&self, code: &str) -> Result<Option<Redirect>, ApiError> {
+        Ok(self.redirects.lock().unwrap().get(code).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<Redirect>, ApiError> {
+        Ok(self.redirects.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn create(&self, redirect: Redirect) -> Result<Redirect, ApiError> {
+        let mut redirects = self.redirects.lock().unwrap();
+        if redirects.contains_key(&redirect.code) {
+            return Err(ApiError::BadRequest(format!(
+                "Redirect {} already exists",
+                redirect.code
+            )));
+        }
+        redirects.insert(redirect.code.clone(), redirect.clone());
+        Ok(redirect)
+    }
+
+    async fn update(&self, redirect: Redirect) -> Result<Redirect, ApiError> {
+        let mut redirects = self.redirects.lock().unwrap();
+        match redirects.get_mut(&redirect.code) {
+            Some(existing) => {
+                *existing = redirect.clone();
+                Ok(redirect)
+            }
+            None => Err(ApiError::NotFound(format!(
+                "Redirect {} not found",
+                redirect.code
+            ))),
+        }
+    }
+
+    async fn delete(&self, code: &str) -> Result<bool, ApiError> {
+        Ok(self.redirects.lock().unwrap().remove(code).is_some())
+    }
+
+    async fn record_hit(&self, code: &str) -> Result<Option<Redirect>, ApiError> {
+        let mut redirects = self.redirects.lock().unwrap();
+        Ok(redirects.get_mut(code).map(|redirect| {
+            redirect.hits += 1;
+            redirect.clone()
+        }))
+    }
+}
+
+/// Serve short links at `GET {prefix}/{code}`
+pub fn redirect_routes<S: RedirectStore>(prefix: &str, store: S) -> Router {
+    let prefix = prefix.trim_end_matches('/');
+    Router::new()
+        .route(&format!("{prefix}/{{*code}}"), get(follow::<S>))
+        .with_state(store)
+}
+
+/// Router whose fallback resolves unmatched request paths as redirect codes,
+/// e.g. `/blog/2019/launch` looks up `blog/2019/launch`
+///
+/// Merge it last; a router can only have one fallback.
+pub fn redirect_fallback<S: RedirectStore>(store: S) -> Router {
+    Router::new().fallback(follow_uri::<S>).with_state(store)
+}
+
+/// Admin endpoints under `base`:
+///
+/// - `GET {base}` – list redirects
+/// - `POST {base}` – create a redirect ([`CreateRedirect`])
+/// - `GET | PATCH | DELETE {base}/{code}` – read, update ([`UpdateRedirect`]) or delete one
+pub fn redirect_admin_routes<S: RedirectStore>(base: &str, store: S) -> Router {
+    let base = base.trim_end_matches('/');
+    Router::new()
+        .route(base, get(list::<S>).post(create::<S>))
+        .route(
+            &format!("{base}/{{*code}}"),
+            get(show::<S>).patch(update::<S>).delete(remove::<S>),
+        )
+        .with_state(store)
+}
+
+async fn follow<S: RedirectStore>(
+    State(store): State<S>,
+    Path(code): Path<String>,
+) -> Result<Response, ApiError> {
+    resolve(&store, &code).await
+}
+
+async fn follow_uri<S: RedirectStore>(
+    State(store): State<S>,
+    OriginalUri(uri): OriginalUri,
+) -> Result<Response, ApiError> {
+    resolve(&store, uri.path().trim_start_matches('/')).await
+}
+
+async fn resolve<S: RedirectStore>(store: &S, code: &str) -> Result<Response, ApiError> {
+    let not_found = || ApiError::NotFound(format!("No redirect for {code}"));
+
+    // Check expiry before counting so expired links don't accumulate hits.
+    let redirect = store.get(code).await?.ok_or_else(not_found)?;
+    if redirect.is_expired() {
+        return Ok((StatusCode::GONE, "This link has expired").into_response());
+    }
+    let redirect = store.record_hit(code).await?.ok_or_else(not_found)?;
+
+    Ok(if redirect.permanent {
+        RedirectResponse::permanent(&redirect.target).into_response()
+    } else {
+        RedirectResponse::temporary(&redirect.target).into_response()
+    })
+}
+
+async fn list<S: RedirectStore>(State(store): State<S>) -> Result<Json<Vec<Redirect>>, ApiError> {
+    Ok(Json(store.list().await?))
+}
+
+async fn create<S: RedirectStore>(
+    State(store): State<S>,
+    ValidatedJson(payload): ValidatedJson<CreateRedirect>,
+) -> Result<(StatusCode, Json<Redirect>), ApiError> {
+    let code = match payload.code {
+        Some(code) => code,
+        None => generate_code(),
+    };
+    let redirect = store
+        .create(Redirect {
+            code,
+            target: payload.target,
+            permanent: payload.permanent,
+            hits: 0,
+            expires_at: payload.expires_at,
+            created_at: Utc::now(),
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(redirect)))
+}
+
+async fn show<S: RedirectStore>(
+    State(store): State<S>,
+    Path(code): Path<String>,
+) -> Result<Json<Redirect>, ApiError> {
+    store
+        .get(&code)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Redirect {code} not found")))
+}
+
+async fn update<S: RedirectStore>(
+    State(store): State<S>,
+    Path(code): Path<String>,
+    ValidatedJson(payload): ValidatedJson<UpdateRedirect>,
+) -> Result<Json<Redirect>, ApiError> {
+    let mut redirect = store
+        .get(&code)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Redirect {code} not found")))?;
+
+    if let Some(target) = payload.target {
+        redirect.target = target;
+    }
+    if let Some(permanent) = payload.permanent {
+        redirect.permanent = permanent;
+    }
+    if payload.expires_at.is_some() {
+        redirect.expires_at = payload.expires_at;
+    }
+
+    Ok(Json(store.update(redirect).await?))
+}
+
+async fn remove<S: RedirectStore>(
+    State(store): State<S>,
+    Path(code): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if store.delete(&code).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(format!("Redirect {code} not found")))
+    }
+}
+
+/// Random base62 code
+fn generate_code() -> String {
+    const ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    let mut n = Uuid::new_v4().as_u128();
+    (0..GENERATED_CODE_LEN)
+        .map(|_| {
+            let c = ALPHABET[(n % 62) as usize] as char;
+            n /= 62;
+            c
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Request, header},
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn send(router: &Router, req: Request<Body>) -> (StatusCode, Response) {
+        let res = router.clone().oneshot(req).await.unwrap();
+        (res.status(), res)
+    }
+
+    fn json_request(method: &str, uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn get_request(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn admin_created_links_redirect_and_count_hits() {
+        let store = InMemoryRedirectStore::new();
+        let admin = redirect_admin_routes("/admin/redirects", store.clone());
+        let links = redirect_routes("/go", store.clone());
+
+        let (status, _) = send(
+            &admin,
+            json_request(
+                "POST",
+                "/admin/redirects",
+                r#"{"code":"promo","target":"https://example.com/sale"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, res) = send(&links, get_request("/go/promo")).await;
+        assert_eq!(status, StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(res.headers()[header::LOCATION], "https://example.com/sale");
+        send(&links, get_request("/go/promo")).await;
+
+        let (_, res) = send(&admin, get_request("/admin/redirects/promo")).await;
+        let body: Value =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["hits"], 2);
+
+        let (status, _) = send(
+            &admin,
+            json_request("PATCH", "/admin/redirects/promo", r#"{"permanent":true}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(&links, get_request("/go/promo")).await;
+        assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+
+        let (status, _) = send(
+            &admin,
+            Request::delete("/admin/redirects/promo")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&links, get_request("/go/promo")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn expired_links_are_gone() {
+        let store = InMemoryRedirectStore::new();
+        store
+            .create(Redirect {
+                code: "old".to_string(),
+                target: "/new".to_string(),
+                permanent: false,
+                hits: 0,
+                expires_at: Some(Utc::now() - chrono::Duration::minutes(1)),
+                created_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+
+        let (status, _) = send(
+            &redirect_routes("/go", store.clone()),
+            get_request("/go/old"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::GONE);
+        assert_eq!(store.get("old").await.unwrap().unwrap().hits, 0);
+    }
+
+    #[tokio::test]
+    async fn fallback_resolves_legacy_paths() {
+        let store = InMemoryRedirectStore::new();
+        let admin = redirect_admin_routes("/admin/redirects", store.clone());
+        send(
+            &admin,
+            json_request(
+                "POST",
+                "/admin/redirects",
+                r#"{"code":"blog/2019/launch","target":"/posts/launch","permanent":true}"#,
+            ),
+        )
+        .await;
+
+        let app = Router::new()
+            .route("/posts/launch", get(|| async { "post" }))
+            .merge(redirect_fallback(store));
+        let (status, res) = send(&app, get_request("/blog/2019/launch")).await;
+        assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(res.headers()[header::LOCATION], "/posts/launch");
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_redirects_and_generates_codes() {
+        let admin = redirect_admin_routes("/admin/redirects", InMemoryRedirectStore::new());
+
+        for body in [
+            r#"{"target":"javascript:alert(1)"}"#,
+            r#"{"target":"//evil.example"}"#,
+            r#"{"target":"/\\evil.example"}"#,
+            r#"{"target":"/\t/evil.example"}"#,
+            r#"{"target":"/ok\\..\\evil"}"#,
+            r#"{"code":"../x","target":"/ok"}"#,
+        ] {
+            let (status, _) = send(&admin, json_request("POST", "/admin/redirects", body)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+        }
+
+        let (status, res) = send(
+            &admin,
+            json_request("POST", "/admin/redirects", r#"{"target":"/ok"}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["code"].as_str().unwrap().len(), GENERATED_CODE_LEN);
+    }
+}
@@ -0,0 +1,269 @@
+//! PDF report generation
+//!
+//! Reports are Handlebars templates rendered to HTML and converted to PDF by
+//! a [`PdfEngine`]. Rendering runs on the blocking thread pool, and the
+//! resulting [`Report`] can be returned straight from a handler.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use dy_rs::report::{HeadlessChrome, Report, ReportGenerator};
+//!
+//! let reports = ReportGenerator::new(HeadlessChrome::default())
+//!     .template("invoice", include_str!("../templates/invoice.hbs"))?;
+//!
+//! async fn invoice(State(reports): State<ReportGenerator<HeadlessChrome>>) -> Result<Report, ApiError> {
+//!     let data = serde_json::json!({ "number": 42, "total": "99.00" });
+//!     Ok(reports.render("invoice", &data).await?.file_name("invoice-42.pdf"))
+//! }
+//! ```
+//!
+//! Reports can also be emailed on a schedule; see [`ReportScheduler`].
+
+mod scheduled;
+
+pub use scheduled::{ReportQuery, ReportScheduler, ScheduledReport};
+
+use std::{path::PathBuf, process::Command, sync::Arc};
+
+use axum::{
+    http::{HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use handlebars::Handlebars;
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+/// Converts rendered HTML into a PDF document
+pub trait PdfEngine: Send + Sync + 'static {
+    /// Convert `html` to PDF bytes
+    ///
+    /// Called on the blocking thread pool.
+    fn html_to_pdf(&self, html: &str) -> Result<Vec<u8>, ApiError>;
+}
+
+/// [`PdfEngine`] that prints pages with a headless Chrome/Chromium binary
+#[derive(Debug, Clone)]
+pub struct HeadlessChrome {
+    pub binary: PathBuf,
+    pub args: Vec<String>,
+}
+
+impl HeadlessChrome {
+    /// Use the browser binary at `binary`
+    pub fn new(binary: impl Into<PathBuf>) -> Self {
+        Self {
+            binary: binary.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Pass an extra command line argument to the browser
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl Default for HeadlessChrome {
+    /// Uses `CHROME_PATH` if set, otherwise `chromium` from `PATH`
+    fn default() -> Self {
+        Self::new(std::env::var("CHROME_PATH").unwrap_or_else(|_| "chromium".to_string()))
+    }
+}
+
+impl PdfEngine for HeadlessChrome {
+    fn html_to_pdf(&self, html: &str) -> Result<Vec<u8>, ApiError> {
+        let dir = std::env::temp_dir().join(format!("dy-rs-report-{}", uuid::Uuid::new_v4()));
+        let io_err = |e: std::io::Error| {
+            ApiError::InternalServerError(format!("Report rendering failed: {e}"))
+        };
+
+        std::fs::create_dir_all(&dir).map_err(io_err)?;
+        let input = dir.join("report.html");
+        let output = dir.join("report.pdf");
+
+        let result = std::fs::write(&input, html).map_err(io_err).and_then(|_| {
+            let status = Command::new(&self.binary)
+                .args(["--headless", "--disable-gpu", "--no-pdf-header-footer"])
+                .args(&self.args)
+                .arg(format!("--print-to-pdf={}", output.display()))
+                .arg(format!("file://{}", input.display()))
+                .status()
+                .map_err(io_err)?;
+            if !status.success() {
+                return Err(ApiError::InternalServerError(format!(
+                    "Report rendering failed: {} exited with {status}",
+                    self.binary.display()
+                )));
+            }
+            std::fs::read(&output).map_err(io_err)
+        });
+
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    }
+}
+
+/// A rendered document, returned from handlers as a download
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub bytes: Vec<u8>,
+    pub content_type: &'static str,
+    pub file_name: String,
+    pub inline: bool,
+}
+
+impl Report {
+    /// Wrap PDF bytes
+    pub fn pdf(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            content_type: "application/pdf",
+            file_name: "report.pdf".to_string(),
+            inline: false,
+        }
+    }
+
+    /// Set the download file name
+    pub fn file_name(mut self, name: impl Into<String>) -> Self {
+        self.file_name = name.into();
+        self
+    }
+
+    /// Display in the browser instead of downloading
+    pub fn inline(mut self) -> Self {
+        self.inline = true;
+        self
+    }
+}
+
+impl IntoResponse for Report {
+    fn into_response(self) -> Response {
+        let disposition = if self.inline { "inline" } else { "attachment" };
+        let file_name: String = self
+            .file_name
+            .chars()
+            .filter(|c| c.is_ascii() && !c.is_ascii_control() && *c != '"')
+            .collect();
+        let disposition =
+            HeaderValue::from_str(&format!("{disposition}; filename=\"{file_name}\""))
+                .unwrap_or_else(|_| HeaderValue::from_static("attachment"));
+
+        (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(self.content_type),
+                ),
+                (header::CONTENT_DISPOSITION, disposition),
+            ],
+            self.bytes,
+        )
+            .into_response()
+    }
+}
+
+/// Renders named templates into [`Report`]s
+pub struct ReportGenerator<E> {
+    templates: Arc<Handlebars<'static>>,
+    engine: Arc<E>,
+}
+
+impl<E> Clone for ReportGenerator<E> {
+    fn clone(&self) -> Self {
+        Self {
+            templates: self.templates.clone(),
+            engine: self.engine.clone(),
+        }
+    }
+}
+
+impl<E: PdfEngine> ReportGenerator<E> {
+    /// Create a generator using `engine` for PDF conversion
+    pub fn new(engine: E) -> Self {
+        let mut templates = Handlebars::new();
+        templates.set_strict_mode(true);
+        Self {
+            templates: Arc::new(templates),
+            engine: Arc::new(engine),
+        }
+    }
+
+    /// Register a Handlebars template under `name`
+    pub fn template(mut self, name: &str, source: &str) -> Result<Self, ApiError> {
+        Arc::make_mut(&mut self.templates)
+            .register_template_string(name, source)
+            .map_err(|e| ApiError::InternalServerError(format!("Invalid report template: {e}")))?;
+        Ok(self)
+    }
+
+    /// Render template `name` to HTML
+    pub fn render_html<T: Serialize>(&self, name: &str, data: &T) -> Result<String, ApiError> {
+        self.templates
+            .render(name, data)
+            .map_err(|e| ApiError::InternalServerError(format!("Report rendering failed: {e}")))
+    }
+
+    /// Render template `name` to a PDF report on the blocking thread pool
+    pub async fn render<T: Serialize>(&self, name: &str, data: &T) -> Result<Report, ApiError> {
+        let html = self.render_html(name, data)?;
+        let engine = self.engine.clone();
+        let bytes = tokio::task::spawn_blocking(move || engine.html_to_pdf(&html))
+            .await
+            .map_err(|e| {
+                ApiError::InternalServerError(format!("Report rendering failed: {e}"))
+            })??;
+        Ok(Report::pdf(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    /// Engine that returns the HTML unchanged, standing in for a browser
+    struct Echo;
+
+    impl PdfEngine for Echo {
+        fn html_to_pdf(&self, html: &str) -> Result<Vec<u8>, ApiError> {
+            Ok(html.as_bytes().to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn renders_templates_through_the_engine() {
+        let reports = ReportGenerator::new(Echo)
+            .template("invoice", "<h1>Invoice {{number}}</h1>")
+            .unwrap();
+
+        let report = reports
+            .render("invoice", &serde_json::json!({ "number": 42 }))
+            .await
+            .unwrap();
+        assert_eq!(report.bytes, b"<h1>Invoice 42</h1>");
+
+        // Strict mode surfaces missing fields instead of rendering blanks.
+        assert!(
+            reports
+                .render_html("invoice", &serde_json::json!({}))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn report_responds_as_a_download() {
+        let response = Report::pdf(b"%PDF-1.7".to_vec())
+            .file_name("invoice-42.pdf")
+            .into_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/pdf");
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"invoice-42.pdf\""
+        );
+    }
+}
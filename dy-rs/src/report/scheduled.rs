@@ -0,0 +1,412 @@
+//! Reports rendered and emailed on a cron schedule
+//!
+//! A [`ScheduledReport`] pairs a query with a template, recipients and a
+//! [`CronSchedule`]. The [`ReportScheduler`] runs each report when it is due,
+//! renders the rows through its [`ReportGenerator`] as the email body,
+//! optionally attaches them as PDF and CSV, and sends the result through a
+//! [`Mailer`]. Failures are logged and reported to the alert recipients.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use dy_rs::mail::LogMailer;
+//! use dy_rs::report::{HeadlessChrome, ReportGenerator, ReportScheduler, ScheduledReport};
+//!
+//! let generator = ReportGenerator::new(HeadlessChrome::default())
+//!     .template("signups", include_str!("../templates/signups.hbs"))?;
+//!
+//! let signups = ScheduledReport::new("daily-signups", "0 7 * * *".parse()?, "signups", move || {
+//!     let pool = pool.clone();
+//!     async move {
+//!         let rows = sqlx::query_scalar("SELECT row_to_json(s) FROM daily_signups s")
+//!             .fetch_all(&pool)
+//!             .await?;
+//!         Ok(rows)
+//!     }
+//! })
+//! .subject("Daily signups")
+//! .recipient("growth@example.com")
+//! .attach_csv();
+//!
+//! ReportScheduler::new(generator, LogMailer)
+//!     .report(signups)
+//!     .alert_to("oncall@example.com")
+//!     .spawn();
+//! ```
+//!
+//! Templates receive `report` (the report name), `generated_at` and `rows`.
+
+use std::{future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tokio::task::JoinHandle;
+
+use super::{PdfEngine, ReportGenerator};
+use crate::{
+    cron::CronSchedule,
+    error::ApiError,
+    mail::{Attachment, Email, Mailer},
+};
+
+/// Fetches the rows of a scheduled report
+///
+/// Closures `Fn() -> impl Future` are queries too.
+#[async_trait]
+pub trait ReportQuery: Send + Sync + 'static {
+    async fn rows(&self) -> Result<Vec<Value>, ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> ReportQuery for F
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Vec<Value>, ApiError>> + Send,
+{
+    async fn rows(&self) -> Result<Vec<Value>, ApiError> {
+        self().await
+    }
+}
+
+/// A report emailed on a schedule
+#[derive(Clone)]
+pub struct ScheduledReport {
+    name: String,
+    schedule: CronSchedule,
+    template: String,
+    subject: String,
+    recipients: Vec<String>,
+    pdf: bool,
+    csv: bool,
+    query: Arc<dyn ReportQuery>,
+}
+
+impl ScheduledReport {
+    /// Declare a report rendering `query`'s rows with template `template`
+    pub fn new(
+        name: impl Into<String>,
+        schedule: CronSchedule,
+        template: impl Into<String>,
+        query: impl ReportQuery,
+    ) -> Self {
+        let name = name.into();
+        Self {
+            subject: name.clone(),
+            name,
+            schedule,
+            template: template.into(),
+            recipients: Vec::new(),
+            pdf: false,
+            csv: false,
+            query: Arc::new(query),
+        }
+    }
+
+    /// Set the email subject (defaults to the report name)
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = subject.into();
+        self
+    }
+
+    /// Add a recipient
+    pub fn recipient(mut self, address: impl Into<String>) -> Self {
+        self.recipients.push(address.into());
+        self
+    }
+
+    /// Attach the rendered report as a PDF
+    pub fn attach_pdf(mut self) -> Self {
+        self.pdf = true;
+        self
+    }
+
+    /// Attach the rows as a CSV file
+    pub fn attach_csv(mut self) -> Self {
+        self.csv = true;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schedule(&self) -> &CronSchedule {
+        &self.schedule
+    }
+}
+
+/// Runs [`ScheduledReport`]s and emails the results
+pub struct ReportScheduler<E, M> {
+    generator: ReportGenerator<E>,
+    mailer: Arc<M>,
+    reports: Vec<ScheduledReport>,
+    alert_to: Vec<String>,
+}
+
+impl<E: PdfEngine, M: Mailer> ReportScheduler<E, M> {
+    /// Create a scheduler rendering with `generator` and sending with `mailer`
+    pub fn new(generator: ReportGenerator<E>, mailer: M) -> Self {
+        Self {
+            generator,
+            mailer: Arc::new(mailer),
+            reports: Vec::new(),
+            alert_to: Vec::new(),
+        }
+    }
+
+    /// Add a report
+    pub fn report(mut self, report: ScheduledReport) -> Self {
+        self.reports.push(report);
+        self
+    }
+
+    /// Email `address` when a report fails
+    pub fn alert_to(mut self, address: impl Into<String>) -> Self {
+        self.alert_to.push(address.into());
+        self
+    }
+
+    /// Run the report called `name` now, regardless of its schedule
+    pub async fn run(&self, name: &str) -> Result<(), ApiError> {
+        let report = self
+            .reports
+            .iter()
+            .find(|report| report.name == name)
+            .ok_or_else(|| ApiError::NotFound(format!("No scheduled report named {name}")))?;
+        self.deliver(report, Utc::now()).await
+    }
+
+    /// Run every report due at the minute containing `at`, alerting on
+    /// failures
+    pub async fn run_due(&self, at: DateTime<Utc>) {
+        for report in self.reports.iter().filter(|r| r.schedule.matches(at)) {
+            let Err(err) = self.deliver(report, at).await else {
+                tracing::info!(report = %report.name, "scheduled report sent");
+                continue;
+            };
+            tracing::error!(report = %report.name, error = %err, "scheduled report failed");
+            self.alert(report, &err).await;
+        }
+    }
+
+    /// Run reports as they fall due on a background task
+    pub fn spawn(self) -> JoinHandle<()> {
+        let scheduler = Arc::new(self);
+        tokio::spawn(async move {
+            let mut last = Utc::now();
+            loop {
+                let Some(next) = scheduler
+                    .reports
+                    .iter()
+                    .filter_map(|report| report.schedule.next_after(last))
+                    .min()
+                else {
+                    return;
+                };
+                let wait = (next - Utc::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+                scheduler.run_due(next).await;
+                last = next;
+            }
+        })
+    }
+
+    async fn deliver(&self, report: &ScheduledReport, at: DateTime<Utc>) -> Result<(), ApiError> {
+        if report.recipients.is_empty() {
+            return Err(ApiError::InternalServerError(format!(
+                "Scheduled report {} has no recipients",
+                report.name
+            )));
+        }
+
+        let rows = report.query.rows().await?;
+        let stem = format!("{}-{}", report.name, at.format("%Y-%m-%d"));
+        let data = serde_json::json!({
+            "report": report.name,
+            "generated_at": at,
+            "rows": rows,
+        });
+
+        let mut email =
+            Email::new(&report.subject).html(self.generator.render_html(&report.template, &data)?);
+        for recipient in &report.recipients {
+            email = email.to(recipient);
+        }
+        if report.pdf {
+            let pdf = self.generator.render(&report.template, &data).await?;
+            email = email.attach(Attachment::new(
+                format!("{stem}.pdf"),
+                pdf.content_type,
+                pdf.bytes,
+            ));
+        }
+        if report.csv {
+            email = email.attach(Attachment::new(
+                format!("{stem}.csv"),
+                "text/csv",
+                rows_to_csv(&rows).into_bytes(),
+            ));
+        }
+
+        self.mailer.send(email).await
+    }
+
+    async fn alert(&self, report: &ScheduledReport, err: &ApiError) {
+        if self.alert_to.is_empty() {
+            return;
+        }
+
+        let mut email =
+            Email::new(format!("Scheduled report {} failed", report.name)).html(format!(
+                "<p>The scheduled report <b>{}</b> ({}) failed:</p><pre>{}</pre>",
+                handlebars::html_escape(&report.name),
+                report.schedule,
+                handlebars::html_escape(&err.to_string())
+            ));
+        for address in &self.alert_to {
+            email = email.to(address);
+        }
+        if let Err(err) = self.mailer.send(email).await {
+            tracing::error!(report = %report.name, error = %err, "failed to send report alert");
+        }
+    }
+}
+
+/// Rows as CSV, with a column for every key seen in any row
+fn rows_to_csv(rows: &[Value]) -> String {
+    let mut columns: Vec<&str> = Vec::new();
+    for row in rows {
+        for key in row.as_object().into_iter().flat_map(|o| o.keys()) {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let mut write_line = |cells: &mut dyn Iterator<Item = String>| {
+        let line: Vec<String> = cells.map(|cell| csv_cell(&cell)).collect();
+        out.push_str(&line.join(","));
+        out.push_str("\r\n");
+    };
+
+    write_line(&mut columns.iter().map(|c| c.to_string()));
+    for row in rows {
+        write_line(&mut columns.iter().map(|column| match &row[*column] {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }));
+    }
+    out
+}
+
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    struct Echo;
+
+    impl PdfEngine for Echo {
+        fn html_to_pdf(&self, html: &str) -> Result<Vec<u8>, ApiError> {
+            Ok(html.as_bytes().to_vec())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Outbox(Arc<Mutex<Vec<Email>>>);
+
+    #[async_trait]
+    impl Mailer for Outbox {
+        async fn send(&self, email: Email) -> Result<(), ApiError> {
+            self.0.lock().unwrap().push(email);
+            Ok(())
+        }
+    }
+
+    fn scheduler(outbox: &Outbox) -> ReportScheduler<Echo, Outbox> {
+        let generator = ReportGenerator::new(Echo)
+            .template("list", "{{#each rows}}<li>{{name}}</li>{{/each}}")
+            .unwrap();
+        ReportScheduler::new(generator, outbox.clone()).alert_to("oncall@example.com")
+    }
+
+    #[tokio::test]
+    async fn renders_and_attaches_reports() {
+        let outbox = Outbox::default();
+        let report =
+            ScheduledReport::new("users", "0 7 * * *".parse().unwrap(), "list", || async {
+                Ok(vec![
+                    json!({ "name": "Ada", "age": 36 }),
+                    json!({ "name": "Lin, \"B\"" }),
+                ])
+            })
+            .subject("Users")
+            .recipient("team@example.com")
+            .attach_pdf()
+            .attach_csv();
+
+        scheduler(&outbox)
+            .report(report)
+            .run("users")
+            .await
+            .unwrap();
+
+        let sent = outbox.0.lock().unwrap();
+        let email = &sent[0];
+        assert_eq!(email.to, ["team@example.com"]);
+        assert_eq!(email.subject, "Users");
+        assert_eq!(email.html, "<li>Ada</li><li>Lin, &quot;B&quot;</li>");
+
+        assert_eq!(email.attachments[0].content_type, "application/pdf");
+        assert!(email.attachments[0].file_name.ends_with(".pdf"));
+        let csv = String::from_utf8(email.attachments[1].bytes.clone()).unwrap();
+        assert_eq!(csv, "age,name\r\n36,Ada\r\n,\"Lin, \"\"B\"\"\"\r\n");
+    }
+
+    #[tokio::test]
+    async fn due_failures_alert_instead_of_sending() {
+        let outbox = Outbox::default();
+        let failing =
+            ScheduledReport::new("broken", "*/5 * * * *".parse().unwrap(), "list", || async {
+                Err(ApiError::InternalServerError("query timed out".to_string()))
+            })
+            .recipient("team@example.com");
+        let not_due =
+            ScheduledReport::new("weekly", "0 0 * * SUN".parse().unwrap(), "list", || async {
+                Ok(Vec::new())
+            })
+            .recipient("team@example.com");
+
+        let scheduler = scheduler(&outbox).report(failing).report(not_due);
+        // 2024-01-01 is a Monday.
+        let at = "2024-01-01T10:05:00Z".parse().unwrap();
+        scheduler.run_due(at).await;
+
+        let sent = outbox.0.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, ["oncall@example.com"]);
+        assert_eq!(sent[0].subject, "Scheduled report broken failed");
+        assert!(sent[0].html.contains("query timed out"));
+    }
+
+    #[tokio::test]
+    async fn unknown_reports_are_not_found() {
+        let outbox = Outbox::default();
+        assert!(matches!(
+            scheduler(&outbox).run("missing").await,
+            Err(ApiError::NotFound(_))
+        ));
+    }
+}
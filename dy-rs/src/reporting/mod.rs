@@ -0,0 +1,90 @@
+//! Reporting server errors to an error tracker
+//!
+//! Every `5xx` response and handler panic becomes an [`ErrorReport`] with the
+//! full error, the request it answered and, for panics, the backtrace.
+//! Reports go to the [`ErrorReporter`] given to
+//! [`App::with_error_reporter`](crate::App::with_error_reporter), after the
+//! response is sent:
+//!
+//! ```rust,ignore
+//! let client = reqwest::Client::new();
+//! let sentry = SentryReporter::new(SentryConfig::from_env(), move |request: http::Request<String>| {
+//!     let client = client.clone();
+//!     async move {
+//!         let response = client
+//!             .execute(request.try_into().map_err(ApiError::external)?)
+//!             .await
+//!             .map_err(ApiError::external)?;
+//!         Ok(response.status())
+//!     }
+//! })?;
+//!
+//! App::new().auto_configure().with_error_reporter(sentry)
+//! ```
+//!
+//! [`SentryReporter`] needs the `sentry` feature; other trackers plug in by
+//! implementing [`ErrorReporter`].
+
+#[cfg(feature = "sentry")]
+mod sentry;
+
+use async_trait::async_trait;
+use axum::{extract::Request, http::header::USER_AGENT};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+
+#[cfg(feature = "sentry")]
+pub use sentry::{SentryConfig, SentryReporter, SentryTransport};
+
+/// A server error or panic and the request it answered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    pub id: Uuid,
+    pub at: DateTime<Utc>,
+    pub status: u16,
+    /// The [`ApiError`] code, `None` for other responses
+    pub code: Option<String>,
+    /// The full error, as logged
+    pub message: String,
+    pub panic: bool,
+    /// Where a panic happened
+    pub backtrace: Option<String>,
+    /// The `x-request-id` of the request
+    pub request_id: String,
+    pub method: String,
+    /// Path and query of the request
+    pub uri: String,
+    pub user_agent: Option<String>,
+}
+
+impl ErrorReport {
+    /// A report of a `500` answering `request`, to fill in
+    pub(crate) fn new(request: &Request, request_id: &str) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            at: Utc::now(),
+            status: 500,
+            code: None,
+            message: "Internal Server Error".to_string(),
+            panic: false,
+            backtrace: None,
+            request_id: request_id.to_string(),
+            method: request.method().to_string(),
+            uri: request.uri().to_string(),
+            user_agent: request
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+/// Where server errors are reported, e.g. an error tracker
+#[async_trait]
+pub trait ErrorReporter: Send + Sync + 'static {
+    async fn report(&self, report: &ErrorReport) -> Result<(), ApiError>;
+}
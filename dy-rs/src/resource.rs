@@ -0,0 +1,491 @@
+//! CRUD resources
+//!
+//! Describe a model with [`Resource`] and its storage with [`CrudService`],
+//! and [`crud_routes`] serves the five standard handlers while
+//! [`crud_openapi`] documents them:
+//!
+//! | Method   | Path          | Handler                                   |
+//! |----------|---------------|-------------------------------------------|
+//! | `GET`    | `/notes`      | one [`Page`] of notes, see [`Pagination`] |
+//! | `POST`   | `/notes`      | validate the body and create, `201`       |
+//! | `GET`    | `/notes/{id}` | one note, or `404`                        |
+//! | `PUT`    | `/notes/{id}` | validate the body and update, or `404`    |
+//! | `DELETE` | `/notes/{id}` | delete, `204`, or `404`                   |
+//!
+//! ```rust,ignore
+//! #[derive(Serialize, ToSchema)]
+//! struct Note { id: i64, title: String }
+//!
+//! #[derive(Deserialize, Validate, ToSchema)]
+//! struct NoteInput {
+//!     #[validate(length(min = 1))]
+//!     title: String,
+//! }
+//!
+//! impl Resource for Note {
+//!     const PATH: &'static str = "/notes";
+//!     type Id = i64;
+//!     type Create = NoteInput;
+//!     type Update = NoteInput;
+//! }
+//!
+//! #[async_trait]
+//! impl CrudService<Note> for PgNotes {
+//!     async fn list(&self, page: &Pagination) -> ApiResult<(Vec<Note>, u64)> { ... }
+//!     async fn get(&self, id: &i64) -> ApiResult<Option<Note>> { ... }
+//!     async fn create(&self, input: NoteInput) -> ApiResult<Note> { ... }
+//!     async fn update(&self, id: &i64, input: NoteInput) -> ApiResult<Option<Note>> { ... }
+//!     async fn delete(&self, id: &i64) -> ApiResult<bool> { ... }
+//! }
+//!
+//! App::new().auto_configure().resource::<Note>(PgNotes::new(pool));
+//! ```
+//!
+//! Responses carry [`CacheTags`] for CDN caching: lists and creates the
+//! model's name (`Note`), single notes `Note:{id}`, and updates and deletes
+//! both. Add a [`PurgeOnMutationLayer`](crate::edge_cache::PurgeOnMutationLayer)
+//! and every change purges the cached responses it made stale:
+//!
+//! ```rust,ignore
+//! App::new()
+//!     .auto_configure()
+//!     .resource::<Note>(PgNotes::new(pool))
+//!     .layer(PurgeOnMutationLayer::new(FastlyPurger::new(service_id, token, transport)));
+//! ```
+
+use std::fmt::Display;
+
+use async_trait::async_trait;
+use axum::{Json, Router, extract::State, http::StatusCode, routing::get};
+use serde::{Serialize, de::DeserializeOwned};
+use utoipa::{
+    IntoParams, PartialSchema, ToSchema,
+    openapi::{
+        self, ComponentsBuilder, OpenApiBuilder, PathItem, PathsBuilder, Ref, RefOr, Required,
+        content::ContentBuilder,
+        path::{HttpMethod, OperationBuilder, ParameterBuilder, ParameterIn},
+        request_body::RequestBodyBuilder,
+        response::ResponseBuilder,
+        schema::{ArrayBuilder, ObjectBuilder, Type},
+    },
+};
+use validator::Validate;
+
+use crate::{
+    edge_cache::CacheTags,
+    error::ApiError,
+    extractors::{Path, ValidatedJson},
+    pagination::{Page, PageLinks, Pagination, PaginationQuery},
+};
+
+/// A model served by [`crud_routes`]
+pub trait Resource: Serialize + ToSchema + Send + 'static {
+    /// Path of the collection, e.g. `/notes`; items live under `{PATH}/{id}`
+    const PATH: &'static str;
+    /// Identifier parsed from the item path
+    type Id: DeserializeOwned + PartialSchema + Display + Send + Sync + 'static;
+    /// Body of `POST {PATH}`
+    type Create: DeserializeOwned + Validate + ToSchema + Send + 'static;
+    /// Body of `PUT {PATH}/{id}`
+    type Update: DeserializeOwned + Validate + ToSchema + Send + 'static;
+}
+
+/// Storage behind the routes of a [`Resource`]
+///
+/// Inputs arrive validated. Return `None` or `false` for unknown IDs and the
+/// handlers answer `404`.
+#[async_trait]
+pub trait CrudService<R: Resource>: Clone + Send + Sync + 'static {
+    /// The requested page of resources, and how many there are in total
+    async fn list(&self, pagination: &Pagination) -> Result<(Vec<R>, u64), ApiError>;
+
+    async fn get(&self, id: &R::Id) -> Result<Option<R>, ApiError>;
+
+    async fn create(&self, input: R::Create) -> Result<R, ApiError>;
+
+    async fn update(&self, id: &R::Id, input: R::Update) -> Result<Option<R>, ApiError>;
+
+    /// Whether there was a resource to delete
+    async fn delete(&self, id: &R::Id) -> Result<bool, ApiError>;
+}
+
+fn not_found<R: Resource>(id: &R::Id) -> ApiError {
+    ApiError::NotFound(format!("{} {id} not found", R::name()))
+}
+
+/// Tag of every response listing `R`s
+fn collection_tags<R: Resource>() -> CacheTags {
+    CacheTags::new().tag(R::name())
+}
+
+/// Tag of the responses showing the `R` with `id`
+fn item_tags<R: Resource>(id: &R::Id) -> CacheTags {
+    CacheTags::new().tag(format!("{}:{id}", R::name()))
+}
+
+/// Tags of the responses a change to the `R` with `id` makes stale
+fn changed_tags<R: Resource>(id: &R::Id) -> CacheTags {
+    collection_tags::<R>()
+        .as_slice()
+        .iter()
+        .chain(item_tags::<R>(id).as_slice())
+        .collect()
+}
+
+/// List handler
+pub async fn list<R: Resource, S: CrudService<R>>(
+    State(service): State<S>,
+    pagination: Pagination,
+) -> Result<(CacheTags, Page<R>), ApiError> {
+    let (items, total) = service.list(&pagination).await?;
+    Ok((collection_tags::<R>(), Page::new(items, total, &pagination)))
+}
+
+/// Get handler
+pub async fn get_one<R: Resource, S: CrudService<R>>(
+    State(service): State<S>,
+    Path(id): Path<R::Id>,
+) -> Result<(CacheTags, Json<R>), ApiError> {
+    match service.get(&id).await? {
+        Some(resource) => Ok((item_tags::<R>(&id), Json(resource))),
+        None => Err(not_found::<R>(&id)),
+    }
+}
+
+/// Create handler
+pub async fn create<R: Resource, S: CrudService<R>>(
+    State(service): State<S>,
+    ValidatedJson(input): ValidatedJson<R::Create>,
+) -> Result<(StatusCode, CacheTags, Json<R>), ApiError> {
+    let resource = service.create(input).await?;
+    Ok((StatusCode::CREATED, collection_tags::<R>(), Json(resource)))
+}
+
+/// Update handler
+pub async fn update<R: Resource, S: CrudService<R>>(
+    State(service): State<S>,
+    Path(id): Path<R::Id>,
+    ValidatedJson(input): ValidatedJson<R::Update>,
+) -> Result<(CacheTags, Json<R>), ApiError> {
+    match service.update(&id, input).await? {
+        Some(resource) => Ok((changed_tags::<R>(&id), Json(resource))),
+        None => Err(not_found::<R>(&id)),
+    }
+}
+
+/// Delete handler
+pub async fn delete<R: Resource, S: CrudService<R>>(
+    State(service): State<S>,
+    Path(id): Path<R::Id>,
+) -> Result<(CacheTags, StatusCode), ApiError> {
+    if service.delete(&id).await? {
+        Ok((changed_tags::<R>(&id), StatusCode::NO_CONTENT))
+    } else {
+        Err(not_found::<R>(&id))
+    }
+}
+
+/// Create the list, get, create, update and delete routes of `R`
+pub fn crud_routes<R: Resource, S: CrudService<R>>(service: S) -> Router {
+    Router::new()
+        .route(R::PATH, get(list::<R, S>).post(create::<R, S>))
+        .route(
+            &format!("{}/{{id}}", R::PATH),
+            get(get_one::<R, S>)
+                .put(update::<R, S>)
+                .delete(delete::<R, S>),
+        )
+        .with_state(service)
+}
+
+/// OpenAPI document for the routes mounted by [`crud_routes`]
+///
+/// Operations are tagged with the model's schema name and have IDs such as
+/// `listNote` and `createNote`. Lists are documented as a `{name}Page`
+/// schema.
+pub fn crud_openapi<R: Resource>() -> openapi::OpenApi {
+    let name = R::name();
+    let page = format!("{name}Page");
+
+    let json = |schema: &str| {
+        ContentBuilder::new()
+            .schema(Some(RefOr::Ref(Ref::from_schema_name(schema))))
+            .build()
+    };
+    let response = |description: &str, schema: Option<&str>| {
+        let mut response = ResponseBuilder::new().description(description);
+        if let Some(schema) = schema {
+            response = response.content("application/json", json(schema));
+        }
+        response.build()
+    };
+    let body = |schema: &str| {
+        RequestBodyBuilder::new()
+            .content("application/json", json(schema))
+            .required(Some(Required::True))
+            .build()
+    };
+    let operation = |verb: &str, summary: String| {
+        OperationBuilder::new()
+            .operation_id(Some(format!("{verb}{name}")))
+            .tag(name.to_string())
+            .summary(Some(summary))
+    };
+    let item = |verb: &str, summary: String| {
+        operation(verb, summary)
+            .parameter(
+                ParameterBuilder::new()
+                    .name("id")
+                    .parameter_in(ParameterIn::Path)
+                    .required(Required::True)
+                    .schema(Some(R::Id::schema())),
+            )
+            .response("404", response("Not found", None))
+    };
+
+    let list = operation("list", format!("List {name}"))
+        .parameters(Some(PaginationQuery::into_params(|| {
+            Some(ParameterIn::Query)
+        })))
+        .response("200", response("One page", Some(&page)))
+        .response("400", response("Invalid pagination", None));
+    let create = operation("create", format!("Create a {name}"))
+        .request_body(Some(body(&R::Create::name())))
+        .response("201", response("Created", Some(&name)))
+        .response("422", response("Validation error", None));
+    let get_one =
+        item("get", format!("Get a {name}")).response("200", response("Success", Some(&name)));
+    let update = item("update", format!("Update a {name}"))
+        .request_body(Some(body(&R::Update::name())))
+        .response("200", response("Updated", Some(&name)))
+        .response("422", response("Validation error", None));
+    let delete =
+        item("delete", format!("Delete a {name}")).response("204", response("Deleted", None));
+
+    let mut collection = PathItem::new(HttpMethod::Get, list);
+    collection.post = Some(create.build());
+    let mut member = PathItem::new(HttpMethod::Get, get_one);
+    member.put = Some(update.build());
+    member.delete = Some(delete.build());
+    let paths = PathsBuilder::new()
+        .path(R::PATH, collection)
+        .path(format!("{}/{{id}}", R::PATH), member);
+
+    let page_schema = ObjectBuilder::new()
+        .property(
+            "items",
+            ArrayBuilder::new().items(RefOr::Ref(Ref::from_schema_name(name.as_ref()))),
+        )
+        .required("items")
+        .property("total", ObjectBuilder::new().schema_type(Type::Integer))
+        .required("total")
+        .property("page", ObjectBuilder::new().schema_type(Type::Integer))
+        .required("page")
+        .property("per_page", ObjectBuilder::new().schema_type(Type::Integer))
+        .required("per_page")
+        .property("links", Ref::from_schema_name("PageLinks"))
+        .required("links");
+    let components = ComponentsBuilder::new()
+        .schema_from::<R>()
+        .schema_from::<R::Create>()
+        .schema_from::<R::Update>()
+        .schema_from::<PageLinks>()
+        .schema(page, page_schema)
+        .build();
+
+    OpenApiBuilder::new()
+        .paths(paths)
+        .components(Some(components))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{Body, to_bytes},
+        extract::Request,
+    };
+    use serde::Deserialize;
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    #[derive(Clone, Serialize, ToSchema)]
+    struct Note {
+        id: u64,
+        title: String,
+    }
+
+    #[derive(Deserialize, Validate, ToSchema)]
+    struct NoteInput {
+        #[validate(length(min = 1))]
+        title: String,
+    }
+
+    impl Resource for Note {
+        const PATH: &'static str = "/notes";
+        type Id = u64;
+        type Create = NoteInput;
+        type Update = NoteInput;
+    }
+
+    #[derive(Clone, Default)]
+    struct Notes(Arc<Mutex<Vec<Note>>>);
+
+    #[async_trait]
+    impl CrudService<Note> for Notes {
+        async fn list(&self, pagination: &Pagination) -> Result<(Vec<Note>, u64), ApiError> {
+            let notes = self.0.lock().unwrap();
+            let page = notes
+                .iter()
+                .skip(pagination.offset)
+                .take(pagination.limit)
+                .cloned()
+                .collect();
+            Ok((page, notes.len() as u64))
+        }
+
+        async fn get(&self, id: &u64) -> Result<Option<Note>, ApiError> {
+            Ok(self.0.lock().unwrap().iter().find(|n| n.id == *id).cloned())
+        }
+
+        async fn create(&self, input: NoteInput) -> Result<Note, ApiError> {
+            let mut notes = self.0.lock().unwrap();
+            let note = Note {
+                id: notes.len() as u64 + 1,
+                title: input.title,
+            };
+            notes.push(note.clone());
+            Ok(note)
+        }
+
+        async fn update(&self, id: &u64, input: NoteInput) -> Result<Option<Note>, ApiError> {
+            let mut notes = self.0.lock().unwrap();
+            let note = notes.iter_mut().find(|n| n.id == *id);
+            Ok(note.map(|note| {
+                note.title = input.title;
+                note.clone()
+            }))
+        }
+
+        async fn delete(&self, id: &u64) -> Result<bool, ApiError> {
+            let mut notes = self.0.lock().unwrap();
+            let before = notes.len();
+            notes.retain(|n| n.id != *id);
+            Ok(notes.len() < before)
+        }
+    }
+
+    async fn send(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+            .unwrap();
+        let res = app.clone().oneshot(request).await.unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn serves_the_five_handlers() {
+        let app = crud_routes::<Note, _>(Notes::default());
+
+        for title in ["one", "two", "three"] {
+            let (status, note) =
+                send(&app, "POST", "/notes", Some(json!({ "title": title }))).await;
+            assert_eq!(status, StatusCode::CREATED);
+            assert_eq!(note["title"], title);
+        }
+        let (status, _) = send(&app, "POST", "/notes", Some(json!({ "title": "" }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, page) = send(&app, "GET", "/notes?per_page=2", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["total"], 3);
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+        assert_eq!(page["links"]["next"], "/notes?page=2&per_page=2");
+
+        let (status, note) = send(&app, "GET", "/notes/2", None).await;
+        assert_eq!((status, &note["title"]), (StatusCode::OK, &json!("two")));
+        let (status, note) = send(&app, "PUT", "/notes/2", Some(json!({ "title": "2" }))).await;
+        assert_eq!((status, &note["title"]), (StatusCode::OK, &json!("2")));
+
+        let (status, _) = send(&app, "DELETE", "/notes/2", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, error) = send(&app, "GET", "/notes/2", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["message"], "Not found: Note 2 not found");
+        let (status, _) = send(&app, "DELETE", "/notes/2", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&app, "GET", "/notes/abc", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn changes_purge_the_cached_responses() {
+        use crate::edge_cache::{CACHE_TAG, PurgeOnMutationLayer, Purger};
+
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<Vec<String>>>>);
+
+        #[async_trait]
+        impl Purger for Recorder {
+            async fn purge(&self, tags: &[String]) -> Result<(), ApiError> {
+                self.0.lock().unwrap().push(tags.to_vec());
+                Ok(())
+            }
+        }
+
+        let purged = Recorder::default();
+        let app = crud_routes::<Note, _>(Notes::default())
+            .layer(PurgeOnMutationLayer::new(purged.clone()));
+
+        send(&app, "POST", "/notes", Some(json!({ "title": "one" }))).await;
+        let request = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(request("/notes")).await.unwrap();
+        assert_eq!(res.headers()[CACHE_TAG], "Note");
+        let res = app.clone().oneshot(request("/notes/1")).await.unwrap();
+        assert_eq!(res.headers()[CACHE_TAG], "Note:1");
+
+        send(&app, "PUT", "/notes/1", Some(json!({ "title": "1" }))).await;
+        send(&app, "PUT", "/notes/9", Some(json!({ "title": "9" }))).await;
+        send(&app, "DELETE", "/notes/1", None).await;
+        assert_eq!(
+            *purged.0.lock().unwrap(),
+            [vec!["Note"], vec!["Note", "Note:1"], vec!["Note", "Note:1"]]
+        );
+    }
+
+    #[test]
+    fn documents_the_routes() {
+        let doc = serde_json::to_value(crud_openapi::<Note>()).unwrap();
+
+        let collection = &doc["paths"]["/notes"];
+        assert_eq!(collection["get"]["operationId"], "listNote");
+        assert_eq!(
+            collection["get"]["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/NotePage"
+        );
+        assert_eq!(
+            collection["post"]["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/NoteInput"
+        );
+        let member = &doc["paths"]["/notes/{id}"];
+        for method in ["get", "put", "delete"] {
+            assert_eq!(member[method]["tags"][0], "Note");
+            assert_eq!(member[method]["parameters"][0]["name"], "id");
+        }
+        for schema in ["Note", "NoteInput", "NotePage", "PageLinks"] {
+            assert!(doc["components"]["schemas"][schema].is_object(), "{schema}");
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Route registration from `#[dy_api]`
+//!
+//! Every annotated handler is recorded alongside its OpenAPI operation, so the
+//! documented path and method are also the ones it is served on.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! #[dy_api(method = get, path = "/users")]
+//! async fn list_users(State(db): State<Database>) -> ApiResult<Vec<User>> { ... }
+//!
+//! App::new()
+//!     .auto_configure()
+//!     .auto_routes_with_state(db)
+//!     .run()
+//!     .await
+//! ```
+//!
+//! axum can't list the routes of a router, so
+//! [`App::routes`](crate::App::routes) rebuilds the route table from what the
+//! app knows: its own routes, the documented operations and the paths added
+//! with `App::route`. Routes of a router passed to `App::mount` are listed
+//! when they're documented.
+
+use std::{any::Any, fmt::Write};
+
+use axum::{
+    Router,
+    handler::Handler,
+    routing::{MethodFilter, on},
+};
+use serde::Serialize;
+use utoipa::openapi::path::HttpMethod;
+
+/// A handler registered by `#[dy_api]`.
+pub struct AutoRoute {
+    pub path: &'static str,
+    pub method: HttpMethod,
+    /// Adds the handler to a `Router<S>` passed as `dyn Any`; returns false if
+    /// the router's state type is not the handler's.
+    pub add: fn(&mut dyn Any) -> bool,
+}
+
+inventory::collect!(AutoRoute);
+
+/// Router with every `#[dy_api]` handler that needs no state.
+pub fn auto_router() -> Router {
+    auto_router_with_state(())
+}
+
+/// Router with every `#[dy_api]` handler that needs no state or extracts
+/// `State<S>`.
+///
+/// Handlers using a different state type are skipped with a warning; use
+/// `#[dy_api(state = AppState)]` when a handler extracts a sub-state through
+/// `FromRef`.
+pub fn auto_router_with_state<S>(state: S) -> Router
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let mut stateful = Router::<S>::new();
+    let mut stateless = Router::<()>::new();
+
+    for entry in inventory::iter::<AutoRoute>() {
+        if (entry.add)(&mut stateful) || (entry.add)(&mut stateless) {
+            tracing::debug!(
+                path = entry.path,
+                method = method_name(&entry.method),
+                "registered route"
+            );
+            continue;
+        }
+        tracing::warn!(
+            path = entry.path,
+            method = method_name(&entry.method),
+            state = std::any::type_name::<S>(),
+            "skipping #[dy_api] route: handler uses a different state type"
+        );
+    }
+
+    stateless.merge(stateful.with_state(state))
+}
+
+/// Used by `#[dy_api]` expansions. Not public API.
+#[doc(hidden)]
+pub fn add_route<S, H, T>(
+    router: &mut dyn Any,
+    path: &'static str,
+    method: HttpMethod,
+    handler: H,
+) -> bool
+where
+    S: Clone + Send + Sync + 'static,
+    H: Handler<T, S>,
+    T: 'static,
+{
+    let Some(router) = router.downcast_mut::<Router<S>>() else {
+        return false;
+    };
+
+    let filter = match method {
+        HttpMethod::Get => MethodFilter::GET,
+        HttpMethod::Post => MethodFilter::POST,
+        HttpMethod::Put => MethodFilter::PUT,
+        HttpMethod::Delete => MethodFilter::DELETE,
+        HttpMethod::Options => MethodFilter::OPTIONS,
+        HttpMethod::Head => MethodFilter::HEAD,
+        HttpMethod::Patch => MethodFilter::PATCH,
+        HttpMethod::Trace => MethodFilter::TRACE,
+    };
+    *router = std::mem::take(router).route(path, on(filter, handler));
+    true
+}
+
+pub(crate) fn method_name(method: &HttpMethod) -> &'static str {
+    match method {
+        HttpMethod::Get => "GET",
+        HttpMethod::Post => "POST",
+        HttpMethod::Put => "PUT",
+        HttpMethod::Delete => "DELETE",
+        HttpMethod::Options => "OPTIONS",
+        HttpMethod::Head => "HEAD",
+        HttpMethod::Patch => "PATCH",
+        HttpMethod::Trace => "TRACE",
+    }
+}
+
+/// A route served by the app, as listed by [`App::routes`](crate::App::routes)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RouteInfo {
+    pub path: String,
+    /// Methods served, or none when they aren't known, as for paths added
+    /// with `App::route`
+    pub methods: Vec<String>,
+    /// The documented operation ID, or a name for the app's own routes
+    pub handler: Option<String>,
+    /// Middleware the request goes through, outermost first
+    pub middleware: Vec<String>,
+}
+
+impl RouteInfo {
+    pub fn new(path: impl Into<String>, methods: &[&str], handler: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            methods: methods.iter().map(|method| method.to_string()).collect(),
+            handler: Some(handler.into()),
+            middleware: Vec::new(),
+        }
+    }
+}
+
+/// `routes` as an aligned text table, one route per line
+pub fn format_table(routes: &[RouteInfo]) -> String {
+    let rows: Vec<[String; 4]> = routes
+        .iter()
+        .map(|route| {
+            let methods = if route.methods.is_empty() {
+                "*".to_string()
+            } else {
+                route.methods.join(",")
+            };
+            [
+                methods,
+                route.path.clone(),
+                route.handler.clone().unwrap_or_else(|| "-".to_string()),
+                route.middleware.join(" > "),
+            ]
+        })
+        .collect();
+    let header = ["METHODS", "PATH", "HANDLER", "MIDDLEWARE"].map(str::to_string);
+    let mut widths = header.clone().map(|cell| cell.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        let _ = writeln!(table, "{}", line.trim_end());
+    }
+    table
+}
@@ -0,0 +1,263 @@
+//! Secrets in AWS Secrets Manager
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{self, Method},
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use super::{SecretSource, SecretsTransport, check, json_field, split_field};
+use crate::error::ApiError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SERVICE: &str = "secretsmanager";
+
+/// Region and credentials for Secrets Manager
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AwsSecretsConfig {
+    pub region: String,
+    /// Endpoint of a compatible service, e.g. LocalStack; AWS when unset
+    pub endpoint: Option<String>,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Session token of temporary credentials
+    pub session_token: Option<String>,
+}
+
+impl AwsSecretsConfig {
+    /// From `AWS_REGION`, `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN`
+    pub fn from_env() -> Self {
+        Self {
+            region: std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            endpoint: None,
+            access_key_id: std::env::var("AWS_ACCESS_KEY_ID").unwrap_or_default(),
+            secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default(),
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        }
+    }
+}
+
+/// Secrets read with `GetSecretValue`, signed with AWS Signature Version 4.
+/// Names are a secret ID or ARN, optionally followed by `#field` to pick a
+/// field of a JSON secret, e.g. `prod/db#password`.
+pub struct AwsSecrets<T> {
+    config: AwsSecretsConfig,
+    transport: T,
+}
+
+impl<T: SecretsTransport> AwsSecrets<T> {
+    pub fn new(config: AwsSecretsConfig, transport: T) -> Self {
+        Self { config, transport }
+    }
+
+    fn request(
+        &self,
+        secret_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<http::Request<Bytes>, ApiError> {
+        let endpoint =
+            self.config.endpoint.clone().unwrap_or_else(|| {
+                format!("https://{SERVICE}.{}.amazonaws.com", self.config.region)
+            });
+        let endpoint = endpoint.trim_end_matches('/');
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint, |(_, host)| host);
+        let body = Bytes::from(serde_json::json!({ "SecretId": secret_id }).to_string());
+        let date = amz_date(now);
+
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1"),
+            ("host", host),
+            ("x-amz-date", date.as_str()),
+            ("x-amz-target", "secretsmanager.GetSecretValue"),
+        ];
+        if let Some(token) = &self.config.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+        let authorization = authorization(
+            &self.config,
+            SERVICE,
+            &Method::POST,
+            "",
+            &headers,
+            &hex::encode(Sha256::digest(&body)),
+            now,
+        );
+
+        let mut request = http::Request::builder()
+            .method(Method::POST)
+            .uri(format!("{endpoint}/"));
+        for (name, value) in &headers {
+            request = request.header(*name, *value);
+        }
+        request
+            .header(http::header::AUTHORIZATION, authorization)
+            .body(body)
+            .map_err(|e| ApiError::InternalServerError(format!("Invalid AWS request: {e}")))
+    }
+}
+
+#[async_trait]
+impl<T: SecretsTransport> SecretSource for AwsSecrets<T> {
+    async fn get(&self, name: &str) -> Result<String, ApiError> {
+        let (secret_id, field) = split_field(name);
+        let response = self
+            .transport
+            .send(self.request(secret_id, Utc::now())?)
+            .await?;
+        check(&response, "Secrets Manager")?;
+        let invalid = |e: serde_json::Error| {
+            ApiError::InternalServerError(format!("Invalid secret `{secret_id}`: {e}"))
+        };
+        let body: Value = serde_json::from_slice(response.body()).map_err(invalid)?;
+        let secret = json_field(&body, "SecretString", name)?;
+        match field {
+            None => Ok(secret),
+            Some(field) => {
+                let json: Value = serde_json::from_str(&secret).map_err(invalid)?;
+                json_field(&json, field, name)
+            }
+        }
+    }
+}
+
+/// `Authorization` header for a request signing `headers`, which must be
+/// sorted by name and include `host` and `x-amz-date`, on path `/`
+fn authorization(
+    config: &AwsSecretsConfig,
+    service: &str,
+    method: &Method,
+    query: &str,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request =
+        format!("{method}\n/\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}");
+
+    let day = now.format("%Y%m%d").to_string();
+    let scope = format!("{day}/{}/{service}/aws4_request", config.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{scope}\n{}",
+        amz_date(now),
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut key = format!("AWS4{}", config.secret_access_key).into_bytes();
+    for part in [
+        day.as_str(),
+        &config.region,
+        service,
+        "aws4_request",
+        &string_to_sign,
+    ] {
+        key = hmac(&key, part);
+    }
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope},SignedHeaders={signed_headers},Signature={}",
+        config.access_key_id,
+        hex::encode(key)
+    )
+}
+
+fn amz_date(now: DateTime<Utc>) -> String {
+    now.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config() -> AwsSecretsConfig {
+        AwsSecretsConfig {
+            region: "us-east-1".to_string(),
+            endpoint: None,
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        }
+    }
+
+    #[test]
+    fn signs_like_aws() {
+        // The IAM ListUsers example from the Signature Version 4 documentation
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let authorization = authorization(
+            &config(),
+            "iam",
+            &Method::GET,
+            "Action=ListUsers&Version=2010-05-08",
+            &[
+                (
+                    "content-type",
+                    "application/x-www-form-urlencoded; charset=utf-8",
+                ),
+                ("host", "iam.amazonaws.com"),
+                ("x-amz-date", "20150830T123600Z"),
+            ],
+            &hex::encode(Sha256::digest(b"")),
+            now,
+        );
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request,\
+             SignedHeaders=content-type;host;x-amz-date,\
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[tokio::test]
+    async fn gets_secret_strings_and_their_fields() {
+        let secrets = AwsSecrets::new(config(), |request: http::Request<Bytes>| async move {
+            assert_eq!(
+                request.uri(),
+                "https://secretsmanager.us-east-1.amazonaws.com/"
+            );
+            assert_eq!(
+                request.headers()["x-amz-target"],
+                "secretsmanager.GetSecretValue"
+            );
+            assert!(
+                request.headers()["authorization"]
+                    .to_str()
+                    .unwrap()
+                    .contains("/us-east-1/secretsmanager/aws4_request")
+            );
+            let body: Value = serde_json::from_slice(request.body()).unwrap();
+            let secret = match body["SecretId"].as_str().unwrap() {
+                "prod/jwt" => "s3cret",
+                _ => r#"{"username":"app","password":"hunter2"}"#,
+            };
+            let body = serde_json::json!({ "Name": "x", "SecretString": secret });
+            Ok(http::Response::new(Bytes::from(body.to_string())))
+        });
+
+        assert_eq!(secrets.get("prod/jwt").await.unwrap(), "s3cret");
+        assert_eq!(secrets.get("prod/db#password").await.unwrap(), "hunter2");
+        assert!(secrets.get("prod/db#port").await.is_err());
+        assert!(secrets.get("prod/jwt#field").await.is_err());
+    }
+}
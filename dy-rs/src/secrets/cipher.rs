@@ -0,0 +1,181 @@
+//! Encrypted config values
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, AeadCore, OsRng, Payload},
+};
+
+use crate::error::ApiError;
+
+/// Environment variable holding the [`MasterKey`]
+pub const MASTER_KEY_ENV: &str = "APP_CONFIG_KEY";
+
+/// Format version, the first byte of every ciphertext
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Decrypts `ENC(...)` config values
+///
+/// [`MasterKey`] is the built-in cipher, used by
+/// [`AppConfig::load`](crate::config::AppConfig::load) when `APP_CONFIG_KEY`
+/// is set. Apps with their own key handling implement this trait, e.g. on
+/// top of an AEAD crate they already use:
+///
+/// ```rust,ignore
+/// struct MasterKey(Aes256Gcm);
+///
+/// impl ConfigCipher for MasterKey {
+///     fn decrypt(&self, ciphertext: &str) -> Result<String, ApiError> {
+///         let bytes = BASE64_STANDARD.decode(ciphertext).map_err(bad)?;
+///         let (nonce, sealed) = bytes.split_at(12);
+///         let plain = self.0.decrypt(nonce.into(), sealed).map_err(bad)?;
+///         String::from_utf8(plain).map_err(bad)
+///     }
+/// }
+///
+/// let key = std::env::var("APP_CONFIG_KEY")?;
+/// let secrets = Secrets::new().cipher(MasterKey::new(&key));
+/// ```
+pub trait ConfigCipher: Send + Sync + 'static {
+    /// The plaintext of `ciphertext`, the text between `ENC(` and `)`
+    fn decrypt(&self, ciphertext: &str) -> Result<String, ApiError>;
+}
+
+impl<F> ConfigCipher for F
+where
+    F: Fn(&str) -> Result<String, ApiError> + Send + Sync + 'static,
+{
+    fn decrypt(&self, ciphertext: &str) -> Result<String, ApiError> {
+        self(ciphertext)
+    }
+}
+
+/// The built-in [`ConfigCipher`], keyed from `APP_CONFIG_KEY`
+///
+/// The key is 32 random bytes, base64-encoded; `dy secrets keygen` makes one
+/// and `dy secrets encrypt` turns a value into `ENC(...)`. Values are sealed
+/// with XChaCha20-Poly1305 under a random nonce, behind a version byte that
+/// is authenticated with them.
+#[derive(Clone)]
+pub struct MasterKey {
+    cipher: XChaCha20Poly1305,
+}
+
+impl MasterKey {
+    /// A key from its base64 form
+    pub fn new(key: &str) -> Result<Self, ApiError> {
+        let key = BASE64.decode(key.trim()).map_err(|_| {
+            ApiError::InternalServerError(format!("{MASTER_KEY_ENV} is not valid base64"))
+        })?;
+        let cipher = XChaCha20Poly1305::new_from_slice(&key).map_err(|_| {
+            ApiError::InternalServerError(format!("{MASTER_KEY_ENV} must be {KEY_LEN} bytes"))
+        })?;
+        Ok(Self { cipher })
+    }
+
+    /// The key in `APP_CONFIG_KEY`, or `None` if it isn't set
+    pub fn from_env() -> Result<Option<Self>, ApiError> {
+        match std::env::var(MASTER_KEY_ENV) {
+            Ok(key) if !key.trim().is_empty() => Self::new(&key).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// A new random key from the operating system, base64-encoded
+    pub fn generate() -> String {
+        BASE64.encode(XChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    /// `plaintext` as an `ENC(...)` config value
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: &[VERSION],
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .expect("config values fit in a ciphertext");
+
+        let mut sealed = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+        sealed.push(VERSION);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        format!("ENC({})", BASE64.encode(sealed))
+    }
+}
+
+impl ConfigCipher for MasterKey {
+    fn decrypt(&self, ciphertext: &str) -> Result<String, ApiError> {
+        let invalid = || {
+            ApiError::InternalServerError(format!(
+                "Encrypted config value can't be decrypted with {MASTER_KEY_ENV}"
+            ))
+        };
+        let sealed = BASE64.decode(ciphertext.trim()).map_err(|_| invalid())?;
+        if sealed.len() < 1 + NONCE_LEN + TAG_LEN || sealed[0] != VERSION {
+            return Err(invalid());
+        }
+        let (nonce, ciphertext) = sealed[1..].split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().map_err(|_| invalid())?;
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &[VERSION],
+        };
+        let plaintext = self
+            .cipher
+            .decrypt(&XNonce::from(nonce), payload)
+            .map_err(|_| invalid())?;
+        String::from_utf8(plaintext).map_err(|_| invalid())
+    }
+}
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MasterKey(***)")
+    }
+}
+
+/// The ciphertext of an `ENC(...)` value, if `value` is one
+pub(crate) fn encrypted(value: &str) -> Option<&str> {
+    value.trim().strip_prefix("ENC(")?.strip_suffix(')')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn master_keys_round_trip_and_detect_tampering() {
+        let key = MasterKey::new(&MasterKey::generate()).unwrap();
+        let value = key.encrypt("postgres://app:hunter2@db/app");
+        assert_ne!(value, key.encrypt("postgres://app:hunter2@db/app"));
+
+        let ciphertext = encrypted(&value).unwrap();
+        assert_eq!(
+            key.decrypt(ciphertext).unwrap(),
+            "postgres://app:hunter2@db/app"
+        );
+        assert_eq!(
+            key.decrypt(encrypted(&key.encrypt("")).unwrap()).unwrap(),
+            ""
+        );
+
+        let mut tampered = BASE64.decode(ciphertext).unwrap();
+        tampered[20] ^= 1;
+        assert!(key.decrypt(&BASE64.encode(tampered)).is_err());
+
+        let other = MasterKey::new(&MasterKey::generate()).unwrap();
+        assert!(other.decrypt(ciphertext).is_err());
+        assert!(key.decrypt("not base64!").is_err());
+
+        assert!(MasterKey::new("c2hvcnQ=").is_err());
+        assert!(MasterKey::new(&BASE64.encode([7; 48])).is_err());
+        assert_eq!(BASE64.decode(MasterKey::generate()).unwrap().len(), KEY_LEN);
+        assert_eq!(format!("{key:?}"), "MasterKey(***)");
+    }
+}
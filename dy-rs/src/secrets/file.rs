@@ -0,0 +1,73 @@
+//! Secrets mounted as files
+
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use super::SecretSource;
+use crate::error::ApiError;
+
+/// Secrets read from files in a directory, as Docker (`/run/secrets`) and
+/// Kubernetes secret volumes mount them. The name is the file name; a
+/// trailing newline is dropped.
+#[derive(Debug, Clone)]
+pub struct FileSecrets {
+    dir: PathBuf,
+}
+
+impl FileSecrets {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Secrets in `/run/secrets`, where Docker mounts them
+    pub fn docker() -> Self {
+        Self::new("/run/secrets")
+    }
+}
+
+#[async_trait]
+impl SecretSource for FileSecrets {
+    async fn get(&self, name: &str) -> Result<String, ApiError> {
+        let valid = !name.is_empty()
+            && name
+                .split('/')
+                .all(|part| !part.is_empty() && part != "." && part != "..");
+        if !valid {
+            return Err(ApiError::InternalServerError(format!(
+                "Invalid secret file name `{name}`"
+            )));
+        }
+        let path = self.dir.join(name);
+        let secret = tokio::fs::read_to_string(&path).await.map_err(|e| {
+            ApiError::InternalServerError(format!("Failed to read secret {}: {e}", path.display()))
+        })?;
+        Ok(secret
+            .strip_suffix('\n')
+            .map(|s| s.strip_suffix('\r').unwrap_or(s))
+            .unwrap_or(&secret)
+            .to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_mounted_files() {
+        let dir = std::env::temp_dir().join(format!("dy-rs-secrets-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("db")).unwrap();
+        std::fs::write(dir.join("jwt"), "s3cret\n").unwrap();
+        std::fs::write(dir.join("db/password"), "hunter2").unwrap();
+        let secrets = FileSecrets::new(&dir);
+
+        assert_eq!(secrets.get("jwt").await.unwrap(), "s3cret");
+        assert_eq!(secrets.get("db/password").await.unwrap(), "hunter2");
+        for bad in ["missing", "../jwt", "/etc/passwd", "db/../jwt", ""] {
+            assert!(secrets.get(bad).await.is_err(), "{bad} read");
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+//! Secrets in configuration values
+//!
+//! Config values can hold `${secret:<source>:<name>}` placeholders instead
+//! of the secret itself. [`Secrets`] maps source names to [`SecretSource`]s
+//! and fills the placeholders in when the configuration is loaded, so JWT
+//! secrets and database passwords stay out of TOML files and environment
+//! variables:
+//!
+//! ```toml
+//! [database]
+//! url = "postgres://app:${secret:file:db_password}@db/app"
+//!
+//! [auth]
+//! jwt_secret = "${secret:vault:app/jwt#secret}"
+//! ```
+//!
+//! ```rust,ignore
+//! let secrets = Secrets::new()
+//!     .source("file", FileSecrets::new("/run/secrets"))
+//!     .source("vault", VaultSecrets::new(VaultConfig::from_env(), transport.clone()))
+//!     .source("aws", AwsSecrets::new(AwsSecretsConfig::from_env(), transport));
+//!
+//! App::new().auto_configure_with_secrets(&secrets).await
+//! ```
+//!
+//! [`FileSecrets`] reads Docker and Kubernetes secrets mounted as files.
+//! [`VaultSecrets`] and [`AwsSecrets`] only build the API request; sending it
+//! is left to a [`SecretsTransport`], usually a closure around the app's HTTP
+//! client.
+//!
+//! Config that has to be committed can carry encrypted values instead,
+//! `jwt_secret = "ENC(base64...)"`, made with `dy secrets encrypt`.
+//! [`AppConfig::load`](crate::config::AppConfig::load) decrypts them with the
+//! [`MasterKey`] in the `APP_CONFIG_KEY` environment variable, which stays
+//! out of git; [`Secrets::cipher`] takes another [`ConfigCipher`].
+
+mod aws;
+mod cipher;
+mod file;
+mod vault;
+
+pub use aws::{AwsSecrets, AwsSecretsConfig};
+pub(crate) use cipher::encrypted;
+pub use cipher::{ConfigCipher, MASTER_KEY_ENV, MasterKey};
+pub use file::FileSecrets;
+pub use vault::{VaultConfig, VaultSecrets};
+
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{body::Bytes, http};
+use serde_json::Value;
+
+use crate::error::ApiError;
+
+const PLACEHOLDER_START: &str = "${secret:";
+
+/// Where secrets are looked up by name
+#[async_trait]
+pub trait SecretSource: Send + Sync + 'static {
+    /// The secret called `name`; an error if there is none
+    async fn get(&self, name: &str) -> Result<String, ApiError>;
+}
+
+/// Sends a secrets manager API request and returns the response
+#[async_trait]
+pub trait SecretsTransport: Send + Sync + 'static {
+    async fn send(&self, request: http::Request<Bytes>) -> Result<http::Response<Bytes>, ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> SecretsTransport for F
+where
+    F: Fn(http::Request<Bytes>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<http::Response<Bytes>, ApiError>> + Send,
+{
+    async fn send(&self, request: http::Request<Bytes>) -> Result<http::Response<Bytes>, ApiError> {
+        self(request).await
+    }
+}
+
+/// Named secret sources resolving `${secret:<source>:<name>}` placeholders,
+/// and the cipher decrypting `ENC(...)` values
+#[derive(Clone, Default)]
+pub struct Secrets {
+    sources: HashMap<String, Arc<dyn SecretSource>>,
+    cipher: Option<Arc<dyn ConfigCipher>>,
+}
+
+impl Secrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up `${secret:<name>:...}` placeholders in `source`
+    pub fn source(mut self, name: impl Into<String>, source: impl SecretSource) -> Self {
+        self.sources.insert(name.into(), Arc::new(source));
+        self
+    }
+
+    /// Decrypt `ENC(...)` values with `cipher` instead of the [`MasterKey`]
+    /// in `APP_CONFIG_KEY`
+    pub fn cipher(mut self, cipher: impl ConfigCipher) -> Self {
+        self.cipher = Some(Arc::new(cipher));
+        self
+    }
+
+    /// The secret `name` from the source called `source`
+    pub async fn get(&self, source: &str, name: &str) -> Result<String, ApiError> {
+        let found = self.sources.get(source).ok_or_else(|| {
+            ApiError::InternalServerError(format!("Unknown secret source `{source}`"))
+        })?;
+        found.get(name).await
+    }
+
+    /// `value` decrypted if it is `ENC(...)`, otherwise with every
+    /// placeholder replaced by its secret
+    pub async fn resolve(&self, value: &str) -> Result<String, ApiError> {
+        self.resolve_cached(value, &mut HashMap::new()).await
+    }
+
+    /// Resolve the placeholders in every string inside `value`, looking up
+    /// each distinct secret once
+    pub async fn resolve_value(&self, value: &mut Value) -> Result<(), ApiError> {
+        let mut cache = HashMap::new();
+        let mut pending = vec![value];
+        while let Some(value) = pending.pop() {
+            match value {
+                Value::String(s)
+                    if s.contains(PLACEHOLDER_START) || cipher::encrypted(s).is_some() =>
+                {
+                    *s = self.resolve_cached(s, &mut cache).await?;
+                }
+                Value::Array(items) => pending.extend(items.iter_mut()),
+                Value::Object(fields) => pending.extend(fields.values_mut()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn resolve_cached(
+        &self,
+        value: &str,
+        cache: &mut HashMap<String, String>,
+    ) -> Result<String, ApiError> {
+        if let Some(ciphertext) = cipher::encrypted(value) {
+            if let Some(cipher) = &self.cipher {
+                return cipher.decrypt(ciphertext);
+            }
+            let key = MasterKey::from_env()?.ok_or_else(|| {
+                ApiError::InternalServerError(format!(
+                    "Encrypted config value but no key; set {MASTER_KEY_ENV} or call Secrets::cipher"
+                ))
+            })?;
+            return key.decrypt(ciphertext);
+        }
+
+        let mut resolved = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find(PLACEHOLDER_START) {
+            resolved.push_str(&rest[..start]);
+            let after = &rest[start + PLACEHOLDER_START.len()..];
+            let end = after.find('}').ok_or_else(|| {
+                ApiError::InternalServerError(format!(
+                    "Unterminated secret placeholder in `{rest}`"
+                ))
+            })?;
+            let reference = &after[..end];
+            let secret = match cache.get(reference) {
+                Some(secret) => secret.clone(),
+                None => {
+                    let (source, name) = reference.split_once(':').ok_or_else(|| {
+                        ApiError::InternalServerError(format!(
+                            "Secret placeholder `{reference}` must be `<source>:<name>`"
+                        ))
+                    })?;
+                    let secret = self.get(source, name).await?;
+                    cache.insert(reference.to_string(), secret.clone());
+                    secret
+                }
+            };
+            resolved.push_str(&secret);
+            rest = &after[end + 1..];
+        }
+        resolved.push_str(rest);
+        Ok(resolved)
+    }
+}
+
+/// Error for a failed secrets manager response
+fn check(response: &http::Response<Bytes>, service: &str) -> Result<(), ApiError> {
+    if response.status().is_success() {
+        return Ok(());
+    }
+    let body = String::from_utf8_lossy(response.body());
+    Err(ApiError::InternalServerError(format!(
+        "{service} request failed with status {}: {}",
+        response.status(),
+        body.chars().take(200).collect::<String>()
+    )))
+}
+
+/// Splits `name#field` into the secret's name and the field to pick from it
+fn split_field(name: &str) -> (&str, Option<&str>) {
+    match name.rsplit_once('#') {
+        Some((name, field)) => (name, Some(field)),
+        None => (name, None),
+    }
+}
+
+/// `field` of the JSON object `json`, which must be a string
+fn json_field(json: &Value, field: &str, name: &str) -> Result<String, ApiError> {
+    json.get(field)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            ApiError::InternalServerError(format!("Secret `{name}` has no string field `{field}`"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Fixed(AtomicUsize);
+
+    #[async_trait]
+    impl SecretSource for Fixed {
+        async fn get(&self, name: &str) -> Result<String, ApiError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            match name {
+                "db" => Ok("hunter2".to_string()),
+                _ => Err(ApiError::NotFound(format!("secret {name}"))),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn replaces_placeholders_in_nested_values() {
+        let secrets = Secrets::new().source("test", Fixed(AtomicUsize::new(0)));
+
+        assert_eq!(
+            secrets
+                .resolve("postgres://app:${secret:test:db}@db/app")
+                .await
+                .unwrap(),
+            "postgres://app:hunter2@db/app"
+        );
+        assert_eq!(secrets.resolve("no secrets").await.unwrap(), "no secrets");
+
+        let mut value = serde_json::json!({
+            "database": { "url": "${secret:test:db}", "replicas": ["x${secret:test:db}"] },
+            "port": 3000,
+        });
+        secrets.resolve_value(&mut value).await.unwrap();
+        assert_eq!(value["database"]["url"], "hunter2");
+        assert_eq!(value["database"]["replicas"][0], "xhunter2");
+        assert_eq!(value["port"], 3000);
+
+        for bad in [
+            "${secret:test:missing}",
+            "${secret:other:db}",
+            "${secret:db}",
+            "${secret:test:db",
+        ] {
+            assert!(secrets.resolve(bad).await.is_err(), "{bad} resolved");
+        }
+    }
+
+    #[tokio::test]
+    async fn decrypts_encrypted_values() {
+        let reverse = |ciphertext: &str| -> Result<String, ApiError> {
+            Ok(ciphertext.chars().rev().collect())
+        };
+        let mut value = serde_json::json!({
+            "jwt_secret": "ENC(terces)",
+            "note": "not ENC(encrypted)",
+        });
+        assert!(
+            Secrets::new()
+                .resolve_value(&mut value.clone())
+                .await
+                .is_err()
+        );
+
+        let secrets = Secrets::new().cipher(reverse);
+        secrets.resolve_value(&mut value).await.unwrap();
+        assert_eq!(value["jwt_secret"], "secret");
+        assert_eq!(value["note"], "not ENC(encrypted)");
+        assert_eq!(secrets.resolve(" ENC(cba) ").await.unwrap(), "abc");
+    }
+
+    #[tokio::test]
+    async fn looks_up_each_secret_once_per_value() {
+        let source = Arc::new(Fixed(AtomicUsize::new(0)));
+        struct Counting(Arc<Fixed>);
+
+        #[async_trait]
+        impl SecretSource for Counting {
+            async fn get(&self, name: &str) -> Result<String, ApiError> {
+                self.0.get(name).await
+            }
+        }
+
+        let secrets = Secrets::new().source("test", Counting(source.clone()));
+        let mut value = serde_json::json!(["${secret:test:db}", "${secret:test:db}"]);
+        secrets.resolve_value(&mut value).await.unwrap();
+        assert_eq!(source.0.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,119 @@
+//! Secrets in a HashiCorp Vault KV version 2 engine
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    http::{self, Method},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{SecretSource, SecretsTransport, check, json_field, split_field};
+use crate::error::ApiError;
+
+/// Where Vault is and how to authenticate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultConfig {
+    /// e.g. `https://vault.internal:8200`
+    pub address: String,
+    pub token: String,
+    /// Mount path of the KV engine
+    pub mount: String,
+    /// Enterprise namespace, if any
+    pub namespace: Option<String>,
+}
+
+impl VaultConfig {
+    /// From `VAULT_ADDR`, `VAULT_TOKEN` and `VAULT_NAMESPACE`, with the KV
+    /// engine at `secret`
+    pub fn from_env() -> Self {
+        Self {
+            address: std::env::var("VAULT_ADDR")
+                .unwrap_or_else(|_| "http://127.0.0.1:8200".to_string()),
+            token: std::env::var("VAULT_TOKEN").unwrap_or_default(),
+            mount: "secret".to_string(),
+            namespace: std::env::var("VAULT_NAMESPACE").ok(),
+        }
+    }
+}
+
+/// Secrets read from Vault's KV v2 engine. Names are `path#field`, e.g.
+/// `app/db#password`; the field defaults to `value`.
+pub struct VaultSecrets<T> {
+    config: VaultConfig,
+    transport: T,
+}
+
+impl<T: SecretsTransport> VaultSecrets<T> {
+    pub fn new(config: VaultConfig, transport: T) -> Self {
+        Self { config, transport }
+    }
+
+    fn request(&self, path: &str) -> Result<http::Request<Bytes>, ApiError> {
+        let url = format!(
+            "{}/v1/{}/data/{}",
+            self.config.address.trim_end_matches('/'),
+            self.config.mount.trim_matches('/'),
+            path.trim_start_matches('/')
+        );
+        let mut request = http::Request::builder()
+            .method(Method::GET)
+            .uri(url)
+            .header("x-vault-token", &self.config.token);
+        if let Some(namespace) = &self.config.namespace {
+            request = request.header("x-vault-namespace", namespace);
+        }
+        request
+            .body(Bytes::new())
+            .map_err(|e| ApiError::InternalServerError(format!("Invalid Vault request: {e}")))
+    }
+}
+
+#[async_trait]
+impl<T: SecretsTransport> SecretSource for VaultSecrets<T> {
+    async fn get(&self, name: &str) -> Result<String, ApiError> {
+        let (path, field) = split_field(name);
+        let response = self.transport.send(self.request(path)?).await?;
+        check(&response, "Vault")?;
+        let body: Value = serde_json::from_slice(response.body()).map_err(|e| {
+            ApiError::InternalServerError(format!("Invalid Vault response for `{path}`: {e}"))
+        })?;
+        json_field(&body["data"]["data"], field.unwrap_or("value"), name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn reads_fields_of_kv_secrets() {
+        let vault = VaultSecrets::new(
+            VaultConfig {
+                address: "https://vault.internal:8200/".to_string(),
+                token: "s.token".to_string(),
+                mount: "kv".to_string(),
+                namespace: Some("team".to_string()),
+            },
+            |request: http::Request<Bytes>| async move {
+                assert_eq!(request.headers()["x-vault-token"], "s.token");
+                assert_eq!(request.headers()["x-vault-namespace"], "team");
+                let status = match request.uri().path() {
+                    "/v1/kv/data/app/db" => StatusCode::OK,
+                    _ => StatusCode::NOT_FOUND,
+                };
+                let body = r#"{"data":{"data":{"password":"hunter2","value":"v"},"metadata":{}}}"#;
+                Ok(http::Response::builder()
+                    .status(status)
+                    .body(Bytes::from(body))
+                    .unwrap())
+            },
+        );
+
+        assert_eq!(vault.get("app/db#password").await.unwrap(), "hunter2");
+        assert_eq!(vault.get("app/db").await.unwrap(), "v");
+        assert!(vault.get("app/db#user").await.is_err());
+        assert!(vault.get("app/other").await.is_err());
+    }
+}
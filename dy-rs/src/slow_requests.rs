@@ -0,0 +1,256 @@
+//! Slow request detection
+//!
+//! [`SlowRequests`] logs a warning with the route, duration, user and
+//! request ID of every request taking longer than a threshold, and counts
+//! them in [`SlowRequestMetrics`], so tail latency shows up without an APM.
+//! Auto-configured apps read the `[slow_requests]` section:
+//!
+//! ```toml
+//! [slow_requests]
+//! threshold_ms = 1000
+//! # per route, as declared; `0` never warns
+//! routes = { "/reports/{id}" = 10000, "/events/stream" = 0 }
+//! ```
+//!
+//! The duration runs until the handler has answered, not until the body has
+//! been sent. The user is the subject of a valid bearer token, as in
+//! [`AuditContext`].
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    task,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{FromRequestParts, MatchedPath, Request},
+    response::Response,
+};
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+
+use crate::audit::AuditContext;
+
+/// Slow request thresholds, the `[slow_requests]` config section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlowRequestConfig {
+    /// Duration past which a request is slow; `0` disables detection
+    pub threshold_ms: u64,
+    /// Thresholds of given routes, keyed by their declared path, e.g.
+    /// `/orders/{id}`; `0` never warns
+    pub routes: BTreeMap<String, u64>,
+}
+
+impl Default for SlowRequestConfig {
+    fn default() -> Self {
+        Self {
+            threshold_ms: 1000,
+            routes: BTreeMap::new(),
+        }
+    }
+}
+
+impl SlowRequestConfig {
+    /// Threshold of `route`, or `None` if it's never slow
+    fn threshold(&self, route: &str) -> Option<Duration> {
+        let ms = self.routes.get(route).copied().unwrap_or(self.threshold_ms);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    }
+}
+
+/// Counts of [`SlowRequests`]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct SlowRequestMetrics {
+    /// Requests past their threshold
+    pub slow_requests: u64,
+    /// The same, by route
+    pub routes: BTreeMap<String, u64>,
+}
+
+/// Warns about requests past their threshold; see the [module docs](self)
+///
+/// Clones share their settings and [`metrics`](Self::metrics).
+#[derive(Debug, Clone, Default)]
+pub struct SlowRequests {
+    settings: Arc<RwLock<SlowRequestConfig>>,
+    slow_requests: Arc<AtomicU64>,
+    routes: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl SlowRequests {
+    pub fn new(config: SlowRequestConfig) -> Self {
+        let slow_requests = Self::default();
+        slow_requests.configure(config);
+        slow_requests
+    }
+
+    pub fn configure(&self, config: SlowRequestConfig) {
+        *self.settings.write().unwrap() = config;
+    }
+
+    pub fn metrics(&self) -> SlowRequestMetrics {
+        SlowRequestMetrics {
+            slow_requests: self.slow_requests.load(Ordering::Relaxed),
+            routes: self.routes.lock().unwrap().clone(),
+        }
+    }
+
+    fn record(&self, route: &str) {
+        self.slow_requests.fetch_add(1, Ordering::Relaxed);
+        *self
+            .routes
+            .lock()
+            .unwrap()
+            .entry(route.to_string())
+            .or_default() += 1;
+    }
+}
+
+impl<S> Layer<S> for SlowRequests {
+    type Service = SlowRequestService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SlowRequestService {
+            inner,
+            slow_requests: self.clone(),
+        }
+    }
+}
+
+/// Service produced by [`SlowRequests`]
+#[derive(Clone)]
+pub struct SlowRequestService<S> {
+    inner: S,
+    slow_requests: SlowRequests,
+}
+
+impl<S> Service<Request> for SlowRequestService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| request.uri().path().to_string());
+        let threshold = self
+            .slow_requests
+            .settings
+            .read()
+            .unwrap()
+            .threshold(&route);
+        let Some(threshold) = threshold else {
+            return Box::pin(self.inner.call(request));
+        };
+        let method = request.method().clone();
+        // Who made the request, before the handler takes it
+        let (mut parts, body) = request.into_parts();
+        let context = AuditContext::from_request_parts(&mut parts, &())
+            .now_or_never()
+            .and_then(Result::ok)
+            .unwrap_or_default();
+        let request = Request::from_parts(parts, body);
+        let started = Instant::now();
+        let handled = self.inner.call(request);
+        let slow_requests = self.slow_requests.clone();
+
+        Box::pin(async move {
+            let response = handled.await?;
+            let elapsed = started.elapsed();
+            if elapsed > threshold {
+                slow_requests.record(&route);
+                tracing::warn!(
+                    %method,
+                    route,
+                    status = response.status().as_u16(),
+                    duration_ms = elapsed.as_millis() as u64,
+                    threshold_ms = threshold.as_millis() as u64,
+                    user = context.actor.as_deref().unwrap_or("anonymous"),
+                    request_id = context.request_id.as_deref().unwrap_or_default(),
+                    "Slow request"
+                );
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    fn router(slow_requests: &SlowRequests) -> Router {
+        Router::new()
+            .route("/fast", get(|| async { "fast" }))
+            .route(
+                "/reports/{id}",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    "report"
+                }),
+            )
+            .layer(slow_requests.clone())
+    }
+
+    async fn get_path(router: &Router, path: &str) {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        router.clone().oneshot(request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_past_the_threshold_are_counted_by_route() {
+        let slow_requests = SlowRequests::new(SlowRequestConfig {
+            threshold_ms: 10,
+            ..SlowRequestConfig::default()
+        });
+        let router = router(&slow_requests);
+
+        get_path(&router, "/fast").await;
+        get_path(&router, "/reports/1").await;
+        get_path(&router, "/reports/2").await;
+
+        let metrics = slow_requests.metrics();
+        assert_eq!(metrics.slow_requests, 2);
+        assert_eq!(metrics.routes["/reports/{id}"], 2);
+        assert!(!metrics.routes.contains_key("/fast"));
+    }
+
+    #[tokio::test]
+    async fn route_overrides_replace_the_threshold() {
+        let slow_requests = SlowRequests::new(SlowRequestConfig {
+            threshold_ms: 10,
+            routes: BTreeMap::from([("/reports/{id}".to_string(), 0)]),
+        });
+        let router = router(&slow_requests);
+
+        get_path(&router, "/reports/1").await;
+        assert_eq!(slow_requests.metrics().slow_requests, 0);
+
+        slow_requests.configure(SlowRequestConfig {
+            threshold_ms: 0,
+            routes: BTreeMap::from([("/reports/{id}".to_string(), 5)]),
+        });
+        get_path(&router, "/reports/1").await;
+        assert_eq!(slow_requests.metrics().slow_requests, 1);
+    }
+}
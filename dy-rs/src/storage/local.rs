@@ -0,0 +1,371 @@
+//! Objects stored as files under a directory
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use axum::{
+    Json, Router,
+    body::{Body, Bytes},
+    extract::{self, Query, State},
+    http::{HeaderMap, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use futures_util::{StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::io::AsyncWriteExt;
+
+use super::{ByteStream, ObjectInfo, PresignMethod, Storage, encode_key, validate_key};
+use crate::{download::FileDownload, error::ApiError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The `[storage.local]` section of the app config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalConfig {
+    /// Directory objects are written to
+    pub dir: PathBuf,
+    /// Path [`LocalStorage::routes`] are mounted at
+    pub base_path: String,
+    /// Signs presigned URLs; without it they can't be created
+    pub secret: Option<String>,
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("storage"),
+            base_path: "/storage".to_string(),
+            secret: None,
+        }
+    }
+}
+
+/// Storage that keeps each object in a file named after its key
+///
+/// Presigned URLs point at [`routes`](Self::routes), which check the
+/// signature and serve or accept the file.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    dir: PathBuf,
+    base_path: String,
+    secret: Option<String>,
+}
+
+impl LocalStorage {
+    /// Store objects under `dir` (created on first write)
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::from_config(&LocalConfig {
+            dir: dir.into(),
+            ..LocalConfig::default()
+        })
+    }
+
+    pub fn from_config(config: &LocalConfig) -> Self {
+        Self {
+            dir: config.dir.clone(),
+            base_path: config.base_path.trim_end_matches('/').to_string(),
+            secret: config.secret.clone(),
+        }
+    }
+
+    /// Sign presigned URLs with `secret`, for routes mounted at `base_path`
+    pub fn presign(mut self, base_path: impl Into<String>, secret: impl Into<String>) -> Self {
+        self.base_path = base_path.into().trim_end_matches('/').to_string();
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Directory the storage writes to
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the file holding the object under `key`
+    pub fn path(&self, key: &str) -> Result<PathBuf, ApiError> {
+        validate_key(key)?;
+        Ok(self.dir.join(key))
+    }
+
+    /// `GET` and `PUT {base_path}/{key}` for presigned URLs
+    pub fn routes(&self) -> Router {
+        Router::new()
+            .route(
+                &format!("{}/{{*key}}", self.base_path),
+                get(download).put(upload),
+            )
+            .with_state(self.clone())
+    }
+
+    fn signature(
+        &self,
+        secret: &str,
+        method: PresignMethod,
+        key: &str,
+        expires: u64,
+    ) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}\n{key}\n{expires}", method.as_str()).as_bytes());
+        mac
+    }
+
+    fn check_signature(
+        &self,
+        method: PresignMethod,
+        key: &str,
+        query: &Presigned,
+    ) -> Result<(), ApiError> {
+        let secret = self.secret.as_deref().ok_or(ApiError::Forbidden)?;
+        let signature = hex::decode(&query.sig).map_err(|_| ApiError::Forbidden)?;
+        if unix_now() > query.expires
+            || self
+                .signature(secret, method, key, query.expires)
+                .verify_slice(&signature)
+                .is_err()
+        {
+            return Err(ApiError::Forbidden);
+        }
+        Ok(())
+    }
+
+    /// Write to a temporary file, then move it into place
+    async fn write(
+        &self,
+        key: &str,
+        mut stream: ByteStream<'_>,
+        content_type: Option<&str>,
+    ) -> Result<ObjectInfo, ApiError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+        }
+        let partial = path.with_file_name(format!(
+            ".{}.{}.partial",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            uuid::Uuid::new_v4().simple()
+        ));
+
+        let written = async {
+            let mut file = tokio::fs::File::create(&partial).await.map_err(io_error)?;
+            let mut size = 0;
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk.map_err(super::stream_error)?;
+                file.write_all(&chunk).await.map_err(io_error)?;
+                size += chunk.len() as u64;
+            }
+            file.flush().await.map_err(io_error)?;
+            tokio::fs::rename(&partial, &path).await.map_err(io_error)?;
+            Ok(size)
+        }
+        .await;
+        let size = match written {
+            Ok(size) => size,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(err);
+            }
+        };
+
+        Ok(ObjectInfo {
+            key: key.to_string(),
+            size,
+            content_type: content_type.map(str::to_string),
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(
+        &self,
+        key: &str,
+        data: Bytes,
+        content_type: Option<&str>,
+    ) -> Result<ObjectInfo, ApiError> {
+        self.write(
+            key,
+            futures_util::stream::iter([Ok(data)]).boxed(),
+            content_type,
+        )
+        .await
+    }
+
+    async fn put_stream(
+        &self,
+        key: &str,
+        stream: ByteStream<'_>,
+        content_type: Option<&str>,
+    ) -> Result<ObjectInfo, ApiError> {
+        self.write(key, stream, content_type).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, ApiError> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data.into())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ApiError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn presigned_url(
+        &self,
+        key: &str,
+        method: PresignMethod,
+        expires_in: Duration,
+    ) -> Result<String, ApiError> {
+        validate_key(key)?;
+        let secret = self.secret.as_deref().ok_or_else(|| {
+            ApiError::InternalServerError("LocalStorage needs a secret to presign URLs".to_string())
+        })?;
+        let expires = unix_now() + expires_in.as_secs();
+        let sig = hex::encode(
+            self.signature(secret, method, key, expires)
+                .finalize()
+                .into_bytes(),
+        );
+        Ok(format!(
+            "{}/{}?expires={expires}&sig={sig}",
+            self.base_path,
+            encode_key(key)
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Presigned {
+    expires: u64,
+    sig: String,
+}
+
+async fn download(
+    State(storage): State<LocalStorage>,
+    extract::Path(key): extract::Path<String>,
+    Query(query): Query<Presigned>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    storage.check_signature(PresignMethod::Get, &key, &query)?;
+    FileDownload::new(storage.path(&key)?).serve(&headers).await
+}
+
+async fn upload(
+    State(storage): State<LocalStorage>,
+    extract::Path(key): extract::Path<String>,
+    Query(query): Query<Presigned>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, ApiError> {
+    storage.check_signature(PresignMethod::Put, &key, &query)?;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    let stream = body.into_data_stream().map_err(io::Error::other).boxed();
+    let info = storage.put_stream(&key, stream, content_type).await?;
+    Ok(Json(info).into_response())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn io_error(e: io::Error) -> ApiError {
+    ApiError::InternalServerError(format!("Storage error: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    fn temp_storage() -> LocalStorage {
+        LocalStorage::new(
+            std::env::temp_dir().join(format!("dy-rs-storage-{}", uuid::Uuid::new_v4())),
+        )
+        .presign("/files", "secret")
+    }
+
+    #[tokio::test]
+    async fn stores_reads_and_deletes_objects() {
+        let storage = temp_storage();
+        let chunks = futures_util::stream::iter(["hello ", "world"].map(|c| Ok(Bytes::from(c))));
+        let info = storage
+            .put_stream("docs/hello.txt", chunks.boxed(), Some("text/plain"))
+            .await
+            .unwrap();
+        assert_eq!(info.size, 11);
+        assert_eq!(
+            storage.get("docs/hello.txt").await.unwrap().unwrap(),
+            "hello world"
+        );
+
+        storage.delete("docs/hello.txt").await.unwrap();
+        assert!(storage.get("docs/hello.txt").await.unwrap().is_none());
+        storage.delete("docs/hello.txt").await.unwrap();
+        assert!(storage.get("../escape").await.is_err());
+
+        tokio::fs::remove_dir_all(storage.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn presigned_urls_allow_only_their_method() {
+        let storage = temp_storage();
+        let app = storage.routes();
+        let put_url = storage
+            .presigned_url("a b.txt", PresignMethod::Put, Duration::from_secs(60))
+            .unwrap();
+        assert!(put_url.starts_with("/files/a%20b.txt?expires="));
+
+        let res = app
+            .clone()
+            .oneshot(Request::put(&put_url).body(Body::from("uploaded")).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(storage.get("a b.txt").await.unwrap().unwrap(), "uploaded");
+
+        // A PUT signature doesn't allow downloads
+        let res = app
+            .clone()
+            .oneshot(Request::get(&put_url).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let get_url = storage
+            .presigned_url("a b.txt", PresignMethod::Get, Duration::from_secs(60))
+            .unwrap();
+        let res = app
+            .clone()
+            .oneshot(Request::get(&get_url).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let tampered = get_url.replace("expires=", "expires=9");
+        let res = app
+            .oneshot(Request::get(&tampered).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        tokio::fs::remove_dir_all(storage.dir()).await.unwrap();
+    }
+}
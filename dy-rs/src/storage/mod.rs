@@ -0,0 +1,300 @@
+//! Object storage
+//!
+//! Files the app keeps (avatars, exports, uploads) go through a [`Storage`]:
+//! [`LocalStorage`] writes them under a directory and [`S3Storage`] to an
+//! S3-compatible bucket (AWS, MinIO, R2...). Which one is used comes from
+//! `[storage]`:
+//!
+//! ```toml
+//! [storage]
+//! backend = "s3"              # or "local" (the default)
+//!
+//! [storage.local]
+//! dir = "storage"
+//! base_path = "/storage"      # where `LocalStorage::routes` are mounted
+//! secret = "change-me"        # signs presigned URLs
+//!
+//! [storage.s3]
+//! bucket = "acme-uploads"
+//! region = "eu-west-1"
+//! endpoint = "http://localhost:9000"  # for S3-compatible services
+//! path_style = true
+//! access_key_id = "..."
+//! secret_access_key = "..."
+//! ```
+//!
+//! ```rust,ignore
+//! let storage = SharedStorage::from(config.storage.storage(s3_transport));
+//! App::new().auto_configure().with_storage(storage.clone());
+//!
+//! async fn upload_avatar(storage: SharedStorage, user: AuthUser, mut form: Multipart) -> ApiResult<ObjectInfo> {
+//!     let field = form.next_field().await?.ok_or_else(|| ApiError::BadRequest("No file".into()))?;
+//!     let info = field.store(&storage, &format!("avatars/{}", user.id)).await?;
+//!     Ok(Json(info))
+//! }
+//! ```
+//!
+//! Keys are `/`-separated paths such as `avatars/42.png`; empty segments,
+//! `.` and `..` are rejected so a key can't escape its bucket or directory.
+
+mod local;
+mod s3;
+
+use std::{io, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use axum::{body::Bytes, extract::FromRequestParts, http::request::Parts};
+use futures_util::{StreamExt, stream::BoxStream};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+
+pub use local::{LocalConfig, LocalStorage};
+pub use s3::{S3Config, S3Storage, S3Transport};
+
+/// Chunks of an object being stored
+pub type ByteStream<'a> = BoxStream<'a, io::Result<Bytes>>;
+
+/// A stored object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+}
+
+/// What a presigned URL lets its holder do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresignMethod {
+    /// Download the object
+    Get,
+    /// Upload the object's content
+    Put,
+}
+
+impl PresignMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PresignMethod::Get => "GET",
+            PresignMethod::Put => "PUT",
+        }
+    }
+}
+
+/// Stores objects by key
+#[async_trait]
+pub trait Storage: Send + Sync + 'static {
+    /// Store `data` under `key`, replacing any existing object
+    async fn put(
+        &self,
+        key: &str,
+        data: Bytes,
+        content_type: Option<&str>,
+    ) -> Result<ObjectInfo, ApiError>;
+
+    /// Store what `stream` yields under `key`
+    ///
+    /// The default collects the stream and calls [`put`](Self::put); backends
+    /// that can write incrementally override it.
+    async fn put_stream(
+        &self,
+        key: &str,
+        mut stream: ByteStream<'_>,
+        content_type: Option<&str>,
+    ) -> Result<ObjectInfo, ApiError> {
+        let mut data = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            data.extend_from_slice(&chunk.map_err(stream_error)?);
+        }
+        self.put(key, data.into(), content_type).await
+    }
+
+    /// The object's content, or `None` if there's no object under `key`
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, ApiError>;
+
+    /// Remove the object under `key`; removing a missing object succeeds
+    async fn delete(&self, key: &str) -> Result<(), ApiError>;
+
+    /// A URL letting anyone who has it `method` the object without
+    /// credentials, until `expires_in` has passed
+    fn presigned_url(
+        &self,
+        key: &str,
+        method: PresignMethod,
+        expires_in: Duration,
+    ) -> Result<String, ApiError>;
+}
+
+/// A [`Storage`] shared between handlers
+///
+/// Added to requests by [`App::with_storage`](crate::App::with_storage), and
+/// usable as an extractor or as a field of the app state.
+#[derive(Clone)]
+pub struct SharedStorage(Arc<dyn Storage>);
+
+impl SharedStorage {
+    pub fn new(storage: impl Storage) -> Self {
+        Self(Arc::new(storage))
+    }
+}
+
+impl From<Arc<dyn Storage>> for SharedStorage {
+    fn from(storage: Arc<dyn Storage>) -> Self {
+        Self(storage)
+    }
+}
+
+#[async_trait]
+impl Storage for SharedStorage {
+    async fn put(
+        &self,
+        key: &str,
+        data: Bytes,
+        content_type: Option<&str>,
+    ) -> Result<ObjectInfo, ApiError> {
+        self.0.put(key, data, content_type).await
+    }
+
+    async fn put_stream(
+        &self,
+        key: &str,
+        stream: ByteStream<'_>,
+        content_type: Option<&str>,
+    ) -> Result<ObjectInfo, ApiError> {
+        self.0.put_stream(key, stream, content_type).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, ApiError> {
+        self.0.get(key).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ApiError> {
+        self.0.delete(key).await
+    }
+
+    fn presigned_url(
+        &self,
+        key: &str,
+        method: PresignMethod,
+        expires_in: Duration,
+    ) -> Result<String, ApiError> {
+        self.0.presigned_url(key, method, expires_in)
+    }
+}
+
+/// Rejects with `500` when no storage was added with `App::with_storage`
+impl<S> FromRequestParts<S> for SharedStorage
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Self>().cloned().ok_or_else(|| {
+            ApiError::InternalServerError(
+                "SharedStorage needs a storage added with App::with_storage".to_string(),
+            )
+        })
+    }
+}
+
+/// Which backend stores objects
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// `[storage.local]`
+    #[default]
+    Local,
+    /// `[storage.s3]`
+    S3,
+}
+
+/// The `[storage]` section of the app config
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    pub local: LocalConfig,
+    pub s3: S3Config,
+}
+
+impl StorageConfig {
+    /// The configured backend
+    ///
+    /// S3 requests are sent with `transport`, which the local backend
+    /// doesn't use, so the same code works with either.
+    pub fn storage(&self, transport: impl S3Transport) -> Arc<dyn Storage> {
+        match self.backend {
+            StorageBackend::Local => Arc::new(LocalStorage::from_config(&self.local)),
+            StorageBackend::S3 => Arc::new(S3Storage::new(self.s3.clone(), transport)),
+        }
+    }
+}
+
+/// Reject keys that are empty or could escape the bucket or directory
+pub(crate) fn validate_key(key: &str) -> Result<(), ApiError> {
+    let valid = !key.is_empty()
+        && key.len() <= 1024
+        && !key.contains(|c: char| c.is_control() || c == '\\')
+        && key
+            .split('/')
+            .all(|segment| !matches!(segment, "" | "." | ".."));
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::BadRequest(format!(
+            "Invalid storage key: {key:?}"
+        )))
+    }
+}
+
+/// Percent-encode `key` for a URL path, keeping the `/` between segments
+pub(crate) fn encode_key(key: &str) -> String {
+    percent_encode(key, true)
+}
+
+/// Percent-encode everything but unreserved characters (and `/` if
+/// `keep_slash`), as S3 signatures expect
+fn percent_encode(text: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            b'/' if keep_slash => out.push('/'),
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    out
+}
+
+fn stream_error(e: io::Error) -> ApiError {
+    ApiError::BadRequest(format!("Failed to read upload: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_stay_inside_the_store() {
+        assert!(validate_key("avatars/42.png").is_ok());
+        assert!(validate_key("a/.hidden").is_ok());
+        for key in ["", "/etc/passwd", "a/../../b", "a//b", "./a", "a\\b", "a/"] {
+            assert!(validate_key(key).is_err(), "{key}");
+        }
+    }
+
+    #[test]
+    fn config_picks_the_backend() {
+        let config: StorageConfig = serde_json::from_value(serde_json::json!({
+            "backend": "s3",
+            "s3": { "bucket": "acme", "region": "eu-west-1" }
+        }))
+        .unwrap();
+        assert_eq!(config.backend, StorageBackend::S3);
+        assert_eq!(config.s3.bucket, "acme");
+        assert_eq!(config.local.dir, std::path::PathBuf::from("storage"));
+    }
+}
@@ -0,0 +1,212 @@
+//! Background tasks tied to the app's lifetime
+//!
+//! Work spawned with a bare `tokio::spawn` from a handler is lost when the
+//! server stops, and a panic in it only shows up as a stray log line. Tasks
+//! spawned on the [`TaskScope`] instead run inside the span of the request
+//! that started them, have panics logged with the task's name, and are
+//! waited for when the server shuts down:
+//!
+//! ```rust,ignore
+//! async fn create_order(tasks: TaskScope, Json(order): Json<NewOrder>) -> ApiResult<Order> {
+//!     let order = save(order).await?;
+//!     let receipt = order.clone();
+//!     tasks.spawn("send_receipt", async move { mailer.send_receipt(&receipt).await });
+//!     Ok(Json(order))
+//! }
+//! ```
+//!
+//! [`App::run`](crate::App::run) stops accepting requests on `Ctrl+C` or
+//! `SIGTERM`, then gives running tasks `server.shutdown_timeout_secs`
+//! (default 30) to finish. Long-running tasks can watch
+//! [`TaskScope::cancelled`] to stop early.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use tokio::sync::{Notify, watch};
+use tracing::Instrument;
+
+/// Spawns tasks that outlive the request but not the app
+#[derive(Clone)]
+pub struct TaskScope {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    active: AtomicUsize,
+    idle: Notify,
+    cancelled: watch::Sender<bool>,
+}
+
+impl TaskScope {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                active: AtomicUsize::new(0),
+                idle: Notify::new(),
+                cancelled: watch::Sender::new(false),
+            }),
+        }
+    }
+
+    /// Run `task` in the background, in the current tracing span
+    ///
+    /// Returns `false` without running it once shutdown has started.
+    pub fn spawn<F>(&self, name: &'static str, task: F) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        if self.is_cancelled() {
+            tracing::warn!(task = name, "not spawning task during shutdown");
+            return false;
+        }
+
+        self.inner.active.fetch_add(1, Ordering::SeqCst);
+        let guard = Running {
+            name,
+            inner: self.inner.clone(),
+        };
+        let span = tracing::info_span!("task", task = name);
+        tokio::spawn(
+            async move {
+                let _guard = guard;
+                task.await;
+            }
+            .instrument(span),
+        );
+        true
+    }
+
+    /// Number of tasks still running
+    pub fn active(&self) -> usize {
+        self.inner.active.load(Ordering::SeqCst)
+    }
+
+    /// Whether shutdown has started
+    pub fn is_cancelled(&self) -> bool {
+        *self.inner.cancelled.borrow()
+    }
+
+    /// Completes once shutdown has started
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.inner.cancelled.subscribe();
+        // The sender lives as long as `self`, so this can't fail
+        let _ = cancelled.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Stop accepting tasks and wait up to `timeout` for running ones
+    ///
+    /// Returns `true` if every task finished in time.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.inner.cancelled.send_replace(true);
+        let idle = async {
+            loop {
+                let notified = self.inner.idle.notified();
+                if self.active() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        let drained = tokio::time::timeout(timeout, idle).await.is_ok();
+        if !drained {
+            tracing::warn!(
+                remaining = self.active(),
+                "background tasks still running after {:?}",
+                timeout
+            );
+        }
+        drained
+    }
+}
+
+impl Default for TaskScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counts a task as running until it finishes, panics or is aborted
+struct Running {
+    name: &'static str,
+    inner: Arc<Inner>,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            tracing::error!(task = self.name, "background task panicked");
+        }
+        if self.inner.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+/// The app's scope, or a detached one when the router wasn't built by
+/// [`App`](crate::App) and no `Extension(TaskScope)` was added
+impl<S> FromRequestParts<S> for TaskScope
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn drain_waits_for_running_tasks() {
+        let scope = TaskScope::new();
+        let (release, released) = oneshot::channel::<()>();
+        let (done_tx, done) = oneshot::channel();
+        scope.spawn("slow", async move {
+            let _ = released.await;
+            let _ = done_tx.send(());
+        });
+        assert_eq!(scope.active(), 1);
+
+        assert!(!scope.drain(Duration::from_millis(20)).await);
+        release.send(()).unwrap();
+        assert!(scope.drain(Duration::from_secs(1)).await);
+        done.await.unwrap();
+        assert_eq!(scope.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn panics_are_contained_and_counted_as_finished() {
+        let scope = TaskScope::new();
+        scope.spawn("boom", async { panic!("boom") });
+        assert!(scope.drain(Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn no_new_tasks_after_shutdown_starts() {
+        let scope = TaskScope::new();
+        let watcher = scope.clone();
+        let (stopped_tx, stopped) = oneshot::channel();
+        scope.spawn("loop", async move {
+            watcher.cancelled().await;
+            let _ = stopped_tx.send(());
+        });
+
+        assert!(scope.drain(Duration::from_secs(1)).await);
+        stopped.await.unwrap();
+        assert!(scope.is_cancelled());
+        assert!(!scope.spawn("late", async {}));
+    }
+}
@@ -0,0 +1,121 @@
+//! Multi-tenancy: tenant resolution, per-tenant databases, caches and limits
+//!
+//! Each request names its tenant, by default in the `x-tenant-id` header. A
+//! [`TenantLayer`] resolves it some other way, from the subdomain, a token
+//! claim or any [`TenantResolver`], and inserts the [`TenantId`] into the
+//! request extensions instead. Handlers then take a [`TenantDb`] to get that
+//! tenant's pool from the [`TenantPools`] manager, or a [`TenantCache`] to
+//! cache per tenant, and [`TenantRateLimit`] gives each tenant its own
+//! request allowance:
+//!
+//! ```rust,ignore
+//! use dy_rs::tenancy::{TenantDb, TenantPools};
+//!
+//! async fn list_orders(db: TenantDb) -> ApiResult<Vec<Order>> {
+//!     let orders = sqlx::query_as("SELECT * FROM orders")
+//!         .fetch_all(&*db)
+//!         .await?;
+//!     Ok(Json(orders))
+//! }
+//!
+//! // database-per-tenant
+//! let pools = TenantPools::new("postgres://db.internal/shop_{tenant}")
+//!     .per_tenant_limit(5)
+//!     .global_limit(200);
+//! // or schema-per-tenant on one database
+//! let pools = TenantPools::new("postgres://db.internal/shop").schema("tenant_{tenant}");
+//!
+//! pools.spawn_evictor(Duration::from_secs(60));
+//! let router = Router::new()
+//!     .route("/orders", get(list_orders))
+//!     .layer(TenantRateLimit::new(100, Duration::from_secs(60)))
+//!     .layer(TenantLayer::new(SubdomainTenant::new("shop.example.com")))
+//!     .layer(Extension(pools));
+//! ```
+
+#[cfg(feature = "sqlx")]
+pub mod pools;
+pub mod resolve;
+pub mod scoped;
+
+#[cfg(feature = "sqlx")]
+pub use pools::{PoolMetrics, TenantDb, TenantPoolConfig, TenantPoolStats, TenantPools};
+#[cfg(feature = "auth")]
+pub use resolve::ClaimTenant;
+pub use resolve::{HeaderTenant, SubdomainTenant, TenantLayer, TenantResolver, TenantService};
+pub use scoped::{TenantCache, TenantRateLimit, TenantRateLimitService};
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+use crate::error::ApiError;
+
+/// Header naming the tenant of a request
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+const MAX_TENANT_ID_LEN: usize = 63;
+
+/// Identifier of the tenant a request belongs to.
+///
+/// Limited to ASCII letters, digits, `_` and `-` (at most 63 characters), so
+/// it can be substituted into database names and schema names as-is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TenantId(String);
+
+impl TenantId {
+    pub fn parse(id: &str) -> Result<Self, ApiError> {
+        let valid = !id.is_empty()
+            && id.len() <= MAX_TENANT_ID_LEN
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+        if valid {
+            Ok(Self(id.to_string()))
+        } else {
+            Err(ApiError::BadRequest(format!("Invalid tenant id `{id}`")))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for TenantId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<S> FromRequestParts<S> for TenantId
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(tenant) = parts.extensions.get::<TenantId>() {
+            return Ok(tenant.clone());
+        }
+        let header = parts
+            .headers
+            .get(TENANT_HEADER)
+            .ok_or_else(|| ApiError::BadRequest(format!("Missing {TENANT_HEADER} header")))?;
+        let id = header
+            .to_str()
+            .map_err(|_| ApiError::BadRequest(format!("Invalid {TENANT_HEADER} header")))?;
+        TenantId::parse(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tenant_ids_are_restricted_to_safe_characters() {
+        assert!(TenantId::parse("acme_co-2").is_ok());
+        for bad in ["", "acme co", "acme;drop", "ä", &"x".repeat(64)] {
+            assert!(TenantId::parse(bad).is_err(), "{bad:?} accepted");
+        }
+    }
+}
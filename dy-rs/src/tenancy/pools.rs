@@ -0,0 +1,492 @@
+//! Lazily created per-tenant connection pools
+//!
+//! [`TenantPools`] keeps one sqlx pool per tenant, created on the tenant's
+//! first request. Every pool is capped at a per-tenant connection limit, and
+//! the limits of all open pools together must stay within a global budget:
+//! when a new tenant doesn't fit, the least recently used pools with no
+//! connections in use are closed to make room, and the request is rejected
+//! if that isn't enough. Pools unused for longer than the idle timeout are
+//! closed by [`TenantPools::evict_idle`].
+
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    PgPool,
+    postgres::{PgConnectOptions, PgPoolOptions},
+};
+
+use super::TenantId;
+use crate::error::ApiError;
+
+/// Placeholder replaced by the tenant id in URL and schema templates
+const TENANT_PLACEHOLDER: &str = "{tenant}";
+
+/// Per-tenant pool settings, the `[tenancy]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantPoolConfig {
+    /// Connection URL; `{tenant}` is replaced by the tenant id for
+    /// database-per-tenant setups
+    pub url: String,
+    /// Schema put on the `search_path`, e.g. `tenant_{tenant}`, for
+    /// schema-per-tenant setups
+    pub schema: Option<String>,
+    /// Connection cap of each tenant's pool
+    pub max_connections_per_tenant: u32,
+    /// Cap on the summed limits of all open pools
+    pub max_connections: u32,
+    /// Limit overrides for individual tenants
+    pub tenant_limits: HashMap<String, u32>,
+    /// Seconds a pool may go unused before it is closed
+    pub idle_timeout_secs: u64,
+    /// Seconds to wait for a connection before giving up
+    pub acquire_timeout_secs: u64,
+}
+
+impl Default for TenantPoolConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            schema: None,
+            max_connections_per_tenant: 5,
+            max_connections: 100,
+            tenant_limits: HashMap::new(),
+            idle_timeout_secs: 600,
+            acquire_timeout_secs: 5,
+        }
+    }
+}
+
+struct TenantPool {
+    pool: PgPool,
+    max_connections: u32,
+    last_used: Instant,
+}
+
+impl TenantPool {
+    fn in_use(&self) -> u32 {
+        self.pool.size().saturating_sub(self.pool.num_idle() as u32)
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    created: AtomicU64,
+    evicted: AtomicU64,
+    rejected: AtomicU64,
+}
+
+struct Inner {
+    config: TenantPoolConfig,
+    pools: Mutex<HashMap<TenantId, TenantPool>>,
+    counters: Counters,
+}
+
+/// Manager of per-tenant pools, shared by cloning
+#[derive(Clone)]
+pub struct TenantPools {
+    inner: Arc<Inner>,
+}
+
+impl TenantPools {
+    /// Pools connecting to `url`, with `{tenant}` replaced by the tenant id
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::from_config(TenantPoolConfig {
+            url: url.into(),
+            ..TenantPoolConfig::default()
+        })
+    }
+
+    pub fn from_config(config: TenantPoolConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                pools: Mutex::new(HashMap::new()),
+                counters: Counters::default(),
+            }),
+        }
+    }
+
+    /// Put the tenant's schema on the `search_path`, e.g. `tenant_{tenant}`
+    pub fn schema(mut self, template: impl Into<String>) -> Self {
+        self.config_mut().schema = Some(template.into());
+        self
+    }
+
+    pub fn per_tenant_limit(mut self, max_connections: u32) -> Self {
+        self.config_mut().max_connections_per_tenant = max_connections;
+        self
+    }
+
+    /// Override the connection cap of one tenant
+    pub fn tenant_limit(mut self, tenant: impl Into<String>, max_connections: u32) -> Self {
+        self.config_mut()
+            .tenant_limits
+            .insert(tenant.into(), max_connections);
+        self
+    }
+
+    pub fn global_limit(mut self, max_connections: u32) -> Self {
+        self.config_mut().max_connections = max_connections;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config_mut().idle_timeout_secs = timeout.as_secs();
+        self
+    }
+
+    fn config_mut(&mut self) -> &mut TenantPoolConfig {
+        &mut Arc::get_mut(&mut self.inner)
+            .expect("TenantPools is configured before it is cloned")
+            .config
+    }
+
+    fn limit_for(&self, tenant: &TenantId) -> u32 {
+        let config = &self.inner.config;
+        config
+            .tenant_limits
+            .get(tenant.as_str())
+            .copied()
+            .unwrap_or(config.max_connections_per_tenant)
+    }
+
+    /// Connection options for `tenant`'s pool
+    pub fn connect_options(&self, tenant: &TenantId) -> Result<PgConnectOptions, ApiError> {
+        let config = &self.inner.config;
+        let url = config.url.replace(TENANT_PLACEHOLDER, tenant.as_str());
+        let options: PgConnectOptions = url.parse().map_err(|err| {
+            ApiError::InternalServerError(format!("Invalid tenant database URL: {err}"))
+        })?;
+        Ok(match &config.schema {
+            Some(schema) => {
+                let schema = schema.replace(TENANT_PLACEHOLDER, tenant.as_str());
+                options.options([("search_path", format!("\"{schema}\""))])
+            }
+            None => options,
+        })
+    }
+
+    /// The pool of `tenant`, created if it isn't open yet.
+    ///
+    /// Fails if the tenant's limit doesn't fit in the global budget even
+    /// after closing every pool with no connections in use.
+    pub async fn pool(&self, tenant: &TenantId) -> Result<PgPool, ApiError> {
+        let limit = self.limit_for(tenant);
+        let global = self.inner.config.max_connections;
+        let options = self.connect_options(tenant)?;
+
+        let (pool, evicted) = {
+            let mut pools = self.inner.pools.lock().unwrap();
+            if let Some(entry) = pools.get_mut(tenant) {
+                entry.last_used = Instant::now();
+                return Ok(entry.pool.clone());
+            }
+
+            let mut budget: u32 = pools.values().map(|p| p.max_connections).sum();
+            let mut idle: Vec<(&TenantId, &TenantPool)> =
+                pools.iter().filter(|(_, p)| p.in_use() == 0).collect();
+            idle.sort_by_key(|(_, p)| p.last_used);
+            let mut to_close = Vec::new();
+            for (id, entry) in idle {
+                if budget + limit <= global {
+                    break;
+                }
+                budget -= entry.max_connections;
+                to_close.push(id.clone());
+            }
+            if budget + limit > global {
+                self.inner.counters.rejected.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(%tenant, limit, global, "tenant connection budget exhausted");
+                return Err(ApiError::InternalServerError(
+                    "Tenant connection budget exhausted".to_string(),
+                ));
+            }
+            let evicted: Vec<_> = to_close
+                .into_iter()
+                .filter_map(|id| pools.remove(&id).map(|entry| (id, entry.pool)))
+                .collect();
+
+            let pool = PgPoolOptions::new()
+                .max_connections(limit)
+                .min_connections(0)
+                .idle_timeout(Duration::from_secs(self.inner.config.idle_timeout_secs))
+                .acquire_timeout(Duration::from_secs(self.inner.config.acquire_timeout_secs))
+                .connect_lazy_with(options);
+            pools.insert(
+                tenant.clone(),
+                TenantPool {
+                    pool: pool.clone(),
+                    max_connections: limit,
+                    last_used: Instant::now(),
+                },
+            );
+            (pool, evicted)
+        };
+
+        self.inner.counters.created.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(%tenant, limit, "opened tenant pool");
+        self.close(evicted).await;
+        Ok(pool)
+    }
+
+    /// Close pools unused for longer than the idle timeout, returning how
+    /// many were closed. Pools with connections in use are kept.
+    pub async fn evict_idle(&self) -> usize {
+        let timeout = Duration::from_secs(self.inner.config.idle_timeout_secs);
+        let evicted: Vec<_> = {
+            let mut pools = self.inner.pools.lock().unwrap();
+            let stale: Vec<TenantId> = pools
+                .iter()
+                .filter(|(_, p)| p.in_use() == 0 && p.last_used.elapsed() >= timeout)
+                .map(|(id, _)| id.clone())
+                .collect();
+            stale
+                .into_iter()
+                .filter_map(|id| pools.remove(&id).map(|entry| (id, entry.pool)))
+                .collect()
+        };
+        let count = evicted.len();
+        self.close(evicted).await;
+        count
+    }
+
+    /// Run [`evict_idle`](Self::evict_idle) every `interval`
+    pub fn spawn_evictor(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let pools = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                pools.evict_idle().await;
+            }
+        })
+    }
+
+    async fn close(&self, evicted: Vec<(TenantId, PgPool)>) {
+        for (tenant, pool) in evicted {
+            self.inner.counters.evicted.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!(%tenant, "closing tenant pool");
+            pool.close().await;
+        }
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        let pools = self.inner.pools.lock().unwrap();
+        let mut tenants: Vec<TenantPoolStats> = pools
+            .iter()
+            .map(|(id, p)| TenantPoolStats {
+                tenant: id.to_string(),
+                connections: p.pool.size(),
+                idle: p.pool.num_idle() as u32,
+                max_connections: p.max_connections,
+                idle_for_ms: p.last_used.elapsed().as_millis() as u64,
+            })
+            .collect();
+        tenants.sort_by(|a, b| a.tenant.cmp(&b.tenant));
+        let counters = &self.inner.counters;
+        PoolMetrics {
+            connections: tenants.iter().map(|t| t.connections).sum(),
+            reserved: tenants.iter().map(|t| t.max_connections).sum(),
+            global_limit: self.inner.config.max_connections,
+            created: counters.created.load(Ordering::Relaxed),
+            evicted: counters.evicted.load(Ordering::Relaxed),
+            rejected: counters.rejected.load(Ordering::Relaxed),
+            tenants,
+        }
+    }
+}
+
+/// Snapshot of one tenant's pool
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TenantPoolStats {
+    pub tenant: String,
+    /// Open connections
+    pub connections: u32,
+    pub idle: u32,
+    pub max_connections: u32,
+    /// Time since the pool was last handed out
+    pub idle_for_ms: u64,
+}
+
+/// Snapshot of all tenant pools, suitable for a metrics endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PoolMetrics {
+    pub tenants: Vec<TenantPoolStats>,
+    /// Open connections across all pools
+    pub connections: u32,
+    /// Summed limits of the open pools, counted against `global_limit`
+    pub reserved: u32,
+    pub global_limit: u32,
+    /// Pools created, closed and refused since startup
+    pub created: u64,
+    pub evicted: u64,
+    pub rejected: u64,
+}
+
+/// The calling tenant's database pool.
+///
+/// Resolved through the [`TenantPools`] in the request extensions, creating
+/// the pool on first use. Derefs to [`PgPool`].
+#[derive(Debug, Clone)]
+pub struct TenantDb {
+    pub tenant: TenantId,
+    pub pool: PgPool,
+}
+
+impl Deref for TenantDb {
+    type Target = PgPool;
+
+    fn deref(&self) -> &PgPool {
+        &self.pool
+    }
+}
+
+impl<S> FromRequestParts<S> for TenantDb
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let tenant = TenantId::from_request_parts(parts, state).await?;
+        let pools = parts.extensions.get::<TenantPools>().ok_or_else(|| {
+            ApiError::InternalServerError(
+                "TenantDb used on a route without a TenantPools extension".to_string(),
+            )
+        })?;
+        let pool = pools.pool(&tenant).await?;
+        Ok(Self { tenant, pool })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenancy::TENANT_HEADER;
+    use axum::{
+        Extension, Router,
+        body::{Body, to_bytes},
+        http::{Request, StatusCode},
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    fn tenant(id: &str) -> TenantId {
+        TenantId::parse(id).unwrap()
+    }
+
+    #[tokio::test]
+    async fn creates_one_pool_per_tenant_lazily() {
+        let pools = TenantPools::new("postgres://localhost/shop_{tenant}").tenant_limit("big", 8);
+
+        pools.pool(&tenant("acme")).await.unwrap();
+        pools.pool(&tenant("acme")).await.unwrap();
+        pools.pool(&tenant("big")).await.unwrap();
+
+        let metrics = pools.metrics();
+        assert_eq!(metrics.created, 2);
+        assert_eq!(metrics.connections, 0);
+        assert_eq!(metrics.reserved, 13);
+        let limits: Vec<_> = metrics
+            .tenants
+            .iter()
+            .map(|t| (t.tenant.as_str(), t.max_connections))
+            .collect();
+        assert_eq!(limits, [("acme", 5), ("big", 8)]);
+    }
+
+    #[tokio::test]
+    async fn makes_room_by_closing_least_recently_used_pools() {
+        let pools = TenantPools::new("postgres://localhost/shop_{tenant}")
+            .per_tenant_limit(5)
+            .global_limit(10);
+
+        let first = pools.pool(&tenant("a")).await.unwrap();
+        pools.pool(&tenant("b")).await.unwrap();
+        pools.pool(&tenant("c")).await.unwrap();
+
+        assert!(first.is_closed());
+        let metrics = pools.metrics();
+        let open: Vec<_> = metrics.tenants.iter().map(|t| t.tenant.as_str()).collect();
+        assert_eq!(open, ["b", "c"]);
+        assert_eq!(metrics.evicted, 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_tenants_that_cannot_fit_the_budget() {
+        let pools = TenantPools::new("postgres://localhost/shop")
+            .global_limit(10)
+            .tenant_limit("huge", 20);
+
+        assert!(pools.pool(&tenant("huge")).await.is_err());
+        assert_eq!(pools.metrics().rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn evicts_idle_pools() {
+        let pools = TenantPools::new("postgres://localhost/shop_{tenant}");
+        pools.pool(&tenant("a")).await.unwrap();
+        assert_eq!(pools.evict_idle().await, 0);
+
+        let pools =
+            TenantPools::new("postgres://localhost/shop_{tenant}").idle_timeout(Duration::ZERO);
+        pools.pool(&tenant("a")).await.unwrap();
+        assert_eq!(pools.evict_idle().await, 1);
+        assert!(pools.metrics().tenants.is_empty());
+    }
+
+    #[test]
+    fn substitutes_the_tenant_into_database_or_schema() {
+        let per_db = TenantPools::new("postgres://localhost/shop_{tenant}");
+        let options = per_db.connect_options(&tenant("acme")).unwrap();
+        assert_eq!(options.get_database(), Some("shop_acme"));
+
+        let per_schema = TenantPools::new("postgres://localhost/shop").schema("tenant_{tenant}");
+        let options = per_schema.connect_options(&tenant("acme")).unwrap();
+        assert_eq!(options.get_database(), Some("shop"));
+        assert_eq!(
+            options.get_options(),
+            Some("-c search_path=\"tenant_acme\"")
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_the_tenant_pool_from_the_header() {
+        let pools = TenantPools::new("postgres://localhost/shop_{tenant}");
+        let router = Router::new()
+            .route(
+                "/whoami",
+                get(|db: TenantDb| async move { db.tenant.to_string() }),
+            )
+            .layer(Extension(pools.clone()));
+        let call = |tenant: Option<&str>| {
+            let mut request = Request::get("/whoami");
+            if let Some(tenant) = tenant {
+                request = request.header(TENANT_HEADER, tenant);
+            }
+            router.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let res = call(Some("acme")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(to_bytes(res.into_body(), 64).await.unwrap(), "acme");
+        assert_eq!(pools.metrics().tenants.len(), 1);
+
+        let res = call(None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = call(Some("../etc")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,394 @@
+//! Working out which tenant a request belongs to
+//!
+//! A [`TenantLayer`] asks its [`TenantResolver`]s in turn and stores the
+//! first answer as the request's [`TenantId`], where the `TenantId`,
+//! [`TenantDb`](super::TenantDb) and [`TenantCache`](super::TenantCache)
+//! extractors pick it up:
+//!
+//! ```rust,ignore
+//! let router = Router::new()
+//!     .route("/orders", get(list_orders))
+//!     .layer(
+//!         TenantLayer::new(SubdomainTenant::new("shop.example.com"))
+//!             .or(ClaimTenant::new("tenant_id"))
+//!             .or(HeaderTenant::new()),
+//!     );
+//! ```
+//!
+//! Implement [`TenantResolver`] for other lookups, such as mapping custom
+//! domains to tenants through the database.
+
+use std::{future::Future, pin::Pin, sync::Arc, task};
+
+use async_trait::async_trait;
+use axum::{
+    extract::Request,
+    http::{HeaderName, header::HOST, request::Parts},
+    response::{IntoResponse, Response},
+};
+use tower::{Layer, Service};
+
+use super::{TENANT_HEADER, TenantId};
+use crate::error::ApiError;
+
+/// Finds the tenant of a request
+#[async_trait]
+pub trait TenantResolver: Send + Sync + 'static {
+    /// The request's tenant, or `None` if this resolver can't tell
+    ///
+    /// Errors, such as an unknown tenant, reject the request.
+    async fn resolve(&self, parts: &mut Parts) -> Result<Option<TenantId>, ApiError>;
+}
+
+/// Reads the tenant from a header, `x-tenant-id` by default
+#[derive(Debug, Clone)]
+pub struct HeaderTenant {
+    header: HeaderName,
+}
+
+impl HeaderTenant {
+    pub fn new() -> Self {
+        Self::named(HeaderName::from_static(TENANT_HEADER))
+    }
+
+    pub fn named(header: HeaderName) -> Self {
+        Self { header }
+    }
+}
+
+impl Default for HeaderTenant {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl TenantResolver for HeaderTenant {
+    async fn resolve(&self, parts: &mut Parts) -> Result<Option<TenantId>, ApiError> {
+        let Some(value) = parts.headers.get(&self.header) else {
+            return Ok(None);
+        };
+        let id = value
+            .to_str()
+            .map_err(|_| ApiError::BadRequest(format!("Invalid {} header", self.header)))?;
+        TenantId::parse(id).map(Some)
+    }
+}
+
+/// Reads the tenant from the subdomain, e.g. `acme` in `acme.shop.example.com`
+#[derive(Debug, Clone)]
+pub struct SubdomainTenant {
+    /// `.shop.example.com`
+    suffix: String,
+    reserved: Vec<String>,
+}
+
+impl SubdomainTenant {
+    /// Tenants are subdomains of `domain`; `www` is not a tenant
+    pub fn new(domain: &str) -> Self {
+        Self {
+            suffix: format!(".{}", domain.trim_matches('.').to_ascii_lowercase()),
+            reserved: vec!["www".to_string()],
+        }
+    }
+
+    /// Subdomains that aren't tenants, such as `www`, `api` or `admin`
+    pub fn reserved<I, S>(mut self, subdomains: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.reserved = subdomains.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+#[async_trait]
+impl TenantResolver for SubdomainTenant {
+    async fn resolve(&self, parts: &mut Parts) -> Result<Option<TenantId>, ApiError> {
+        let forwarded = parts
+            .extensions
+            .get::<crate::proxy::RequestOrigin>()
+            .and_then(|origin| origin.hostname())
+            .map(str::to_string);
+        let host = forwarded
+            .or_else(|| parts.uri.host().map(str::to_string))
+            .or_else(|| {
+                let host = parts.headers.get(HOST)?.to_str().ok()?;
+                // Drop the port
+                Some(
+                    host.rsplit_once(':')
+                        .map_or(host, |(host, _)| host)
+                        .to_string(),
+                )
+            })
+            .map(|host| host.to_ascii_lowercase());
+        let Some(subdomain) = host.as_deref().and_then(|h| h.strip_suffix(&self.suffix)) else {
+            return Ok(None);
+        };
+        // Only the label right below the domain names the tenant
+        let label = subdomain.rsplit('.').next().unwrap_or(subdomain);
+        if self.reserved.iter().any(|reserved| reserved == label) {
+            return Ok(None);
+        }
+        TenantId::parse(label).map(Some)
+    }
+}
+
+/// Reads the tenant from a claim of the verified access token
+///
+/// Like [`ClaimHeaders`](crate::auth::ClaimHeaders), it needs the
+/// [`AuthConfig`](crate::auth::AuthConfig) in the request extensions.
+/// Requests without a valid token are left to the next resolver.
+#[cfg(feature = "auth")]
+#[derive(Debug, Clone)]
+pub struct ClaimTenant {
+    claim: String,
+}
+
+#[cfg(feature = "auth")]
+impl ClaimTenant {
+    /// Use `claim`, which may be a dotted path such as `org.id`
+    pub fn new(claim: impl Into<String>) -> Self {
+        Self {
+            claim: claim.into(),
+        }
+    }
+}
+
+#[cfg(feature = "auth")]
+#[async_trait]
+impl TenantResolver for ClaimTenant {
+    async fn resolve(&self, parts: &mut Parts) -> Result<Option<TenantId>, ApiError> {
+        use axum::http::header::AUTHORIZATION;
+        use serde_json::Value;
+
+        if crate::auth::extractors::extract_auth_user_from_parts(parts).is_err() {
+            return Ok(None);
+        }
+        // The signature was checked above; this only reads the claims again
+        let claims = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| jsonwebtoken::dangerous::insecure_decode::<Value>(token).ok())
+            .map(|data| data.claims);
+        let value = claims.as_ref().and_then(|claims| {
+            self.claim
+                .split('.')
+                .try_fold(claims, |value, key| value.get(key))
+        });
+        match value {
+            Some(Value::String(id)) => TenantId::parse(id).map(Some),
+            Some(Value::Number(id)) => TenantId::parse(&id.to_string()).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Layer storing the resolved [`TenantId`] in the request extensions
+///
+/// Requests no resolver finds a tenant for are rejected with `400`, unless
+/// the layer is [`optional`](Self::optional).
+#[derive(Clone)]
+pub struct TenantLayer {
+    resolvers: Arc<Vec<Box<dyn TenantResolver>>>,
+    required: bool,
+}
+
+impl TenantLayer {
+    pub fn new(resolver: impl TenantResolver) -> Self {
+        Self {
+            resolvers: Arc::new(vec![Box::new(resolver)]),
+            required: true,
+        }
+    }
+
+    /// Ask `resolver` when the ones before it find no tenant
+    pub fn or(mut self, resolver: impl TenantResolver) -> Self {
+        Arc::get_mut(&mut self.resolvers)
+            .expect("TenantLayer is configured before it is cloned")
+            .push(Box::new(resolver));
+        self
+    }
+
+    /// Let requests without a tenant through
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+
+    async fn resolve(&self, parts: &mut Parts) -> Result<Option<TenantId>, ApiError> {
+        for resolver in self.resolvers.iter() {
+            if let Some(tenant) = resolver.resolve(parts).await? {
+                return Ok(Some(tenant));
+            }
+        }
+        if self.required {
+            return Err(ApiError::BadRequest(
+                "Could not determine the tenant".to_string(),
+            ));
+        }
+        Ok(None)
+    }
+}
+
+impl<S> Layer<S> for TenantLayer {
+    type Service = TenantService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenantService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service produced by [`TenantLayer`]
+#[derive(Clone)]
+pub struct TenantService<S> {
+    inner: S,
+    layer: TenantLayer,
+}
+
+impl<S> Service<Request> for TenantService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The ready service goes into the future; a fresh clone stays here
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            match layer.resolve(&mut parts).await {
+                Ok(Some(tenant)) => {
+                    parts.extensions.insert(tenant);
+                }
+                Ok(None) => {}
+                Err(err) => return Ok(err.into_response()),
+            }
+            inner.call(Request::from_parts(parts, body)).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::StatusCode,
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    async fn call(router: &Router, host: &str, tenant: Option<&str>) -> (StatusCode, String) {
+        let mut request = Request::get("/whoami").header(HOST, host);
+        if let Some(tenant) = tenant {
+            request = request.header(TENANT_HEADER, tenant);
+        }
+        let res = router
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), 1024).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn resolvers_are_asked_in_order() {
+        let router = Router::new()
+            .route(
+                "/whoami",
+                get(|tenant: TenantId| async move { tenant.to_string() }),
+            )
+            .layer(
+                TenantLayer::new(SubdomainTenant::new("shop.example.com")).or(HeaderTenant::new()),
+            );
+
+        let ok = |body: &str| (StatusCode::OK, body.to_string());
+        assert_eq!(
+            call(&router, "acme.shop.example.com:8080", None).await,
+            ok("acme")
+        );
+        // The subdomain wins over a header naming another tenant
+        assert_eq!(
+            call(&router, "ACME.shop.example.com", Some("globex")).await,
+            ok("acme")
+        );
+        assert_eq!(
+            call(&router, "www.shop.example.com", Some("globex")).await,
+            ok("globex")
+        );
+        assert_eq!(
+            call(&router, "shop.example.com", None).await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            call(&router, "evil.example.org", Some("bad tenant"))
+                .await
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[cfg(feature = "auth")]
+    #[tokio::test]
+    async fn claims_name_the_tenant() {
+        use crate::auth::AuthConfig;
+        use axum::http::header::AUTHORIZATION;
+        use jsonwebtoken::{EncodingKey, Header};
+
+        let config = AuthConfig::default();
+        let mut claims = serde_json::to_value(crate::auth::Claims::new_access(
+            "user-1",
+            "a@example.com",
+            vec![],
+            &config,
+        ))
+        .unwrap();
+        claims["org"] = serde_json::json!({ "id": "acme" });
+        let token = jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+        )
+        .unwrap();
+
+        let mut parts = Request::get("/")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0;
+        parts.extensions.insert(config);
+        let tenant = ClaimTenant::new("org.id")
+            .resolve(&mut parts)
+            .await
+            .unwrap();
+        assert_eq!(tenant, Some(TenantId::parse("acme").unwrap()));
+        assert_eq!(
+            ClaimTenant::new("tenant_id")
+                .resolve(&mut parts)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
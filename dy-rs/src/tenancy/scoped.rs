@@ -0,0 +1,316 @@
+//! Caches and rate limits kept apart per tenant
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use tower::{Layer, Service};
+
+use super::TenantId;
+use crate::{
+    cache::{Cache, SharedCache},
+    error::{ApiError, RateLimit},
+};
+
+/// The app's [`SharedCache`] with every key prefixed by the calling tenant,
+/// so tenants can't read or overwrite each other's entries
+///
+/// Usable with [`CacheExt`](crate::cache::CacheExt) like any cache.
+#[derive(Clone)]
+pub struct TenantCache {
+    tenant: TenantId,
+    cache: SharedCache,
+}
+
+impl TenantCache {
+    pub fn new(cache: SharedCache, tenant: TenantId) -> Self {
+        Self { tenant, cache }
+    }
+
+    pub fn tenant(&self) -> &TenantId {
+        &self.tenant
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("tenant:{}:{key}", self.tenant)
+    }
+}
+
+#[async_trait]
+impl Cache for TenantCache {
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        self.cache.get_bytes(&self.key(key)).await
+    }
+
+    async fn set_bytes(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), ApiError> {
+        self.cache.set_bytes(&self.key(key), value, ttl).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ApiError> {
+        self.cache.delete(&self.key(key)).await
+    }
+}
+
+impl<S> FromRequestParts<S> for TenantCache
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let tenant = TenantId::from_request_parts(parts, state).await?;
+        let cache = SharedCache::from_request_parts(parts, state).await?;
+        Ok(Self::new(cache, tenant))
+    }
+}
+
+/// Layer capping how many requests each tenant may make
+///
+/// Every tenant gets its own token bucket holding `requests` tokens, refilled
+/// evenly over `per`, so one busy tenant can't starve the others. The tenant
+/// comes from the request extensions, so add the layer inside a
+/// [`TenantLayer`](super::TenantLayer) (that is, `.layer()` it first).
+/// Requests without a tenant aren't limited. Responses to the others carry
+/// the tenant's [`RateLimit`] in `X-RateLimit-*` headers, and limited ones get
+/// `429` with a `Retry-After` header.
+#[derive(Clone)]
+pub struct TenantRateLimit {
+    inner: Arc<Limits>,
+}
+
+struct Limits {
+    default: Quota,
+    tenants: HashMap<String, Quota>,
+    buckets: Mutex<HashMap<TenantId, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Quota {
+    requests: u32,
+    per: Duration,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TenantRateLimit {
+    /// Allow each tenant `requests` requests every `per`
+    pub fn new(requests: u32, per: Duration) -> Self {
+        Self {
+            inner: Arc::new(Limits {
+                default: Quota { requests, per },
+                tenants: HashMap::new(),
+                buckets: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Give `tenant` its own allowance, e.g. for a higher plan
+    pub fn tenant_limit(mut self, tenant: impl Into<String>, requests: u32) -> Self {
+        let limits = Arc::get_mut(&mut self.inner)
+            .expect("TenantRateLimit is configured before it is cloned");
+        let quota = Quota {
+            requests,
+            per: limits.default.per,
+        };
+        limits.tenants.insert(tenant.into(), quota);
+        self
+    }
+
+    /// Take a token for `tenant`, or say how long until one is available in
+    /// the limit's `retry_after`
+    pub fn check(&self, tenant: &TenantId) -> Result<RateLimit, RateLimit> {
+        self.check_at(tenant, Instant::now())
+    }
+
+    fn check_at(&self, tenant: &TenantId, now: Instant) -> Result<RateLimit, RateLimit> {
+        let quota = self
+            .inner
+            .tenants
+            .get(tenant.as_str())
+            .copied()
+            .unwrap_or(self.inner.default);
+        let capacity = f64::from(quota.requests);
+        let per_token = quota.per.as_secs_f64() / capacity.max(1.0);
+
+        let mut buckets = self.inner.buckets.lock().unwrap();
+        let bucket = buckets.entry(tenant.clone()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed / per_token).min(capacity);
+        bucket.updated = now;
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let limit = RateLimit {
+            limit: quota.requests,
+            remaining: bucket.tokens as u32,
+            reset: Duration::from_secs_f64((capacity - bucket.tokens) * per_token),
+            retry_after: (!allowed)
+                .then(|| Duration::from_secs_f64((1.0 - bucket.tokens) * per_token)),
+        };
+        if allowed { Ok(limit) } else { Err(limit) }
+    }
+}
+
+impl<S> Layer<S> for TenantRateLimit {
+    type Service = TenantRateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenantRateLimitService {
+            inner,
+            limit: self.clone(),
+        }
+    }
+}
+
+/// Service produced by [`TenantRateLimit`]
+#[derive(Clone)]
+pub struct TenantRateLimitService<S> {
+    inner: S,
+    limit: TenantRateLimit,
+}
+
+impl<S> Service<Request> for TenantRateLimitService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let Some(tenant) = request.extensions().get::<TenantId>() else {
+            return Box::pin(self.inner.call(request));
+        };
+        let limit = match self.limit.check(tenant) {
+            Ok(limit) => limit,
+            Err(limit) => {
+                let response = ApiError::rate_limited(
+                    format!("Rate limit exceeded for tenant {tenant}"),
+                    &limit,
+                )
+                .into_response();
+                return Box::pin(async move { Ok(response) });
+            }
+        };
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = future.await?;
+            response.headers_mut().extend(limit.headers());
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{CacheExt, LruCache};
+    use crate::tenancy::{HeaderTenant, TENANT_HEADER, TenantLayer};
+    use axum::{Router, body::Body, http::StatusCode, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn tenants_get_separate_cache_entries() {
+        let shared = SharedCache::new(LruCache::new());
+        let acme = TenantCache::new(shared.clone(), TenantId::parse("acme").unwrap());
+        let globex = TenantCache::new(shared.clone(), TenantId::parse("globex").unwrap());
+
+        acme.set("plan", &"pro", None).await.unwrap();
+        assert_eq!(
+            acme.get::<String>("plan").await.unwrap().as_deref(),
+            Some("pro")
+        );
+        assert_eq!(globex.get::<String>("plan").await.unwrap(), None);
+        assert!(
+            shared
+                .get_bytes("tenant:acme:plan")
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let limit = TenantRateLimit::new(2, Duration::from_secs(10)).tenant_limit("big", 4);
+        let acme = TenantId::parse("acme").unwrap();
+        let big = TenantId::parse("big").unwrap();
+        let start = Instant::now();
+
+        assert_eq!(limit.check_at(&acme, start).unwrap().remaining, 1);
+        assert_eq!(limit.check_at(&acme, start).unwrap().remaining, 0);
+        assert_eq!(
+            limit.check_at(&acme, start),
+            Err(RateLimit {
+                limit: 2,
+                remaining: 0,
+                reset: Duration::from_secs(10),
+                retry_after: Some(Duration::from_secs(5)),
+            })
+        );
+        assert!(
+            limit
+                .check_at(&acme, start + Duration::from_secs(5))
+                .is_ok()
+        );
+        for _ in 0..4 {
+            assert!(limit.check_at(&big, start).is_ok());
+        }
+        assert!(limit.check_at(&big, start).is_err());
+    }
+
+    #[tokio::test]
+    async fn limited_requests_get_429() {
+        let router = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(TenantRateLimit::new(1, Duration::from_secs(60)))
+            .layer(TenantLayer::new(HeaderTenant::new()));
+        let call = |tenant: &str| {
+            router.clone().oneshot(
+                Request::get("/")
+                    .header(TENANT_HEADER, tenant)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let res = call("acme").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["x-ratelimit-limit"], "1");
+        assert_eq!(res.headers()["x-ratelimit-remaining"], "0");
+        assert_eq!(res.headers()["x-ratelimit-reset"], "60");
+        let res = call("acme").await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[axum::http::header::RETRY_AFTER], "60");
+        assert_eq!(res.headers()["x-ratelimit-remaining"], "0");
+        assert_eq!(call("globex").await.unwrap().status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,419 @@
+//! OpenAPI snapshot and contract tests
+//!
+//! [`assert_openapi_snapshot`] compares the app's final OpenAPI document
+//! with a committed copy, rendered with sorted keys so it only changes when
+//! the API does. [`assert_no_breaking_changes`] only fails on changes that
+//! break existing clients, listed by [`breaking_changes`]:
+//!
+//! ```rust,ignore
+//! #[test]
+//! fn api_contract() {
+//!     let app = App::new().mount(routes());
+//!     assert_openapi_snapshot(&app.openapi_document(), "tests/openapi.json");
+//!     assert_no_breaking_changes(&app.openapi_document(), "openapi/v1.json");
+//! }
+//! ```
+//!
+//! Run the tests with `UPDATE_OPENAPI_SNAPSHOTS=1` to write the current
+//! document to the snapshot instead; a missing snapshot is written too.
+//!
+//! Breaking changes are removed paths, operations, success responses and
+//! component schemas; new required parameters and request bodies; and, in
+//! any schema, a changed type, a removed property or enum value, or a
+//! property that became required.
+
+use std::{fmt, path::Path};
+
+use serde_json::{Map, Value};
+use utoipa::openapi::OpenApi;
+
+use crate::openapi;
+
+/// Set to write snapshots instead of comparing them
+pub const UPDATE_ENV: &str = "UPDATE_OPENAPI_SNAPSHOTS";
+
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// A change that breaks clients of the old document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakingChange {
+    /// Where, e.g. `GET /users` or `#/components/schemas/User.email`
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for BreakingChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// `doc` as pretty JSON with sorted keys, ending in a newline
+pub fn openapi_snapshot(doc: &OpenApi) -> String {
+    // Without serde_json's `preserve_order`, objects are sorted by key
+    let value = serde_json::to_value(doc).expect("OpenAPI documents serialize to JSON");
+    let mut snapshot = serde_json::to_string_pretty(&value).expect("JSON values serialize to JSON");
+    snapshot.push('\n');
+    snapshot
+}
+
+/// Panic unless `doc` renders as the snapshot at `path`; see the
+/// [module docs](self)
+#[track_caller]
+pub fn assert_openapi_snapshot(doc: &OpenApi, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let snapshot = openapi_snapshot(doc);
+    let committed = std::fs::read_to_string(path).ok();
+    if std::env::var_os(UPDATE_ENV).is_some() || committed.is_none() {
+        write_snapshot(path, &snapshot);
+        return;
+    }
+    let committed = committed.unwrap_or_default();
+    if committed.replace("\r\n", "\n") != snapshot {
+        let changes = breaking_changes(&parse(path, &committed), &to_value(doc));
+        panic!(
+            "The OpenAPI document differs from {}; rerun with {UPDATE_ENV}=1 to update it{}",
+            path.display(),
+            describe(&changes)
+        );
+    }
+}
+
+/// Panic if `doc` breaks clients of the committed document at `path`, JSON
+/// or YAML by its extension
+#[track_caller]
+pub fn assert_no_breaking_changes(doc: &OpenApi, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let committed = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+    let changes = breaking_changes(&parse(path, &committed), &to_value(doc));
+    if !changes.is_empty() {
+        panic!(
+            "The OpenAPI document breaks clients of {}{}",
+            path.display(),
+            describe(&changes)
+        );
+    }
+}
+
+/// Changes from `old` to `new`, two OpenAPI documents as JSON, that break
+/// clients of `old`
+pub fn breaking_changes(old: &Value, new: &Value) -> Vec<BreakingChange> {
+    let mut changes = Changes::default();
+    for (path, old_item) in entries(&old["paths"]) {
+        let new_item = &new["paths"][path];
+        if new_item.is_null() {
+            changes.push(path, "path removed");
+            continue;
+        }
+        for method in METHODS {
+            let old_operation = &old_item[method];
+            if old_operation.is_null() {
+                continue;
+            }
+            let location = format!("{} {path}", method.to_ascii_uppercase());
+            match &new_item[method] {
+                Value::Null => changes.push(&location, "operation removed"),
+                new_operation => changes.operation(&location, old_operation, new_operation),
+            }
+        }
+    }
+    for (name, old_schema) in entries(&old["components"]["schemas"]) {
+        let location = format!("#/components/schemas/{name}");
+        match &new["components"]["schemas"][name] {
+            Value::Null => changes.push(&location, "schema removed"),
+            new_schema => changes.schema(&location, old_schema, new_schema),
+        }
+    }
+    changes.0
+}
+
+#[derive(Default)]
+struct Changes(Vec<BreakingChange>);
+
+impl Changes {
+    fn push(&mut self, location: &str, message: impl Into<String>) {
+        self.0.push(BreakingChange {
+            location: location.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn operation(&mut self, location: &str, old: &Value, new: &Value) {
+        let old_parameters = as_slice(&old["parameters"]);
+        for parameter in as_slice(&new["parameters"]) {
+            if parameter["required"] != Value::Bool(true) {
+                continue;
+            }
+            let was_required = old_parameters.iter().any(|old| {
+                old["name"] == parameter["name"]
+                    && old["in"] == parameter["in"]
+                    && old["required"] == Value::Bool(true)
+            });
+            if !was_required {
+                self.push(
+                    location,
+                    format!(
+                        "{} parameter `{}` is now required",
+                        parameter["in"].as_str().unwrap_or("a"),
+                        parameter["name"].as_str().unwrap_or_default()
+                    ),
+                );
+            }
+        }
+
+        if new["requestBody"]["required"] == Value::Bool(true)
+            && old["requestBody"]["required"] != Value::Bool(true)
+        {
+            self.push(location, "request body is now required");
+        }
+        self.content(
+            &format!("{location} request body"),
+            &old["requestBody"]["content"],
+            &new["requestBody"]["content"],
+        );
+
+        for (status, old_response) in entries(&old["responses"]) {
+            let new_response = &new["responses"][status];
+            if new_response.is_null() {
+                if status.starts_with('2') {
+                    self.push(location, format!("{status} response removed"));
+                }
+                continue;
+            }
+            self.content(
+                &format!("{location} {status} response"),
+                &old_response["content"],
+                &new_response["content"],
+            );
+        }
+    }
+
+    fn content(&mut self, location: &str, old: &Value, new: &Value) {
+        for (media_type, old_media) in entries(old) {
+            if let Some(new_schema) = new[media_type].get("schema") {
+                self.schema(location, &old_media["schema"], new_schema);
+            }
+        }
+    }
+
+    fn schema(&mut self, location: &str, old: &Value, new: &Value) {
+        if old.is_null() {
+            return;
+        }
+        let (old_ref, new_ref) = (&old["$ref"], &new["$ref"]);
+        if !old_ref.is_null() || !new_ref.is_null() {
+            if old_ref != new_ref {
+                self.push(
+                    location,
+                    format!(
+                        "schema changed from {} to {}",
+                        describe_schema(old),
+                        describe_schema(new)
+                    ),
+                );
+            }
+            return;
+        }
+
+        if !old["type"].is_null() && !new["type"].is_null() && old["type"] != new["type"] {
+            self.push(
+                location,
+                format!("type changed from {} to {}", old["type"], new["type"]),
+            );
+            return;
+        }
+
+        let new_properties = &new["properties"];
+        for (name, old_property) in entries(&old["properties"]) {
+            let property_location = format!("{location}.{name}");
+            match &new_properties[name] {
+                Value::Null => self.push(&property_location, "property removed"),
+                new_property => self.schema(&property_location, old_property, new_property),
+            }
+        }
+        let old_required = as_slice(&old["required"]);
+        for name in as_slice(&new["required"]) {
+            if !old_required.contains(name) {
+                self.push(
+                    &format!("{location}.{}", name.as_str().unwrap_or_default()),
+                    "property is now required",
+                );
+            }
+        }
+
+        if let Value::Array(new_values) = &new["enum"] {
+            for value in as_slice(&old["enum"]) {
+                if !new_values.contains(value) {
+                    self.push(location, format!("enum value {value} removed"));
+                }
+            }
+        }
+
+        if !new["items"].is_null() {
+            self.schema(&format!("{location}[]"), &old["items"], &new["items"]);
+        }
+    }
+}
+
+fn entries(value: &Value) -> impl Iterator<Item = (&String, &Value)> {
+    value.as_object().into_iter().flat_map(Map::iter)
+}
+
+fn as_slice(value: &Value) -> &[Value] {
+    value.as_array().map(Vec::as_slice).unwrap_or_default()
+}
+
+fn describe_schema(schema: &Value) -> String {
+    match (schema["$ref"].as_str(), schema["type"].as_str()) {
+        (Some(reference), _) => reference.to_string(),
+        (None, Some(ty)) => ty.to_string(),
+        (None, None) => "an inline schema".to_string(),
+    }
+}
+
+fn describe(changes: &[BreakingChange]) -> String {
+    if changes.is_empty() {
+        return String::new();
+    }
+    let list: Vec<String> = changes
+        .iter()
+        .map(|change| format!("  - {change}"))
+        .collect();
+    format!("\nBreaking changes:\n{}", list.join("\n"))
+}
+
+fn to_value(doc: &OpenApi) -> Value {
+    serde_json::to_value(doc).expect("OpenAPI documents serialize to JSON")
+}
+
+/// The committed document at `path`, as JSON
+fn parse(path: &Path, contents: &str) -> Value {
+    let yaml = matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("yaml" | "yml")
+    );
+    let parsed = if yaml {
+        openapi::from_yaml(contents).map(|doc| to_value(&doc))
+    } else {
+        serde_json::from_str(contents).map_err(|e| e.to_string())
+    };
+    parsed.unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()))
+}
+
+fn write_snapshot(path: &Path, snapshot: &str) {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .unwrap_or_else(|e| panic!("Failed to create {}: {e}", dir.display()));
+    }
+    std::fs::write(path, snapshot)
+        .unwrap_or_else(|e| panic!("Failed to write {}: {e}", path.display()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn document() -> Value {
+        json!({
+            "paths": {
+                "/users": {
+                    "get": {
+                        "parameters": [{ "name": "page", "in": "query" }],
+                        "responses": {
+                            "200": {
+                                "content": {
+                                    "application/json": {
+                                        "schema": { "type": "array", "items": { "$ref": "#/components/schemas/User" } }
+                                    }
+                                }
+                            },
+                            "404": {}
+                        }
+                    },
+                    "post": { "responses": { "201": {} } }
+                },
+                "/health": { "get": { "responses": { "200": {} } } }
+            },
+            "components": {
+                "schemas": {
+                    "User": {
+                        "type": "object",
+                        "required": ["id"],
+                        "properties": {
+                            "id": { "type": "string" },
+                            "email": { "type": "string" },
+                            "role": { "type": "string", "enum": ["admin", "member"] }
+                        }
+                    },
+                    "Legacy": { "type": "object" }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn compatible_changes_pass() {
+        let mut new = document();
+        new["paths"]["/orders"] = json!({ "get": { "responses": { "200": {} } } });
+        new["components"]["schemas"]["User"]["properties"]["name"] = json!({ "type": "string" });
+        new["paths"]["/users"]["get"]["responses"]
+            .as_object_mut()
+            .unwrap()
+            .remove("404");
+        assert_eq!(breaking_changes(&document(), &new), []);
+    }
+
+    #[test]
+    fn breaking_changes_are_listed() {
+        let mut new = document();
+        let paths = new["paths"].as_object_mut().unwrap();
+        paths.remove("/health");
+        paths["/users"].as_object_mut().unwrap().remove("post");
+        paths["/users"]["get"]["parameters"][0]["required"] = json!(true);
+        let user = &mut new["components"]["schemas"]["User"];
+        user["properties"].as_object_mut().unwrap().remove("email");
+        user["properties"]["id"]["type"] = json!("integer");
+        user["properties"]["role"]["enum"] = json!(["admin"]);
+        user["required"] = json!(["id", "role"]);
+        new["components"]["schemas"]
+            .as_object_mut()
+            .unwrap()
+            .remove("Legacy");
+
+        let changes: Vec<String> = breaking_changes(&document(), &new)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "/health: path removed",
+                "GET /users: query parameter `page` is now required",
+                "POST /users: operation removed",
+                "#/components/schemas/Legacy: schema removed",
+                "#/components/schemas/User.email: property removed",
+                "#/components/schemas/User.id: type changed from \"string\" to \"integer\"",
+                "#/components/schemas/User.role: enum value \"member\" removed",
+                "#/components/schemas/User.role: property is now required",
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshots_are_written_then_compared() {
+        let doc = crate::openapi::health_openapi();
+        let path = std::env::temp_dir()
+            .join(format!("dy-rs-{}", uuid::Uuid::new_v4()))
+            .join("openapi.json");
+        assert_openapi_snapshot(&doc, &path);
+        let snapshot = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(snapshot, openapi_snapshot(&doc));
+        assert_openapi_snapshot(&doc, &path);
+        assert_no_breaking_changes(&doc, &path);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,466 @@
+//! In-process integration testing
+//!
+//! A [`TestClient`] sends requests straight to an app's router, without
+//! binding a socket, and buffers the responses for assertions:
+//!
+//! ```rust,ignore
+//! let client = TestClient::new(App::new().mount(routes()));
+//!
+//! let user: User = client
+//!     .post("/users")
+//!     .json(&json!({ "name": "Ana" }))
+//!     .await
+//!     .assert_status(StatusCode::CREATED)
+//!     .json();
+//!
+//! client.get("/health").await.assert_ok();
+//! ```
+//!
+//! Requests are plain futures, so any async runtime can drive them; requests
+//! appear to come from `127.0.0.1`. Assertions panic with the response body,
+//! so a failing test shows the error the app returned.
+//!
+//! End-to-end tests that need a real listener, e.g. for WebSockets or
+//! server-sent events, start a [`TestServer`] instead. It runs the app as
+//! [`App::run`] does, on a random local port, until it's shut down or
+//! dropped:
+//!
+//! ```rust,ignore
+//! let server = TestServer::start(App::new().mount(routes())).await?;
+//! let events = connect_sse(server.url("/events")).await;
+//! let socket = connect_ws(server.ws_url("/chat")).await;
+//! server.shutdown().await?;
+//! ```
+//!
+//! [`contract`] compares the app's OpenAPI document with a committed copy.
+
+use std::{
+    fmt,
+    future::{Future, IntoFuture},
+    net::SocketAddr,
+    pin::Pin,
+};
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::ConnectInfo,
+    http::{HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode, header},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tower::ServiceExt;
+
+use crate::{App, listen::Listener};
+
+pub mod contract;
+
+/// Error of a [`TestServer`]
+pub type ServerError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Client sending requests to an app in-process; see the [module docs](self)
+#[derive(Clone)]
+pub struct TestClient {
+    router: Router,
+    headers: HeaderMap,
+}
+
+impl TestClient {
+    /// A client for `app`, with its auto-configured stack
+    pub fn new(app: App) -> Self {
+        Self::from_router(app.into_router())
+    }
+
+    /// A client for a bare router
+    pub fn from_router(router: Router) -> Self {
+        Self {
+            router,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Send `value` as the `name` header of every request
+    pub fn default_header(mut self, name: HeaderName, value: impl AsRef<str>) -> Self {
+        self.headers.insert(name, header_value(value.as_ref()));
+        self
+    }
+
+    /// Authenticate every request with `token` as a bearer token
+    pub fn bearer(self, token: impl fmt::Display) -> Self {
+        self.default_header(header::AUTHORIZATION, format!("Bearer {token}"))
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest {
+        self.request(Method::POST, path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest {
+        self.request(Method::PUT, path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest {
+        self.request(Method::PATCH, path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest {
+        self.request(Method::DELETE, path)
+    }
+
+    /// A `method` request to `path`, sent when awaited
+    pub fn request(&self, method: Method, path: &str) -> TestRequest {
+        TestRequest {
+            router: self.router.clone(),
+            method,
+            path: path.to_string(),
+            headers: self.headers.clone(),
+            body: Bytes::new(),
+        }
+    }
+}
+
+/// Request built by a [`TestClient`]; await it for the [`TestResponse`]
+#[must_use = "requests are only sent when awaited"]
+pub struct TestRequest {
+    router: Router,
+    method: Method,
+    path: String,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestRequest {
+    pub fn header(mut self, name: HeaderName, value: impl AsRef<str>) -> Self {
+        self.headers.insert(name, header_value(value.as_ref()));
+        self
+    }
+
+    /// Authenticate with `token` as a bearer token
+    pub fn bearer(self, token: impl fmt::Display) -> Self {
+        self.header(header::AUTHORIZATION, format!("Bearer {token}"))
+    }
+
+    /// Send `value` as a JSON body
+    pub fn json<T: Serialize + ?Sized>(mut self, value: &T) -> Self {
+        self.body = serde_json::to_vec(value)
+            .expect("test request body should serialize to JSON")
+            .into();
+        self.headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        self
+    }
+
+    /// Send `pairs` as a form body
+    pub fn form(mut self, pairs: &[(&str, &str)]) -> Self {
+        let form: Vec<String> = pairs
+            .iter()
+            .map(|(name, value)| format!("{}={}", form_encode(name), form_encode(value)))
+            .collect();
+        self.body = form.join("&").into();
+        self.headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        self
+    }
+
+    /// Send `body` as is
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Send the request and buffer the response
+    pub async fn send(self) -> TestResponse {
+        let mut request = Request::builder()
+            .method(self.method)
+            .uri(&self.path)
+            .body(Body::from(self.body))
+            .unwrap_or_else(|e| panic!("invalid test request to {}: {e}", self.path));
+        *request.headers_mut() = self.headers;
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0))));
+
+        let response = match self.router.oneshot(request).await {
+            Ok(response) => response,
+            Err(infallible) => match infallible {},
+        };
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .unwrap_or_else(|e| panic!("failed to read the response to {}: {e}", self.path));
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        }
+    }
+}
+
+impl IntoFuture for TestRequest {
+    type Output = TestResponse;
+    type IntoFuture = Pin<Box<dyn Future<Output = TestResponse> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.send())
+    }
+}
+
+/// Buffered response to a [`TestRequest`]
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestResponse {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The `name` header, if present and text
+    pub fn header(&self, name: impl header::AsHeaderName) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    pub fn bytes(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The body as text, lossily
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// The body deserialized from JSON; panics if it isn't a `T`
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| {
+            panic!(
+                "response body is not the expected JSON ({e}), status {}: {}",
+                self.status,
+                self.text()
+            )
+        })
+    }
+
+    /// Panic unless the status is `status`
+    #[track_caller]
+    pub fn assert_status(self, status: StatusCode) -> Self {
+        assert_eq!(
+            self.status,
+            status,
+            "unexpected status, body: {}",
+            self.text()
+        );
+        self
+    }
+
+    /// Panic unless the status is `2xx`
+    #[track_caller]
+    pub fn assert_ok(self) -> Self {
+        assert!(
+            self.status.is_success(),
+            "expected a 2xx status, got {}: {}",
+            self.status,
+            self.text()
+        );
+        self
+    }
+}
+
+/// An app served on a random local port; see the [module docs](self)
+///
+/// Needs a Tokio runtime. Dropping it stops the server without waiting.
+pub struct TestServer {
+    addr: SocketAddr,
+    stop: Option<oneshot::Sender<()>>,
+    server: JoinHandle<Result<(), String>>,
+}
+
+impl TestServer {
+    /// Bind a random port on `127.0.0.1` and serve `app` there in the
+    /// background. Fails if `app` couldn't be configured or the port bound.
+    pub async fn start(mut app: App) -> Result<Self, ServerError> {
+        if let Some(error) = app.take_build_error() {
+            return Err(error.into());
+        }
+        let listener = Listener::tcp("127.0.0.1:0").bind().await?;
+        let addr = match listener.local()? {
+            Listener::Tcp(addr) => addr.parse()?,
+            other => return Err(format!("not a TCP listener: {other}").into()),
+        };
+        let (stop, stopped) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            app.serve(Some(listener), async {
+                stopped.await.ok();
+            })
+            .await
+            .map_err(|e| e.to_string())
+        });
+        Ok(Self {
+            addr,
+            stop: Some(stop),
+            server,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// `http://127.0.0.1:{port}`
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The URL of `path`, e.g. `/events`
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url())
+    }
+
+    /// The `ws://` URL of `path`
+    pub fn ws_url(&self, path: &str) -> String {
+        format!("ws://{}{path}", self.addr)
+    }
+
+    /// Stop accepting connections, wait for open ones and background tasks
+    /// as on `SIGTERM`, and return the error the app stopped with, if any
+    pub async fn shutdown(mut self) -> Result<(), ServerError> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        (&mut self.server).await?.map_err(ServerError::from)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).unwrap_or_else(|e| panic!("invalid test header {value:?}: {e}"))
+}
+
+/// `application/x-www-form-urlencoded` encoding of `value`
+fn form_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Form, Json, extract::Path, routing::get};
+    use serde::Deserialize;
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        id: u32,
+        name: String,
+    }
+
+    fn client() -> TestClient {
+        let router = Router::new()
+            .route(
+                "/users/{id}",
+                get(|Path(id): Path<u32>| async move { Json(json!({ "id": id, "name": "Ana" })) })
+                    .put(|Path(id): Path<u32>, Json(body): Json<Value>| async move {
+                        Json(json!({ "id": id, "name": body["name"] }))
+                    }),
+            )
+            .route(
+                "/whoami",
+                get(|headers: HeaderMap| async move {
+                    headers[header::AUTHORIZATION].to_str().unwrap().to_string()
+                })
+                .post(|Form(form): Form<HashMap<String, String>>| async move { form["q"].clone() }),
+            );
+        TestClient::from_router(router)
+    }
+
+    #[tokio::test]
+    async fn requests_reach_the_router_in_process() {
+        let client = client();
+        let user: User = client.get("/users/7").await.assert_ok().json();
+        assert_eq!(
+            user,
+            User {
+                id: 7,
+                name: "Ana".to_string()
+            }
+        );
+
+        let user: User = client
+            .put("/users/7")
+            .json(&json!({ "name": "Bea" }))
+            .await
+            .assert_status(StatusCode::OK)
+            .json();
+        assert_eq!(user.name, "Bea");
+
+        let response = client.clone().bearer("abc").get("/whoami").await;
+        assert_eq!(response.text(), "Bearer abc");
+
+        let response = client.post("/whoami").form(&[("q", "a b&c")]).await;
+        assert_eq!(response.text(), "a b&c");
+
+        let missing = client.get("/nope").await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_servers_listen_until_shut_down() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let app = App::new().route("/ping", get(|| async { "pong" }));
+        let server = TestServer::start(app).await.unwrap();
+        assert!(server.url("/ping").starts_with("http://127.0.0.1:"));
+        assert!(server.ws_url("/chat").starts_with("ws://127.0.0.1:"));
+
+        let mut stream = tokio::net::TcpStream::connect(server.addr()).await.unwrap();
+        stream
+            .write_all(b"GET /ping HTTP/1.1\r\nHost: app\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with("pong"), "{response}");
+
+        let addr = server.addr();
+        server.shutdown().await.unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "unexpected status")]
+    async fn status_assertions_panic_with_the_body() {
+        client().get("/users/x").await.assert_status(StatusCode::OK);
+    }
+}
@@ -0,0 +1,318 @@
+//! W3C trace context
+//!
+//! [`TraceContextLayer`] joins the trace of an incoming `traceparent`, or
+//! starts one, and gives the request a span of its own in it. The request is
+//! handled in a tracing span carrying `trace_id` and `span_id`, so its log
+//! lines can be found next to those of the calling services, and the response
+//! carries the request's `traceparent` and the caller's `tracestate`.
+//!
+//! Handlers take the [`TraceContext`] as an extractor, and
+//! [`HttpClient`](crate::client::HttpClient) passes a child of it on with
+//! every outbound call:
+//!
+//! ```rust,ignore
+//! async fn show(trace: TraceContext) -> String {
+//!     trace.trace_id().to_string()
+//! }
+//! ```
+
+use std::{convert::Infallible, future::Future, pin::Pin, task};
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{HeaderMap, HeaderName, HeaderValue, request::Parts},
+    response::Response,
+};
+use tower::{Layer, Service};
+use tracing::Instrument;
+
+/// Header carrying the W3C trace context
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Header carrying vendor-specific trace data along a `traceparent`
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// Longest `tracestate` passed on, as the spec lets vendors truncate beyond
+const MAX_TRACESTATE: usize = 512;
+
+/// Sampled flag of a `traceparent`
+const SAMPLED: u8 = 0x01;
+
+/// A span's place in a distributed trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: String,
+    span_id: String,
+    parent_id: Option<String>,
+    flags: u8,
+    tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// The first span of a new, sampled trace
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: uuid::Uuid::new_v4().simple().to_string(),
+            span_id: new_span_id(),
+            parent_id: None,
+            flags: SAMPLED,
+            tracestate: None,
+        }
+    }
+
+    /// The span described by a `traceparent` header, or `None` if it's
+    /// malformed
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+        let (version, trace_id, span_id, flags) = match parts.as_slice() {
+            [version, trace_id, span_id, flags] => (*version, *trace_id, *span_id, *flags),
+            // Later versions may append fields
+            [version, trace_id, span_id, flags, ..] if *version != "00" => {
+                (*version, *trace_id, *span_id, *flags)
+            }
+            _ => return None,
+        };
+        let valid = is_hex_id(version, 2)
+            && version != "ff"
+            && is_hex_id(trace_id, 32)
+            && is_hex_id(span_id, 16)
+            && is_hex_id(flags, 2);
+        if !valid {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            parent_id: None,
+            flags: u8::from_str_radix(flags, 16).ok()?,
+            tracestate: None,
+        })
+    }
+
+    /// The context sent in `headers`, if any
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let context = Self::parse(header(TRACEPARENT_HEADER)?)?;
+        Some(context.tracestate(header(TRACESTATE_HEADER)))
+    }
+
+    /// A new span in the same trace, under this one
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id.clone(),
+            span_id: new_span_id(),
+            parent_id: Some(self.span_id.clone()),
+            flags: self.flags,
+            tracestate: self.tracestate.clone(),
+        }
+    }
+
+    /// The same span with `tracestate`, dropped when longer than the spec
+    /// asks vendors to keep
+    pub fn tracestate(mut self, tracestate: Option<&str>) -> Self {
+        self.tracestate = tracestate
+            .map(str::trim)
+            .filter(|state| !state.is_empty() && state.len() <= MAX_TRACESTATE)
+            .map(str::to_string);
+        self
+    }
+
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    pub fn span_id(&self) -> &str {
+        &self.span_id
+    }
+
+    /// The span this one was started under, as far as this service knows
+    pub fn parent_id(&self) -> Option<&str> {
+        self.parent_id.as_deref()
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags & SAMPLED != 0
+    }
+
+    /// The `traceparent` header naming this span
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+
+    pub fn tracestate_header(&self) -> Option<&str> {
+        self.tracestate.as_deref()
+    }
+
+    /// Set `traceparent` and `tracestate` in `headers`, keeping those
+    /// already there
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        if let Ok(traceparent) = HeaderValue::from_str(&self.traceparent()) {
+            headers
+                .entry(HeaderName::from_static(TRACEPARENT_HEADER))
+                .or_insert(traceparent);
+        }
+        if let Some(state) = &self.tracestate
+            && let Ok(state) = HeaderValue::from_str(state)
+        {
+            headers
+                .entry(HeaderName::from_static(TRACESTATE_HEADER))
+                .or_insert(state);
+        }
+    }
+}
+
+fn new_span_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..16].to_string()
+}
+
+/// Whether `id` is `len` lowercase hex digits, not all zero
+fn is_hex_id(id: &str, len: usize) -> bool {
+    id.len() == len
+        && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        && (len == 2 || id.bytes().any(|b| b != b'0'))
+}
+
+/// The request's span, as set by [`TraceContextLayer`], or a child of the
+/// headers' context without it. Extracting it never fails.
+impl<S> FromRequestParts<S> for TraceContext
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_else(|| {
+            TraceContext::from_headers(&parts.headers)
+                .map(|remote| remote.child())
+                .unwrap_or_else(TraceContext::new_root)
+        }))
+    }
+}
+
+/// Joins or starts a trace for every request; see the [module docs](self)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceContextLayer;
+
+impl<S> Layer<S> for TraceContextLayer {
+    type Service = TraceContextService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceContextService { inner }
+    }
+}
+
+/// Service produced by [`TraceContextLayer`]
+#[derive(Clone)]
+pub struct TraceContextService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for TraceContextService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let context = TraceContext::from_headers(request.headers())
+            .map(|remote| remote.child())
+            .unwrap_or_else(TraceContext::new_root);
+        let span = tracing::error_span!(
+            "trace",
+            trace_id = %context.trace_id,
+            span_id = %context.span_id,
+            parent_id = context.parent_id.as_deref().unwrap_or_default(),
+        );
+        request.extensions_mut().insert(context.clone());
+        let handled = span.in_scope(|| self.inner.call(request));
+
+        Box::pin(
+            async move {
+                let mut response = handled.await?;
+                context.inject(response.headers_mut());
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    const REMOTE: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    #[test]
+    fn traceparents_are_validated() {
+        let context = TraceContext::parse(REMOTE).unwrap();
+        assert_eq!(context.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(context.span_id(), "b7ad6b7169203331");
+        assert!(context.is_sampled());
+        assert_eq!(context.traceparent(), REMOTE);
+
+        for invalid in [
+            "garbage",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+        ] {
+            assert_eq!(TraceContext::parse(invalid), None, "{invalid}");
+        }
+        assert!(
+            TraceContext::parse("01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00-extra")
+                .is_some_and(|context| !context.is_sampled())
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_join_the_remote_trace() {
+        let router = Router::new()
+            .route(
+                "/",
+                get(|trace: TraceContext| async move {
+                    format!("{} {}", trace.trace_id(), trace.parent_id().unwrap_or("-"))
+                }),
+            )
+            .layer(TraceContextLayer);
+
+        let request = Request::get("/")
+            .header(TRACEPARENT_HEADER, REMOTE)
+            .header(TRACESTATE_HEADER, "vendor=abc")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            "0af7651916cd43dd8448eb211c80319c b7ad6b7169203331".as_bytes()
+        );
+        let traceparent = TraceContext::parse(headers[TRACEPARENT_HEADER].to_str().unwrap());
+        let traceparent = traceparent.unwrap();
+        assert_eq!(traceparent.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        assert_ne!(traceparent.span_id(), "b7ad6b7169203331");
+        assert_eq!(headers[TRACESTATE_HEADER], "vendor=abc");
+
+        let response = router
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let traceparent = response.headers()[TRACEPARENT_HEADER].to_str().unwrap();
+        assert!(TraceContext::parse(traceparent).is_some_and(|context| context.is_sampled()));
+        assert!(!response.headers().contains_key(TRACESTATE_HEADER));
+    }
+}
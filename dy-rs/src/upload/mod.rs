@@ -0,0 +1,33 @@
+//! File upload support
+//!
+//! Provides resumable uploads over the [tus protocol](https://tus.io/protocols/resumable-upload)
+//! so clients on flaky connections can pick up large uploads where they left off,
+//! and streaming `multipart/form-data` uploads with [`Multipart`], whose file
+//! fields can go straight into [`Storage`](crate::storage::Storage).
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use dy_rs::prelude::*;
+//! use dy_rs::upload::{FileUploadStore, TusConfig, tus_routes};
+//!
+//! let store = FileUploadStore::new("uploads");
+//!
+//! App::new()
+//!     .auto_configure()
+//!     .mount(tus_routes("/files", store, TusConfig::default()))
+//!     .run()
+//!     .await?;
+//! ```
+//!
+//! To keep uploads in the app's [`Storage`](crate::storage::Storage), S3
+//! included, use a [`StorageUploadStore`] instead of the local
+//! [`FileUploadStore`].
+
+pub mod multipart;
+pub mod store;
+pub mod tus;
+
+pub use multipart::{Field, Multipart};
+pub use store::{FileUploadStore, StorageUploadStore, UploadInfo, UploadStore};
+pub use tus::{TUS_VERSION, TusConfig, TusError, parse_metadata, tus_routes};
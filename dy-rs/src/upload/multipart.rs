@@ -0,0 +1,379 @@
+//! Streaming `multipart/form-data` uploads
+//!
+//! [`Multipart`] reads form fields one at a time as the body arrives, so a
+//! file field can be streamed into [`Storage`] without holding it in memory:
+//!
+//! ```rust,ignore
+//! async fn upload(storage: SharedStorage, mut form: Multipart) -> ApiResult<Vec<ObjectInfo>> {
+//!     let mut stored = Vec::new();
+//!     while let Some(field) = form.next_field().await? {
+//!         if let Some(name) = field.file_name().map(str::to_string) {
+//!             stored.push(field.store(&storage, &format!("uploads/{name}")).await?);
+//!         }
+//!     }
+//!     Ok(Json(stored))
+//! }
+//! ```
+//!
+//! Like other body extractors it is subject to the route's body limit;
+//! raise it with `DefaultBodyLimit::max` on upload routes.
+
+use std::io;
+
+use axum::{
+    RequestExt,
+    body::{BodyDataStream, Bytes},
+    extract::{FromRequest, Request},
+    http::header::CONTENT_TYPE,
+};
+use futures_util::StreamExt;
+
+use crate::{
+    error::ApiError,
+    storage::{ObjectInfo, Storage},
+};
+
+/// Largest accepted header block of a single part
+const MAX_PART_HEADERS: usize = 8 * 1024;
+
+/// A `multipart/form-data` body, read field by field
+pub struct Multipart {
+    body: BodyDataStream,
+    /// `\r\n--{boundary}`
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Before the first boundary
+    Preamble,
+    /// Just after a boundary
+    Boundary,
+    /// Inside a part's content
+    Content,
+    /// After the closing boundary
+    End,
+}
+
+impl<S> FromRequest<S> for Multipart
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let boundary = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_boundary)
+            .ok_or_else(|| {
+                ApiError::BadRequest("Expected a multipart/form-data body".to_string())
+            })?;
+        Ok(Self::new(
+            req.with_limited_body().into_body().into_data_stream(),
+            &boundary,
+        ))
+    }
+}
+
+impl Multipart {
+    fn new(body: BodyDataStream, boundary: &str) -> Self {
+        Self {
+            body,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // Lets the first boundary match the delimiter without a preamble
+            buf: b"\r\n".to_vec(),
+            state: State::Preamble,
+        }
+    }
+
+    /// The next field, skipping whatever is left of the previous one
+    pub async fn next_field(&mut self) -> Result<Option<Field<'_>>, ApiError> {
+        while matches!(self.state, State::Preamble | State::Content) {
+            self.content_chunk().await?;
+        }
+        if self.state == State::End {
+            return Ok(None);
+        }
+
+        // After a boundary: `--` closes the body, a line break starts a part
+        self.fill(2).await?;
+        if self.buf.starts_with(b"--") {
+            self.state = State::End;
+            return Ok(None);
+        }
+        let headers = self.read_headers().await?;
+        self.state = State::Content;
+
+        let mut field = Field {
+            multipart: self,
+            name: None,
+            file_name: None,
+            content_type: None,
+        };
+        for line in headers.split("\r\n").filter(|line| !line.is_empty()) {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-disposition") {
+                field.name = disposition_param(value, "name");
+                field.file_name = disposition_param(value, "filename");
+            } else if name.eq_ignore_ascii_case("content-type") {
+                field.content_type = Some(value.to_string());
+            }
+        }
+        Ok(Some(field))
+    }
+
+    /// Read more of the body into the buffer, failing if it ended
+    async fn read_more(&mut self) -> Result<(), ApiError> {
+        match self.body.next().await {
+            Some(Ok(chunk)) => {
+                self.buf.extend_from_slice(&chunk);
+                Ok(())
+            }
+            Some(Err(e)) => Err(ApiError::BadRequest(format!("Failed to read body: {e}"))),
+            None => Err(ApiError::BadRequest(
+                "Multipart body ended unexpectedly".to_string(),
+            )),
+        }
+    }
+
+    async fn fill(&mut self, len: usize) -> Result<(), ApiError> {
+        while self.buf.len() < len {
+            self.read_more().await?;
+        }
+        Ok(())
+    }
+
+    /// The header block of the part starting at the buffer
+    async fn read_headers(&mut self) -> Result<String, ApiError> {
+        loop {
+            if let Some(end) = find(&self.buf, b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+                self.buf.drain(..end + 4);
+                return Ok(headers);
+            }
+            if self.buf.len() > MAX_PART_HEADERS {
+                return Err(ApiError::BadRequest(
+                    "Multipart part headers are too large".to_string(),
+                ));
+            }
+            self.read_more().await?;
+        }
+    }
+
+    /// The next chunk of the current part's content, or `None` once the
+    /// part's boundary is reached
+    async fn content_chunk(&mut self) -> Result<Option<Bytes>, ApiError> {
+        loop {
+            if !matches!(self.state, State::Preamble | State::Content) {
+                return Ok(None);
+            }
+            if let Some(at) = find(&self.buf, &self.delimiter) {
+                let chunk: Vec<u8> = self.buf.drain(..at).collect();
+                self.buf.drain(..self.delimiter.len());
+                let preamble = self.state == State::Preamble;
+                self.state = State::Boundary;
+                if !chunk.is_empty() && !preamble {
+                    return Ok(Some(chunk.into()));
+                }
+                return Ok(None);
+            }
+            // Keep enough to match a delimiter split across reads
+            let safe = self.buf.len().saturating_sub(self.delimiter.len() - 1);
+            if safe > 0 {
+                let chunk: Vec<u8> = self.buf.drain(..safe).collect();
+                if self.state == State::Content {
+                    return Ok(Some(chunk.into()));
+                }
+            }
+            self.read_more().await?;
+        }
+    }
+}
+
+/// A field of a [`Multipart`] body
+pub struct Field<'a> {
+    multipart: &'a mut Multipart,
+    name: Option<String>,
+    file_name: Option<String>,
+    content_type: Option<String>,
+}
+
+impl Field<'_> {
+    /// The form field's name
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The uploaded file's name, for file fields
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// The next chunk of the field's content
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, ApiError> {
+        self.multipart.content_chunk().await
+    }
+
+    /// The whole content, in memory
+    pub async fn bytes(mut self) -> Result<Bytes, ApiError> {
+        let mut data = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data.into())
+    }
+
+    pub async fn text(self) -> Result<String, ApiError> {
+        String::from_utf8(self.bytes().await?.to_vec())
+            .map_err(|_| ApiError::BadRequest("Form field is not valid UTF-8".to_string()))
+    }
+
+    /// Stream the content into `storage` under `key`
+    pub async fn store(self, storage: &dyn Storage, key: &str) -> Result<ObjectInfo, ApiError> {
+        let content_type = self.content_type.clone();
+        // Errors are reported as `io::Error`s by the stream and surface from
+        // `put_stream`
+        let stream = futures_util::stream::unfold(self, |mut field| async move {
+            match field.chunk().await {
+                Ok(Some(chunk)) => Some((Ok(chunk), field)),
+                Ok(None) => None,
+                Err(e) => Some((Err(io::Error::other(e.to_string())), field)),
+            }
+        });
+        storage
+            .put_stream(key, stream.boxed(), content_type.as_deref())
+            .await
+    }
+}
+
+/// The boundary of a `multipart/form-data` content type
+fn parse_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+    params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+            .filter(|boundary| !boundary.is_empty() && boundary.len() <= 70)
+    })
+}
+
+/// A parameter of a `Content-Disposition` value, e.g. `name="avatar"`
+fn disposition_param(value: &str, param: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|part| {
+        let (name, value) = part.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case(param)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use axum::body::Body;
+
+    const BODY: &str = "preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Holiday\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"beach.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        sand\r\n--Xy and sea\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"skipped\"\r\n\
+        \r\n\
+        never read\r\n\
+        --XyZ--\r\n";
+
+    /// The body in `size`-byte chunks, to split boundaries across reads
+    fn request(size: usize) -> Request {
+        let chunks: Vec<Result<Bytes, io::Error>> = BODY
+            .as_bytes()
+            .chunks(size)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        Request::builder()
+            .method("POST")
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=\"XyZ\"")
+            .body(Body::from_stream(futures_util::stream::iter(chunks)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn streams_fields_into_storage() {
+        let storage = LocalStorage::new(
+            std::env::temp_dir().join(format!("dy-rs-multipart-{}", uuid::Uuid::new_v4())),
+        );
+        for size in [1, 3, 7, BODY.len()] {
+            let mut form = Multipart::from_request(request(size), &()).await.unwrap();
+
+            let title = form.next_field().await.unwrap().unwrap();
+            assert_eq!(title.name(), Some("title"));
+            assert_eq!(title.file_name(), None);
+            assert_eq!(title.text().await.unwrap(), "Holiday");
+
+            let photo = form.next_field().await.unwrap().unwrap();
+            assert_eq!(photo.file_name(), Some("beach.txt"));
+            let info = photo.store(&storage, "photos/beach.txt").await.unwrap();
+            assert_eq!(info.content_type.as_deref(), Some("text/plain"));
+            assert_eq!(
+                storage.get("photos/beach.txt").await.unwrap().unwrap(),
+                "sand\r\n--Xy and sea"
+            );
+
+            // Unread fields are skipped
+            assert!(form.next_field().await.unwrap().is_some());
+            assert!(form.next_field().await.unwrap().is_none());
+            assert!(form.next_field().await.unwrap().is_none());
+        }
+        tokio::fs::remove_dir_all(storage.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_and_non_multipart_bodies() {
+        let truncated = Request::builder()
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=XyZ")
+            .body(Body::from(
+                "--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nhal",
+            ))
+            .unwrap();
+        let mut form = Multipart::from_request(truncated, &()).await.unwrap();
+        let field = form.next_field().await.unwrap().unwrap();
+        assert!(field.bytes().await.is_err());
+
+        let json = Request::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        assert!(Multipart::from_request(json, &()).await.is_err());
+    }
+}
@@ -0,0 +1,517 @@
+//! Storage backends for in-progress uploads
+//!
+//! [`FileUploadStore`] keeps uploads on local disk. [`StorageUploadStore`]
+//! keeps them in the app's [`Storage`], so they end up wherever it puts
+//! objects, S3 included.
+
+use std::path::{Path, PathBuf};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use crate::error::ApiError;
+use crate::storage::{SharedStorage, Storage};
+
+/// State of a single upload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadInfo {
+    /// Upload ID (used in the upload URL)
+    pub id: String,
+
+    /// Total size of the upload in bytes
+    pub length: u64,
+
+    /// Number of bytes received so far
+    pub offset: u64,
+
+    /// Raw `Upload-Metadata` header sent on creation
+    pub metadata: Option<String>,
+
+    /// When the upload expires if it is not completed
+    pub expires_at: DateTime<Utc>,
+}
+
+impl UploadInfo {
+    /// Check if all bytes have been received
+    pub fn is_complete(&self) -> bool {
+        self.offset >= self.length
+    }
+
+    /// Check if the upload has expired
+    pub fn is_expired(&self) -> bool {
+        !self.is_complete() && self.expires_at <= Utc::now()
+    }
+}
+
+/// Upload storage trait - implement this to keep uploads somewhere other than local disk
+#[async_trait::async_trait]
+pub trait UploadStore: Send + Sync + 'static {
+    /// Create a new, empty upload
+    async fn create(
+        &self,
+        length: u64,
+        metadata: Option<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<UploadInfo, ApiError>;
+
+    /// Get the current state of an upload
+    async fn info(&self, id: &str) -> Result<Option<UploadInfo>, ApiError>;
+
+    /// Append `data` at `offset`, returning the updated upload
+    ///
+    /// Callers check that the data does not exceed the upload length, and
+    /// that `offset` matches the stored offset; the built-in stores check the
+    /// offset again and fail with `409 Conflict` if it moved.
+    async fn append(&self, id: &str, offset: u64, data: Bytes) -> Result<UploadInfo, ApiError>;
+
+    /// Delete an upload and its data
+    async fn delete(&self, id: &str) -> Result<(), ApiError>;
+}
+
+/// Upload store that keeps data and state files in a local directory
+///
+/// Each upload is stored as `{id}.bin` with its state in `{id}.json`.
+#[derive(Debug, Clone)]
+pub struct FileUploadStore {
+    dir: PathBuf,
+}
+
+impl FileUploadStore {
+    /// Create a store rooted at `dir` (created on first upload)
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Directory the store writes to
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the data file for an upload
+    pub fn data_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.bin"))
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    async fn write_info(&self, info: &UploadInfo) -> Result<(), ApiError> {
+        let json = serde_json::to_vec(info)
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to encode upload: {e}")))?;
+        tokio::fs::write(self.info_path(&info.id), json)
+            .await
+            .map_err(io_error)
+    }
+
+    /// Delete all expired, incomplete uploads, returning how many were removed
+    pub async fn purge_expired(&self) -> Result<usize, ApiError> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let mut removed = 0;
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if let Some(info) = self.info(id).await?
+                && info.is_expired()
+            {
+                self.delete(id).await?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+fn offset_moved() -> ApiError {
+    ApiError::conflict("Upload offset changed")
+}
+
+fn not_found() -> ApiError {
+    ApiError::NotFound("Upload not found".to_string())
+}
+
+fn io_error(e: std::io::Error) -> ApiError {
+    ApiError::InternalServerError(format!("Upload storage error: {e}"))
+}
+
+// IDs come from the URL, so never let them escape the upload directory.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[async_trait::async_trait]
+impl UploadStore for FileUploadStore {
+    async fn create(
+        &self,
+        length: u64,
+        metadata: Option<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<UploadInfo, ApiError> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(io_error)?;
+
+        let info = UploadInfo {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            length,
+            offset: 0,
+            metadata,
+            expires_at,
+        };
+
+        tokio::fs::File::create(self.data_path(&info.id))
+            .await
+            .map_err(io_error)?;
+        self.write_info(&info).await?;
+
+        Ok(info)
+    }
+
+    async fn info(&self, id: &str) -> Result<Option<UploadInfo>, ApiError> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+
+        match tokio::fs::read(self.info_path(id)).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).map(Some).map_err(|e| {
+                ApiError::InternalServerError(format!("Corrupt upload state for {id}: {e}"))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    async fn append(&self, id: &str, offset: u64, data: Bytes) -> Result<UploadInfo, ApiError> {
+        let mut info = self
+            .info(id)
+            .await?
+            .ok_or_else(|| ApiError::NotFound("Upload not found".to_string()))?;
+        if info.offset != offset {
+            return Err(offset_moved());
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(self.data_path(id))
+            .await
+            .map_err(io_error)?;
+        // Truncate anything past `offset` left over from an interrupted write.
+        file.set_len(offset).await.map_err(io_error)?;
+        file.seek(std::io::SeekFrom::Start(offset))
+            .await
+            .map_err(io_error)?;
+        file.write_all(&data).await.map_err(io_error)?;
+        file.flush().await.map_err(io_error)?;
+
+        info.offset = offset + data.len() as u64;
+        self.write_info(&info).await?;
+
+        Ok(info)
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), ApiError> {
+        if !is_valid_id(id) {
+            return Err(ApiError::NotFound("Upload not found".to_string()));
+        }
+
+        for path in [self.data_path(id), self.info_path(id)] {
+            match tokio::fs::remove_file(path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Upload store that keeps uploads in a [`Storage`] backend
+///
+/// Each chunk is stored as its own object under
+/// `{prefix}/incomplete/{id}/`, next to the upload's state. Once the last
+/// byte arrives the chunks are joined into [`object_key`](Self::object_key),
+/// `{prefix}/{id}`, and removed; the state stays until the upload is
+/// deleted, so `HEAD` keeps answering.
+///
+/// ```rust,ignore
+/// let storage = SharedStorage::from(config.storage.storage(s3_transport));
+/// let store = StorageUploadStore::new(storage, "uploads");
+/// App::new().mount(tus_routes("/files", store, TusConfig::default()));
+/// ```
+///
+/// Storage can't list objects, so expired uploads are removed when a client
+/// next touches them rather than by a sweep.
+#[derive(Clone)]
+pub struct StorageUploadStore {
+    storage: SharedStorage,
+    prefix: String,
+}
+
+/// What a [`StorageUploadStore`] keeps about an upload
+#[derive(Serialize, Deserialize)]
+struct StoredUpload {
+    info: UploadInfo,
+    /// Offsets of the chunks stored so far
+    chunks: Vec<u64>,
+}
+
+impl StorageUploadStore {
+    /// Keep uploads in `storage`, under the keys starting with `prefix`
+    pub fn new(storage: impl Storage, prefix: impl Into<String>) -> Self {
+        Self {
+            storage: SharedStorage::new(storage),
+            prefix: prefix.into().trim_matches('/').to_string(),
+        }
+    }
+
+    /// Key of a completed upload's content
+    pub fn object_key(&self, id: &str) -> String {
+        self.key(id)
+    }
+
+    fn key(&self, path: &str) -> String {
+        match self.prefix.as_str() {
+            "" => path.to_string(),
+            prefix => format!("{prefix}/{path}"),
+        }
+    }
+
+    fn state_key(&self, id: &str) -> String {
+        self.key(&format!("incomplete/{id}/state.json"))
+    }
+
+    fn chunk_key(&self, id: &str, offset: u64) -> String {
+        self.key(&format!("incomplete/{id}/{offset:020}"))
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<StoredUpload>, ApiError> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+        let Some(bytes) = self.storage.get(&self.state_key(id)).await? else {
+            return Ok(None);
+        };
+        serde_json::from_slice(&bytes).map(Some).map_err(|e| {
+            ApiError::InternalServerError(format!("Corrupt upload state for {id}: {e}"))
+        })
+    }
+
+    async fn save(&self, upload: &StoredUpload) -> Result<(), ApiError> {
+        let json = serde_json::to_vec(upload)
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to encode upload: {e}")))?;
+        self.storage
+            .put(
+                &self.state_key(&upload.info.id),
+                json.into(),
+                Some("application/json"),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Join the chunks into the upload's object, then remove them
+    async fn assemble(&self, upload: &mut StoredUpload) -> Result<(), ApiError> {
+        let id = upload.info.id.clone();
+        let chunks = futures_util::stream::iter(upload.chunks.clone())
+            .then(|offset| {
+                let key = self.chunk_key(&id, offset);
+                async move {
+                    match self.storage.get(&key).await {
+                        Ok(Some(data)) => Ok(data),
+                        Ok(None) => Err(std::io::Error::other(format!("missing chunk {key}"))),
+                        Err(e) => Err(std::io::Error::other(e.to_string())),
+                    }
+                }
+            })
+            .boxed();
+        self.storage
+            .put_stream(&self.object_key(&id), chunks, None)
+            .await?;
+        for offset in std::mem::take(&mut upload.chunks) {
+            self.storage.delete(&self.chunk_key(&id, offset)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl UploadStore for StorageUploadStore {
+    async fn create(
+        &self,
+        length: u64,
+        metadata: Option<String>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<UploadInfo, ApiError> {
+        let mut upload = StoredUpload {
+            info: UploadInfo {
+                id: uuid::Uuid::new_v4().simple().to_string(),
+                length,
+                offset: 0,
+                metadata,
+                expires_at,
+            },
+            chunks: Vec::new(),
+        };
+        if upload.info.is_complete() {
+            self.assemble(&mut upload).await?;
+        }
+        self.save(&upload).await?;
+        Ok(upload.info)
+    }
+
+    async fn info(&self, id: &str) -> Result<Option<UploadInfo>, ApiError> {
+        Ok(self.load(id).await?.map(|upload| upload.info))
+    }
+
+    async fn append(&self, id: &str, offset: u64, data: Bytes) -> Result<UploadInfo, ApiError> {
+        let mut upload = self.load(id).await?.ok_or_else(not_found)?;
+        if upload.info.offset != offset {
+            return Err(offset_moved());
+        }
+
+        if !data.is_empty() {
+            let len = data.len() as u64;
+            self.storage
+                .put(&self.chunk_key(id, offset), data, None)
+                .await?;
+            upload.chunks.push(offset);
+            upload.info.offset = offset + len;
+        }
+        if upload.info.is_complete() && !upload.chunks.is_empty() {
+            self.assemble(&mut upload).await?;
+        }
+        self.save(&upload).await?;
+
+        Ok(upload.info)
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), ApiError> {
+        let upload = self.load(id).await?.ok_or_else(not_found)?;
+        for offset in &upload.chunks {
+            self.storage.delete(&self.chunk_key(id, *offset)).await?;
+        }
+        self.storage.delete(&self.object_key(id)).await?;
+        self.storage.delete(&self.state_key(id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> FileUploadStore {
+        FileUploadStore::new(
+            std::env::temp_dir().join(format!("dy-rs-uploads-{}", uuid::Uuid::new_v4())),
+        )
+    }
+
+    #[tokio::test]
+    async fn appends_chunks_in_order() {
+        let store = temp_store();
+        let info = store
+            .create(11, None, Utc::now() + chrono::Duration::hours(1))
+            .await
+            .unwrap();
+
+        store
+            .append(&info.id, 0, Bytes::from("hello "))
+            .await
+            .unwrap();
+        let info = store
+            .append(&info.id, 6, Bytes::from("world"))
+            .await
+            .unwrap();
+
+        assert!(info.is_complete());
+        let data = tokio::fs::read(store.data_path(&info.id)).await.unwrap();
+        assert_eq!(data, b"hello world");
+
+        tokio::fs::remove_dir_all(store.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn purges_expired_uploads() {
+        let store = temp_store();
+        let expired = store
+            .create(10, None, Utc::now() - chrono::Duration::seconds(1))
+            .await
+            .unwrap();
+        let live = store
+            .create(10, None, Utc::now() + chrono::Duration::hours(1))
+            .await
+            .unwrap();
+
+        assert_eq!(store.purge_expired().await.unwrap(), 1);
+        assert!(store.info(&expired.id).await.unwrap().is_none());
+        assert!(store.info(&live.id).await.unwrap().is_some());
+
+        tokio::fs::remove_dir_all(store.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn storage_uploads_are_joined_into_one_object() {
+        use crate::storage::LocalStorage;
+
+        let dir = std::env::temp_dir().join(format!("dy-rs-uploads-{}", uuid::Uuid::new_v4()));
+        let storage = LocalStorage::new(&dir);
+        let store = StorageUploadStore::new(storage.clone(), "uploads");
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+        let info = store.create(11, None, expires_at).await.unwrap();
+
+        store
+            .append(&info.id, 0, Bytes::from("hello "))
+            .await
+            .unwrap();
+        // A client still on the old offset is turned away
+        let stale = store.append(&info.id, 0, Bytes::from("HELLO ")).await;
+        assert!(matches!(stale, Err(ApiError::Conflict(_))));
+        let info = store
+            .append(&info.id, 6, Bytes::from("world"))
+            .await
+            .unwrap();
+
+        assert!(info.is_complete());
+        let key = store.object_key(&info.id);
+        assert_eq!(key, format!("uploads/{}", info.id));
+        assert_eq!(storage.get(&key).await.unwrap().unwrap(), "hello world");
+        assert!(
+            storage
+                .get(&store.chunk_key(&info.id, 0))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(store.info(&info.id).await.unwrap(), Some(info.clone()));
+
+        let empty = store.create(0, None, expires_at).await.unwrap();
+        let key = store.object_key(&empty.id);
+        assert_eq!(storage.get(&key).await.unwrap().unwrap(), "");
+
+        store.delete(&info.id).await.unwrap();
+        assert!(store.info(&info.id).await.unwrap().is_none());
+        assert!(store.info("../state").await.unwrap().is_none());
+
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_path_traversal_ids() {
+        let store = temp_store();
+        assert!(store.info("../etc/passwd").await.unwrap().is_none());
+    }
+}
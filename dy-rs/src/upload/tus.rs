@@ -0,0 +1,591 @@
+//! tus 1.0 resumable upload endpoints
+//!
+//! Implements the core protocol plus the `creation`, `expiration` and
+//! `termination` extensions.
+//!
+//! `PATCH` and `DELETE` requests for the same upload are handled one at a
+//! time, so two clients sending the same offset can't interleave their
+//! writes: the second one gets `409 Conflict` with the new offset.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{head, post},
+};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::OwnedMutexGuard;
+
+use super::store::{UploadInfo, UploadStore};
+use crate::error::ApiError;
+
+/// Protocol version implemented by these endpoints
+pub const TUS_VERSION: &str = "1.0.0";
+
+const TUS_EXTENSIONS: &str = "creation,expiration,termination";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Configuration for tus uploads
+#[derive(Debug, Clone)]
+pub struct TusConfig {
+    /// Largest accepted upload in bytes (default: 1 GiB)
+    pub max_size: u64,
+
+    /// Largest accepted PATCH body in bytes (default: 16 MiB)
+    pub max_chunk_size: usize,
+
+    /// Time an incomplete upload is kept, in seconds (default: 24 hours)
+    pub expiration_secs: u64,
+}
+
+impl TusConfig {
+    /// Set the largest accepted upload
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Set the largest accepted PATCH body
+    pub fn max_chunk_size(mut self, bytes: usize) -> Self {
+        self.max_chunk_size = bytes;
+        self
+    }
+
+    /// Set how long incomplete uploads are kept
+    pub fn expiration(mut self, duration: Duration) -> Self {
+        self.expiration_secs = duration.as_secs();
+        self
+    }
+}
+
+impl Default for TusConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 1024 * 1024 * 1024,     // 1 GiB
+            max_chunk_size: 16 * 1024 * 1024, // 16 MiB
+            expiration_secs: 24 * 60 * 60,    // 24 hours
+        }
+    }
+}
+
+/// tus protocol error
+#[derive(Debug)]
+pub enum TusError {
+    /// Missing or unsupported `Tus-Resumable` header
+    UnsupportedVersion,
+    /// Missing or invalid `Upload-Length` / `Upload-Offset` header
+    InvalidHeader(&'static str),
+    /// PATCH without `application/offset+octet-stream` content type
+    InvalidContentType,
+    /// Upload or chunk exceeds the configured limits
+    TooLarge,
+    /// Upload does not exist
+    NotFound,
+    /// Upload expired before it was completed
+    Expired,
+    /// `Upload-Offset` does not match the stored offset
+    OffsetMismatch { expected: u64 },
+    /// Storage backend failure
+    Store(ApiError),
+}
+
+impl From<ApiError> for TusError {
+    fn from(err: ApiError) -> Self {
+        TusError::Store(err)
+    }
+}
+
+#[derive(Serialize)]
+struct TusErrorResponse {
+    code: String,
+    message: String,
+}
+
+impl IntoResponse for TusError {
+    fn into_response(self) -> Response {
+        let (status, code, message) = match self {
+            TusError::UnsupportedVersion => (
+                StatusCode::PRECONDITION_FAILED,
+                "TUS_VERSION_UNSUPPORTED",
+                format!("Tus-Resumable: {TUS_VERSION} header required"),
+            ),
+            TusError::InvalidHeader(name) => (
+                StatusCode::BAD_REQUEST,
+                "TUS_INVALID_HEADER",
+                format!("Missing or invalid {name} header"),
+            ),
+            TusError::InvalidContentType => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "TUS_INVALID_CONTENT_TYPE",
+                format!("Content-Type must be {OFFSET_CONTENT_TYPE}"),
+            ),
+            TusError::TooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "TUS_TOO_LARGE",
+                "Upload exceeds the maximum size".to_string(),
+            ),
+            TusError::NotFound => (
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                "Upload not found".to_string(),
+            ),
+            TusError::Expired => (
+                StatusCode::GONE,
+                "TUS_UPLOAD_EXPIRED",
+                "Upload has expired".to_string(),
+            ),
+            TusError::OffsetMismatch { expected } => (
+                StatusCode::CONFLICT,
+                "TUS_OFFSET_MISMATCH",
+                format!("Upload-Offset must be {expected}"),
+            ),
+            TusError::Store(err) => return with_tus_headers(err.into_response()),
+        };
+
+        with_tus_headers(
+            (
+                status,
+                Json(TusErrorResponse {
+                    code: code.to_string(),
+                    message,
+                }),
+            )
+                .into_response(),
+        )
+    }
+}
+
+fn with_tus_headers(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert("tus-resumable", HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+/// Parse an `Upload-Metadata` header into key/value pairs
+///
+/// Values are base64-encoded on the wire; keys without a value map to an
+/// empty string. Entries that fail to decode are skipped.
+pub fn parse_metadata(header: &str) -> BTreeMap<String, String> {
+    header
+        .split(',')
+        .filter_map(|pair| {
+            let mut parts = pair.trim().splitn(2, ' ');
+            let key = parts.next().filter(|k| !k.is_empty())?;
+            let value = match parts.next() {
+                Some(encoded) => {
+                    let bytes = base64::engine::general_purpose::STANDARD
+                        .decode(encoded.trim())
+                        .ok()?;
+                    String::from_utf8(bytes).ok()?
+                }
+                None => String::new(),
+            };
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+struct TusState<S> {
+    store: Arc<S>,
+    config: TusConfig,
+    base_path: String,
+    locks: Arc<UploadLocks>,
+}
+
+impl<S> Clone for TusState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            config: self.config.clone(),
+            base_path: self.base_path.clone(),
+            locks: self.locks.clone(),
+        }
+    }
+}
+
+/// One lock per upload being changed, dropped once nobody holds or waits
+/// for it
+#[derive(Default)]
+struct UploadLocks(Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>);
+
+impl UploadLocks {
+    async fn lock(&self, id: &str) -> UploadGuard<'_> {
+        let lock = self
+            .0
+            .lock()
+            .unwrap()
+            .entry(id.to_string())
+            .or_default()
+            .clone();
+        UploadGuard {
+            locks: self,
+            id: id.to_string(),
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+}
+
+struct UploadGuard<'a> {
+    locks: &'a UploadLocks,
+    id: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for UploadGuard<'_> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        let mut locks = self.locks.0.lock().unwrap();
+        // Only the map's own reference is left
+        if locks
+            .get(&self.id)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.id);
+        }
+    }
+}
+
+fn require_tus_version(headers: &HeaderMap) -> Result<(), TusError> {
+    match headers.get("tus-resumable").and_then(|v| v.to_str().ok()) {
+        Some(TUS_VERSION) => Ok(()),
+        _ => Err(TusError::UnsupportedVersion),
+    }
+}
+
+fn u64_header(headers: &HeaderMap, name: &'static str) -> Result<u64, TusError> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .ok_or(TusError::InvalidHeader(name))
+}
+
+fn http_date(time: DateTime<Utc>) -> HeaderValue {
+    HeaderValue::from_str(&time.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+        .expect("formatted date is a valid header value")
+}
+
+async fn load_live<S: UploadStore>(store: &S, id: &str) -> Result<UploadInfo, TusError> {
+    let info = store.info(id).await?.ok_or(TusError::NotFound)?;
+    if info.is_expired() {
+        store.delete(id).await?;
+        return Err(TusError::Expired);
+    }
+    Ok(info)
+}
+
+async fn options_handler<S: UploadStore>(State(state): State<TusState<S>>) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert("tus-version", HeaderValue::from_static(TUS_VERSION));
+    headers.insert("tus-extension", HeaderValue::from_static(TUS_EXTENSIONS));
+    headers.insert("tus-max-size", HeaderValue::from(state.config.max_size));
+    with_tus_headers((StatusCode::NO_CONTENT, headers).into_response())
+}
+
+async fn create_handler<S: UploadStore>(
+    State(state): State<TusState<S>>,
+    headers: HeaderMap,
+) -> Result<Response, TusError> {
+    require_tus_version(&headers)?;
+
+    let length = u64_header(&headers, "upload-length")?;
+    if length > state.config.max_size {
+        return Err(TusError::TooLarge);
+    }
+
+    let metadata = headers
+        .get("upload-metadata")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let expires_at = Utc::now() + chrono::Duration::seconds(state.config.expiration_secs as i64);
+
+    let info = state.store.create(length, metadata, expires_at).await?;
+    tracing::debug!(upload_id = %info.id, length, "tus upload created");
+
+    let location = format!("{}/{}", state.base_path.trim_end_matches('/'), info.id);
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::LOCATION,
+        HeaderValue::from_str(&location).map_err(|_| TusError::NotFound)?,
+    );
+    response_headers.insert("upload-expires", http_date(info.expires_at));
+
+    Ok(with_tus_headers(
+        (StatusCode::CREATED, response_headers).into_response(),
+    ))
+}
+
+async fn head_handler<S: UploadStore>(
+    State(state): State<TusState<S>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, TusError> {
+    require_tus_version(&headers)?;
+    let info = load_live(state.store.as_ref(), &id).await?;
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert("upload-offset", HeaderValue::from(info.offset));
+    response_headers.insert("upload-length", HeaderValue::from(info.length));
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    if !info.is_complete() {
+        response_headers.insert("upload-expires", http_date(info.expires_at));
+    }
+    if let Some(value) = info
+        .metadata
+        .as_deref()
+        .and_then(|m| HeaderValue::from_str(m).ok())
+    {
+        response_headers.insert("upload-metadata", value);
+    }
+
+    Ok(with_tus_headers(
+        (StatusCode::OK, response_headers).into_response(),
+    ))
+}
+
+async fn patch_handler<S: UploadStore>(
+    State(state): State<TusState<S>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, TusError> {
+    require_tus_version(&headers)?;
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if content_type != Some(OFFSET_CONTENT_TYPE) {
+        return Err(TusError::InvalidContentType);
+    }
+
+    let offset = u64_header(&headers, "upload-offset")?;
+    let _guard = state.locks.lock(&id).await;
+    let info = load_live(state.store.as_ref(), &id).await?;
+    if offset != info.offset {
+        return Err(TusError::OffsetMismatch {
+            expected: info.offset,
+        });
+    }
+    if offset + body.len() as u64 > info.length {
+        return Err(TusError::TooLarge);
+    }
+
+    let info = state.store.append(&id, offset, body).await?;
+    if info.is_complete() {
+        tracing::info!(upload_id = %info.id, length = info.length, "tus upload completed");
+    }
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert("upload-offset", HeaderValue::from(info.offset));
+    if !info.is_complete() {
+        response_headers.insert("upload-expires", http_date(info.expires_at));
+    }
+
+    Ok(with_tus_headers(
+        (StatusCode::NO_CONTENT, response_headers).into_response(),
+    ))
+}
+
+async fn delete_handler<S: UploadStore>(
+    State(state): State<TusState<S>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, TusError> {
+    require_tus_version(&headers)?;
+    let _guard = state.locks.lock(&id).await;
+    state.store.info(&id).await?.ok_or(TusError::NotFound)?;
+    state.store.delete(&id).await?;
+    Ok(with_tus_headers(StatusCode::NO_CONTENT.into_response()))
+}
+
+/// Create tus upload routes under `base_path`
+///
+/// - `OPTIONS {base_path}` - protocol discovery
+/// - `POST {base_path}` - create an upload
+/// - `HEAD {base_path}/{id}` - current offset
+/// - `PATCH {base_path}/{id}` - append a chunk
+/// - `DELETE {base_path}/{id}` - terminate an upload
+pub fn tus_routes<S: UploadStore>(base_path: &str, store: S, config: TusConfig) -> Router {
+    let body_limit = config.max_chunk_size;
+    let state = TusState {
+        store: Arc::new(store),
+        config,
+        base_path: base_path.to_string(),
+        locks: Arc::default(),
+    };
+
+    Router::new()
+        .route(
+            base_path,
+            post(create_handler::<S>).options(options_handler::<S>),
+        )
+        .route(
+            &format!("{}/{{id}}", base_path.trim_end_matches('/')),
+            head(head_handler::<S>)
+                .patch(patch_handler::<S>)
+                .delete(delete_handler::<S>),
+        )
+        .layer(DefaultBodyLimit::max(body_limit))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upload::FileUploadStore;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    fn test_app() -> (Router, FileUploadStore) {
+        let store = FileUploadStore::new(
+            std::env::temp_dir().join(format!("dy-rs-tus-{}", uuid::Uuid::new_v4())),
+        );
+        let app = tus_routes("/files", store.clone(), TusConfig::default().max_size(100));
+        (app, store)
+    }
+
+    fn tus_request(method: &str, uri: &str) -> axum::http::request::Builder {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("tus-resumable", TUS_VERSION)
+    }
+
+    fn patch(uri: &str, offset: u64, data: &'static str) -> Request<Body> {
+        tus_request("PATCH", uri)
+            .header("content-type", OFFSET_CONTENT_TYPE)
+            .header("upload-offset", offset)
+            .body(Body::from(data))
+            .unwrap()
+    }
+
+    async fn create(app: &Router, length: u64) -> Response {
+        app.clone()
+            .oneshot(
+                tus_request("POST", "/files")
+                    .header("upload-length", length)
+                    .header("upload-metadata", "filename aGVsbG8udHh0")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn resumable_upload_flow() {
+        let (app, store) = test_app();
+
+        let res = create(&app, 11).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let location = res.headers()["location"].to_str().unwrap().to_string();
+        assert!(location.starts_with("/files/"));
+
+        let res = app
+            .clone()
+            .oneshot(patch(&location, 0, "hello "))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()["upload-offset"], "6");
+
+        // A client resuming after a dropped connection asks for the offset first.
+        let res = app
+            .clone()
+            .oneshot(tus_request("HEAD", &location).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.headers()["upload-offset"], "6");
+        assert_eq!(res.headers()["upload-length"], "11");
+        assert_eq!(res.headers()["upload-metadata"], "filename aGVsbG8udHh0");
+
+        let res = app
+            .clone()
+            .oneshot(patch(&location, 6, "world"))
+            .await
+            .unwrap();
+        assert_eq!(res.headers()["upload-offset"], "11");
+
+        let id = location.rsplit('/').next().unwrap();
+        let data = tokio::fs::read(store.data_path(id)).await.unwrap();
+        assert_eq!(data, b"hello world");
+
+        tokio::fs::remove_dir_all(store.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_offset_and_oversized_uploads() {
+        let (app, store) = test_app();
+
+        assert_eq!(
+            create(&app, 1000).await.status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let res = create(&app, 10).await;
+        let location = res.headers()["location"].to_str().unwrap().to_string();
+        let res = app
+            .clone()
+            .oneshot(patch(&location, 3, "abc"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        tokio::fs::remove_dir_all(store.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_patches_do_not_interleave() {
+        let (app, store) = test_app();
+
+        let res = create(&app, 6).await;
+        let location = res.headers()["location"].to_str().unwrap().to_string();
+        let (first, second) = tokio::join!(
+            app.clone().oneshot(patch(&location, 0, "aaaaaa")),
+            app.clone().oneshot(patch(&location, 0, "bbbbbb")),
+        );
+        let mut statuses = [first.unwrap().status(), second.unwrap().status()];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::NO_CONTENT, StatusCode::CONFLICT]);
+
+        let id = location.rsplit('/').next().unwrap();
+        let data = tokio::fs::read(store.data_path(id)).await.unwrap();
+        assert!(data == b"aaaaaa" || data == b"bbbbbb");
+
+        tokio::fs::remove_dir_all(store.dir()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn requires_tus_resumable_header() {
+        let (app, _store) = test_app();
+        let res = app
+            .oneshot(
+                Request::post("/files")
+                    .header("upload-length", 5)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(res.headers()["tus-resumable"], TUS_VERSION);
+    }
+
+    #[test]
+    fn parses_upload_metadata() {
+        let metadata = parse_metadata("filename aGVsbG8udHh0,is_confidential");
+        assert_eq!(metadata["filename"], "hello.txt");
+        assert_eq!(metadata["is_confidential"], "");
+    }
+}
@@ -0,0 +1,380 @@
+//! API versioning
+//!
+//! [`App::mount_versioned`](crate::App::mount_versioned) serves a router
+//! below its version's path prefix, assigns the documented operations under
+//! that prefix to the version and serves their docs at `/docs/{version}`:
+//!
+//! ```rust,ignore
+//! App::new()
+//!     .auto_configure()
+//!     .mount_versioned("v2", v2_routes)
+//!     .mount_versioned(
+//!         ApiVersion::new("v1")
+//!             .deprecated(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap())
+//!             .sunset(Utc.with_ymd_and_hms(2026, 12, 31, 0, 0, 0).unwrap())
+//!             .link("https://example.com/docs/migrating-to-v2"),
+//!         v1_routes,
+//!     )
+//!     .run()
+//!     .await
+//! ```
+//!
+//! Responses of a deprecated version carry `Deprecation` (RFC 9745),
+//! `Sunset` (RFC 8594) and `Link` headers, and its operations are marked
+//! deprecated in the OpenAPI document.
+//!
+//! Handlers read the version with the [`Version`] extractor: the path prefix
+//! of a versioned mount, otherwise the version asked for in the `Accept`
+//! header, as `application/vnd.example.v2+json` or
+//! `application/json; version=2`.
+
+use std::{fmt, future::Future, pin::Pin, sync::Arc, task, time::SystemTime};
+
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts, Request},
+    http::{HeaderMap, HeaderName, HeaderValue, header, request::Parts},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use tower::{Layer, Service};
+
+use crate::error::ApiError;
+
+/// A version of the API and its deprecation schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiVersion {
+    name: String,
+    deprecated: Option<DateTime<Utc>>,
+    sunset: Option<DateTime<Utc>>,
+    link: Option<String>,
+}
+
+impl ApiVersion {
+    /// A current version, e.g. `v2`, served below `/v2`
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            deprecated: None,
+            sunset: None,
+            link: None,
+        }
+    }
+
+    /// Deprecated since `at`
+    pub fn deprecated(mut self, at: DateTime<Utc>) -> Self {
+        self.deprecated = Some(at);
+        self
+    }
+
+    /// Removed at `at`
+    pub fn sunset(mut self, at: DateTime<Utc>) -> Self {
+        self.sunset = Some(at);
+        self
+    }
+
+    /// Page telling clients how to move off this version
+    pub fn link(mut self, url: impl Into<String>) -> Self {
+        self.link = Some(url.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the version is deprecated or has a sunset date
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecated.is_some() || self.sunset.is_some()
+    }
+
+    /// The `Deprecation`, `Sunset` and `Link` headers of its responses
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(at) = self.deprecated
+            && let Ok(value) = HeaderValue::from_str(&format!("@{}", at.timestamp()))
+        {
+            headers.insert(HeaderName::from_static("deprecation"), value);
+        }
+        if let Some(at) = self.sunset
+            && let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(SystemTime::from(at)))
+        {
+            headers.insert(HeaderName::from_static("sunset"), value);
+        }
+        if let Some(link) = self.link.as_ref().filter(|_| self.is_deprecated()) {
+            let rel = if self.deprecated.is_some() {
+                "deprecation"
+            } else {
+                "sunset"
+            };
+            if let Ok(value) = HeaderValue::from_str(&format!("<{link}>; rel=\"{rel}\"")) {
+                headers.insert(header::LINK, value);
+            }
+        }
+        headers
+    }
+}
+
+impl From<&str> for ApiVersion {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for ApiVersion {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+/// The API version a request asked for; see the [module docs](self)
+///
+/// Versions given in the `Accept` header as a bare number are prefixed with
+/// `v`, so `version=2` and a `/v2` prefix read the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version(pub String);
+
+impl Version {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The version asked for in `headers`' `Accept`, if any
+    pub fn from_accept(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(accepted_version)
+            .map(|version| {
+                if version.starts_with(|c: char| c.is_ascii_digit()) {
+                    Self(format!("v{version}"))
+                } else {
+                    Self(version.to_string())
+                }
+            })
+    }
+}
+
+/// The version named by one media range: its `version` parameter, or a
+/// `vnd.{name}.{version}` subtype
+fn accepted_version(range: &str) -> Option<&str> {
+    let mut parts = range.split(';').map(str::trim);
+    let media_type = parts.next()?;
+    let parameter = parts.find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("version")
+            .then(|| value.trim().trim_matches('"'))
+    });
+    if let Some(version) = parameter.filter(|version| !version.is_empty()) {
+        return Some(version);
+    }
+    let subtype = media_type.split_once('/')?.1;
+    let vendor = subtype.strip_prefix("vnd.")?;
+    let vendor = vendor.split_once('+').map_or(vendor, |(vendor, _)| vendor);
+    let (_, version) = vendor.rsplit_once('.')?;
+    let number = version.strip_prefix('v')?;
+    number
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then_some(version)
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq<&str> for Version {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl<S> FromRequestParts<S> for Version
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        <Self as OptionalFromRequestParts<S>>::from_request_parts(parts, state)
+            .await?
+            .ok_or_else(|| {
+                ApiError::bad_request("Name an API version in the path or the Accept header")
+            })
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for Version
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<Self>()
+            .cloned()
+            .or_else(|| Self::from_accept(&parts.headers)))
+    }
+}
+
+/// Layer marking requests with an [`ApiVersion`] and adding its deprecation
+/// headers to the responses; [`App::mount_versioned`](crate::App::mount_versioned)
+/// adds it to the mounted router
+#[derive(Debug, Clone)]
+pub struct VersionLayer {
+    version: Version,
+    headers: Arc<HeaderMap>,
+}
+
+impl VersionLayer {
+    pub fn new(version: impl Into<ApiVersion>) -> Self {
+        let version = version.into();
+        Self {
+            headers: Arc::new(version.headers()),
+            version: Version(version.name),
+        }
+    }
+}
+
+impl<S> Layer<S> for VersionLayer {
+    type Service = VersionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VersionService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service produced by [`VersionLayer`]
+#[derive(Clone)]
+pub struct VersionService<S> {
+    inner: S,
+    layer: VersionLayer,
+}
+
+impl<S> Service<Request> for VersionService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        request.extensions_mut().insert(self.layer.version.clone());
+        let handled = self.inner.call(request);
+        let headers = self.layer.headers.clone();
+
+        Box::pin(async move {
+            let mut response = handled.await?;
+            for (name, value) in headers.iter() {
+                response.headers_mut().insert(name, value.clone());
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode, routing::get};
+    use chrono::TimeZone;
+    use tower::ServiceExt;
+
+    fn accepting(accept: &str) -> Option<Version> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+        Version::from_accept(&headers)
+    }
+
+    #[test]
+    fn accept_headers_name_versions() {
+        assert_eq!(accepting("application/vnd.example.v2+json").unwrap(), "v2");
+        assert_eq!(accepting("application/json; version=2").unwrap(), "v2");
+        assert_eq!(
+            accepting("text/html, application/json;version=\"v3\"").unwrap(),
+            "v3"
+        );
+        assert_eq!(accepting("application/vnd.example+json"), None);
+        assert_eq!(accepting("application/json"), None);
+    }
+
+    #[tokio::test]
+    async fn deprecated_versions_announce_their_sunset() {
+        let v1 = ApiVersion::new("v1")
+            .deprecated(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap())
+            .sunset(Utc.with_ymd_and_hms(2026, 12, 31, 0, 0, 0).unwrap())
+            .link("https://example.com/migrate");
+        let handler = get(|version: Version| async move { version.to_string() });
+        let router = Router::new()
+            .nest(
+                "/v1",
+                Router::new()
+                    .route("/users", handler.clone())
+                    .layer(VersionLayer::new(v1)),
+            )
+            .nest(
+                "/v2",
+                Router::new()
+                    .route("/users", handler.clone())
+                    .layer(VersionLayer::new("v2")),
+            )
+            .route("/users", handler);
+
+        let response = router
+            .clone()
+            .oneshot(Request::get("/v1/users").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let headers = response.headers().clone();
+        assert_eq!(headers["deprecation"], "@1767225600");
+        assert_eq!(headers["sunset"], "Thu, 31 Dec 2026 00:00:00 GMT");
+        assert_eq!(
+            headers[header::LINK],
+            "<https://example.com/migrate>; rel=\"deprecation\""
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "v1".as_bytes());
+
+        let response = router
+            .clone()
+            .oneshot(Request::get("/v2/users").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key("deprecation"));
+
+        let request = Request::get("/users")
+            .header(header::ACCEPT, "application/vnd.example.v2+json")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "v2".as_bytes());
+
+        let response = router
+            .oneshot(Request::get("/users").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,340 @@
+//! Startup warm-up and readiness
+//!
+//! Warm-up requests run against the in-process router once the server is
+//! bound, so connection pools, caches and lazily-built state are primed
+//! before real traffic arrives. `/ready` answers `503` until they have all
+//! completed, which keeps orchestrators from routing to the instance early,
+//! and while the database passed to `App::with_database` doesn't answer.
+//! `/health` stays a pure liveness check.
+//!
+//! ```toml
+//! [[warmup.requests]]
+//! path = "/users?limit=1"
+//!
+//! [[warmup.requests]]
+//! method = "POST"
+//! path = "/search"
+//! body = { query = "warmup" }
+//! expect_status = 200
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+use crate::db::{Database, SharedDatabase};
+
+/// Path of the readiness probe
+pub const READY_PATH: &str = "/ready";
+
+/// A synthetic request sent while warming up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WarmupRequest {
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub path: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// JSON body
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+    /// Status the response must have; any non-5xx status is accepted otherwise
+    #[serde(default)]
+    pub expect_status: Option<u16>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+impl WarmupRequest {
+    pub fn get(path: impl Into<String>) -> Self {
+        Self {
+            method: default_method(),
+            path: path.into(),
+            headers: BTreeMap::new(),
+            body: None,
+            expect_status: None,
+        }
+    }
+
+    pub fn post(path: impl Into<String>, body: serde_json::Value) -> Self {
+        Self {
+            method: "POST".to_string(),
+            body: Some(body),
+            ..Self::get(path)
+        }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn expect_status(mut self, status: u16) -> Self {
+        self.expect_status = Some(status);
+        self
+    }
+
+    fn to_request(&self) -> Result<Request<Body>, String> {
+        let method = Method::from_bytes(self.method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| format!("invalid method `{}`", self.method))?;
+        let mut builder = Request::builder().method(method).uri(&self.path);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let body = match &self.body {
+            Some(body) => {
+                builder = builder.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        builder.body(body).map_err(|err| err.to_string())
+    }
+
+    fn accepts(&self, status: StatusCode) -> bool {
+        match self.expect_status {
+            Some(expected) => status.as_u16() == expected,
+            None => !status.is_server_error(),
+        }
+    }
+}
+
+/// Warm-up settings, the `[warmup]` config section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WarmupConfig {
+    pub requests: Vec<WarmupRequest>,
+}
+
+/// Outcome of one warm-up request
+#[derive(Debug, Clone, PartialEq)]
+pub struct WarmupOutcome {
+    pub method: String,
+    pub path: String,
+    pub status: Option<StatusCode>,
+    pub elapsed: Duration,
+    /// Why the request counts as failed, if it does
+    pub error: Option<String>,
+}
+
+impl WarmupOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Send each request to `router` in order, logging how each one went.
+///
+/// Failures are reported but don't stop the warm-up.
+pub async fn run_warmup(router: &Router, requests: &[WarmupRequest]) -> Vec<WarmupOutcome> {
+    let mut outcomes = Vec::with_capacity(requests.len());
+    for warmup in requests {
+        let started = Instant::now();
+        let (status, error) = match warmup.to_request() {
+            Ok(request) => {
+                let status = router
+                    .clone()
+                    .oneshot(request)
+                    .await
+                    .map(|response| response.status())
+                    .unwrap_or_else(|never| match never {});
+                let error =
+                    (!warmup.accepts(status)).then(|| format!("unexpected status {status}"));
+                (Some(status), error)
+            }
+            Err(err) => (None, Some(err)),
+        };
+        let outcome = WarmupOutcome {
+            method: warmup.method.clone(),
+            path: warmup.path.clone(),
+            status,
+            elapsed: started.elapsed(),
+            error,
+        };
+        match &outcome.error {
+            None => tracing::info!(
+                method = %outcome.method,
+                path = %outcome.path,
+                elapsed_ms = outcome.elapsed.as_millis() as u64,
+                "warm-up request done"
+            ),
+            Some(error) => tracing::warn!(
+                method = %outcome.method,
+                path = %outcome.path,
+                %error,
+                "warm-up request failed"
+            ),
+        }
+        outcomes.push(outcome);
+    }
+    outcomes
+}
+
+/// Shared readiness flag, reported by [`readiness_router`]
+#[derive(Debug, Clone)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn new(ready: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(ready)))
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.0.store(ready, Ordering::Release);
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+/// Router serving [`READY_PATH`], also pinging `database` once warmed up
+pub fn readiness_router(readiness: Readiness, database: Option<SharedDatabase>) -> Router {
+    Router::new().route(
+        READY_PATH,
+        axum::routing::get(move || {
+            let readiness = readiness.clone();
+            let database = database.clone();
+            async move { readiness_response(&readiness, database.as_ref()).await }
+        }),
+    )
+}
+
+async fn readiness_response(readiness: &Readiness, database: Option<&SharedDatabase>) -> Response {
+    let unavailable = |status: &str| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": status })),
+        )
+            .into_response()
+    };
+    if !readiness.is_ready() {
+        return unavailable("warming_up");
+    }
+    if let Some(database) = database
+        && let Err(err) = database.ping().await
+    {
+        tracing::warn!(backend = database.backend(), error = %err, "Database ping failed");
+        return unavailable("database_unavailable");
+    }
+    Json(serde_json::json!({ "status": "ready" })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::{get, post};
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn runs_requests_and_reports_failures() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let router = Router::new()
+            .route(
+                "/users",
+                get(move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async { "[]" }
+                }),
+            )
+            .route(
+                "/search",
+                post(|Json(body): Json<serde_json::Value>| async move { Json(body) }),
+            )
+            .route(
+                "/broken",
+                get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+            );
+
+        let outcomes = run_warmup(
+            &router,
+            &[
+                WarmupRequest::get("/users"),
+                WarmupRequest::post("/search", serde_json::json!({ "q": "x" })).expect_status(200),
+                WarmupRequest::get("/broken"),
+                WarmupRequest::get("/missing").expect_status(200),
+            ],
+        )
+        .await;
+
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        let ok: Vec<bool> = outcomes.iter().map(WarmupOutcome::is_ok).collect();
+        assert_eq!(ok, [true, true, false, false]);
+        assert_eq!(outcomes[3].status, Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn readiness_follows_the_flag() {
+        let readiness = Readiness::new(false);
+        let router = readiness_router(readiness.clone(), None);
+        let probe = || async {
+            router
+                .clone()
+                .oneshot(Request::get(READY_PATH).body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status()
+        };
+
+        assert_eq!(probe().await, StatusCode::SERVICE_UNAVAILABLE);
+        readiness.set_ready(true);
+        assert_eq!(probe().await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn readiness_checks_the_database() {
+        struct Down;
+
+        #[async_trait::async_trait]
+        impl Database for Down {
+            fn backend(&self) -> &'static str {
+                "down"
+            }
+
+            async fn ping(&self) -> Result<(), crate::error::ApiError> {
+                Err(crate::error::ApiError::database("connection refused"))
+            }
+        }
+
+        let router = readiness_router(Readiness::new(true), Some(SharedDatabase::new(Down)));
+        let res = router
+            .oneshot(Request::get(READY_PATH).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(res.into_body(), 64).await.unwrap();
+        assert_eq!(body, r#"{"status":"database_unavailable"}"#);
+    }
+
+    #[test]
+    fn requests_deserialize_with_defaults() {
+        let request: WarmupRequest = serde_json::from_value(serde_json::json!({
+            "path": "/users",
+        }))
+        .unwrap();
+        assert_eq!(request, WarmupRequest::get("/users"));
+    }
+}
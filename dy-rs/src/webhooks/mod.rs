@@ -0,0 +1,505 @@
+//! Outgoing webhooks
+//!
+//! External systems register a [`WebhookEndpoint`]; [`Webhooks::dispatch`]
+//! then sends each typed [`WebhookEvent`] to every endpoint subscribed to it.
+//! Bodies are signed with the endpoint's secret, failed deliveries are retried
+//! with exponential backoff, and every attempt is recorded in the
+//! [`WebhookStore`]:
+//!
+//! ```rust,ignore
+//! #[derive(Serialize)]
+//! struct OrderPaid { order_id: Uuid, total_cents: i64 }
+//!
+//! impl WebhookEvent for OrderPaid {
+//!     const TYPE: &'static str = "order.paid";
+//! }
+//!
+//! let app = App::new().auto_configure();
+//! let webhooks = Webhooks::new(InMemoryWebhookStore::new(), transport).tasks(app.tasks());
+//! webhooks
+//!     .register(WebhookEndpoint::new("https://erp.example.com/hooks").events(["order.paid"]))
+//!     .await?;
+//!
+//! webhooks.dispatch(&OrderPaid { order_id, total_cents }).await?;
+//! ```
+//!
+//! Receivers get a JSON body `{"id", "type", "created_at", "data"}` with a
+//! `Webhook-Signature: t=<unix time>,v1=<hex HMAC-SHA256 of "<t>.<body>">`
+//! header, which [`verify_signature`] checks.
+//!
+//! Deliveries run as [`TaskScope`] tasks, so shutdown waits for attempts in
+//! flight but not for backoff sleeps; a delivery interrupted by shutdown
+//! stays [`Pending`](DeliveryStatus::Pending) and can be resumed with
+//! [`Webhooks::redeliver`]. Like the CDN purgers, sending the request is left
+//! to a [`WebhookTransport`], usually a closure around the app's HTTP client.
+
+mod store;
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use axum::http::{self, StatusCode};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{error::ApiError, tasks::TaskScope};
+
+pub use store::{
+    Delivery, DeliveryAttempt, DeliveryStatus, InMemoryWebhookStore, WebhookEndpoint, WebhookStore,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Header carrying the delivery's signature
+pub const SIGNATURE_HEADER: &str = "webhook-signature";
+/// Header carrying the event ID, for receivers to drop duplicates
+pub const ID_HEADER: &str = "webhook-id";
+/// Header carrying the event type
+pub const EVENT_HEADER: &str = "webhook-event";
+
+/// An event that can be sent to webhook endpoints
+pub trait WebhookEvent: Serialize {
+    /// The type receivers subscribe to, e.g. `order.paid`
+    const TYPE: &'static str;
+}
+
+/// Sends a webhook request and returns the response status
+#[async_trait]
+pub trait WebhookTransport: Send + Sync + 'static {
+    async fn send(&self, request: http::Request<String>) -> Result<StatusCode, ApiError>;
+}
+
+#[async_trait]
+impl<F, Fut> WebhookTransport for F
+where
+    F: Fn(http::Request<String>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<StatusCode, ApiError>> + Send,
+{
+    async fn send(&self, request: http::Request<String>) -> Result<StatusCode, ApiError> {
+        self(request).await
+    }
+}
+
+/// How often and how long failed deliveries are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    /// Wait before the second attempt; doubled for each one after
+    pub initial_delay: Duration,
+    /// Longest wait between attempts
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            initial_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after failed attempt number `attempt` (1-based)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// Registers endpoints and delivers events to them
+pub struct Webhooks<T> {
+    store: Arc<dyn WebhookStore>,
+    transport: Arc<T>,
+    retry: RetryPolicy,
+    tasks: TaskScope,
+}
+
+impl<T> Clone for Webhooks<T> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            transport: self.transport.clone(),
+            retry: self.retry,
+            tasks: self.tasks.clone(),
+        }
+    }
+}
+
+impl<T: WebhookTransport> Webhooks<T> {
+    pub fn new(store: impl WebhookStore, transport: T) -> Self {
+        Self {
+            store: Arc::new(store),
+            transport: Arc::new(transport),
+            retry: RetryPolicy::default(),
+            tasks: TaskScope::new(),
+        }
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Run deliveries in `tasks`, usually [`App::tasks`](crate::App::tasks),
+    /// so shutdown waits for them
+    pub fn tasks(mut self, tasks: TaskScope) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    pub fn store(&self) -> &dyn WebhookStore {
+        self.store.as_ref()
+    }
+
+    /// Subscribe an endpoint; its URL must be `http` or `https`
+    pub async fn register(&self, endpoint: WebhookEndpoint) -> Result<WebhookEndpoint, ApiError> {
+        let valid = ["https://", "http://"]
+            .iter()
+            .any(|scheme| endpoint.url.starts_with(scheme))
+            && endpoint.url.parse::<http::Uri>().is_ok();
+        if !valid {
+            return Err(ApiError::BadRequest(format!(
+                "Invalid webhook URL: {}",
+                endpoint.url
+            )));
+        }
+        self.store.create_endpoint(endpoint).await
+    }
+
+    /// Send `event` to every subscribed endpoint
+    ///
+    /// Returns the pending deliveries; attempts run in the background.
+    pub async fn dispatch<E: WebhookEvent>(&self, event: &E) -> Result<Vec<Delivery>, ApiError> {
+        let event_id = Uuid::new_v4();
+        let now = Utc::now();
+        let payload = serde_json::json!({
+            "id": event_id,
+            "type": E::TYPE,
+            "created_at": now,
+            "data": event,
+        })
+        .to_string();
+
+        let mut deliveries = Vec::new();
+        for endpoint in self.store.endpoints().await? {
+            if !endpoint.subscribes_to(E::TYPE) {
+                continue;
+            }
+            let delivery = Delivery {
+                id: Uuid::new_v4(),
+                endpoint_id: endpoint.id,
+                event_id,
+                event_type: E::TYPE.to_string(),
+                payload: payload.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: Vec::new(),
+                next_attempt_at: Some(now),
+                created_at: now,
+            };
+            self.store.save_delivery(&delivery).await?;
+            self.spawn(delivery.clone());
+            deliveries.push(delivery);
+        }
+        Ok(deliveries)
+    }
+
+    /// Try a delivery again now, whatever its status, and retry it as
+    /// usual if that fails
+    pub async fn redeliver(&self, id: Uuid) -> Result<Delivery, ApiError> {
+        let mut delivery = self
+            .store
+            .delivery(id)
+            .await?
+            .ok_or_else(|| ApiError::NotFound(format!("Delivery {id} not found")))?;
+        delivery.status = DeliveryStatus::Pending;
+        delivery.next_attempt_at = Some(Utc::now());
+        self.store.save_delivery(&delivery).await?;
+        self.spawn(delivery.clone());
+        Ok(delivery)
+    }
+
+    fn spawn(&self, delivery: Delivery) {
+        let webhooks = self.clone();
+        let id = delivery.id;
+        if !self.tasks.spawn("webhook_delivery", async move {
+            if let Err(e) = webhooks.run(delivery).await {
+                tracing::error!(delivery = %id, "webhook delivery failed: {e}");
+            }
+        }) {
+            tracing::warn!(delivery = %id, "webhook delivery left pending during shutdown");
+        }
+    }
+
+    /// Attempt `delivery` until it succeeds or runs out of attempts
+    async fn run(&self, mut delivery: Delivery) -> Result<(), ApiError> {
+        // Numbered on from earlier attempts, so a redelivery gets a full set
+        let first = delivery.attempts.len() as u32;
+        loop {
+            let endpoint = self
+                .store
+                .endpoint(delivery.endpoint_id)
+                .await?
+                .filter(|endpoint| endpoint.active);
+            let Some(endpoint) = endpoint else {
+                delivery.status = DeliveryStatus::Failed;
+                delivery.next_attempt_at = None;
+                return self.store.save_delivery(&delivery).await;
+            };
+
+            let attempt = self.attempt(&delivery, &endpoint).await;
+            let succeeded = attempt.error.is_none();
+            let number = attempt.attempt - first;
+            delivery.attempts.push(attempt);
+
+            if succeeded || number >= self.retry.max_attempts {
+                delivery.status = if succeeded {
+                    DeliveryStatus::Succeeded
+                } else {
+                    DeliveryStatus::Failed
+                };
+                delivery.next_attempt_at = None;
+                return self.store.save_delivery(&delivery).await;
+            }
+
+            let delay = self.retry.delay(number);
+            delivery.next_attempt_at = Some(Utc::now() + delay);
+            self.store.save_delivery(&delivery).await?;
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.tasks.cancelled() => return Ok(()),
+            }
+        }
+    }
+
+    async fn attempt(&self, delivery: &Delivery, endpoint: &WebhookEndpoint) -> DeliveryAttempt {
+        let started = Instant::now();
+        let at = Utc::now();
+        let request = request(delivery, endpoint, at.timestamp());
+        let (status, error) = match self.transport.send(request).await {
+            Ok(status) if status.is_success() => (Some(status.as_u16()), None),
+            Ok(status) => (
+                Some(status.as_u16()),
+                Some(format!("Endpoint answered {status}")),
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        if let Some(error) = &error {
+            tracing::warn!(delivery = %delivery.id, url = %endpoint.url, "webhook attempt failed: {error}");
+        }
+        DeliveryAttempt {
+            attempt: delivery.attempts.len() as u32 + 1,
+            at,
+            status,
+            error,
+            duration_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// The signed request delivering `delivery` to `endpoint`
+fn request(
+    delivery: &Delivery,
+    endpoint: &WebhookEndpoint,
+    timestamp: i64,
+) -> http::Request<String> {
+    http::Request::post(&endpoint.url)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .header(ID_HEADER, delivery.event_id.to_string())
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(
+            SIGNATURE_HEADER,
+            sign(&endpoint.secret, timestamp, &delivery.payload),
+        )
+        .body(delivery.payload.clone())
+        .expect("valid webhook request")
+}
+
+/// The `Webhook-Signature` value for `body` sent at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    format!(
+        "t={timestamp},v1={}",
+        hex::encode(mac(secret, timestamp, body).finalize().into_bytes())
+    )
+}
+
+/// Check a received `Webhook-Signature` header against the body
+///
+/// Signatures older than `tolerance` are rejected so captured requests can't
+/// be replayed.
+pub fn verify_signature(secret: &str, header: &str, body: &str, tolerance: Duration) -> bool {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(hex::decode(value).ok()),
+            _ => {}
+        }
+    }
+    let Some(timestamp) = timestamp else {
+        return false;
+    };
+    if Utc::now().timestamp().abs_diff(timestamp) > tolerance.as_secs() {
+        return false;
+    }
+    signatures
+        .iter()
+        .any(|signature| mac(secret, timestamp, body).verify_slice(signature).is_ok())
+}
+
+fn mac(secret: &str, timestamp: i64, body: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    #[derive(Serialize)]
+    struct OrderPaid {
+        order_id: u32,
+    }
+
+    impl WebhookEvent for OrderPaid {
+        const TYPE: &'static str = "order.paid";
+    }
+
+    /// The delivery once it stops being pending
+    async fn settled<T: WebhookTransport>(webhooks: &Webhooks<T>, id: Uuid) -> Delivery {
+        for _ in 0..200 {
+            let delivery = webhooks.store().delivery(id).await.unwrap().unwrap();
+            if delivery.status != DeliveryStatus::Pending {
+                return delivery;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("delivery {id} still pending");
+    }
+
+    fn quick_retries(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_delivered_and_records_attempts() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let transport = {
+            let sent = sent.clone();
+            let calls = calls.clone();
+            move |request: http::Request<String>| {
+                let sent = sent.clone();
+                let calls = calls.clone();
+                async move {
+                    sent.lock().unwrap().push(request);
+                    match calls.fetch_add(1, Ordering::SeqCst) {
+                        0 => Err(ApiError::InternalServerError("connection refused".into())),
+                        1 => Ok(StatusCode::BAD_GATEWAY),
+                        _ => Ok(StatusCode::NO_CONTENT),
+                    }
+                }
+            }
+        };
+        let webhooks =
+            Webhooks::new(InMemoryWebhookStore::new(), transport).retry(quick_retries(5));
+        let endpoint = webhooks
+            .register(WebhookEndpoint::new("https://erp.example.com/hooks").secret("whsec_test"))
+            .await
+            .unwrap();
+        webhooks
+            .register(WebhookEndpoint::new("https://other.example.com").events(["user.created"]))
+            .await
+            .unwrap();
+
+        let deliveries = webhooks.dispatch(&OrderPaid { order_id: 7 }).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+
+        let delivery = settled(&webhooks, deliveries[0].id).await;
+        assert_eq!(delivery.endpoint_id, endpoint.id);
+        assert_eq!(delivery.status, DeliveryStatus::Succeeded);
+        let statuses: Vec<_> = delivery.attempts.iter().map(|a| a.status).collect();
+        assert_eq!(statuses, [None, Some(502), Some(204)]);
+        assert!(delivery.next_attempt_at.is_none());
+
+        let sent = sent.lock().unwrap();
+        let request = &sent[2];
+        assert_eq!(request.headers()[EVENT_HEADER], "order.paid");
+        assert_eq!(request.headers()[ID_HEADER], delivery.event_id.to_string());
+        let body: serde_json::Value = serde_json::from_str(request.body()).unwrap();
+        assert_eq!(body["data"]["order_id"], 7);
+        let signature = request.headers()[SIGNATURE_HEADER].to_str().unwrap();
+        assert!(verify_signature(
+            "whsec_test",
+            signature,
+            request.body(),
+            Duration::from_secs(300)
+        ));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let webhooks = Webhooks::new(InMemoryWebhookStore::new(), |_| async {
+            Ok(StatusCode::INTERNAL_SERVER_ERROR)
+        })
+        .retry(quick_retries(3));
+        let endpoint = webhooks
+            .register(WebhookEndpoint::new("https://erp.example.com/hooks"))
+            .await
+            .unwrap();
+        assert!(
+            webhooks
+                .register(WebhookEndpoint::new("ftp://erp.example.com"))
+                .await
+                .is_err()
+        );
+
+        let deliveries = webhooks.dispatch(&OrderPaid { order_id: 1 }).await.unwrap();
+        let delivery = settled(&webhooks, deliveries[0].id).await;
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+        assert_eq!(delivery.attempts.len(), 3);
+        assert_eq!(
+            webhooks.store().deliveries(endpoint.id).await.unwrap(),
+            [delivery]
+        );
+    }
+
+    #[test]
+    fn signatures_are_checked_and_expire() {
+        let now = Utc::now().timestamp();
+        let header = sign("secret", now, "{}");
+        let tolerance = Duration::from_secs(300);
+        assert!(verify_signature("secret", &header, "{}", tolerance));
+        assert!(!verify_signature("secret", &header, "{ }", tolerance));
+        assert!(!verify_signature("other", &header, "{}", tolerance));
+        let old = sign("secret", now - 600, "{}");
+        assert!(!verify_signature("secret", &old, "{}", tolerance));
+
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1), Duration::from_secs(10));
+        assert_eq!(policy.delay(3), Duration::from_secs(40));
+        assert_eq!(policy.delay(40), policy.max_delay);
+    }
+}
@@ -0,0 +1,198 @@
+//! Where endpoints and deliveries are kept
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+
+/// An external URL subscribed to events
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub url: String,
+    /// Signs every delivery; share it with the receiver
+    pub secret: String,
+    /// Event types delivered; empty means all of them
+    pub events: Vec<String>,
+    /// Inactive endpoints receive nothing
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookEndpoint {
+    /// An endpoint receiving every event, with a generated secret
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            url: url.into(),
+            secret: format!("whsec_{}", Uuid::new_v4().simple()),
+            events: Vec::new(),
+            active: true,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = secret.into();
+        self
+    }
+
+    /// Only deliver events of these types
+    pub fn events<I, S>(mut self, events: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.events = events.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether events of `event_type` are delivered here
+    pub fn subscribes_to(&self, event_type: &str) -> bool {
+        self.active && (self.events.is_empty() || self.events.iter().any(|e| e == event_type))
+    }
+}
+
+/// Where a delivery stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Not delivered yet; more attempts are scheduled
+    Pending,
+    /// The endpoint answered with a 2xx status
+    Succeeded,
+    /// Every attempt failed
+    Failed,
+}
+
+/// One try at delivering an event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    /// 1 for the first try
+    pub attempt: u32,
+    pub at: DateTime<Utc>,
+    /// The endpoint's response status, if it answered
+    pub status: Option<u16>,
+    /// Why the attempt failed, if it did
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// An event sent to one endpoint, with every attempt made
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub endpoint_id: Uuid,
+    /// ID of the event, the same for every endpoint it was sent to
+    pub event_id: Uuid,
+    pub event_type: String,
+    /// The signed JSON body
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: Vec<DeliveryAttempt>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Webhook storage trait - implement this to keep endpoints and deliveries in a database
+#[async_trait::async_trait]
+pub trait WebhookStore: Send + Sync + 'static {
+    async fn create_endpoint(&self, endpoint: WebhookEndpoint)
+    -> Result<WebhookEndpoint, ApiError>;
+
+    async fn endpoint(&self, id: Uuid) -> Result<Option<WebhookEndpoint>, ApiError>;
+
+    async fn endpoints(&self) -> Result<Vec<WebhookEndpoint>, ApiError>;
+
+    /// Returns `false` if there was no such endpoint
+    async fn delete_endpoint(&self, id: Uuid) -> Result<bool, ApiError>;
+
+    /// Insert or replace a delivery
+    async fn save_delivery(&self, delivery: &Delivery) -> Result<(), ApiError>;
+
+    async fn delivery(&self, id: Uuid) -> Result<Option<Delivery>, ApiError>;
+
+    /// Deliveries made to an endpoint, oldest first
+    async fn deliveries(&self, endpoint_id: Uuid) -> Result<Vec<Delivery>, ApiError>;
+}
+
+/// Webhook store kept in memory, for tests and single-instance apps
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryWebhookStore {
+    endpoints: Arc<Mutex<HashMap<Uuid, WebhookEndpoint>>>,
+    deliveries: Arc<Mutex<Vec<Delivery>>>,
+}
+
+impl InMemoryWebhookStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for InMemoryWebhookStore {
+    async fn create_endpoint(
+        &self,
+        endpoint: WebhookEndpoint,
+    ) -> Result<WebhookEndpoint, ApiError> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if endpoints.contains_key(&endpoint.id) {
+            return Err(ApiError::BadRequest(format!(
+                "Webhook endpoint {} already exists",
+                endpoint.id
+            )));
+        }
+        endpoints.insert(endpoint.id, endpoint.clone());
+        Ok(endpoint)
+    }
+
+    async fn endpoint(&self, id: Uuid) -> Result<Option<WebhookEndpoint>, ApiError> {
+        Ok(self.endpoints.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn endpoints(&self) -> Result<Vec<WebhookEndpoint>, ApiError> {
+        let mut endpoints: Vec<_> = self.endpoints.lock().unwrap().values().cloned().collect();
+        endpoints.sort_by_key(|endpoint| endpoint.created_at);
+        Ok(endpoints)
+    }
+
+    async fn delete_endpoint(&self, id: Uuid) -> Result<bool, ApiError> {
+        Ok(self.endpoints.lock().unwrap().remove(&id).is_some())
+    }
+
+    async fn save_delivery(&self, delivery: &Delivery) -> Result<(), ApiError> {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        match deliveries.iter_mut().find(|d| d.id == delivery.id) {
+            Some(existing) => *existing = delivery.clone(),
+            None => deliveries.push(delivery.clone()),
+        }
+        Ok(())
+    }
+
+    async fn delivery(&self, id: Uuid) -> Result<Option<Delivery>, ApiError> {
+        Ok(self
+            .deliveries
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|d| d.id == id)
+            .cloned())
+    }
+
+    async fn deliveries(&self, endpoint_id: Uuid) -> Result<Vec<Delivery>, ApiError> {
+        Ok(self
+            .deliveries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|d| d.endpoint_id == endpoint_id)
+            .cloned()
+            .collect())
+    }
+}
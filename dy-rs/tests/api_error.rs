@@ -0,0 +1,110 @@
+//! Expansion tests for `#[derive(ApiErrorKind)]` and `#[dy_api(errors = ...)]`.
+
+use axum::{body, http::StatusCode, response::IntoResponse};
+use dy_rs::error::ErrorDoc;
+use dy_rs::openapi::{DocInfo, build_auto_openapi};
+use dy_rs::prelude::*;
+use serde_json::Value;
+
+#[derive(Debug, thiserror::Error, ApiErrorKind)]
+enum OrderError {
+    #[error("order {0} not found")]
+    #[api_error(status = 404)]
+    NotFound(u32),
+    #[error("not enough stock of {sku}")]
+    #[api_error(status = 409, code = "OUT_OF_STOCK")]
+    InsufficientStock { sku: String },
+    #[error("payment gateway failed: {0}")]
+    #[api_error(status = 502)]
+    PaymentGateway(String),
+    #[error("card declined by issuer {0}")]
+    #[api_error(status = 402, message = "Your card was declined")]
+    CardDeclined(String),
+}
+
+#[derive(Debug, thiserror::Error, ApiErrorKind)]
+#[error("account {0} is locked")]
+#[api_error(status = 423)]
+struct AccountLocked(String);
+
+#[dy_api(method = post, path = "/orders", errors = [OrderError, AccountLocked])]
+async fn place_order() -> Result<Json<()>, ApiError> {
+    Err(OrderError::NotFound(7))?
+}
+
+#[dy_api(method = get, path = "/orders/{id}", errors = OrderError)]
+async fn get_order() -> Result<Json<()>, OrderError> {
+    Err(OrderError::NotFound(7))
+}
+
+async fn body_of(error: impl IntoResponse) -> (StatusCode, Value) {
+    let response = error.into_response();
+    let status = response.status();
+    let body = body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn responses_use_status_code_and_public_message() {
+    let (status, body) = body_of(ApiError::from(OrderError::NotFound(7))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "NOT_FOUND");
+    assert_eq!(body["message"], "order 7 not found");
+
+    let (status, body) = body_of(OrderError::InsufficientStock {
+        sku: "mug".to_string(),
+    })
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "OUT_OF_STOCK");
+
+    // Server errors don't leak their details
+    let (status, body) = body_of(OrderError::PaymentGateway("timeout".to_string())).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["code"], "PAYMENT_GATEWAY");
+    assert_eq!(body["message"], "Bad Gateway");
+
+    let (status, body) = body_of(OrderError::CardDeclined("acme".to_string())).await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(body["message"], "Your card was declined");
+
+    let (status, body) = body_of(AccountLocked("ada".to_string())).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    assert_eq!(body["code"], "ACCOUNT_LOCKED");
+    assert_eq!(body["message"], "account ada is locked");
+
+    let (status, _) = body_of(place_order().await.unwrap_err()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = body_of(get_order().await.unwrap_err()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn documents_every_status_and_code() {
+    assert_eq!(
+        AccountLocked::documented(),
+        [ErrorDoc {
+            status: 423,
+            code: "ACCOUNT_LOCKED"
+        }]
+    );
+    assert_eq!(OrderError::documented().len(), 4);
+}
+
+#[test]
+fn declared_errors_appear_in_openapi() {
+    let doc = serde_json::to_value(build_auto_openapi(DocInfo::default())).unwrap();
+    let responses = &doc["paths"]["/orders"]["post"]["responses"];
+    assert_eq!(responses["404"]["description"], "Error: NOT_FOUND");
+    assert_eq!(responses["409"]["description"], "Error: OUT_OF_STOCK");
+    assert_eq!(responses["423"]["description"], "Error: ACCOUNT_LOCKED");
+    assert_eq!(
+        responses["502"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/ErrorResponse"
+    );
+    assert!(responses.get("200").is_some());
+    assert!(doc["paths"]["/orders/{id}"]["get"]["responses"]["423"].is_null());
+    assert!(doc["components"]["schemas"]["ErrorResponse"].is_object());
+}
@@ -0,0 +1,59 @@
+//! Placement of middleware added with `App::layer` and
+//! `App::layer_before_auto`.
+
+use axum::{
+    body::Body,
+    http::{HeaderValue, Request},
+    middleware::{self, Next},
+    response::Response,
+};
+use dy_rs::prelude::*;
+use tower::ServiceExt;
+
+/// Middleware appending its name to the `x-seen` request header, which the
+/// handler echoes
+async fn mark(
+    State(name): State<&'static str>,
+    mut request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    let seen = match request.headers().get("x-seen") {
+        Some(seen) => format!("{},{name}", seen.to_str().unwrap()),
+        None => name.to_string(),
+    };
+    request
+        .headers_mut()
+        .insert("x-seen", HeaderValue::from_str(&seen).unwrap());
+    next.run(request).await
+}
+
+#[tokio::test]
+async fn outer_layers_see_requests_first() {
+    let app = App::new()
+        .route(
+            "/seen",
+            get(|headers: axum::http::HeaderMap| async move {
+                headers["x-seen"].to_str().unwrap().to_string()
+            }),
+        )
+        .layer(middleware::from_fn_with_state("inner", mark))
+        .layer(middleware::from_fn_with_state("wrapping_inner", mark))
+        .layer_before_auto(middleware::from_fn_with_state("outer", mark));
+
+    let route = app
+        .routes()
+        .into_iter()
+        .find(|route| route.path == "/seen")
+        .unwrap();
+    assert_eq!(route.middleware, ["FromFnLayer"; 3]);
+
+    let response = app
+        .into_router()
+        .oneshot(Request::get("/seen").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(body, "outer,wrapping_inner,inner".as_bytes());
+}
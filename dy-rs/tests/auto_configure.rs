@@ -0,0 +1,41 @@
+//! Auto-configuration from code, without panics.
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use dy_rs::{BuildError, SubscriberOptions, config::AppConfig, prelude::*};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn apps_configure_from_code_more_than_once() {
+    // The second app finds the first one's subscriber and keeps it
+    for _ in 0..2 {
+        let app = App::new()
+            .auto_configure_with(
+                AppConfig::default(),
+                SubscriberOptions::new().filter("warn"),
+            )
+            .unwrap();
+        let response = app
+            .into_router()
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[test]
+fn invalid_configurations_are_returned() {
+    let mut config = AppConfig::default();
+    config.server.port = 0;
+    let error = App::new()
+        .auto_configure_with(config, SubscriberOptions::new().skip())
+        .err()
+        .unwrap();
+    let BuildError::Invalid(report) = error else {
+        panic!("expected an invalid configuration, got {error}");
+    };
+    assert!(report.problems.iter().any(|(key, _)| key == "server.port"));
+}
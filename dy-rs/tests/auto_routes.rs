@@ -0,0 +1,135 @@
+//! Routing of `#[dy_api]` handlers through `routes::auto_router*`.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use axum::{
+    body::Body,
+    extract::FromRef,
+    http::{Request, StatusCode},
+};
+use dy_rs::prelude::*;
+use dy_rs::routes::{auto_router, auto_router_with_state};
+use tower::ServiceExt;
+
+type Counter = Arc<AtomicUsize>;
+
+#[derive(Clone, Default)]
+struct AppState {
+    counter: Counter,
+}
+
+impl FromRef<AppState> for Counter {
+    fn from_ref(state: &AppState) -> Self {
+        state.counter.clone()
+    }
+}
+
+#[dy_api(method = get, path = "/ping")]
+async fn ping() -> &'static str {
+    "pong"
+}
+
+#[dy_api(method = post, path = "/hits")]
+async fn hit(State(counter): State<Counter>) -> String {
+    (counter.fetch_add(1, Ordering::SeqCst) + 1).to_string()
+}
+
+#[dy_api(method = get, path = "/hits", state = AppState)]
+async fn hits(State(counter): State<Counter>) -> String {
+    counter.load(Ordering::SeqCst).to_string()
+}
+
+#[dy_api(method = get, path = "/manual", skip_route)]
+async fn manual() {}
+
+async fn call(app: &Router, method: &str, uri: &str) -> (StatusCode, String) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn stateless_handlers_are_routed() {
+    let app = auto_router();
+
+    assert_eq!(
+        call(&app, "GET", "/ping").await,
+        (StatusCode::OK, "pong".into())
+    );
+    // Stateful handlers are skipped without their state.
+    assert_eq!(call(&app, "POST", "/hits").await.0, StatusCode::NOT_FOUND);
+    assert_eq!(call(&app, "GET", "/manual").await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn handlers_receive_matching_state() {
+    let counter = Counter::default();
+    let app = auto_router_with_state(counter.clone());
+
+    assert_eq!(
+        call(&app, "POST", "/hits").await,
+        (StatusCode::OK, "1".into())
+    );
+    assert_eq!(call(&app, "GET", "/ping").await.0, StatusCode::OK);
+    // Wrong method on a routed path.
+    assert_eq!(
+        call(&app, "DELETE", "/hits").await.0,
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn state_override_uses_from_ref() {
+    let state = AppState::default();
+    state.counter.store(7, Ordering::SeqCst);
+    let app = auto_router_with_state(state);
+
+    assert_eq!(
+        call(&app, "GET", "/hits").await,
+        (StatusCode::OK, "7".into())
+    );
+}
+
+#[allow(dead_code)]
+fn manual_routes() -> Router {
+    Router::new().route("/manual", get(manual))
+}
+
+#[test]
+fn route_table_lists_documented_and_manual_routes() {
+    let app = App::new()
+        .auto_routes()
+        .route("/status", get(|| async { "up" }));
+    let routes = app.routes();
+
+    let ping = routes.iter().find(|route| route.path == "/ping").unwrap();
+    assert_eq!(ping.methods, ["GET"]);
+    assert_eq!(ping.handler.as_deref(), Some("ping"));
+    let status = routes.iter().find(|route| route.path == "/status").unwrap();
+    assert!(status.methods.is_empty());
+
+    let table = dy_rs::routes::format_table(&routes);
+    assert!(table.starts_with("METHODS"));
+    assert!(
+        table
+            .lines()
+            .any(|line| line.starts_with("*") && line.contains("/status"))
+    );
+}
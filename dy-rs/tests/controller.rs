@@ -0,0 +1,150 @@
+//! Expansion tests for `#[dy_controller]`.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{HeaderValue, Request, StatusCode},
+    middleware::map_response,
+    response::Response,
+};
+use dy_rs::openapi::{DocInfo, build_auto_openapi};
+use dy_rs::prelude::*;
+use serde_json::Value;
+use tower::ServiceExt;
+
+type Names = Arc<Vec<String>>;
+
+#[derive(Serialize, ToSchema)]
+struct Item {
+    name: String,
+}
+
+async fn tag_response(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert("x-controller", HeaderValue::from_static("items"));
+    response
+}
+
+struct ItemController;
+
+#[dy_controller(
+    path = "/items",
+    tag = "Items",
+    version = "v2",
+    security = "bearerAuth",
+    layer = map_response(tag_response)
+)]
+impl ItemController {
+    #[dy_api(method = get, path = "/")]
+    async fn list(State(names): State<Names>) -> ApiResult<Vec<Item>> {
+        Ok(Json(
+            names
+                .iter()
+                .map(|name| Item { name: name.clone() })
+                .collect(),
+        ))
+    }
+
+    #[dy_api(method = get, path = "/{index}", tag = "Lookup")]
+    async fn get(State(names): State<Names>, Path(index): Path<usize>) -> ApiResult<Item> {
+        names
+            .get(index)
+            .map(|name| Json(Item { name: name.clone() }))
+            .ok_or_else(|| ApiError::NotFound(format!("No item {index}")))
+    }
+
+    /// Not annotated: left untouched and not routed.
+    #[allow(dead_code)]
+    fn helper() {}
+}
+
+struct OrderController;
+
+#[dy_controller(path = "/orders", tag = "Orders")]
+impl OrderController {
+    #[dy_api(method = get, path = "/")]
+    async fn list() -> ApiResult<Vec<Item>> {
+        Ok(Json(Vec::new()))
+    }
+
+    #[dy_api(method = post, path = "/", operation_id = "placeOrder")]
+    async fn create() -> ApiResult<Item> {
+        Ok(Json(Item {
+            name: "order".to_string(),
+        }))
+    }
+}
+
+fn app() -> Router {
+    ItemController::routes().with_state(Arc::new(vec!["a".to_string(), "b".to_string()]))
+}
+
+async fn get(uri: &str) -> Response {
+    app()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn routes_are_served_under_the_prefix_with_layers() {
+    let response = get("/items").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-controller"], "items");
+
+    let response = get("/items/1").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], br#"{"name":"b"}"#);
+
+    assert_eq!(get("/items/9").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn operations_are_documented_with_shared_metadata() {
+    let doc: Value = serde_json::to_value(build_auto_openapi(DocInfo::default())).unwrap();
+
+    let list = &doc["paths"]["/items"]["get"];
+    assert_eq!(list["tags"], serde_json::json!(["Items"]));
+    assert_eq!(list["security"], serde_json::json!([{ "bearerAuth": [] }]));
+    assert_eq!(list["operationId"], "ItemController_list");
+    assert_eq!(list["x-api-version"], "v2");
+
+    let item = &doc["paths"]["/items/{index}"]["get"];
+    assert_eq!(item["tags"], serde_json::json!(["Lookup"]));
+    assert_eq!(item["parameters"][0]["name"], "index");
+}
+
+#[tokio::test]
+async fn operation_ids_are_unique_across_controllers() {
+    let response = OrderController::routes()
+        .oneshot(Request::get("/orders").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let doc: Value = serde_json::to_value(build_auto_openapi(DocInfo::default())).unwrap();
+
+    assert_eq!(
+        doc["paths"]["/items"]["get"]["operationId"],
+        "ItemController_list"
+    );
+    assert_eq!(
+        doc["paths"]["/orders"]["get"]["operationId"],
+        "OrderController_list"
+    );
+    // An explicit operation_id is kept as it is
+    assert_eq!(doc["paths"]["/orders"]["post"]["operationId"], "placeOrder");
+}
+
+#[test]
+fn controller_handlers_are_not_auto_routed() {
+    assert!(
+        dy_rs::openapi::inventory::iter::<dy_rs::routes::AutoRoute>()
+            .all(|route| !route.path.starts_with("/items"))
+    );
+}
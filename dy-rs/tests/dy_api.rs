@@ -0,0 +1,348 @@
+//! Expansion tests for `#[dy_api]` against the generated OpenAPI document.
+
+use dy_rs::openapi::{DocInfo, build_auto_openapi, filter_openapi};
+use dy_rs::prelude::*;
+use serde_json::Value;
+
+#[derive(Serialize, ToSchema)]
+struct Profile {
+    name: String,
+}
+
+#[dy_api(method = get, path = "/me", response = Profile, security = "bearerAuth")]
+async fn me() -> Json<Profile> {
+    Json(Profile {
+        name: "me".to_string(),
+    })
+}
+
+#[dy_api(
+    method = get,
+    path = "/reports",
+    security = ["bearerAuth", "oauth2:reports.read reports.export"]
+)]
+async fn reports() {}
+
+#[allow(dead_code)]
+fn routes() -> Router {
+    Router::new()
+        .route("/me", get(me))
+        .route("/reports", get(reports))
+        .route("/v1/profile", get(legacy_profile))
+        .route("/avatars", post(upload_avatar))
+        .route("/files/{id}", get(download_file))
+        .route("/notes", post(create_note))
+        .route("/profiles/{id}", patch(update_profile))
+        .route("/teams/{team}/members/{member}", get(team_member))
+        .route("/search", get(search))
+        .route("/profiles/bulk", post(bulk_profiles))
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[allow(dead_code)]
+struct UpdateProfile {
+    name: Option<String>,
+}
+
+#[dy_api(method = post, path = "/profiles/bulk")]
+async fn bulk_profiles(
+    payload: dy_rs::bulk::BulkJson<UpdateProfile>,
+) -> dy_rs::bulk::BulkResponse<Profile> {
+    dy_rs::bulk::BulkWriter::new()
+        .run(payload, &|items: Vec<UpdateProfile>| async move {
+            Ok(items
+                .into_iter()
+                .map(|item| Profile {
+                    name: item.name.unwrap_or_default(),
+                })
+                .collect())
+        })
+        .await
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[allow(dead_code)]
+struct SearchParams {
+    q: String,
+    limit: Option<u32>,
+}
+
+/// No `request`/`response`: both come from the signature.
+#[dy_api(method = patch, path = "/profiles/{id}")]
+async fn update_profile(
+    Path(_id): Path<Uuid>,
+    Json(_payload): Json<UpdateProfile>,
+) -> ApiResult<Profile> {
+    Ok(Json(Profile {
+        name: "updated".to_string(),
+    }))
+}
+
+#[dy_api(method = get, path = "/teams/{team}/members/{member}")]
+async fn team_member(
+    Path((_team, _member)): Path<(String, u32)>,
+) -> Result<Json<Vec<Profile>>, ApiError> {
+    Ok(Json(Vec::new()))
+}
+
+#[dy_api(method = get, path = "/search")]
+async fn search(Query(_params): Query<SearchParams>) -> Json<Vec<String>> {
+    Json(Vec::new())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[allow(dead_code)]
+struct AvatarForm {
+    #[schema(format = Binary, value_type = String)]
+    file: Vec<u8>,
+}
+
+#[dy_api(
+    method = post,
+    path = "/avatars",
+    request = AvatarForm,
+    request_content_type = "multipart/form-data",
+    status = 204
+)]
+async fn upload_avatar() {}
+
+#[dy_api(
+    method = get,
+    path = "/files/{id}",
+    response_content_type = "application/octet-stream"
+)]
+async fn download_file() {}
+
+#[dy_api(
+    method = post,
+    path = "/notes",
+    request_content_type = "text/plain",
+    response = Profile
+)]
+async fn create_note() {}
+
+#[dy_api(
+    method = get,
+    path = "/v1/profile",
+    operation_id = "getProfileV1",
+    version = "v1",
+    deprecated,
+    external_docs = "https://example.com/migrate",
+    external_docs_description = "Migration guide"
+)]
+async fn legacy_profile() {}
+
+#[dy_api(
+    method = post,
+    path = "/partners/orders",
+    response_content_type = "application/jose"
+)]
+async fn partner_order(dy_rs::jwe::JweJson(_order): dy_rs::jwe::JweJson<Value>) -> Json<Profile> {
+    unimplemented!()
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[allow(dead_code)]
+struct TokenForm {
+    grant_type: String,
+}
+
+#[dy_api(method = post, path = "/oauth/token")]
+async fn token(ValidatedForm(_form): ValidatedForm<TokenForm>) -> Json<Profile> {
+    unimplemented!()
+}
+
+#[dy_api(method = get, path = "/profiles")]
+async fn list_profiles(pagination: Pagination) -> ApiResult<Page<Profile>> {
+    Ok(Json(Page::new(Vec::new(), 0, &pagination)))
+}
+
+fn document() -> Value {
+    serde_json::to_value(build_auto_openapi(DocInfo::default())).unwrap()
+}
+
+#[test]
+fn security_requirements_are_attached() {
+    let doc = document();
+
+    assert_eq!(
+        doc["paths"]["/me"]["get"]["security"],
+        serde_json::json!([{ "bearerAuth": [] }])
+    );
+    assert_eq!(
+        doc["paths"]["/reports"]["get"]["security"],
+        serde_json::json!([
+            { "bearerAuth": [] },
+            { "oauth2": ["reports.read", "reports.export"] }
+        ])
+    );
+}
+
+#[test]
+fn operation_id_defaults_to_fn_name() {
+    let doc = document();
+    assert_eq!(doc["paths"]["/me"]["get"]["operationId"], "me");
+    assert!(doc["paths"]["/me"]["get"].get("deprecated").is_none());
+}
+
+#[test]
+fn operation_metadata_overrides_are_applied() {
+    let doc = document();
+    let op = &doc["paths"]["/v1/profile"]["get"];
+    assert_eq!(op["operationId"], "getProfileV1");
+    assert_eq!(op["deprecated"], true);
+    assert_eq!(op["externalDocs"]["url"], "https://example.com/migrate");
+    assert_eq!(op["externalDocs"]["description"], "Migration guide");
+    assert_eq!(op["x-api-version"], "v1");
+}
+
+#[test]
+fn version_filters_keep_unversioned_operations() {
+    let doc = build_auto_openapi(DocInfo::default());
+    let v1 = filter_openapi(&doc, &DocFilter::new().version("v1"));
+    let v2 = filter_openapi(&doc, &DocFilter::new().version("v2"));
+
+    assert!(v1.paths.paths.contains_key("/v1/profile"));
+    assert!(!v2.paths.paths.contains_key("/v1/profile"));
+    assert!(v1.paths.paths.contains_key("/me") && v2.paths.paths.contains_key("/me"));
+}
+
+#[test]
+fn form_bodies_are_documented_as_urlencoded() {
+    let request = &document()["paths"]["/oauth/token"]["post"]["requestBody"]["content"];
+    assert!(request.get("application/json").is_none());
+    assert!(
+        request["application/x-www-form-urlencoded"]["schema"]["properties"]["grant_type"]
+            .is_object()
+    );
+}
+
+#[test]
+fn paginated_lists_document_params_and_envelope() {
+    let doc = document();
+    let op = &doc["paths"]["/profiles"]["get"];
+    let params: Vec<&str> = op["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(params, ["page", "per_page", "limit", "offset"]);
+
+    let schema = &op["responses"]["200"]["content"]["application/json"]["schema"];
+    assert!(schema["properties"]["items"].is_object(), "{schema}");
+    assert!(schema["properties"]["links"].is_object(), "{schema}");
+    assert!(doc["components"]["schemas"]["PageLinks"].is_object());
+}
+
+#[test]
+fn jwe_bodies_are_documented_as_jose() {
+    let op = &document()["paths"]["/partners/orders"]["post"];
+    let request = &op["requestBody"]["content"];
+    assert!(request.get("application/json").is_none());
+    assert_eq!(request["application/jose"]["schema"]["type"], "string");
+    assert!(
+        request["application/jose"]["schema"]
+            .get("format")
+            .is_none()
+    );
+    assert!(op["responses"]["200"]["content"]["application/jose"].is_object());
+}
+
+#[test]
+fn bearer_scheme_is_registered() {
+    let doc = document();
+    let scheme = &doc["components"]["securitySchemes"]["bearerAuth"];
+    assert_eq!(scheme["type"], "http");
+    assert_eq!(scheme["scheme"], "bearer");
+}
+
+#[test]
+fn non_json_content_types_are_documented() {
+    let doc = document();
+
+    let avatar = &doc["paths"]["/avatars"]["post"]["requestBody"]["content"];
+    assert!(avatar.get("application/json").is_none());
+    assert_eq!(
+        avatar["multipart/form-data"]["schema"]["properties"]["file"]["format"],
+        "binary"
+    );
+
+    let file = &doc["paths"]["/files/{id}"]["get"]["responses"]["200"]["content"];
+    assert_eq!(file["application/octet-stream"]["schema"]["type"], "string");
+    assert_eq!(
+        file["application/octet-stream"]["schema"]["format"],
+        "binary"
+    );
+
+    let note = &doc["paths"]["/notes"]["post"];
+    let text_schema = &note["requestBody"]["content"]["text/plain"]["schema"];
+    assert_eq!(text_schema["type"], "string");
+    assert!(text_schema.get("format").is_none());
+    assert!(note["responses"]["200"]["content"]["application/json"].is_object());
+}
+
+#[test]
+fn request_and_response_are_inferred_from_signature() {
+    let doc = document();
+    let op = &doc["paths"]["/profiles/{id}"]["patch"];
+
+    assert!(
+        op["requestBody"]["content"]["application/json"]["schema"]["properties"]["name"]
+            .is_object()
+    );
+    assert!(op["responses"]["200"]["content"]["application/json"]["schema"].is_object());
+    assert!(doc["components"]["schemas"]["UpdateProfile"].is_object());
+
+    let id = &op["parameters"][0];
+    assert_eq!(id["name"], "id");
+    assert_eq!(id["in"], "path");
+    assert_eq!(id["required"], true);
+    assert_eq!(id["schema"]["format"], "uuid");
+}
+
+#[test]
+fn path_tuple_and_query_params_are_inferred() {
+    let doc = document();
+
+    let params = &doc["paths"]["/teams/{team}/members/{member}"]["get"]["parameters"];
+    assert_eq!(params[0]["name"], "team");
+    assert_eq!(params[0]["schema"]["type"], "string");
+    assert_eq!(params[1]["name"], "member");
+    assert_eq!(params[1]["schema"]["type"], "integer");
+    let members = &doc["paths"]["/teams/{team}/members/{member}"]["get"]["responses"]["200"];
+    assert_eq!(
+        members["content"]["application/json"]["schema"]["type"],
+        "array"
+    );
+
+    let params = doc["paths"]["/search"]["get"]["parameters"]
+        .as_array()
+        .unwrap()
+        .clone();
+    let names: Vec<_> = params.iter().map(|p| p["name"].clone()).collect();
+    assert_eq!(names, ["q", "limit"]);
+    assert!(params.iter().all(|p| p["in"] == "query"));
+    assert!(doc["components"]["schemas"].get("String").is_none());
+}
+
+#[test]
+fn untyped_path_params_come_from_the_template() {
+    let doc = document();
+    let param = &doc["paths"]["/files/{id}"]["get"]["parameters"][0];
+    assert_eq!(param["name"], "id");
+    assert_eq!(param["schema"]["type"], "string");
+}
+
+#[test]
+fn bulk_endpoints_are_documented_as_multi_status() {
+    let doc = document();
+    let op = &doc["paths"]["/profiles/bulk"]["post"];
+
+    let request = &op["requestBody"]["content"]["application/json"]["schema"];
+    assert_eq!(request["type"], "array");
+    assert!(op["responses"].get("200").is_none());
+    let response = &op["responses"]["207"]["content"]["application/json"]["schema"];
+    assert!(response["properties"]["results"].is_object());
+    assert!(doc["components"]["schemas"]["Profile"].is_object());
+}
@@ -0,0 +1,54 @@
+//! Expansion tests for `#[derive(Entity)]`.
+
+#![cfg(feature = "sqlx")]
+
+use dy_rs::db::{Entity, Postgres, QueryBuilder};
+use dy_rs::prelude::*;
+
+#[derive(sqlx::FromRow, Entity)]
+#[entity(table = "users")]
+#[allow(dead_code)]
+struct User {
+    id: Uuid,
+    email: String,
+    #[entity(generated)]
+    created_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow, Entity)]
+#[entity(table = "blog.posts")]
+#[allow(dead_code)]
+struct Post {
+    #[entity(id)]
+    slug: String,
+    title: String,
+    views: i64,
+}
+
+#[test]
+fn maps_fields_to_columns() {
+    assert_eq!(User::TABLE, "users");
+    assert_eq!(User::ID_COLUMN, "id");
+    assert_eq!(User::COLUMNS, ["id", "email"]);
+
+    assert_eq!(Post::TABLE, "blog.posts");
+    assert_eq!(Post::ID_COLUMN, "slug");
+    assert_eq!(Post::COLUMNS, ["slug", "title", "views"]);
+}
+
+#[test]
+fn binds_each_column() {
+    let post = Post {
+        slug: "hello".to_string(),
+        title: "Hello".to_string(),
+        views: 3,
+    };
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    for (i, column) in Post::COLUMNS.iter().enumerate() {
+        if i > 0 {
+            query.push(", ");
+        }
+        post.push_bind(column, &mut query);
+    }
+    assert_eq!(query.sql(), "SELECT $1, $2, $3");
+}
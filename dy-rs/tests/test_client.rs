@@ -0,0 +1,26 @@
+//! Testing an `App` in-process with `testing::TestClient`.
+
+use axum::http::StatusCode;
+use dy_rs::prelude::*;
+use dy_rs::testing::TestClient;
+use serde_json::{Value, json};
+
+#[tokio::test]
+async fn test_client_serves_the_app_stack() {
+    let app = App::new().route(
+        "/echo",
+        post(|Json(body): Json<Value>| async move { (StatusCode::CREATED, Json(body)) }),
+    );
+    let client = TestClient::new(app);
+
+    let body: Value = client
+        .post("/echo")
+        .json(&json!({ "name": "Ana" }))
+        .await
+        .assert_status(StatusCode::CREATED)
+        .json();
+    assert_eq!(body, json!({ "name": "Ana" }));
+
+    let response = client.get("/echo").await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
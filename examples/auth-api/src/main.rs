@@ -14,7 +14,7 @@
 //! - GET /admin - Requires "admin" role
 
 use axum::response::IntoResponse;
-use dy_rs::auth::{auth_routes_with_store, AuthConfig, AuthUser, InMemoryUserStore};
+use dy_rs::auth::{auth_openapi, auth_routes_with_store, AuthConfig, AuthUser, InMemoryUserStore};
 use dy_rs::prelude::*;
 
 /// Protected route - requires any valid JWT
@@ -89,6 +89,7 @@ async fn main() {
     // Build and run the app
     App::new()
         .auto_configure()
+        .with_openapi(auth_openapi())
         .mount(auth_routes_with_store(auth_config.clone(), user_store))
         .mount(protected_routes)
         .mount(public_routes)
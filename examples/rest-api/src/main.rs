@@ -41,8 +41,6 @@ type Database = Arc<Mutex<HashMap<Uuid, User>>>;
 #[dy_api(
     method = post,
     path = "/users",
-    request = CreateUserRequest,
-    response = User,
     tag = "Users",
     summary = "Create a new user"
 )]
@@ -66,7 +64,6 @@ async fn create_user(
 #[dy_api(
     method = get,
     path = "/users",
-    response = User,
     tag = "Users",
     summary = "List all users"
 )]
@@ -79,7 +76,6 @@ async fn list_users(State(db): State<Database>) -> ApiResult<Vec<User>> {
 #[dy_api(
     method = get,
     path = "/users/{id}",
-    response = User,
     tag = "Users",
     summary = "Get a user by ID"
 )]
@@ -99,8 +95,6 @@ async fn get_user(
 #[dy_api(
     method = patch,
     path = "/users/{id}",
-    request = UpdateUserRequest,
-    response = User,
     tag = "Users",
     summary = "Update a user"
 )]
@@ -125,7 +119,6 @@ async fn update_user(
 #[dy_api(
     method = delete,
     path = "/users/{id}",
-    response = User,
     tag = "Users",
     summary = "Delete a user"
 )]
@@ -141,24 +134,15 @@ async fn delete_user(
     Ok(Json(user))
 }
 
-fn user_routes() -> Router<Database> {
-    Router::new()
-        .route("/users", post(create_user))
-        .route("/users", get(list_users))
-        .route("/users/{id}", get(get_user))
-        .route("/users/{id}", patch(update_user))
-        .route("/users/{id}", delete(delete_user))
-}
-
 #[tokio::main]
 async fn main() {
     // Create shared database
     let db: Database = Arc::new(Mutex::new(HashMap::new()));
 
-    // Build and run the app
+    // Build and run the app; #[dy_api] handlers are routed automatically
     App::new()
         .auto_configure()
-        .mount(user_routes().with_state(db))
+        .auto_routes_with_state(db)
         .run()
         .await
         .unwrap();